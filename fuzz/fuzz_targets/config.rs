@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ray_tracing::config::Config;
+
+fuzz_target!(|text: &str| {
+    let _ = Config::parse(text);
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ray_tracing::json::Json;
+
+// any text parses to a document or to an error, and a document can be written back
+fuzz_target!(|text: &str| {
+    if let Ok(json) = Json::parse(text) {
+        let written = json.to_pretty_string();
+        assert!(Json::parse(&written).is_ok());
+    }
+});
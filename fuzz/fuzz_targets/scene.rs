@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ray_tracing::scene::Scene;
+
+use std::path::Path;
+
+// the files a scene refers to are looked for in a directory that doesn't exist, so the
+// target only exercises the parser
+fuzz_target!(|text: &str| {
+    let _ = Scene::parse(text, Path::new("fuzz-missing-directory"));
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ray_tracing::json::Json;
+use ray_tracing::sequence::SequenceManifest;
+use ray_tracing::settings::RenderSettings;
+
+fuzz_target!(|text: &str| {
+    if let Ok(json) = Json::parse(text) {
+        let _ = RenderSettings::from_json(&json);
+        let _ = SequenceManifest::from_json(&json);
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ray_tracing::texture::TextureImage;
+
+// a decoded texture can be sampled anywhere
+fuzz_target!(|bytes: &[u8]| {
+    if let Ok(texture) = TextureImage::decode(bytes) {
+        texture.sample(0.25, 0.75);
+        texture.sample(-1.5, 2.0);
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ray_tracing::volume::DensityGrid;
+
+use nalgebra::Vector3;
+
+// a decoded grid can be looked up anywhere
+fuzz_target!(|bytes: &[u8]| {
+    if let Ok(grid) = DensityGrid::from_vol_bytes(bytes) {
+        grid.density(Vector3::new(0.5, 0.5, 0.5));
+        grid.voxel_size();
+    }
+});
@@ -0,0 +1,578 @@
+use crate::camera::Camera;
+use crate::colorspace::blackbody;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::object::SceneLight;
+use crate::object::Sphere;
+use crate::parameter::ParameterError;
+use crate::random::Rng;
+use crate::scene::Scene;
+use crate::settings::json_to_count;
+use crate::settings::json_to_f64;
+
+use nalgebra::Vector3;
+
+/// Keyframed scalar value, linearly interpolated between keys and held constant
+/// before the first and after the last one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Track {
+    /// `(time, value)` pairs sorted by time
+    keys: Vec<(f64, f64)>,
+}
+
+impl Track {
+    pub fn new(mut keys: Vec<(f64, f64)>) -> Track {
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Track { keys }
+    }
+
+    pub fn constant(value: f64) -> Track {
+        Track::new(vec![(0.0, value)])
+    }
+
+    /// The `(time, value)` keys, sorted by time
+    pub fn keys(&self) -> &[(f64, f64)] {
+        &self.keys
+    }
+
+    /// Track from a JSON array of `[time, value]` keys
+    pub fn from_json(key: &str, json: &Json) -> Result<Track, JsonError> {
+        let error = || JsonError::new(&format!("'{}' must hold [time, value] keys", key));
+        let Json::Array(items) = json else {
+            return Err(error());
+        };
+        let keys = items
+            .iter()
+            .map(|item| match item {
+                Json::Array(pair) if pair.len() == 2 => Ok((
+                    pair[0].as_f64().ok_or_else(error)?,
+                    pair[1].as_f64().ok_or_else(error)?,
+                )),
+                _ => Err(error()),
+            })
+            .collect::<Result<Vec<(f64, f64)>, JsonError>>()?;
+        Ok(Track::new(keys))
+    }
+
+    /// Track as a JSON array of keys (see `from_json`)
+    pub fn to_json(&self) -> Json {
+        Json::Array(
+            self.keys
+                .iter()
+                .map(|(time, value)| Json::Array(vec![Json::Number(*time), Json::Number(*value)]))
+                .collect(),
+        )
+    }
+
+    /// Value of the track at the given time, `None` if the track has no keys
+    pub fn sample(&self, time: f64) -> Option<f64> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        if time <= first.0 {
+            return Some(first.1);
+        }
+        if time >= last.0 {
+            return Some(last.1);
+        }
+        let next = self.keys.iter().position(|key| key.0 > time)?;
+        let (t0, v0) = self.keys[next - 1];
+        let (t1, v1) = self.keys[next];
+        let s = (time - t0) / (t1 - t0);
+        Some(v0 + (v1 - v0) * s)
+    }
+}
+
+/// Procedural flicker: smooth value noise scaling the intensity down by up to `amount`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Flicker {
+    /// Maximum relative drop of the intensity, in the range `[0, 1]`
+    pub amount: f64,
+    /// Number of noise lattice points per second (higher is more nervous)
+    pub frequency: f64,
+    pub seed: u64,
+}
+
+impl Flicker {
+    /// Candle-like flicker
+    pub fn candle(seed: u64) -> Flicker {
+        Flicker {
+            amount: 0.35,
+            frequency: 8.0,
+            seed,
+        }
+    }
+
+    /// Flicker from a JSON object with its `amount`, `frequency` and `seed`, those left out
+    /// being the ones of a candle (with the seed 0)
+    pub fn from_json(json: &Json) -> Result<Flicker, JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("'flicker' must be a JSON object"));
+        };
+        let mut flicker = Flicker::candle(0);
+        for (key, value) in entries {
+            match key.as_str() {
+                "amount" => flicker.amount = json_to_f64(key, value)?,
+                "frequency" => flicker.frequency = json_to_f64(key, value)?,
+                "seed" => flicker.seed = json_to_count(key, value)?,
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown flicker property '{}'",
+                        key
+                    )))
+                }
+            }
+        }
+        Ok(flicker)
+    }
+
+    /// Flicker as a JSON object (see `from_json`)
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("amount".to_string(), Json::Number(self.amount)),
+            ("frequency".to_string(), Json::Number(self.frequency)),
+            ("seed".to_string(), Json::Number(self.seed as f64)),
+        ])
+    }
+
+    /// Intensity multiplier at the given time, in the range `[1 - amount, 1]`
+    pub fn sample(&self, time: f64) -> f64 {
+        let noise = fractal_noise(time * self.frequency, self.seed);
+        1.0 - self.amount.clamp(0.0, 1.0) * noise
+    }
+}
+
+/// Two octaves of 1D value noise, in the range `[0, 1)`
+fn fractal_noise(x: f64, seed: u64) -> f64 {
+    (value_noise(x, seed) * 2.0 + value_noise(x * 2.7, seed ^ 0x5bd1_e995)) / 3.0
+}
+
+/// 1D value noise: random values at the integer points with smoothstep interpolation
+fn value_noise(x: f64, seed: u64) -> f64 {
+    let cell = x.floor();
+    let s = x - cell;
+    let s = s * s * (3.0 - 2.0 * s);
+    let a = lattice(cell as i64, seed);
+    let b = lattice(cell as i64 + 1, seed);
+    a + (b - a) * s
+}
+
+/// Random value in `[0, 1)` attached to an integer lattice point
+fn lattice(index: i64, seed: u64) -> f64 {
+    Rng::new(seed ^ (index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)).next_f64()
+}
+
+/// Smooth random offset with each coordinate in the range `[-1, 1]`, the three axes being
+/// independent noises
+fn wobble(x: f64, seed: u64) -> Vector3<f64> {
+    Vector3::from_fn(|axis, _| {
+        let seed = seed ^ (axis as u64 + 1).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+        fractal_noise(x, seed) * 2.0 - 1.0
+    })
+}
+
+/// Procedural camera shake: the camera sways and its view direction wanders with smooth
+/// noise, like a camera held by hand, without keyframing every frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraShake {
+    /// Maximum offset of the position of the camera along each axis, in scene units
+    pub amplitude: f64,
+    /// Maximum rotation of the view direction, in radians
+    pub angle: f64,
+    /// Number of noise lattice points per second (higher is more nervous)
+    pub frequency: f64,
+    pub seed: u64,
+}
+
+impl CameraShake {
+    /// Handheld camera shake, `strength` 1 being a steady hand (a sway of 2 cm and half a
+    /// degree)
+    pub fn handheld(strength: f64, seed: u64) -> CameraShake {
+        CameraShake {
+            amplitude: 0.02 * strength,
+            angle: 0.5_f64.to_radians() * strength,
+            frequency: 1.5,
+            seed,
+        }
+    }
+
+    /// State of the camera at the given time
+    pub fn apply(&self, camera: &Camera, time: f64) -> Camera {
+        let x = time * self.frequency;
+        let offset = wobble(x, self.seed) * self.amplitude;
+        // the target moves across the view direction, by the angle seen from the camera
+        let forward = camera.target - camera.position;
+        let distance = forward.norm();
+        let axis = forward / distance;
+        let turn = wobble(x, self.seed ^ 0x27d4_eb2f_1656_67c5);
+        let turn = (turn - axis * turn.dot(&axis)) * (self.angle.tan() * distance);
+        let mut shaken = camera.clone();
+        shaken.position += offset;
+        shaken.target += offset + turn;
+        shaken
+    }
+}
+
+/// Procedural noise on the transform of one object of the scene: its center drifts and its
+/// radius breathes with smooth noise
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransformNoise {
+    /// Index of the animated object in the scene
+    pub object: usize,
+    /// Maximum offset of the center along each axis, in scene units
+    pub translation: Vector3<f64>,
+    /// Maximum relative change of the radius, in the range `[0, 1)`
+    pub scale: f64,
+    /// Number of noise lattice points per second (higher is more nervous)
+    pub frequency: f64,
+    pub seed: u64,
+}
+
+impl TransformNoise {
+    /// Noise moving the object by up to `translation` along each axis, without scaling it
+    pub fn new(object: usize, translation: Vector3<f64>, seed: u64) -> TransformNoise {
+        TransformNoise {
+            object,
+            translation,
+            scale: 0.0,
+            frequency: 1.0,
+            seed,
+        }
+    }
+
+    pub fn with_scale(mut self, scale: f64) -> TransformNoise {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_frequency(mut self, frequency: f64) -> TransformNoise {
+        self.frequency = frequency;
+        self
+    }
+
+    /// State of the object at the given time
+    pub fn apply(&self, sphere: &Sphere, time: f64) -> Sphere {
+        let x = time * self.frequency;
+        let offset = wobble(x, self.seed);
+        let mut animated = sphere.clone();
+        animated.center += offset.component_mul(&self.translation);
+        let breath = fractal_noise(x, self.seed ^ 0x1656_67b1_9e37_79f9) * 2.0 - 1.0;
+        animated.radius *= 1.0 + self.scale.clamp(0.0, 0.99) * breath;
+        animated
+    }
+}
+
+/// Animation of the intensity and color temperature of one light of the scene
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightAnimation {
+    /// Index of the animated light in the scene
+    pub light: usize,
+    pub intensity: Option<Track>,
+    /// Color temperature in kelvins
+    pub temperature: Option<Track>,
+    pub flicker: Option<Flicker>,
+}
+
+impl LightAnimation {
+    pub fn new(light: usize) -> LightAnimation {
+        LightAnimation {
+            light,
+            intensity: None,
+            temperature: None,
+            flicker: None,
+        }
+    }
+
+    pub fn with_intensity(mut self, track: Track) -> LightAnimation {
+        self.intensity = Some(track);
+        self
+    }
+
+    pub fn with_temperature(mut self, track: Track) -> LightAnimation {
+        self.temperature = Some(track);
+        self
+    }
+
+    pub fn with_flicker(mut self, flicker: Flicker) -> LightAnimation {
+        self.flicker = Some(flicker);
+        self
+    }
+
+    /// State of the light at the given time
+    pub fn apply(&self, light: &SceneLight, time: f64) -> SceneLight {
+        let mut animated = light.clone();
+        if let Some(intensity) = self.intensity.as_ref().and_then(|t| t.sample(time)) {
+            animated.intensity = intensity;
+        }
+        if let Some(kelvin) = self.temperature.as_ref().and_then(|t| t.sample(time)) {
+            animated.color = blackbody(kelvin);
+        }
+        if let Some(flicker) = &self.flicker {
+            animated.intensity *= flicker.sample(time);
+        }
+        animated
+    }
+}
+
+/// Light of a scene as an animation names it, by its name or by its index among the lights
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnimatedLight {
+    Index(usize),
+    Name(String),
+}
+
+/// Animation of a light declared by a scene, the light being found in the scene when the
+/// animation is rendered (see `SceneAnimation::light_animations`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightAnimationSpec {
+    pub light: AnimatedLight,
+    pub intensity: Option<Track>,
+    /// Color temperature in kelvins
+    pub temperature: Option<Track>,
+    pub flicker: Option<Flicker>,
+}
+
+impl LightAnimationSpec {
+    pub fn new(light: AnimatedLight) -> LightAnimationSpec {
+        LightAnimationSpec {
+            light,
+            intensity: None,
+            temperature: None,
+            flicker: None,
+        }
+    }
+
+    pub fn with_intensity(mut self, track: Track) -> LightAnimationSpec {
+        self.intensity = Some(track);
+        self
+    }
+
+    pub fn with_temperature(mut self, track: Track) -> LightAnimationSpec {
+        self.temperature = Some(track);
+        self
+    }
+
+    pub fn with_flicker(mut self, flicker: Flicker) -> LightAnimationSpec {
+        self.flicker = Some(flicker);
+        self
+    }
+
+    /// Animation from a JSON object with the `light` it animates, its name or its index,
+    /// and optionally its `intensity` and `temperature` tracks (see `Track::from_json`) and
+    /// its `flicker` (see `Flicker::from_json`)
+    pub fn from_json(json: &Json) -> Result<LightAnimationSpec, JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("a light animation must be a JSON object"));
+        };
+        let mut light = None;
+        let mut spec = LightAnimationSpec::new(AnimatedLight::Index(0));
+        for (key, value) in entries {
+            match key.as_str() {
+                "light" => {
+                    light = Some(match value {
+                        Json::String(name) => AnimatedLight::Name(name.clone()),
+                        _ => AnimatedLight::Index(json_to_count(key, value).map_err(|_| {
+                            JsonError::new("'light' must be the name or the index of a light")
+                        })? as usize),
+                    })
+                }
+                "intensity" => spec.intensity = Some(Track::from_json(key, value)?),
+                "temperature" => spec.temperature = Some(Track::from_json(key, value)?),
+                "flicker" => spec.flicker = Some(Flicker::from_json(value)?),
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown light animation property '{}'",
+                        key
+                    )))
+                }
+            }
+        }
+        spec.light =
+            light.ok_or_else(|| JsonError::new("a light animation must have a 'light'"))?;
+        Ok(spec)
+    }
+
+    /// Animation as a JSON object (see `from_json`)
+    pub fn to_json(&self) -> Json {
+        let light = match &self.light {
+            AnimatedLight::Index(index) => Json::Number(*index as f64),
+            AnimatedLight::Name(name) => Json::String(name.clone()),
+        };
+        let mut entries = vec![("light".to_string(), light)];
+        if let Some(intensity) = &self.intensity {
+            entries.push(("intensity".to_string(), intensity.to_json()));
+        }
+        if let Some(temperature) = &self.temperature {
+            entries.push(("temperature".to_string(), temperature.to_json()));
+        }
+        if let Some(flicker) = &self.flicker {
+            entries.push(("flicker".to_string(), flicker.to_json()));
+        }
+        Json::Object(entries)
+    }
+
+    /// The animation of the light it names in a scene
+    ///
+    /// ### Returns
+    ///
+    /// `Result<LightAnimation, ParameterError>` - The animation, or the error naming the
+    /// light when it isn't in the scene
+    ///
+    pub fn resolve(&self, scene: &Scene) -> Result<LightAnimation, ParameterError> {
+        let light = match &self.light {
+            AnimatedLight::Index(index) if *index < scene.lights.len() => *index,
+            AnimatedLight::Index(index) => {
+                return Err(ParameterError::new(&format!(
+                    "the scene has no light {}, it has {} lights",
+                    index,
+                    scene.lights.len()
+                )))
+            }
+            AnimatedLight::Name(name) => scene
+                .lights
+                .iter()
+                .position(|light| light.name.as_deref() == Some(name.as_str()))
+                .ok_or_else(|| {
+                    ParameterError::new(&format!("the scene has no light named '{}'", name))
+                })?,
+        };
+        Ok(LightAnimation {
+            light,
+            intensity: self.intensity.clone(),
+            temperature: self.temperature.clone(),
+            flicker: self.flicker,
+        })
+    }
+}
+
+/// Animation declared by a scene for the `animate` command: its frame rate and the
+/// animations of its lights
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneAnimation {
+    /// Number of frames per second
+    pub fps: f64,
+    pub lights: Vec<LightAnimationSpec>,
+}
+
+impl Default for SceneAnimation {
+    fn default() -> SceneAnimation {
+        SceneAnimation {
+            fps: 24.0,
+            lights: Vec::new(),
+        }
+    }
+}
+
+impl SceneAnimation {
+    pub fn with_fps(mut self, fps: f64) -> SceneAnimation {
+        self.fps = fps;
+        self
+    }
+
+    pub fn with_light(mut self, light: LightAnimationSpec) -> SceneAnimation {
+        self.lights.push(light);
+        self
+    }
+
+    /// Animation from a JSON object with its `fps` (24 by default) and the animations of
+    /// its `lights` (see `LightAnimationSpec::from_json`)
+    pub fn from_json(json: &Json) -> Result<SceneAnimation, JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("'animation' must be a JSON object"));
+        };
+        let mut animation = SceneAnimation::default();
+        for (key, value) in entries {
+            match key.as_str() {
+                "fps" => {
+                    animation.fps = json_to_f64(key, value)?;
+                    if animation.fps <= 0.0 {
+                        return Err(JsonError::new("'fps' must be positive"));
+                    }
+                }
+                "lights" => {
+                    let Json::Array(lights) = value else {
+                        return Err(JsonError::new("'lights' must be an array"));
+                    };
+                    animation.lights = lights
+                        .iter()
+                        .map(LightAnimationSpec::from_json)
+                        .collect::<Result<Vec<LightAnimationSpec>, JsonError>>()?;
+                }
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown animation property '{}'",
+                        key
+                    )))
+                }
+            }
+        }
+        Ok(animation)
+    }
+
+    /// Animation as a JSON object (see `from_json`)
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("fps".to_string(), Json::Number(self.fps)),
+            (
+                "lights".to_string(),
+                Json::Array(self.lights.iter().map(|light| light.to_json()).collect()),
+            ),
+        ])
+    }
+
+    /// The animations of the lights found in a scene (see `LightAnimationSpec::resolve`)
+    pub fn light_animations(&self, scene: &Scene) -> Result<Vec<LightAnimation>, ParameterError> {
+        self.lights
+            .iter()
+            .map(|light| light.resolve(scene))
+            .collect()
+    }
+}
+
+/// Evaluate the lights of a scene at the given time
+///
+/// ### Arguments
+///
+/// * `lights` - The lights in their rest state
+/// * `animations` - The animations to apply (animations of missing lights are ignored)
+/// * `time` - The time in seconds
+///
+/// ### Returns
+///
+/// `Vec<SceneLight>` - The animated lights
+///
+pub fn animate_lights(
+    lights: &[SceneLight],
+    animations: &[LightAnimation],
+    time: f64,
+) -> Vec<SceneLight> {
+    let mut animated = lights.to_vec();
+    for animation in animations {
+        if let Some(light) = animated.get_mut(animation.light) {
+            *light = animation.apply(light, time);
+        }
+    }
+    animated
+}
+
+/// Evaluate the objects of a scene at the given time
+///
+/// ### Arguments
+///
+/// * `spheres` - The objects in their rest state
+/// * `noises` - The noises to apply (noises of missing objects are ignored)
+/// * `time` - The time in seconds
+///
+/// ### Returns
+///
+/// `Vec<Sphere>` - The animated objects, the hierarchy of a scene made of them has to be
+/// rebuilt (see `Scene::rebuild`)
+///
+pub fn animate_objects(spheres: &[Sphere], noises: &[TransformNoise], time: f64) -> Vec<Sphere> {
+    let mut animated = spheres.to_vec();
+    for noise in noises {
+        if let Some(sphere) = animated.get_mut(noise.object) {
+            *sphere = noise.apply(sphere, time);
+        }
+    }
+    animated
+}
@@ -0,0 +1,97 @@
+use crate::image::Image;
+use crate::image::ImageStream;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::sync::TryLockError;
+
+/// Whether renders keep a copy of their finished rows, set by `install_panic_hook`
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a render is running, between `begin_image` and `end_image`
+static RENDERING: AtomicBool = AtomicBool::new(false);
+
+/// Image being rendered, updated row by row
+static PARTIAL: Mutex<Option<Image>> = Mutex::new(None);
+
+/// Save the rows rendered so far to `filename` when the program panics, before the
+/// default panic message is printed
+///
+/// Rows that were not rendered yet stay black. Renders limited by a memory budget don't
+/// keep a copy of the image and are not saved
+pub fn install_panic_hook(filename: &str) {
+    ENABLED.store(true, Ordering::Relaxed);
+    let filename = filename.to_string();
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // the panicking thread may hold the lock, a partial image is not worth a deadlock
+        let partial = match PARTIAL.try_lock() {
+            Ok(partial) => Some(partial),
+            Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        };
+        if let Some(image) = partial.as_ref().and_then(|partial| partial.as_ref()) {
+            // panicking again inside the hook would abort, so errors are only reported
+            match save(image, &filename) {
+                Ok(()) => eprintln!("partial image saved to {}", filename),
+                Err(error) => eprintln!("failed to save the partial image: {}", error),
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+/// Start keeping the rows of a new image (only marks the render as running without a panic
+/// hook)
+pub fn begin_image(width: u32, height: u32) {
+    RENDERING.store(true, Ordering::Relaxed);
+    if ENABLED.load(Ordering::Relaxed) {
+        *lock() = Some(Image::new(width, height));
+    }
+}
+
+/// Copy a finished row of RGB pixels into the partial image
+pub fn record_row(j: u32, row: &[u8]) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(image) = lock().as_mut() {
+        let offset = j as usize * image.width as usize * 3;
+        if let Some(target) = image.data.get_mut(offset..offset + row.len()) {
+            target.copy_from_slice(row);
+        }
+    }
+}
+
+/// Drop the partial image once the render is complete
+pub fn end_image() {
+    RENDERING.store(false, Ordering::Relaxed);
+    if ENABLED.load(Ordering::Relaxed) {
+        *lock() = None;
+    }
+}
+
+/// Whether the rows of the images being rendered are kept for the panic hook
+pub fn keeps_rows() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Whether a render has started and not finished, e.g. when a panic stops it
+pub fn rendering() -> bool {
+    RENDERING.load(Ordering::Relaxed)
+}
+
+/// Write the partial image without panicking
+fn save(image: &Image, filename: &str) -> std::io::Result<()> {
+    let mut stream = ImageStream::create(filename, image.width, image.height)?;
+    stream.write_rows(&image.data)?;
+    stream.finish()
+}
+
+/// Lock the partial image, ignoring the poisoning left by a panicking render thread
+fn lock() -> std::sync::MutexGuard<'static, Option<Image>> {
+    PARTIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
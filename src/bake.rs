@@ -0,0 +1,144 @@
+use crate::color::Color;
+use crate::colorspace::ColorPipeline;
+use crate::image::Image;
+use crate::object::Sphere;
+use crate::ray::Ray;
+use crate::render::direct_lighting;
+use crate::render::is_occluded;
+use crate::sampling::Onb;
+use crate::scene::Scene;
+
+use nalgebra::Vector3;
+
+/// What gets evaluated at each texel of the bake target
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BakeMode {
+    /// Diffuse irradiance from the scene lights, shadows included
+    Lightmap,
+    /// Cosine-weighted fraction of the hemisphere that is not occluded within `max_distance`
+    AmbientOcclusion { samples: u32, max_distance: f64 },
+    /// Bent normal: cosine-weighted average of the directions of the hemisphere that are not
+    /// occluded within `max_distance`, in world space, its coordinates mapped from `[-1, 1]`
+    /// to `[0, 1]` like a normal map; the normal where everything is occluded
+    BentNormal { samples: u32, max_distance: f64 },
+}
+
+impl BakeMode {
+    /// Whether the texels are colors, encoded by the color pipeline, rather than data
+    /// written as they are (bent normals)
+    pub fn is_color(&self) -> bool {
+        !matches!(self, BakeMode::BentNormal { .. })
+    }
+}
+
+/// Bake lighting of a sphere into its UV layout (see `Sphere::surface_at`)
+///
+/// ### Arguments
+///
+/// * `target` - The sphere to bake, usually one of the spheres of `scene`
+/// * `width` - The width of the resulting texture
+/// * `height` - The height of the resulting texture
+/// * `mode` - What to evaluate at each texel
+/// * `scene` - The scene
+/// * `pipeline` - The color pipeline used to encode the texels
+///
+/// ### Returns
+///
+/// Image - The baked texture, with `v = 1` on the first row, the data of the modes that
+/// aren't colors scaled to 8 bits without the pipeline
+///
+pub fn bake(
+    target: &Sphere,
+    width: u32,
+    height: u32,
+    mode: BakeMode,
+    scene: &Scene,
+    pipeline: &ColorPipeline,
+) -> Image {
+    let values = bake_values(target, width, height, mode, scene);
+    let mut image = Image::new(width, height);
+    for (k, value) in values.iter().enumerate() {
+        let [r, g, b] = if mode.is_color() {
+            pipeline.to_rgb8(*value)
+        } else {
+            value
+                .to_array()
+                .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+        };
+        image.set_pixel_rgb(k as u32 % width, k as u32 / width, r, g, b);
+    }
+    image
+}
+
+/// Linear values of the texels of a bake (see `bake`), row by row from `v = 1`, to be
+/// saved in another pixel format than RGB8
+pub fn bake_values(
+    target: &Sphere,
+    width: u32,
+    height: u32,
+    mode: BakeMode,
+    scene: &Scene,
+) -> Vec<Color> {
+    let mut values = Vec::with_capacity(width as usize * height as usize);
+    for j in 0..height {
+        for i in 0..width {
+            // evaluate at texel centers
+            let u = (i as f64 + 0.5) / width as f64;
+            let v = 1.0 - (j as f64 + 0.5) / height as f64;
+            let (point, n) = target.surface_at(u, v);
+            values.push(match mode {
+                BakeMode::Lightmap => direct_lighting(point, n, -n, 0.0, scene).0,
+                BakeMode::AmbientOcclusion {
+                    samples,
+                    max_distance,
+                } => Color::gray(occlusion(point, n, samples, max_distance, scene).0),
+                BakeMode::BentNormal {
+                    samples,
+                    max_distance,
+                } => {
+                    let bent = occlusion(point, n, samples, max_distance, scene).1;
+                    Color::new(bent.x, bent.y, bent.z) * 0.5 + Color::gray(0.5)
+                }
+            });
+        }
+    }
+    values
+}
+
+/// Estimate ambient occlusion and the bent normal at a point using a deterministic spiral
+/// of directions over the hemisphere around the normal
+fn occlusion(
+    point: Vector3<f64>,
+    n: Vector3<f64>,
+    samples: u32,
+    max_distance: f64,
+    scene: &Scene,
+) -> (f64, Vector3<f64>) {
+    let frame = Onb::from_w(n);
+
+    // golden-angle spiral gives evenly spread directions without a random generator
+    let golden_angle = std::f64::consts::PI * (3.0 - 5.0_f64.sqrt());
+    let mut visible = 0.0;
+    let mut total = 0.0;
+    let mut bent = Vector3::default();
+    for k in 0..samples {
+        let cos_theta = 1.0 - (k as f64 + 0.5) / samples as f64;
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = golden_angle * k as f64;
+        let local = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let direction = frame.to_world(local);
+
+        let ray = Ray::offset(point, n, direction).with_t_max(max_distance);
+        if !is_occluded(&ray, scene) {
+            visible += cos_theta;
+            bent += direction * cos_theta;
+        }
+        total += cos_theta;
+    }
+    let bent = bent.try_normalize(1e-12).unwrap_or(n);
+    if total > 0.0 {
+        (visible / total, bent)
+    } else {
+        (1.0, n)
+    }
+}
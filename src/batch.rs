@@ -0,0 +1,131 @@
+/// Error raised by a line of a batch file that can't be split into arguments
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchError {
+    pub message: String,
+}
+
+impl BatchError {
+    pub fn new(message: &str) -> BatchError {
+        BatchError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// Split a line of a batch file into arguments like a shell does, e.g.
+/// `--scene "my scene.json" --set 'name=a b' depth`
+///
+/// Blanks separate the arguments. Single quotes keep everything up to the next single quote
+/// as it is, double quotes keep everything up to the next double quote except a backslash
+/// before a double quote or a backslash, and a backslash outside quotes keeps the character
+/// after it. A quoted empty string is an empty argument
+///
+/// ### Arguments
+///
+/// * `line` - The line, without its line break
+///
+/// ### Returns
+///
+/// `Result<Vec<String>, BatchError>` - The arguments, an error for a quote left open
+///
+pub fn split_arguments(line: &str) -> Result<Vec<String>, BatchError> {
+    let mut arguments = Vec::new();
+    // the argument being read, `None` between arguments
+    let mut argument: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                arguments.extend(argument.take());
+            }
+            '\'' => {
+                let text = argument.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => text.push(c),
+                        None => return Err(BatchError::new("unclosed single quote")),
+                    }
+                }
+            }
+            '"' => {
+                let text = argument.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => text.push(c),
+                            Some(c) => {
+                                text.push('\\');
+                                text.push(c);
+                            }
+                            None => return Err(BatchError::new("unclosed double quote")),
+                        },
+                        Some(c) => text.push(c),
+                        None => return Err(BatchError::new("unclosed double quote")),
+                    }
+                }
+            }
+            '\\' => {
+                let text = argument.get_or_insert_with(String::new);
+                text.push(chars.next().unwrap_or('\\'));
+            }
+            c => argument.get_or_insert_with(String::new).push(c),
+        }
+    }
+    arguments.extend(argument);
+    Ok(arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blanks_separate_the_arguments() {
+        let arguments = split_arguments("  --scene a.json\tdepth  ").unwrap();
+        assert_eq!(arguments, ["--scene", "a.json", "depth"]);
+    }
+
+    #[test]
+    fn quotes_keep_blanks() {
+        let line = r#"--scene "my scene.json" --set 'name=a b' lpe glass="C T+ D L""#;
+        let arguments = split_arguments(line).unwrap();
+        assert_eq!(
+            arguments,
+            [
+                "--scene",
+                "my scene.json",
+                "--set",
+                "name=a b",
+                "lpe",
+                "glass=C T+ D L"
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes_and_empty_arguments() {
+        // a backslash in single quotes is kept and closes nothing
+        let arguments = split_arguments(r"'it\'s").unwrap();
+        assert_eq!(arguments, [r"it\s"]);
+        let arguments = split_arguments(r#"a\ b "say \"hi\" \n" '' c:\\d"#).unwrap();
+        assert_eq!(arguments, ["a b", r#"say "hi" \n"#, "", r"c:\d"]);
+    }
+
+    #[test]
+    fn unclosed_quotes_are_errors() {
+        let error = split_arguments(r#"--scene "a.json depth"#).unwrap_err();
+        assert_eq!(error.message, "unclosed double quote");
+        assert!(split_arguments("--set 'name=a").is_err());
+        assert!(split_arguments(r#"x "\"#).is_err());
+    }
+}
@@ -0,0 +1,469 @@
+use crate::object::Sphere;
+use crate::ray::Ray;
+
+use nalgebra::Vector3;
+
+/// Objects per leaf of the hierarchy, splitting further costs more box tests than the
+/// sphere tests it saves
+const LEAF_SIZE: usize = 2;
+
+/// Axis aligned box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: Vector3<f64>,
+    pub max: Vector3<f64>,
+}
+
+impl Bounds {
+    /// Box holding nothing, the neutral element of `union`
+    pub fn empty() -> Bounds {
+        Bounds {
+            min: Vector3::repeat(f64::INFINITY),
+            max: Vector3::repeat(f64::NEG_INFINITY),
+        }
+    }
+
+    /// Box around a sphere, padded a little so rounding doesn't make the box miss rays
+    /// grazing the sphere
+    ///
+    /// Spheres with a position or a radius that isn't finite can't be hit, they get an
+    /// empty box
+    pub fn of(sphere: &Sphere) -> Bounds {
+        if !sphere.radius.is_finite() || sphere.center.iter().any(|c| !c.is_finite()) {
+            return Bounds::empty();
+        }
+        let radius = sphere.radius.abs() * (1.0 + 1e-9) + 1e-9;
+        Bounds {
+            min: sphere.center - Vector3::repeat(radius),
+            max: sphere.center + Vector3::repeat(radius),
+        }
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vector3<f64> {
+        (self.min + self.max) / 2.0
+    }
+
+    /// Whether the ray crosses the box between its `t_min` and `t_max` (slab test)
+    pub fn hit(&self, ray: &Ray, t_max: f64) -> bool {
+        let (mut near, mut far) = (ray.t_min, t_max);
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let t0 = (self.min[axis] - ray.origin[axis]) * inverse;
+            let t1 = (self.max[axis] - ray.origin[axis]) * inverse;
+            // a ray parallel to the slab and starting on its plane gives NaN, which keeps
+            // the box (the sphere test decides)
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+            if near > far {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    /// Objects `indices[start..start + count]`
+    Leaf {
+        bounds: Bounds,
+        start: usize,
+        count: usize,
+    },
+    /// Node with its first child right after it and its second child at `second`
+    Interior { bounds: Bounds, second: usize },
+}
+
+impl Node {
+    fn bounds(&self) -> &Bounds {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Bounding volume hierarchy over the spheres of a scene, so a ray is only tested against
+/// the spheres whose boxes it crosses
+///
+/// The hierarchy refers to the spheres by their index, it has to be rebuilt when spheres
+/// are added, removed, moved or resized (see `Scene::rebuild`)
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+    /// Hierarchy splitting the spheres in halves along the longest axis of their centers
+    pub fn build(spheres: &[Sphere]) -> Bvh {
+        let bounds: Vec<Bounds> = spheres.iter().map(Bounds::of).collect();
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * spheres.len()),
+            indices: (0..spheres.len()).collect(),
+        };
+        if !spheres.is_empty() {
+            bvh.split(&bounds, 0, spheres.len());
+        }
+        bvh
+    }
+
+    /// Add the node of the objects `indices[start..end]` and its children
+    fn split(&mut self, bounds: &[Bounds], start: usize, end: usize) {
+        let node_bounds = self.indices[start..end]
+            .iter()
+            .fold(Bounds::empty(), |total, &index| total.union(&bounds[index]));
+        if end - start <= LEAF_SIZE {
+            self.nodes.push(Node::Leaf {
+                bounds: node_bounds,
+                start,
+                count: end - start,
+            });
+            return;
+        }
+        let centers = self.indices[start..end]
+            .iter()
+            .fold(Bounds::empty(), |total, &index| {
+                let center = bounds[index].center();
+                total.union(&Bounds {
+                    min: center,
+                    max: center,
+                })
+            });
+        let extent = centers.max - centers.min;
+        let axis = extent.imax();
+        self.indices[start..end]
+            .sort_by(|&a, &b| bounds[a].center()[axis].total_cmp(&bounds[b].center()[axis]));
+        let middle = (start + end) / 2;
+        let node = self.nodes.len();
+        self.nodes.push(Node::Interior {
+            bounds: node_bounds,
+            second: 0,
+        });
+        self.split(bounds, start, middle);
+        let second_index = self.nodes.len();
+        self.split(bounds, middle, end);
+        if let Node::Interior { second, .. } = &mut self.nodes[node] {
+            *second = second_index;
+        }
+    }
+
+    /// Number of spheres in the hierarchy
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Boxes of the nodes with their depth (the root at 0), parents before their children
+    pub fn boxes(&self) -> Vec<(usize, Bounds)> {
+        let mut boxes = Vec::with_capacity(self.nodes.len());
+        if self.nodes.is_empty() {
+            return boxes;
+        }
+        let mut stack = vec![(0, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            boxes.push((depth, *self.nodes[node].bounds()));
+            if let Node::Interior { second, .. } = self.nodes[node] {
+                stack.push((second, depth + 1));
+                stack.push((node + 1, depth + 1));
+            }
+        }
+        boxes
+    }
+
+    /// Wavefront OBJ drawing the edges of the node boxes as lines, one group `depth_<n>` per
+    /// depth so a viewer can show the levels one by one, to see where the boxes of a scene
+    /// overlap and rays end up visiting many nodes
+    ///
+    /// Empty boxes (spheres that can't be hit) are left out
+    ///
+    /// ### Arguments
+    ///
+    /// * `max_depth` - The deepest level written, all of them for `None`
+    ///
+    /// ### Returns
+    ///
+    /// `String` - The text of the OBJ file
+    ///
+    pub fn to_obj(&self, max_depth: Option<usize>) -> String {
+        // the 12 edges of a box between its corners, corner `k` taking the maximum along
+        // the axes of the bits of `k`
+        const EDGES: [(usize, usize); 12] = [
+            (0, 1),
+            (2, 3),
+            (4, 5),
+            (6, 7),
+            (0, 2),
+            (1, 3),
+            (4, 6),
+            (5, 7),
+            (0, 4),
+            (1, 5),
+            (2, 6),
+            (3, 7),
+        ];
+        let mut boxes: Vec<(usize, Bounds)> = self
+            .boxes()
+            .into_iter()
+            .filter(|(depth, bounds)| {
+                max_depth.is_none_or(|max_depth| *depth <= max_depth)
+                    && bounds
+                        .min
+                        .iter()
+                        .chain(bounds.max.iter())
+                        .all(|c| c.is_finite())
+            })
+            .collect();
+        boxes.sort_by_key(|(depth, _)| *depth);
+        let mut text = format!("# {} nodes, {} spheres\n", self.nodes.len(), self.len());
+        let mut group = None;
+        for (number, (depth, bounds)) in boxes.iter().enumerate() {
+            if group != Some(depth) {
+                text += &format!("g depth_{}\n", depth);
+                group = Some(depth);
+            }
+            for corner in 0..8 {
+                let pick = |axis: usize| {
+                    if corner & (1 << axis) != 0 {
+                        bounds.max[axis]
+                    } else {
+                        bounds.min[axis]
+                    }
+                };
+                text += &format!("v {} {} {}\n", pick(0), pick(1), pick(2));
+            }
+            // OBJ vertices are numbered from 1, across the whole file
+            let first = number * 8 + 1;
+            for (a, b) in EDGES {
+                text += &format!("l {} {}\n", first + a, first + b);
+            }
+        }
+        text
+    }
+
+    /// Find the sphere closest to the ray origin along the ray
+    ///
+    /// Gives the same hit as testing the spheres one after the other: of spheres hit at
+    /// the same distance, the one with the highest index
+    ///
+    /// ### Arguments
+    ///
+    /// * `ray` - The ray, only hits between its `t_min` and `t_max` are considered
+    /// * `spheres` - The spheres the hierarchy was built from
+    ///
+    /// ### Returns
+    ///
+    /// `Option<(usize, f64)>` - The index of the closest sphere and the distance to it
+    ///
+    pub fn nearest(&self, ray: &Ray, spheres: &[Sphere]) -> Option<(usize, f64)> {
+        let mut nearest: Option<(usize, f64)> = None;
+        self.visit(ray, |index, t_max| {
+            if let Some(distance) = spheres[index].intersect(ray, ray.t_min, t_max) {
+                let closer = match nearest {
+                    Some((best, best_distance)) => {
+                        distance < best_distance || (distance == best_distance && index > best)
+                    }
+                    None => true,
+                };
+                if closer {
+                    nearest = Some((index, distance));
+                }
+            }
+            (nearest.map_or(ray.t_max, |(_, distance)| distance), false)
+        });
+        nearest
+    }
+
+    /// Whether a sphere blocks the ray between its `t_min` and `t_max`
+    pub fn any_hit(&self, ray: &Ray, spheres: &[Sphere]) -> bool {
+        let mut occluded = false;
+        self.visit(ray, |index, t_max| {
+            occluded = spheres[index].intersect(ray, ray.t_min, t_max).is_some();
+            (t_max, occluded)
+        });
+        occluded
+    }
+
+    /// Whether a sphere blocks each of the rays between its `t_min` and `t_max`, like
+    /// `any_hit` for every ray
+    ///
+    /// The rays are traced together, 64 at a time: the hierarchy is walked once for all of
+    /// them, every node being visited with the rays crossing its box that are not blocked
+    /// yet. Shadow rays of a point towards the lights start at the same place and mostly
+    /// cross the same boxes, so the nodes are loaded once for all the lights
+    ///
+    /// ### Arguments
+    ///
+    /// * `rays` - The rays
+    /// * `spheres` - The spheres the hierarchy was built from
+    ///
+    /// ### Returns
+    ///
+    /// `Vec<bool>` - Whether every ray is blocked, in the order of the rays
+    ///
+    pub fn occluded(&self, rays: &[Ray], spheres: &[Sphere]) -> Vec<bool> {
+        let mut occluded = vec![false; rays.len()];
+        for (packet, blocked) in rays.chunks(64).zip(occluded.chunks_mut(64)) {
+            let mask = self.occluded_packet(packet, spheres);
+            for (k, blocked) in blocked.iter_mut().enumerate() {
+                *blocked = mask & (1 << k) != 0;
+            }
+        }
+        occluded
+    }
+
+    /// Rays of a packet of at most 64 blocked by a sphere, as a bit mask
+    fn occluded_packet(&self, rays: &[Ray], spheres: &[Sphere]) -> u64 {
+        let all = u64::MAX >> (64 - rays.len());
+        let mut blocked = 0;
+        if self.nodes.is_empty() || rays.is_empty() {
+            return blocked;
+        }
+        // the same bound as in `visit`, every node is stacked with the rays still to test
+        let mut stack = [(0, 0); 64];
+        stack[0] = (0, all);
+        let mut size = 1;
+        while size > 0 {
+            size -= 1;
+            let (node, mut active) = stack[size];
+            active &= !blocked;
+            for (k, ray) in rays.iter().enumerate() {
+                if active & (1 << k) != 0 && !self.nodes[node].bounds().hit(ray, ray.t_max) {
+                    active &= !(1 << k);
+                }
+            }
+            if active == 0 {
+                continue;
+            }
+            match self.nodes[node] {
+                Node::Leaf { start, count, .. } => {
+                    for &index in &self.indices[start..start + count] {
+                        for (k, ray) in rays.iter().enumerate() {
+                            if active & (1 << k) != 0
+                                && spheres[index]
+                                    .intersect(ray, ray.t_min, ray.t_max)
+                                    .is_some()
+                            {
+                                blocked |= 1 << k;
+                                active &= !(1 << k);
+                            }
+                        }
+                    }
+                    if blocked == all {
+                        break;
+                    }
+                }
+                Node::Interior { second, .. } => {
+                    stack[size] = (second, active);
+                    stack[size + 1] = (node + 1, active);
+                    size += 2;
+                }
+            }
+        }
+        blocked
+    }
+
+    /// Call `test` with the index of every sphere in a box crossed by the ray, nearest box
+    /// first; `test` gets the farthest distance still of interest and returns the new one,
+    /// and whether the search is over
+    fn visit(&self, ray: &Ray, mut test: impl FnMut(usize, f64) -> (f64, bool)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut t_max = ray.t_max;
+        // the halves split evenly, so the stack never holds more than one node per level
+        let mut stack = [0; 64];
+        let mut size = 1;
+        while size > 0 {
+            size -= 1;
+            let node = stack[size];
+            if !self.nodes[node].bounds().hit(ray, t_max) {
+                continue;
+            }
+            match self.nodes[node] {
+                Node::Leaf { start, count, .. } => {
+                    for &index in &self.indices[start..start + count] {
+                        let (limit, done) = test(index, t_max);
+                        if done {
+                            return;
+                        }
+                        t_max = limit;
+                    }
+                }
+                Node::Interior { second, .. } => {
+                    // the child nearer to the ray origin is visited first, its hits let
+                    // the other child be skipped
+                    let (first, second) = (node + 1, second);
+                    let near_first = (self.nodes[first].bounds().center() - ray.origin)
+                        .dot(&ray.direction)
+                        <= (self.nodes[second].bounds().center() - ray.origin).dot(&ray.direction);
+                    let (near, far) = if near_first {
+                        (first, second)
+                    } else {
+                        (second, first)
+                    };
+                    stack[size] = far;
+                    stack[size + 1] = near;
+                    size += 2;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spheres(count: usize) -> Vec<Sphere> {
+        (0..count)
+            .map(|k| {
+                let material = crate::object::Material::new(
+                    Vector3::new(1.0, 0.0, 0.0),
+                    crate::color::Color::new(0.5, 0.5, 0.5),
+                    10.0,
+                );
+                Sphere::new(Vector3::new(k as f64 * 3.0, 0.0, -10.0), 1.0, material)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn boxes_have_the_depth_of_their_node() {
+        let bvh = Bvh::build(&spheres(5));
+        let boxes = bvh.boxes();
+        assert_eq!(boxes.len(), bvh.nodes.len());
+        assert_eq!(boxes[0].0, 0);
+        // the root holds every other box
+        for (_, bounds) in &boxes[1..] {
+            assert_eq!(boxes[0].1.union(bounds), boxes[0].1);
+        }
+        assert!(boxes.iter().any(|(depth, _)| *depth == 2));
+        assert!(Bvh::build(&[]).boxes().is_empty());
+    }
+
+    #[test]
+    fn obj_has_eight_vertices_and_twelve_lines_per_box() {
+        let bvh = Bvh::build(&spheres(5));
+        let text = bvh.to_obj(None);
+        let count = |prefix: &str| text.lines().filter(|line| line.starts_with(prefix)).count();
+        assert_eq!(count("v "), 8 * bvh.boxes().len());
+        assert_eq!(count("l "), 12 * bvh.boxes().len());
+        assert_eq!(count("g depth_0"), 1);
+        let root = bvh.to_obj(Some(0));
+        assert_eq!(
+            root.lines().filter(|line| line.starts_with("v ")).count(),
+            8
+        );
+        assert!(!root.contains("depth_1"));
+    }
+}
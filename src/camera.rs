@@ -0,0 +1,198 @@
+use crate::bvh::Bounds;
+use crate::lens::LensKind;
+use crate::ray::Ray;
+use crate::render::primary_direction;
+
+use nalgebra::Vector3;
+
+/// Named camera, the field of view and the image size come from the render settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub name: String,
+    pub position: Vector3<f64>,
+    /// Point the camera looks at
+    pub target: Vector3<f64>,
+    /// Approximate up direction, the image is not rolled around the view axis with respect to it
+    pub up: Vector3<f64>,
+    /// Lens the rays leave the camera through, a pinhole by default (see `lens::Lens`)
+    pub lens: LensKind,
+}
+
+impl Default for Camera {
+    /// Camera at the origin looking along the negative Z axis
+    fn default() -> Self {
+        Camera::new("default", Vector3::default(), Vector3::new(0.0, 0.0, -1.0))
+    }
+}
+
+impl Camera {
+    pub fn new(name: &str, position: Vector3<f64>, target: Vector3<f64>) -> Camera {
+        Camera {
+            name: name.to_string(),
+            position,
+            target,
+            up: Vector3::new(0.0, 1.0, 0.0),
+            lens: LensKind::default(),
+        }
+    }
+
+    pub fn with_up(mut self, up: Vector3<f64>) -> Camera {
+        self.up = up;
+        self
+    }
+
+    pub fn with_lens(mut self, lens: LensKind) -> Camera {
+        self.lens = lens;
+        self
+    }
+
+    /// Camera ray through the given point of the image plane and the center of the lens
+    ///
+    /// ### Arguments
+    ///
+    /// * `x` - The horizontal position on the image, in pixels (`i + 0.5` is the pixel center)
+    /// * `y` - The vertical position on the image, in pixels (`j + 0.5` is the pixel center)
+    /// * `width` - The width of the image
+    /// * `height` - The height of the image
+    /// * `fov` - The vertical field of view in radians
+    ///
+    /// ### Returns
+    ///
+    /// Ray - The ray starting at the camera position, with a normalized direction
+    ///
+    pub fn ray(&self, x: f64, y: f64, width: u32, height: u32, fov: f64) -> Ray {
+        // a ray blocked inside a realistic lens falls back to the pinhole one
+        self.lens_ray(x, y, width, height, fov, (0.5, 0.5))
+            .unwrap_or_else(|| {
+                let local = primary_direction(x, y, width, height, fov);
+                Ray::new(self.position, self.to_world(local))
+            })
+    }
+
+    /// Camera ray through the given point of the image plane and a point of the aperture of
+    /// the lens (see `lens::Lens::ray`), `None` when the lens blocks it
+    pub fn lens_ray(
+        &self,
+        x: f64,
+        y: f64,
+        width: u32,
+        height: u32,
+        fov: f64,
+        aperture: (f64, f64),
+    ) -> Option<Ray> {
+        let local = primary_direction(x, y, width, height, fov);
+        if self.lens == LensKind::Pinhole {
+            return Some(Ray::new(self.position, self.to_world(local)));
+        }
+        let image = (
+            (2.0 * x / width as f64 - 1.0) * width as f64 / height as f64,
+            1.0 - 2.0 * y / height as f64,
+        );
+        let (origin, direction) = self.lens.lens().ray(local, image, aperture)?;
+        Some(Ray::new(
+            self.position + self.to_world(origin),
+            self.to_world(direction),
+        ))
+    }
+
+    /// Direction of the camera frame in the scene, the frame looking along the negative Z
+    /// axis
+    fn to_world(&self, v: Vector3<f64>) -> Vector3<f64> {
+        let forward = (self.target - self.position).normalize();
+        let right = forward.cross(&self.up).normalize();
+        let up = right.cross(&forward);
+        right * v.x + up * v.y - forward * v.z
+    }
+
+    /// Distance of a point in front of the camera along its view axis (negative behind it)
+    pub fn depth(&self, point: Vector3<f64>) -> f64 {
+        let forward = (self.target - self.position).normalize();
+        (point - self.position).dot(&forward)
+    }
+
+    /// Point of the image plane a point of the scene is seen at, the inverse of `ray`
+    ///
+    /// ### Arguments
+    ///
+    /// * `point` - The point of the scene
+    /// * `width` - The width of the image
+    /// * `height` - The height of the image
+    /// * `fov` - The vertical field of view in radians
+    ///
+    /// ### Returns
+    ///
+    /// `Option<(f64, f64)>` - The horizontal and vertical position on the image in pixels,
+    /// `None` if the point is not in front of the camera
+    ///
+    pub fn project(
+        &self,
+        point: Vector3<f64>,
+        width: u32,
+        height: u32,
+        fov: f64,
+    ) -> Option<(f64, f64)> {
+        let forward = (self.target - self.position).normalize();
+        let right = forward.cross(&self.up).normalize();
+        let up = right.cross(&forward);
+        let offset = point - self.position;
+        let depth = offset.dot(&forward);
+        if depth <= 0.0 {
+            return None;
+        }
+        let scale = (fov / 2.0).tan();
+        let aspect = width as f64 / height as f64;
+        let x = (offset.dot(&right) / depth / (scale * aspect) + 1.0) * width as f64 / 2.0;
+        let y = (1.0 - offset.dot(&up) / depth / scale) * height as f64 / 2.0;
+        Some((x, y))
+    }
+
+    /// The camera moved back or forth along its view axis to see the whole of a box, e.g.
+    /// the bounds of a scene that doesn't fit the camera it came with
+    ///
+    /// The camera keeps the direction it looks in and looks at the center of the box; the
+    /// sphere around the box fits the narrower of the two fields of view, so the box is seen
+    /// whole from any direction. A thin lens gets focused on the center
+    ///
+    /// ### Arguments
+    ///
+    /// * `bounds` - The box to see, see `Scene::bounds`
+    /// * `padding` - The margin around the box, a fraction of its size (0.1 for 10%)
+    /// * `width` - The width of the image
+    /// * `height` - The height of the image
+    /// * `fov` - The vertical field of view in radians
+    ///
+    /// ### Returns
+    ///
+    /// `Camera` - The framed camera, the camera as it is for an empty or infinite box
+    ///
+    pub fn frame(
+        &self,
+        bounds: &Bounds,
+        padding: f64,
+        width: u32,
+        height: u32,
+        fov: f64,
+    ) -> Camera {
+        let radius = (bounds.max - bounds.min).norm() / 2.0;
+        if !radius.is_finite() || bounds.min.iter().zip(bounds.max.iter()).any(|(a, b)| a > b) {
+            return self.clone();
+        }
+        let forward = (self.target - self.position)
+            .try_normalize(0.0)
+            .unwrap_or(Vector3::new(0.0, 0.0, -1.0));
+        let aspect = width as f64 / height.max(1) as f64;
+        let horizontal = 2.0 * ((fov / 2.0).tan() * aspect).atan();
+        let half = fov.min(horizontal) / 2.0;
+        let distance = radius * (1.0 + padding.max(0.0)) / half.sin();
+        let center = bounds.center();
+        let mut camera = Camera {
+            position: center - forward * distance,
+            target: center,
+            ..self.clone()
+        };
+        if let LensKind::Thin(lens) = &mut camera.lens {
+            lens.focus_distance = distance;
+        }
+        camera
+    }
+}
@@ -0,0 +1,142 @@
+use crate::image::Image;
+use crate::label::draw_label;
+use crate::label::label_size;
+use crate::settings::RenderSettings;
+
+use std::time::SystemTime;
+
+/// Corner of an image a caption is burnt into
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    #[default]
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /// Corner from its name: `top-left`, `top-right`, `bottom-left` or `bottom-right`
+    pub fn from_name(name: &str) -> Option<Corner> {
+        match name {
+            "top-left" => Some(Corner::TopLeft),
+            "top-right" => Some(Corner::TopRight),
+            "bottom-left" => Some(Corner::BottomLeft),
+            "bottom-right" => Some(Corner::BottomRight),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Corner::TopLeft => "top-left",
+            Corner::TopRight => "top-right",
+            Corner::BottomLeft => "bottom-left",
+            Corner::BottomRight => "bottom-right",
+        }
+    }
+}
+
+/// Line of text burnt into a corner of a review render (dailies): the scene, the frame of
+/// an image sequence, the samples per pixel and the date of the render
+#[derive(Clone, Debug, PartialEq)]
+pub struct Caption {
+    pub scene: String,
+    /// Frame of the image in its sequence, `None` for a still image
+    pub frame: Option<u32>,
+    pub samples_per_pixel: u32,
+    /// Date of the render, `YYYY-MM-DD`
+    pub date: String,
+    pub corner: Corner,
+}
+
+impl Caption {
+    /// Caption of a still image rendered today, in the bottom left corner
+    pub fn new(scene: &str, settings: &RenderSettings) -> Caption {
+        Caption {
+            scene: scene.to_string(),
+            frame: None,
+            samples_per_pixel: settings.samples_per_pixel.max(1),
+            date: today(),
+            corner: Corner::default(),
+        }
+    }
+
+    pub fn with_frame(mut self, frame: u32) -> Caption {
+        self.frame = Some(frame);
+        self
+    }
+
+    pub fn with_date(mut self, date: &str) -> Caption {
+        self.date = date.to_string();
+        self
+    }
+
+    pub fn with_corner(mut self, corner: Corner) -> Caption {
+        self.corner = corner;
+        self
+    }
+
+    /// Text of the caption, drawn in capitals by the built-in font
+    pub fn text(&self) -> String {
+        let mut parts = vec![self.scene.clone()];
+        if let Some(frame) = self.frame {
+            parts.push(format!("frame {:04}", frame));
+        }
+        parts.push(format!("{} spp", self.samples_per_pixel));
+        parts.push(self.date.clone());
+        parts.join("  ")
+    }
+
+    /// Draw the caption into its corner of an image, white on a black box, with a font
+    /// scaled up with the image (see `label::draw_label`)
+    ///
+    /// Captions wider than the image are cut at its edge
+    pub fn burn(&self, image: &mut Image) {
+        let text = self.text();
+        let scale = (image.height / 256).max(1);
+        let (width, height) = label_size(&text, scale);
+        let margin = 2 * scale;
+        let left = margin;
+        let right = image.width.saturating_sub(width + margin);
+        let top = margin;
+        let bottom = image.height.saturating_sub(height + margin);
+        let (x, y) = match self.corner {
+            Corner::TopLeft => (left, top),
+            Corner::TopRight => (right, top),
+            Corner::BottomLeft => (left, bottom),
+            Corner::BottomRight => (right, bottom),
+        };
+        draw_label(image, x, y, &text, scale);
+    }
+}
+
+/// Date of the current day in UTC, `YYYY-MM-DD`
+pub fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (year, month, day) = civil_date((seconds / 86400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Year, month and day of a number of days since 1970-01-01 in the Gregorian calendar
+/// (Howard Hinnant's `civil_from_days`)
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    // days counted from 0000-03-01, so leap days fall at the end of the years
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
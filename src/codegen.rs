@@ -0,0 +1,766 @@
+use crate::color::Color;
+use crate::envmap::Environment;
+use crate::envmap::EnvironmentSource;
+use crate::json::Json;
+use crate::lens::LensKind;
+use crate::object::LightKind;
+use crate::object::Material;
+use crate::object::SceneLight;
+use crate::scene::Scene;
+use crate::settings::color_to_json;
+use crate::settings::vector_to_json;
+use crate::sky::Dome;
+use crate::texture::UvTransform;
+
+use nalgebra::Vector3;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+/// Scene converted to a scene file or to Rust code, with what the conversion left out
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SceneExport {
+    pub text: String,
+    /// Parts of the scene the text doesn't hold, one description each
+    pub omitted: Vec<String>,
+}
+
+/// Scene file describing a scene, e.g. one built in code, loading back to the same scene
+///
+/// Named materials shared by several objects go to the `materials` library, the others are
+/// written with their objects. Scenes moved next to their camera are written where they
+/// were, asking to be moved again (`camera_relative`). The procedural objects without a
+/// description are left out (see `Procedural::to_json`)
+///
+/// ### Arguments
+///
+/// * `scene` - The scene to describe
+///
+/// ### Returns
+///
+/// `SceneExport` - The JSON text of the scene file
+///
+pub fn scene_to_json(scene: &Scene) -> SceneExport {
+    let mut omitted = Vec::new();
+    let mut entries: Vec<(String, Json)> = Vec::new();
+    let mut add = |key: &str, value: Json| entries.push((key.to_string(), value));
+    let origin = scene.origin;
+
+    let library = library_materials(scene);
+    if !library.is_empty() {
+        add(
+            "materials",
+            Json::Array(library.iter().map(|m| m.to_json()).collect()),
+        );
+    }
+    let objects = scene
+        .spheres
+        .iter()
+        .map(|sphere| {
+            let mut object = Vec::new();
+            if let Some(name) = &sphere.name {
+                object.push(("name".to_string(), Json::String(name.clone())));
+            }
+            object.push(("center".to_string(), vector_to_json(sphere.center + origin)));
+            object.push(("radius".to_string(), Json::Number(sphere.radius)));
+            if sphere.holdout {
+                object.push(("holdout".to_string(), Json::Bool(true)));
+            }
+            let material = match library.iter().any(|m| same_material(m, &sphere.material)) {
+                true => Json::String(sphere.material.name.clone().unwrap_or_default()),
+                false => sphere.material.to_json(),
+            };
+            object.push(("material".to_string(), material));
+            Json::Object(object)
+        })
+        .collect();
+    add("objects", Json::Array(objects));
+
+    let mut procedurals = Vec::new();
+    for (index, procedural) in scene.procedurals.iter().enumerate() {
+        match procedural.generator.to_json() {
+            Some(json) => procedurals.push(json),
+            None => omitted.push(format!("procedural object {} has no description", index)),
+        }
+    }
+    if !procedurals.is_empty() {
+        add("procedural", Json::Array(procedurals));
+    }
+
+    let lights = scene
+        .lights
+        .iter()
+        .map(|light| {
+            let mut entries = Vec::new();
+            if let Some(name) = &light.name {
+                entries.push(("name".to_string(), Json::String(name.clone())));
+            }
+            match light.kind {
+                LightKind::Directional {
+                    direction,
+                    angular_diameter,
+                } => {
+                    entries.push(("direction".to_string(), vector_to_json(direction)));
+                    // the directional lights of scene files are suns unless they say otherwise
+                    let sun = SceneLight::sun(direction, light.intensity);
+                    if !matches!(sun.kind, LightKind::Directional { angular_diameter: a, .. }
+                        if a == angular_diameter)
+                    {
+                        let diameter = Json::Number(angular_diameter.to_degrees());
+                        entries.push(("angular_diameter".to_string(), diameter));
+                    }
+                }
+                LightKind::Spot {
+                    direction,
+                    cos_inner,
+                    cos_outer,
+                } => {
+                    let position = vector_to_json(light.position + origin);
+                    entries.push(("position".to_string(), position));
+                    let (angle, softness) = spot_cone(cos_inner, cos_outer);
+                    let spot = Json::Object(vec![
+                        ("direction".to_string(), vector_to_json(direction)),
+                        ("angle".to_string(), Json::Number(angle.to_degrees())),
+                        ("softness".to_string(), Json::Number(softness)),
+                    ]);
+                    entries.push(("spot".to_string(), spot));
+                }
+                LightKind::Point => {
+                    let position = vector_to_json(light.position + origin);
+                    entries.push(("position".to_string(), position));
+                }
+            }
+            entries.push(("intensity".to_string(), Json::Number(light.intensity)));
+            if light.color != Color::WHITE {
+                entries.push(("color".to_string(), color_to_json(light.color)));
+            }
+            if light.radius != 0.0 {
+                entries.push(("radius".to_string(), Json::Number(light.radius)));
+            }
+            if light.shadow_samples != 1 {
+                let samples = Json::Number(light.shadow_samples as f64);
+                entries.push(("shadow_samples".to_string(), samples));
+            }
+            if let Some(distance) = light.max_distance {
+                entries.push(("max_distance".to_string(), Json::Number(distance)));
+            }
+            Json::Object(entries)
+        })
+        .collect();
+    add("lights", Json::Array(lights));
+
+    let cameras = scene
+        .cameras
+        .iter()
+        .map(|camera| {
+            let mut entries = vec![
+                ("name".to_string(), Json::String(camera.name.clone())),
+                (
+                    "position".to_string(),
+                    vector_to_json(camera.position + origin),
+                ),
+                ("target".to_string(), vector_to_json(camera.target + origin)),
+            ];
+            if camera.up != Vector3::y() {
+                entries.push(("up".to_string(), vector_to_json(camera.up)));
+            }
+            if camera.lens != LensKind::Pinhole {
+                entries.push(("lens".to_string(), camera.lens.to_json()));
+            }
+            Json::Object(entries)
+        })
+        .collect();
+    add("cameras", Json::Array(cameras));
+
+    if let Some(environment) = &scene.environment {
+        add("environment", environment.to_json());
+    }
+    if let Some(medium) = &scene.medium {
+        add("medium", medium.to_json());
+    }
+    if let Some(background) = scene.background {
+        add("background", color_to_json(background));
+    }
+    if let Some(max_depth) = scene.max_depth {
+        add("max_depth", Json::Number(max_depth as f64));
+    }
+    if !scene.outputs.is_empty() {
+        add(
+            "outputs",
+            Json::Array(scene.outputs.iter().map(|o| o.to_json()).collect()),
+        );
+    }
+    if let Some(animation) = &scene.animation {
+        add("animation", animation.to_json());
+    }
+    if !scene.layers.is_empty() {
+        add(
+            "layers",
+            Json::Array(scene.layers.iter().map(|l| l.to_json()).collect()),
+        );
+    }
+    if origin != Vector3::zeros() {
+        add("camera_relative", Json::Bool(true));
+    }
+
+    SceneExport {
+        text: Json::Object(entries).to_pretty_string() + "\n",
+        omitted,
+    }
+}
+
+/// Rust function building a scene with the builders of the crate, the code of a scene
+/// file for a program or a minimal reproduction of a bug
+///
+/// Equal materials are bound to one variable and shared by their objects. The textures,
+/// media, lenses and outputs that aren't plain values are read from their JSON
+/// descriptions (`texture_from_json`, `medium_from_json`, `LensKind::from_json`, ...)
+/// inside the function; procedural objects without a description are left out
+///
+/// ### Arguments
+///
+/// * `scene` - The scene to build
+///
+/// ### Returns
+///
+/// `SceneExport` - The code of a module with a `scene()` function returning the scene
+///
+pub fn scene_to_rust(scene: &Scene) -> SceneExport {
+    let mut code = RustCode::default();
+    let mut omitted = Vec::new();
+    let origin = scene.origin;
+    code.import("ray_tracing::scene::Scene");
+    code.import("std::error::Error");
+
+    // every material is a variable, moved into its last object
+    let mut materials: Vec<(&Arc<Material>, String, usize)> = Vec::new();
+    for sphere in &scene.spheres {
+        match materials
+            .iter_mut()
+            .find(|(m, _, _)| same_material(m, &sphere.material))
+        {
+            Some((_, _, uses)) => *uses += 1,
+            None => {
+                let base = match &sphere.material.name {
+                    Some(name) => format!("{}_material", identifier(name)),
+                    None => format!("material_{}", materials.len() + 1),
+                };
+                let mut variable = base.clone();
+                let mut copy = 1;
+                while materials.iter().any(|(_, known, _)| *known == variable) {
+                    copy += 1;
+                    variable = format!("{}_{}", base, copy);
+                }
+                materials.push((&sphere.material, variable, 1));
+            }
+        }
+    }
+    let mut body = String::new();
+    for (material, variable, _) in &materials {
+        code.import("std::sync::Arc");
+        let chain = code.material(material);
+        let binding = format!("let {} = Arc::new", variable);
+        body += &format!("    {}\n", call(&binding, &chain, 4, ";"));
+    }
+
+    let mut calls = Vec::new();
+    for sphere in &scene.spheres {
+        let (_, variable, uses) = materials
+            .iter_mut()
+            .find(|(m, _, _)| same_material(m, &sphere.material))
+            .expect("the materials of the spheres are listed");
+        *uses -= 1;
+        let material = match uses {
+            0 => variable.clone(),
+            _ => format!("{}.clone()", variable),
+        };
+        code.import("ray_tracing::object::Sphere");
+        let mut chain = vec![format!(
+            "Sphere::new({}, {}, {})",
+            code.vector(sphere.center + origin),
+            float(sphere.radius),
+            material
+        )];
+        if let Some(name) = &sphere.name {
+            chain.push(format!(".with_name({:?})", name));
+        }
+        if sphere.holdout {
+            chain.push(".with_holdout(true)".to_string());
+        }
+        calls.push(call(".add_sphere", &chain, 8, ""));
+    }
+
+    for (index, procedural) in scene.procedurals.iter().enumerate() {
+        match procedural
+            .generator
+            .to_json()
+            .and_then(|json| code.sphereflake(&json))
+        {
+            Some(chain) => calls.push(call(".add_procedural", &chain, 8, "")),
+            None => omitted.push(format!("procedural object {} has no description", index)),
+        }
+    }
+
+    for light in &scene.lights {
+        let chain = code.light(light, origin);
+        calls.push(call(".add_light", &chain, 8, ""));
+    }
+
+    for camera in &scene.cameras {
+        code.import("ray_tracing::camera::Camera");
+        let mut chain = vec![format!(
+            "Camera::new({:?}, {}, {})",
+            camera.name,
+            code.vector(camera.position + origin),
+            code.vector(camera.target + origin)
+        )];
+        if camera.up != Vector3::y() {
+            chain.push(format!(".with_up({})", code.vector(camera.up)));
+        }
+        if camera.lens != LensKind::Pinhole {
+            code.import("ray_tracing::lens::LensKind");
+            let lens = code.json(&camera.lens.to_json());
+            chain.push(format!(".with_lens(LensKind::from_json(&{})?)", lens));
+        }
+        calls.push(call(".camera", &chain, 8, ""));
+    }
+
+    if let Some(environment) = &scene.environment {
+        let chain = code.environment(environment);
+        calls.push(call(".environment", &chain, 8, ""));
+    }
+    if let Some(medium) = &scene.medium {
+        code.import("ray_tracing::medium::medium_from_json");
+        let medium = format!("medium_from_json(&{})?", code.json(&medium.to_json()));
+        calls.push(call(".medium", &[medium], 8, ""));
+    }
+    if let Some(background) = scene.background {
+        calls.push(format!(".background({})", code.color(background)));
+    }
+    if let Some(max_depth) = scene.max_depth {
+        calls.push(format!(".max_depth({})", max_depth));
+    }
+    for layer in &scene.layers {
+        code.import("ray_tracing::layers::LayerSpec");
+        let layer = code.json(&layer.to_json());
+        calls.push(format!(".add_layer(LayerSpec::from_json(&{})?)", layer));
+    }
+    if let Some(animation) = &scene.animation {
+        code.import("ray_tracing::animation::SceneAnimation");
+        let animation = code.json(&animation.to_json());
+        calls.push(format!(
+            ".animation(SceneAnimation::from_json(&{})?)",
+            animation
+        ));
+    }
+    if origin != Vector3::zeros() {
+        calls.push(".camera_relative(true)".to_string());
+    }
+
+    let mutable = if scene.outputs.is_empty() { "" } else { "mut " };
+    body += &format!("    let {}scene = Scene::builder()\n", mutable);
+    for call in calls {
+        body += &format!("        {}\n", call);
+    }
+    body += "        .build()?;\n";
+    if !scene.outputs.is_empty() {
+        code.import("ray_tracing::outputs::OutputSpec");
+        body += "    scene.outputs = vec![\n";
+        for output in &scene.outputs {
+            let output = code.json(&output.to_json());
+            body += &format!("        OutputSpec::from_json(&{})?,\n", output);
+        }
+        body += "    ];\n";
+    }
+    body += "    Ok(scene)\n";
+
+    // the paths of the crate first, then the others, functions before types in a module
+    let mut imports: Vec<&String> = code.imports.iter().collect();
+    imports.sort_by_key(|path| {
+        let (module, item) = path.rsplit_once("::").unwrap_or(("", path));
+        let starts_uppercase = item.starts_with(|c: char| c.is_ascii_uppercase());
+        (
+            !path.starts_with("ray_tracing::"),
+            module,
+            starts_uppercase,
+            item,
+        )
+    });
+    let mut text = String::new();
+    for (k, path) in imports.iter().enumerate() {
+        if k > 0 && path.starts_with("ray_tracing::") != imports[k - 1].starts_with("ray_tracing::")
+        {
+            text += "\n";
+        }
+        text += &format!("use {};\n", path);
+    }
+    text += "\n/// Scene built with the builders of the `ray_tracing` crate\n";
+    text += "pub fn scene() -> Result<Scene, Box<dyn Error>> {\n";
+    text += &body;
+    text += "}\n";
+    SceneExport { text, omitted }
+}
+
+/// Whether two objects are made of the same material, shared or equal
+fn same_material(a: &Arc<Material>, b: &Arc<Material>) -> bool {
+    Arc::ptr_eq(a, b) || a == b
+}
+
+/// Named materials of the objects, each shared by several objects and the only material
+/// with its name, in the order of their first objects
+fn library_materials(scene: &Scene) -> Vec<Arc<Material>> {
+    let mut materials: Vec<&Arc<Material>> = Vec::new();
+    for sphere in &scene.spheres {
+        if !materials.iter().any(|m| same_material(m, &sphere.material)) {
+            materials.push(&sphere.material);
+        }
+    }
+    materials
+        .iter()
+        .filter(|material| {
+            let Some(name) = &material.name else {
+                return false;
+            };
+            let uses = scene
+                .spheres
+                .iter()
+                .filter(|sphere| same_material(&sphere.material, material))
+                .count();
+            let namesakes = materials
+                .iter()
+                .filter(|other| other.name.as_ref() == Some(name))
+                .count();
+            uses > 1 && namesakes == 1
+        })
+        .map(|material| (*material).clone())
+        .collect()
+}
+
+/// Half angle of the cone of a spot light in radians and its softness, the arguments of
+/// `SceneLight::spot`, from the cosines of its inner and outer cones
+fn spot_cone(cos_inner: f64, cos_outer: f64) -> (f64, f64) {
+    let angle = cos_outer.clamp(-1.0, 1.0).acos();
+    let softness = if angle > 0.0 {
+        1.0 - cos_inner.clamp(-1.0, 1.0).acos() / angle
+    } else {
+        0.0
+    };
+    (angle, softness)
+}
+
+/// Snake case identifier made of the letters and digits of a name
+fn identifier(name: &str) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !identifier.starts_with(|c: char| c.is_ascii_lowercase()) {
+        identifier.insert(0, 'm');
+    }
+    identifier
+}
+
+/// Rust literal of a number, which always has a decimal point or an exponent
+fn float(value: f64) -> String {
+    if value.is_nan() {
+        "f64::NAN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 {
+            "f64::INFINITY"
+        } else {
+            "f64::NEG_INFINITY"
+        }
+        .to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// Width of the lines of the generated code, past which method chains are broken up
+const LINE_WIDTH: usize = 100;
+
+/// Method chain, a constructor and the methods called on its value, followed by `end`, on
+/// one line if it fits or with one method per line below it
+fn layout(chain: &[String], indent: usize, end: &str) -> String {
+    let line = chain.concat() + end;
+    if indent + line.len() <= LINE_WIDTH {
+        return line;
+    }
+    chain.join(&format!("\n{}", " ".repeat(indent + 4))) + end
+}
+
+/// Call taking a method chain, on one line if it fits or with the chain on the lines below
+/// it, e.g. `.add_sphere(Sphere::new(...).with_name(...))`
+fn call(function: &str, chain: &[String], indent: usize, end: &str) -> String {
+    let line = format!("{}({}){}", function, chain.concat(), end);
+    if indent + line.len() <= LINE_WIDTH {
+        return line;
+    }
+    format!(
+        "{}(\n{}{}\n{}){}",
+        function,
+        " ".repeat(indent + 4),
+        layout(chain, indent + 4, ","),
+        " ".repeat(indent),
+        end
+    )
+}
+
+/// Code being generated, with the paths it uses
+#[derive(Default)]
+struct RustCode {
+    imports: BTreeSet<String>,
+}
+
+impl RustCode {
+    fn import(&mut self, path: &str) {
+        self.imports.insert(path.to_string());
+    }
+
+    fn vector(&mut self, vector: Vector3<f64>) -> String {
+        self.import("nalgebra::Vector3");
+        format!(
+            "Vector3::new({}, {}, {})",
+            float(vector.x),
+            float(vector.y),
+            float(vector.z)
+        )
+    }
+
+    fn color(&mut self, color: Color) -> String {
+        self.import("ray_tracing::color::Color");
+        let [r, g, b] = color.to_array();
+        format!("Color::new({}, {}, {})", float(r), float(g), float(b))
+    }
+
+    /// JSON value parsed at run time, from a raw string literal on one line
+    fn json(&mut self, json: &Json) -> String {
+        self.import("ray_tracing::json::Json");
+        let text = json
+            .to_pretty_string()
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut hashes = "#".to_string();
+        while text.contains(&format!("\"{}", hashes)) {
+            hashes += "#";
+        }
+        format!("Json::parse(r{}\"{}\"{})?", hashes, text, hashes)
+    }
+
+    fn material(&mut self, material: &Material) -> Vec<String> {
+        self.import("ray_tracing::object::Material");
+        let defaults = Material::default();
+        let albedo = self.vector(material.albedo);
+        let texture = material.diffuse_color.to_json();
+        let solid = match texture.as_array() {
+            Some([r, g, b]) => r
+                .as_f64()
+                .zip(g.as_f64())
+                .zip(b.as_f64())
+                .map(|((r, g), b)| Color::new(r, g, b)),
+            _ => None,
+        };
+        let mut chain = vec![format!(
+            "Material::new({}, {}, {})",
+            albedo,
+            self.color(solid.unwrap_or(Color::WHITE)),
+            float(material.specular_exponent)
+        )];
+        if solid.is_none() {
+            self.import("ray_tracing::texture::texture_from_json");
+            self.import("std::path::Path");
+            chain.push(format!(
+                ".with_diffuse_texture(texture_from_json(&{}, Path::new(\"\"))?)",
+                self.json(&texture)
+            ));
+        }
+        if material.refraction != defaults.refraction
+            || material.refractive_index != defaults.refractive_index
+        {
+            chain.push(format!(
+                ".with_refraction({}, {})",
+                float(material.refraction),
+                float(material.refractive_index)
+            ));
+        }
+        if material.priority != defaults.priority {
+            chain.push(format!(".with_priority({})", material.priority));
+        }
+        if let Some(interior) = &material.interior {
+            self.import("ray_tracing::medium::medium_from_json");
+            chain.push(format!(
+                ".with_interior(medium_from_json(&{})?)",
+                self.json(&interior.to_json())
+            ));
+        }
+        if let Some(texture) = &material.texture {
+            chain.push(format!(".with_texture({:?})", texture));
+        }
+        if material.texture_scale != defaults.texture_scale {
+            let [u, v] = material.texture_scale;
+            chain.push(format!(".with_texture_scale({}, {})", float(u), float(v)));
+        }
+        if material.texture_space != defaults.texture_space {
+            self.import("ray_tracing::colorspace::TextureSpace");
+            chain.push(format!(
+                ".with_texture_space(TextureSpace::{:?})",
+                material.texture_space
+            ));
+        }
+        if material.uv_transform != UvTransform::default() {
+            self.import("ray_tracing::texture::UvTransform");
+            let UvTransform {
+                scale,
+                offset,
+                rotation,
+            } = material.uv_transform;
+            chain.push(format!(
+                ".with_uv_transform(UvTransform {{ scale: [{}, {}], offset: [{}, {}], rotation: {} }})",
+                float(scale[0]),
+                float(scale[1]),
+                float(offset[0]),
+                float(offset[1]),
+                float(rotation)
+            ));
+        }
+        if let Some(name) = &material.name {
+            chain.push(format!(".with_name({:?})", name));
+        }
+        chain
+    }
+
+    fn light(&mut self, light: &SceneLight, origin: Vector3<f64>) -> Vec<String> {
+        self.import("ray_tracing::object::SceneLight");
+        let intensity = float(light.intensity);
+        let constructor = match light.kind {
+            LightKind::Point => {
+                format!(
+                    "SceneLight::new({}, {})",
+                    self.vector(light.position + origin),
+                    intensity
+                )
+            }
+            LightKind::Directional {
+                direction,
+                angular_diameter,
+            } => format!(
+                "SceneLight::directional({}, {}, {})",
+                self.vector(direction),
+                intensity,
+                float(angular_diameter)
+            ),
+            LightKind::Spot {
+                direction,
+                cos_inner,
+                cos_outer,
+            } => {
+                let (angle, softness) = spot_cone(cos_inner, cos_outer);
+                format!(
+                    "SceneLight::spot({}, {}, {}, {}, {})",
+                    self.vector(light.position + origin),
+                    self.vector(direction),
+                    intensity,
+                    float(angle),
+                    float(softness)
+                )
+            }
+        };
+        let mut chain = vec![constructor];
+        if light.color != Color::WHITE {
+            chain.push(format!(".with_color({})", self.color(light.color)));
+        }
+        if light.radius != 0.0 {
+            chain.push(format!(".with_radius({})", float(light.radius)));
+        }
+        if light.shadow_samples != 1 {
+            chain.push(format!(".with_shadow_samples({})", light.shadow_samples));
+        }
+        if let Some(distance) = light.max_distance {
+            chain.push(format!(".with_max_distance({})", float(distance)));
+        }
+        if let Some(name) = &light.name {
+            chain.push(format!(".with_name({:?})", name));
+        }
+        chain
+    }
+
+    fn environment(&mut self, environment: &Environment) -> Vec<String> {
+        self.import("ray_tracing::envmap::Environment");
+        let constructor = match &environment.source {
+            EnvironmentSource::Map(path) => format!("Environment::new({:?})", path),
+            EnvironmentSource::Dome(dome) => {
+                self.import("ray_tracing::sky::Dome");
+                let dome = match dome {
+                    Dome::Color(color) => format!("Dome::Color({})", self.color(*color)),
+                    Dome::Blackbody(kelvin) => format!("Dome::Blackbody({})", float(*kelvin)),
+                    Dome::Sky(sky) => {
+                        self.import("ray_tracing::sky::PhysicalSky");
+                        let (elevation, azimuth) = sky.sun_angles();
+                        format!(
+                            "Dome::Sky(PhysicalSky::new({}, {}).with_turbidity({}).with_sun_intensity({}))",
+                            float(elevation),
+                            float(azimuth),
+                            float(sky.turbidity),
+                            float(sky.sun_intensity)
+                        )
+                    }
+                };
+                format!("Environment::dome({})", dome)
+            }
+        };
+        let mut chain = vec![constructor];
+        if environment.rotation != 0.0 {
+            chain.push(format!(".with_rotation({})", float(environment.rotation)));
+        }
+        if environment.intensity != 1.0 {
+            chain.push(format!(".with_intensity({})", float(environment.intensity)));
+        }
+        if environment.space != Default::default() {
+            self.import("ray_tracing::colorspace::TextureSpace");
+            chain.push(format!(
+                ".with_space(TextureSpace::{:?})",
+                environment.space
+            ));
+        }
+        if let Some(ground) = &environment.ground {
+            self.import("ray_tracing::envmap::GroundProjection");
+            chain.push(format!(
+                ".with_ground_projection(GroundProjection::new({}, {}, {}))",
+                self.vector(ground.center),
+                float(ground.height),
+                float(ground.radius)
+            ));
+        }
+        chain
+    }
+
+    /// Sphereflake from its description (see `Procedural::to_json`), `None` for other
+    /// procedurals
+    fn sphereflake(&mut self, json: &Json) -> Option<Vec<String>> {
+        if json.get("type").and_then(Json::as_str) != Some("sphereflake") {
+            return None;
+        }
+        let number = |key: &str| json.get(key).and_then(Json::as_f64);
+        let center = match json.get("center").and_then(Json::as_array) {
+            Some([x, y, z]) => Vector3::new(x.as_f64()?, y.as_f64()?, z.as_f64()?),
+            _ => return None,
+        };
+        let material = Material::from_json(json.get("material")?).ok()?;
+        self.import("ray_tracing::procedural::SphereFlake");
+        self.import("std::sync::Arc");
+        Some(vec![format!(
+            "Arc::new(SphereFlake::new({}, {}, {}, {}))",
+            self.vector(center),
+            float(number("radius")?),
+            number("depth")? as u32,
+            self.material(&material).concat()
+        )])
+    }
+}
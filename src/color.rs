@@ -0,0 +1,139 @@
+use nalgebra::Vector3;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Sub};
+
+/// Linear RGB color or radiance, kept apart from geometric `Vector3` values so the two
+/// can't be mixed up
+///
+/// All the radiometric code goes through this type, so a spectral representation can
+/// replace the three channels later without touching the geometry
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Color {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::new(1.0, 1.0, 1.0);
+
+    pub const fn new(r: f64, g: f64, b: f64) -> Color {
+        Color { r, g, b }
+    }
+
+    /// Gray with all the channels set to `value`
+    pub const fn gray(value: f64) -> Color {
+        Color::new(value, value, value)
+    }
+
+    /// Relative luminance (Rec.709 / sRGB primaries)
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// Largest of the three channels
+    pub fn max_channel(&self) -> f64 {
+        self.r.max(self.g).max(self.b)
+    }
+
+    /// Sum of the squared channels, e.g. to measure the difference of two colors
+    pub fn norm_squared(&self) -> f64 {
+        self.r * self.r + self.g * self.g + self.b * self.b
+    }
+
+    /// Apply a function to every channel
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Color {
+        Color::new(f(self.r), f(self.g), f(self.b))
+    }
+
+    /// Clamp every channel to the range `[min, max]`
+    pub fn clamp(&self, min: f64, max: f64) -> Color {
+        self.map(|c| c.clamp(min, max))
+    }
+
+    /// Encode with a pure power curve (`c^(1 / gamma)`), negative channels become 0
+    pub fn gamma(&self, gamma: f64) -> Color {
+        self.map(|c| c.max(0.0).powf(1.0 / gamma))
+    }
+
+    /// Linear interpolation from `self` (`t = 0`) to `other` (`t = 1`)
+    pub fn lerp(&self, other: Color, t: f64) -> Color {
+        *self + (other - *self) * t
+    }
+
+    /// Whether every channel is a finite number
+    pub fn is_finite(&self) -> bool {
+        self.r.is_finite() && self.g.is_finite() && self.b.is_finite()
+    }
+
+    pub fn to_array(&self) -> [f64; 3] {
+        [self.r, self.g, self.b]
+    }
+}
+
+impl From<Vector3<f64>> for Color {
+    fn from(v: Vector3<f64>) -> Color {
+        Color::new(v.x, v.y, v.z)
+    }
+}
+
+impl From<Color> for Vector3<f64> {
+    fn from(c: Color) -> Vector3<f64> {
+        Vector3::new(c.r, c.g, c.b)
+    }
+}
+
+impl Add for Color {
+    type Output = Color;
+    fn add(self, other: Color) -> Color {
+        Color::new(self.r + other.r, self.g + other.g, self.b + other.b)
+    }
+}
+
+impl AddAssign for Color {
+    fn add_assign(&mut self, other: Color) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Color {
+    type Output = Color;
+    fn sub(self, other: Color) -> Color {
+        Color::new(self.r - other.r, self.g - other.g, self.b - other.b)
+    }
+}
+
+/// Channel-wise product (filtering a light by a surface color)
+impl Mul for Color {
+    type Output = Color;
+    fn mul(self, other: Color) -> Color {
+        Color::new(self.r * other.r, self.g * other.g, self.b * other.b)
+    }
+}
+
+impl Mul<f64> for Color {
+    type Output = Color;
+    fn mul(self, s: f64) -> Color {
+        Color::new(self.r * s, self.g * s, self.b * s)
+    }
+}
+
+impl MulAssign<f64> for Color {
+    fn mul_assign(&mut self, s: f64) {
+        *self = *self * s;
+    }
+}
+
+impl Div<f64> for Color {
+    type Output = Color;
+    fn div(self, s: f64) -> Color {
+        Color::new(self.r / s, self.g / s, self.b / s)
+    }
+}
+
+impl Sum for Color {
+    fn sum<I: Iterator<Item = Color>>(iter: I) -> Color {
+        iter.fold(Color::BLACK, |a, b| a + b)
+    }
+}
@@ -0,0 +1,369 @@
+use crate::color::Color;
+use crate::look::FilmLook;
+
+use nalgebra::{Matrix3, Vector3};
+use std::sync::Arc;
+
+/// Color space in which the scene colors are authored and radiance is computed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WorkingSpace {
+    /// Linear Rec.709 / sRGB primaries
+    #[default]
+    LinearSrgb,
+    /// ACEScg (AP1 primaries, linear)
+    AcesCg,
+}
+
+/// Color space the texels of a texture file are stored in
+///
+/// Color textures (base colors) are usually painted in sRGB, data textures (roughness,
+/// normal maps, masks) hold linear values that must not be converted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureSpace {
+    /// Values used as they are stored, neither decoded nor converted to the working space
+    #[default]
+    Linear,
+    /// sRGB transfer function and primaries, decoded to linear values in the working space
+    Srgb,
+}
+
+/// Transform applied to scene-referred radiance to get display-referred values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViewTransform {
+    /// Plain clipping of values above 1.0
+    Standard,
+    /// Simple `c / (1 + c)` tone curve
+    Reinhard,
+    /// Fitted ACES RRT + sRGB ODT
+    AcesSrgb,
+}
+
+/// Transfer function used to encode display values into the output file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputEncoding {
+    /// Values are written as they are
+    Linear,
+    /// Piecewise sRGB transfer function
+    Srgb,
+    /// Pure power curve with the given gamma
+    Gamma(f64),
+}
+
+impl WorkingSpace {
+    /// Working space from its name: `linear-srgb` or `acescg`
+    pub fn from_name(name: &str) -> Option<WorkingSpace> {
+        match name {
+            "linear-srgb" => Some(WorkingSpace::LinearSrgb),
+            "acescg" => Some(WorkingSpace::AcesCg),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WorkingSpace::LinearSrgb => "linear-srgb",
+            WorkingSpace::AcesCg => "acescg",
+        }
+    }
+}
+
+impl TextureSpace {
+    /// Texture space from its name: `linear` or `srgb`
+    pub fn from_name(name: &str) -> Option<TextureSpace> {
+        match name {
+            "linear" => Some(TextureSpace::Linear),
+            "srgb" => Some(TextureSpace::Srgb),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextureSpace::Linear => "linear",
+            TextureSpace::Srgb => "srgb",
+        }
+    }
+
+    /// Color of a stored texel in the working space
+    ///
+    /// ### Arguments
+    ///
+    /// * `texel` - The texel as stored in the file, in `[0, 1]`
+    /// * `working_space` - The working space of the render
+    ///
+    /// ### Returns
+    ///
+    /// Color - The texel color the scene is shaded with
+    ///
+    pub fn decode(&self, texel: Color, working_space: WorkingSpace) -> Color {
+        match self {
+            TextureSpace::Linear => texel,
+            TextureSpace::Srgb => {
+                from_linear_srgb(working_space, texel.map(|c| OutputEncoding::Srgb.decode(c)))
+            }
+        }
+    }
+}
+
+impl ViewTransform {
+    /// View transform from its name: `standard`, `reinhard` or `aces`
+    pub fn from_name(name: &str) -> Option<ViewTransform> {
+        match name {
+            "standard" => Some(ViewTransform::Standard),
+            "reinhard" => Some(ViewTransform::Reinhard),
+            "aces" => Some(ViewTransform::AcesSrgb),
+            _ => None,
+        }
+    }
+}
+
+/// Configurable color pipeline: working space -> view transform -> output encoding
+///
+/// Every render takes one of these, so all the conversions from radiance to file
+/// values live in a single place instead of being spread over the output code
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorPipeline {
+    pub working_space: WorkingSpace,
+    pub view: ViewTransform,
+    pub encoding: OutputEncoding,
+    /// Exposure adjustment in stops, applied before the view transform
+    pub exposure: f64,
+    /// Film emulation of the development, shared by the copies of the pipeline
+    pub look: Option<Arc<FilmLook>>,
+}
+
+impl Default for ColorPipeline {
+    /// Pipeline matching the historical output of the renderer (no tone mapping, no gamma)
+    fn default() -> Self {
+        ColorPipeline {
+            working_space: WorkingSpace::LinearSrgb,
+            view: ViewTransform::Standard,
+            encoding: OutputEncoding::Linear,
+            exposure: 0.0,
+            look: None,
+        }
+    }
+}
+
+impl ColorPipeline {
+    pub fn new(
+        working_space: WorkingSpace,
+        view: ViewTransform,
+        encoding: OutputEncoding,
+    ) -> ColorPipeline {
+        ColorPipeline {
+            working_space,
+            view,
+            encoding,
+            exposure: 0.0,
+            look: None,
+        }
+    }
+
+    /// Standard sRGB display pipeline
+    pub fn srgb() -> ColorPipeline {
+        ColorPipeline::new(
+            WorkingSpace::LinearSrgb,
+            ViewTransform::Standard,
+            OutputEncoding::Srgb,
+        )
+    }
+
+    /// ACES filmic view for sRGB displays
+    pub fn aces_srgb() -> ColorPipeline {
+        ColorPipeline::new(
+            WorkingSpace::LinearSrgb,
+            ViewTransform::AcesSrgb,
+            OutputEncoding::Srgb,
+        )
+    }
+
+    pub fn with_exposure(mut self, exposure: f64) -> ColorPipeline {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_look(mut self, look: FilmLook) -> ColorPipeline {
+        self.look = Some(Arc::new(look));
+        self
+    }
+
+    /// Convert scene-referred radiance in the working space to encoded display values
+    ///
+    /// A film look replaces the view transform and the encoding with its response curve,
+    /// and grades the encoded values with its LUT
+    ///
+    /// ### Arguments
+    ///
+    /// * `color` - Radiance in the working space
+    ///
+    /// ### Returns
+    ///
+    /// Color - Encoded display values in the range `[0, 1]`
+    ///
+    pub fn apply(&self, color: Color) -> Color {
+        let linear = to_linear_srgb(self.working_space, color) * 2.0_f64.powf(self.exposure);
+        let look = self.look.as_deref();
+        let encoded = match look.and_then(|look| look.response.as_ref()) {
+            Some(response) => response.apply(linear),
+            None => {
+                let display = match self.view {
+                    ViewTransform::Standard => linear,
+                    ViewTransform::Reinhard => linear.map(|c| c.max(0.0) / (1.0 + c.max(0.0))),
+                    ViewTransform::AcesSrgb => aces_fitted(linear),
+                };
+                display.map(|c| self.encoding.encode(c.clamp(0.0, 1.0)))
+            }
+        };
+        match look.and_then(|look| look.lut.as_ref()) {
+            Some(lut) => lut.apply(encoded).map(|c| c.clamp(0.0, 1.0)),
+            None => encoded.map(|c| c.clamp(0.0, 1.0)),
+        }
+    }
+
+    /// Convert radiance to an 8-bit RGB triplet
+    pub fn to_rgb8(&self, color: Color) -> [u8; 3] {
+        let encoded = self.apply(color);
+        [
+            (encoded.r * 255.0) as u8,
+            (encoded.g * 255.0) as u8,
+            (encoded.b * 255.0) as u8,
+        ]
+    }
+}
+
+impl OutputEncoding {
+    /// Encode a single linear display value in `[0, 1]`
+    pub fn encode(&self, c: f64) -> f64 {
+        match *self {
+            OutputEncoding::Linear => c,
+            OutputEncoding::Srgb => {
+                if c <= 0.0031308 {
+                    c * 12.92
+                } else {
+                    1.055 * c.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            OutputEncoding::Gamma(gamma) => c.powf(1.0 / gamma),
+        }
+    }
+
+    /// Inverse of `encode`
+    pub fn decode(&self, c: f64) -> f64 {
+        match *self {
+            OutputEncoding::Linear => c,
+            OutputEncoding::Srgb => {
+                if c <= 0.04045 {
+                    c / 12.92
+                } else {
+                    ((c + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            OutputEncoding::Gamma(gamma) => c.powf(gamma),
+        }
+    }
+}
+
+/// Convert a color from the given working space to linear sRGB primaries
+pub fn to_linear_srgb(space: WorkingSpace, color: Color) -> Color {
+    match space {
+        WorkingSpace::LinearSrgb => color,
+        WorkingSpace::AcesCg => Color::from(acescg_to_srgb() * Vector3::from(color)),
+    }
+}
+
+/// Convert a color from linear sRGB primaries to the given working space
+pub fn from_linear_srgb(space: WorkingSpace, color: Color) -> Color {
+    match space {
+        WorkingSpace::LinearSrgb => color,
+        WorkingSpace::AcesCg => Color::from(srgb_to_acescg() * Vector3::from(color)),
+    }
+}
+
+fn srgb_to_acescg() -> Matrix3<f64> {
+    Matrix3::new(
+        0.6130974, 0.3395231, 0.0473795, //
+        0.0701937, 0.9163539, 0.0134524, //
+        0.0206156, 0.1095698, 0.8698151,
+    )
+}
+
+fn acescg_to_srgb() -> Matrix3<f64> {
+    Matrix3::new(
+        1.7050510, -0.6217921, -0.0832589, //
+        -0.1302564, 1.1408048, -0.0105484, //
+        -0.0240034, -0.1289690, 1.1529724,
+    )
+}
+
+/// Stephen Hill's fit of the ACES RRT + sRGB ODT, takes and returns linear sRGB
+fn aces_fitted(color: Color) -> Color {
+    let input = Matrix3::new(
+        0.59719, 0.35458, 0.04823, //
+        0.07600, 0.90834, 0.01566, //
+        0.02840, 0.13383, 0.83777,
+    );
+    let output = Matrix3::new(
+        1.60475, -0.53108, -0.07367, //
+        -0.10208, 1.10813, -0.00605, //
+        -0.00327, -0.07276, 1.07602,
+    );
+    let v = input * Vector3::from(color);
+    let fitted = v.map(|c| {
+        let a = c * (c + 0.0245786) - 0.000090537;
+        let b = c * (0.983729 * c + 0.4329510) + 0.238081;
+        a / b
+    });
+    Color::from(output * fitted)
+}
+
+/// Color of a black body radiator at the given temperature
+///
+/// The Planck spectrum is integrated against an analytic fit of the CIE 1931 color matching
+/// functions (Wyman et al. 2013) and converted to linear sRGB
+///
+/// ### Arguments
+///
+/// * `kelvin` - The temperature in kelvins
+///
+/// ### Returns
+///
+/// Color - Linear sRGB color normalized to unit luminance
+///
+pub fn blackbody(kelvin: f64) -> Color {
+    let kelvin = kelvin.max(100.0);
+    let mut xyz = Vector3::new(0.0, 0.0, 0.0);
+    let mut lambda = 380.0;
+    while lambda <= 780.0 {
+        xyz += cie_xyz(lambda) * planck(lambda * 1e-9, kelvin);
+        lambda += 5.0;
+    }
+    let xyz_to_srgb = Matrix3::new(
+        3.2406, -1.5372, -0.4986, //
+        -0.9689, 1.8758, 0.0415, //
+        0.0557, -0.2040, 1.0570,
+    );
+    let rgb = Color::from(xyz_to_srgb * (xyz / xyz.y)).map(|c| c.max(0.0));
+    rgb / rgb.luminance()
+}
+
+/// Spectral radiance of a black body (Planck's law), wavelength in meters
+fn planck(wavelength: f64, kelvin: f64) -> f64 {
+    const H: f64 = 6.626_070_15e-34;
+    const C: f64 = 2.997_924_58e8;
+    const K: f64 = 1.380_649e-23;
+    2.0 * H * C * C / wavelength.powi(5) / ((H * C / (wavelength * K * kelvin)).exp() - 1.0)
+}
+
+/// Multi-lobe gaussian fit of the CIE 1931 color matching functions, wavelength in nanometers
+fn cie_xyz(lambda: f64) -> Vector3<f64> {
+    let g = |mu: f64, sigma_low: f64, sigma_high: f64| {
+        let sigma = if lambda < mu { sigma_low } else { sigma_high };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+    Vector3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
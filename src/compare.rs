@@ -0,0 +1,161 @@
+use crate::color::Color;
+use crate::colorspace::ColorPipeline;
+use crate::image::Image;
+use crate::label::draw_label;
+use crate::label::label_size;
+use crate::render::render_pixel;
+use crate::render::render_radiance;
+use crate::render::sanitize_radiance;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+/// One side of a comparison: a scene and the settings to render it with
+pub struct Variant<'a> {
+    /// Text drawn over this side of the image
+    pub label: String,
+    pub settings: RenderSettings,
+    pub scene: &'a Scene,
+}
+
+impl<'a> Variant<'a> {
+    pub fn new(label: &str, settings: RenderSettings, scene: &'a Scene) -> Variant<'a> {
+        Variant {
+            label: label.to_string(),
+            settings,
+            scene,
+        }
+    }
+}
+
+/// Render the left half of the image with one variant and the right half with another,
+/// separated by a white divider and labeled on both sides of it
+///
+/// ### Arguments
+///
+/// * `left` - The variant rendered on the left, its settings give the size of the image
+/// * `right` - The variant rendered on the right (rendered at the size of the left one)
+/// * `pipeline` - The color pipeline used to encode the pixels
+///
+/// ### Returns
+///
+/// `Image` - The comparison image
+///
+pub fn render_split(left: &Variant, right: &Variant, pipeline: &ColorPipeline) -> Image {
+    let (width, height) = (left.settings.width, left.settings.height);
+    let right_settings = RenderSettings {
+        width,
+        height,
+        ..right.settings.clone()
+    };
+    let divider = width / 2;
+    let mut image = Image::new(width, height);
+    for j in 0..height {
+        for i in 0..width {
+            let color = if i < divider {
+                render_pixel(i, j, left.scene, &left.settings)
+            } else {
+                render_pixel(i, j, right.scene, &right_settings)
+            };
+            image.set_pixel(i, j, pipeline.to_rgb8(sanitize_radiance(color)));
+        }
+    }
+
+    // the labels hang from the top of the image on both sides of the divider
+    let thickness = (width / 512).max(1);
+    for j in 0..height {
+        for i in divider.saturating_sub(thickness)..(divider + thickness).min(width) {
+            image.set_pixel(i, j, [255, 255, 255]);
+        }
+    }
+    let scale = (height / 256).max(1);
+    let margin = 4 * scale;
+    let (left_width, _) = label_size(&left.label, scale);
+    draw_label(
+        &mut image,
+        divider.saturating_sub(thickness + margin + left_width),
+        margin,
+        &left.label,
+        scale,
+    );
+    draw_label(
+        &mut image,
+        divider + thickness + margin,
+        margin,
+        &right.label,
+        scale,
+    );
+    image
+}
+
+/// Render two scenes with the same settings (but for the values the scenes set, see
+/// `RenderSettings::with_scene_values`), and so the same camera rays and sampling seeds,
+/// and draw the signed difference of their luminance as a heatmap (see
+/// `difference_heatmap`)
+///
+/// The noise of the renders is the same wherever the scenes agree, so only what a change
+/// of a material or a light actually did to the image shows up
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings of both renders
+/// * `before` - The scene before the change
+/// * `after` - The scene after the change
+///
+/// ### Returns
+///
+/// `(Image, f64)` - The heatmap and the largest difference of luminance, shown at full
+/// intensity
+///
+pub fn render_difference(settings: &RenderSettings, before: &Scene, after: &Scene) -> (Image, f64) {
+    let before = render_radiance(&settings.clone().with_scene_values(before), before);
+    let after = render_radiance(&settings.clone().with_scene_values(after), after);
+    difference_heatmap(settings.width, settings.height, &before, &after)
+}
+
+/// Heatmap of the signed difference of the luminance of two images of radiance, `after`
+/// minus `before`: black where they agree, red where `after` is brighter and blue where it
+/// is darker, the largest difference at full intensity
+///
+/// ### Arguments
+///
+/// * `width` - The width of the images
+/// * `height` - The height of the images
+/// * `before` - The radiance of the first image, row by row from the top left corner
+/// * `after` - The radiance of the second image, in the same layout
+///
+/// ### Returns
+///
+/// `(Image, f64)` - The heatmap and the largest difference of luminance (zero when the
+/// images are the same)
+///
+pub fn difference_heatmap(
+    width: u32,
+    height: u32,
+    before: &[Color],
+    after: &[Color],
+) -> (Image, f64) {
+    let differences: Vec<f64> = before
+        .iter()
+        .zip(after)
+        .map(|(before, after)| after.luminance() - before.luminance())
+        .collect();
+    let largest = differences
+        .iter()
+        .fold(0.0_f64, |largest, d| largest.max(d.abs()));
+    let pipeline = ColorPipeline::srgb();
+    let mut image = Image::new(width, height);
+    for (difference, rgb) in differences.iter().zip(image.data.chunks_exact_mut(3)) {
+        let t = if largest > 0.0 {
+            difference / largest
+        } else {
+            0.0
+        };
+        let color = if t >= 0.0 {
+            Color::new(t, 0.0, 0.0)
+        } else {
+            Color::new(0.0, 0.0, -t)
+        };
+        rgb.copy_from_slice(&pipeline.to_rgb8(color));
+    }
+    (image, largest)
+}
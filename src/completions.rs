@@ -0,0 +1,131 @@
+/// Shells completion scripts can be written for
+pub const SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+/// Completion script of a shell for a program taking a command as its first argument
+///
+/// The commands complete the first argument and the flags any argument, the value after a
+/// flag completing as a path when it is a `file` or a `directory`
+///
+/// ### Arguments
+///
+/// * `shell` - The shell, one of `SHELLS`
+/// * `program` - The name of the program
+/// * `commands` - The commands with their descriptions
+/// * `flags` - The flags with the kind of value they take, empty for switches
+///
+/// ### Returns
+///
+/// `Option<String>` - The script, `None` for an unknown shell
+///
+pub fn completion_script(
+    shell: &str,
+    program: &str,
+    commands: &[(&str, &str)],
+    flags: &[(&str, &str)],
+) -> Option<String> {
+    let function = format!("_{}", program.replace('-', "_"));
+    let is_path = |value: &str| value == "file" || value == "directory";
+    let script = match shell {
+        "bash" => {
+            let names: Vec<&str> = commands.iter().map(|(name, _)| *name).collect();
+            let all: Vec<&str> = flags.iter().map(|(flag, _)| *flag).collect();
+            let paths: Vec<&str> = flags
+                .iter()
+                .filter(|(_, value)| is_path(value))
+                .map(|(flag, _)| *flag)
+                .collect();
+            let values: Vec<&str> = flags
+                .iter()
+                .filter(|(_, value)| !value.is_empty() && !is_path(value))
+                .map(|(flag, _)| *flag)
+                .collect();
+            let mut lines = vec![
+                format!("{}() {{", function),
+                "    local current=${COMP_WORDS[COMP_CWORD]}".to_string(),
+                "    local previous=${COMP_WORDS[COMP_CWORD-1]}".to_string(),
+                "    case $previous in".to_string(),
+                format!(
+                    "        {}) COMPREPLY=($(compgen -f -- \"$current\")); return ;;",
+                    paths.join("|")
+                ),
+            ];
+            if !values.is_empty() {
+                lines.push(format!("        {}) return ;;", values.join("|")));
+            }
+            lines.extend([
+                "    esac".to_string(),
+                "    if [[ $COMP_CWORD -eq 1 && $current != -* ]]; then".to_string(),
+                format!(
+                    "        COMPREPLY=($(compgen -W \"{}\" -- \"$current\"))",
+                    names.join(" ")
+                ),
+                "    else".to_string(),
+                format!(
+                    "        COMPREPLY=($(compgen -W \"{}\" -f -- \"$current\"))",
+                    all.join(" ")
+                ),
+                "    fi".to_string(),
+                "}".to_string(),
+                format!("complete -F {} {}", function, program),
+            ]);
+            lines
+        }
+        "zsh" => {
+            let mut lines = vec![
+                format!("#compdef {}", program),
+                String::new(),
+                format!("{}() {{", function),
+                "    local -a commands".to_string(),
+                "    commands=(".to_string(),
+            ];
+            for (name, description) in commands {
+                lines.push(format!("        '{}:{}'", name, quote_single(description)));
+            }
+            lines.extend(["    )".to_string(), "    _arguments \\".to_string()]);
+            for (flag, value) in flags {
+                lines.push(match *value {
+                    "" => format!("        '*{}' \\", flag),
+                    "directory" => format!("        '*{}:{}:_files -/' \\", flag, value),
+                    "file" => format!("        '*{}:{}:_files' \\", flag, value),
+                    _ => format!("        '*{}:{}: ' \\", flag, value),
+                });
+            }
+            lines.extend([
+                "        '1:command:{_describe command commands}' \\".to_string(),
+                "        '*::argument:_files'".to_string(),
+                "}".to_string(),
+                String::new(),
+                format!("{} \"$@\"", function),
+            ]);
+            lines
+        }
+        "fish" => {
+            let mut lines = Vec::new();
+            for (name, description) in commands {
+                lines.push(format!(
+                    "complete -c {} -n __fish_use_subcommand -f -a {} -d '{}'",
+                    program,
+                    name,
+                    description.replace('\'', "\\'")
+                ));
+            }
+            for (flag, value) in flags {
+                let long = flag.trim_start_matches('-');
+                lines.push(match *value {
+                    "" => format!("complete -c {} -l {}", program, long),
+                    "file" | "directory" => format!("complete -c {} -l {} -r -F", program, long),
+                    _ => format!("complete -c {} -l {} -r -f", program, long),
+                });
+            }
+            lines
+        }
+        _ => return None,
+    };
+    Some(script.join("\n") + "\n")
+}
+
+/// Text inside single quotes of a shell, a quote ending the string, being escaped and
+/// starting it again
+fn quote_single(text: &str) -> String {
+    text.replace('\'', "'\\''")
+}
@@ -0,0 +1,492 @@
+use crate::colorspace::ColorPipeline;
+use crate::colorspace::OutputEncoding;
+use crate::colorspace::ViewTransform;
+use crate::colorspace::WorkingSpace;
+use crate::look::FilmLook;
+use crate::look::Lut;
+use crate::look::ResponseCurve;
+use crate::pathtemplate::expand_template;
+use crate::pathtemplate::PathTokens;
+use crate::pathtemplate::TemplateError;
+use crate::settings::RenderSettings;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Environment variable holding the path of the config file, in place of the one in the
+/// user config directory
+pub const CONFIG_VARIABLE: &str = "RAY_TRACING_CONFIG";
+
+/// Error raised when a config file can't be read
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigError {
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(message: &str) -> ConfigError {
+        ConfigError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Draft renders of the `preview` command
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PreviewSettings {
+    /// Factor the image size is divided by
+    pub scale: u32,
+    pub samples_per_pixel: u32,
+    /// Shadow rays of every light
+    pub shadow_samples: u32,
+}
+
+impl Default for PreviewSettings {
+    fn default() -> Self {
+        PreviewSettings {
+            scale: 4,
+            samples_per_pixel: 1,
+            shadow_samples: 1,
+        }
+    }
+}
+
+/// Defaults of the user, read from a config file and overridden by the command line
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    /// Directory the output files are written to, the working directory when `None`
+    pub output_dir: Option<String>,
+    /// Path of the beauty image and of the frames of animations inside the output
+    /// directory, with tokens expanded at save time (see `pathtemplate::expand_template`),
+    /// `out.png` and `frame_{frame:04}.png` when `None`
+    pub output_name: Option<String>,
+    /// Number of render threads used when the render settings leave it at 0
+    pub threads: Option<usize>,
+    /// Longest side of the proxies written next to the images, none are written when `None`
+    pub proxy_size: Option<u32>,
+    pub preview: PreviewSettings,
+    /// Color pipeline of the images written
+    pub color: ColorPipeline,
+}
+
+impl Config {
+    /// Path of the user config file: the one in `CONFIG_VARIABLE` if it is set, otherwise
+    /// `ray_tracing/config.toml` in `$XDG_CONFIG_HOME` or in `~/.config`
+    pub fn user_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os(CONFIG_VARIABLE) {
+            return Some(PathBuf::from(path));
+        }
+        let directory = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(directory.join("ray_tracing").join("config.toml"))
+    }
+
+    /// Load the user config file, the defaults when there is none
+    pub fn load_user() -> Result<Config, Box<dyn std::error::Error>> {
+        match Config::user_path() {
+            Some(path) if path.exists() => Config::load(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    /// Load a config file
+    pub fn load(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(Config::parse(&text)?)
+    }
+
+    /// Read a config from TOML text, missing keys keep their default values
+    ///
+    /// ```toml
+    /// output_dir = "renders"
+    /// output_name = "{scene}/{camera}_{frame:04}.png"
+    /// threads = 8
+    /// proxy_size = 256
+    ///
+    /// [preview]
+    /// scale = 4
+    /// samples_per_pixel = 1
+    /// shadow_samples = 1
+    ///
+    /// [color]
+    /// working_space = "linear-srgb"  # or "acescg"
+    /// view = "aces"                  # "standard", "reinhard" or "aces"
+    /// encoding = "gamma"             # "linear", "srgb" or "gamma"
+    /// gamma = 2.2
+    /// exposure = 0.5
+    /// response = "curves/film.txt"   # response curve of a camera or film stock
+    /// lut = "luts/look.cube"         # film emulation LUT (see `FilmLook`)
+    /// ```
+    ///
+    /// Only the part of TOML needed for this is read: tables, comments and keys holding
+    /// strings or numbers
+    ///
+    /// ### Arguments
+    ///
+    /// * `text` - The content of the config file
+    ///
+    /// ### Returns
+    ///
+    /// `Result<Config, ConfigError>` - The config, or an error naming the line it was
+    /// found on
+    ///
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        let mut table = String::new();
+        let mut encoding = None;
+        let mut gamma = 2.2;
+        let mut look = FilmLook::new();
+        for (index, line) in text.lines().enumerate() {
+            let at_line =
+                |message: &str| ConfigError::new(&format!("line {}: {}", index + 1, message));
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| at_line("unclosed table name"))?;
+                table = name.trim().to_string();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| at_line("expected <key> = <value>"))?;
+            let (key, value) = (
+                key.trim(),
+                parse_value(value.trim()).map_err(|e| at_line(&e))?,
+            );
+            let count = || {
+                value
+                    .as_number()
+                    .filter(|n| n.fract() == 0.0 && *n >= 0.0)
+                    .ok_or_else(|| at_line(&format!("`{}` must be a whole number", key)))
+            };
+            let number = || {
+                value
+                    .as_number()
+                    .ok_or_else(|| at_line(&format!("`{}` must be a number", key)))
+            };
+            let name = || {
+                value
+                    .as_str()
+                    .ok_or_else(|| at_line(&format!("`{}` must be a string", key)))
+            };
+            let unknown = || at_line(&format!("unknown value `{}`", name().unwrap_or_default()));
+            match (table.as_str(), key) {
+                ("", "output_dir") => config.output_dir = Some(name()?.to_string()),
+                ("", "output_name") => {
+                    let template = name()?;
+                    expand_template(template, &PathTokens::default())
+                        .map_err(|error| at_line(&error.message))?;
+                    config.output_name = Some(template.to_string());
+                }
+                ("", "threads") => config.threads = Some(count()? as usize),
+                ("", "proxy_size") => config.proxy_size = Some((count()? as u32).max(1)),
+                ("preview", "scale") => config.preview.scale = (count()? as u32).max(1),
+                ("preview", "samples_per_pixel") => {
+                    config.preview.samples_per_pixel = (count()? as u32).max(1)
+                }
+                ("preview", "shadow_samples") => {
+                    config.preview.shadow_samples = (count()? as u32).max(1)
+                }
+                ("color", "working_space") => {
+                    config.color.working_space =
+                        WorkingSpace::from_name(name()?).ok_or_else(unknown)?
+                }
+                ("color", "view") => {
+                    config.color.view = ViewTransform::from_name(name()?).ok_or_else(unknown)?
+                }
+                ("color", "encoding") => match name()? {
+                    "linear" | "srgb" | "gamma" => encoding = Some(name()?.to_string()),
+                    _ => return Err(unknown()),
+                },
+                ("color", "gamma") => gamma = number()?,
+                ("color", "exposure") => config.color.exposure = number()?,
+                ("color", "response") => {
+                    let response = ResponseCurve::load(Path::new(name()?))
+                        .map_err(|error| at_line(&format!("response curve: {}", error)))?;
+                    look.response = Some(response);
+                }
+                ("color", "lut") => {
+                    let lut = Lut::load(Path::new(name()?))
+                        .map_err(|error| at_line(&format!("LUT: {}", error)))?;
+                    look.lut = Some(lut);
+                }
+                _ => {
+                    let path = if table.is_empty() {
+                        key.to_string()
+                    } else {
+                        format!("{}.{}", table, key)
+                    };
+                    return Err(at_line(&format!("unknown key `{}`", path)));
+                }
+            }
+        }
+        // the gamma may come before or after the encoding it belongs to
+        config.color.encoding = match encoding.as_deref() {
+            Some("srgb") => OutputEncoding::Srgb,
+            Some("gamma") => OutputEncoding::Gamma(gamma),
+            _ => OutputEncoding::Linear,
+        };
+        if look != FilmLook::default() {
+            config.color = config.color.with_look(look);
+        }
+        Ok(config)
+    }
+
+    /// Path of an output file, inside the output directory if there is one
+    pub fn output_path(&self, name: &str) -> String {
+        match &self.output_dir {
+            Some(directory) => Path::new(directory)
+                .join(name)
+                .to_string_lossy()
+                .to_string(),
+            None => name.to_string(),
+        }
+    }
+
+    /// Name of an image from the output name template, relative to the output directory
+    /// (see `output_path`)
+    ///
+    /// ### Arguments
+    ///
+    /// * `default_name` - The template used when the config has no output name
+    /// * `tokens` - The values of the tokens of the template
+    ///
+    /// ### Returns
+    ///
+    /// `Result<String, TemplateError>` - The name, the directories in it may not exist yet
+    ///
+    pub fn image_name(
+        &self,
+        default_name: &str,
+        tokens: &PathTokens,
+    ) -> Result<String, TemplateError> {
+        expand_template(self.output_name.as_deref().unwrap_or(default_name), tokens)
+    }
+
+    /// Render settings with the defaults of the config filled in where the settings leave
+    /// them open (the default working space and no thread count)
+    pub fn apply(&self, settings: RenderSettings) -> RenderSettings {
+        let settings = if settings.working_space == WorkingSpace::default() {
+            settings.with_working_space(self.color.working_space)
+        } else {
+            settings
+        };
+        match self.threads {
+            Some(threads) if settings.threads == 0 => settings.with_threads(threads),
+            _ => settings,
+        }
+    }
+
+    /// Settings of a draft render, smaller and with fewer samples than the given ones
+    pub fn preview_settings(&self, settings: &RenderSettings) -> RenderSettings {
+        RenderSettings {
+            width: (settings.width / self.preview.scale).max(1),
+            height: (settings.height / self.preview.scale).max(1),
+            ..settings.clone()
+        }
+        .with_samples_per_pixel(self.preview.samples_per_pixel)
+    }
+}
+
+/// Value of a key of the config file
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    String(String),
+    Number(f64),
+}
+
+impl Value {
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// Line without its `#` comment, if any, a `#` inside a basic or a literal string being kept
+fn strip_comment(line: &str) -> &str {
+    // the quote of the string the character is in, if any
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match (c, quote) {
+            ('\\', Some('"')) => {
+                escaped = !escaped;
+                continue;
+            }
+            ('"' | '\'', None) => quote = Some(c),
+            ('"', Some('"')) if !escaped => quote = None,
+            ('\'', Some('\'')) => quote = None,
+            ('#', None) => return &line[..index],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// Read a value: a basic string in double quotes, a literal string in single quotes or a
+/// number
+fn parse_value(text: &str) -> Result<Value, String> {
+    if let Some(literal) = text.strip_prefix('\'') {
+        let literal = literal.strip_suffix('\'').ok_or("unclosed string")?;
+        return Ok(Value::String(literal.to_string()));
+    }
+    if let Some(basic) = text.strip_prefix('"') {
+        let basic = basic.strip_suffix('"').ok_or("unclosed string")?;
+        let mut unescaped = String::new();
+        let mut chars = basic.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+            unescaped.push(match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('"') => '"',
+                Some('\\') => '\\',
+                _ => return Err("unknown escape in string".to_string()),
+            });
+        }
+        return Ok(Value::String(unescaped));
+    }
+    text.replace('_', "")
+        .parse()
+        .map(Value::Number)
+        .map_err(|_| format!("invalid value `{}`", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_key() {
+        let text = r#"
+            # defaults of the user
+            output_dir = "renders"   # next to the scenes
+            output_name = '{scene}/{camera}_{frame:04}.png'
+            threads = 8
+            proxy_size = 1_024
+
+            [preview]
+            scale = 2
+            samples_per_pixel = 4
+            shadow_samples = 3
+
+            [ color ]
+            gamma = 2.4
+            encoding = "gamma"
+            working_space = "acescg"
+            view = "aces"
+            exposure = -0.5
+        "#;
+        let config = Config::parse(text).unwrap();
+        assert_eq!(config.output_dir.as_deref(), Some("renders"));
+        assert_eq!(
+            config.output_name.as_deref(),
+            Some("{scene}/{camera}_{frame:04}.png")
+        );
+        assert_eq!(config.threads, Some(8));
+        assert_eq!(config.proxy_size, Some(1024));
+        let preview = PreviewSettings {
+            scale: 2,
+            samples_per_pixel: 4,
+            shadow_samples: 3,
+        };
+        assert_eq!(config.preview, preview);
+        assert_eq!(config.color.working_space, WorkingSpace::AcesCg);
+        assert_eq!(config.color.view, ViewTransform::AcesSrgb);
+        // the gamma came before its encoding
+        assert_eq!(config.color.encoding, OutputEncoding::Gamma(2.4));
+        assert_eq!(config.color.exposure, -0.5);
+        assert_eq!(config.color.look, None);
+    }
+
+    #[test]
+    fn empty_config_is_the_default() {
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+        assert_eq!(Config::parse("# nothing\n\n"), Ok(Config::default()));
+    }
+
+    #[test]
+    fn strings_keep_hashes_and_escapes() {
+        let config = Config::parse(r#"output_dir = "a#b \"c\" \\ d" # comment"#).unwrap();
+        assert_eq!(config.output_dir.as_deref(), Some(r#"a#b "c" \ d"#));
+        let config = Config::parse(r"output_dir = 'C:\renders #1' # comment").unwrap();
+        assert_eq!(config.output_dir.as_deref(), Some(r"C:\renders #1"));
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        let cases = [
+            ("threads = 4\n[preview", "line 2: unclosed table name"),
+            ("threads", "line 1: expected <key> = <value>"),
+            ("output_dir = \"renders", "line 1: unclosed string"),
+            ("output_dir = 'renders", "line 1: unclosed string"),
+            ("output_dir = \"a\\qb\"", "line 1: unknown escape in string"),
+            ("threads = many", "line 1: invalid value `many`"),
+            ("threads = 2.5", "line 1: `threads` must be a whole number"),
+            ("threads = -1", "line 1: `threads` must be a whole number"),
+            (
+                "threads = \"8\"",
+                "line 1: `threads` must be a whole number",
+            ),
+            ("output_dir = 3", "line 1: `output_dir` must be a string"),
+            (
+                "[color]\nexposure = 'high'",
+                "line 2: `exposure` must be a number",
+            ),
+            (
+                "[color]\nview = \"filmic\"",
+                "line 2: unknown value `filmic`",
+            ),
+            (
+                "[color]\nencoding = \"rec709\"",
+                "line 2: unknown value `rec709`",
+            ),
+            ("samples = 4", "line 1: unknown key `samples`"),
+            (
+                "\n[preview]\nthreads = 4",
+                "line 3: unknown key `preview.threads`",
+            ),
+        ];
+        for (text, message) in cases {
+            assert_eq!(
+                Config::parse(text),
+                Err(ConfigError::new(message)),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_output_name_is_an_error() {
+        let error = Config::parse("output_name = \"{scene\"").unwrap_err();
+        assert!(error.message.starts_with("line 1: "), "{}", error);
+    }
+}
@@ -0,0 +1,287 @@
+use crate::camera::Camera;
+use crate::convergence::AccumulationSnapshot;
+use crate::image::Rect;
+use crate::look::Lut;
+use crate::reconstruction::CrossBilateral;
+
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+/// Settings of a progressive render applied when its samples are developed into an image,
+/// so they can change while it renders without throwing away the samples taken so far
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplaySettings {
+    /// Exposure adjustment in stops, added to the exposure of the color pipeline
+    pub exposure: f64,
+    /// Filter smoothing the noise of the image, `None` to show the samples as they are
+    pub denoise: Option<CrossBilateral>,
+    /// Largest value of a color channel, brighter pixels (fireflies) are clamped to it
+    pub clamp: f64,
+    /// LUT the developed image is viewed through, e.g. the grade it is meant for, `None`
+    /// to view it as the color pipeline leaves it
+    pub lut: Option<Arc<Lut>>,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            exposure: 0.0,
+            denoise: None,
+            clamp: f64::INFINITY,
+            lut: None,
+        }
+    }
+}
+
+/// Region of the image a progressive render refines first, e.g. around the mouse cursor of
+/// a viewer, in pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Focus {
+    /// Center of the region, from the top left corner of the image
+    pub x: f64,
+    pub y: f64,
+    /// Half the side of the square region
+    pub radius: f64,
+}
+
+impl Focus {
+    pub fn new(x: f64, y: f64, radius: f64) -> Focus {
+        Focus { x, y, radius }
+    }
+
+    /// Pixels of the region inside an image of the given size, `None` if it is outside
+    pub fn rect(&self, width: u32, height: u32) -> Option<Rect> {
+        let clip = |value: f64, size: u32| value.floor().clamp(0.0, size as f64) as u32;
+        let (left, right) = (
+            clip(self.x - self.radius, width),
+            clip(self.x + self.radius, width),
+        );
+        let (top, bottom) = (
+            clip(self.y - self.radius, height),
+            clip(self.y + self.radius, height),
+        );
+        if left >= right || top >= bottom {
+            return None;
+        }
+        Some(Rect::new(left, top, right - left, bottom - top))
+    }
+}
+
+#[derive(Debug, Default)]
+struct ControlState {
+    paused: bool,
+    stopped: bool,
+    /// Whether the camera is being moved, only previews are rendered meanwhile
+    navigating: bool,
+    /// Camera the render was moved to, the camera of the settings until then
+    camera: Option<Camera>,
+    /// Whether a coarse image refined block by block is shown before the first pass
+    coarse_to_fine: bool,
+    /// Region getting more samples than the rest of the image
+    focus: Option<Focus>,
+    display: DisplaySettings,
+    /// Frame error at which the render ends, `None` to render all its passes
+    target_error: Option<f64>,
+    /// Accumulation buffer after the last pass
+    snapshot: Option<Arc<AccumulationSnapshot>>,
+    /// Incremented on every change, so the renderer knows when to develop the image again
+    version: u64,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<ControlState>,
+    changed: Condvar,
+}
+
+/// Handle controlling a progressive render (see `render::render_progressive`) from another
+/// thread, e.g. the event loop of a window: pausing, resuming or stopping it, moving its
+/// camera, changing its display settings and reading its samples and their error
+///
+/// Clones of the handle control the same render
+#[derive(Clone, Debug, Default)]
+pub struct RenderControl {
+    shared: Arc<Shared>,
+}
+
+impl RenderControl {
+    pub fn new() -> RenderControl {
+        RenderControl::default()
+    }
+
+    pub fn with_display(self, display: DisplaySettings) -> RenderControl {
+        self.set_display(display);
+        self
+    }
+
+    /// Show a complete image within milliseconds when the render starts, and again once its
+    /// camera stops moving: one pixel in every block of `render::COARSE_BLOCK_SIZE` pixels
+    /// squared is traced and fills its block, then the blocks are split in four until they
+    /// are single pixels, before the first pass
+    pub fn with_coarse_to_fine(self, coarse_to_fine: bool) -> RenderControl {
+        self.update(|state| state.coarse_to_fine = coarse_to_fine);
+        self
+    }
+
+    pub fn is_coarse_to_fine(&self) -> bool {
+        self.lock().coarse_to_fine
+    }
+
+    /// End the render once the relative error of the whole image (see
+    /// `convergence::ErrorEstimate::frame_error`) is at most `target_error`, e.g. 0.01 for
+    /// one percent, instead of after a fixed number of passes
+    pub fn with_target_error(self, target_error: f64) -> RenderControl {
+        self.set_target_error(Some(target_error));
+        self
+    }
+
+    pub fn set_target_error(&self, target_error: Option<f64>) {
+        self.update(|state| state.target_error = target_error);
+    }
+
+    pub fn target_error(&self) -> Option<f64> {
+        self.lock().target_error
+    }
+
+    /// Accumulation buffer of the render after its last pass with its error estimate, for a
+    /// dashboard or a caller stopping the render with its own criterion; `None` before the
+    /// first pass and after the camera moved
+    pub fn snapshot(&self) -> Option<Arc<AccumulationSnapshot>> {
+        self.lock().snapshot.clone()
+    }
+
+    /// Replace the snapshot of the accumulation buffer, called by the render
+    ///
+    /// The change isn't counted in `version`, the image to develop is the same
+    pub fn set_snapshot(&self, snapshot: Option<AccumulationSnapshot>) {
+        self.lock().snapshot = snapshot.map(Arc::new);
+    }
+
+    /// Stop the render after the pass being rendered, until `resume` is called
+    pub fn pause(&self) {
+        self.update(|state| state.paused = true);
+    }
+
+    pub fn resume(&self) {
+        self.update(|state| state.paused = false);
+    }
+
+    /// End the render after the pass being rendered, the image of the samples taken so far
+    /// being the result
+    pub fn stop(&self) {
+        self.update(|state| state.stopped = true);
+    }
+
+    /// Move the camera of the render, e.g. dragged in a viewer: the samples taken from the
+    /// previous camera are thrown away and, until `stop_navigating` is called, only quick
+    /// previews are rendered (see `integrator::Playblast`)
+    pub fn move_camera(&self, camera: Camera) {
+        self.update(|state| {
+            state.camera = Some(camera);
+            state.navigating = true;
+        });
+    }
+
+    /// The camera stopped moving, the render starts over from it with the integrator of the
+    /// settings
+    pub fn stop_navigating(&self) {
+        self.update(|state| state.navigating = false);
+    }
+
+    pub fn is_navigating(&self) -> bool {
+        self.lock().navigating
+    }
+
+    /// Camera the render was moved to, `None` while it renders from the camera of its
+    /// settings
+    pub fn camera(&self) -> Option<Camera> {
+        self.lock().camera.clone()
+    }
+
+    /// Refine a region of the image first, e.g. around the mouse cursor as it moves (foveated
+    /// refinement): every pass over the whole image is followed by
+    /// `render::FOCUS_PASSES` passes over the region, the one set when they start; `None`
+    /// samples the whole image evenly again
+    pub fn set_focus(&self, focus: Option<Focus>) {
+        self.update(|state| state.focus = focus);
+    }
+
+    pub fn focus(&self) -> Option<Focus> {
+        self.lock().focus
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.lock().paused
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.lock().stopped
+    }
+
+    pub fn display(&self) -> DisplaySettings {
+        self.lock().display.clone()
+    }
+
+    /// Replace the display settings, the image is developed again with them even while the
+    /// render is paused
+    pub fn set_display(&self, display: DisplaySettings) {
+        self.update(|state| state.display = display);
+    }
+
+    pub fn set_exposure(&self, exposure: f64) {
+        self.update(|state| state.display.exposure = exposure);
+    }
+
+    pub fn set_denoise(&self, denoise: Option<CrossBilateral>) {
+        self.update(|state| state.display.denoise = denoise);
+    }
+
+    pub fn set_clamp(&self, clamp: f64) {
+        self.update(|state| state.display.clamp = clamp);
+    }
+
+    /// Number of changes made through the handle so far
+    pub fn version(&self) -> u64 {
+        self.lock().version
+    }
+
+    /// Wait while the render is paused or navigating and nothing changed since the change
+    /// `version`, a preview of the camera being moved needing no more samples
+    ///
+    /// ### Arguments
+    ///
+    /// * `version` - The number of changes the caller already took into account
+    ///
+    /// ### Returns
+    ///
+    /// `u64` - The number of changes made so far
+    ///
+    pub fn wait(&self, version: u64) -> u64 {
+        let mut state = self.lock();
+        while (state.paused || state.navigating) && !state.stopped && state.version == version {
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        state.version
+    }
+
+    fn update(&self, change: impl FnOnce(&mut ControlState)) {
+        let mut state = self.lock();
+        change(&mut state);
+        state.version += 1;
+        self.shared.changed.notify_all();
+    }
+
+    /// The state, even if a thread panicked holding it (it is always left consistent)
+    fn lock(&self) -> MutexGuard<'_, ControlState> {
+        self.shared
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
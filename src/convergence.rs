@@ -0,0 +1,127 @@
+use crate::color::Color;
+use crate::film::Film;
+use crate::image::Rect;
+use crate::render::sanitize_radiance;
+
+/// Luminance under which the error of a pixel is taken relative to this value rather than
+/// to the pixel, so the noise of nearly black pixels doesn't hold back a render
+pub const ERROR_FLOOR: f64 = 0.01;
+
+/// Spread of the passes of a pixel, from the luminance of every pass on its own
+///
+/// Every pass of a progressive render is an independent estimate of the pixel, so their
+/// spread measures the noise left in their average
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PassSpread {
+    /// Number of passes that sampled the pixel
+    pub passes: u32,
+    /// Mean luminance of the passes
+    pub mean: f64,
+    /// Sum of the squared differences between the passes and their mean
+    pub squares: f64,
+}
+
+impl PassSpread {
+    /// Add the luminance of a pass
+    pub fn add(&mut self, luminance: f64) {
+        self.passes += 1;
+        let delta = luminance - self.mean;
+        self.mean += delta / self.passes as f64;
+        self.squares += delta * (luminance - self.mean);
+    }
+
+    /// Variance of the luminance of a single pass, 0 before two passes
+    pub fn variance(&self) -> f64 {
+        if self.passes < 2 {
+            return 0.0;
+        }
+        self.squares / (self.passes - 1) as f64
+    }
+
+    /// Relative error of the average of the passes: its standard error divided by its
+    /// luminance (at least `ERROR_FLOOR`), infinite before two passes as nothing tells yet
+    pub fn error(&self) -> f64 {
+        if self.passes < 2 {
+            return f64::INFINITY;
+        }
+        (self.variance() / self.passes as f64).sqrt() / self.mean.max(ERROR_FLOOR)
+    }
+}
+
+/// Error estimate of the pixels of a progressive render, from the spread of their passes
+/// (see `PassSpread`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorEstimate {
+    pub rect: Rect,
+    pixels: Vec<PassSpread>,
+}
+
+impl ErrorEstimate {
+    pub fn new(rect: Rect) -> ErrorEstimate {
+        ErrorEstimate {
+            rect,
+            pixels: vec![PassSpread::default(); rect.width as usize * rect.height as usize],
+        }
+    }
+
+    /// Add the pixels of the film of a pass, those it covers and sampled
+    pub fn add_pass(&mut self, film: &Film) {
+        for j in film.rect.y..film.rect.y + film.rect.height {
+            for i in film.rect.x..film.rect.x + film.rect.width {
+                let (Some(index), pixel) = (self.rect.index(i, j), film.pixel(i, j)) else {
+                    continue;
+                };
+                if pixel.samples > 0 {
+                    self.pixels[index].add(sanitize_radiance(pixel.value()).luminance());
+                }
+            }
+        }
+    }
+
+    /// Spread of the passes of a pixel, from its position in the image, `None` outside
+    pub fn pixel(&self, i: u32, j: u32) -> Option<&PassSpread> {
+        self.rect.index(i, j).map(|index| &self.pixels[index])
+    }
+
+    /// Relative error of the pixels row by row (see `PassSpread::error`)
+    pub fn errors(&self) -> Vec<f64> {
+        self.pixels.iter().map(PassSpread::error).collect()
+    }
+
+    /// Relative error of the whole image: the root mean square of the errors of its pixels,
+    /// so a few noisy pixels count more than in their mean, infinite until every pixel had
+    /// two passes
+    pub fn frame_error(&self) -> f64 {
+        if self.pixels.is_empty() {
+            return 0.0;
+        }
+        let squares: f64 = self.pixels.iter().map(|pixel| pixel.error().powi(2)).sum();
+        (squares / self.pixels.len() as f64).sqrt()
+    }
+}
+
+/// Copy of the accumulation buffer of a progressive render after a pass, with its error
+/// estimate, for callers deciding when the image is good enough (see
+/// `RenderControl::snapshot`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccumulationSnapshot {
+    /// The pixels of the image the buffer covers
+    pub rect: Rect,
+    /// Number of passes over the whole image accumulated
+    pub passes: u32,
+    /// Radiance of the pixels row by row, before the display settings
+    pub radiance: Vec<Color>,
+    /// Number of samples of the pixels row by row, more in a region in focus
+    pub samples: Vec<u32>,
+    /// Relative error of the pixels row by row (see `PassSpread::error`)
+    pub errors: Vec<f64>,
+    /// Relative error of the whole image (see `ErrorEstimate::frame_error`)
+    pub frame_error: f64,
+}
+
+impl AccumulationSnapshot {
+    /// Relative error of a pixel, from its position in the image, `None` outside
+    pub fn error(&self, i: u32, j: u32) -> Option<f64> {
+        self.rect.index(i, j).map(|index| self.errors[index])
+    }
+}
@@ -0,0 +1,101 @@
+use crate::color::Color;
+use crate::colorspace::ColorPipeline;
+use crate::render::cast_ray;
+use crate::render::nearest_sphere;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+/// Debug pattern drawn on top of the shaded surfaces seen by the camera
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overlay {
+    /// Checkerboard of `tiles` x `tiles` squares in UV space, tinted by the UV coordinates
+    /// (red grows with `u`, green with `v`) so flipped or stretched layouts stand out
+    UvChecker { tiles: u32 },
+}
+
+impl Overlay {
+    /// Color of the overlay for the given UV coordinates
+    pub fn color(&self, u: f64, v: f64) -> Color {
+        match *self {
+            Overlay::UvChecker { tiles } => {
+                let tiles = tiles.max(1) as f64;
+                let cell = (u * tiles).floor() as i64 + (v * tiles).floor() as i64;
+                let brightness = if cell % 2 == 0 { 1.0 } else { 0.3 };
+                Color::new(u, v, 0.5) * brightness
+            }
+        }
+    }
+}
+
+/// Luminance shown as the middle of the false color ramp (18% gray)
+pub const MIDDLE_GRAY: f64 = 0.18;
+
+/// Exposure value of a luminance in stops above middle gray
+pub fn exposure_value(luminance: f64) -> f64 {
+    (luminance.max(0.0) / MIDDLE_GRAY).log2()
+}
+
+/// Display color of a luminance on the false color ramp
+///
+/// The ramp goes from purple at -6 EV through blue, cyan and gray (middle gray, 0 EV) to
+/// yellow, orange and red at +6 EV; darker values are black and brighter ones white, so
+/// crushed and clipped areas stand out
+pub fn false_color(luminance: f64) -> Color {
+    const RAMP: [(f64, Color); 7] = [
+        (-6.0, Color::new(0.3, 0.0, 0.5)),
+        (-4.0, Color::new(0.1, 0.1, 0.9)),
+        (-2.0, Color::new(0.0, 0.7, 0.8)),
+        (0.0, Color::new(0.5, 0.5, 0.5)),
+        (2.0, Color::new(0.9, 0.9, 0.0)),
+        (4.0, Color::new(1.0, 0.5, 0.0)),
+        (6.0, Color::new(1.0, 0.0, 0.0)),
+    ];
+    let ev = exposure_value(luminance);
+    if ev.is_nan() || ev < RAMP[0].0 {
+        return Color::BLACK;
+    }
+    for pair in RAMP.windows(2) {
+        let ((ev0, c0), (ev1, c1)) = (pair[0], pair[1]);
+        if ev <= ev1 {
+            return c0.lerp(c1, (ev - ev0) / (ev1 - ev0));
+        }
+    }
+    Color::WHITE
+}
+
+/// Render a scene with an overlay mixed half and half into the shading of every sphere
+/// seen by the camera (reflections are left untouched)
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings (image size, field of view, bounces and background)
+/// * `scene` - The scene
+/// * `overlay` - The pattern to draw
+/// * `pipeline` - The color pipeline used to encode the image
+///
+/// ### Returns
+///
+/// `Vec<u8>` - The RGB framebuffer
+///
+pub fn render_overlay(
+    settings: &RenderSettings,
+    scene: &Scene,
+    overlay: Overlay,
+    pipeline: &ColorPipeline,
+) -> Vec<u8> {
+    let (width, height) = (settings.width, settings.height);
+    let mut buffer = vec![0; width as usize * height as usize * 3];
+    for j in 0..height {
+        for i in 0..width {
+            let ray = settings.camera_ray(i as f64 + 0.5, j as f64 + 0.5);
+            let mut color = cast_ray(&ray, scene, settings, 0);
+            if let Some((index, distance)) = nearest_sphere(&ray, scene) {
+                let (u, v) = scene.sphere(index).uv_at(ray.at(distance));
+                color = color * 0.5 + overlay.color(u, v) * 0.5;
+            }
+            let index = (j * width + i) as usize;
+            buffer[index * 3..index * 3 + 3].copy_from_slice(&pipeline.to_rgb8(color));
+        }
+    }
+    buffer
+}
@@ -0,0 +1,186 @@
+use crate::color::Color;
+use crate::medium::MediumStack;
+use crate::ray::Ray;
+use crate::render::cast_ray_components;
+use crate::render::nearest_sphere;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+use std::io::Write;
+use std::path::Path;
+
+/// One entry of a deep pixel: a surface fragment at a given depth
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeepSample {
+    /// Distance from the camera to the front of the fragment
+    pub depth: f64,
+    /// Premultiplied color of the fragment
+    pub color: Color,
+    /// Pixel coverage of the fragment, in the range `[0, 1]`
+    pub alpha: f64,
+}
+
+/// Image storing a depth-sorted list of samples per pixel instead of a single color,
+/// so that elements can be merged with other renders in depth order during compositing
+pub struct DeepImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Vec<DeepSample>>,
+}
+
+/// Number of surfaces a sub-pixel ray goes through at most, the surfaces behind them are
+/// left out of the pixel
+pub const MAX_DEEP_SURFACES: usize = 16;
+
+/// Render a deep image of a scene
+///
+/// Every pixel is sampled with a grid of sub-pixel rays. A ray hitting a transparent object
+/// goes on in a straight line through it, so the surfaces of the object and those behind
+/// it get samples of their own: the samples of a surface hold its light without the light
+/// refracted through it, and their alpha is its opacity (1 minus the refraction weight of
+/// its material), so compositing them front to back gives back the light seen through it,
+/// without the bending of the refraction. Rays hitting the same surface of the same sphere
+/// are merged into one deep sample whose alpha is the coverage of that surface in the
+/// pixel. Rays missing every sphere contribute no sample (the background stays transparent)
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings (image size, field of view, bounces and background)
+/// * `scene` - The scene
+/// * `samples_per_axis` - Sub-pixel samples per axis (`n * n` samples per pixel)
+///
+/// ### Returns
+///
+/// DeepImage - The rendered deep image
+///
+pub fn render_deep(settings: &RenderSettings, scene: &Scene, samples_per_axis: u32) -> DeepImage {
+    let (width, height) = (settings.width, settings.height);
+    let n = samples_per_axis.max(1);
+    let weight = 1.0 / (n * n) as f64;
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+
+    for j in 0..height {
+        for i in 0..width {
+            // samples of the current pixel, keyed by the index of the hit sphere and whether
+            // the ray hit it from the inside
+            let mut fragments: Vec<((usize, bool), DeepSample)> = Vec::new();
+            for sy in 0..n {
+                for sx in 0..n {
+                    let x = i as f64 + (sx as f64 + 0.5) / n as f64;
+                    let y = j as f64 + (sy as f64 + 0.5) / n as f64;
+                    let mut ray = settings.camera_ray(x, y);
+                    let mut media = MediumStack::at(ray.origin, &scene.spheres);
+                    let mut traveled = 0.0;
+                    for _ in 0..MAX_DEEP_SURFACES {
+                        let Some((index, distance)) = nearest_sphere(&ray, scene) else {
+                            break;
+                        };
+                        let sphere = scene.sphere(index);
+                        let refraction = if sphere.holdout {
+                            0.0
+                        } else {
+                            sphere.material.refraction.clamp(0.0, 1.0)
+                        };
+                        // the surface of a transparent object inside a medium of higher
+                        // priority is no surface, the ray only goes through it
+                        if refraction == 0.0 || !media.is_false_hit(index, scene) {
+                            let mut components =
+                                cast_ray_components(&ray, &media, scene, settings, 0);
+                            components.transmission = Color::BLACK;
+                            let key = (index, media.contains(index));
+                            let depth = traveled + distance;
+                            let color = components.sum() * weight;
+                            let alpha = (1.0 - refraction) * weight;
+                            match fragments.iter_mut().find(|(k, _)| *k == key) {
+                                Some((_, sample)) => {
+                                    sample.depth = sample.depth.min(depth);
+                                    sample.color += color;
+                                    sample.alpha += alpha;
+                                }
+                                None => fragments.push((
+                                    key,
+                                    DeepSample {
+                                        depth,
+                                        color,
+                                        alpha,
+                                    },
+                                )),
+                            }
+                        }
+                        if refraction == 0.0 {
+                            break;
+                        }
+                        let point = ray.at(distance);
+                        let n = (point - sphere.center).normalize();
+                        ray = Ray::offset(point, n, ray.direction).with_time(ray.time);
+                        traveled += distance;
+                        media = media.cross(index);
+                    }
+                }
+            }
+            let mut samples: Vec<DeepSample> =
+                fragments.into_iter().map(|(_, sample)| sample).collect();
+            samples.sort_by(|a, b| a.depth.total_cmp(&b.depth));
+            pixels.push(samples);
+        }
+    }
+
+    DeepImage {
+        width,
+        height,
+        pixels,
+    }
+}
+
+impl DeepImage {
+    /// Composite the samples of every pixel front to back over the given background
+    ///
+    /// Like every deep compositing, this takes the samples of a pixel as spread evenly over
+    /// it: samples covering different parts of the pixel, such as two spheres side by side
+    /// along their silhouettes, are composited over each other, and the farther one loses
+    /// the coverage of the nearer one there. The flat image is exact inside the objects and
+    /// slightly too dark along the silhouettes in front of other objects
+    ///
+    /// ### Returns
+    ///
+    /// `Vec<Color>` - The flat colors of the image, row by row
+    ///
+    pub fn flatten(&self, background: Color) -> Vec<Color> {
+        self.pixels
+            .iter()
+            .map(|samples| {
+                let mut color = Color::BLACK;
+                let mut alpha = 0.0;
+                for sample in samples {
+                    color += sample.color * (1.0 - alpha);
+                    alpha += sample.alpha * (1.0 - alpha);
+                }
+                color + background * (1.0 - alpha)
+            })
+            .collect()
+    }
+
+    /// Save the deep image in a simple text format
+    ///
+    /// The file starts with a `RTDEEP 1` line and a `<width> <height>` line, followed by one
+    /// line per pixel (row by row) holding the sample count and then `depth r g b alpha`
+    /// for every sample, colors being premultiplied
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        let file = std::fs::File::create(Path::new(filename))?;
+        let mut w = std::io::BufWriter::new(file);
+        writeln!(w, "RTDEEP 1")?;
+        writeln!(w, "{} {}", self.width, self.height)?;
+        for samples in &self.pixels {
+            write!(w, "{}", samples.len())?;
+            for s in samples {
+                write!(
+                    w,
+                    " {} {} {} {} {}",
+                    s.depth, s.color.r, s.color.g, s.color.b, s.alpha
+                )?;
+            }
+            writeln!(w)?;
+        }
+        w.flush()
+    }
+}
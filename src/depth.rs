@@ -0,0 +1,81 @@
+use crate::camera::Camera;
+use crate::render::PrimaryHits;
+
+/// Encoding of the values of a depth pass
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthEncoding {
+    /// Distance along the view axis, clamped to the range
+    Linear,
+    /// Distance along the view axis mapped from the range to `[0, 1]`, 0 at the near end
+    Normalized,
+    /// `1 / z` mapped from the range to `[0, 1]`, 1 at the near end, spending the precision
+    /// close to the camera
+    Inverse,
+}
+
+impl DepthEncoding {
+    /// Encoding from its name: `linear`, `normalized` or `inverse`
+    pub fn from_name(name: &str) -> Option<DepthEncoding> {
+        match name {
+            "linear" => Some(DepthEncoding::Linear),
+            "normalized" => Some(DepthEncoding::Normalized),
+            "inverse" => Some(DepthEncoding::Inverse),
+            _ => None,
+        }
+    }
+
+    /// Encode a depth, `near` and `far` being the ends of the range (pixels where nothing is
+    /// hit have an infinite depth and get the value of the far end)
+    ///
+    /// An empty range encodes every depth like its near end
+    pub fn encode(&self, depth: f64, near: f64, far: f64) -> f64 {
+        if far <= near {
+            return match self {
+                DepthEncoding::Linear => near,
+                DepthEncoding::Normalized => 0.0,
+                DepthEncoding::Inverse => 1.0,
+            };
+        }
+        let depth = depth.clamp(near, far);
+        match self {
+            DepthEncoding::Linear => depth,
+            DepthEncoding::Normalized => (depth - near) / (far - near),
+            DepthEncoding::Inverse => (1.0 / depth - 1.0 / far) / (1.0 / near - 1.0 / far),
+        }
+    }
+}
+
+/// Depth of the surface seen at every pixel center along the view axis of the camera
+///
+/// ### Arguments
+///
+/// * `hits` - The camera ray hits, traced with one sample per pixel
+/// * `camera` - The camera the hits were traced from
+///
+/// ### Returns
+///
+/// `Vec<f64>` - The depths row by row from the top left corner, infinite where nothing is
+/// hit
+///
+pub fn camera_depths(hits: &PrimaryHits, camera: &Camera) -> Vec<f64> {
+    let (width, height) = hits.size();
+    let mut depths = Vec::with_capacity(width as usize * height as usize);
+    for j in 0..height {
+        for i in 0..width {
+            let depth = match hits.first_hit(i, j) {
+                (ray, Some((_, distance))) => camera.depth(ray.at(distance)),
+                (_, None) => f64::INFINITY,
+            };
+            depths.push(depth);
+        }
+    }
+    depths
+}
+
+/// Smallest and largest finite depth of a depth pass, `None` if nothing is hit
+pub fn depth_range(depths: &[f64]) -> Option<(f64, f64)> {
+    let finite = depths.iter().copied().filter(|depth| depth.is_finite());
+    let near = finite.clone().reduce(f64::min)?;
+    let far = finite.reduce(f64::max)?;
+    Some((near, far))
+}
@@ -0,0 +1,369 @@
+use crate::color::Color;
+use crate::colorspace::TextureSpace;
+use crate::image::Image;
+use crate::json::Json;
+use crate::random::stratified_2d;
+use crate::random::Rng;
+use crate::ray::Ray;
+use crate::render::MISSING_TEXTURE_COLOR;
+use crate::settings::vector_to_json;
+use crate::sky::Dome;
+use crate::texture::texture;
+use crate::texture::TextureFormat;
+use crate::texture::TextureImage;
+
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+
+/// Samples per output pixel of the layout conversions, on a jittered grid
+const CONVERSION_SAMPLES: u32 = 16;
+
+/// Faces of a cubemap in the order they are laid out left to right: +X, -X, +Y, -Y, +Z, -Z
+pub const CUBE_FACES: usize = 6;
+
+/// Equirectangular image or analytic dome lighting the rays that leave the scene, in place
+/// of the background color
+#[derive(Clone, Debug, PartialEq)]
+pub struct Environment {
+    pub source: EnvironmentSource,
+    /// Rotation of the map about the vertical (+Y) axis in radians, following the right
+    /// hand rule
+    pub rotation: f64,
+    /// Factor the colors of the map are multiplied by
+    pub intensity: f64,
+    /// Color space of the image file
+    pub space: TextureSpace,
+    /// Projection of the lower half of the map on a virtual ground, `None` for a map at
+    /// infinity
+    pub ground: Option<GroundProjection>,
+}
+
+/// What the rays leaving the scene see
+#[derive(Clone, Debug, PartialEq)]
+pub enum EnvironmentSource {
+    /// Path of an equirectangular PNG image
+    Map(String),
+    Dome(Dome),
+}
+
+/// Dome the environment map is projected on, flat at the bottom, so objects stand on the
+/// photographed ground instead of floating above it
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroundProjection {
+    /// Point of the ground under the position the map was captured from
+    pub center: Vector3<f64>,
+    /// Height of the capture position above the ground
+    pub height: f64,
+    /// Radius of the dome around the capture position, the ground ends at its border
+    pub radius: f64,
+}
+
+impl Environment {
+    pub fn new(path: &str) -> Environment {
+        Environment::with_source(EnvironmentSource::Map(path.to_string()))
+    }
+
+    /// Environment of an analytic dome instead of a map
+    pub fn dome(dome: Dome) -> Environment {
+        Environment::with_source(EnvironmentSource::Dome(dome))
+    }
+
+    fn with_source(source: EnvironmentSource) -> Environment {
+        Environment {
+            source,
+            rotation: 0.0,
+            intensity: 1.0,
+            space: TextureSpace::default(),
+            ground: None,
+        }
+    }
+
+    /// Path of the map, `None` for a dome
+    pub fn path(&self) -> Option<&str> {
+        match &self.source {
+            EnvironmentSource::Map(path) => Some(path),
+            EnvironmentSource::Dome(_) => None,
+        }
+    }
+
+    /// Path of the map or kind of the dome, e.g. `sky dome`
+    pub fn description(&self) -> String {
+        match &self.source {
+            EnvironmentSource::Map(path) => path.clone(),
+            EnvironmentSource::Dome(dome) => format!("{} dome", dome.name()),
+        }
+    }
+
+    /// Environment as the `environment` of a scene file, the rotation in degrees and the
+    /// properties keeping their default values left out
+    pub fn to_json(&self) -> Json {
+        let mut entries = vec![match &self.source {
+            EnvironmentSource::Map(path) => ("texture".to_string(), Json::String(path.clone())),
+            EnvironmentSource::Dome(dome) => ("dome".to_string(), dome.to_json()),
+        }];
+        if self.rotation != 0.0 {
+            entries.push((
+                "rotation".to_string(),
+                Json::Number(self.rotation.to_degrees()),
+            ));
+        }
+        if self.intensity != 1.0 {
+            entries.push(("intensity".to_string(), Json::Number(self.intensity)));
+        }
+        if self.space != TextureSpace::default() {
+            let space = Json::String(self.space.name().to_string());
+            entries.push(("space".to_string(), space));
+        }
+        if let Some(ground) = &self.ground {
+            let ground = Json::Object(vec![
+                ("center".to_string(), vector_to_json(ground.center)),
+                ("height".to_string(), Json::Number(ground.height)),
+                ("radius".to_string(), Json::Number(ground.radius)),
+            ]);
+            entries.push(("ground_projection".to_string(), ground));
+        }
+        Json::Object(entries)
+    }
+
+    /// Direction towards the sun of a sky dome in the scene, with the cosine of the angular
+    /// radius of its disk, `None` without a sun
+    pub fn sun(&self) -> Option<(Vector3<f64>, f64)> {
+        let EnvironmentSource::Dome(dome) = &self.source else {
+            return None;
+        };
+        let (direction, cos_radius) = dome.sun()?;
+        // the inverse of the rotation the directions are looked up with
+        let (sin, cos) = self.rotation.sin_cos();
+        let turned = Vector3::new(
+            cos * direction.x + sin * direction.z,
+            direction.y,
+            -sin * direction.x + cos * direction.z,
+        );
+        Some((turned, cos_radius))
+    }
+
+    pub fn with_rotation(mut self, rotation: f64) -> Environment {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Environment {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_space(mut self, space: TextureSpace) -> Environment {
+        self.space = space;
+        self
+    }
+
+    pub fn with_ground_projection(mut self, ground: GroundProjection) -> Environment {
+        self.ground = Some(ground);
+        self
+    }
+
+    /// Radiance seen by a ray leaving the scene (magenta if the map fails to load)
+    ///
+    /// Domes are given in the working space of the format, without loading anything
+    ///
+    /// ### Arguments
+    ///
+    /// * `ray` - The ray leaving the scene
+    /// * `budget` - The texture memory budget in bytes (see `TextureCache::get`)
+    /// * `format` - How the map is loaded, its color space is the one of the environment
+    ///
+    /// ### Returns
+    ///
+    /// Color - The radiance seen along the ray
+    ///
+    pub fn radiance(&self, ray: &Ray, budget: usize, format: TextureFormat) -> Color {
+        let direction = match &self.ground {
+            Some(ground) => ground.lookup_direction(ray),
+            None => ray.direction,
+        };
+        // the map is looked up with the inverse rotation
+        let (sin, cos) = self.rotation.sin_cos();
+        let turned = Vector3::new(
+            cos * direction.x - sin * direction.z,
+            direction.y,
+            sin * direction.x + cos * direction.z,
+        );
+        let path = match &self.source {
+            EnvironmentSource::Map(path) => path,
+            EnvironmentSource::Dome(dome) => {
+                return dome.radiance(turned, format.working_space) * self.intensity;
+            }
+        };
+        let Some(map) = texture(path, budget, format.with_space(self.space)) else {
+            return MISSING_TEXTURE_COLOR;
+        };
+        let (x, y) = equirect_position(turned);
+        map.sample(x, 1.0 - y) * self.intensity
+    }
+}
+
+impl GroundProjection {
+    pub fn new(center: Vector3<f64>, height: f64, radius: f64) -> GroundProjection {
+        GroundProjection {
+            center,
+            height,
+            radius,
+        }
+    }
+
+    /// Direction of the map seen by a ray: from the capture position towards the point
+    /// where the ray meets the dome or the ground
+    ///
+    /// Rays starting outside of the dome see the map at infinity
+    pub fn lookup_direction(&self, ray: &Ray) -> Vector3<f64> {
+        let capture = self.center + Vector3::new(0.0, self.height, 0.0);
+        let offset = ray.origin - capture;
+        // the far intersection with the dome, the ray starts inside it
+        let b = offset.dot(&ray.direction);
+        let c = offset.norm_squared() - self.radius * self.radius;
+        if c > 0.0 {
+            return ray.direction;
+        }
+        let mut t = -b + (b * b - c).sqrt();
+        // the part of the dome below the ground is cut off by the ground plane
+        if ray.direction.y < 0.0 && ray.at(t).y < self.center.y {
+            t = (self.center.y - ray.origin.y) / ray.direction.y;
+        }
+        (ray.at(t) - capture).normalize()
+    }
+}
+
+/// Direction of a point of an equirectangular image, `x` and `y` in `[0, 1]` from the top
+/// left corner
+///
+/// The center of the image looks along -Z (the default camera direction), +Y is up
+pub fn equirect_direction(x: f64, y: f64) -> Vector3<f64> {
+    let phi = (x - 0.5) * 2.0 * PI;
+    let theta = y * PI;
+    Vector3::new(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+/// Point of an equirectangular image seen in a direction, the inverse of
+/// `equirect_direction`
+pub fn equirect_position(direction: Vector3<f64>) -> (f64, f64) {
+    let d = direction.normalize();
+    let x = 0.5 + d.x.atan2(-d.z) / (2.0 * PI);
+    let y = d.y.clamp(-1.0, 1.0).acos() / PI;
+    (x, y)
+}
+
+/// Direction of a point of a cubemap face, `s` and `t` in `[0, 1]` from the top left corner
+/// of the face (faces seen from inside the cube, with +Y up on the side faces)
+pub fn cube_direction(face: usize, s: f64, t: f64) -> Vector3<f64> {
+    let (a, b) = (2.0 * s - 1.0, 2.0 * t - 1.0);
+    let direction = match face {
+        0 => Vector3::new(1.0, -b, -a),
+        1 => Vector3::new(-1.0, -b, a),
+        2 => Vector3::new(a, 1.0, b),
+        3 => Vector3::new(a, -1.0, -b),
+        4 => Vector3::new(a, -b, 1.0),
+        _ => Vector3::new(-a, -b, -1.0),
+    };
+    direction.normalize()
+}
+
+/// Face and point of a cubemap seen in a direction, the inverse of `cube_direction`
+pub fn cube_position(direction: Vector3<f64>) -> (usize, f64, f64) {
+    let d = direction;
+    let (ax, ay, az) = (d.x.abs(), d.y.abs(), d.z.abs());
+    // project on the face of the largest component
+    let (face, a, b) = if ax >= ay && ax >= az {
+        if d.x > 0.0 {
+            (0, -d.z / ax, -d.y / ax)
+        } else {
+            (1, d.z / ax, -d.y / ax)
+        }
+    } else if ay >= az {
+        if d.y > 0.0 {
+            (2, d.x / ay, d.z / ay)
+        } else {
+            (3, d.x / ay, -d.z / ay)
+        }
+    } else if d.z > 0.0 {
+        (4, d.x / az, -d.y / az)
+    } else {
+        (5, -d.x / az, -d.y / az)
+    };
+    (face, (a + 1.0) / 2.0, (b + 1.0) / 2.0)
+}
+
+/// Convert an equirectangular environment map to a cubemap, its six square faces side by
+/// side (see `CUBE_FACES`)
+///
+/// ### Arguments
+///
+/// * `equirect` - The equirectangular map
+/// * `face_size` - The size of a face in pixels
+///
+/// ### Returns
+///
+/// `Image` - The cubemap, `6 * face_size` pixels wide and `face_size` pixels high
+///
+pub fn equirect_to_cubemap(equirect: &TextureImage, face_size: u32) -> Image {
+    let face_size = face_size.max(1);
+    let width = face_size * CUBE_FACES as u32;
+    resample(width, face_size, |x, y| {
+        let face = ((x * CUBE_FACES as f64) as usize).min(CUBE_FACES - 1);
+        let s = x * CUBE_FACES as f64 - face as f64;
+        let (u, v) = equirect_position(cube_direction(face, s, y));
+        equirect.sample(u, 1.0 - v)
+    })
+}
+
+/// Convert a cubemap laid out like the output of `equirect_to_cubemap` to an
+/// equirectangular environment map
+///
+/// ### Arguments
+///
+/// * `cubemap` - The cubemap, its six faces side by side
+/// * `width` - The width of the equirectangular map, half of it for its height
+///
+/// ### Returns
+///
+/// `Image` - The equirectangular map
+///
+pub fn cubemap_to_equirect(cubemap: &TextureImage, width: u32) -> Image {
+    let width = width.max(2);
+    resample(width, width / 2, |x, y| {
+        let (face, s, t) = cube_position(equirect_direction(x, y));
+        // keep the lookup inside the face, the bilinear filter would blend neighbor faces
+        let half_texel = 0.5 / cubemap.height as f64;
+        let s = s.clamp(half_texel, 1.0 - half_texel);
+        let t = t.clamp(half_texel, 1.0 - half_texel);
+        cubemap.sample((face as f64 + s) / CUBE_FACES as f64, 1.0 - t)
+    })
+}
+
+/// Image whose pixels average a color function over a jittered grid of points, the points
+/// given in `[0, 1]` from the top left corner of the image
+fn resample(width: u32, height: u32, color: impl Fn(f64, f64) -> Color) -> Image {
+    let mut image = Image::new(width, height);
+    for j in 0..height {
+        for i in 0..width {
+            let mut rng = Rng::new((j as u64) << 32 | i as u64);
+            let mut sum = Color::BLACK;
+            for k in 0..CONVERSION_SAMPLES {
+                let (u, v) = stratified_2d(k, CONVERSION_SAMPLES, &mut rng);
+                sum += color(
+                    (i as f64 + u) / width as f64,
+                    (j as f64 + v) / height as f64,
+                );
+            }
+            let average = sum / CONVERSION_SAMPLES as f64;
+            let pixel = average
+                .to_array()
+                .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+            image.set_pixel(i, j, pixel);
+        }
+    }
+    image
+}
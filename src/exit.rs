@@ -0,0 +1,229 @@
+use crate::autosave;
+use crate::batch::BatchError;
+use crate::config::ConfigError;
+use crate::i18n::tr;
+use crate::i18n::tr_format;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::lpe::LpeError;
+use crate::parameter::ParameterError;
+use crate::pathtemplate::TemplateError;
+
+use std::error::Error;
+use std::fmt::Display;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+/// Whether errors are reported as JSON, set by `set_error_format`
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Exit code of the program for each kind of failure, so scripts can tell them apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    /// Any other failure, e.g. a scene that doesn't validate
+    Failure = 1,
+    /// Invalid command line: unknown command, missing argument, invalid override, light
+    /// path expression or batch file line
+    Usage = 2,
+    /// Scene, settings or config file that can't be parsed
+    SceneParse = 3,
+    /// Texture, scene or other input file missing or unreadable
+    AssetMissing = 4,
+    /// Render stopped before it finished
+    RenderCancelled = 5,
+    /// Output that can't be written
+    Io = 6,
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// Name of the kind of failure in JSON reports
+    pub fn name(self) -> &'static str {
+        match self {
+            ExitCode::Failure => "failure",
+            ExitCode::Usage => "usage",
+            ExitCode::SceneParse => "scene-parse",
+            ExitCode::AssetMissing => "asset-missing",
+            ExitCode::RenderCancelled => "render-cancelled",
+            ExitCode::Io => "io",
+        }
+    }
+
+    /// Kind of failure an error stands for, from its type
+    pub fn of_error(error: &(dyn Error + 'static)) -> ExitCode {
+        if let Some(error) = error.downcast_ref::<std::io::Error>() {
+            return match error.kind() {
+                std::io::ErrorKind::NotFound | std::io::ErrorKind::PermissionDenied => {
+                    ExitCode::AssetMissing
+                }
+                _ => ExitCode::Io,
+            };
+        }
+        if error.is::<JsonError>() || error.is::<ConfigError>() {
+            ExitCode::SceneParse
+        } else if error.is::<ParameterError>()
+            || error.is::<TemplateError>()
+            || error.is::<LpeError>()
+            || error.is::<BatchError>()
+        {
+            ExitCode::Usage
+        } else if error.is::<png::DecodingError>() {
+            ExitCode::AssetMissing
+        } else {
+            ExitCode::Failure
+        }
+    }
+}
+
+/// How errors are written to stderr
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// A line of text, panics keep the default panic message
+    Text,
+    /// A JSON object with the `error` kind, the exit `code` and the `message`
+    Json,
+}
+
+impl ErrorFormat {
+    /// Format from its name: `text` or `json`
+    pub fn from_name(name: &str) -> Option<ErrorFormat> {
+        match name {
+            "text" => Some(ErrorFormat::Text),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+pub fn set_error_format(format: ErrorFormat) {
+    JSON_ERRORS.store(format == ErrorFormat::Json, Ordering::Relaxed);
+}
+
+/// Report an error in the chosen format and end the program with its exit code
+pub fn exit_with(code: ExitCode, message: &str) -> ! {
+    report(code, message);
+    std::process::exit(code.code())
+}
+
+/// Report a usage error with the synopsis of a command, e.g. `bake <sphere index> [ao]`, and
+/// end the program with `ExitCode::Usage`
+pub fn exit_with_usage(synopsis: &str) -> ! {
+    exit_with(ExitCode::Usage, &tr_format("usage: {}", &[&synopsis]))
+}
+
+/// Make panics end the program with an exit code instead of the generic 101: `Usage` for
+/// messages starting with `usage:`, `RenderCancelled` for panics during a render and
+/// `Failure` for the others
+///
+/// Hooks installed afterwards, such as `autosave::install_panic_hook`, run before this one
+pub fn install_error_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        let code = if message.starts_with("usage:") {
+            ExitCode::Usage
+        } else if autosave::rendering() {
+            ExitCode::RenderCancelled
+        } else {
+            ExitCode::Failure
+        };
+        if JSON_ERRORS.load(Ordering::Relaxed) {
+            report(code, &message);
+        } else {
+            default_hook(info);
+        }
+        std::process::exit(code.code());
+    }));
+}
+
+/// Write an error to stderr in the chosen format
+fn report(code: ExitCode, message: &str) {
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        let report = Json::Object(vec![
+            ("error".to_string(), Json::String(code.name().to_string())),
+            ("code".to_string(), Json::Number(code.code() as f64)),
+            ("message".to_string(), Json::String(message.to_string())),
+        ]);
+        eprintln!("{}", report.to_pretty_string());
+    } else {
+        eprintln!("{}: {}", tr("error"), message);
+    }
+}
+
+/// End the program with a reported error instead of panicking on a failed result
+pub trait OrExit<T> {
+    /// Value of the result, or exit with the code of the error (see `ExitCode::of_error`)
+    /// after reporting it behind the given message, translated by the catalog of the program
+    /// (see `i18n::tr`)
+    fn or_exit(self, message: &str) -> T;
+
+    /// Value of the result, or exit like `or_exit` behind a message filled in from a
+    /// template, the template being translated before it is filled (see `i18n::tr_format`)
+    fn or_exit_format(self, template: &str, args: &[&dyn Display]) -> T;
+
+    /// Value of the result, or exit like `or_exit` but always with `ExitCode::Io`, for
+    /// outputs that can't be written: a missing directory or a denied permission there is
+    /// no missing asset
+    fn or_exit_io(self, message: &str) -> T;
+
+    /// Value of the result, or exit like `or_exit_io` behind a message filled in from a
+    /// template (see `or_exit_format`)
+    fn or_exit_io_format(self, template: &str, args: &[&dyn Display]) -> T;
+}
+
+impl<T, E: Into<Box<dyn Error>>> OrExit<T> for Result<T, E> {
+    fn or_exit(self, message: &str) -> T {
+        self.unwrap_or_else(|error| exit_after(error.into(), &tr(message)))
+    }
+
+    fn or_exit_format(self, template: &str, args: &[&dyn Display]) -> T {
+        self.unwrap_or_else(|error| exit_after(error.into(), &tr_format(template, args)))
+    }
+
+    fn or_exit_io(self, message: &str) -> T {
+        self.unwrap_or_else(|error| {
+            exit_with(ExitCode::Io, &format!("{}: {}", tr(message), error.into()))
+        })
+    }
+
+    fn or_exit_io_format(self, template: &str, args: &[&dyn Display]) -> T {
+        self.unwrap_or_else(|error| {
+            let message = tr_format(template, args);
+            exit_with(ExitCode::Io, &format!("{}: {}", message, error.into()))
+        })
+    }
+}
+
+/// End the program with a usage error instead of panicking on a missing or invalid argument
+pub trait OrUsage<T> {
+    /// Value of the option or the result, or exit with `exit_with_usage`
+    fn or_usage(self, synopsis: &str) -> T;
+}
+
+impl<T> OrUsage<T> for Option<T> {
+    fn or_usage(self, synopsis: &str) -> T {
+        self.unwrap_or_else(|| exit_with_usage(synopsis))
+    }
+}
+
+impl<T, E> OrUsage<T> for Result<T, E> {
+    fn or_usage(self, synopsis: &str) -> T {
+        self.unwrap_or_else(|_| exit_with_usage(synopsis))
+    }
+}
+
+/// Report an error behind a translated message and exit with its code
+fn exit_after(error: Box<dyn Error>, message: &str) -> ! {
+    exit_with(
+        ExitCode::of_error(error.as_ref()),
+        &format!("{}: {}", message, error),
+    )
+}
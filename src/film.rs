@@ -0,0 +1,290 @@
+use crate::color::Color;
+use crate::colorspace::ColorPipeline;
+use crate::gbuffer::write_pfm;
+use crate::half::HalfColor;
+use crate::image::Image;
+use crate::image::Rect;
+use crate::render::sanitize_radiance;
+
+/// Camera samples accumulated in a pixel of a film
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FilmPixel {
+    /// Sum of the radiance of the samples times their filter weights
+    pub sum: Color,
+    /// Sum of the filter weights of the samples
+    pub weight: f64,
+    /// Number of samples added
+    pub samples: u32,
+}
+
+impl FilmPixel {
+    /// Add the radiance of a camera sample with its reconstruction filter weight (see
+    /// `render::camera_samples`)
+    pub fn add(&mut self, color: Color, weight: f64) {
+        self.sum += color * weight;
+        self.weight += weight;
+        self.samples += 1;
+    }
+
+    /// Add the samples of another pixel, e.g. one rendered in another pass
+    pub fn merge(&mut self, other: &FilmPixel) {
+        self.sum += other.sum;
+        self.weight += other.weight;
+        self.samples += other.samples;
+    }
+
+    /// Radiance of the pixel: the weighted sum of its samples divided by the sum of their
+    /// weights, black before any sample is added or when the negative weights of the filter
+    /// cancel out the positive ones
+    pub fn value(&self) -> Color {
+        if self.weight > 0.0 {
+            self.sum / self.weight
+        } else {
+            Color::BLACK
+        }
+    }
+}
+
+/// Camera samples of a pixel stored in half precision (see `HalfFilm`), less than half the
+/// memory of a `FilmPixel`
+///
+/// The radiance is kept as the average of the samples rather than their sum, so it stays
+/// in the range of half precision however many samples are added
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HalfPixel {
+    pub value: HalfColor,
+    pub weight: f32,
+    pub samples: u32,
+}
+
+impl HalfPixel {
+    pub fn new(pixel: &FilmPixel) -> HalfPixel {
+        HalfPixel {
+            value: HalfColor::new(pixel.value()),
+            weight: pixel.weight as f32,
+            samples: pixel.samples,
+        }
+    }
+
+    /// Samples of the pixel in full precision, up to the rounding of the stored values
+    pub fn pixel(&self) -> FilmPixel {
+        let weight = self.weight as f64;
+        FilmPixel {
+            sum: self.value.color() * weight,
+            weight,
+            samples: self.samples,
+        }
+    }
+}
+
+/// Sensor the camera samples of a render are accumulated on, separate from how the samples
+/// are taken
+///
+/// A film covers a rectangle of the image (the whole image, a band of rows or a tile), its
+/// pixels keep the filtered sums of their samples, so more samples can be added to them
+/// later or films of the same pixels merged, and it is developed into an image with a color
+/// pipeline or saved as linear radiance once the samples are in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Film {
+    /// Pixels of the image the film covers
+    pub rect: Rect,
+    /// Accumulated samples, row by row from the top left corner of the rectangle
+    pixels: Vec<FilmPixel>,
+}
+
+impl Film {
+    pub fn new(rect: Rect) -> Film {
+        Film {
+            rect,
+            pixels: vec![FilmPixel::default(); rect.width as usize * rect.height as usize],
+        }
+    }
+
+    /// Film covering a whole image
+    pub fn full(width: u32, height: u32) -> Film {
+        Film::new(Rect::new(0, 0, width, height))
+    }
+
+    /// Index of a pixel of the image in `pixels`, `None` if the film doesn't cover it
+    fn index(&self, i: u32, j: u32) -> Option<usize> {
+        self.rect.index(i, j)
+    }
+
+    /// Samples of a pixel, from its position in the image
+    ///
+    /// Panics if the film doesn't cover the pixel
+    pub fn pixel(&self, i: u32, j: u32) -> &FilmPixel {
+        match self.index(i, j) {
+            Some(index) => &self.pixels[index],
+            None => panic!("pixel ({}, {}) is outside the film {:?}", i, j, self.rect),
+        }
+    }
+
+    /// Samples of a pixel, from its position in the image, to add samples to
+    ///
+    /// Panics if the film doesn't cover the pixel
+    pub fn pixel_mut(&mut self, i: u32, j: u32) -> &mut FilmPixel {
+        match self.index(i, j) {
+            Some(index) => &mut self.pixels[index],
+            None => panic!("pixel ({}, {}) is outside the film {:?}", i, j, self.rect),
+        }
+    }
+
+    /// Add a camera sample to a pixel, from its position in the image (see
+    /// `FilmPixel::add`)
+    pub fn add_sample(&mut self, i: u32, j: u32, color: Color, weight: f64) {
+        self.pixel_mut(i, j).add(color, weight);
+    }
+
+    /// Rows of the film with the row of the image they are, so they can be filled by
+    /// separate threads
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = (u32, &mut [FilmPixel])> {
+        let first_row = self.rect.y;
+        self.pixels
+            .chunks_mut(self.rect.width.max(1) as usize)
+            .enumerate()
+            .map(move |(k, row)| (first_row + k as u32, row))
+    }
+
+    /// Add the samples of another film to the pixels both films cover
+    pub fn merge(&mut self, other: &Film) {
+        for j in other.rect.y..other.rect.y + other.rect.height {
+            for i in other.rect.x..other.rect.x + other.rect.width {
+                if let Some(index) = self.index(i, j) {
+                    self.pixels[index].merge(other.pixel(i, j));
+                }
+            }
+        }
+    }
+
+    /// Radiance of the pixels row by row, invalid values replaced by black (see
+    /// `render::sanitize_radiance`)
+    pub fn radiance(&self) -> Vec<Color> {
+        self.pixels
+            .iter()
+            .map(|pixel| sanitize_radiance(pixel.value()))
+            .collect()
+    }
+
+    /// Number of samples of the pixels row by row
+    pub fn samples(&self) -> Vec<u32> {
+        self.pixels.iter().map(|pixel| pixel.samples).collect()
+    }
+
+    /// Image of the film, the size of its rectangle, with the radiance converted to 8-bit
+    /// RGB by a color pipeline
+    pub fn develop(&self, pipeline: &ColorPipeline) -> Image {
+        let mut image = Image::new(self.rect.width, self.rect.height);
+        develop_pixels(pipeline, &self.pixels, &mut image.data);
+        image
+    }
+
+    /// Save the linear radiance of the film as a PFM image
+    pub fn save_pfm(&self, filename: &str) -> std::io::Result<()> {
+        let values: Vec<f64> = self.radiance().iter().flat_map(Color::to_array).collect();
+        write_pfm(filename, self.rect.width, self.rect.height, 3, &values)
+    }
+}
+
+/// Film keeping its samples in half precision (see `HalfPixel`), to accumulate the passes
+/// of a large render with less memory and memory traffic
+///
+/// Samples are added a film at a time (see `merge`), the pixels being converted to full
+/// precision and back, so every merge rounds the radiance to about three significant digits
+#[derive(Clone, Debug, PartialEq)]
+pub struct HalfFilm {
+    pub rect: Rect,
+    pixels: Vec<HalfPixel>,
+}
+
+impl HalfFilm {
+    pub fn new(rect: Rect) -> HalfFilm {
+        HalfFilm {
+            rect,
+            pixels: vec![HalfPixel::default(); rect.width as usize * rect.height as usize],
+        }
+    }
+
+    /// Add the samples of a film to the pixels both films cover
+    pub fn merge(&mut self, other: &Film) {
+        for j in other.rect.y..other.rect.y + other.rect.height {
+            for i in other.rect.x..other.rect.x + other.rect.width {
+                let Some(index) = self.rect.index(i, j) else {
+                    continue;
+                };
+                let mut pixel = self.pixels[index].pixel();
+                pixel.merge(other.pixel(i, j));
+                self.pixels[index] = HalfPixel::new(&pixel);
+            }
+        }
+    }
+
+    /// Radiance of the pixels row by row, like `Film::radiance`
+    pub fn radiance(&self) -> Vec<Color> {
+        self.pixels
+            .iter()
+            .map(|pixel| sanitize_radiance(pixel.pixel().value()))
+            .collect()
+    }
+
+    /// Number of samples of the pixels row by row
+    pub fn samples(&self) -> Vec<u32> {
+        self.pixels.iter().map(|pixel| pixel.samples).collect()
+    }
+}
+
+/// Film accumulating the samples of the passes of a render, in full or half precision (see
+/// `RenderSettings::half_precision`)
+#[derive(Clone, Debug, PartialEq)]
+pub enum Accumulator {
+    Full(Film),
+    Half(HalfFilm),
+}
+
+impl Accumulator {
+    pub fn new(rect: Rect, half_precision: bool) -> Accumulator {
+        if half_precision {
+            Accumulator::Half(HalfFilm::new(rect))
+        } else {
+            Accumulator::Full(Film::new(rect))
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        match self {
+            Accumulator::Full(film) => film.rect,
+            Accumulator::Half(film) => film.rect,
+        }
+    }
+
+    /// Add the samples of a film to the pixels both films cover
+    pub fn merge(&mut self, other: &Film) {
+        match self {
+            Accumulator::Full(film) => film.merge(other),
+            Accumulator::Half(film) => film.merge(other),
+        }
+    }
+
+    /// Radiance of the pixels row by row, invalid values replaced by black
+    pub fn radiance(&self) -> Vec<Color> {
+        match self {
+            Accumulator::Full(film) => film.radiance(),
+            Accumulator::Half(film) => film.radiance(),
+        }
+    }
+
+    /// Number of samples of the pixels row by row
+    pub fn samples(&self) -> Vec<u32> {
+        match self {
+            Accumulator::Full(film) => film.samples(),
+            Accumulator::Half(film) => film.samples(),
+        }
+    }
+}
+
+/// Convert the radiance of film pixels to RGB triplets with a color pipeline
+pub fn develop_pixels(pipeline: &ColorPipeline, pixels: &[FilmPixel], data: &mut [u8]) {
+    for (pixel, rgb) in pixels.iter().zip(data.chunks_exact_mut(3)) {
+        rgb.copy_from_slice(&pipeline.to_rgb8(sanitize_radiance(pixel.value())));
+    }
+}
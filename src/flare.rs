@@ -0,0 +1,176 @@
+use crate::color::Color;
+use crate::colorspace::ColorPipeline;
+use crate::image::Image;
+use crate::object::LightKind;
+use crate::ray::Ray;
+use crate::render::is_occluded;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+/// Ghosts of a flare: their position along the line from the light through the center of
+/// the image (0 at the light, 2 at its mirror image), their radius as a fraction of the
+/// image height and their tint
+const GHOSTS: [(f64, f64, Color); 4] = [
+    (0.6, 0.02, Color::new(0.6, 0.8, 1.0)),
+    (1.3, 0.05, Color::new(1.0, 0.7, 0.4)),
+    (1.7, 0.015, Color::new(0.5, 1.0, 0.6)),
+    (2.2, 0.09, Color::new(0.6, 0.5, 1.0)),
+];
+
+/// Light seen by the camera, the seed of a flare
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlareSource {
+    /// Position of the light on the image, in pixels
+    pub x: f64,
+    pub y: f64,
+    /// Light emitted towards the camera (see `SceneLight::radiance`)
+    pub radiance: Color,
+}
+
+/// Lens flare drawn over the final image around the lights the camera sees: a glow, a
+/// starburst of thin streaks and ghosts reflected between the lens elements
+///
+/// Lights are points without a size, the camera rays never hit them, so the flare is drawn
+/// from their positions projected on the image; lights hidden by an object, outside of the
+/// image or turned away from the camera (spot lights) have no flare
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LensFlare {
+    /// Factor the flare radiance is multiplied by, relative to the light intensity
+    pub strength: f64,
+    /// Number of streaks of the starburst, 0 for none
+    pub rays: u32,
+    /// Whether the ghosts are drawn
+    pub ghosts: bool,
+}
+
+impl LensFlare {
+    /// Flare with a six-pointed starburst and ghosts
+    pub fn new(strength: f64) -> LensFlare {
+        LensFlare {
+            strength,
+            rays: 6,
+            ghosts: true,
+        }
+    }
+
+    pub fn with_rays(mut self, rays: u32) -> LensFlare {
+        self.rays = rays;
+        self
+    }
+
+    pub fn with_ghosts(mut self, ghosts: bool) -> LensFlare {
+        self.ghosts = ghosts;
+        self
+    }
+
+    /// Radiance of the flares of the sources at a point of the image
+    ///
+    /// ### Arguments
+    ///
+    /// * `sources` - The lights seen by the camera (see `flare_sources`)
+    /// * `x`, `y` - The point of the image, in pixels
+    /// * `width`, `height` - The size of the image, the flares scale with its height
+    ///
+    /// ### Returns
+    ///
+    /// Color - The radiance the flares add at the point
+    ///
+    pub fn radiance(
+        &self,
+        sources: &[FlareSource],
+        (x, y): (f64, f64),
+        (width, height): (u32, u32),
+    ) -> Color {
+        let size = height as f64;
+        let center = (width as f64 / 2.0, height as f64 / 2.0);
+        let mut total = Color::BLACK;
+        for source in sources {
+            let (dx, dy) = (x - source.x, y - source.y);
+            let distance = dx.hypot(dy);
+            let glow = (-(distance / (0.01 * size)).powi(2)).exp()
+                + 0.15 / (1.0 + (distance / (0.03 * size)).powi(2));
+            // a streak on each side of every line through the light
+            let mut streaks = 0.0;
+            let lines = self.rays.div_ceil(2);
+            for line in 0..lines {
+                let angle = std::f64::consts::PI * line as f64 / lines as f64;
+                let across = (dy * angle.cos() - dx * angle.sin()).abs();
+                streaks +=
+                    0.5 * (-across / (0.002 * size)).exp() * (-distance / (0.15 * size)).exp();
+            }
+            total += source.radiance * (glow + streaks);
+            if !self.ghosts {
+                continue;
+            }
+            for (position, radius, tint) in GHOSTS {
+                let ghost_x = source.x + (center.0 - source.x) * position;
+                let ghost_y = source.y + (center.1 - source.y) * position;
+                let edge = (x - ghost_x).hypot(y - ghost_y) / (radius * size);
+                // soft disks, a little brighter at their rim
+                let disk = 1.0 - smoothstep(0.8, 1.0, edge);
+                total += source.radiance * tint * (0.03 * disk * (0.5 + 0.5 * edge.min(1.0)));
+            }
+        }
+        total * self.strength
+    }
+
+    /// Draw the flares of the lights the camera sees over an image, the flare radiance
+    /// going through the color pipeline and adding to the pixels
+    pub fn apply(
+        &self,
+        image: &mut Image,
+        scene: &Scene,
+        settings: &RenderSettings,
+        pipeline: &ColorPipeline,
+    ) {
+        let sources = flare_sources(scene, settings);
+        if sources.is_empty() {
+            return;
+        }
+        let size = (image.width, image.height);
+        for j in 0..image.height {
+            for i in 0..image.width {
+                let flare = self.radiance(&sources, (i as f64 + 0.5, j as f64 + 0.5), size);
+                let Some(pixel) = image.pixel(i, j) else {
+                    continue;
+                };
+                let added = pipeline.apply(flare).to_array();
+                let [r, g, b] =
+                    [0, 1, 2].map(|c| (pixel[c] as f64 + added[c] * 255.0).min(255.0) as u8);
+                image.set_pixel_rgb(i, j, r, g, b);
+            }
+        }
+    }
+}
+
+/// Lights of the scene the camera of the settings sees: in front of it, inside the image,
+/// not hidden by an object and, for spot lights, turned towards it
+pub fn flare_sources(scene: &Scene, settings: &RenderSettings) -> Vec<FlareSource> {
+    let eye = settings.camera.position;
+    scene
+        .lights
+        .iter()
+        .filter_map(|light| {
+            let radiance = light.radiance() * light.attenuation(eye);
+            if radiance.max_channel() <= 0.0 {
+                return None;
+            }
+            let (direction, distance) = light.direction_from(eye);
+            let point = match light.kind {
+                // the sun is seen in its direction, wherever the camera stands
+                LightKind::Directional { .. } => eye + direction,
+                _ => light.position,
+            };
+            let (x, y) = settings.project(point)?;
+            let inside = (0.0..settings.width as f64).contains(&x)
+                && (0.0..settings.height as f64).contains(&y);
+            let ray = Ray::new(eye, direction).with_t_max(distance);
+            (inside && !is_occluded(&ray, scene)).then_some(FlareSource { x, y, radiance })
+        })
+        .collect()
+}
+
+fn smoothstep(low: f64, high: f64, x: f64) -> f64 {
+    let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -0,0 +1,184 @@
+use crate::color::Color;
+use crate::colorspace::ColorPipeline;
+use crate::debug::exposure_value;
+use crate::debug::false_color;
+use crate::image::Image;
+use crate::ray::Ray;
+use crate::reconstruction::Reconstruction;
+use crate::render::cast_ray;
+use crate::render::is_valid_radiance;
+use crate::render::nearest_sphere;
+use crate::render::reflect;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+use nalgebra::Vector3;
+
+/// Linear radiance of a rendered frame together with auxiliary feature buffers (AOVs)
+/// of the surfaces seen by the camera (seen through mirrors when the camera looks at one)
+///
+/// The AOVs are noise free, so they can guide reconstruction filters that share information
+/// between neighbouring pixels
+#[derive(Clone)]
+pub struct FrameBuffer {
+    pub width: u32,
+    pub height: u32,
+    /// Radiance, row by row from the top left corner
+    pub color: Vec<Color>,
+    /// Diffuse color of the visible surface (the background color where nothing is hit)
+    pub albedo: Vec<Color>,
+    /// Surface normal of the visible surface (zero where nothing is hit)
+    pub normal: Vec<Vector3<f64>>,
+    /// Distance from the camera to the visible surface (infinite where nothing is hit)
+    pub depth: Vec<f64>,
+    /// Pixels whose radiance came out NaN, infinite or negative and was replaced by black
+    pub invalid: Vec<bool>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: u32, height: u32) -> FrameBuffer {
+        let size = width as usize * height as usize;
+        FrameBuffer {
+            width,
+            height,
+            color: vec![Color::BLACK; size],
+            albedo: vec![Color::BLACK; size],
+            normal: vec![Vector3::default(); size],
+            depth: vec![f64::INFINITY; size],
+            invalid: vec![false; size],
+        }
+    }
+
+    /// Index of a pixel in the buffers
+    pub fn index(&self, i: u32, j: u32) -> usize {
+        (j * self.width + i) as usize
+    }
+
+    /// Copy of the frame with the radiance replaced by the output of a reconstruction
+    pub fn reconstruct(&self, reconstruction: &dyn Reconstruction) -> FrameBuffer {
+        let mut frame = self.clone();
+        frame.color = reconstruction.reconstruct(self);
+        frame
+    }
+
+    /// Image of the frame with the pixels flagged in `invalid` painted magenta
+    pub fn nan_highlight(&self, pipeline: &ColorPipeline) -> Image {
+        let mut image = self.to_image(pipeline);
+        for j in 0..self.height {
+            for i in 0..self.width {
+                if self.invalid[self.index(i, j)] {
+                    image.set_pixel_rgb(i, j, 255, 0, 255);
+                }
+            }
+        }
+        image
+    }
+
+    /// Image of the luminance of the frame mapped through the false color ramp (see
+    /// `debug::false_color`), with black isolines between whole EV steps if asked for
+    pub fn false_color(&self, isolines: bool) -> Image {
+        let ev = |i: u32, j: u32| exposure_value(self.color[self.index(i, j)].luminance()).floor();
+        let mut image = Image::new(self.width, self.height);
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let color = false_color(self.color[self.index(i, j)].luminance());
+                let mut pixel = color.to_array().map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8);
+                // a line wherever the right or the lower neighbor is in another EV step
+                let edge = (i + 1 < self.width && ev(i + 1, j) != ev(i, j))
+                    || (j + 1 < self.height && ev(i, j + 1) != ev(i, j));
+                if isolines && edge {
+                    pixel = [0, 0, 0];
+                }
+                let [r, g, b] = pixel;
+                image.set_pixel_rgb(i, j, r, g, b);
+            }
+        }
+        image
+    }
+
+    /// Convert the radiance to an image with the given color pipeline
+    pub fn to_image(&self, pipeline: &ColorPipeline) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let [r, g, b] = pipeline.to_rgb8(self.color[self.index(i, j)]);
+                image.set_pixel_rgb(i, j, r, g, b);
+            }
+        }
+        image
+    }
+}
+
+/// Render the radiance and the AOVs of a scene
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings (image size, field of view, bounces and background)
+/// * `scene` - The scene
+///
+/// ### Returns
+///
+/// `FrameBuffer` - The rendered frame
+///
+pub fn render_framebuffer(settings: &RenderSettings, scene: &Scene) -> FrameBuffer {
+    let mut frame = render_features(settings, scene);
+    for j in 0..settings.height {
+        for i in 0..settings.width {
+            let ray = settings.camera_ray(i as f64 + 0.5, j as f64 + 0.5);
+            let index = frame.index(i, j);
+            // invalid radiance is flagged rather than asserted on, so it can be inspected with
+            // `nan_highlight`
+            let color = cast_ray(&ray, scene, settings, 0);
+            frame.invalid[index] = !is_valid_radiance(color);
+            if !frame.invalid[index] {
+                frame.color[index] = color;
+            }
+        }
+    }
+    frame
+}
+
+/// Render the AOVs of a scene only, the radiance being left black, to guide the
+/// reconstruction of a radiance rendered some other way (e.g. progressively)
+pub fn render_features(settings: &RenderSettings, scene: &Scene) -> FrameBuffer {
+    let mut frame = FrameBuffer::new(settings.width, settings.height);
+    for j in 0..settings.height {
+        for i in 0..settings.width {
+            let ray = settings.camera_ray(i as f64 + 0.5, j as f64 + 0.5);
+            let index = frame.index(i, j);
+            let (albedo, normal, depth) = surface_features(ray, scene, settings);
+            frame.albedo[index] = albedo;
+            frame.normal[index] = normal;
+            frame.depth[index] = depth;
+        }
+    }
+    frame
+}
+
+/// Albedo, normal and distance along the camera path of the first surface that is not
+/// mostly a mirror, following mirror reflections like `cast_ray` does
+fn surface_features(
+    mut ray: Ray,
+    scene: &Scene,
+    settings: &RenderSettings,
+) -> (Color, Vector3<f64>, f64) {
+    let mut depth = 0.0;
+    for _ in 0..=settings.max_depth {
+        let Some((index, distance)) = nearest_sphere(&ray, scene) else {
+            return (settings.background, Vector3::default(), f64::INFINITY);
+        };
+        let sphere = scene.sphere(index);
+        let point = ray.at(distance);
+        let n = (point - sphere.center).normalize();
+        depth += distance;
+        if sphere.material.albedo[2] <= sphere.material.albedo[0] {
+            let color = sphere
+                .material
+                .diffuse_color
+                .eval(sphere.texture_uv(point), point);
+            return (color, n, depth);
+        }
+        ray = Ray::offset(point, n, reflect(ray.direction, n));
+    }
+    (settings.background, Vector3::default(), f64::INFINITY)
+}
@@ -0,0 +1,128 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::integrator::IntegratorKind;
+use crate::integrator::PathTracer;
+use crate::json::Json;
+use crate::object::Material;
+use crate::object::Sphere;
+use crate::render::render_radiance;
+use crate::scene::Scene;
+use crate::settings::color_to_json;
+use crate::settings::RenderSettings;
+
+use nalgebra::Vector3;
+
+/// Largest relative deviation of the average radiance of the furnace render from the
+/// environment that still passes
+pub const FURNACE_TOLERANCE: f64 = 0.02;
+
+/// Radiance of the uniform environment of the furnace
+pub const FURNACE_ENVIRONMENT: Color = Color::new(0.5, 0.5, 0.5);
+
+/// Size of the furnace render in pixels
+const FURNACE_SIZE: u32 = 32;
+
+/// Outcome of `furnace_test`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FurnaceReport {
+    /// Radiance of the environment lighting the sphere
+    pub environment: Color,
+    /// Average radiance of the sphere
+    pub mean: Color,
+    /// Largest relative deviation of `mean` from `environment` over the color channels
+    pub deviation: f64,
+    pub tolerance: f64,
+}
+
+impl FurnaceReport {
+    /// Whether the sphere reflects the environment within the tolerance
+    pub fn passed(&self) -> bool {
+        self.deviation <= self.tolerance
+    }
+
+    /// Whether the sphere sends back more light than it gets
+    pub fn gains_energy(&self) -> bool {
+        self.mean
+            .to_array()
+            .iter()
+            .zip(self.environment.to_array())
+            .any(|(mean, environment)| *mean > environment * (1.0 + self.tolerance))
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("passed".to_string(), Json::Bool(self.passed())),
+            ("environment".to_string(), color_to_json(self.environment)),
+            ("mean".to_string(), color_to_json(self.mean)),
+            ("deviation".to_string(), Json::Number(self.deviation)),
+            ("tolerance".to_string(), Json::Number(self.tolerance)),
+        ])
+    }
+}
+
+/// Scene of the furnace test: a unit sphere of the material at the origin and no lights,
+/// with a single camera, `furnace`, close enough for the sphere to fill the image of
+/// `furnace_settings`
+pub fn furnace_scene(material: Material) -> Scene {
+    let camera = Camera::new(
+        "furnace",
+        Vector3::new(0.0, 0.0, 3.0),
+        Vector3::new(0.0, 0.0, 0.0),
+    );
+    Scene::new(
+        vec![Sphere::new(Vector3::default(), 1.0, material).with_name("sphere")],
+        Vec::new(),
+    )
+    .with_cameras(vec![camera])
+}
+
+/// Settings of the furnace test: the path tracer, the scene lit by the uniform
+/// `FURNACE_ENVIRONMENT` only
+pub fn furnace_settings(scene: &Scene) -> RenderSettings {
+    RenderSettings::new(FURNACE_SIZE, FURNACE_SIZE)
+        .with_fov(20.0_f64.to_radians())
+        .with_samples_per_pixel(16)
+        .with_max_depth(8)
+        .with_background(FURNACE_ENVIRONMENT)
+        .with_integrator(IntegratorKind::PathTracer(PathTracer))
+        .with_camera(scene.cameras[0].clone())
+}
+
+/// White furnace test of a material: render a sphere of the material lit by a uniform
+/// environment and compare its average radiance with the environment
+///
+/// A sphere can't see itself, so a material reflecting all the light it gets (white, with
+/// weights summing to one) looks exactly like the environment, whatever its shape of
+/// reflection; a brighter sphere means the material creates energy, a darker one that it
+/// absorbs some (expected for colored materials, a bug for white ones)
+///
+/// ### Arguments
+///
+/// * `material` - The material of the sphere
+/// * `tolerance` - The largest relative deviation from the environment that passes
+///
+/// ### Returns
+///
+/// `FurnaceReport` - The average radiance of the sphere and its deviation
+///
+pub fn furnace_test(material: Material, tolerance: f64) -> FurnaceReport {
+    let scene = furnace_scene(material);
+    let settings = furnace_settings(&scene);
+    let radiance = render_radiance(&settings, &scene);
+    let total = radiance
+        .iter()
+        .fold(Color::BLACK, |total, color| total + *color);
+    let mean = total / radiance.len().max(1) as f64;
+    let deviation = mean
+        .to_array()
+        .iter()
+        .zip(FURNACE_ENVIRONMENT.to_array())
+        .map(|(mean, environment)| (mean - environment).abs() / environment)
+        .fold(0.0, f64::max);
+    FurnaceReport {
+        environment: FURNACE_ENVIRONMENT,
+        mean,
+        deviation,
+        tolerance,
+    }
+}
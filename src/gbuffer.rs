@@ -0,0 +1,181 @@
+use crate::color::Color;
+use crate::render::PrimaryHits;
+use crate::scene::Scene;
+
+use nalgebra::Vector3;
+use std::io::Write;
+
+/// Surfaces seen by the camera, one per pixel, for relighting the frame outside of the
+/// renderer
+pub struct GBuffer {
+    pub width: u32,
+    pub height: u32,
+    /// World space position of the visible surface (zero where nothing is hit)
+    pub position: Vec<Vector3<f64>>,
+    /// Surface normal of the visible surface (zero where nothing is hit)
+    pub normal: Vec<Vector3<f64>>,
+    /// Diffuse color of the visible surface (black where nothing is hit)
+    pub albedo: Vec<Color>,
+    /// Index of the visible sphere in the scene
+    pub object_id: Vec<Option<usize>>,
+    /// Mean curvature of the visible surface, estimated from the positions and normals of
+    /// the neighboring pixels: positive on convex shapes (1 / radius on a sphere), negative
+    /// in the creases where objects meet, zero where nothing is hit
+    pub curvature: Vec<f64>,
+}
+
+impl GBuffer {
+    /// G-buffer of the first camera ray of every pixel from cached camera ray hits
+    ///
+    /// ### Arguments
+    ///
+    /// * `hits` - The camera ray hits, traced with one sample per pixel to get the surfaces
+    ///   at the pixel centers
+    /// * `scene` - The scene the hits were traced in
+    ///
+    /// ### Returns
+    ///
+    /// `GBuffer` - The buffers, row by row from the top left corner
+    ///
+    pub fn from_hits(hits: &PrimaryHits, scene: &Scene) -> GBuffer {
+        let (width, height) = hits.size();
+        let size = width as usize * height as usize;
+        let mut gbuffer = GBuffer {
+            width,
+            height,
+            position: vec![Vector3::default(); size],
+            normal: vec![Vector3::default(); size],
+            albedo: vec![Color::BLACK; size],
+            object_id: vec![None; size],
+            curvature: vec![0.0; size],
+        };
+        for j in 0..height {
+            for i in 0..width {
+                let (ray, hit) = hits.first_hit(i, j);
+                let Some((index, distance)) = hit else {
+                    continue;
+                };
+                let k = (j * width + i) as usize;
+                let sphere = scene.sphere(index);
+                let point = ray.at(distance);
+                gbuffer.position[k] = point + scene.origin;
+                gbuffer.normal[k] = (point - sphere.center).normalize();
+                gbuffer.albedo[k] = sphere
+                    .material
+                    .diffuse_color
+                    .eval(sphere.texture_uv(point), point);
+                gbuffer.object_id[k] = Some(index);
+            }
+        }
+        gbuffer.curvature = gbuffer.estimate_curvature();
+        gbuffer
+    }
+
+    /// Curvature at every pixel (see `curvature`): the change of the normal towards each of
+    /// the four neighbors hitting a surface, projected on the change of the position, over
+    /// the squared distance between the two points, averaged
+    ///
+    /// Across the neighbors the change of normal divided by the change of position is the
+    /// curvature of the surface between them, whether the two points are on the same
+    /// object or on two objects touching, which makes contacts show as cavities. Across a
+    /// silhouette the points are far apart and the estimate fades towards zero
+    fn estimate_curvature(&self) -> Vec<f64> {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let mut curvature = vec![0.0; self.position.len()];
+        for j in 0..height {
+            for i in 0..width {
+                let k = (j * width + i) as usize;
+                if self.object_id[k].is_none() {
+                    continue;
+                }
+                let (mut sum, mut count) = (0.0, 0);
+                for (di, dj) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    let (x, y) = (i + di, j + dj);
+                    if x < 0 || y < 0 || x >= width || y >= height {
+                        continue;
+                    }
+                    let other = (y * width + x) as usize;
+                    let step = self.position[other] - self.position[k];
+                    if self.object_id[other].is_none() || step.norm_squared() == 0.0 {
+                        continue;
+                    }
+                    let turn = self.normal[other] - self.normal[k];
+                    sum += turn.dot(&step) / step.norm_squared();
+                    count += 1;
+                }
+                if count > 0 {
+                    curvature[k] = sum / count as f64;
+                }
+            }
+        }
+        curvature
+    }
+
+    /// Save the buffers as PFM (portable float map) images `<prefix>_position.pfm`,
+    /// `<prefix>_normal.pfm`, `<prefix>_albedo.pfm`, `<prefix>_id.pfm` and
+    /// `<prefix>_curvature.pfm`
+    ///
+    /// The object id image has a single channel holding the sphere index, -1 where nothing
+    /// is hit, and so has the curvature image
+    pub fn save(&self, prefix: &str) -> std::io::Result<()> {
+        let vectors = |values: &[Vector3<f64>]| -> Vec<f64> {
+            values.iter().flat_map(|v| [v.x, v.y, v.z]).collect()
+        };
+        let colors: Vec<f64> = self.albedo.iter().flat_map(|c| c.to_array()).collect();
+        let ids: Vec<f64> = self
+            .object_id
+            .iter()
+            .map(|id| id.map_or(-1.0, |id| id as f64))
+            .collect();
+        let position = format!("{}_position.pfm", prefix);
+        write_pfm(
+            &position,
+            self.width,
+            self.height,
+            3,
+            &vectors(&self.position),
+        )?;
+        let normal = format!("{}_normal.pfm", prefix);
+        write_pfm(&normal, self.width, self.height, 3, &vectors(&self.normal))?;
+        let albedo = format!("{}_albedo.pfm", prefix);
+        write_pfm(&albedo, self.width, self.height, 3, &colors)?;
+        write_pfm(
+            &format!("{}_id.pfm", prefix),
+            self.width,
+            self.height,
+            1,
+            &ids,
+        )?;
+        write_pfm(
+            &format!("{}_curvature.pfm", prefix),
+            self.width,
+            self.height,
+            1,
+            &self.curvature,
+        )
+    }
+}
+
+/// Write a PFM image with 1 or 3 channels from values given row by row from the top
+///
+/// PFM stores little-endian 32-bit floats with the rows from the bottom of the image up
+pub fn write_pfm(
+    filename: &str,
+    width: u32,
+    height: u32,
+    channels: usize,
+    values: &[f64],
+) -> std::io::Result<()> {
+    let file = std::fs::File::create(filename)?;
+    let mut w = std::io::BufWriter::new(file);
+    let kind = if channels == 3 { "PF" } else { "Pf" };
+    // a negative scale marks the data as little-endian
+    write!(w, "{}\n{} {}\n-1.0\n", kind, width, height)?;
+    let row_size = width as usize * channels;
+    for row in values.chunks(row_size).rev() {
+        for value in row {
+            w.write_all(&(*value as f32).to_le_bytes())?;
+        }
+    }
+    w.flush()
+}
@@ -0,0 +1,84 @@
+use crate::color::Color;
+
+/// Largest finite half precision value
+pub const HALF_MAX: f64 = 65504.0;
+
+/// Half precision (IEEE 754 binary16) bits of a value, rounded to the nearest
+///
+/// Values beyond `HALF_MAX` become infinite, values too small for a normal half are kept as
+/// subnormals down to about 6e-8, NaN stays NaN
+pub fn to_half(value: f64) -> u16 {
+    // rounded from the double itself, going through a float would round twice
+    let bits = value.to_bits();
+    let sign = ((bits >> 48) & 0x8000) as u16;
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    let mantissa = bits & 0xf_ffff_ffff_ffff;
+    if exponent == 0x7ff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    // exponent of the value for the half bias of 15
+    let half_exponent = exponent - 1023 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        // subnormal: the implicit leading bit is shifted into the mantissa
+        let mantissa = mantissa | 1 << 52;
+        let shift = (43 - half_exponent) as u32;
+        return sign | round_shifted(mantissa, shift) as u16;
+    }
+    // a mantissa rounding up to the next power of two carries into the exponent, which is
+    // the right result, up to infinity
+    sign | (((half_exponent as u64) << 10) + round_shifted(mantissa, 42)) as u16
+}
+
+/// Value `bits >> shift` rounded to the nearest, ties to even
+fn round_shifted(bits: u64, shift: u32) -> u64 {
+    let kept = bits >> shift;
+    let rest = bits & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    if rest > half || (rest == half && kept & 1 == 1) {
+        kept + 1
+    } else {
+        kept
+    }
+}
+
+/// Value of half precision bits, exactly
+pub fn from_half(half: u16) -> f64 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f64;
+    sign * match exponent {
+        0 => mantissa * 2.0_f64.powi(-24),
+        0x1f if mantissa == 0.0 => f64::INFINITY,
+        0x1f => f64::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2.0_f64.powi(exponent - 15),
+    }
+}
+
+/// Color stored in half precision, a quarter of the memory of a `Color`
+///
+/// Half precision keeps about three significant digits, enough for textures and images
+/// but not for sums of many samples
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HalfColor {
+    pub bits: [u16; 3],
+}
+
+impl HalfColor {
+    pub fn new(color: Color) -> HalfColor {
+        HalfColor {
+            bits: color.to_array().map(to_half),
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        let [r, g, b] = self.bits.map(from_half);
+        Color::new(r, g, b)
+    }
+}
@@ -0,0 +1,517 @@
+use crate::json::Json;
+use crate::json::JsonError;
+
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// Translations of the messages of the program into one language, keyed by their English
+/// text like a gettext catalog, so a message without a translation stays in English
+///
+/// The messages are the contexts of the errors of the command line, the problems found by
+/// `validate::validate` and the descriptions of the commands; a GUI built on the crate can
+/// show the same strings by looking them up in the catalog (or list them with `to_json`).
+/// Templates hold `{}` where their arguments go (see `format`)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Catalog {
+    /// English messages and their translations
+    pub entries: Vec<(String, String)>,
+}
+
+impl Catalog {
+    /// Catalog leaving the messages in English
+    pub fn english() -> Catalog {
+        Catalog::default()
+    }
+
+    pub fn russian() -> Catalog {
+        Catalog {
+            entries: RUSSIAN
+                .iter()
+                .map(|(english, russian)| (english.to_string(), russian.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Built-in catalog of a language, from its code or a locale name such as the value
+    /// of `LANG`: `en` (or `C` and `POSIX`) or `ru`, e.g. `ru_RU.UTF-8`
+    pub fn builtin(language: &str) -> Option<Catalog> {
+        let code = language
+            .split(['_', '.', '-', '@'])
+            .next()
+            .unwrap_or_default();
+        match code.to_lowercase().as_str() {
+            "en" | "c" | "posix" => Some(Catalog::english()),
+            "ru" => Some(Catalog::russian()),
+            _ => None,
+        }
+    }
+
+    /// Catalog of another language from a JSON object mapping the English messages to
+    /// their translations, in the format of `to_json`
+    pub fn from_json(json: &Json) -> Result<Catalog, JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("a message catalog must be a JSON object"));
+        };
+        let entries = entries
+            .iter()
+            .map(|(english, translation)| match translation.as_str() {
+                Some(translation) => Ok((english.clone(), translation.to_string())),
+                None => Err(JsonError::new(&format!(
+                    "the translation of '{}' must be a string",
+                    english
+                ))),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Catalog { entries })
+    }
+
+    /// Read a catalog from a JSON file (see `from_json`)
+    pub fn load(path: &str) -> Result<Catalog, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(Catalog::from_json(&Json::parse(&text)?)?)
+    }
+
+    /// Catalog as a JSON object mapping the English messages to their translations, the
+    /// starting point of the catalog of another language
+    pub fn to_json(&self) -> Json {
+        Json::Object(
+            self.entries
+                .iter()
+                .map(|(english, translation)| (english.clone(), Json::String(translation.clone())))
+                .collect(),
+        )
+    }
+
+    /// Translation of a message, the message itself without one
+    pub fn translate<'a>(&'a self, message: &'a str) -> &'a str {
+        self.entries
+            .iter()
+            .find(|(english, _)| english == message)
+            .map_or(message, |(_, translation)| translation.as_str())
+    }
+
+    /// Translation of a template with its `{}` replaced by the arguments in order
+    ///
+    /// ### Arguments
+    ///
+    /// * `template` - The English template, e.g. `"{} casts no shadow rays"`
+    /// * `args` - The values replacing the `{}` of the translation
+    ///
+    /// ### Returns
+    ///
+    /// `String` - The message, with the `{}` left without an argument kept as they are
+    ///
+    pub fn format(&self, template: &str, args: &[&dyn Display]) -> String {
+        let mut parts = self.translate(template).split("{}");
+        let mut message = parts.next().unwrap_or_default().to_string();
+        let mut args = args.iter();
+        for part in parts {
+            match args.next() {
+                Some(arg) => message += &arg.to_string(),
+                None => message += "{}",
+            }
+            message += part;
+        }
+        message
+    }
+}
+
+/// Catalog of the program, set by `install`
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Catalog of the program until one is installed
+static ENGLISH: Catalog = Catalog {
+    entries: Vec::new(),
+};
+
+/// Use a catalog for the messages of the program from now on, once; returns `false` if a
+/// catalog was installed already
+pub fn install(catalog: Catalog) -> bool {
+    CATALOG.set(catalog).is_ok()
+}
+
+/// Catalog of the program, English until one is installed (see `install`)
+pub fn catalog() -> &'static Catalog {
+    CATALOG.get().unwrap_or(&ENGLISH)
+}
+
+/// Message in the language of the program
+pub fn tr(message: &str) -> String {
+    catalog().translate(message).to_string()
+}
+
+/// Template in the language of the program with its arguments (see `Catalog::format`)
+pub fn tr_format(template: &str, args: &[&dyn Display]) -> String {
+    catalog().format(template, args)
+}
+
+/// Language asked for by the environment: `RAY_TRACING_LANG`, or the locale of the messages
+/// (`LC_ALL`, `LC_MESSAGES` or `LANG`, the first one set)
+pub fn language_from_env() -> Option<String> {
+    ["RAY_TRACING_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// Russian translations of the messages
+const RUSSIAN: &[(&str, &str)] = &[
+    // errors
+    ("error", "ошибка"),
+    ("usage: {}", "использование: {}"),
+    ("invalid scene: {}", "некорректная сцена: {}"),
+    ("invalid default scene", "некорректная встроенная сцена"),
+    ("invalid glass scene", "некорректная сцена со стеклом"),
+    ("invalid output name", "некорректное имя файла вывода"),
+    (
+        "failed to apply an override",
+        "не удалось применить параметр",
+    ),
+    (
+        "failed to create the output directory",
+        "не удалось создать каталог вывода",
+    ),
+    (
+        "failed to find the executable",
+        "не удалось найти исполняемый файл",
+    ),
+    ("failed to load the LUT", "не удалось загрузить LUT"),
+    (
+        "failed to load the config file",
+        "не удалось загрузить файл конфигурации",
+    ),
+    (
+        "failed to load the density grid",
+        "не удалось загрузить сетку плотности",
+    ),
+    (
+        "failed to load the environment map",
+        "не удалось загрузить карту окружения",
+    ),
+    (
+        "failed to load the image",
+        "не удалось загрузить изображение",
+    ),
+    (
+        "failed to load the material",
+        "не удалось загрузить материал",
+    ),
+    (
+        "failed to load the message catalog",
+        "не удалось загрузить каталог сообщений",
+    ),
+    (
+        "failed to load the previous scene",
+        "не удалось загрузить предыдущую сцену",
+    ),
+    (
+        "failed to load the render settings",
+        "не удалось загрузить параметры рендера",
+    ),
+    (
+        "failed to load the response curve",
+        "не удалось загрузить кривую отклика",
+    ),
+    ("failed to load the scene", "не удалось загрузить сцену"),
+    ("failed to pack the scene", "не удалось упаковать сцену"),
+    (
+        "failed to parse the sweep",
+        "не удалось разобрать перебор параметров",
+    ),
+    (
+        "failed to read the batch file",
+        "не удалось прочитать файл команд",
+    ),
+    (
+        "invalid line {} of the batch file",
+        "некорректная строка {} файла команд",
+    ),
+    (
+        "failed to render the sweep",
+        "не удалось отрендерить перебор параметров",
+    ),
+    (
+        "failed to resize the image",
+        "не удалось изменить размер изображения",
+    ),
+    ("failed to start the server", "не удалось запустить сервер"),
+    (
+        "failed to supersample the image",
+        "не удалось отрендерить изображение с суперсэмплингом",
+    ),
+    (
+        "failed to write frames.json",
+        "не удалось записать frames.json",
+    ),
+    (
+        "failed to write probes.json",
+        "не удалось записать probes.json",
+    ),
+    ("failed to write bvh.obj", "не удалось записать bvh.obj"),
+    (
+        "failed to write the G-buffer",
+        "не удалось записать G-буфер",
+    ),
+    ("failed to write the bake", "не удалось записать запекание"),
+    ("failed to write the depth", "не удалось записать глубину"),
+    (
+        "failed to write the deep image",
+        "не удалось записать глубокое изображение",
+    ),
+    (
+        "failed to write the image",
+        "не удалось записать изображение",
+    ),
+    (
+        "failed to write the light pass",
+        "не удалось записать проход источника",
+    ),
+    (
+        "failed to write the light path pass",
+        "не удалось записать проход путей света",
+    ),
+    ("failed to write the motion", "не удалось записать движение"),
+    ("failed to write the pass", "не удалось записать проход"),
+    (
+        "failed to write the scene pack",
+        "не удалось записать архив сцены",
+    ),
+    ("failed to write {}", "не удалось записать {}"),
+    (
+        "invalid light path expression {}",
+        "неверное выражение световых путей {}",
+    ),
+    ("left out", "не сохранено"),
+    (
+        "unknown command '{}', `help` lists the commands",
+        "неизвестная команда '{}', список команд выводит `help`",
+    ),
+    ("unknown camera '{}'", "неизвестная камера '{}'"),
+    ("unknown demo scene '{}'", "неизвестная демо-сцена '{}'"),
+    (
+        "sphere index {} is out of range, the scene has {} spheres",
+        "номер сферы {} вне диапазона, в сцене сфер: {}",
+    ),
+    (
+        "no light named '{}' in the scene",
+        "в сцене нет источника с именем '{}'",
+    ),
+    (
+        "the near end of an inverse depth range must be positive",
+        "ближняя граница обратного диапазона глубины должна быть положительной",
+    ),
+    // problems found by the validation
+    (
+        "the settings use no samples per pixel",
+        "в параметрах ноль сэмплов на пиксель",
+    ),
+    (
+        "the field of view of {} degrees is out of range",
+        "угол обзора {} градусов вне допустимого диапазона",
+    ),
+    (
+        "texture '{}' failed to load: {}",
+        "текстура '{}' не загрузилась: {}",
+    ),
+    (
+        "environment map '{}' failed to load: {}",
+        "карта окружения '{}' не загрузилась: {}",
+    ),
+    ("object", "объект"),
+    ("light", "источник"),
+    ("{} has a radius of {}", "{}: радиус {}"),
+    (
+        "{} has a position that is not finite",
+        "{}: координаты не конечны",
+    ),
+    (
+        "{} has a negative intensity",
+        "{}: отрицательная интенсивность",
+    ),
+    ("{} casts no shadow rays", "{}: ноль теневых лучей"),
+    (
+        "{} has a maximum distance that isn't positive",
+        "{}: максимальное расстояние не положительно",
+    ),
+    (
+        "camera '{}' looks at its own position",
+        "камера '{}' направлена на собственное положение",
+    ),
+    (
+        "object name '{}' is used more than once",
+        "имя объекта '{}' используется несколько раз",
+    ),
+    (
+        "light name '{}' is used more than once",
+        "имя источника '{}' используется несколько раз",
+    ),
+    (
+        "camera name '{}' is used more than once",
+        "имя камеры '{}' используется несколько раз",
+    ),
+    // commands
+    (
+        "render the beauty image to out.png",
+        "рендер изображения в out.png",
+    ),
+    (
+        "render a quick draft to out_preview.png, or a material: preview material <file>",
+        "быстрый черновик в out_preview.png или материал: preview material <файл>",
+    ),
+    (
+        "check the scene and the settings without rendering",
+        "проверка сцены и параметров без рендера",
+    ),
+    (
+        "describe the scene and the settings",
+        "описание сцены и параметров",
+    ),
+    (
+        "check the installation with a built-in render and time a tile",
+        "проверка установки встроенным рендером и замер времени плитки",
+    ),
+    (
+        "check that a material conserves energy: furnace [<material.json>]",
+        "проверка сохранения энергии материалом: furnace [<material.json>]",
+    ),
+    (
+        "write a pass or an AOV for compositing: post <command> ...",
+        "проход или AOV для композитинга: post <команда> ...",
+    ),
+    (
+        "run the commands listed in a file, one invocation per line",
+        "выполнение команд из файла, по одному запуску на строку",
+    ),
+    (
+        "render over HTTP: GET /render?<parameter>=<value>&...",
+        "рендер по HTTP: GET /render?<параметр>=<значение>&...",
+    ),
+    ("list the commands", "список команд"),
+    (
+        "print a shell completion script: completions bash | zsh | fish",
+        "скрипт автодополнения для оболочки: completions bash | zsh | fish",
+    ),
+    (
+        "bake the lighting of a sphere into its UV layout",
+        "запекание освещения сферы в ее UV-развертку",
+    ),
+    (
+        "bake irradiance probes as spherical harmonics: probes <x,y,z> ...",
+        "запекание зондов освещенности в сферические гармоники: probes <x,y,z> ...",
+    ),
+    (
+        "write the object and material ID mattes",
+        "маски объектов и материалов",
+    ),
+    (
+        "render the layers of the scene separately",
+        "отдельный рендер слоёв сцены",
+    ),
+    (
+        "the scene defines no render layers",
+        "в сцене нет слоёв рендера",
+    ),
+    ("invalid render layer", "некорректный слой рендера"),
+    (
+        "write the position, normal, albedo, object id and curvature AOVs",
+        "AOV положения, нормали, альбедо, номера объекта и кривизны",
+    ),
+    (
+        "write the contribution of every light or light group",
+        "вклад каждого источника или группы источников",
+    ),
+    (
+        "write the diffuse, specular, reflection, transmission and emission passes",
+        "проходы диффузного света, бликов, отражений, преломлений и излучения",
+    ),
+    (
+        "write the light of the paths matching expressions: lpe <name>=<expression> ...",
+        "свет путей, подходящих под выражения: lpe <имя>=<выражение> ...",
+    ),
+    (
+        "write the screen space motion since a previous frame",
+        "движение в экранных координатах с предыдущего кадра",
+    ),
+    (
+        "write the depth of the surfaces along the view axis",
+        "глубина поверхностей вдоль оси взгляда",
+    ),
+    ("write a deep image", "deep-изображение"),
+    ("render the animation of the scene", "анимация сцены"),
+    (
+        "invalid light animation",
+        "некорректная анимация источника света",
+    ),
+    (
+        "render a spot light shining through fog",
+        "прожектор, светящий сквозь туман",
+    ),
+    (
+        "render with few shadow rays and reconstruct the image",
+        "рендер с малым числом теневых лучей и восстановление изображения",
+    ),
+    (
+        "write the luminance through a false color ramp",
+        "яркость в ложных цветах",
+    ),
+    (
+        "draw a UV checkerboard over every sphere",
+        "шахматная доска UV-развертки на каждой сфере",
+    ),
+    (
+        "write the boxes of the bounding volume hierarchy to bvh.obj: bvh [max depth]",
+        "боксы иерархии ограничивающих объемов в bvh.obj: bvh [наибольшая глубина]",
+    ),
+    (
+        "add nested glass, water and air balls",
+        "вложенные шары из стекла, воды и воздуха",
+    ),
+    (
+        "render the two halves of the image with different settings",
+        "две половины изображения с разными параметрами",
+    ),
+    (
+        "render two scene files and write their difference: diff <before> <after>",
+        "разница двух файлов сцены: diff <до> <после>",
+    ),
+    (
+        "render a contact sheet over the values of one or two parameters",
+        "таблица кадров по значениям одного или двух параметров",
+    ),
+    (
+        "render a material on the shader ball",
+        "материал на шаре для просмотра материалов",
+    ),
+    (
+        "convert an environment map between layouts",
+        "преобразование раскладки карты окружения",
+    ),
+    (
+        "resize an image with a box, bilinear or Lanczos filter",
+        "изменение размера изображения фильтром box, билинейным или Ланцоша",
+    ),
+    (
+        "render the scene from every camera",
+        "рендер сцены со всех камер",
+    ),
+    (
+        "grade an image with a 1D or 3D .cube LUT: lut <input.png> <file.cube> <output>",
+        "цветокоррекция изображения 1D или 3D LUT .cube: lut <input.png> <file.cube> <output>",
+    ),
+    (
+        "bundle a scene and its files into one archive: pack <scene.json> <scene.rtpack>",
+        "упаковка сцены и ее файлов в один архив: pack <scene.json> <scene.rtpack>",
+    ),
+    (
+        "render a 256 pixel draft of a scene file: thumbnail <scene.json> <output.png>",
+        "черновик файла сцены в 256 пикселей: thumbnail <scene.json> <output.png>",
+    ),
+    (
+        "write the Rust code building a scene file: codegen <scene.json> [<output.rs>]",
+        "код на Rust, строящий сцену из файла: codegen <scene.json> [<output.rs>]",
+    ),
+    (
+        "write the scene as a scene file: save-scene <output.json>",
+        "сохранение сцены в файл сцены: save-scene <output.json>",
+    ),
+];
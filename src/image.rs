@@ -1,5 +1,106 @@
+use crate::interlace::write_interlaced_png;
+use crate::label::draw_label;
+use crate::label::label_size;
+use crate::proxy::proxy_size;
+use crate::proxy::save_proxy;
+use crate::proxy::ProxyBuilder;
+use crate::resample::resize;
+use crate::resample::ResizeFilter;
+
+use std::io::Write;
 use std::path::Path;
 
+/// Largest width or height of an image in pixels
+pub const MAX_IMAGE_DIMENSION: u32 = 1 << 16;
+
+/// Error raised for image sizes that can't be held in memory and for pixels outside of an
+/// image
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageError {
+    pub message: String,
+}
+
+impl ImageError {
+    pub fn new(message: &str) -> ImageError {
+        ImageError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// Check that an image of the given size can be rendered and held in memory: neither side
+/// is zero or larger than `MAX_IMAGE_DIMENSION`
+///
+/// ### Arguments
+///
+/// * `width`, `height` - The size of the image in pixels
+///
+/// ### Returns
+///
+/// `Result<usize, ImageError>` - The size of its RGB pixels in bytes
+///
+pub fn check_image_size(width: u32, height: u32) -> Result<usize, ImageError> {
+    if width == 0 || height == 0 {
+        let message = format!("the image size {}x{} is empty", width, height);
+        return Err(ImageError::new(&message));
+    }
+    if width > MAX_IMAGE_DIMENSION || height > MAX_IMAGE_DIMENSION {
+        return Err(ImageError::new(&format!(
+            "the image size {}x{} is larger than {} pixels on a side",
+            width, height, MAX_IMAGE_DIMENSION
+        )));
+    }
+    rgb_size(width, height)
+}
+
+/// Size of the RGB pixels of an image in bytes, computed without overflowing
+fn rgb_size(width: u32, height: u32) -> Result<usize, ImageError> {
+    (width as u64)
+        .checked_mul(height as u64)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .and_then(|bytes| usize::try_from(bytes).ok())
+        .ok_or_else(|| {
+            ImageError::new(&format!("the image size {}x{} is too large", width, height))
+        })
+}
+
+/// Rectangle of pixels of an image, from its top left corner
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Index of a pixel of the image among the pixels of the rectangle row by row, `None`
+    /// if the rectangle doesn't cover it
+    pub fn index(&self, i: u32, j: u32) -> Option<usize> {
+        let (x, y) = (i.checked_sub(self.x)?, j.checked_sub(self.y)?);
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(y as usize * self.width as usize + x as usize)
+    }
+}
 
 pub struct Image {
     pub width: u32,
@@ -8,32 +109,607 @@ pub struct Image {
 }
 
 impl Image {
+    /// Black image, empty when a side is zero
+    ///
+    /// Panics if the size doesn't fit in memory, `try_new` returns an error instead
     pub fn new(width: u32, height: u32) -> Image {
+        let size = rgb_size(width, height).unwrap_or_else(|error| panic!("{}", error));
         Image {
             width,
             height,
-            data: vec![0; (width * height * 3) as usize],
+            data: vec![0; size],
+        }
+    }
+
+    /// Black image of a size checked with `check_image_size`
+    pub fn try_new(width: u32, height: u32) -> Result<Image, ImageError> {
+        let size = check_image_size(width, height)?;
+        Ok(Image {
+            width,
+            height,
+            data: vec![0; size],
+        })
+    }
+
+    /// Copy of the image resized with a filter, the pixels being filtered as linear values
+    /// (see `resample::resize`)
+    pub fn resize(&self, width: u32, height: u32, filter: ResizeFilter) -> Image {
+        resize(self, width, height, filter, true)
+    }
+
+    /// Offset of the first byte of a pixel in `data`, `None` outside of the image
+    pub fn offset(&self, x: u32, y: u32) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some((y as usize * self.width as usize + x as usize) * 3)
+    }
+
+    /// Color of a pixel, `None` outside of the image
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 3]> {
+        let offset = self.offset(x, y)?;
+        Some([
+            self.data[offset],
+            self.data[offset + 1],
+            self.data[offset + 2],
+        ])
+    }
+
+    /// Set the color of a pixel, panics outside of the image
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 3]) {
+        let [r, g, b] = color;
+        self.set_pixel_rgb(x, y, r, g, b);
+    }
+
+    /// Set the color of a pixel from its channels, without allocating, panics outside of
+    /// the image
+    pub fn set_pixel_rgb(&mut self, x: u32, y: u32, r: u8, g: u8, b: u8) {
+        let offset = self
+            .offset(x, y)
+            .unwrap_or_else(|| panic!("pixel ({}, {}) outside of the image", x, y));
+        self.data[offset..offset + 3].copy_from_slice(&[r, g, b]);
+    }
+
+    /// Copy a row of RGB pixels into the image, panics if the row is outside of the image or
+    /// doesn't hold `width` pixels
+    pub fn write_row(&mut self, y: u32, row: &[u8]) {
+        self.blit_tile(Rect::new(0, y, self.width, 1), row);
+    }
+
+    /// Copy a tile of RGB pixels, stored row after row, into a rectangle of the image
+    ///
+    /// Panics if the rectangle isn't inside the image or the tile doesn't hold as many
+    /// pixels as the rectangle
+    ///
+    /// ### Arguments
+    ///
+    /// * `rect` - The rectangle of the image covered by the tile
+    /// * `pixels` - The RGB pixels of the tile
+    ///
+    pub fn blit_tile(&mut self, rect: Rect, pixels: &[u8]) {
+        let inside = rect.x as u64 + rect.width as u64 <= self.width as u64
+            && rect.y as u64 + rect.height as u64 <= self.height as u64;
+        if !inside {
+            panic!(
+                "tile {}x{} at ({}, {}) outside of the {}x{} image",
+                rect.width, rect.height, rect.x, rect.y, self.width, self.height
+            );
+        }
+        let row_size = rect.width as usize * 3;
+        if pixels.len() != row_size * rect.height as usize {
+            panic!(
+                "tile of {} bytes for a {}x{} rectangle",
+                pixels.len(),
+                rect.width,
+                rect.height
+            );
+        }
+        if row_size == 0 {
+            return;
+        }
+        for (j, row) in pixels.chunks_exact(row_size).enumerate() {
+            let target = ((rect.y as usize + j) * self.width as usize + rect.x as usize) * 3;
+            self.data[target..target + row_size].copy_from_slice(row);
+        }
+    }
+
+    /// Set the color of a pixel, an error outside of the image
+    pub fn try_set_pixel(&mut self, x: u32, y: u32, color: [u8; 3]) -> Result<(), ImageError> {
+        let offset = self.offset(x, y).ok_or_else(|| {
+            ImageError::new(&format!(
+                "pixel ({}, {}) outside of the {}x{} image",
+                x, y, self.width, self.height
+            ))
+        })?;
+        self.data[offset..offset + 3].copy_from_slice(&color);
+        Ok(())
+    }
+
+    /// Save the image in the format of the extension of the file name: `.tga` (run-length
+    /// encoded) or `.bmp` for pipelines that only read these, PNG for any other
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        let path = Path::new(filename);
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("tga") => self.save_with(filename, Image::write_tga)?,
+            Some("bmp") => self.save_with(filename, Image::write_bmp)?,
+            _ => self.save_png(filename)?,
+        }
+        save_proxy(self, filename)
+    }
+
+    /// Save the image as an RGB PNG whatever the extension, without a proxy
+    pub fn save_png(&self, filename: &str) -> std::io::Result<()> {
+        let path = Path::new(filename);
+        let file = std::fs::File::create(path)?;
+        let w = &mut std::io::BufWriter::new(file);
+
+        let mut encoder = png::Encoder::new(w, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Write the image to a file with one of the `write_*` encoders
+    fn save_with(
+        &self,
+        filename: &str,
+        write: fn(&Image, &mut dyn Write) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let file = std::fs::File::create(Path::new(filename))?;
+        let mut w = std::io::BufWriter::new(file);
+        write(self, &mut w)?;
+        w.flush()
+    }
+
+    /// Encode the image as a run-length encoded true color TGA, its rows from the top down
+    ///
+    /// Runs of identical pixels are stored once, the other pixels as they are, packets
+    /// never crossing the end of a row
+    pub fn write_tga(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        let (Ok(width), Ok(height)) = (u16::try_from(self.width), u16::try_from(self.height))
+        else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "TGA images are at most 65535 pixels on a side",
+            ));
+        };
+        let mut header = [0; 18];
+        // run-length encoded true color, 24 bits per pixel, origin at the top left
+        header[2] = 10;
+        header[12..14].copy_from_slice(&width.to_le_bytes());
+        header[14..16].copy_from_slice(&height.to_le_bytes());
+        header[16] = 24;
+        header[17] = 0x20;
+        w.write_all(&header)?;
+
+        let bgr = |pixel: &[u8]| [pixel[2], pixel[1], pixel[0]];
+        let mut packets = Vec::new();
+        for row in self.data.chunks_exact((self.width as usize * 3).max(1)) {
+            let pixels: Vec<&[u8]> = row.chunks_exact(3).collect();
+            let mut i = 0;
+            while i < pixels.len() {
+                let mut run = 1;
+                while i + run < pixels.len() && run < 128 && pixels[i + run] == pixels[i] {
+                    run += 1;
+                }
+                if run > 1 {
+                    packets.push(0x80 | (run - 1) as u8);
+                    packets.extend_from_slice(&bgr(pixels[i]));
+                    i += run;
+                    continue;
+                }
+                // raw pixels up to the start of the next run
+                let start = i;
+                while i < pixels.len()
+                    && i - start < 128
+                    && !(i + 1 < pixels.len() && pixels[i + 1] == pixels[i])
+                {
+                    i += 1;
+                }
+                packets.push((i - start - 1) as u8);
+                for pixel in &pixels[start..i] {
+                    packets.extend_from_slice(&bgr(pixel));
+                }
+            }
+            w.write_all(&packets)?;
+            packets.clear();
         }
+        // TGA 2.0 footer without extension or developer areas
+        w.write_all(&[0; 8])?;
+        w.write_all(b"TRUEVISION-XFILE.\0")
+    }
+
+    /// Encode the image as an uncompressed 24-bit BMP, its rows from the bottom up and
+    /// padded to 4 bytes
+    pub fn write_bmp(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        const HEADER_SIZE: u32 = 14 + 40;
+        let row_size = (self.width as u64 * 3).next_multiple_of(4);
+        let Some(file_size) = (row_size * self.height as u64)
+            .checked_add(HEADER_SIZE as u64)
+            .and_then(|size| u32::try_from(size).ok())
+        else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "BMP files are at most 4 GiB",
+            ));
+        };
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(b"BM");
+        header.extend_from_slice(&file_size.to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+        header.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+        header.extend_from_slice(&40u32.to_le_bytes());
+        header.extend_from_slice(&(self.width as i32).to_le_bytes());
+        header.extend_from_slice(&(self.height as i32).to_le_bytes());
+        // one plane, 24 bits per pixel, no compression
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&24u16.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&(file_size - HEADER_SIZE).to_le_bytes());
+        // 72 DPI, no palette
+        header.extend_from_slice(&2835u32.to_le_bytes());
+        header.extend_from_slice(&2835u32.to_le_bytes());
+        header.extend_from_slice(&[0; 8]);
+        w.write_all(&header)?;
+
+        let mut line = vec![0; row_size as usize];
+        for row in self
+            .data
+            .chunks_exact((self.width as usize * 3).max(1))
+            .rev()
+        {
+            for (target, pixel) in line.chunks_exact_mut(3).zip(row.chunks_exact(3)) {
+                target.copy_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+            }
+            w.write_all(&line)?;
+        }
+        Ok(())
+    }
+
+    /// Encode the image as an RGB PNG in memory, e.g. to send it over the network
+    pub fn encode_png(&self) -> std::io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)?;
+        writer.finish()?;
+        Ok(bytes)
     }
 
-    pub fn set_pixel(&mut self, x: u32, y: u32, color: Vec<u8>) {
-        let offset = (y * self.width * 3 + x * 3) as usize;
-        self.data[offset] = color[0];
-        self.data[offset + 1] = color[1];
-        self.data[offset + 2] = color[2];
+    /// Encode the image as an interlaced RGB PNG in memory, which viewers can show at a
+    /// coarse resolution before all of it is received (see `interlace::write_interlaced_png`)
+    pub fn encode_png_interlaced(&self) -> std::io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        write_interlaced_png(&mut bytes, self.width, self.height, &self.data)?;
+        Ok(bytes)
     }
 
-    pub fn save(&self, filename: &str) {
+    /// Save the image as an RGBA PNG with the given alpha, in the range `[0, 1]` for every
+    /// pixel row by row
+    ///
+    /// The pixels are taken as premultiplied by the alpha (a partly transparent pixel holds
+    /// the color of its opaque part only) and divided by it, PNG storing straight alpha
+    pub fn save_with_alpha(&self, filename: &str, alpha: &[f64]) -> std::io::Result<()> {
         let path = Path::new(filename);
-        let file = std::fs::File::create(&path).unwrap();
-        let ref mut w = std::io::BufWriter::new(file);
+        let file = std::fs::File::create(path)?;
+        let w = &mut std::io::BufWriter::new(file);
 
         let mut encoder = png::Encoder::new(w, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut data = Vec::with_capacity(self.data.len() / 3 * 4);
+        for (pixel, a) in self.data.chunks(3).zip(alpha) {
+            for c in pixel {
+                let straight = if *a > 0.0 { *c as f64 / a } else { 0.0 };
+                data.push(straight.round().min(255.0) as u8);
+            }
+            data.push((a.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+        save_proxy(self, filename)
+    }
+}
+
+/// PNG file written row by row, so images don't need to be held in memory as a whole
+///
+/// The PNG goes to a file by default, or to any writer such as a network connection
+pub struct ImageStream<W: Write + 'static = std::io::BufWriter<std::fs::File>> {
+    writer: png::StreamWriter<'static, W>,
+    width: u32,
+    /// Number of rows written so far
+    rows: u32,
+    /// Proxy of the image, when proxies are enabled (see `proxy::set_proxy_size`)
+    proxy: Option<ProxyBuilder>,
+}
+
+impl ImageStream {
+    pub fn create(filename: &str, width: u32, height: u32) -> std::io::Result<ImageStream> {
+        let file = std::fs::File::create(Path::new(filename))?;
+        let mut stream = ImageStream::new(std::io::BufWriter::new(file), width, height)?;
+        stream.proxy = proxy_size().map(|size| ProxyBuilder::new(filename, width, height, size));
+        Ok(stream)
+    }
+}
+
+impl<W: Write + 'static> ImageStream<W> {
+    /// Start an RGB PNG of the given size on a writer, the header being written at once
+    pub fn new(w: W, width: u32, height: u32) -> std::io::Result<ImageStream<W>> {
+        let mut encoder = png::Encoder::new(w, width, height);
         encoder.set_color(png::ColorType::Rgb);
         encoder.set_depth(png::BitDepth::Eight);
 
-        let mut writer = encoder.write_header().unwrap();
-        writer.write_image_data(&self.data).unwrap();
-        writer.finish().unwrap();
+        let writer = encoder.write_header()?.into_stream_writer()?;
+        Ok(ImageStream {
+            writer,
+            width,
+            rows: 0,
+            proxy: None,
+        })
+    }
+
+    /// Append complete rows of RGB pixels, from the top of the image down
+    pub fn write_rows(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if let Some(proxy) = &mut self.proxy {
+            proxy.add_rows(self.rows, data);
+        }
+        self.rows += (data.len() / (self.width as usize * 3).max(1)) as u32;
+        self.writer.write_all(data)
+    }
+
+    /// Flush the file, all the rows of the image must have been written, and save its proxy
+    pub fn finish(self) -> std::io::Result<()> {
+        self.writer.finish()?;
+        match self.proxy {
+            Some(proxy) => proxy.finish(),
+            None => Ok(()),
+        }
     }
-}
\ No newline at end of file
+}
+
+/// Tile images into one, left to right and top to bottom, with a caption under each
+///
+/// Every tile is as large as the largest image, smaller images are centered in their tile
+///
+/// ### Arguments
+///
+/// * `images` - The images to tile
+/// * `cols` - The number of tiles per row
+/// * `labels` - The captions, in the order of the images (images past the end of the list
+///   have no caption)
+///
+/// ### Returns
+///
+/// `Image` - The contact sheet
+///
+pub fn contact_sheet(images: &[Image], cols: usize, labels: &[&str]) -> Image {
+    const GAP: u32 = 2;
+    let cols = cols.clamp(1, images.len().max(1));
+    let rows = images.len().div_ceil(cols);
+    let width = images.iter().map(|image| image.width).max().unwrap_or(0);
+    let height = images.iter().map(|image| image.height).max().unwrap_or(0);
+    let scale = (height / 256).max(1);
+    let (_, caption_height) = label_size("", scale);
+    let caption_height = if labels.is_empty() { 0 } else { caption_height };
+    let tile_width = width + GAP;
+    let tile_height = height + caption_height + GAP;
+    let mut sheet = Image::new(
+        (cols as u32 * tile_width).saturating_sub(GAP),
+        (rows as u32 * tile_height).saturating_sub(GAP),
+    );
+    for (k, image) in images.iter().enumerate() {
+        let x = (k % cols) as u32 * tile_width;
+        let y = (k / cols) as u32 * tile_height;
+        let left = x + (width - image.width) / 2;
+        let top = y + (height - image.height) / 2;
+        sheet.blit_tile(Rect::new(left, top, image.width, image.height), &image.data);
+        if let Some(label) = labels.get(k) {
+            draw_label(&mut sheet, x, y + height, label, scale);
+        }
+    }
+    sheet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Image of the given pixels, one row
+    fn row_image(pixels: &[[u8; 3]]) -> Image {
+        Image {
+            width: pixels.len() as u32,
+            height: 1,
+            data: pixels.concat(),
+        }
+    }
+
+    /// Packets of a TGA image, `(run, pixel count)` for each of them, and the decoded image
+    fn decode_tga(bytes: &[u8]) -> (Vec<(bool, usize)>, Image) {
+        assert_eq!(bytes[2], 10, "not a run-length encoded true color TGA");
+        let width = u16::from_le_bytes([bytes[12], bytes[13]]) as u32;
+        let height = u16::from_le_bytes([bytes[14], bytes[15]]) as u32;
+        let mut image = Image::new(width, height);
+        let mut packets = Vec::new();
+        let (mut at, mut k) = (18, 0);
+        while k < image.data.len() {
+            let header = bytes[at];
+            let count = (header & 0x7f) as usize + 1;
+            let run = header & 0x80 != 0;
+            packets.push((run, count));
+            at += 1;
+            for n in 0..count {
+                let pixel = if run {
+                    &bytes[at..at + 3]
+                } else {
+                    &bytes[at + n * 3..][..3]
+                };
+                image.data[k..k + 3].copy_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+                k += 3;
+            }
+            at += if run { 3 } else { count * 3 };
+        }
+        assert_eq!(&bytes[at + 8..], b"TRUEVISION-XFILE.\0");
+        (packets, image)
+    }
+
+    fn encode(image: &Image, write: fn(&Image, &mut dyn Write) -> std::io::Result<()>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(image, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn tga_runs_split_at_128_pixels() {
+        let cases: [(usize, &[(bool, usize)]); 5] = [
+            (127, &[(true, 127)]),
+            (128, &[(true, 128)]),
+            // a single pixel left after a full run is a raw packet
+            (129, &[(true, 128), (false, 1)]),
+            (256, &[(true, 128), (true, 128)]),
+            (258, &[(true, 128), (true, 128), (true, 2)]),
+        ];
+        for (length, expected) in cases {
+            let image = row_image(&vec![[10, 20, 30]; length]);
+            let (packets, decoded) = decode_tga(&encode(&image, Image::write_tga));
+            assert_eq!(decoded.data, image.data, "run of {} pixels", length);
+            assert_eq!(packets, expected, "run of {} pixels", length);
+        }
+    }
+
+    #[test]
+    fn tga_raw_packets_split_at_128_pixels() {
+        for length in [128, 129, 300] {
+            let pixels: Vec<[u8; 3]> = (0..length).map(|i| [i as u8, (i / 256) as u8, 7]).collect();
+            let image = row_image(&pixels);
+            let (packets, decoded) = decode_tga(&encode(&image, Image::write_tga));
+            assert_eq!(decoded.data, image.data, "{} different pixels", length);
+            assert!(packets.iter().all(|&(run, count)| !run && count <= 128));
+            assert_eq!(
+                packets.iter().map(|(_, count)| count).sum::<usize>(),
+                length
+            );
+        }
+    }
+
+    #[test]
+    fn tga_packets_stop_at_the_end_of_rows() {
+        let mut image = Image::new(3, 2);
+        image.data.fill(200);
+        image.set_pixel_rgb(2, 1, 1, 2, 3);
+        let (packets, decoded) = decode_tga(&encode(&image, Image::write_tga));
+        assert_eq!(decoded.data, image.data);
+        assert_eq!(packets, [(true, 3), (true, 2), (false, 1)]);
+    }
+
+    #[test]
+    fn tga_rejects_sides_over_65535_pixels() {
+        let image = Image {
+            width: 65536,
+            height: 0,
+            data: Vec::new(),
+        };
+        let error = image.write_tga(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn image_sizes_are_checked_without_overflowing() {
+        assert_eq!(check_image_size(1024, 768), Ok(1024 * 768 * 3));
+        let largest = MAX_IMAGE_DIMENSION as usize;
+        assert_eq!(
+            check_image_size(MAX_IMAGE_DIMENSION, MAX_IMAGE_DIMENSION),
+            Ok(largest * largest * 3)
+        );
+        let cases = [
+            (0, 768, "the image size 0x768 is empty"),
+            (1024, 0, "the image size 1024x0 is empty"),
+            (
+                MAX_IMAGE_DIMENSION + 1,
+                1,
+                "the image size 65537x1 is larger than 65536 pixels on a side",
+            ),
+            (
+                1,
+                u32::MAX,
+                "the image size 1x4294967295 is larger than 65536 pixels on a side",
+            ),
+        ];
+        for (width, height, message) in cases {
+            assert_eq!(
+                check_image_size(width, height),
+                Err(ImageError::new(message))
+            );
+            assert!(Image::try_new(width, height).is_err());
+        }
+        // width * height * 3 overflows 64 bits
+        let error = rgb_size(u32::MAX, u32::MAX).unwrap_err();
+        assert_eq!(
+            error.message,
+            "the image size 4294967295x4294967295 is too large"
+        );
+    }
+
+    #[test]
+    fn pixels_outside_of_the_image_are_none() {
+        let mut image = Image::new(4, 3);
+        assert_eq!(image.offset(3, 2), Some((2 * 4 + 3) * 3));
+        for (x, y) in [(4, 0), (0, 3), (u32::MAX, u32::MAX), (u32::MAX, 0)] {
+            assert_eq!(image.offset(x, y), None);
+            assert_eq!(image.pixel(x, y), None);
+            assert!(image.try_set_pixel(x, y, [1, 2, 3]).is_err());
+        }
+        assert_eq!(image.try_set_pixel(1, 1, [1, 2, 3]), Ok(()));
+        assert_eq!(image.pixel(1, 1), Some([1, 2, 3]));
+    }
+
+    #[test]
+    fn rect_indices_stay_inside_the_rect() {
+        let rect = Rect::new(10, 20, 3, 2);
+        assert_eq!(rect.index(10, 20), Some(0));
+        assert_eq!(rect.index(12, 21), Some(5));
+        for (i, j) in [(9, 20), (13, 20), (10, 22), (0, 0), (u32::MAX, u32::MAX)] {
+            assert_eq!(rect.index(i, j), None, "({}, {})", i, j);
+        }
+        let far = Rect::new(u32::MAX - 1, u32::MAX - 1, 1, 1);
+        assert_eq!(far.index(u32::MAX - 1, u32::MAX - 1), Some(0));
+        assert_eq!(far.index(u32::MAX, u32::MAX), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "tile 2x2 at (3, 0) outside of the 4x3 image")]
+    fn tiles_outside_of_the_image_panic() {
+        Image::new(4, 3).blit_tile(Rect::new(3, 0, 2, 2), &[0; 12]);
+    }
+
+    #[test]
+    #[should_panic(expected = "tile 2x2 at (4294967295, 0) outside of the 4x3 image")]
+    fn tiles_far_outside_of_the_image_panic() {
+        Image::new(4, 3).blit_tile(Rect::new(u32::MAX, 0, 2, 2), &[0; 12]);
+    }
+
+    #[test]
+    fn bmp_rows_are_padded_and_bottom_up() {
+        let mut image = Image::new(2, 2);
+        image.set_pixel_rgb(0, 0, 1, 2, 3);
+        image.set_pixel_rgb(1, 1, 4, 5, 6);
+        let bytes = encode(&image, Image::write_bmp);
+        // 2 pixels of 3 bytes padded to 8 bytes a row
+        assert_eq!(bytes.len(), 54 + 2 * 8);
+        assert_eq!(u32::from_le_bytes(bytes[2..6].try_into().unwrap()), 70);
+        assert_eq!(&bytes[54..62], &[0, 0, 0, 6, 5, 4, 0, 0]);
+        assert_eq!(&bytes[62..70], &[3, 2, 1, 0, 0, 0, 0, 0]);
+    }
+}
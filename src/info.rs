@@ -0,0 +1,194 @@
+use crate::json::Json;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+use std::fmt;
+
+/// Summary of a scene and of the render its settings describe
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Vertical field of view in degrees
+    pub fov: f64,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    /// Number of render threads the settings resolve to
+    pub threads: usize,
+    pub objects: usize,
+    /// Number of holdout objects among them
+    pub holdouts: usize,
+    pub lights: usize,
+    /// Names of the scene cameras
+    pub cameras: Vec<String>,
+    /// Names of the materials used by the objects, each once
+    pub materials: Vec<String>,
+    /// Files of the textures used by the materials, each once
+    pub textures: Vec<String>,
+    /// Path of the environment map or kind of its dome, `None` for the background color
+    pub environment: Option<String>,
+}
+
+impl SceneInfo {
+    pub fn new(scene: &Scene, settings: &RenderSettings) -> SceneInfo {
+        let mut materials: Vec<String> = Vec::new();
+        let mut textures: Vec<String> = Vec::new();
+        for sphere in &scene.spheres {
+            if let Some(name) = &sphere.material.name {
+                if !materials.contains(name) {
+                    materials.push(name.clone());
+                }
+            }
+            if let Some(path) = &sphere.material.texture {
+                if !textures.contains(path) {
+                    textures.push(path.clone());
+                }
+            }
+        }
+        SceneInfo {
+            width: settings.width,
+            height: settings.height,
+            fov: settings.fov.to_degrees(),
+            samples_per_pixel: settings.samples_per_pixel,
+            max_depth: settings.max_depth,
+            threads: settings.thread_count(),
+            objects: scene.spheres.len(),
+            holdouts: scene.spheres.iter().filter(|sphere| sphere.holdout).count(),
+            lights: scene.lights.len(),
+            cameras: scene
+                .cameras
+                .iter()
+                .map(|camera| camera.name.clone())
+                .collect(),
+            materials,
+            textures,
+            environment: scene.environment.as_ref().map(|env| env.description()),
+        }
+    }
+
+    /// Summary as a JSON object, for scripts
+    pub fn to_json(&self) -> Json {
+        let names = |names: &[String]| {
+            Json::Array(
+                names
+                    .iter()
+                    .map(|name| Json::String(name.clone()))
+                    .collect(),
+            )
+        };
+        Json::Object(vec![
+            ("width".to_string(), Json::Number(self.width as f64)),
+            ("height".to_string(), Json::Number(self.height as f64)),
+            ("fov".to_string(), Json::Number(self.fov)),
+            (
+                "samples_per_pixel".to_string(),
+                Json::Number(self.samples_per_pixel as f64),
+            ),
+            ("max_depth".to_string(), Json::Number(self.max_depth as f64)),
+            ("threads".to_string(), Json::Number(self.threads as f64)),
+            ("objects".to_string(), Json::Number(self.objects as f64)),
+            ("holdouts".to_string(), Json::Number(self.holdouts as f64)),
+            ("lights".to_string(), Json::Number(self.lights as f64)),
+            ("cameras".to_string(), names(&self.cameras)),
+            ("materials".to_string(), names(&self.materials)),
+            ("textures".to_string(), names(&self.textures)),
+            (
+                "environment".to_string(),
+                self.environment.clone().map_or(Json::Null, Json::String),
+            ),
+        ])
+    }
+}
+
+impl fmt::Display for SceneInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "image: {}x{}, {:.1} degree field of view",
+            self.width, self.height, self.fov
+        )?;
+        writeln!(
+            f,
+            "sampling: {} samples per pixel, {} bounces, {} threads",
+            self.samples_per_pixel, self.max_depth, self.threads
+        )?;
+        writeln!(f, "objects: {} ({} holdouts)", self.objects, self.holdouts)?;
+        writeln!(f, "lights: {}", self.lights)?;
+        writeln!(f, "cameras: {}", self.cameras.join(", "))?;
+        writeln!(f, "materials: {}", self.materials.join(", "))?;
+        writeln!(f, "textures: {}", self.textures.join(", "))?;
+        write!(
+            f,
+            "environment: {}",
+            self.environment.as_deref().unwrap_or("none")
+        )
+    }
+}
+
+/// Figures of a finished render
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderStatistics {
+    /// File the image was written to
+    pub output: String,
+    pub width: u32,
+    pub height: u32,
+    /// Camera rays of every pixel of the image, those of supersampling included
+    pub samples_per_pixel: u32,
+    pub threads: usize,
+    /// Wall clock time of the render in seconds
+    pub seconds: f64,
+}
+
+impl RenderStatistics {
+    pub fn new(output: &str, settings: &RenderSettings, seconds: f64) -> RenderStatistics {
+        RenderStatistics {
+            output: output.to_string(),
+            width: settings.width,
+            height: settings.height,
+            samples_per_pixel: settings.samples_per_pixel * settings.supersampling.pow(2),
+            threads: settings.thread_count(),
+            seconds,
+        }
+    }
+
+    /// Camera rays traced per second
+    pub fn rays_per_second(&self) -> f64 {
+        let rays = self.width as f64 * self.height as f64 * self.samples_per_pixel as f64;
+        rays / self.seconds.max(f64::EPSILON)
+    }
+
+    /// Statistics as a JSON object, for scripts
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("output".to_string(), Json::String(self.output.clone())),
+            ("width".to_string(), Json::Number(self.width as f64)),
+            ("height".to_string(), Json::Number(self.height as f64)),
+            (
+                "samples_per_pixel".to_string(),
+                Json::Number(self.samples_per_pixel as f64),
+            ),
+            ("threads".to_string(), Json::Number(self.threads as f64)),
+            ("seconds".to_string(), Json::Number(self.seconds)),
+            (
+                "rays_per_second".to_string(),
+                Json::Number(self.rays_per_second()),
+            ),
+        ])
+    }
+}
+
+impl fmt::Display for RenderStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}x{} pixels, {} samples per pixel, {} threads: {:.3} s ({:.0} camera rays/s)",
+            self.output,
+            self.width,
+            self.height,
+            self.samples_per_pixel,
+            self.threads,
+            self.seconds,
+            self.rays_per_second()
+        )
+    }
+}
@@ -0,0 +1,944 @@
+use crate::color::Color;
+use crate::gbuffer::GBuffer;
+use crate::image::Image;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::light::power_heuristic;
+use crate::light::retain_unoccluded;
+use crate::light::EnvironmentLight;
+use crate::light::Light;
+use crate::light::LightSample;
+use crate::medium::Medium;
+use crate::medium::MediumSample;
+use crate::medium::MediumStack;
+use crate::ray::Ray;
+use crate::render::cast_ray;
+use crate::render::direct_lighting;
+use crate::render::is_occluded;
+use crate::render::nearest_sphere;
+use crate::render::phong_factors;
+use crate::render::reflect;
+use crate::render::refract;
+use crate::render::surface_color;
+use crate::sampler::Sampler;
+use crate::sampling::cosine_hemisphere;
+use crate::sampling::cosine_hemisphere_pdf;
+use crate::sampling::henyey_greenstein_direction;
+use crate::sampling::Onb;
+use crate::scene::Scene;
+use crate::settings::json_to_count;
+use crate::settings::json_to_f64;
+use crate::settings::RenderSettings;
+use crate::volume::henyey_greenstein;
+
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+
+/// Rendering algorithm computing the radiance a ray carries back towards the camera
+pub trait Integrator: Sync {
+    /// Radiance arriving along a ray
+    ///
+    /// ### Arguments
+    ///
+    /// * `ray` - The ray (with a normalized direction)
+    /// * `scene` - The scene
+    /// * `settings` - The render settings (background and maximum number of bounces)
+    /// * `sampler` - The sampler of the random values the algorithm needs, at the current
+    ///   sample of the pixel
+    /// * `depth` - The number of bounces before this ray (0 for camera rays)
+    ///
+    /// ### Returns
+    ///
+    /// `Color` - The radiance of the ray
+    ///
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        settings: &RenderSettings,
+        sampler: &mut dyn Sampler,
+        depth: u32,
+    ) -> Color;
+}
+
+/// Classic recursive ray tracing (see `render::cast_ray`): direct light from the lights,
+/// perfect mirror reflections and refractions, deterministic
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Whitted;
+
+impl Integrator for Whitted {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        settings: &RenderSettings,
+        _sampler: &mut dyn Sampler,
+        depth: u32,
+    ) -> Color {
+        cast_ray(ray, scene, settings, depth)
+    }
+}
+
+/// Whitted shading with diffuse interreflections: every diffuse surface also sends a ray
+/// in a cosine-weighted random direction, so the light bounced by the surfaces around (and
+/// by the background) lights it too
+///
+/// The bounces count against `RenderSettings::max_depth` and the limits of every lobe
+/// (see `PathLimits`), the image is noisy and needs many samples per pixel
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PathTracer;
+
+/// Limits of the paths of the path tracer trading noise for bias, the usual knobs of the
+/// production renderers: the number of bounces of every lobe, the clamps of the radiance
+/// and the mollification of the highlights after a diffuse bounce
+///
+/// `None` leaves a limit out, the paths only end at `RenderSettings::max_depth`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PathLimits {
+    /// Maximum number of diffuse bounces of a path
+    pub diffuse_depth: Option<u32>,
+    /// Maximum number of mirror reflections of a path, the glossy lobe of the materials
+    pub glossy_depth: Option<u32>,
+    /// Maximum number of refractions of a path, in or out of the transparent objects
+    pub transmission_depth: Option<u32>,
+    /// Largest channel of the light reaching the camera directly: the lights at the surfaces
+    /// and in the media seen by the camera, and the background
+    pub clamp_direct: Option<f64>,
+    /// Largest channel of the radiance of the rays after the first bounce, the fireflies of
+    /// the caustics and of the small bright lights seen through bounces
+    pub clamp_indirect: Option<f64>,
+    /// Smallest roughness of the highlights of the surfaces a path reaches after a diffuse
+    /// bounce, blurring the sharp highlights only the rare paths find (see
+    /// `mollified_exponent`)
+    pub roughness_clamp: Option<f64>,
+}
+
+impl PathLimits {
+    /// Specular exponent of a highlight reached after a diffuse bounce, lowered to the
+    /// exponent of the roughness clamp, the roughness of a Phong lobe of exponent `n` being
+    /// `sqrt(2 / (n + 2))` (Walter et al., "Microfacet Models for Refraction through Rough
+    /// Surfaces", 2007)
+    pub fn mollified_exponent(&self, exponent: f64) -> f64 {
+        match self.roughness_clamp {
+            Some(roughness) if roughness > 0.0 => {
+                exponent.min(2.0 / (roughness * roughness) - 2.0).max(0.0)
+            }
+            _ => exponent,
+        }
+    }
+
+    /// Limits as a JSON object, the limits left out omitted
+    pub fn to_json(&self) -> Json {
+        let depths = [
+            ("diffuse_depth", self.diffuse_depth),
+            ("glossy_depth", self.glossy_depth),
+            ("transmission_depth", self.transmission_depth),
+        ];
+        let values = [
+            ("clamp_direct", self.clamp_direct),
+            ("clamp_indirect", self.clamp_indirect),
+            ("roughness_clamp", self.roughness_clamp),
+        ];
+        Json::Object(
+            depths
+                .into_iter()
+                .filter_map(|(key, depth)| Some((key.to_string(), Json::Number(depth? as f64))))
+                .chain(
+                    values
+                        .into_iter()
+                        .filter_map(|(key, value)| Some((key.to_string(), Json::Number(value?)))),
+                )
+                .collect(),
+        )
+    }
+
+    /// Read the limits from a JSON object, the missing keys left out
+    pub fn from_json(json: &Json) -> Result<PathLimits, JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("'path_limits' must be a JSON object"));
+        };
+        let mut limits = PathLimits::default();
+        for (key, value) in entries {
+            match key.as_str() {
+                "diffuse_depth" => limits.diffuse_depth = Some(json_to_count(key, value)? as u32),
+                "glossy_depth" => limits.glossy_depth = Some(json_to_count(key, value)? as u32),
+                "transmission_depth" => {
+                    limits.transmission_depth = Some(json_to_count(key, value)? as u32)
+                }
+                "clamp_direct" => limits.clamp_direct = Some(json_to_limit(key, value)?),
+                "clamp_indirect" => limits.clamp_indirect = Some(json_to_limit(key, value)?),
+                "roughness_clamp" => limits.roughness_clamp = Some(json_to_limit(key, value)?),
+                _ => return Err(JsonError::new(&format!("unknown path limit '{}'", key))),
+            }
+        }
+        Ok(limits)
+    }
+}
+
+/// Read a clamp, which must be positive
+fn json_to_limit(key: &str, value: &Json) -> Result<f64, JsonError> {
+    match json_to_f64(key, value)? {
+        limit if limit > 0.0 => Ok(limit),
+        _ => Err(JsonError::new(&format!("'{}' must be positive", key))),
+    }
+}
+
+/// Color scaled down to keep its largest channel under a clamp, its hue kept
+fn clamp_radiance(color: Color, clamp: Option<f64>) -> Color {
+    match clamp {
+        Some(clamp) if color.max_channel() > clamp => color * (clamp / color.max_channel()),
+        _ => color,
+    }
+}
+
+/// Whether a lobe can be sampled once more under its limit
+fn within(limit: Option<u32>, count: u32) -> bool {
+    limit.is_none_or(|limit| count < limit)
+}
+
+/// Ray of a path with the way its direction was sampled, for the multiple importance
+/// sampling of the lights the rays can reach (see `light::Light`)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Bounce {
+    /// Number of bounces before the ray (0 for camera rays)
+    depth: u32,
+    /// Solid angle density the direction of the ray was sampled with, `None` for the camera
+    /// rays and the mirrored and refracted ones, which the lights can't sample
+    pdf: Option<f64>,
+    /// Number of diffuse bounces, mirror reflections and refractions before the ray, for
+    /// the limits of every lobe (see `PathLimits`)
+    diffuse: u32,
+    glossy: u32,
+    transmission: u32,
+}
+
+impl Bounce {
+    /// Next ray of the path, sampled with the given density
+    fn next(&self, pdf: Option<f64>) -> Bounce {
+        Bounce {
+            depth: self.depth + 1,
+            pdf,
+            ..*self
+        }
+    }
+
+    /// Next ray of the path after a diffuse bounce sampled with the given density
+    fn diffuse(&self, pdf: f64) -> Bounce {
+        Bounce {
+            diffuse: self.diffuse + 1,
+            ..self.next(Some(pdf))
+        }
+    }
+
+    /// Next ray of the path after a mirror reflection
+    fn glossy(&self) -> Bounce {
+        Bounce {
+            glossy: self.glossy + 1,
+            ..self.next(None)
+        }
+    }
+
+    /// Next ray of the path after a refraction
+    fn transmission(&self) -> Bounce {
+        Bounce {
+            transmission: self.transmission + 1,
+            ..self.next(None)
+        }
+    }
+}
+
+/// Radiance of a ray traveling through the given transparent objects for the path tracer,
+/// like `render::cast_ray_in_media`, clamped after the first bounce (see
+/// `PathLimits::clamp_indirect`)
+///
+/// The surfaces and the media are lit by samples of every light, the lights the rays can
+/// reach (the environment) weighted against the diffuse bounces and the scattered rays
+/// with the power heuristic
+fn trace_path(
+    ray: &Ray,
+    media: &MediumStack,
+    scene: &Scene,
+    lights: &[&dyn Light],
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+    bounce: Bounce,
+) -> Color {
+    let color = path_radiance(ray, media, scene, lights, settings, sampler, bounce);
+    if bounce.depth == 0 {
+        return color;
+    }
+    clamp_radiance(color, settings.path_limits.clamp_indirect)
+}
+
+/// Radiance of a ray of a path before the indirect clamp (see `trace_path`)
+fn path_radiance(
+    ray: &Ray,
+    media: &MediumStack,
+    scene: &Scene,
+    lights: &[&dyn Light],
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+    bounce: Bounce,
+) -> Color {
+    let depth = bounce.depth;
+    let limits = &settings.path_limits;
+    // the light reaching the camera without any bounce has its own clamp
+    let direct_clamp = if depth == 0 {
+        limits.clamp_direct
+    } else {
+        None
+    };
+    let hit = nearest_sphere(ray, scene).filter(|(_, distance)| *distance < 1000.0);
+
+    // the light may be scattered by the medium before reaching the surface (or leaving the
+    // scene), otherwise what reaches the camera is weighted by the transmittance
+    let medium = media.medium(scene, settings.outside_medium());
+    let mut weight = Color::WHITE;
+    if !medium.is_vacuum() && depth <= settings.max_depth {
+        let segment = ray.with_t_max(hit.map_or(f64::INFINITY, |(_, distance)| distance));
+        match medium.sample(&segment, sampler.get_1d()) {
+            MediumSample::Scattered { t, weight } => {
+                let point = ray.at(t);
+                let mut color = clamp_radiance(
+                    medium_direct_lighting(point, ray, medium, scene, lights, sampler),
+                    direct_clamp,
+                );
+                let local = {
+                    let (u, v) = sampler.get_2d();
+                    henyey_greenstein_direction(u, v, medium.anisotropy())
+                };
+                let direction = Onb::from_w(ray.direction).to_world(local);
+                let pdf = henyey_greenstein(local.z, medium.anisotropy());
+                let scattered = Ray::new(point, direction).with_time(ray.time);
+                color += trace_path(
+                    &scattered,
+                    media,
+                    scene,
+                    lights,
+                    settings,
+                    sampler,
+                    bounce.next(Some(pdf)),
+                );
+                return weight * color;
+            }
+            MediumSample::Passed { weight: passed } => weight = passed,
+        }
+    }
+
+    let Some((index, distance)) = hit.filter(|_| depth <= settings.max_depth) else {
+        // the lights the ray reaches also sampled the direction it was sampled in
+        let light_pdf: f64 = lights
+            .iter()
+            .map(|light| light.pdf_li(ray.origin, ray.direction))
+            .sum();
+        let mis = match bounce.pdf {
+            Some(pdf) if light_pdf > 0.0 => power_heuristic(pdf, light_pdf),
+            _ => 1.0,
+        };
+        return weight * clamp_radiance(settings.background_radiance(ray), direct_clamp) * mis;
+    };
+    let sphere = scene.sphere(index);
+    if sphere.holdout {
+        return Color::BLACK;
+    }
+    let point = ray.at(distance);
+    let n = (point - sphere.center).normalize();
+    let material = &sphere.material;
+    if material.refraction > 0.0 && media.is_false_hit(index, scene) {
+        let through = Ray::offset(point, n, ray.direction).with_time(ray.time);
+        let crossed = media.cross(index);
+        return weight * trace_path(&through, &crossed, scene, lights, settings, sampler, bounce);
+    }
+
+    // direct light, the diffuse bounce below samples the same light when it reaches a light,
+    // the highlights found through a diffuse bounce are mollified
+    let facing = if ray.direction.dot(&n) > 0.0 { -n } else { n };
+    let bounces = material.albedo[0] > 0.0 && within(limits.diffuse_depth, bounce.diffuse);
+    let exponent = if bounce.diffuse > 0 {
+        limits.mollified_exponent(material.specular_exponent)
+    } else {
+        material.specular_exponent
+    };
+    let mut diffuse_light = Color::BLACK;
+    let mut specular_light = Color::BLACK;
+    for (light, sample) in light_samples(lights, point, n, scene, sampler) {
+        let (diffuse, specular) = phong_factors(sample.direction, n, ray.direction, exponent);
+        let mis = if light.is_delta() || !bounces {
+            1.0
+        } else {
+            let bounce_pdf = cosine_hemisphere_pdf(sample.direction.dot(&facing));
+            power_heuristic(sample.pdf, bounce_pdf)
+        };
+        let sample_weight = sample.radiance / (sample.pdf * light.samples() as f64);
+        diffuse_light += sample_weight * (diffuse * mis);
+        specular_light += sample_weight * specular;
+    }
+    let albedo = surface_color(sphere, point, settings) * material.albedo[0];
+    let mut color = clamp_radiance(
+        albedo * diffuse_light + specular_light * material.albedo[1],
+        direct_clamp,
+    );
+
+    // the cosine of the bounce cancels out with its density, leaving the albedo
+    if bounces {
+        let local = {
+            let (u, v) = sampler.get_2d();
+            cosine_hemisphere(u, v)
+        };
+        let pdf = cosine_hemisphere_pdf(local.z);
+        let bounce_ray =
+            Ray::offset(point, facing, Onb::from_w(facing).to_world(local)).with_time(ray.time);
+        color += albedo
+            * trace_path(
+                &bounce_ray,
+                media,
+                scene,
+                lights,
+                settings,
+                sampler,
+                bounce.diffuse(pdf),
+            );
+    }
+
+    if material.albedo[2] <= 0.0 && material.refraction <= 0.0 {
+        return weight * color;
+    }
+    let mut reflect_color = Color::BLACK;
+    if within(limits.glossy_depth, bounce.glossy) {
+        let reflect_ray = Ray::offset(point, n, reflect(ray.direction, n)).with_time(ray.time);
+        reflect_color = trace_path(
+            &reflect_ray,
+            media,
+            scene,
+            lights,
+            settings,
+            sampler,
+            bounce.glossy(),
+        );
+    }
+    color += reflect_color * material.albedo[2];
+    if material.refraction > 0.0 {
+        let crossed = media.cross(index);
+        let eta = media.refractive_index(scene) / crossed.refractive_index(scene);
+        let facing = if media.contains(index) { -n } else { n };
+        let refract_color = match refract(ray.direction, facing, eta) {
+            Some(direction) if within(limits.transmission_depth, bounce.transmission) => {
+                let refract_ray = Ray::offset(point, n, direction).with_time(ray.time);
+                trace_path(
+                    &refract_ray,
+                    &crossed,
+                    scene,
+                    lights,
+                    settings,
+                    sampler,
+                    bounce.transmission(),
+                )
+            }
+            Some(_) => Color::BLACK,
+            // total internal reflection
+            None => reflect_color,
+        };
+        color += refract_color * material.refraction;
+    }
+    weight * color
+}
+
+/// Light of the lights scattered towards the origin of a ray at a point of a medium, the
+/// lights attenuated by the medium on their way; like the surfaces, the medium doesn't
+/// divide the light intensities by pi
+fn medium_direct_lighting(
+    point: Vector3<f64>,
+    ray: &Ray,
+    medium: &dyn Medium,
+    scene: &Scene,
+    lights: &[&dyn Light],
+    sampler: &mut dyn Sampler,
+) -> Color {
+    let mut color = Color::BLACK;
+    // the point is on no surface, the shadow rays are only pushed off along the ray
+    for (light, sample) in light_samples(lights, point, ray.direction, scene, sampler) {
+        // the light travels along `-direction` and is scattered back along the ray, the
+        // scattered rays sample the same density
+        let phase = henyey_greenstein(sample.direction.dot(&ray.direction), medium.anisotropy());
+        let mis = if light.is_delta() {
+            1.0
+        } else {
+            power_heuristic(sample.pdf, phase)
+        };
+        let shadow_ray = Ray::new(point, sample.direction).with_t_max(sample.distance);
+        color += sample.radiance
+            * medium.transmittance(&shadow_ray)
+            * (phase * PI * mis / (sample.pdf * light.samples() as f64));
+    }
+    color
+}
+
+/// Samples of every light reaching a point, `light.samples()` of each light taken from the
+/// sampler, the shadow rays of all of them traced together (see `retain_unoccluded`)
+fn light_samples<'a>(
+    lights: &[&'a dyn Light],
+    point: Vector3<f64>,
+    n: Vector3<f64>,
+    scene: &Scene,
+    sampler: &mut dyn Sampler,
+) -> Vec<(&'a dyn Light, LightSample)> {
+    let mut samples = Vec::new();
+    for light in lights {
+        for _ in 0..light.samples() {
+            let u = sampler.get_2d();
+            if let Some(sample) = light.sample_li(point, u) {
+                samples.push((*light, sample));
+            }
+        }
+    }
+    retain_unoccluded(&mut samples, point, n, scene);
+    samples
+}
+
+impl Integrator for PathTracer {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        settings: &RenderSettings,
+        sampler: &mut dyn Sampler,
+        depth: u32,
+    ) -> Color {
+        // the environment map lights the scene like the lights do
+        let environment = settings.environment.as_ref().map(|environment| {
+            EnvironmentLight::new(
+                environment,
+                settings.texture_budget,
+                settings.texture_format(environment.space),
+            )
+        });
+        let mut lights: Vec<&dyn Light> = scene
+            .lights
+            .iter()
+            .map(|light| light as &dyn Light)
+            .collect();
+        if let Some(environment) = &environment {
+            lights.push(environment);
+        }
+        let media = MediumStack::at(ray.origin, &scene.spheres);
+        let bounce = Bounce {
+            depth,
+            ..Bounce::default()
+        };
+        trace_path(ray, &media, scene, &lights, settings, sampler, bounce)
+    }
+}
+
+/// Ambient occlusion of the surfaces seen by the camera: white where a ray in a random
+/// cosine-weighted direction of the surface escapes further than `distance`, black where it
+/// hits another surface, so the average over the samples of a pixel is the fraction of
+/// light from a uniform sky reaching the surface
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientOcclusion {
+    /// Distance beyond which surfaces don't occlude each other
+    pub distance: f64,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        AmbientOcclusion { distance: 1.0 }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        _settings: &RenderSettings,
+        sampler: &mut dyn Sampler,
+        _depth: u32,
+    ) -> Color {
+        let hit = nearest_sphere(ray, scene).filter(|(_, distance)| *distance < 1000.0);
+        let Some((index, distance)) = hit else {
+            return Color::WHITE;
+        };
+        let sphere = scene.sphere(index);
+        if sphere.holdout {
+            return Color::BLACK;
+        }
+        let point = ray.at(distance);
+        let n = (point - sphere.center).normalize();
+        let facing = if ray.direction.dot(&n) > 0.0 { -n } else { n };
+        let local = {
+            let (u, v) = sampler.get_2d();
+            cosine_hemisphere(u, v)
+        };
+        let occlusion_ray = Ray::offset(point, facing, Onb::from_w(facing).to_world(local))
+            .with_t_max(self.distance);
+        if is_occluded(&occlusion_ray, scene) {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        }
+    }
+}
+
+/// Surface property shown by the `DebugShading` integrator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    /// Surface normal, its coordinates mapped from `[-1, 1]` to `[0, 1]`
+    Normal,
+    /// Diffuse color, the texture included
+    Albedo,
+    /// UV coordinates in red and green
+    Uv,
+}
+
+impl DebugView {
+    /// View from its name: `normal`, `albedo` or `uv`
+    pub fn from_name(name: &str) -> Option<DebugView> {
+        match name {
+            "normal" => Some(DebugView::Normal),
+            "albedo" => Some(DebugView::Albedo),
+            "uv" => Some(DebugView::Uv),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugView::Normal => "normal",
+            DebugView::Albedo => "albedo",
+            DebugView::Uv => "uv",
+        }
+    }
+}
+
+/// Unlit property of the surfaces seen by the camera, black where nothing is hit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugShading {
+    pub view: DebugView,
+}
+
+impl Integrator for DebugShading {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        settings: &RenderSettings,
+        _sampler: &mut dyn Sampler,
+        _depth: u32,
+    ) -> Color {
+        let hit = nearest_sphere(ray, scene).filter(|(_, distance)| *distance < 1000.0);
+        let Some((index, distance)) = hit else {
+            return Color::BLACK;
+        };
+        let sphere = scene.sphere(index);
+        let point = ray.at(distance);
+        match self.view {
+            DebugView::Normal => {
+                let n = (point - sphere.center).normalize();
+                Color::new(n.x, n.y, n.z) * 0.5 + Color::new(0.5, 0.5, 0.5)
+            }
+            DebugView::Albedo => surface_color(sphere, point, settings),
+            DebugView::Uv => {
+                let (u, v) = sphere.uv_at(point);
+                Color::new(u, v, 0.0)
+            }
+        }
+    }
+}
+
+/// Fast flat shading of the surfaces seen by the camera for previews while the camera
+/// moves (a playblast): the surface color lit by every light through the cosine of the
+/// angle to it, without shadows, highlights, reflections or refractions, so only the camera
+/// rays are traced
+///
+/// A scene without lights is lit by a headlight at the camera, so it doesn't turn black
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Playblast;
+
+impl Integrator for Playblast {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        settings: &RenderSettings,
+        _sampler: &mut dyn Sampler,
+        _depth: u32,
+    ) -> Color {
+        let hit = nearest_sphere(ray, scene).filter(|(_, distance)| *distance < 1000.0);
+        let Some((index, distance)) = hit else {
+            return settings.background_radiance(ray);
+        };
+        let sphere = scene.sphere(index);
+        if sphere.holdout {
+            return Color::BLACK;
+        }
+        let point = ray.at(distance);
+        let n = (point - sphere.center).normalize();
+        let facing = if ray.direction.dot(&n) > 0.0 { -n } else { n };
+        let light = if scene.lights.is_empty() {
+            Color::gray(facing.dot(&-ray.direction).max(0.0))
+        } else {
+            scene
+                .lights
+                .iter()
+                .fold(Color::BLACK, |light, scene_light| {
+                    let (direction, _) = scene_light.direction_from(point);
+                    light
+                        + scene_light.radiance()
+                            * (scene_light.attenuation(point) * facing.dot(&direction).max(0.0))
+                })
+        };
+        surface_color(sphere, point, settings) * light
+    }
+}
+
+/// Cosine of the angle between the normals of neighboring pixels above which the toon
+/// outlines mark a crease
+const OUTLINE_CREASE: f64 = 0.75;
+
+/// Distance of a neighboring pixel from the tangent plane of the surface, relative to the
+/// depth of the surface, above which the toon outlines mark an edge
+const OUTLINE_DEPTH: f64 = 0.05;
+
+/// Fraction of the view direction grazing the surface (1 at the silhouette) from which the
+/// toon rim light shows
+const RIM_EDGE: f64 = 0.7;
+
+/// Cel shading for non-photorealistic images: the light of the lights in a few flat bands
+/// with hard shadows and a hard highlight, a rim light along the silhouettes and, once the
+/// image is developed, ink outlines along the silhouettes and the creases
+///
+/// The outlines are drawn from the normals and the depths of the surfaces seen at the
+/// pixel centers (the AOVs of `gbuffer::GBuffer`), where they jump between neighboring
+/// pixels (see `draw_outlines`). Mirrors and glass are shaded flat like the other surfaces
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Toon {
+    /// Number of shades of the lit side, at least 1
+    pub bands: u32,
+    /// Strength of the rim light, 0 for none
+    pub rim: f64,
+    /// Whether the outlines are drawn over the developed image
+    pub outline: bool,
+}
+
+impl Default for Toon {
+    fn default() -> Self {
+        Toon {
+            bands: 3,
+            rim: 0.3,
+            outline: true,
+        }
+    }
+}
+
+impl Toon {
+    /// Darken the pixels on the outlines of the surfaces
+    ///
+    /// A pixel is on an outline where a neighbor sees no surface or a further surface off
+    /// its tangent plane by more than `OUTLINE_DEPTH`, so the line stays on the side of the
+    /// nearer surface and a floor seen at a grazing angle isn't taken for an edge, or where
+    /// the normals of the neighbors turn by more than `OUTLINE_CREASE`
+    ///
+    /// ### Arguments
+    ///
+    /// * `image` - The developed image
+    /// * `gbuffer` - The surfaces seen at the pixel centers, at the size of the image
+    /// * `eye` - The position of the camera, the depths are measured from it
+    ///
+    pub fn draw_outlines(&self, image: &mut Image, gbuffer: &GBuffer, eye: Vector3<f64>) {
+        let (width, height) = (gbuffer.width as i64, gbuffer.height as i64);
+        let depth = |k: usize| gbuffer.object_id[k].map(|_| (gbuffer.position[k] - eye).norm());
+        for j in 0..height {
+            for i in 0..width {
+                let k = (j * width + i) as usize;
+                let Some(near) = depth(k) else {
+                    continue;
+                };
+                let edge = [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(di, dj)| {
+                    let (x, y) = (i + di, j + dj);
+                    if x < 0 || y < 0 || x >= width || y >= height {
+                        return false;
+                    }
+                    let other = (y * width + x) as usize;
+                    match depth(other) {
+                        Some(far) => {
+                            let step = gbuffer.position[other] - gbuffer.position[k];
+                            let off_plane = step.dot(&gbuffer.normal[k]).abs();
+                            (far > near && off_plane > OUTLINE_DEPTH * near)
+                                || gbuffer.normal[k].dot(&gbuffer.normal[other]) < OUTLINE_CREASE
+                        }
+                        None => true,
+                    }
+                });
+                if edge {
+                    image.set_pixel_rgb(i as u32, j as u32, 0, 0, 0);
+                }
+            }
+        }
+    }
+}
+
+impl Integrator for Toon {
+    fn li(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        settings: &RenderSettings,
+        _sampler: &mut dyn Sampler,
+        _depth: u32,
+    ) -> Color {
+        let hit = nearest_sphere(ray, scene).filter(|(_, distance)| *distance < 1000.0);
+        let Some((index, distance)) = hit else {
+            return settings.background_radiance(ray);
+        };
+        let sphere = scene.sphere(index);
+        if sphere.holdout {
+            return Color::BLACK;
+        }
+        let point = ray.at(distance);
+        let n = (point - sphere.center).normalize();
+        let material = &sphere.material;
+        let (diffuse, specular) =
+            direct_lighting(point, n, ray.direction, material.specular_exponent, scene);
+        // the light is snapped up to its band, keeping its color, and the shadowed side
+        // keeps half a band so the shapes still read in the shadows
+        let bands = self.bands.max(1) as f64;
+        let level = diffuse.max_channel();
+        let lit = if level > 0.0 {
+            diffuse * ((level.min(1.0) * bands).ceil() / bands / level)
+        } else {
+            Color::BLACK
+        };
+        let mut color =
+            surface_color(sphere, point, settings) * (lit + Color::WHITE * (0.5 / bands));
+        if specular.max_channel() > 0.5 {
+            color += Color::WHITE * material.albedo[1];
+        }
+        let grazing = 1.0 - ray.direction.dot(&n).abs();
+        if grazing > RIM_EDGE {
+            color += Color::WHITE * self.rim;
+        }
+        color
+    }
+}
+
+/// Integrator of a render, chosen in the render settings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntegratorKind {
+    Whitted(Whitted),
+    PathTracer(PathTracer),
+    AmbientOcclusion(AmbientOcclusion),
+    Debug(DebugShading),
+    Toon(Toon),
+    Playblast(Playblast),
+}
+
+impl Default for IntegratorKind {
+    fn default() -> Self {
+        IntegratorKind::Whitted(Whitted)
+    }
+}
+
+impl IntegratorKind {
+    pub fn integrator(&self) -> &dyn Integrator {
+        match self {
+            IntegratorKind::Whitted(integrator) => integrator,
+            IntegratorKind::PathTracer(integrator) => integrator,
+            IntegratorKind::AmbientOcclusion(integrator) => integrator,
+            IntegratorKind::Debug(integrator) => integrator,
+            IntegratorKind::Toon(integrator) => integrator,
+            IntegratorKind::Playblast(integrator) => integrator,
+        }
+    }
+
+    /// Name of the integrator: `whitted`, `path`, `ao`, `debug` or `toon`
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntegratorKind::Whitted(_) => "whitted",
+            IntegratorKind::PathTracer(_) => "path",
+            IntegratorKind::AmbientOcclusion(_) => "ao",
+            IntegratorKind::Debug(_) => "debug",
+            IntegratorKind::Toon(_) => "toon",
+            IntegratorKind::Playblast(_) => "playblast",
+        }
+    }
+
+    /// Integrator as a JSON object: its `type` and its parameters, `distance` for `ao`,
+    /// `view` for `debug` and `bands`, `rim` and `outline` for `toon`
+    pub fn to_json(&self) -> Json {
+        let mut entries = vec![("type".to_string(), Json::String(self.name().to_string()))];
+        match self {
+            IntegratorKind::AmbientOcclusion(ao) => {
+                entries.push(("distance".to_string(), Json::Number(ao.distance)))
+            }
+            IntegratorKind::Debug(debug) => entries.push((
+                "view".to_string(),
+                Json::String(debug.view.name().to_string()),
+            )),
+            IntegratorKind::Toon(toon) => entries.extend([
+                ("bands".to_string(), Json::Number(toon.bands as f64)),
+                ("rim".to_string(), Json::Number(toon.rim)),
+                ("outline".to_string(), Json::Bool(toon.outline)),
+            ]),
+            _ => {}
+        }
+        Json::Object(entries)
+    }
+
+    /// Read an integrator from a JSON object (see `to_json`), missing parameters keep their
+    /// default values
+    pub fn from_json(json: &Json) -> Result<IntegratorKind, JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("'integrator' must be a JSON object"));
+        };
+        let name = entries
+            .iter()
+            .find(|(key, _)| key == "type")
+            .and_then(|(_, value)| value.as_str())
+            .ok_or_else(|| JsonError::new("'integrator' must have a 'type' string"))?;
+        let mut integrator = match name {
+            "whitted" => IntegratorKind::Whitted(Whitted),
+            "path" => IntegratorKind::PathTracer(PathTracer),
+            "ao" => IntegratorKind::AmbientOcclusion(AmbientOcclusion::default()),
+            "debug" => IntegratorKind::Debug(DebugShading {
+                view: DebugView::Normal,
+            }),
+            "toon" => IntegratorKind::Toon(Toon::default()),
+            "playblast" => IntegratorKind::Playblast(Playblast),
+            _ => {
+                return Err(JsonError::new(
+                    "'type' must be whitted, path, ao, debug, toon or playblast",
+                ))
+            }
+        };
+        for (key, value) in entries {
+            match (key.as_str(), &mut integrator) {
+                ("type", _) => {}
+                ("distance", IntegratorKind::AmbientOcclusion(ao)) => {
+                    ao.distance = json_to_f64(key, value)?
+                }
+                ("view", IntegratorKind::Debug(debug)) => {
+                    debug.view = value
+                        .as_str()
+                        .and_then(DebugView::from_name)
+                        .ok_or_else(|| JsonError::new("'view' must be normal, albedo or uv"))?
+                }
+                ("bands", IntegratorKind::Toon(toon)) => {
+                    toon.bands = json_to_count(key, value)?.max(1) as u32
+                }
+                ("rim", IntegratorKind::Toon(toon)) => toon.rim = json_to_f64(key, value)?,
+                ("outline", IntegratorKind::Toon(toon)) => {
+                    toon.outline = value
+                        .as_bool()
+                        .ok_or_else(|| JsonError::new("'outline' must be true or false"))?
+                }
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown setting '{}' of the {} integrator",
+                        key, name
+                    )))
+                }
+            }
+        }
+        Ok(integrator)
+    }
+}
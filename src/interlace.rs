@@ -0,0 +1,113 @@
+use crc32fast::Hasher;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+/// Signature every PNG file starts with
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Passes of the Adam7 interlacing as the column and row of their first pixel and the step
+/// between their columns and rows
+const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Write RGB pixels as an Adam7 interlaced PNG
+///
+/// The pixels are sent in seven passes, the first holding one pixel in 64, so a viewer
+/// receiving the file over a slow connection can show the whole image coarsely and refine
+/// it as the rest arrives. The `png` encoder only writes images row after row, the chunks
+/// are written here instead
+///
+/// ### Arguments
+///
+/// * `w` - The writer the PNG goes to
+/// * `width`, `height` - The size of the image in pixels
+/// * `data` - The RGB pixels of the image, row by row
+///
+/// ### Returns
+///
+/// `std::io::Result<()>` - An error if the writer fails
+///
+pub fn write_interlaced_png<W: Write>(
+    mut w: W,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> std::io::Result<()> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGB, deflate, adaptive filtering, Adam7
+    header.extend_from_slice(&[8, 2, 0, 0, 1]);
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for (x0, y0, dx, dy) in ADAM7_PASSES {
+        let columns = width.saturating_sub(x0).div_ceil(dx) as usize;
+        let rows = height.saturating_sub(y0).div_ceil(dy);
+        if columns == 0 || rows == 0 {
+            continue;
+        }
+        let mut previous = vec![0; columns * 3];
+        let mut row = Vec::with_capacity(columns * 3);
+        for j in 0..rows {
+            let y = (y0 + j * dy) as usize;
+            row.clear();
+            for i in 0..columns {
+                let offset = (y * width as usize + x0 as usize + i * dx as usize) * 3;
+                row.extend_from_slice(&data[offset..offset + 3]);
+            }
+            encoder.write_all(&[4])?;
+            encoder.write_all(&paeth_filter(&row, &previous))?;
+            std::mem::swap(&mut previous, &mut row);
+        }
+    }
+
+    w.write_all(&PNG_SIGNATURE)?;
+    write_chunk(&mut w, b"IHDR", &header)?;
+    write_chunk(&mut w, b"IDAT", &encoder.finish()?)?;
+    write_chunk(&mut w, b"IEND", &[])?;
+    w.flush()
+}
+
+/// Differences of a row of RGB pixels with the Paeth predictor of each byte, from the byte
+/// to its left, the one above it and the one above and to the left
+fn paeth_filter(row: &[u8], previous: &[u8]) -> Vec<u8> {
+    let byte = |bytes: &[u8], k: usize| if k >= 3 { bytes[k - 3] } else { 0 };
+    (0..row.len())
+        .map(|k| {
+            let (a, b, c) = (byte(row, k), previous[k], byte(previous, k));
+            let p = a as i16 + b as i16 - c as i16;
+            let (pa, pb, pc) = (
+                (p - a as i16).abs(),
+                (p - b as i16).abs(),
+                (p - c as i16).abs(),
+            );
+            let predictor = if pa <= pb && pa <= pc {
+                a
+            } else if pb <= pc {
+                b
+            } else {
+                c
+            };
+            row[k].wrapping_sub(predictor)
+        })
+        .collect()
+}
+
+/// Write a PNG chunk: its length, its type, its data and the CRC of the type and data
+fn write_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+    let mut hasher = Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    w.write_all(&hasher.finalize().to_be_bytes())
+}
@@ -0,0 +1,187 @@
+use crate::colorspace::ColorPipeline;
+use crate::image::Image;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::parameter::ParameterError;
+use crate::render::cast_ray;
+use crate::render::nearest_sphere;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+/// Named collection of scene objects rendered into its own image
+pub struct RenderLayer {
+    pub name: String,
+    /// Indices of the spheres belonging to the layer
+    pub objects: Vec<usize>,
+}
+
+impl RenderLayer {
+    pub fn new(name: &str, objects: Vec<usize>) -> RenderLayer {
+        RenderLayer {
+            name: name.to_string(),
+            objects,
+        }
+    }
+}
+
+/// Object of a layer as a scene lists it, by its name or by its index among the spheres
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayerObject {
+    Index(usize),
+    Name(String),
+}
+
+/// Render layer declared by a scene, its objects found in the scene when it is rendered
+/// (see `resolve`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerSpec {
+    pub name: String,
+    pub objects: Vec<LayerObject>,
+}
+
+impl LayerSpec {
+    pub fn new(name: &str, objects: Vec<LayerObject>) -> LayerSpec {
+        LayerSpec {
+            name: name.to_string(),
+            objects,
+        }
+    }
+
+    /// Layer from a JSON object with its `name` and its `objects`, each the name of an
+    /// object or its index among the spheres of the scene, e.g.
+    /// `{"name": "foreground", "objects": ["ivory_ball", 1]}`
+    pub fn from_json(json: &Json) -> Result<LayerSpec, JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("a layer must be a JSON object"));
+        };
+        let mut name = None;
+        let mut objects = Vec::new();
+        for (key, value) in entries {
+            match key.as_str() {
+                "name" => {
+                    name = Some(
+                        value
+                            .as_str()
+                            .ok_or_else(|| JsonError::new("'name' must be a string"))?
+                            .to_string(),
+                    )
+                }
+                "objects" => {
+                    let Json::Array(items) = value else {
+                        return Err(JsonError::new("'objects' must be an array"));
+                    };
+                    for item in items {
+                        objects.push(match item {
+                            Json::String(name) => LayerObject::Name(name.clone()),
+                            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => {
+                                LayerObject::Index(*n as usize)
+                            }
+                            _ => {
+                                return Err(JsonError::new(
+                                    "'objects' must hold object names or indices",
+                                ))
+                            }
+                        });
+                    }
+                }
+                _ => return Err(JsonError::new(&format!("unknown layer property '{}'", key))),
+            }
+        }
+        let name = name.ok_or_else(|| JsonError::new("a layer must have a 'name'"))?;
+        Ok(LayerSpec { name, objects })
+    }
+
+    /// Layer as a JSON object (see `from_json`)
+    pub fn to_json(&self) -> Json {
+        let objects = self
+            .objects
+            .iter()
+            .map(|object| match object {
+                LayerObject::Index(index) => Json::Number(*index as f64),
+                LayerObject::Name(name) => Json::String(name.clone()),
+            })
+            .collect();
+        Json::Object(vec![
+            ("name".to_string(), Json::String(self.name.clone())),
+            ("objects".to_string(), Json::Array(objects)),
+        ])
+    }
+
+    /// The layer with its objects found in a scene
+    ///
+    /// ### Returns
+    ///
+    /// `Result<RenderLayer, ParameterError>` - The layer, or the error naming the first
+    /// object that isn't in the scene
+    ///
+    pub fn resolve(&self, scene: &Scene) -> Result<RenderLayer, ParameterError> {
+        let objects = self
+            .objects
+            .iter()
+            .map(|object| match object {
+                LayerObject::Index(index) if *index < scene.spheres.len() => Ok(*index),
+                LayerObject::Index(index) => Err(ParameterError::new(&format!(
+                    "layer '{}': the scene has no object {}, it has {} objects",
+                    self.name,
+                    index,
+                    scene.spheres.len()
+                ))),
+                LayerObject::Name(name) => scene
+                    .spheres
+                    .iter()
+                    .position(|sphere| sphere.name.as_deref() == Some(name.as_str()))
+                    .ok_or_else(|| {
+                        ParameterError::new(&format!(
+                            "layer '{}': the scene has no object named '{}'",
+                            self.name, name
+                        ))
+                    }),
+            })
+            .collect::<Result<Vec<usize>, ParameterError>>()?;
+        Ok(RenderLayer::new(&self.name, objects))
+    }
+}
+
+/// Render every layer into a separate image in a single pass over the pixels
+///
+/// Objects outside of a layer act as holdouts for it: they still cast shadows and show up
+/// in reflections, but the pixels where they are directly visible stay black. Pixels where
+/// no object is visible are black in every layer, so the layers can be added together on
+/// top of the background to rebuild the full image
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings (image size, field of view, bounces and background)
+/// * `scene` - The scene
+/// * `layers` - The layers to render
+/// * `pipeline` - The color pipeline used to encode the images
+///
+/// ### Returns
+///
+/// `Vec<Image>` - One image per layer, in the order of `layers`
+///
+pub fn render_layers(
+    settings: &RenderSettings,
+    scene: &Scene,
+    layers: &[RenderLayer],
+    pipeline: &ColorPipeline,
+) -> Vec<Image> {
+    let (width, height) = (settings.width, settings.height);
+    let mut images: Vec<Image> = layers.iter().map(|_| Image::new(width, height)).collect();
+    for j in 0..height {
+        for i in 0..width {
+            let ray = settings.camera_ray(i as f64 + 0.5, j as f64 + 0.5);
+            let Some((index, _)) = nearest_sphere(&ray, scene) else {
+                continue;
+            };
+            // the pixel is shaded once and written to every layer owning the visible object
+            let color = pipeline.to_rgb8(cast_ray(&ray, scene, settings, 0));
+            for (layer, image) in layers.iter().zip(images.iter_mut()) {
+                if layer.objects.contains(&index) {
+                    image.set_pixel(i, j, color);
+                }
+            }
+        }
+    }
+    images
+}
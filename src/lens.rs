@@ -0,0 +1,712 @@
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::sampling::concentric_disk;
+use crate::settings::json_to_f64;
+
+use nalgebra::Vector3;
+
+/// Model of the lens of a camera: where the rays through a point of the image leave the
+/// camera and in which direction
+///
+/// Lenses work in the frame of the camera, at the origin and looking along the negative Z
+/// axis with +Y up; `camera::Camera` places their rays in the scene
+pub trait Lens: std::fmt::Debug + Send + Sync {
+    /// Ray leaving the lens for a point of the image and a point of the aperture
+    ///
+    /// ### Arguments
+    ///
+    /// * `direction` - The normalized direction of the pinhole ray through the point of
+    ///   the image
+    /// * `image` - The point of the image, +X to the right and +Y up, the top and bottom
+    ///   edges at 1 and -1 (the left and right ones at the aspect ratio)
+    /// * `aperture` - The point of the aperture, both coordinates in the range `[0, 1)`,
+    ///   `(0.5, 0.5)` being its center
+    ///
+    /// ### Returns
+    ///
+    /// `Option<(Vector3<f64>, Vector3<f64>)>` - The origin of the ray and its normalized
+    /// direction, `None` when the ray is blocked inside the lens (vignetting)
+    ///
+    fn ray(
+        &self,
+        direction: Vector3<f64>,
+        image: (f64, f64),
+        aperture: (f64, f64),
+    ) -> Option<(Vector3<f64>, Vector3<f64>)>;
+
+    /// Whether the rays depend on the point of the aperture, so every camera sample needs
+    /// one
+    fn has_aperture(&self) -> bool;
+}
+
+/// Ideal pinhole: every ray leaves from the origin, everything is in focus
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pinhole;
+
+impl Lens for Pinhole {
+    fn ray(
+        &self,
+        direction: Vector3<f64>,
+        _image: (f64, f64),
+        _aperture: (f64, f64),
+    ) -> Option<(Vector3<f64>, Vector3<f64>)> {
+        Some((Vector3::default(), direction))
+    }
+
+    fn has_aperture(&self) -> bool {
+        false
+    }
+}
+
+/// Thin lens with a round aperture: the points at the focus distance are sharp, the others
+/// blur into disks growing with the aperture (depth of field)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThinLens {
+    /// Radius of the aperture in scene units, 0 for a pinhole
+    pub aperture: f64,
+    /// Distance of the plane in focus along the view axis
+    pub focus_distance: f64,
+}
+
+impl Lens for ThinLens {
+    fn ray(
+        &self,
+        direction: Vector3<f64>,
+        _image: (f64, f64),
+        aperture: (f64, f64),
+    ) -> Option<(Vector3<f64>, Vector3<f64>)> {
+        Some(thin_lens_ray(
+            direction,
+            self.aperture,
+            self.focus_distance,
+            aperture,
+        ))
+    }
+
+    fn has_aperture(&self) -> bool {
+        self.aperture > 0.0
+    }
+}
+
+/// Split diopter: a thin lens half covered by a close-up lens, so each side of a line
+/// across the image is in focus at its own distance, e.g. a face close to the camera on
+/// one side and a person far away on the other
+///
+/// The focus goes from one distance to the other over a band of width `blend` along the
+/// line, interpolated in diopters (the inverse of the distance) as the power of the lens
+/// changes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SplitDiopter {
+    /// Radius of the aperture in scene units
+    pub aperture: f64,
+    /// Focus distance on the side of the close-up lens, left of the line when it's
+    /// vertical
+    pub near: f64,
+    /// Focus distance on the other side
+    pub far: f64,
+    /// Angle of the line from the vertical in radians, counterclockwise
+    pub angle: f64,
+    /// Offset of the line from the center of the image along its normal, in image heights
+    /// over 2 (the units of `Lens::ray`)
+    pub offset: f64,
+    /// Width of the band the focus changes over, in the same units
+    pub blend: f64,
+}
+
+impl SplitDiopter {
+    /// Split diopter with a vertical line through the center of the image and a narrow
+    /// transition
+    pub fn new(aperture: f64, near: f64, far: f64) -> SplitDiopter {
+        SplitDiopter {
+            aperture,
+            near,
+            far,
+            angle: 0.0,
+            offset: 0.0,
+            blend: 0.05,
+        }
+    }
+
+    pub fn with_angle(mut self, angle: f64) -> SplitDiopter {
+        self.angle = angle;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: f64) -> SplitDiopter {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: f64) -> SplitDiopter {
+        self.blend = blend;
+        self
+    }
+
+    /// Focus distance at a point of the image
+    pub fn focus_distance(&self, (x, y): (f64, f64)) -> f64 {
+        // signed distance to the line, negative on the side of the close-up lens
+        let (sin, cos) = self.angle.sin_cos();
+        let side = x * cos + y * sin - self.offset;
+        let t = if self.blend > 0.0 {
+            (side / self.blend + 0.5).clamp(0.0, 1.0)
+        } else if side < 0.0 {
+            0.0
+        } else {
+            1.0
+        };
+        let t = t * t * (3.0 - 2.0 * t);
+        1.0 / (1.0 / self.near + (1.0 / self.far - 1.0 / self.near) * t)
+    }
+}
+
+impl Lens for SplitDiopter {
+    fn ray(
+        &self,
+        direction: Vector3<f64>,
+        image: (f64, f64),
+        aperture: (f64, f64),
+    ) -> Option<(Vector3<f64>, Vector3<f64>)> {
+        let focus_distance = self.focus_distance(image);
+        Some(thin_lens_ray(
+            direction,
+            self.aperture,
+            focus_distance,
+            aperture,
+        ))
+    }
+
+    fn has_aperture(&self) -> bool {
+        self.aperture > 0.0
+    }
+}
+
+/// Ray of a thin lens from a point of its aperture through the point of the pinhole ray
+/// at the focus distance
+fn thin_lens_ray(
+    direction: Vector3<f64>,
+    radius: f64,
+    focus_distance: f64,
+    (u, v): (f64, f64),
+) -> (Vector3<f64>, Vector3<f64>) {
+    let (x, y) = concentric_disk(u, v);
+    let origin = Vector3::new(x * radius, y * radius, 0.0);
+    let focus = direction * (focus_distance / -direction.z);
+    (origin, (focus - origin).normalize())
+}
+
+/// Prescription of a double Gauss lens of 50 mm focal length at f/2 (US patent 2,673,491,
+/// scaled from 100 mm), as `[curvature radius, thickness, ior, aperture diameter]` rows in
+/// millimeters from the front of the lens
+pub const DOUBLE_GAUSS_50MM: [[f64; 4]; 11] = [
+    [29.475, 3.76, 1.67, 25.2],
+    [84.83, 0.12, 1.0, 25.2],
+    [19.275, 4.025, 1.67, 23.0],
+    [40.77, 3.275, 1.699, 23.0],
+    [12.75, 5.705, 1.0, 18.0],
+    [0.0, 4.5, 1.0, 17.1],
+    [-14.495, 1.18, 1.603, 17.0],
+    [40.77, 6.065, 1.658, 20.0],
+    [-20.385, 0.19, 1.0, 20.0],
+    [437.065, 3.22, 1.717, 20.0],
+    [-39.73, 0.0, 1.0, 20.0],
+];
+
+/// Spherical surface of a lens prescription
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LensElement {
+    /// Radius of curvature, positive when the center of the sphere is behind the surface
+    /// (towards the film), 0 for the aperture stop, which is flat
+    pub curvature_radius: f64,
+    /// Distance along the axis to the next surface, to the film for the last one
+    pub thickness: f64,
+    /// Index of refraction of the glass behind the surface, 1 for air
+    pub ior: f64,
+    /// Radius of the opening of the surface, the rays passing farther from the axis are
+    /// blocked
+    pub aperture_radius: f64,
+}
+
+/// Physically based lens: the rays are refracted through a sequence of spherical glass
+/// surfaces, giving the distortion, the vignetting and the bokeh of a real lens
+///
+/// The camera sits at the center of the film, the lens in front of it. The image covers
+/// the height of the film, so the lens sets the field of view rather than the render
+/// settings; the aperture is sampled over the exit pupil seen from the center of the film,
+/// so the edges of the image darken a little more than with a real lens. The lens is
+/// focused by moving it away from the film. `Camera::project` still follows the pinhole
+/// camera, so what is drawn from projected points (lens flares) only roughly lines up
+#[derive(Clone, Debug, PartialEq)]
+pub struct RealisticLens {
+    /// Surfaces from the front of the lens (the side of the scene) to the back, in scene
+    /// units, the thickness of the last one being the distance to the film
+    pub elements: Vec<LensElement>,
+    /// Height of the film in scene units
+    pub film_height: f64,
+    /// Scene units per millimeter of the prescription
+    pub scale: f64,
+    /// Distance from the film to the plane in focus, in scene units
+    pub focus_distance: f64,
+    /// Fraction of the radius of the aperture stop left open, 1 for a wide open lens
+    pub stop: f64,
+    /// Radius of the exit pupil seen from the center of the film
+    pupil_radius: f64,
+}
+
+impl RealisticLens {
+    /// Lens of a prescription in millimeters, focused at a distance
+    ///
+    /// ### Arguments
+    ///
+    /// * `prescription` - The surfaces as `[curvature radius, thickness, ior, aperture
+    ///   diameter]` rows from the front of the lens (see `LensElement`), an ior of 0 being
+    ///   air
+    /// * `film_height` - The height of the film in millimeters (24 for a full frame)
+    /// * `scale` - The scene units per millimeter (0.001 for a scene in meters)
+    /// * `focus_distance` - The distance from the film to the plane in focus, in scene units
+    ///
+    pub fn new(
+        prescription: &[[f64; 4]],
+        film_height: f64,
+        scale: f64,
+        focus_distance: f64,
+    ) -> RealisticLens {
+        let elements = prescription
+            .iter()
+            .map(|[radius, thickness, ior, diameter]| LensElement {
+                curvature_radius: radius * scale,
+                thickness: thickness * scale,
+                ior: if *ior == 0.0 { 1.0 } else { *ior },
+                aperture_radius: diameter * scale / 2.0,
+            })
+            .collect();
+        let mut lens = RealisticLens {
+            elements,
+            film_height: film_height * scale,
+            scale,
+            focus_distance,
+            stop: 1.0,
+            pupil_radius: 0.0,
+        };
+        lens.focus();
+        lens
+    }
+
+    /// The 50 mm double Gauss lens of `DOUBLE_GAUSS_50MM` on a full frame film
+    pub fn double_gauss_50mm(scale: f64, focus_distance: f64) -> RealisticLens {
+        RealisticLens::new(&DOUBLE_GAUSS_50MM, 24.0, scale, focus_distance)
+    }
+
+    pub fn with_stop(mut self, stop: f64) -> RealisticLens {
+        self.stop = stop;
+        self.focus();
+        self
+    }
+
+    /// Field of view of the lens along the height of the film, in radians, from the chief
+    /// ray through the edge of the film
+    pub fn fov(&self) -> Option<f64> {
+        let (_, direction) = self.trace_from_film(
+            Vector3::new(0.0, -self.film_height / 2.0, 0.0),
+            Vector3::new(0.0, 0.0, self.rear_z()),
+        )?;
+        Some(2.0 * direction.y.atan2(-direction.z))
+    }
+
+    /// Position along the axis of the first surface of each element, the film being at 0
+    /// and the scene towards negative Z
+    fn vertices(&self) -> Vec<f64> {
+        let mut z = 0.0;
+        let mut vertices = vec![0.0; self.elements.len()];
+        for (vertex, element) in vertices.iter_mut().zip(&self.elements).rev() {
+            z -= element.thickness;
+            *vertex = z;
+        }
+        vertices
+    }
+
+    fn rear_z(&self) -> f64 {
+        self.vertices().last().copied().unwrap_or(0.0)
+    }
+
+    /// Opening of an element, the aperture stop narrowed by `stop`
+    fn opening(&self, element: &LensElement) -> f64 {
+        if element.curvature_radius == 0.0 {
+            element.aperture_radius * self.stop
+        } else {
+            element.aperture_radius
+        }
+    }
+
+    /// Ray through one surface: the point it crosses the surface at and its direction on
+    /// the other side, `None` if it misses the opening or is totally reflected
+    fn refract(
+        &self,
+        (origin, direction): (Vector3<f64>, Vector3<f64>),
+        element: &LensElement,
+        vertex: f64,
+        ior_ratio: f64,
+    ) -> Option<(Vector3<f64>, Vector3<f64>)> {
+        let radius = element.curvature_radius;
+        let (point, normal) = if radius == 0.0 {
+            let t = (vertex - origin.z) / direction.z;
+            (origin + direction * t, None)
+        } else {
+            let center = Vector3::new(0.0, 0.0, vertex + radius);
+            let offset = origin - center;
+            let b = offset.dot(&direction);
+            let c = offset.norm_squared() - radius * radius;
+            let discriminant = b * b - c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            // the surface is the cap of the sphere around the axis: the nearer hit for a
+            // ray going through the sphere towards its center, the farther one otherwise
+            let nearer = (direction.z > 0.0) != (radius < 0.0);
+            let t = if nearer {
+                -b - discriminant.sqrt()
+            } else {
+                -b + discriminant.sqrt()
+            };
+            if t < 0.0 {
+                return None;
+            }
+            let point = origin + direction * t;
+            let normal = (point - center).normalize();
+            let normal = if normal.dot(&direction) > 0.0 {
+                -normal
+            } else {
+                normal
+            };
+            (point, Some(normal))
+        };
+        if point.x * point.x + point.y * point.y > self.opening(element).powi(2) {
+            return None;
+        }
+        let Some(normal) = normal else {
+            return Some((point, direction));
+        };
+        let cos_i = -normal.dot(&direction);
+        let sin2_t = ior_ratio * ior_ratio * (1.0 - cos_i * cos_i).max(0.0);
+        if sin2_t >= 1.0 {
+            return None;
+        }
+        let cos_t = (1.0 - sin2_t).sqrt();
+        let refracted = direction * ior_ratio + normal * (ior_ratio * cos_i - cos_t);
+        Some((point, refracted.normalize()))
+    }
+
+    /// Ray from a point of the film towards a point behind the lens, traced through the
+    /// surfaces out of the front of the lens
+    fn trace_from_film(
+        &self,
+        film: Vector3<f64>,
+        target: Vector3<f64>,
+    ) -> Option<(Vector3<f64>, Vector3<f64>)> {
+        let vertices = self.vertices();
+        let mut ray = (film, (target - film).normalize());
+        for (index, element) in self.elements.iter().enumerate().rev() {
+            // from the glass behind the surface into the glass in front of it
+            let before = index.checked_sub(1).map_or(1.0, |i| self.elements[i].ior);
+            ray = self.refract(ray, element, vertices[index], element.ior / before)?;
+        }
+        Some(ray)
+    }
+
+    /// Ray from a point of the scene traced through the surfaces out of the back of the
+    /// lens
+    fn trace_from_scene(
+        &self,
+        origin: Vector3<f64>,
+        direction: Vector3<f64>,
+    ) -> Option<(Vector3<f64>, Vector3<f64>)> {
+        let vertices = self.vertices();
+        let mut ray = (origin, direction.normalize());
+        for (index, element) in self.elements.iter().enumerate() {
+            let before = index.checked_sub(1).map_or(1.0, |i| self.elements[i].ior);
+            ray = self.refract(ray, element, vertices[index], before / element.ior)?;
+        }
+        Some(ray)
+    }
+
+    /// Move the lens so the plane at the focus distance is sharp on the film, and measure
+    /// the exit pupil
+    fn focus(&mut self) {
+        let Some(last) = self.elements.len().checked_sub(1) else {
+            return;
+        };
+        // the image of a point on the axis is where a ray from it crosses the axis again
+        // behind the lens; the distance to the plane in focus is measured from the film, so
+        // moving the film onto the image moves the point a little, a few steps settle it
+        let height = 0.1 * self.elements[0].aperture_radius;
+        for _ in 0..100 {
+            let front = self.vertices()[0];
+            let object = Vector3::new(0.0, 0.0, -self.focus_distance);
+            let target = Vector3::new(height, 0.0, front);
+            let Some((origin, direction)) = self.trace_from_scene(object, target - object) else {
+                return;
+            };
+            if direction.x >= 0.0 {
+                // the lens doesn't bring the rays back to the axis
+                return;
+            }
+            // the film is at 0, the image behind it when positive
+            let image = origin.z - origin.x / direction.x * direction.z;
+            self.elements[last].thickness = (self.elements[last].thickness + image).max(0.0);
+            if image.abs() < 1e-9 * self.elements[last].thickness {
+                break;
+            }
+        }
+        // widest ray from the center of the film that still goes through the lens
+        let rear = self.rear_z();
+        let passes = |radius: f64| {
+            self.trace_from_film(Vector3::default(), Vector3::new(radius, 0.0, rear))
+                .is_some()
+        };
+        let (mut low, mut high) = (0.0, self.elements[last].aperture_radius);
+        for _ in 0..32 {
+            let middle = (low + high) / 2.0;
+            if passes(middle) {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        self.pupil_radius = low;
+    }
+}
+
+impl Lens for RealisticLens {
+    fn ray(
+        &self,
+        _direction: Vector3<f64>,
+        (x, y): (f64, f64),
+        (u, v): (f64, f64),
+    ) -> Option<(Vector3<f64>, Vector3<f64>)> {
+        // the lens turns the image upside down on the film
+        let film = Vector3::new(-x, -y, 0.0) * (self.film_height / 2.0);
+        let (px, py) = concentric_disk(u, v);
+        let target =
+            Vector3::new(px, py, 0.0) * self.pupil_radius + Vector3::new(0.0, 0.0, self.rear_z());
+        self.trace_from_film(film, target)
+    }
+
+    fn has_aperture(&self) -> bool {
+        true
+    }
+}
+
+/// Realistic lens of a JSON object (see `LensKind::from_json`)
+fn realistic_from_json(entries: &[(String, Json)]) -> Result<RealisticLens, JsonError> {
+    let mut prescription = None;
+    let (mut film_height, mut scale, mut focus_distance, mut stop) = (24.0, 0.001, 10.0, 1.0);
+    for (key, value) in entries {
+        match key.as_str() {
+            "type" => {}
+            "preset" => match value.as_str() {
+                Some("double_gauss_50mm") => prescription = Some(DOUBLE_GAUSS_50MM.to_vec()),
+                _ => return Err(JsonError::new("'preset' must be double_gauss_50mm")),
+            },
+            "elements" => {
+                let rows = value.as_array().ok_or_else(|| {
+                    JsonError::new(
+                        "'elements' must be an array of [radius, thickness, ior, aperture] rows",
+                    )
+                })?;
+                let mut elements = Vec::with_capacity(rows.len());
+                for row in rows {
+                    match row.as_array() {
+                        Some([radius, thickness, ior, aperture]) => elements.push([
+                            json_to_f64(key, radius)?,
+                            json_to_f64(key, thickness)?,
+                            json_to_f64(key, ior)?,
+                            json_to_f64(key, aperture)?,
+                        ]),
+                        _ => {
+                            return Err(JsonError::new(
+                                "a lens element must be [radius, thickness, ior, aperture]",
+                            ))
+                        }
+                    }
+                }
+                prescription = Some(elements);
+            }
+            "film_height" => film_height = json_to_f64(key, value)?,
+            "scale" => scale = json_to_f64(key, value)?,
+            "focus_distance" => focus_distance = json_to_f64(key, value)?,
+            "stop" => stop = json_to_f64(key, value)?,
+            _ => {
+                return Err(JsonError::new(&format!(
+                    "unknown setting '{}' of the realistic lens",
+                    key
+                )))
+            }
+        }
+    }
+    let prescription = prescription
+        .filter(|elements| !elements.is_empty())
+        .ok_or_else(|| JsonError::new("a realistic lens needs 'elements' or a 'preset'"))?;
+    if film_height <= 0.0 || scale <= 0.0 || focus_distance <= 0.0 {
+        return Err(JsonError::new(
+            "'film_height', 'scale' and 'focus_distance' must be positive",
+        ));
+    }
+    if !(stop > 0.0 && stop <= 1.0) {
+        return Err(JsonError::new("'stop' must be between 0 and 1"));
+    }
+    Ok(RealisticLens::new(&prescription, film_height, scale, focus_distance).with_stop(stop))
+}
+
+/// Lens of a camera
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum LensKind {
+    #[default]
+    Pinhole,
+    Thin(ThinLens),
+    SplitDiopter(SplitDiopter),
+    Realistic(RealisticLens),
+}
+
+impl LensKind {
+    pub fn lens(&self) -> &dyn Lens {
+        match self {
+            LensKind::Pinhole => &Pinhole,
+            LensKind::Thin(lens) => lens,
+            LensKind::SplitDiopter(lens) => lens,
+            LensKind::Realistic(lens) => lens,
+        }
+    }
+
+    /// Name of the lens: `pinhole`, `thin`, `split_diopter` or `realistic`
+    pub fn name(&self) -> &'static str {
+        match self {
+            LensKind::Pinhole => "pinhole",
+            LensKind::Thin(_) => "thin",
+            LensKind::SplitDiopter(_) => "split_diopter",
+            LensKind::Realistic(_) => "realistic",
+        }
+    }
+
+    /// Lens as a JSON object: its `type` and its parameters, the angles in degrees
+    pub fn to_json(&self) -> Json {
+        let mut entries = vec![("type".to_string(), Json::String(self.name().to_string()))];
+        let mut number =
+            |key: &str, value: f64| entries.push((key.to_string(), Json::Number(value)));
+        match self {
+            LensKind::Pinhole => {}
+            LensKind::Thin(lens) => {
+                number("aperture", lens.aperture);
+                number("focus_distance", lens.focus_distance);
+            }
+            LensKind::SplitDiopter(lens) => {
+                number("aperture", lens.aperture);
+                number("near", lens.near);
+                number("far", lens.far);
+                number("angle", lens.angle.to_degrees());
+                number("offset", lens.offset);
+                number("blend", lens.blend);
+            }
+            LensKind::Realistic(lens) => {
+                number("film_height", lens.film_height / lens.scale);
+                number("scale", lens.scale);
+                number("focus_distance", lens.focus_distance);
+                number("stop", lens.stop);
+            }
+        }
+        if let LensKind::Realistic(lens) = self {
+            // the prescription in millimeters, the thickness of the last surface being the
+            // distance to the film the lens was focused at
+            let elements = lens
+                .elements
+                .iter()
+                .map(|element| {
+                    let row = [
+                        element.curvature_radius,
+                        element.thickness,
+                        element.ior,
+                        element.aperture_radius * 2.0,
+                    ];
+                    Json::Array(row.map(|value| Json::Number(value / lens.scale)).to_vec())
+                })
+                .collect();
+            entries.push(("elements".to_string(), Json::Array(elements)));
+        }
+        Json::Object(entries)
+    }
+
+    /// Read a lens from a JSON object (see `to_json`)
+    ///
+    /// * `pinhole` - no parameters
+    /// * `thin` - the radius of the `aperture` and the `focus_distance`
+    /// * `split_diopter` - the radius of the `aperture`, the `near` and `far` focus
+    ///   distances, the `angle` of the split line from the vertical in degrees, its
+    ///   `offset` from the center and the width of the `blend` between the two sides (see
+    ///   `SplitDiopter`)
+    /// * `realistic` - the `elements` of the prescription as `[curvature radius,
+    ///   thickness, ior, aperture diameter]` rows in millimeters from the front of the lens
+    ///   (see `LensElement`) or a `preset` (`double_gauss_50mm`), the `film_height` in
+    ///   millimeters (24 by default), the `scale` of a millimeter in scene units (0.001 by
+    ///   default), the `focus_distance` from the film in scene units (10 by default) and the `stop`, the
+    ///   fraction of the aperture stop left open (1 by default)
+    pub fn from_json(json: &Json) -> Result<LensKind, JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("'lens' must be a JSON object"));
+        };
+        let name = json
+            .get("type")
+            .and_then(Json::as_str)
+            .ok_or_else(|| JsonError::new("'lens' must have a 'type' string"))?;
+        if name == "realistic" {
+            return realistic_from_json(entries).map(LensKind::Realistic);
+        }
+        let mut lens = match name {
+            "pinhole" => LensKind::Pinhole,
+            "thin" => LensKind::Thin(ThinLens {
+                aperture: 0.0,
+                focus_distance: 1.0,
+            }),
+            "split_diopter" => LensKind::SplitDiopter(SplitDiopter::new(0.0, 1.0, 10.0)),
+            _ => {
+                return Err(JsonError::new(
+                    "'type' must be pinhole, thin, split_diopter or realistic",
+                ))
+            }
+        };
+        for (key, value) in entries {
+            match (key.as_str(), &mut lens) {
+                ("type", _) => {}
+                ("aperture", LensKind::Thin(lens)) => lens.aperture = json_to_f64(key, value)?,
+                ("focus_distance", LensKind::Thin(lens)) => {
+                    lens.focus_distance = json_to_f64(key, value)?
+                }
+                ("aperture", LensKind::SplitDiopter(lens)) => {
+                    lens.aperture = json_to_f64(key, value)?
+                }
+                ("near", LensKind::SplitDiopter(lens)) => lens.near = json_to_f64(key, value)?,
+                ("far", LensKind::SplitDiopter(lens)) => lens.far = json_to_f64(key, value)?,
+                ("angle", LensKind::SplitDiopter(lens)) => {
+                    lens.angle = json_to_f64(key, value)?.to_radians()
+                }
+                ("offset", LensKind::SplitDiopter(lens)) => lens.offset = json_to_f64(key, value)?,
+                ("blend", LensKind::SplitDiopter(lens)) => lens.blend = json_to_f64(key, value)?,
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown setting '{}' of the {} lens",
+                        key, name
+                    )))
+                }
+            }
+        }
+        let (aperture, distances) = match &lens {
+            LensKind::Pinhole | LensKind::Realistic(_) => (0.0, vec![]),
+            LensKind::Thin(lens) => (lens.aperture, vec![lens.focus_distance]),
+            LensKind::SplitDiopter(lens) => (lens.aperture, vec![lens.near, lens.far]),
+        };
+        if aperture < 0.0 {
+            return Err(JsonError::new("'aperture' can't be negative"));
+        }
+        if distances.iter().any(|distance| *distance <= 0.0) {
+            return Err(JsonError::new("focus distances must be positive"));
+        }
+        Ok(lens)
+    }
+}
@@ -0,0 +1,79 @@
+pub mod animation;
+pub mod autosave;
+pub mod bake;
+pub mod batch;
+pub mod bvh;
+pub mod camera;
+pub mod caption;
+pub mod codegen;
+pub mod color;
+pub mod colorspace;
+pub mod compare;
+pub mod completions;
+pub mod config;
+pub mod control;
+pub mod convergence;
+pub mod debug;
+pub mod deep;
+pub mod depth;
+pub mod envmap;
+pub mod exit;
+pub mod film;
+pub mod flare;
+pub mod framebuffer;
+pub mod furnace;
+pub mod gbuffer;
+pub mod half;
+pub mod i18n;
+pub mod image;
+pub mod info;
+pub mod integrator;
+pub mod interlace;
+pub mod json;
+pub mod label;
+pub mod layers;
+pub mod lens;
+pub mod light;
+pub mod lightgroups;
+pub mod look;
+pub mod lpe;
+pub mod matte;
+pub mod medium;
+pub mod motion;
+pub mod object;
+pub mod outputs;
+pub mod pack;
+pub mod parameter;
+pub mod passes;
+pub mod pathtemplate;
+pub mod pixelfilter;
+pub mod pixelformat;
+pub mod pixelorder;
+pub mod preview;
+pub mod probe;
+pub mod procedural;
+pub mod proxy;
+pub mod random;
+pub mod ray;
+pub mod reconstruction;
+pub mod render;
+pub mod renderer;
+pub mod resample;
+pub mod sampler;
+pub mod sampling;
+pub mod scatter;
+pub mod scene;
+#[cfg(feature = "bench-scenes")]
+pub mod scenes;
+pub mod selftest;
+pub mod sequence;
+pub mod serve;
+pub mod settings;
+pub mod sink;
+pub mod sky;
+pub mod sweep;
+pub mod temporal;
+pub mod texture;
+pub mod texturenodes;
+pub mod validate;
+pub mod volume;
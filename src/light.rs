@@ -0,0 +1,199 @@
+use crate::color::Color;
+use crate::envmap::Environment;
+use crate::object::SceneLight;
+use crate::ray::Ray;
+use crate::render::are_occluded;
+use crate::sampling::uniform_cone;
+use crate::sampling::uniform_cone_pdf;
+use crate::sampling::uniform_sphere;
+use crate::sampling::uniform_sphere_pdf;
+use crate::sampling::Onb;
+use crate::scene::Scene;
+use crate::texture::TextureFormat;
+
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+
+/// Light arriving at a point from a sample of a light (see `Light::sample_li`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightSample {
+    /// Direction from the point towards the sample
+    pub direction: Vector3<f64>,
+    /// Distance to the sample along `direction`, infinite for lights at infinity
+    pub distance: f64,
+    /// Light arriving from the sample, in the scale of the light intensities: the surfaces
+    /// don't divide it by pi (see `render::direct_lighting`)
+    pub radiance: Color,
+    /// Solid angle density of `direction`, 1 for the delta lights
+    pub pdf: f64,
+}
+
+/// Source of light shading the surfaces, sampled from the shaded points
+///
+/// Delta lights (points, spots, distant sources and the soft shadow spheres of point lights)
+/// can't be reached by the rays, they are only seen through their samples; the other
+/// lights (the environment) are also seen by the rays leaving the scene, the two estimates
+/// are combined with multiple importance sampling using the densities of the samples
+pub trait Light: Sync {
+    /// Sample the light arriving at a point
+    ///
+    /// ### Arguments
+    ///
+    /// * `point` - The point receiving the light
+    /// * `u` - Sample coordinates in the range `[0, 1)`
+    ///
+    /// ### Returns
+    ///
+    /// `Option<LightSample>` - The light arriving from the sample, `None` when the light
+    /// doesn't reach the point
+    ///
+    fn sample_li(&self, point: Vector3<f64>, u: (f64, f64)) -> Option<LightSample>;
+
+    /// Solid angle density `sample_li` samples a direction with from a point, 0 for the
+    /// delta lights
+    fn pdf_li(&self, _point: Vector3<f64>, _direction: Vector3<f64>) -> f64 {
+        0.0
+    }
+
+    /// Whether the light can only be reached through its samples
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    /// Number of samples of the light taken for every shaded point
+    fn samples(&self) -> u32 {
+        1
+    }
+}
+
+/// Point, spot and directional lights, the radius of point and spot lights only softening
+/// their shadows: they are sampled on their sphere, emitting their full intensity from every
+/// sample (see `SceneLight::sample_direction`)
+impl Light for SceneLight {
+    fn sample_li(&self, point: Vector3<f64>, (u, v): (f64, f64)) -> Option<LightSample> {
+        let attenuation = self.attenuation(point);
+        // a point exactly at the light position has no direction towards it
+        if self.direction_from(point).1 <= 0.0 || attenuation <= 0.0 {
+            return None;
+        }
+        let (direction, distance) = self.sample_direction(point, u, v);
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.radiance() * attenuation,
+            pdf: 1.0,
+        })
+    }
+
+    fn samples(&self) -> u32 {
+        self.shadow_samples.max(1)
+    }
+}
+
+/// Fraction of the samples of an environment with a sun taken in the sun disk
+const SUN_SAMPLE_FRACTION: f64 = 0.5;
+
+/// Environment map lighting the scene from every direction, sampled uniformly over the
+/// sphere of directions
+///
+/// The sun disk of a sky dome is too small to be found by uniform samples, half of the
+/// samples are taken inside it
+pub struct EnvironmentLight<'a> {
+    pub environment: &'a Environment,
+    /// Memory budget of the texture cache (see `settings::RenderSettings::texture_budget`)
+    pub budget: usize,
+    /// How the map is loaded (see `settings::RenderSettings::texture_format`)
+    pub format: TextureFormat,
+}
+
+impl<'a> EnvironmentLight<'a> {
+    pub fn new(
+        environment: &'a Environment,
+        budget: usize,
+        format: TextureFormat,
+    ) -> EnvironmentLight<'a> {
+        EnvironmentLight {
+            environment,
+            budget,
+            format,
+        }
+    }
+}
+
+impl Light for EnvironmentLight<'_> {
+    fn sample_li(&self, point: Vector3<f64>, (u, v): (f64, f64)) -> Option<LightSample> {
+        let direction = match self.environment.sun() {
+            Some((sun, cos_radius)) if u < SUN_SAMPLE_FRACTION => {
+                let local = uniform_cone(u / SUN_SAMPLE_FRACTION, v, cos_radius);
+                Onb::from_w(sun).to_world(local)
+            }
+            Some(_) => {
+                let u = (u - SUN_SAMPLE_FRACTION) / (1.0 - SUN_SAMPLE_FRACTION);
+                uniform_sphere(u, v)
+            }
+            None => uniform_sphere(u, v),
+        };
+        let ray = Ray::new(point, direction);
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            // a surface under a uniform sky reflects the radiance of the sky times its albedo
+            radiance: self.environment.radiance(&ray, self.budget, self.format) / PI,
+            pdf: self.pdf_li(point, direction),
+        })
+    }
+
+    fn pdf_li(&self, _point: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        let Some((sun, cos_radius)) = self.environment.sun() else {
+            return uniform_sphere_pdf();
+        };
+        let in_sun = if direction.dot(&sun) >= cos_radius {
+            uniform_cone_pdf(cos_radius)
+        } else {
+            0.0
+        };
+        SUN_SAMPLE_FRACTION * in_sun + (1.0 - SUN_SAMPLE_FRACTION) * uniform_sphere_pdf()
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+/// Drop the samples of lights reaching a surface point whose shadow ray an object blocks
+///
+/// The samples of all the lights are taken first and their shadow rays traced together
+/// (see `render::are_occluded`), rather than one shadow ray after every sample
+///
+/// ### Arguments
+///
+/// * `samples` - The samples, with whatever the caller weights them with (e.g. the light
+///   they come from), in the order they were taken
+/// * `point` - The shaded point
+/// * `n` - The surface normal at `point`, the shadow rays start off the surface
+/// * `scene` - The scene, its spheres are the occluders
+///
+pub fn retain_unoccluded<T>(
+    samples: &mut Vec<(T, LightSample)>,
+    point: Vector3<f64>,
+    n: Vector3<f64>,
+    scene: &Scene,
+) {
+    let shadow_rays: Vec<Ray> = samples
+        .iter()
+        .map(|(_, sample)| Ray::offset(point, n, sample.direction).with_t_max(sample.distance))
+        .collect();
+    let mut occluded = are_occluded(&shadow_rays, scene).into_iter();
+    samples.retain(|_| !occluded.next().unwrap_or(true));
+}
+
+/// Weight of a sample taken with the density `pdf` among the samples of two strategies,
+/// the other one sampling with the density `other_pdf` (Veach's power heuristic)
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,102 @@
+use crate::color::Color;
+use crate::object::SceneLight;
+use crate::render::camera_rays;
+use crate::render::cast_ray;
+use crate::render::sanitize_radiance;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+/// Name of the extra pass holding the background seen by the camera, directly or through
+/// reflections and refractions
+pub const BACKGROUND_PASS: &str = "background";
+
+/// Named set of lights whose contribution to the image is rendered into its own pass
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightGroup {
+    pub name: String,
+    /// Indices of the lights belonging to the group
+    pub lights: Vec<usize>,
+}
+
+impl LightGroup {
+    pub fn new(name: &str, lights: Vec<usize>) -> LightGroup {
+        LightGroup {
+            name: name.to_string(),
+            lights,
+        }
+    }
+
+    /// One group per light, named after the light (or `light<index>` for unnamed lights)
+    pub fn per_light(lights: &[SceneLight]) -> Vec<LightGroup> {
+        lights
+            .iter()
+            .enumerate()
+            .map(|(index, light)| {
+                let name = match &light.name {
+                    Some(name) => name.clone(),
+                    None => format!("light{}", index),
+                };
+                LightGroup::new(&name, vec![index])
+            })
+            .collect()
+    }
+}
+
+/// Render the contribution of every light group to the image into a separate linear pass,
+/// for mixing the lights after the render
+///
+/// Shading is linear in the lights: the light reaching the camera through reflections and
+/// refractions is a sum of the direct lighting of every light. A group pass is shaded with
+/// the lights of the group only and a black background, and the background gets a pass of
+/// its own, so when every light belongs to exactly one group the passes add up to the
+/// full render
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings
+/// * `scene` - The scene
+/// * `groups` - The light groups
+///
+/// ### Returns
+///
+/// `Vec<Vec<Color>>` - The radiance of the pixels row by row from the top left corner, one
+/// pass per group in the order of `groups` followed by the `BACKGROUND_PASS`
+///
+pub fn render_light_groups(
+    settings: &RenderSettings,
+    scene: &Scene,
+    groups: &[LightGroup],
+) -> Vec<Vec<Color>> {
+    let dark = settings
+        .clone()
+        .with_background(Color::BLACK)
+        .with_environment(None);
+    let mut passes: Vec<(Scene, &RenderSettings)> = groups
+        .iter()
+        .map(|group| {
+            let group_lights = group
+                .lights
+                .iter()
+                .map(|&k| scene.lights[k].clone())
+                .collect();
+            (scene.clone().with_lights(group_lights), &dark)
+        })
+        .collect();
+    passes.push((scene.clone().with_lights(Vec::new()), settings));
+
+    let size = settings.width as usize * settings.height as usize;
+    let mut images = vec![Vec::with_capacity(size); passes.len()];
+    for j in 0..settings.height {
+        for i in 0..settings.width {
+            let rays = camera_rays(i, j, settings);
+            for ((pass_scene, pass_settings), image) in passes.iter().zip(images.iter_mut()) {
+                let mut color = Color::BLACK;
+                for ray in &rays {
+                    color += cast_ray(ray, pass_scene, pass_settings, 0);
+                }
+                image.push(sanitize_radiance(color / rays.len() as f64));
+            }
+        }
+    }
+    images
+}
@@ -0,0 +1,443 @@
+use crate::color::Color;
+use crate::image::Image;
+
+use std::path::Path;
+
+/// Error raised by a LUT or a response curve that can't be read
+#[derive(Clone, Debug, PartialEq)]
+pub struct LookError {
+    pub message: String,
+}
+
+impl LookError {
+    pub fn new(message: &str) -> LookError {
+        LookError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for LookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for LookError {}
+
+/// Layout of the entries of a LUT
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LutDimensions {
+    /// A curve per channel, each channel graded on its own
+    One,
+    /// Cube over the colors, the channels graded together
+    Three,
+}
+
+/// Lookup table of the `.cube` format, mapping display colors to graded ones, e.g. the look
+/// of a film stock exported from a grading tool
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut {
+    pub title: Option<String>,
+    pub dimensions: LutDimensions,
+    /// Number of entries of the curves, or along each side of the cube
+    pub size: usize,
+    /// Colors of the entries: the three curves side by side, or the cube with red changing
+    /// fastest, then green, then blue
+    pub table: Vec<Color>,
+    /// Input colors at the first and the last entries of the cube
+    pub domain_min: Color,
+    pub domain_max: Color,
+}
+
+impl Lut {
+    /// Load a `.cube` file
+    pub fn load(path: &Path) -> Result<Lut, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(Lut::parse_cube(&text)?)
+    }
+
+    /// Read a LUT from the text of a `.cube` file: the `LUT_1D_SIZE` of the curves or the
+    /// `LUT_3D_SIZE` of the cube, an optional `TITLE`, `DOMAIN_MIN` and `DOMAIN_MAX`, then
+    /// one `r g b` line per entry
+    ///
+    /// ### Arguments
+    ///
+    /// * `text` - The content of the file
+    ///
+    /// ### Returns
+    ///
+    /// `Result<Lut, LookError>` - The LUT, or an error naming the line it was found on
+    ///
+    pub fn parse_cube(text: &str) -> Result<Lut, LookError> {
+        let mut lut = Lut {
+            title: None,
+            dimensions: LutDimensions::Three,
+            size: 0,
+            table: Vec::new(),
+            domain_min: Color::BLACK,
+            domain_max: Color::WHITE,
+        };
+        for (index, line) in text.lines().enumerate() {
+            let at_line =
+                |message: &str| LookError::new(&format!("line {}: {}", index + 1, message));
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            match keyword {
+                "TITLE" => lut.title = Some(rest.trim_matches('"').to_string()),
+                "LUT_1D_SIZE" | "LUT_3D_SIZE" => {
+                    if lut.size != 0 {
+                        return Err(at_line("a LUT has a single size"));
+                    }
+                    let (dimensions, max) = match keyword {
+                        "LUT_1D_SIZE" => (LutDimensions::One, 65536),
+                        _ => (LutDimensions::Three, 256),
+                    };
+                    lut.dimensions = dimensions;
+                    lut.size = rest
+                        .parse()
+                        .ok()
+                        .filter(|size| (2..=max).contains(size))
+                        .ok_or_else(|| {
+                            at_line(&format!("{} must be between 2 and {}", keyword, max))
+                        })?
+                }
+                "DOMAIN_MIN" => lut.domain_min = parse_rgb(rest).map_err(|e| at_line(&e))?,
+                "DOMAIN_MAX" => lut.domain_max = parse_rgb(rest).map_err(|e| at_line(&e))?,
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    return Err(at_line(&format!("unknown keyword `{}`", keyword)))
+                }
+                _ => {
+                    if lut.size == 0 {
+                        return Err(at_line("the entries must come after the size"));
+                    }
+                    lut.table.push(parse_rgb(line).map_err(|e| at_line(&e))?);
+                }
+            }
+        }
+        if lut.size == 0 {
+            return Err(LookError::new("missing LUT_1D_SIZE or LUT_3D_SIZE"));
+        }
+        let entries = match lut.dimensions {
+            LutDimensions::One => lut.size,
+            LutDimensions::Three => lut.size.pow(3),
+        };
+        if lut.table.len() != entries {
+            return Err(LookError::new(&format!(
+                "a LUT of size {} needs {} entries, found {}",
+                lut.size,
+                entries,
+                lut.table.len()
+            )));
+        }
+        let [min, max] = [lut.domain_min, lut.domain_max].map(|c| c.to_array());
+        if (0..3).any(|c| max[c] <= min[c]) {
+            return Err(LookError::new("DOMAIN_MAX must be above DOMAIN_MIN"));
+        }
+        Ok(lut)
+    }
+
+    /// Graded color of a color, interpolated between the two entries around each channel
+    /// of the curves or the eight entries around it in the cube, the colors outside of the
+    /// domain taking the entries on its border
+    pub fn apply(&self, color: Color) -> Color {
+        let last = (self.size - 1) as f64;
+        let [min, max] = [self.domain_min, self.domain_max].map(|c| c.to_array());
+        let position = color.to_array();
+        let mut cell = [0; 3];
+        let mut fraction = [0.0; 3];
+        for c in 0..3 {
+            let x = ((position[c] - min[c]) / (max[c] - min[c])).clamp(0.0, 1.0) * last;
+            cell[c] = (x.floor() as usize).min(self.size - 2);
+            fraction[c] = x - cell[c] as f64;
+        }
+        if self.dimensions == LutDimensions::One {
+            let [r, g, b] = [0, 1, 2].map(|c| {
+                let low = self.table[cell[c]].to_array()[c];
+                let high = self.table[cell[c] + 1].to_array()[c];
+                low + (high - low) * fraction[c]
+            });
+            return Color::new(r, g, b);
+        }
+        let entry = |r: usize, g: usize, b: usize| {
+            let [r, g, b] = [cell[0] + r, cell[1] + g, cell[2] + b];
+            self.table[r + (g + b * self.size) * self.size]
+        };
+        let lerp = |a: Color, b: Color, t: f64| a * (1.0 - t) + b * t;
+        let [fr, fg, fb] = fraction;
+        let along_red = |g, b| lerp(entry(0, g, b), entry(1, g, b), fr);
+        let near = lerp(along_red(0, 0), along_red(1, 0), fg);
+        let far = lerp(along_red(0, 1), along_red(1, 1), fg);
+        lerp(near, far, fb)
+    }
+
+    /// Grade the pixels of an 8-bit image, e.g. a render already through the color
+    /// pipeline
+    pub fn apply_image(&self, image: &mut Image) {
+        for rgb in image.data.chunks_exact_mut(3) {
+            let color = Color::new(rgb[0] as f64, rgb[1] as f64, rgb[2] as f64) / 255.0;
+            let graded = self.apply(color).to_array();
+            for (value, graded) in rgb.iter_mut().zip(graded) {
+                *value = (graded.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+    }
+}
+
+/// Measured response of a camera or of a film stock: the value each channel of the image
+/// takes for a scene value, e.g. recovered from brackets of photographs
+///
+/// The curve is sampled at exposures in stops (the base 2 logarithm of the scene value,
+/// middle gray being about -2.5) and interpolated linearly between them, the exposures
+/// outside of the samples taking the values at the ends. The values are encoded for the
+/// display, so the curve stands in for both the view transform and the output encoding
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResponseCurve {
+    /// Exposures in stops, increasing, and the value of each channel there
+    pub samples: Vec<(f64, [f64; 3])>,
+}
+
+impl ResponseCurve {
+    /// Load a response curve file (see `parse`)
+    pub fn load(path: &Path) -> Result<ResponseCurve, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(ResponseCurve::parse(&text)?)
+    }
+
+    /// Read a response curve from text: one `exposure r g b` line per sample, by increasing
+    /// exposure, and `#` comments
+    ///
+    /// ### Arguments
+    ///
+    /// * `text` - The content of the file
+    ///
+    /// ### Returns
+    ///
+    /// `Result<ResponseCurve, LookError>` - The curve, or an error naming the line it was
+    /// found on
+    ///
+    pub fn parse(text: &str) -> Result<ResponseCurve, LookError> {
+        let mut samples: Vec<(f64, [f64; 3])> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let at_line =
+                |message: &str| LookError::new(&format!("line {}: {}", index + 1, message));
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let numbers: Vec<f64> = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| at_line("expected numbers"))?;
+            let [exposure, r, g, b] = numbers[..] else {
+                return Err(at_line("expected <exposure> <r> <g> <b>"));
+            };
+            if samples.last().is_some_and(|(last, _)| exposure <= *last) {
+                return Err(at_line("the exposures must increase"));
+            }
+            samples.push((exposure, [r, g, b]));
+        }
+        if samples.len() < 2 {
+            return Err(LookError::new(
+                "a response curve needs at least two samples",
+            ));
+        }
+        Ok(ResponseCurve { samples })
+    }
+
+    /// Display values of a scene color, each channel through its own curve
+    pub fn apply(&self, color: Color) -> Color {
+        let channels = color.to_array();
+        let [r, g, b] = [0, 1, 2].map(|c| self.channel(c, channels[c]));
+        Color::new(r, g, b)
+    }
+
+    fn channel(&self, channel: usize, value: f64) -> f64 {
+        let first = self.samples[0];
+        let last = self.samples[self.samples.len() - 1];
+        // black is infinitely many stops down
+        let exposure = if value > 0.0 {
+            value.log2()
+        } else {
+            f64::NEG_INFINITY
+        };
+        if exposure <= first.0 {
+            return first.1[channel];
+        }
+        if exposure >= last.0 {
+            return last.1[channel];
+        }
+        let above = self.samples.partition_point(|(e, _)| *e <= exposure);
+        let (e0, v0) = self.samples[above - 1];
+        let (e1, v1) = self.samples[above];
+        let t = (exposure - e0) / (e1 - e0);
+        v0[channel] + (v1[channel] - v0[channel]) * t
+    }
+}
+
+/// Photographic look applied when the radiance is developed into an image: the response
+/// curve of a camera or film stock in place of the view transform and the output encoding,
+/// then a LUT grading the encoded colors
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FilmLook {
+    pub response: Option<ResponseCurve>,
+    pub lut: Option<Lut>,
+}
+
+impl FilmLook {
+    pub fn new() -> FilmLook {
+        FilmLook::default()
+    }
+
+    pub fn with_response(mut self, response: ResponseCurve) -> FilmLook {
+        self.response = Some(response);
+        self
+    }
+
+    pub fn with_lut(mut self, lut: Lut) -> FilmLook {
+        self.lut = Some(lut);
+        self
+    }
+}
+
+fn parse_rgb(text: &str) -> Result<Color, String> {
+    let numbers: Vec<f64> = text
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| "expected numbers".to_string())?;
+    match numbers[..] {
+        [r, g, b] => Ok(Color::new(r, g, b)),
+        _ => Err("expected <r> <g> <b>".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `.cube` text of the identity cube of size 2 with a header, cut after `entries`
+    /// entries
+    fn identity_cube(entries: usize) -> String {
+        let mut text = "# identity\nTITLE \"identity\"\nLUT_3D_SIZE 2\n\n".to_string();
+        for k in 0..entries {
+            text += &format!("{} {} {}\n", k & 1, (k >> 1) & 1, (k >> 2) & 1);
+        }
+        text
+    }
+
+    #[test]
+    fn reads_a_3d_cube() {
+        let lut = Lut::parse_cube(&identity_cube(8)).unwrap();
+        assert_eq!(lut.title.as_deref(), Some("identity"));
+        assert_eq!((lut.dimensions, lut.size), (LutDimensions::Three, 2));
+        let color = Color::new(0.25, 0.5, 0.75);
+        assert!((lut.apply(color) - color).norm_squared() < 1e-24);
+        // the colors outside of the domain take the entries on its border
+        assert_eq!(
+            lut.apply(Color::new(2.0, -1.0, 0.0)),
+            Color::new(1.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn reads_1d_curves_over_a_domain() {
+        let text = "LUT_1D_SIZE 3\r\nDOMAIN_MIN 0 0 0\r\nDOMAIN_MAX 2 2 2\r\n\
+                    0 0 1\r\n0.5 1 1\r\n1 1 1\r\n";
+        let lut = Lut::parse_cube(text).unwrap();
+        assert_eq!((lut.dimensions, lut.size), (LutDimensions::One, 3));
+        assert_eq!(
+            lut.apply(Color::new(0.5, 1.0, 2.0)),
+            Color::new(0.25, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn truncated_cubes_are_errors() {
+        for entries in [0, 1, 7] {
+            let error = Lut::parse_cube(&identity_cube(entries)).unwrap_err();
+            let message = format!("a LUT of size 2 needs 8 entries, found {}", entries);
+            assert_eq!(error, LookError::new(&message));
+        }
+        // the last entry cut in the middle of its line
+        let mut text = identity_cube(7);
+        text += "1 1";
+        let error = Lut::parse_cube(&text).unwrap_err();
+        assert_eq!(error, LookError::new("line 12: expected <r> <g> <b>"));
+        // the file cut before the size
+        let error = Lut::parse_cube("TITLE \"cut\"\n").unwrap_err();
+        assert_eq!(error, LookError::new("missing LUT_1D_SIZE or LUT_3D_SIZE"));
+    }
+
+    #[test]
+    fn malformed_cubes_name_their_line() {
+        let cases = [
+            (
+                "0 0 0\nLUT_3D_SIZE 2",
+                "line 1: the entries must come after the size",
+            ),
+            (
+                "LUT_3D_SIZE 2\nLUT_1D_SIZE 2",
+                "line 2: a LUT has a single size",
+            ),
+            (
+                "LUT_3D_SIZE 1",
+                "line 1: LUT_3D_SIZE must be between 2 and 256",
+            ),
+            (
+                "LUT_3D_SIZE 257",
+                "line 1: LUT_3D_SIZE must be between 2 and 256",
+            ),
+            (
+                "LUT_1D_SIZE two",
+                "line 1: LUT_1D_SIZE must be between 2 and 65536",
+            ),
+            (
+                "LUT_3D_SIZE 2\nLUT_FORMAT 1",
+                "line 2: unknown keyword `LUT_FORMAT`",
+            ),
+            ("LUT_1D_SIZE 2\n0 0 x", "line 2: expected numbers"),
+            (
+                "LUT_1D_SIZE 2\nDOMAIN_MIN 0 0",
+                "line 2: expected <r> <g> <b>",
+            ),
+        ];
+        for (text, message) in cases {
+            assert_eq!(
+                Lut::parse_cube(text),
+                Err(LookError::new(message)),
+                "{}",
+                text
+            );
+        }
+        let text = "LUT_1D_SIZE 2\nDOMAIN_MIN 0 1 0\nDOMAIN_MAX 1 1 1\n0 0 0\n1 1 1\n";
+        let error = Lut::parse_cube(text).unwrap_err();
+        assert_eq!(error, LookError::new("DOMAIN_MAX must be above DOMAIN_MIN"));
+        let mut text = identity_cube(8);
+        text += "1 1 1\n";
+        let error = Lut::parse_cube(&text).unwrap_err();
+        assert_eq!(
+            error,
+            LookError::new("a LUT of size 2 needs 8 entries, found 9")
+        );
+    }
+
+    #[test]
+    fn response_curves_need_increasing_exposures() {
+        let curve = ResponseCurve::parse("# stops r g b\n-4 0 0 0\n0 1 1 1 # white\n").unwrap();
+        assert_eq!(curve.samples.len(), 2);
+        let cases = [
+            ("-4 0 0 0", "a response curve needs at least two samples"),
+            ("-4 0 0 0\n-4 1 1 1", "line 2: the exposures must increase"),
+            ("-4 0 0\n0 1 1 1", "line 1: expected <exposure> <r> <g> <b>"),
+        ];
+        for (text, message) in cases {
+            assert_eq!(ResponseCurve::parse(text), Err(LookError::new(message)));
+        }
+    }
+}
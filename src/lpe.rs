@@ -0,0 +1,555 @@
+use crate::color::Color;
+use crate::medium::MediumStack;
+use crate::ray::Ray;
+use crate::render::camera_rays;
+use crate::render::direct_lighting;
+use crate::render::nearest_sphere;
+use crate::render::reflect;
+use crate::render::refract;
+use crate::render::sanitize_radiance;
+use crate::render::surface_color;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+/// Error raised by a light path expression that can't be read
+#[derive(Clone, Debug, PartialEq)]
+pub struct LpeError {
+    pub message: String,
+}
+
+impl LpeError {
+    pub fn new(message: &str) -> LpeError {
+        LpeError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for LpeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for LpeError {}
+
+/// Event along the path of the light from the camera, in the order the rays are traced
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightEvent {
+    /// `C`, the camera the path starts at
+    Camera,
+    /// `D`, diffuse reflection of a light by a surface
+    Diffuse,
+    /// `G`, glossy highlight of a light on a surface
+    Glossy,
+    /// `R`, mirror reflection
+    Reflection,
+    /// `T`, transmission through the surface of a transparent object
+    Transmission,
+    /// `L`, a light, the end of the paths going through `D` or `G`
+    Light,
+    /// `B`, the background or the environment map, the end of the other paths
+    Background,
+}
+
+impl LightEvent {
+    const ALL: [LightEvent; 7] = [
+        LightEvent::Camera,
+        LightEvent::Diffuse,
+        LightEvent::Glossy,
+        LightEvent::Reflection,
+        LightEvent::Transmission,
+        LightEvent::Light,
+        LightEvent::Background,
+    ];
+
+    /// Event from its letter in an expression
+    pub fn from_letter(letter: char) -> Option<LightEvent> {
+        LightEvent::ALL
+            .into_iter()
+            .find(|event| event.letter() == letter)
+    }
+
+    pub fn letter(&self) -> char {
+        match self {
+            LightEvent::Camera => 'C',
+            LightEvent::Diffuse => 'D',
+            LightEvent::Glossy => 'G',
+            LightEvent::Reflection => 'R',
+            LightEvent::Transmission => 'T',
+            LightEvent::Light => 'L',
+            LightEvent::Background => 'B',
+        }
+    }
+
+    fn bit(&self) -> u8 {
+        1 << LightEvent::ALL
+            .iter()
+            .position(|event| event == self)
+            .unwrap_or(0)
+    }
+}
+
+/// Regular expression over the events of a light path, selecting the light carried by
+/// some paths only, e.g. `C T+ D L` for the diffuse surfaces seen through glass or
+/// `C R+ B` for the background seen in mirrors (see `render_lpe`)
+///
+/// The expressions are made of the letters of `LightEvent`, `.` for any event, sets like
+/// `[RT]` or `[^D]`, groups in parentheses, alternatives separated by `|` and the `*`, `+`
+/// and `?` repetitions; spaces are ignored. An expression matches whole paths, from `C` to
+/// `L` or `B`
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightPathExpression {
+    /// Expression of the rest of the paths, after the events traced so far
+    pattern: Pattern,
+}
+
+impl LightPathExpression {
+    /// Read an expression
+    ///
+    /// ### Arguments
+    ///
+    /// * `text` - The expression, e.g. `C [RT]* D L`
+    ///
+    /// ### Returns
+    ///
+    /// `Result<LightPathExpression, LpeError>` - The expression, or an error naming the
+    /// position in the text it was found at, counted in characters from 1
+    ///
+    pub fn parse(text: &str) -> Result<LightPathExpression, LpeError> {
+        let chars: Vec<(usize, char)> = text
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| !c.is_whitespace())
+            .collect();
+        if chars.is_empty() {
+            return Err(LpeError::new("empty expression"));
+        }
+        let end = text.chars().count();
+        let mut parser = Parser {
+            chars,
+            position: 0,
+            end,
+        };
+        let pattern = parser.alternatives()?;
+        if let Some(c) = parser.peek() {
+            return Err(parser.error(&format!("unexpected '{}'", c)));
+        }
+        Ok(LightPathExpression { pattern })
+    }
+
+    /// Whether the expression matches a whole path
+    pub fn matches(&self, path: &[LightEvent]) -> bool {
+        path.iter()
+            .fold(self.clone(), |expression, event| expression.after(*event))
+            .pattern
+            .nullable()
+    }
+
+    /// Expression of the rest of the paths once an event is traced
+    fn after(&self, event: LightEvent) -> LightPathExpression {
+        LightPathExpression {
+            pattern: self.pattern.derive(event.bit()),
+        }
+    }
+
+    /// Whether a path ending with the event matches
+    fn ends_with(&self, event: LightEvent) -> bool {
+        self.after(event).pattern.nullable()
+    }
+
+    /// Whether no path can match anymore, so the rays don't need to be traced further
+    fn is_dead(&self) -> bool {
+        self.pattern == Pattern::Empty
+    }
+}
+
+/// Regular expression over the events, matched by derivatives (Brzozowski, "Derivatives of
+/// Regular Expressions", 1964): the derivative by an event is the expression of the rest of
+/// the paths starting with it, so the paths are matched event by event while they are traced
+#[derive(Clone, Debug, PartialEq)]
+enum Pattern {
+    /// Matches nothing
+    Empty,
+    /// Matches the empty path
+    Epsilon,
+    /// Matches one event among a set, as a mask of the bits of `LightEvent`
+    Event(u8),
+    Concat(Box<Pattern>, Box<Pattern>),
+    Alternative(Box<Pattern>, Box<Pattern>),
+    Star(Box<Pattern>),
+}
+
+impl Pattern {
+    // the constructors simplify the empty patterns away, so a pattern that can't match
+    // anything is `Empty` itself
+    fn concat(a: Pattern, b: Pattern) -> Pattern {
+        match (a, b) {
+            (Pattern::Empty, _) | (_, Pattern::Empty) => Pattern::Empty,
+            (Pattern::Epsilon, b) => b,
+            (a, Pattern::Epsilon) => a,
+            (a, b) => Pattern::Concat(Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn alternative(a: Pattern, b: Pattern) -> Pattern {
+        match (a, b) {
+            (Pattern::Empty, b) => b,
+            (a, Pattern::Empty) => a,
+            (a, b) if a == b => a,
+            (a, b) => Pattern::Alternative(Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn star(a: Pattern) -> Pattern {
+        match a {
+            Pattern::Empty | Pattern::Epsilon => Pattern::Epsilon,
+            a => Pattern::Star(Box::new(a)),
+        }
+    }
+
+    /// Whether the pattern matches the empty path
+    fn nullable(&self) -> bool {
+        match self {
+            Pattern::Empty | Pattern::Event(_) => false,
+            Pattern::Epsilon | Pattern::Star(_) => true,
+            Pattern::Concat(a, b) => a.nullable() && b.nullable(),
+            Pattern::Alternative(a, b) => a.nullable() || b.nullable(),
+        }
+    }
+
+    fn derive(&self, event: u8) -> Pattern {
+        match self {
+            Pattern::Empty | Pattern::Epsilon => Pattern::Empty,
+            Pattern::Event(mask) if mask & event != 0 => Pattern::Epsilon,
+            Pattern::Event(_) => Pattern::Empty,
+            Pattern::Concat(a, b) => {
+                let first = Pattern::concat(a.derive(event), (**b).clone());
+                if a.nullable() {
+                    Pattern::alternative(first, b.derive(event))
+                } else {
+                    first
+                }
+            }
+            Pattern::Alternative(a, b) => Pattern::alternative(a.derive(event), b.derive(event)),
+            Pattern::Star(a) => Pattern::concat(a.derive(event), self.clone()),
+        }
+    }
+}
+
+/// Recursive descent parser of the expressions, the spaces taken out
+struct Parser {
+    /// The characters other than spaces, with their position in the text
+    chars: Vec<(usize, char)>,
+    position: usize,
+    /// Number of characters of the text
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).map(|(_, c)| *c)
+    }
+
+    fn error(&self, message: &str) -> LpeError {
+        let at = self
+            .chars
+            .get(self.position)
+            .map_or(self.end, |(at, _)| *at);
+        LpeError::new(&format!("at {}: {}", at + 1, message))
+    }
+
+    fn alternatives(&mut self) -> Result<Pattern, LpeError> {
+        let mut pattern = self.sequence()?;
+        while self.peek() == Some('|') {
+            self.position += 1;
+            pattern = Pattern::alternative(pattern, self.sequence()?);
+        }
+        Ok(pattern)
+    }
+
+    fn sequence(&mut self) -> Result<Pattern, LpeError> {
+        let mut pattern = Pattern::Epsilon;
+        while self.peek().is_some_and(|c| c != '|' && c != ')') {
+            pattern = Pattern::concat(pattern, self.repetition()?);
+        }
+        Ok(pattern)
+    }
+
+    fn repetition(&mut self) -> Result<Pattern, LpeError> {
+        let mut pattern = self.atom()?;
+        while let Some(c) = self.peek().filter(|c| "*+?".contains(*c)) {
+            self.position += 1;
+            pattern = match c {
+                '*' => Pattern::star(pattern),
+                '+' => Pattern::concat(pattern.clone(), Pattern::star(pattern)),
+                _ => Pattern::alternative(pattern, Pattern::Epsilon),
+            };
+        }
+        Ok(pattern)
+    }
+
+    fn atom(&mut self) -> Result<Pattern, LpeError> {
+        let Some(c) = self.peek() else {
+            return Err(self.error("unexpected end of the expression"));
+        };
+        self.position += 1;
+        match c {
+            '.' => Ok(Pattern::Event(u8::MAX)),
+            '(' => {
+                let pattern = self.alternatives()?;
+                if self.peek() != Some(')') {
+                    return Err(self.error("expected ')'"));
+                }
+                self.position += 1;
+                Ok(pattern)
+            }
+            '[' => {
+                let negated = self.peek() == Some('^');
+                if negated {
+                    self.position += 1;
+                }
+                let mut mask = 0;
+                while let Some(c) = self.peek().filter(|c| *c != ']') {
+                    mask |= self.event(c)?.bit();
+                    self.position += 1;
+                }
+                if self.peek() != Some(']') {
+                    return Err(self.error("expected ']'"));
+                }
+                self.position += 1;
+                Ok(Pattern::Event(if negated { !mask } else { mask }))
+            }
+            '*' | '+' | '?' => {
+                self.position -= 1;
+                Err(self.error(&format!("nothing to repeat before '{}'", c)))
+            }
+            c => {
+                self.position -= 1;
+                let event = self.event(c)?;
+                self.position += 1;
+                Ok(Pattern::Event(event.bit()))
+            }
+        }
+    }
+
+    fn event(&self, c: char) -> Result<LightEvent, LpeError> {
+        LightEvent::from_letter(c).ok_or_else(|| {
+            self.error(&format!(
+                "unknown event '{}', expected C, D, G, R, T, L or B",
+                c
+            ))
+        })
+    }
+}
+
+/// Render the light of the paths matching an expression into a linear pass, the paths
+/// being those of the Whitted shading (see `render::cast_ray`): the camera rays bounce on
+/// mirrors (`R`) and through glass (`T`) and end at the direct light of a surface (`D L`
+/// or `G L`) or at the background (`B`)
+///
+/// `C .*` gives the full render, and expressions selecting paths no other expression
+/// selects give passes adding up to it. Rays the expression can't match anymore are not
+/// traced further
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings
+/// * `scene` - The scene
+/// * `expression` - The paths to keep
+///
+/// ### Returns
+///
+/// `Vec<Color>` - The radiance of the pixels row by row from the top left corner, averaged
+/// over the camera rays of every pixel
+///
+pub fn render_lpe(
+    settings: &RenderSettings,
+    scene: &Scene,
+    expression: &LightPathExpression,
+) -> Vec<Color> {
+    let camera = expression.after(LightEvent::Camera);
+    let mut pixels = Vec::with_capacity(settings.width as usize * settings.height as usize);
+    for j in 0..settings.height {
+        for i in 0..settings.width {
+            let rays = camera_rays(i, j, settings);
+            let mut sum = Color::BLACK;
+            for ray in &rays {
+                let media = MediumStack::at(ray.origin, &scene.spheres);
+                sum += lpe_radiance(ray, &media, scene, settings, 0, &camera);
+            }
+            pixels.push(sanitize_radiance(sum / rays.len() as f64));
+        }
+    }
+    pixels
+}
+
+/// Radiance of a ray carried by the paths matching the rest of an expression, like
+/// `render::cast_ray_components`
+fn lpe_radiance(
+    ray: &Ray,
+    media: &MediumStack,
+    scene: &Scene,
+    settings: &RenderSettings,
+    depth: u32,
+    expression: &LightPathExpression,
+) -> Color {
+    if expression.is_dead() {
+        return Color::BLACK;
+    }
+    let hit = nearest_sphere(ray, scene).filter(|(_, distance)| *distance < 1000.0);
+    let color = match hit.filter(|_| depth <= settings.max_depth) {
+        Some(hit) => lpe_shade(ray, media, hit, scene, settings, depth, expression),
+        None if expression.ends_with(LightEvent::Background) => settings.background_radiance(ray),
+        None => Color::BLACK,
+    };
+    let medium = media.medium(scene, settings.outside_medium());
+    if medium.is_vacuum() {
+        return color;
+    }
+    let segment = ray.with_t_max(hit.map_or(f64::INFINITY, |(_, distance)| distance));
+    color * medium.transmittance(&segment)
+}
+
+/// Radiance of a ray at the surface it hits carried by the paths matching the rest of an
+/// expression, like `render::shade_components`
+fn lpe_shade(
+    ray: &Ray,
+    media: &MediumStack,
+    (index, distance): (usize, f64),
+    scene: &Scene,
+    settings: &RenderSettings,
+    depth: u32,
+    expression: &LightPathExpression,
+) -> Color {
+    let sphere = scene.sphere(index);
+    if sphere.holdout {
+        return Color::BLACK;
+    }
+    let point = ray.at(distance);
+    let n = (point - sphere.center).normalize();
+    let material = &sphere.material;
+    if material.refraction > 0.0 && media.is_false_hit(index, scene) {
+        let through = Ray::offset(point, n, ray.direction).with_time(ray.time);
+        let crossed = media.cross(index);
+        return lpe_radiance(&through, &crossed, scene, settings, depth, expression);
+    }
+
+    let mut color = Color::BLACK;
+    let diffuse = expression
+        .after(LightEvent::Diffuse)
+        .ends_with(LightEvent::Light);
+    let glossy = expression
+        .after(LightEvent::Glossy)
+        .ends_with(LightEvent::Light);
+    if diffuse || glossy {
+        let (diffuse_light, specular_light) =
+            direct_lighting(point, n, ray.direction, material.specular_exponent, scene);
+        if diffuse {
+            color += surface_color(sphere, point, settings) * diffuse_light * material.albedo[0];
+        }
+        if glossy {
+            color += specular_light * material.albedo[1];
+        }
+    }
+
+    let reflected = expression.after(LightEvent::Reflection);
+    let mut reflect_color = Color::BLACK;
+    if !reflected.is_dead() {
+        let reflect_ray = Ray::offset(point, n, reflect(ray.direction, n)).with_time(ray.time);
+        reflect_color = lpe_radiance(&reflect_ray, media, scene, settings, depth + 1, &reflected);
+        color += reflect_color * material.albedo[2];
+    }
+
+    if material.refraction > 0.0 {
+        let inside = media.contains(index);
+        let crossed = media.cross(index);
+        let eta = media.refractive_index(scene) / crossed.refractive_index(scene);
+        let facing = if inside { -n } else { n };
+        let refract_color = match refract(ray.direction, facing, eta) {
+            Some(direction) => {
+                let transmitted = expression.after(LightEvent::Transmission);
+                let refract_ray = Ray::offset(point, n, direction).with_time(ray.time);
+                lpe_radiance(
+                    &refract_ray,
+                    &crossed,
+                    scene,
+                    settings,
+                    depth + 1,
+                    &transmitted,
+                )
+            }
+            // total internal reflection, the light follows the reflected paths
+            None => reflect_color,
+        };
+        color += refract_color * material.refraction;
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use LightEvent::*;
+
+    fn parse(text: &str) -> LightPathExpression {
+        LightPathExpression::parse(text).unwrap()
+    }
+
+    #[test]
+    fn matches_whole_paths() {
+        let glass = parse("C T+ D L");
+        assert!(glass.matches(&[Camera, Transmission, Diffuse, Light]));
+        assert!(glass.matches(&[Camera, Transmission, Transmission, Diffuse, Light]));
+        assert!(!glass.matches(&[Camera, Diffuse, Light]));
+        assert!(!glass.matches(&[Camera, Transmission, Diffuse]));
+        let everything = parse("C .*");
+        assert!(everything.matches(&[Camera, Reflection, Background]));
+        let sets = parse("C [^D]* (D | G) L?");
+        assert!(sets.matches(&[Camera, Reflection, Transmission, Glossy]));
+        assert!(!sets.matches(&[Camera, Diffuse, Diffuse, Light]));
+    }
+
+    #[test]
+    fn expressions_that_cannot_match_are_dead() {
+        let mirrors = parse("C R+ B");
+        assert!(!mirrors.after(Camera).is_dead());
+        assert!(mirrors.after(Camera).after(Diffuse).is_dead());
+        assert!(mirrors
+            .after(Camera)
+            .after(Reflection)
+            .ends_with(Background));
+    }
+
+    #[test]
+    fn invalid_expressions_name_their_position() {
+        let cases = [
+            ("", "empty expression"),
+            ("   ", "empty expression"),
+            (
+                "C X L",
+                "at 3: unknown event 'X', expected C, D, G, R, T, L or B",
+            ),
+            (
+                "C [RX] L",
+                "at 5: unknown event 'X', expected C, D, G, R, T, L or B",
+            ),
+            ("C (D L", "at 7: expected ')'"),
+            ("C [RT", "at 6: expected ']'"),
+            ("C D L)", "at 6: unexpected ')'"),
+            ("*C", "at 1: nothing to repeat before '*'"),
+            ("C (+D) L", "at 4: nothing to repeat before '+'"),
+            ("C |?", "at 4: nothing to repeat before '?'"),
+            ("C (", "at 4: expected ')'"),
+            (
+                "c d l",
+                "at 1: unknown event 'c', expected C, D, G, R, T, L or B",
+            ),
+        ];
+        for (text, message) in cases {
+            let error = LightPathExpression::parse(text).unwrap_err();
+            assert_eq!(error, LpeError::new(message), "{:?}", text);
+        }
+    }
+}
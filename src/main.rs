@@ -1,273 +1,2092 @@
-mod image;
-mod object;
+use ray_tracing::animation::animate_lights;
+use ray_tracing::animation::AnimatedLight;
+use ray_tracing::animation::CameraShake;
+use ray_tracing::animation::Flicker;
+use ray_tracing::animation::LightAnimationSpec;
+use ray_tracing::animation::SceneAnimation;
+use ray_tracing::animation::Track;
+use ray_tracing::autosave::install_panic_hook;
+use ray_tracing::bake::bake;
+use ray_tracing::bake::bake_values;
+use ray_tracing::bake::BakeMode;
+use ray_tracing::batch::split_arguments;
+use ray_tracing::camera::Camera;
+use ray_tracing::caption::Caption;
+use ray_tracing::caption::Corner;
+use ray_tracing::codegen::scene_to_json;
+use ray_tracing::codegen::scene_to_rust;
+use ray_tracing::color::Color;
+use ray_tracing::colorspace::ColorPipeline;
+use ray_tracing::compare::render_difference;
+use ray_tracing::compare::render_split;
+use ray_tracing::compare::Variant;
+use ray_tracing::completions::completion_script;
+use ray_tracing::completions::SHELLS;
+use ray_tracing::config::Config;
+use ray_tracing::debug::render_overlay;
+use ray_tracing::debug::Overlay;
+use ray_tracing::deep::render_deep;
+use ray_tracing::depth::camera_depths;
+use ray_tracing::depth::depth_range;
+use ray_tracing::depth::DepthEncoding;
+use ray_tracing::envmap::cubemap_to_equirect;
+use ray_tracing::envmap::equirect_to_cubemap;
+use ray_tracing::exit::exit_with;
+use ray_tracing::exit::exit_with_usage;
+use ray_tracing::exit::install_error_hook;
+use ray_tracing::exit::set_error_format;
+use ray_tracing::exit::ErrorFormat;
+use ray_tracing::exit::ExitCode;
+use ray_tracing::exit::OrExit;
+use ray_tracing::exit::OrUsage;
+use ray_tracing::flare::LensFlare;
+use ray_tracing::framebuffer::render_framebuffer;
+use ray_tracing::furnace::furnace_test;
+use ray_tracing::furnace::FURNACE_TOLERANCE;
+use ray_tracing::gbuffer::write_pfm;
+use ray_tracing::gbuffer::GBuffer;
+use ray_tracing::i18n::install;
+use ray_tracing::i18n::language_from_env;
+use ray_tracing::i18n::tr;
+use ray_tracing::i18n::tr_format;
+use ray_tracing::i18n::Catalog;
+use ray_tracing::image::check_image_size;
+use ray_tracing::image::Image;
+use ray_tracing::info::RenderStatistics;
+use ray_tracing::info::SceneInfo;
+use ray_tracing::integrator::IntegratorKind;
+use ray_tracing::json::Json;
+use ray_tracing::layers::render_layers;
+use ray_tracing::layers::LayerObject;
+use ray_tracing::layers::LayerSpec;
+use ray_tracing::layers::RenderLayer;
+use ray_tracing::lightgroups::render_light_groups;
+use ray_tracing::lightgroups::LightGroup;
+use ray_tracing::lightgroups::BACKGROUND_PASS;
+use ray_tracing::look::FilmLook;
+use ray_tracing::look::Lut;
+use ray_tracing::look::ResponseCurve;
+use ray_tracing::lpe::render_lpe;
+use ray_tracing::lpe::LightPathExpression;
+use ray_tracing::matte::holdout_alpha;
+use ray_tracing::matte::id_mattes;
+use ray_tracing::motion::motion_vectors;
+use ray_tracing::object::Material;
+use ray_tracing::object::SceneLight;
+use ray_tracing::object::Sphere;
+use ray_tracing::outputs::OutputLayers;
+use ray_tracing::pack::ScenePack;
+use ray_tracing::parameter::apply_override;
+use ray_tracing::parameter::env_overrides;
+use ray_tracing::passes::render_passes;
+use ray_tracing::passes::PASS_NAMES;
+use ray_tracing::pathtemplate::expand_template;
+use ray_tracing::pathtemplate::has_token;
+use ray_tracing::pathtemplate::PathTokens;
+use ray_tracing::pixelformat::save_pixels;
+use ray_tracing::pixelformat::PixelFormat;
+use ray_tracing::preview::shader_ball;
+use ray_tracing::preview::thumbnail_scene;
+use ray_tracing::preview::thumbnail_settings;
+use ray_tracing::preview::SHADER_BALL_FOV;
+use ray_tracing::preview::THUMBNAIL_SIZE;
+use ray_tracing::probe::probes_to_json;
+use ray_tracing::probe::IrradianceProbe;
+use ray_tracing::proxy::set_proxy_size;
+use ray_tracing::proxy::DEFAULT_PROXY_SIZE;
+use ray_tracing::render::render;
+use ray_tracing::render::render_radiance;
+use ray_tracing::render::render_supersampled;
+use ray_tracing::render::render_to_file;
+use ray_tracing::render::PrimaryHits;
+use ray_tracing::resample::ResizeFilter;
+use ray_tracing::scene::Scene;
+#[cfg(feature = "bench-scenes")]
+use ray_tracing::scenes::BenchScene;
+use ray_tracing::selftest::selftest;
+use ray_tracing::sequence::frame_hash;
+use ray_tracing::sequence::FrameRecord;
+use ray_tracing::sequence::SequenceManifest;
+use ray_tracing::serve::serve;
+use ray_tracing::settings::RenderSettings;
+use ray_tracing::sweep::render_sweep;
+use ray_tracing::sweep::SweepAxis;
+use ray_tracing::temporal::TemporalAccumulator;
+use ray_tracing::texture::TextureImage;
+use ray_tracing::validate::validate;
+use ray_tracing::volume::render_fog;
+use ray_tracing::volume::DensityGrid;
+use ray_tracing::volume::Emission;
+use ray_tracing::volume::Fog;
 
-use image::Image;
-use object::Light;
-use object::Material;
-use object::Sphere;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
 
-use std::f64::consts::PI;
-
-use futures;
-use futures::executor::block_on;
 use nalgebra::Vector3;
 
+/// Commands of the binary and what they do, the first argument picks one and `render` runs
+/// without any
+const COMMANDS: &[(&str, &str)] = &[
+    ("render", "render the beauty image to out.png"),
+    (
+        "preview",
+        "render a quick draft to out_preview.png, or a material: preview material <file>",
+    ),
+    (
+        "validate",
+        "check the scene and the settings without rendering",
+    ),
+    ("info", "describe the scene and the settings"),
+    (
+        "selftest",
+        "check the installation with a built-in render and time a tile",
+    ),
+    (
+        "furnace",
+        "check that a material conserves energy: furnace [<material.json>]",
+    ),
+    (
+        "post",
+        "write a pass or an AOV for compositing: post <command> ...",
+    ),
+    (
+        "batch",
+        "run the commands listed in a file, one invocation per line",
+    ),
+    (
+        "serve",
+        "render over HTTP: GET /render?<parameter>=<value>&...",
+    ),
+    ("help", "list the commands"),
+    (
+        "completions",
+        "print a shell completion script: completions bash | zsh | fish",
+    ),
+    ("bake", "bake the lighting of a sphere into its UV layout"),
+    (
+        "probes",
+        "bake irradiance probes as spherical harmonics: probes <x,y,z> ...",
+    ),
+    ("mattes", "write the object and material ID mattes"),
+    ("layers", "render the layers of the scene separately"),
+    (
+        "gbuffer",
+        "write the position, normal, albedo, object id and curvature AOVs",
+    ),
+    (
+        "light-passes",
+        "write the contribution of every light or light group",
+    ),
+    (
+        "passes",
+        "write the diffuse, specular, reflection, transmission and emission passes",
+    ),
+    (
+        "lpe",
+        "write the light of the paths matching expressions: lpe <name>=<expression> ...",
+    ),
+    (
+        "motion-vectors",
+        "write the screen space motion since a previous frame",
+    ),
+    (
+        "depth",
+        "write the depth of the surfaces along the view axis",
+    ),
+    ("deep", "write a deep image"),
+    ("animate", "render the animation of the scene"),
+    ("fog", "render a spot light shining through fog"),
+    (
+        "reconstruct",
+        "render with few shadow rays and reconstruct the image",
+    ),
+    (
+        "false-color",
+        "write the luminance through a false color ramp",
+    ),
+    ("uv-checker", "draw a UV checkerboard over every sphere"),
+    (
+        "bvh",
+        "write the boxes of the bounding volume hierarchy to bvh.obj: bvh [max depth]",
+    ),
+    ("glass", "add nested glass, water and air balls"),
+    (
+        "compare",
+        "render the two halves of the image with different settings",
+    ),
+    (
+        "diff",
+        "render two scene files and write their difference: diff <before> <after>",
+    ),
+    (
+        "sweep",
+        "render a contact sheet over the values of one or two parameters",
+    ),
+    ("preview-material", "render a material on the shader ball"),
+    ("envmap", "convert an environment map between layouts"),
+    (
+        "resize",
+        "resize an image with a box, bilinear or Lanczos filter",
+    ),
+    ("cameras", "render the scene from every camera"),
+    (
+        "lut",
+        "grade an image with a 1D or 3D .cube LUT: lut <input.png> <file.cube> <output>",
+    ),
+    (
+        "pack",
+        "bundle a scene and its files into one archive: pack <scene.json> <scene.rtpack>",
+    ),
+    (
+        "thumbnail",
+        "render a 256 pixel draft of a scene file: thumbnail <scene.json> <output.png>",
+    ),
+    (
+        "codegen",
+        "write the Rust code building a scene file: codegen <scene.json> [<output.rs>]",
+    ),
+    (
+        "save-scene",
+        "write the scene as a scene file: save-scene <output.json>",
+    ),
+];
+
+/// Flags taking a value, with the kind of value, and switches, with an empty one
+const FLAGS: &[(&str, &str)] = &[
+    ("--settings", "file"),
+    ("--scene", "file"),
+    ("--camera", "name"),
+    ("--set", "parameter=value"),
+    ("--config", "file"),
+    ("--output", "directory"),
+    ("--output-name", "template"),
+    ("--json", ""),
+    ("--error-format", "format"),
+    ("--pixel-format", "format"),
+    ("--proxy", ""),
+    ("--caption", "corner"),
+    ("--flare", "strength"),
+    ("--shake", "strength"),
+    ("--response", "file"),
+    ("--film-lut", "file"),
+    ("--auto-frame", ""),
+    ("--soft-shadows", ""),
+    ("--lang", "language"),
+];
+
+/// Commands writing passes and AOVs for compositing, which can follow `post`
+const POST_COMMANDS: &[&str] = &[
+    "mattes",
+    "layers",
+    "gbuffer",
+    "light-passes",
+    "passes",
+    "lpe",
+    "motion-vectors",
+    "depth",
+    "deep",
+    "reconstruct",
+    "false-color",
+    "uv-checker",
+];
+
+/// The built-in scene, rendered without `--scene`
+fn builtin_scene() -> Scene {
+    let ivory = Material::new(Vector3::new(0.6, 0.3, 0.1), Color::new(0.4, 0.4, 0.3), 50.0)
+        .with_name("ivory");
+    let red_rubber = Material::new(Vector3::new(0.9, 0.1, 0.0), Color::new(0.3, 0.1, 0.1), 10.0)
+        .with_name("red_rubber");
+    let mirror = Material::new(
+        Vector3::new(0.0, 10.0, 0.8),
+        Color::new(1.0, 1.0, 1.0),
+        1425.0,
+    )
+    .with_name("mirror");
+
+    let spheres = vec![
+        Sphere::new(Vector3::new(-3.0, 0.0, -16.0), 2.0, ivory).with_name("ivory_ball"),
+        Sphere::new(Vector3::new(-1.0, -1.5, -12.0), 2.0, red_rubber).with_name("rubber_ball"),
+        Sphere::new(Vector3::new(1.5, -0.5, -18.0), 3.0, mirror.clone()).with_name("mirror"),
+        Sphere::new(Vector3::new(7.0, 5.0, -18.0), 4.0, mirror).with_name("big_mirror"),
+    ];
+
+    let lights = vec![
+        SceneLight::new(Vector3::new(-20.0, 20.0, 20.0), 1.5).with_name("key"),
+        SceneLight::new(Vector3::new(30.0, 50.0, -25.0), 1.8).with_name("back"),
+        SceneLight::new(Vector3::new(30.0, 20.0, 30.0), 1.7).with_name("fill"),
+    ];
+
+    // the default camera plus standard angles around the middle of the scene
+    let center = Vector3::new(1.5, 0.5, -16.0);
+    Scene::builder()
+        .add_spheres(spheres)
+        .add_lights(lights)
+        .camera(Camera::default())
+        .camera(Camera::new(
+            "three-quarter",
+            Vector3::new(-8.0, 5.0, -6.0),
+            center,
+        ))
+        .camera(Camera::new("side", Vector3::new(-12.0, 1.0, -16.0), center))
+        .camera(
+            Camera::new("top", Vector3::new(1.5, 14.0, -16.0), center)
+                .with_up(Vector3::new(0.0, 0.0, -1.0)),
+        )
+        .animation(
+            SceneAnimation::default().with_light(
+                LightAnimationSpec::new(AnimatedLight::Name("key".to_string()))
+                    .with_temperature(Track::new(vec![(0.0, 1900.0), (2.0, 2700.0)]))
+                    .with_flicker(Flicker::candle(7)),
+            ),
+        )
+        .add_layer(LayerSpec::new(
+            "foreground",
+            vec![
+                LayerObject::Name("ivory_ball".to_string()),
+                LayerObject::Name("rubber_ball".to_string()),
+            ],
+        ))
+        .add_layer(LayerSpec::new(
+            "mirrors",
+            vec![
+                LayerObject::Name("mirror".to_string()),
+                LayerObject::Name("big_mirror".to_string()),
+            ],
+        ))
+        .build()
+        .or_exit("invalid default scene")
+}
+
+fn main() {
+    let scene = builtin_scene();
+
+    // `--error-format text | json` reports errors as text (the default) or as JSON objects
+    // on stderr, the exit code telling the kind of failure in both cases (see `ExitCode`)
+    let mut args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--error-format") {
+        let usage = "--error-format text | json";
+        let format = args
+            .get(index + 1)
+            .and_then(|name| ErrorFormat::from_name(name));
+        set_error_format(format.unwrap_or_else(|| exit_with_usage(usage)));
+        args.drain(index..index + 2);
+    }
+    install_error_hook();
+
+    // `--lang <language | catalog.json>` shows the messages in another language, `en`, `ru`
+    // or the one of a catalog of translations (see `Catalog::from_json`), by default the
+    // language of the environment (`RAY_TRACING_LANG`, or the locale) when there is one
+    let usage = "--lang en | ru | <catalog.json>";
+    let language = match args.iter().position(|arg| arg == "--lang") {
+        Some(index) => {
+            let language = args.get(index + 1).or_usage(usage).clone();
+            args.drain(index..index + 2);
+            let catalog = if language.ends_with(".json") {
+                Catalog::load(&language).or_exit("failed to load the message catalog")
+            } else {
+                Catalog::builtin(&language).or_usage(usage)
+            };
+            Some(catalog)
+        }
+        None => language_from_env().and_then(|language| Catalog::builtin(&language)),
+    };
+    if let Some(catalog) = language {
+        install(catalog);
+    }
+
+    // the user config file (`~/.config/ray_tracing/config.toml` or the one given with
+    // `--config <file.toml>`, see `Config::parse`) sets the output directory, the thread
+    // count, the preview renders and the color pipeline, the command line overrides it
+    let mut config = match args.iter().position(|arg| arg == "--config") {
+        Some(index) => {
+            let path = args.get(index + 1).or_usage("--config <file.toml>").clone();
+            args.drain(index..index + 2);
+            Config::load(Path::new(&path)).or_exit("failed to load the config file")
+        }
+        None => Config::load_user().or_exit("failed to load the config file"),
+    };
+
+    // `--output <directory>` writes the output files into the directory
+    if let Some(index) = args.iter().position(|arg| arg == "--output") {
+        let directory = args.get(index + 1).or_usage("--output <directory>").clone();
+        args.drain(index..index + 2);
+        config.output_dir = Some(directory);
+    }
+    if let Some(directory) = &config.output_dir {
+        std::fs::create_dir_all(directory).or_exit_io("failed to create the output directory");
+    }
+
+    // `--output-name <template>` names the beauty image and the animation frames, e.g.
+    // `{scene}/{camera}_{frame:04}.png`, the tokens being expanded at save time (see
+    // `expand_template`), the directories in the name are created as needed
+    if let Some(index) = args.iter().position(|arg| arg == "--output-name") {
+        let template = args
+            .get(index + 1)
+            .or_usage("--output-name <template>")
+            .clone();
+        args.drain(index..index + 2);
+        expand_template(&template, &PathTokens::default()).or_exit("invalid output name");
+        config.output_name = Some(template);
+    }
+
+    // `--proxy` writes a proxy of every image next to it, e.g. `out.preview.png`, with its
+    // longest side of `proxy_size` pixels from the config (256 by default)
+    if let Some(index) = args.iter().position(|arg| arg == "--proxy") {
+        args.remove(index);
+        config.proxy_size = config.proxy_size.or(Some(DEFAULT_PROXY_SIZE));
+    }
+    set_proxy_size(config.proxy_size.unwrap_or(0));
+
+    // `--response <file>` develops the images through the measured response curve of a
+    // camera or film stock and `--film-lut <file.cube>` grades them with a LUT, e.g. the
+    // look of a film stock (see `FilmLook`)
+    let mut look = config.color.look.as_deref().cloned().unwrap_or_default();
+    if let Some(index) = args.iter().position(|arg| arg == "--response") {
+        let path = args.get(index + 1).or_usage("--response <file>").clone();
+        args.drain(index..index + 2);
+        let response = ResponseCurve::load(Path::new(&path));
+        look.response = Some(response.or_exit("failed to load the response curve"));
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--film-lut") {
+        let path = args
+            .get(index + 1)
+            .or_usage("--film-lut <file.cube>")
+            .clone();
+        args.drain(index..index + 2);
+        look.lut = Some(Lut::load(Path::new(&path)).or_exit("failed to load the LUT"));
+    }
+    if look != FilmLook::default() {
+        config.color = config.color.clone().with_look(look);
+    }
+    let output = |name: &str| config.output_path(name);
 
-const BACKGROUND_COLOR: Vector3<f64> = Vector3::new(0.7, 0.8, 1.0);
-const MAX_RECURSION_DEPTH: u32 = 6;
-
-/// Returning the reflection of the vector `i` on the normal `n`
-///
-/// ### Arguments
-///
-/// * `i` - The incident vector
-/// * `n` - The normal vector
-///
-/// ### Returns
-///
-/// Vector3<f64> - The reflected vector
-///
-fn reflect(i: Vector3<f64>, n: Vector3<f64>) -> Vector3<f64> {
-    return i - n * 2.0 * (i.dot(&n));
-}
-
-/// Generate a ray from the camera to given object and evaluate the intersection
-/// if there is an intersection, return the intersection point, normal and material
-///
-/// ### Arguments
-///
-/// * `ray_origin` - The origin of the ray (point of origin)
-/// * `ray_direction` - The direction of the ray
-/// * `spheres` - The sphere to intersect with
-///
-/// ### Returns
-///
-/// `(bool, Vector3<f64>, Vector3<f64>, Material)` - Whether the ray intersects the sphere or not,
-/// the intersection point, the normal and the material
-///
-fn scene_intersect(
-    ray_origin: Vector3<f64>,
-    ray_direction: Vector3<f64>,
-    spheres: &Vec<Sphere>,
-) -> Option<(bool, Vector3<f64>, Vector3<f64>, Material)> {
-    // initialize defaults
-    let mut point = Vector3::default();
-    let mut n = Vector3::default();
-    let mut material = Material::default();
-
-    // initialize minimum distance to max value of f64 (infinity used for comparison)
-    let mut spheres_dist = std::f64::MAX;
-    // iterate over all spheres in the scene
-    // and evaluate the intersection with the ray
-    // to get all its properties
-    for sphere in spheres {
-        let mut dist_i = 0.0; // distance to intersection
-        let (is_intersect, dist_i0) = sphere.ray_intersect(ray_origin, ray_direction, dist_i);
-        dist_i = dist_i0;
-        if is_intersect && dist_i < spheres_dist {
-            spheres_dist = dist_i; // update minimum distance with the current distance
-            point = ray_origin + ray_direction * dist_i; // get the intersection point
-            n = (point - sphere.center).normalize() as Vector3<f64>; // change the normal to point to center of the sphere
-            material = sphere.material.clone(); // get material of the sphere
+    // `--json` prints the output of `info`, `validate` and the statistics of the beauty
+    // render as JSON, for scripts
+    let json = match args.iter().position(|arg| arg == "--json") {
+        Some(index) => {
+            args.remove(index);
+            true
         }
+        None => false,
+    };
+
+    // `--pixel-format <format>` saves the bakes and the depth pass with one gray or three RGB
+    // channels of 8 or 16 bits (PNG) or 32-bit floats (PFM), e.g. `gray16` for a bake of the
+    // ambient occlusion (see `PixelFormat::from_name`)
+    let pixel_format = match args.iter().position(|arg| arg == "--pixel-format") {
+        Some(index) => {
+            let usage = "--pixel-format gray8 | gray16 | gray32 | rgb8 | rgb16 | rgb32";
+            let format = args
+                .get(index + 1)
+                .and_then(|name| PixelFormat::from_name(name));
+            args.drain(index..index + 2);
+            Some(format.or_usage(usage))
+        }
+        None => None,
+    };
+
+    // `--caption <corner>` burns the scene name, the frame number, the samples per pixel and
+    // the date into a corner of the beauty image, the preview and the animation frames, for
+    // review renders
+    let caption_corner = match args.iter().position(|arg| arg == "--caption") {
+        Some(index) => {
+            let usage = "--caption top-left | top-right | bottom-left | bottom-right";
+            let corner = args.get(index + 1).and_then(|name| Corner::from_name(name));
+            args.drain(index..index + 2);
+            Some(corner.or_usage(usage))
+        }
+        None => None,
+    };
+
+    // `--flare <strength>` draws lens flares around the lights the camera sees over the beauty
+    // image and the animation frames, 1 for flares as bright as the lights (see `LensFlare`)
+    let flare = match args.iter().position(|arg| arg == "--flare") {
+        Some(index) => {
+            let strength = args.get(index + 1).and_then(|value| value.parse().ok());
+            args.drain(index..index + 2);
+            Some(LensFlare::new(strength.or_usage("--flare <strength>")))
+        }
+        None => None,
+    };
+
+    // `--shake <strength>` shakes the camera of the animation frames like a camera held by
+    // hand, 1 for a steady hand (see `CameraShake::handheld`)
+    let shake = match args.iter().position(|arg| arg == "--shake") {
+        Some(index) => {
+            let strength = args.get(index + 1).and_then(|value| value.parse().ok());
+            args.drain(index..index + 2);
+            Some(CameraShake::handheld(
+                strength.or_usage("--shake <strength>"),
+                11,
+            ))
+        }
+        None => None,
+    };
+
+    // a panic during a render saves the rows finished so far to `crash_partial.png`
+    install_panic_hook(&output("crash_partial.png"));
+
+    // `--settings <file.json>` loads the image size, field of view, samples, bounces,
+    // background and thread count from a file (1024x768 with a 90 degree field of view by
+    // default), it can be given before or after the subcommand
+    let settings = match args.iter().position(|arg| arg == "--settings") {
+        Some(index) => {
+            let path = args
+                .get(index + 1)
+                .or_usage("--settings <file.json>")
+                .clone();
+            args.drain(index..index + 2);
+            RenderSettings::load(&path).or_exit("failed to load the render settings")
+        }
+        None => RenderSettings::default(),
+    };
+    let settings = config.apply(settings);
+    // the working space of a settings file replaces the one of the config
+    let mut pipeline = config.color.clone();
+    pipeline.working_space = settings.working_space;
+
+    // `--scene <file.json>` replaces the built-in scene with one loaded from a file (see
+    // `Scene::load`)
+    let (scene, scene_name) = match args.iter().position(|arg| arg == "--scene") {
+        Some(index) => {
+            let path = args.get(index + 1).or_usage("--scene <file.json>").clone();
+            args.drain(index..index + 2);
+            let name = Path::new(&path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy());
+            let name = name.map_or("scene".to_string(), |name| name.to_string());
+            (Scene::load(&path).or_exit("failed to load the scene"), name)
+        }
+        None => (scene, "default".to_string()),
+    };
+
+    // the background and the number of bounces of a scene file replace those of the
+    // settings, `--set` replaces both
+    let settings = settings.with_scene_values(&scene);
+
+    // `--camera <name>` renders from one of the scene cameras instead of the default one
+    let settings = match args.iter().position(|arg| arg == "--camera") {
+        Some(index) => {
+            let name = args.get(index + 1).or_usage("--camera <name>").clone();
+            args.drain(index..index + 2);
+            let camera = scene.camera(&name).unwrap_or_else(|| {
+                exit_with(ExitCode::Usage, &tr_format("unknown camera '{}'", &[&name]))
+            });
+            settings.with_camera(camera.clone())
+        }
+        None => settings,
+    };
+
+    // `--set <parameter>=<value>` overrides a value of the scene or of the settings after
+    // they are loaded (e.g. `--set camera.fov=60 --set materials.mirror.ior=1.3`, see
+    // `set_parameter`), it can be repeated and comes after the overrides listed in the
+    // `RAY_TRACING_SET` environment variable
+    let mut scene = scene;
+    let mut settings = settings;
+    let mut overrides = env_overrides();
+    while let Some(index) = args.iter().position(|arg| arg == "--set") {
+        let text = args
+            .get(index + 1)
+            .or_usage("--set <parameter>=<value>")
+            .clone();
+        args.drain(index..index + 2);
+        overrides.push(text);
     }
-    Some((spheres_dist < 1000.0, point, n, material))
-}
-
-/// Compute the color of the ray at the point of intersection
-///
-/// ### Arguments
-///
-/// * `origin` - The origin of the ray (point of origin)
-/// * `direction` - The direction of the ray (normalized)
-/// * `spheres` - The list of spheres in the scene
-/// * `lights` - The list of lights in the scene
-///
-/// ### Returns
-///
-/// Vector3<f64> - The color of the ray at the point of intersection
-///
-/// ### Notes
-///
-/// The ray is defined by the parametric equation:
-///
-/// `P(t) = origin + t * direction`
-///
-fn cast_ray(
-    origin: Vector3<f64>,
-    direction: Vector3<f64>,
-    spheres: &Vec<Sphere>,
-    lights: &Vec<Light>,
-    depth: u32,
-) -> Vector3<f64> {
-    // check if the ray intersects any object
-    // if it does, compute the intersection point, the normal and the color
-    // if it doesn't, or if the maximum recursion depth has been reached (to avoid infinite recursion
-    // when the ray hits the mirror surface), return the background color
-    let (is_intersect, point, n, material) = scene_intersect(origin, direction, spheres).unwrap();
-    if !is_intersect || depth > MAX_RECURSION_DEPTH {
-        return BACKGROUND_COLOR;
-    }
-
-    // compute the reflection direction (not need to normalize because all vectors are already
-    // normalized) and the color of the reflected ray (recursive call, cuz the reflected ray can
-    // also reflect on other surfaces)
-    let reflect_direction = reflect(direction, n);
-    let reflect_origin = if reflect_direction.dot(&n) < 0.0 {
-        point - n * 1e-3
+    for text in &overrides {
+        apply_override(&mut scene, &mut settings, text).or_exit("failed to apply an override");
+    }
+
+    // `--soft-shadows` turns the point lights of the scene into spheres of radius 2 traced
+    // with 16 shadow rays, for soft shadow edges at 16 times the cost of the shadows
+    if let Some(index) = args.iter().position(|arg| arg == "--soft-shadows") {
+        args.remove(index);
+        let lights: Vec<SceneLight> = scene
+            .lights
+            .iter()
+            .map(|light| {
+                if light.radius > 0.0 {
+                    light.clone()
+                } else {
+                    light.clone().with_radius(2.0).with_shadow_samples(16)
+                }
+            })
+            .collect();
+        scene = scene.with_lights(lights);
+    }
+
+    // `--auto-frame` moves the camera along its view axis to see every object of the scene
+    // with a margin of 5%, for imported scenes that don't fit the camera (see `Camera::frame`)
+    if let Some(index) = args.iter().position(|arg| arg == "--auto-frame") {
+        args.remove(index);
+        let camera = settings.camera.frame(
+            &scene.bounds(),
+            0.05,
+            settings.width,
+            settings.height,
+            settings.fov,
+        );
+        settings = settings.with_camera(camera);
+    }
+
+    // the rays leaving the scene see its environment map if it has one, and all the rays
+    // go through its medium
+    let settings = settings
+        .with_environment(scene.environment.clone())
+        .with_medium(scene.medium.clone());
+
+    // `--demo <scene>[:<parameter>]` renders one of the benchmark scenes instead of this one
+    // (see `demo_command`)
+    #[cfg(feature = "bench-scenes")]
+    let demo = args.iter().position(|arg| arg == "--demo").map(|index| {
+        let name = args.get(index + 1).or_usage("--demo <scene>[:<parameter>]");
+        let name = name.clone();
+        args.drain(index..index + 2);
+        name
+    });
+
+    // `post <command> ...` runs one of the pass and AOV commands, e.g. `post depth linear`,
+    // and `preview material <file>` is `preview-material <file>`
+    if args.get(1).map(String::as_str) == Some("post") {
+        let command = args.get(2).map(String::as_str).unwrap_or("");
+        if !POST_COMMANDS.contains(&command) {
+            exit_with_usage(&format!("post <{}> ...", POST_COMMANDS.join(" | ")));
+        }
+        args.remove(1);
+    }
+    if args.get(1).map(String::as_str) == Some("preview")
+        && args.get(2).map(String::as_str) == Some("material")
+    {
+        args.splice(1..3, ["preview-material".to_string()]);
+    }
+    if let Some(command) = args.get(1) {
+        if !COMMANDS.iter().any(|(name, _)| name == command) {
+            let template = "unknown command '{}', `help` lists the commands";
+            let message = tr_format(template, &[command]);
+            exit_with(ExitCode::Usage, &message);
+        }
+    }
+
+    let context = Context {
+        args,
+        scene,
+        scene_name,
+        settings,
+        pipeline,
+        config,
+        json,
+        pixel_format,
+        caption_corner,
+        flare,
+        shake,
+    };
+    #[cfg(feature = "bench-scenes")]
+    if let Some(name) = demo {
+        demo_command(&context, &name);
+        return;
+    }
+    match context.args.get(1).map(String::as_str) {
+        Some("help") => print_commands(),
+        Some("completions") => completions_command(&context),
+        Some("validate") => validate_command(&context),
+        Some("info") => info_command(&context),
+        Some("selftest") => selftest_command(&context),
+        Some("batch") => batch_command(&context),
+        Some("furnace") => furnace_command(&context),
+        Some("serve") => serve_command(&context),
+        Some("bake") => bake_command(&context),
+        Some("probes") => probes_command(&context),
+        Some("mattes") => mattes_command(&context),
+        Some("layers") => layers_command(&context),
+        Some("gbuffer") => gbuffer_command(&context),
+        Some("light-passes") => light_passes_command(&context),
+        Some("passes") => passes_command(&context),
+        Some("lpe") => lpe_command(&context),
+        Some("motion-vectors") => motion_vectors_command(&context),
+        Some("depth") => depth_command(&context),
+        Some("deep") => deep_command(&context),
+        Some("animate") => animate_command(&context),
+        Some("fog") => fog_command(&context),
+        Some("reconstruct") => reconstruct_command(&context),
+        Some("false-color") => false_color_command(&context),
+        Some("uv-checker") => uv_checker_command(&context),
+        Some("bvh") => bvh_command(&context),
+        Some("glass") => glass_command(&context),
+        Some("compare") => compare_command(&context),
+        Some("diff") => diff_command(&context),
+        Some("sweep") => sweep_command(&context),
+        Some("preview-material") => preview_material_command(&context),
+        Some("resize") => resize_command(&context),
+        Some("lut") => lut_command(&context),
+        Some("pack") => pack_command(&context),
+        Some("thumbnail") => thumbnail_command(&context),
+        Some("codegen") => codegen_command(&context),
+        Some("save-scene") => save_scene_command(&context),
+        Some("envmap") => envmap_command(&context),
+        Some("cameras") => cameras_command(&context),
+        Some("preview") => preview_command(&context),
+        _ => render_command(&context),
+    }
+}
+
+/// Arguments, scene, settings and options of an invocation, shared by the commands
+struct Context {
+    /// The arguments left once the flags are taken out, the command first
+    args: Vec<String>,
+    scene: Scene,
+    /// Name of the scene file without its extension, `default` for the built-in scene
+    scene_name: String,
+    settings: RenderSettings,
+    pipeline: ColorPipeline,
+    config: Config,
+    /// Whether `--json` asks for the output as JSON
+    json: bool,
+    pixel_format: Option<PixelFormat>,
+    caption_corner: Option<Corner>,
+    flare: Option<LensFlare>,
+    shake: Option<CameraShake>,
+}
+
+impl Context {
+    /// Path of an output file, in the output directory if there is one
+    fn output(&self, name: &str) -> String {
+        self.config.output_path(name)
+    }
+
+    /// Values of the tokens of the output name of an image rendered with the given settings
+    fn path_tokens(&self, settings: &RenderSettings, frame: u32) -> PathTokens {
+        PathTokens {
+            scene: self.scene_name.clone(),
+            camera: settings.camera.name.clone(),
+            frame,
+            samples_per_pixel: settings.samples_per_pixel.max(1),
+            width: settings.width,
+            height: settings.height,
+        }
+    }
+
+    /// Caption of an image rendered with the given settings, if `--caption` asks for one
+    fn caption(&self, settings: &RenderSettings) -> Option<Caption> {
+        let corner = self.caption_corner?;
+        Some(Caption::new(&self.scene_name, settings).with_corner(corner))
+    }
+}
+
+/// `--demo <scene>[:<parameter>]` renders one of the benchmark scenes instead of the scene
+/// into `out_demo.png` and reports the render time (see `BenchScene::from_name`)
+#[cfg(feature = "bench-scenes")]
+fn demo_command(context: &Context, name: &str) {
+    let bench = BenchScene::from_name(name).unwrap_or_else(|| {
+        exit_with(
+            ExitCode::Usage,
+            &tr_format("unknown demo scene '{}'", &[&name]),
+        )
+    });
+    let settings = context
+        .settings
+        .clone()
+        .with_fov(bench.fov)
+        .with_camera(bench.scene.cameras[0].clone())
+        .with_environment(bench.scene.environment.clone())
+        .with_medium(bench.scene.medium.clone());
+    let start = Instant::now();
+    let framebuffer = render(&settings, &bench.scene, &context.pipeline);
+    println!(
+        "{}: {} spheres, {}x{} pixels, {} samples per pixel, {} threads: {:.3} s",
+        name,
+        bench.scene.spheres.len(),
+        settings.width,
+        settings.height,
+        settings.samples_per_pixel,
+        settings.thread_count(),
+        start.elapsed().as_secs_f64()
+    );
+    framebuffer_to_image(&framebuffer, settings.width, settings.height)
+        .save(&context.output("out_demo.png"))
+        .or_exit_io("failed to write the image");
+}
+
+/// `completions bash | zsh | fish` prints a completion script for the shell, e.g.
+/// `ray_tracing completions bash > /etc/bash_completion.d/ray_tracing`
+fn completions_command(context: &Context) {
+    let args = &context.args;
+    let usage = format!("completions {}", SHELLS.join(" | "));
+    let shell = args.get(2).or_usage(&usage);
+    let script = completion_script(shell, "ray_tracing", COMMANDS, FLAGS).or_usage(&usage);
+    print!("{}", script);
+}
+
+/// `validate` checks that the textures load, that the objects, lights and cameras aren't
+/// degenerate and that no name is used twice, and exits with an error if anything is wrong
+fn validate_command(context: &Context) {
+    let Context {
+        scene,
+        settings,
+        json,
+        ..
+    } = context;
+    let problems = validate(scene, settings);
+    if *json {
+        let list = problems.iter().map(|p| Json::String(p.clone())).collect();
+        let report = Json::Object(vec![
+            ("ok".to_string(), Json::Bool(problems.is_empty())),
+            ("problems".to_string(), Json::Array(list)),
+        ]);
+        println!("{}", report.to_pretty_string());
+    } else if problems.is_empty() {
+        println!("ok");
     } else {
-        point + n * 1e-3
+        for problem in &problems {
+            println!("{}", problem);
+        }
+    }
+    if !problems.is_empty() {
+        std::process::exit(ExitCode::Failure.code());
+    }
+}
+
+/// `info` describes the image, the sampling and the contents of the scene
+fn info_command(context: &Context) {
+    let Context {
+        scene,
+        settings,
+        json,
+        ..
+    } = context;
+    let info = SceneInfo::new(scene, settings);
+    if *json {
+        println!("{}", info.to_json().to_pretty_string());
+    } else {
+        println!("{}", info);
+    }
+}
+
+/// `selftest` renders a tiny built-in scene, compares the hash of its pixels with the
+/// expected one and times the render of a tile, to check that an installation works, and
+/// exits with an error if the render doesn't match
+fn selftest_command(context: &Context) {
+    let Context { settings, json, .. } = context;
+    let report = selftest(settings.threads);
+    if *json {
+        println!("{}", report.to_json().to_pretty_string());
+    } else {
+        println!(
+            "render: {} (checksum {:016x}, expected {:016x})",
+            if report.passed() { "ok" } else { "mismatch" },
+            report.checksum,
+            report.expected
+        );
+        println!("backend: cpu");
+        println!("{}", report.tile);
+    }
+    if !report.passed() {
+        std::process::exit(ExitCode::Failure.code());
+    }
+}
+
+/// `batch <file>` runs every line of the file (except empty lines and `#` comments) as
+/// the arguments of a separate invocation, e.g. `--scene "my scene.json" depth`, split like a
+/// shell does (see `split_arguments`), and exits with an error if any of them failed
+fn batch_command(context: &Context) {
+    let args = &context.args;
+    let path = args.get(2).or_usage("batch <file>");
+    let text = std::fs::read_to_string(path).or_exit("failed to read the batch file");
+    let executable = std::env::current_exe().or_exit("failed to find the executable");
+    // every line is split before any runs, so a quote left open stops nothing halfway
+    let invocations: Vec<(usize, &str, Vec<String>)> = text
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            let arguments = split_arguments(line)
+                .or_exit_format("invalid line {} of the batch file", &[&(number + 1)]);
+            (number, line, arguments)
+        })
+        .collect();
+    let mut failed = 0;
+    for (number, line, arguments) in invocations {
+        println!("{}", line);
+        let status = std::process::Command::new(&executable)
+            .args(arguments)
+            .status();
+        if !status.is_ok_and(|status| status.success()) {
+            eprintln!("line {} failed: {}", number + 1, line);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        std::process::exit(ExitCode::Failure.code());
+    }
+}
+
+/// `furnace [<material.json>]` renders a sphere of the material (white and diffuse by
+/// default) lit by a uniform environment and exits with an error if its average radiance
+/// is not the one of the environment, which catches materials creating or losing energy
+fn furnace_command(context: &Context) {
+    let Context { args, json, .. } = context;
+    let material = match args.get(2) {
+        Some(path) => Material::load(path).or_exit("failed to load the material"),
+        None => Material::new(Vector3::new(1.0, 0.0, 0.0), Color::WHITE, 10.0),
     };
-    let reflect_color = cast_ray(
-        reflect_origin,
-        reflect_direction,
-        spheres,
-        lights,
-        depth + 1,
+    let report = furnace_test(material, FURNACE_TOLERANCE);
+    if *json {
+        println!("{}", report.to_json().to_pretty_string());
+    } else {
+        println!(
+            "furnace: {} (mean {:?}, environment {:?}, deviation {:.4}, tolerance {})",
+            if report.passed() { "ok" } else { "mismatch" },
+            report.mean.to_array(),
+            report.environment.to_array(),
+            report.deviation,
+            report.tolerance
+        );
+    }
+    if !report.passed() {
+        std::process::exit(ExitCode::Failure.code());
+    }
+}
+
+/// `serve [<address>] [interlaced]` renders the scene for every `GET /render` request and
+/// streams the PNG image as it renders, the query string holding overrides for that
+/// render only (see `serve`), on 127.0.0.1:8080 by default; `interlaced` sends Adam7
+/// interlaced images once they are rendered instead
+fn serve_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        pipeline,
+        ..
+    } = context;
+    let interlaced = args.iter().skip(2).any(|arg| arg == "interlaced");
+    let address = args
+        .iter()
+        .skip(2)
+        .find(|arg| *arg != "interlaced")
+        .map_or("127.0.0.1:8080", String::as_str);
+    println!("serving renders on http://{}/render", address);
+    serve(address, scene, settings, pipeline, interlaced).or_exit("failed to start the server");
+}
+
+/// `bake <sphere index> [ao]` bakes the lighting of one sphere into its UV layout, saved
+/// to `bake.png` or in the `--pixel-format`; `ao` bakes the ambient occlusion instead,
+/// with the bent normals alongside in `bake_bent_normal.png`
+fn bake_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        pipeline,
+        pixel_format,
+        ..
+    } = context;
+    let index: usize = args
+        .get(2)
+        .and_then(|arg| arg.parse().ok())
+        .or_usage("bake <sphere index> [ao]");
+    let modes = if args.get(3).map(String::as_str) == Some("ao") {
+        let (samples, max_distance) = (64, 5.0);
+        vec![
+            (
+                "bake",
+                BakeMode::AmbientOcclusion {
+                    samples,
+                    max_distance,
+                },
+            ),
+            (
+                "bake_bent_normal",
+                BakeMode::BentNormal {
+                    samples,
+                    max_distance,
+                },
+            ),
+        ]
+    } else {
+        vec![("bake", BakeMode::Lightmap)]
+    };
+    let target = scene.spheres.get(index).unwrap_or_else(|| {
+        let template = "sphere index {} is out of range, the scene has {} spheres";
+        let message = tr_format(template, &[&index, &scene.spheres.len()]);
+        exit_with(ExitCode::Usage, &message)
+    });
+    for (name, mode) in modes {
+        if let Some(format) = *pixel_format {
+            let values = bake_values(target, 512, 256, mode, scene);
+            // floats keep the linear values, integers are encoded for display
+            let values: Vec<Color> = if format.is_float() || !mode.is_color() {
+                values
+            } else {
+                values.iter().map(|value| pipeline.apply(*value)).collect()
+            };
+            let file = context.output(&format!("{}.{}", name, format.extension()));
+            save_pixels(&file, 512, 256, format, &values).or_exit_io("failed to write the bake");
+            continue;
+        }
+        let image = bake(target, 512, 256, mode, scene, pipeline);
+        image
+            .save(&context.output(&format!("{}.png", name)))
+            .or_exit_io("failed to write the image");
+    }
+}
+
+/// `probes <x,y,z> ...` bakes the light arriving at every position into spherical
+/// harmonics with the integrator of the settings, saved to `probes.json`
+fn probes_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        ..
+    } = context;
+    let usage = "probes <x,y,z> [<x,y,z> ...]";
+    let positions: Vec<Vector3<f64>> = args[2..]
+        .iter()
+        .map(|arg| {
+            let coordinates: Vec<f64> = arg
+                .split(',')
+                .map(|c| c.trim().parse().or_usage(usage))
+                .collect();
+            match coordinates[..] {
+                [x, y, z] => Vector3::new(x, y, z),
+                _ => exit_with_usage(usage),
+            }
+        })
+        .collect();
+    if positions.is_empty() {
+        exit_with_usage(usage);
+    }
+    let probes: Vec<IrradianceProbe> = positions
+        .iter()
+        .map(|position| IrradianceProbe::bake(*position, scene, settings, 1024))
+        .collect();
+    let text = probes_to_json(&probes).to_pretty_string() + "\n";
+    std::fs::write(context.output("probes.json"), text).or_exit_io("failed to write probes.json");
+}
+
+/// `mattes` writes per-object and per-material ID mattes instead of the beauty image
+fn mattes_command(context: &Context) {
+    let Context {
+        scene, settings, ..
+    } = context;
+    let mattes = id_mattes(settings, scene, 4);
+    for index in 0..mattes.objects.len() {
+        mattes
+            .object_matte(index)
+            .save(&context.output(&format!("matte_object_{}.png", index)))
+            .or_exit_io("failed to write the image");
+    }
+    for index in 0..mattes.materials.len() {
+        mattes
+            .material_matte(index)
+            .save(&context.output(&format!("matte_material_{}.png", index)))
+            .or_exit_io("failed to write the image");
+    }
+    mattes
+        .id_preview()
+        .save(&context.output("matte_ids.png"))
+        .or_exit_io("failed to write the image");
+}
+
+/// `layers` renders the render layers of the scene into separate images, the foreground
+/// spheres and the mirrors of the built-in scene (see `LayerSpec`)
+fn layers_command(context: &Context) {
+    let Context {
+        scene,
+        settings,
+        pipeline,
+        ..
+    } = context;
+    if scene.layers.is_empty() {
+        exit_with(ExitCode::Usage, &tr("the scene defines no render layers"));
+    }
+    let layers: Vec<RenderLayer> = scene
+        .layers
+        .iter()
+        .map(|layer| layer.resolve(scene).or_exit("invalid render layer"))
+        .collect();
+    let images = render_layers(settings, scene, &layers, pipeline);
+    for (layer, image) in layers.iter().zip(images) {
+        image
+            .save(&context.output(&format!("layer_{}.png", layer.name)))
+            .or_exit_io("failed to write the image");
+    }
+}
+
+/// `gbuffer` writes the position, normal, albedo, object id and curvature of the surface
+/// seen at every pixel center to `gbuffer_*.pfm`, to relight the frame in other tools
+fn gbuffer_command(context: &Context) {
+    let Context {
+        scene, settings, ..
+    } = context;
+    let center_settings = settings.clone().with_samples_per_pixel(1);
+    let hits = PrimaryHits::trace(&center_settings, scene);
+    GBuffer::from_hits(&hits, scene)
+        .save(&context.output("gbuffer"))
+        .or_exit_io("failed to write the G-buffer");
+}
+
+/// `light-passes [<group>=<light>,<light> ...]` writes the contribution of every light
+/// (or of every group of lights, named after the scene lights) to `light_<name>.pfm` and
+/// the background to `light_background.pfm`, the passes add up to the full render
+fn light_passes_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    let groups: Vec<LightGroup> = if args.len() > 2 {
+        args[2..]
+            .iter()
+            .map(|arg| {
+                let (name, members) = arg
+                    .split_once('=')
+                    .or_usage("light-passes [<group>=<light>,<light> ...]");
+                let lights = members
+                    .split(',')
+                    .map(|member| {
+                        scene
+                            .lights
+                            .iter()
+                            .position(|light| light.name.as_deref() == Some(member))
+                            .unwrap_or_else(|| {
+                                let template = "no light named '{}' in the scene";
+                                exit_with(ExitCode::Usage, &tr_format(template, &[&member]))
+                            })
+                    })
+                    .collect();
+                LightGroup::new(name, lights)
+            })
+            .collect()
+    } else {
+        LightGroup::per_light(&scene.lights)
+    };
+    let passes = render_light_groups(settings, scene, &groups);
+    let names = groups
+        .iter()
+        .map(|group| group.name.as_str())
+        .chain([BACKGROUND_PASS]);
+    for (name, pass) in names.zip(passes) {
+        let values: Vec<f64> = pass.iter().flat_map(|c| c.to_array()).collect();
+        write_pfm(
+            &context.output(&format!("light_{}.pfm", name)),
+            width,
+            height,
+            3,
+            &values,
+        )
+        .or_exit_io("failed to write the light pass");
+    }
+}
+
+/// `passes` writes the diffuse, specular, reflection, transmission and emission
+/// components of the render to `pass_<name>.pfm`, the passes add up to the full render
+fn passes_command(context: &Context) {
+    let Context {
+        scene, settings, ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    let passes = render_passes(settings, scene);
+    for name in PASS_NAMES {
+        let values: Vec<f64> = passes
+            .pass(name)
+            .iter()
+            .flat_map(|c| c.to_array())
+            .collect();
+        write_pfm(
+            &context.output(&format!("pass_{}.pfm", name)),
+            width,
+            height,
+            3,
+            &values,
+        )
+        .or_exit_io("failed to write the pass");
+    }
+}
+
+/// `lpe <name>=<expression> ...` writes the light of the paths matching every light path
+/// expression to `lpe_<name>.pfm`, e.g. `lpe glass="C T+ D L"`
+fn lpe_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    let usage = "lpe <name>=<expression> ...";
+    if args.len() < 3 {
+        exit_with_usage(usage);
+    }
+    for arg in &args[2..] {
+        let (name, text) = arg.split_once('=').or_usage(usage);
+        let expression = LightPathExpression::parse(text)
+            .or_exit_format("invalid light path expression {}", &[&name]);
+        let pass = render_lpe(settings, scene, &expression);
+        let values: Vec<f64> = pass.iter().flat_map(|c| c.to_array()).collect();
+        write_pfm(
+            &context.output(&format!("lpe_{}.pfm", name)),
+            width,
+            height,
+            3,
+            &values,
+        )
+        .or_exit_io("failed to write the light path pass");
+    }
+}
+
+/// `motion-vectors <previous camera> [<previous scene.json>]` writes the screen space
+/// motion since a previous frame seen by the named camera to `motion.pfm` (offsets in
+/// pixels in the red and green channels), the previous frame being the current scene or
+/// the one loaded from the file
+fn motion_vectors_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    let usage = "motion-vectors <previous camera> [<previous scene.json>]";
+    let camera_name = args.get(2).or_usage(usage);
+    let previous_scene = match args.get(3) {
+        Some(path) => Scene::load(path).or_exit("failed to load the previous scene"),
+        None => scene.clone(),
+    };
+    let camera = previous_scene.camera(camera_name).unwrap_or_else(|| {
+        exit_with(
+            ExitCode::Usage,
+            &tr_format("unknown camera '{}'", &[camera_name]),
+        )
+    });
+    let previous_settings = settings.clone().with_camera(camera.clone());
+    let center_settings = settings.clone().with_samples_per_pixel(1);
+    let hits = PrimaryHits::trace(&center_settings, scene);
+    let vectors = motion_vectors(
+        &hits,
+        &scene.spheres,
+        &previous_settings,
+        &previous_scene.spheres,
     );
+    let values: Vec<f64> = vectors.iter().flat_map(|[x, y]| [*x, *y, 0.0]).collect();
+    write_pfm(&context.output("motion.pfm"), width, height, 3, &values)
+        .or_exit_io("failed to write the motion");
+}
+
+/// `depth [linear | normalized | inverse] [<near> <far>]` writes the distance of the
+/// surfaces along the view axis to `depth.pfm` (or in the `--pixel-format`), encoded over
+/// the given range (the range of the visible surfaces by default), for depth of field and
+/// fog in compositing
+fn depth_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        pixel_format,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    let usage = "depth [linear | normalized | inverse] [<near> <far>]";
+    let encoding = match args.get(2) {
+        Some(name) => DepthEncoding::from_name(name).or_usage(usage),
+        None => DepthEncoding::Normalized,
+    };
+    let center_settings = settings.clone().with_samples_per_pixel(1);
+    let hits = PrimaryHits::trace(&center_settings, scene);
+    let depths = camera_depths(&hits, &settings.camera);
+    let (near, far) = match (args.get(3), args.get(4)) {
+        (Some(near), Some(far)) => (
+            near.parse::<f64>().or_usage(usage),
+            far.parse::<f64>().or_usage(usage),
+        ),
+        _ => depth_range(&depths).unwrap_or((1.0, 1.0)),
+    };
+    if near <= 0.0 && encoding == DepthEncoding::Inverse {
+        let message = "the near end of an inverse depth range must be positive";
+        exit_with(ExitCode::Usage, &tr(message));
+    }
+    let values: Vec<f64> = depths
+        .iter()
+        .map(|depth| encoding.encode(*depth, near, far))
+        .collect();
+    if let Some(format) = *pixel_format {
+        let values: Vec<Color> = values.into_iter().map(Color::gray).collect();
+        let file = context.output(&format!("depth.{}", format.extension()));
+        save_pixels(&file, width, height, format, &values).or_exit_io("failed to write the depth");
+        return;
+    }
+    write_pfm(&context.output("depth.pfm"), width, height, 1, &values)
+        .or_exit_io("failed to write the depth");
+}
+
+/// `deep` writes a deep image with per-pixel sample lists to `out.deep`
+fn deep_command(context: &Context) {
+    let Context {
+        scene, settings, ..
+    } = context;
+    render_deep(settings, scene, 2)
+        .save(&context.output("out.deep"))
+        .or_exit_io("failed to write the deep image");
+}
 
-    // compute color diffused by lambertian shading
-    // lambertian shading is the simplest and most common shading model:
-    // the color of a point is proportional to the cosine of the angle between the normal and the
-    // light vector
-    let mut diffuse_light_intensity = 0.0;
-    let mut specular_light_intensity = 0.0;
-    for light in lights {
-        let light_direction = (light.position - point).normalize();
-        let light_distance = (light.position - point).norm();
-
-        // evaluate shadow origin and direction to check if the point is in shadow
-        let shadow_origin = if light_direction.dot(&n) < 0.0 {
-            point - n * 1e-3
+/// `animate <frames>` renders the animation of the scene at its frame rate (see
+/// `SceneAnimation`), a flickering candle-like key light at 24 frames per second in the
+/// built-in scene, `animate <frames> temporal [history weight]` jitters the camera rays in
+/// every frame differently and blends each frame with the previous ones (0.8 of history by default);
+/// the frames are listed with their times and input hashes in `frames.json`, updated after
+/// every frame, and running the command again skips the frames it lists as rendered from
+/// the same inputs (except for temporal sequences, whose frames all depend on the first)
+fn animate_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        pipeline,
+        config,
+        flare,
+        shake,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    let frames: u32 = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(24);
+    let animation = scene.animation.clone().unwrap_or_default();
+    let animations = animation
+        .light_animations(scene)
+        .or_exit("invalid light animation");
+    let temporal = args.get(3).map(String::as_str) == Some("temporal");
+    let weight: f64 = args.get(4).and_then(|arg| arg.parse().ok()).unwrap_or(0.8);
+    let mut accumulator = TemporalAccumulator::new(width, height, weight);
+    let mut previous_settings: Option<RenderSettings> = None;
+    let previous = SequenceManifest::load(&context.output("frames.json"))
+        .ok()
+        .filter(|_| !temporal);
+    let mut manifest = SequenceManifest::new(animation.fps, width, height);
+    let directory = Path::new(config.output_dir.as_deref().unwrap_or("."));
+    if config
+        .output_name
+        .as_deref()
+        .is_some_and(|name| !has_token(name, "frame"))
+    {
+        exit_with(
+            ExitCode::Usage,
+            "the output name of an animation needs a {frame} token",
+        );
+    }
+    for frame in 0..frames {
+        let time = frame as f64 / animation.fps;
+        let frame_lights = animate_lights(&scene.lights, &animations, time);
+        let frame_scene = scene.clone().with_lights(frame_lights);
+        let frame_settings = if temporal {
+            settings.clone().with_seed(frame as u64 + 1)
         } else {
-            point + n * 1e-3
+            settings.clone()
         };
-
-        // Check if the point lies in the shadow of the current light
-        // If it does, skip this light
-        // If it doesn't, add the contribution of the light to the diffuse and specular light
-        let (shadow_intersect, shadow_pt, _, _) =
-            scene_intersect(shadow_origin, light_direction, spheres).unwrap();
-        if shadow_intersect && (shadow_pt - shadow_origin).norm() < light_distance {
+        let frame_settings = match &shake {
+            Some(shake) => {
+                let camera = shake.apply(&frame_settings.camera, time);
+                frame_settings.with_camera(camera)
+            }
+            None => frame_settings,
+        };
+        let file = config
+            .image_name(
+                "frame_{frame:04}.png",
+                &context.path_tokens(&frame_settings, frame),
+            )
+            .or_exit("invalid output name");
+        let hash = frame_hash(&frame_settings, &frame_scene.spheres, &frame_scene.lights);
+        let completed = previous
+            .as_ref()
+            .and_then(|p| p.completed(directory, &file, hash));
+        if let Some(done) = completed {
+            manifest.frames.push(done.clone());
             continue;
         }
+        let path = context.output(&file);
+        create_parent_directory(&path);
+        if temporal {
+            let radiance = render_radiance(&frame_settings, &frame_scene);
+            // the spheres stand still, the history follows the camera when it shakes
+            let motion = previous_settings
+                .as_ref()
+                .filter(|_| shake.is_some())
+                .map(|p| {
+                    let center_settings = frame_settings.clone().with_samples_per_pixel(1);
+                    let hits = PrimaryHits::trace(&center_settings, &frame_scene);
+                    motion_vectors(&hits, &frame_scene.spheres, p, &frame_scene.spheres)
+                });
+            previous_settings = Some(frame_settings.clone());
+            let blended = accumulator.accumulate(&radiance, motion.as_deref());
+            let mut image = Image::new(width, height);
+            for (k, color) in blended.iter().enumerate() {
+                let (i, j) = (k as u32 % width, k as u32 / width);
+                let [r, g, b] = pipeline.to_rgb8(*color);
+                image.set_pixel_rgb(i, j, r, g, b);
+            }
+            if let Some(flare) = &flare {
+                flare.apply(&mut image, &frame_scene, &frame_settings, pipeline);
+            }
+            if let Some(caption) = context.caption(&frame_settings) {
+                caption.with_frame(frame).burn(&mut image);
+            }
+            image
+                .save(&path)
+                .or_exit_io_format("failed to write {}", &[&path]);
+        } else {
+            let framebuffer = render(&frame_settings, &frame_scene, pipeline);
+            let mut image = framebuffer_to_image(&framebuffer, width, height);
+            if let Some(flare) = &flare {
+                flare.apply(&mut image, &frame_scene, &frame_settings, pipeline);
+            }
+            if let Some(caption) = context.caption(&frame_settings) {
+                caption.with_frame(frame).burn(&mut image);
+            }
+            image
+                .save(&path)
+                .or_exit_io_format("failed to write {}", &[&path]);
+        }
+        manifest.frames.push(FrameRecord { file, time, hash });
+        // the manifest keeps track of the finished frames in case the render is interrupted
+        manifest
+            .save(&context.output("frames.json"))
+            .or_exit_io("failed to write frames.json");
+    }
+    manifest
+        .save(&context.output("frames.json"))
+        .or_exit_io("failed to write frames.json");
+}
 
-        // add the contribution of the light to color diffusing
-        let max_light = nalgebra::clamp(light_direction.dot(&n), 0.0, 1.0);
-        diffuse_light_intensity += light.intensity * max_light;
-
-        // process specular light
-        let minus_ref = reflect(light_direction, n).dot(&direction);
-        let power = nalgebra::clamp(minus_ref, 0.0, 1.0);
-        specular_light_intensity += power.powf(material.specular_exponent) * light.intensity;
-    }
-    let mut diffuse_color = material.diffuse_color * diffuse_light_intensity * material.albedo[0];
-    diffuse_color += Vector3::new(1.0, 1.0, 1.0) * specular_light_intensity * material.albedo[1];
-    diffuse_color += reflect_color * material.albedo[2];
-    diffuse_color
-}
-
-/// Asyncronous version of the `cast_ray` function with same arguments
-/// except for the `i` and `j` arguments which are used to write the
-/// pixel color to the image buffer
-async fn cast_ray_async(
-    origin: Vector3<f64>,
-    direction: Vector3<f64>,
-    spheres: &Vec<Sphere>,
-    lights: &Vec<Light>,
-    depth: u32,
-    i: u32,
-    j: u32,
-) -> (u32, u32, Vector3<f64>) {
-    (i, j, cast_ray(origin, direction, spheres, lights, depth))
-}
-
-/// Render a scene with spheres and lights
-async fn render(
-    width: u32,
-    height: u32,
-    fov: f64,
-    spheres: &Vec<Sphere>,
-    lights: &Vec<Light>,
-) -> Vec<u8> {
-    // `buffer` is a 1D array of pixels (RGB triplets) with the size of the image
-    let mut buffer = vec![0; (width * height * 3) as usize];
-    let mut tasks = Vec::new();
+/// `fog [cloud | fire | <file.vol>]` adds a spot light shining through fog on top of the
+/// scene lights
+fn fog_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        pipeline,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    // dim the scene lights so the shaft of the spot light stands out
+    let mut fog_lights = scene.lights.clone();
+    for light in fog_lights.iter_mut() {
+        light.intensity *= 0.5;
+    }
+    fog_lights.push(SceneLight::spot(
+        Vector3::new(-8.0, 12.0, -8.0),
+        Vector3::new(7.0, -13.5, -4.0),
+        12.0,
+        0.25,
+        0.3,
+    ));
+    let mut fog = Fog::new(0.04, 0.6);
+    // `fog cloud` uses a procedural cloud, `fog fire` a procedural fireball lighting the
+    // scene and `fog <file.vol>` loads a density grid
+    if let Some(source) = args.get(2) {
+        let center = Vector3::new(3.0, 3.0, -14.0);
+        let cloud = |p: Vector3<f64>| {
+            let d = p - center;
+            let falloff = 1.0 - (d.x * d.x / 25.0 + d.y * d.y / 16.0 + d.z * d.z / 16.0);
+            let bumps = (p.x * 2.1).sin() * (p.y * 2.7).sin() * (p.z * 1.9).sin();
+            (falloff + 0.35 * bumps).max(0.0)
+        };
+        let (min, max) = (
+            center - Vector3::new(5.0, 4.0, 4.0),
+            center + Vector3::new(5.0, 4.0, 4.0),
+        );
+        if source == "fire" {
+            let heat = DensityGrid::from_fn(min, max, [60, 48, 48], |p| cloud(p) * 1.5 - 0.2);
+            let emission = Emission::new(Arc::new(heat), 800.0, 2200.0, 0.3);
+            fog_lights.extend(emission.to_lights(3, 0.02));
+            let smoke = DensityGrid::from_fn(min, max, [30, 24, 24], cloud);
+            fog = Fog::new(0.2, 0.0)
+                .with_grid(Arc::new(smoke))
+                .with_emission(emission);
+        } else {
+            let grid = if source == "cloud" {
+                DensityGrid::from_fn(min, max, [60, 48, 48], cloud)
+            } else {
+                DensityGrid::load_vol(source).or_exit("failed to load the density grid")
+            };
+            fog = Fog::new(0.5, 0.3).with_grid(Arc::new(grid));
+        }
+    }
+    let fog_scene = scene.clone().with_lights(fog_lights);
+    let framebuffer = render_fog(settings, &fog_scene, &fog, pipeline);
+    framebuffer_to_image(&framebuffer, width, height)
+        .save(&context.output("out_fog.png"))
+        .or_exit_io("failed to write the image");
+}
 
-    for j in 0..height {
-        for i in 0..width {
-            // X and Y calculated from the camera's perspective by the formula
-            // x = (2 * (i + 0.5) / width - 1) * tan(fov / 2) * width / height
-            // y = -(2 * (j + 0.5) / height - 1) * tan(fov / 2)
-            // z = -1
-            let x =
-                (2.0 * (i as f64 + 0.5) / width as f64 - 1.0) * (fov / 2.0).tan() * width as f64
-                    / height as f64;
-            let y = -(2.0 * (j as f64 + 0.5) / height as f64 - 1.0) * (fov / 2.0).tan();
-            // The camera is at (0, 0, 0) and looks along the negative Z axis
-            // The direction of the ray is the normalized vector from the camera to the pixel
-            let direction = Vector3::new(x, y, -1.0).normalize();
-            let task = cast_ray_async(Vector3::default(), direction, spheres, lights, 0, i, j);
-            tasks.push(task);
+/// `reconstruct [shadow samples]` renders with few shadow rays per light and writes the
+/// noisy frame next to the one reconstructed with a filter guided by the AOVs (and the
+/// pixels with invalid radiance, if any)
+fn reconstruct_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        pipeline,
+        ..
+    } = context;
+    let samples: u32 = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(2);
+    let noisy_lights: Vec<SceneLight> = scene
+        .lights
+        .iter()
+        .map(|light| light.clone().with_shadow_samples(samples))
+        .collect();
+    let noisy_scene = scene.clone().with_lights(noisy_lights);
+    let frame = render_framebuffer(settings, &noisy_scene);
+    frame
+        .to_image(pipeline)
+        .save(&context.output("out_noisy.png"))
+        .or_exit_io("failed to write the image");
+    if frame.invalid.contains(&true) {
+        frame
+            .nan_highlight(pipeline)
+            .save(&context.output("out_invalid.png"))
+            .or_exit_io("failed to write the image");
+    }
+    // the filter comes from the settings, it is applied to the listed component passes
+    // only when there are any
+    let reconstructed = if settings.denoise_passes.is_empty() {
+        frame.reconstruct(&settings.denoise)
+    } else {
+        let passes = render_passes(settings, &noisy_scene);
+        let mut reconstructed = frame.clone();
+        reconstructed.color =
+            passes.reconstruct(&frame, &settings.denoise, &settings.denoise_passes);
+        reconstructed
+    };
+    reconstructed
+        .to_image(pipeline)
+        .save(&context.output("out_reconstructed.png"))
+        .or_exit_io("failed to write the image");
+}
+
+/// `false-color [isolines]` writes the luminance of the render through a false color ramp
+/// to `out_false_color.png`, optionally with lines between whole EV steps
+fn false_color_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        ..
+    } = context;
+    let isolines = args.get(2).map(String::as_str) == Some("isolines");
+    render_framebuffer(settings, scene)
+        .false_color(isolines)
+        .save(&context.output("out_false_color.png"))
+        .or_exit_io("failed to write the image");
+}
+
+/// `uv-checker [tiles]` draws a UV checkerboard over every sphere to inspect the UV layout
+fn uv_checker_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        pipeline,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    let tiles: u32 = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(8);
+    let overlay = Overlay::UvChecker { tiles };
+    let framebuffer = render_overlay(settings, scene, overlay, pipeline);
+    framebuffer_to_image(&framebuffer, width, height)
+        .save(&context.output("out_uv.png"))
+        .or_exit_io("failed to write the image");
+}
+
+/// `bvh [max depth]` writes the boxes of the bounding volume hierarchy of the scene to
+/// `bvh.obj`, one group of lines per depth, to see why a scene is slow to trace
+fn bvh_command(context: &Context) {
+    let Context { args, scene, .. } = context;
+    let max_depth = args
+        .get(2)
+        .map(|arg| arg.parse().or_usage("bvh [max depth]"));
+    std::fs::write(context.output("bvh.obj"), scene.bvh().to_obj(max_depth))
+        .or_exit_io("failed to write bvh.obj");
+}
+
+/// `glass` adds a glass ball overlapped by a water ball with an air bubble in it, to check
+/// the refraction through nested transparent objects (the glass has priority over the
+/// water where they overlap and the bubble over both)
+fn glass_command(context: &Context) {
+    let Context {
+        scene,
+        settings,
+        pipeline,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    let glass = Material::new(
+        Vector3::new(0.0, 0.5, 0.1),
+        Color::new(0.6, 0.7, 0.8),
+        125.0,
+    )
+    .with_refraction(0.8, 1.5)
+    .with_priority(2);
+    let water = Material::new(
+        Vector3::new(0.0, 0.3, 0.05),
+        Color::new(0.6, 0.7, 0.8),
+        125.0,
+    )
+    .with_refraction(0.9, 1.33)
+    .with_priority(1);
+    let bubble = Material::new(
+        Vector3::new(0.0, 0.2, 0.05),
+        Color::new(1.0, 1.0, 1.0),
+        125.0,
+    )
+    .with_refraction(1.0, 1.0)
+    .with_priority(3);
+    let glass_scene = Scene::builder()
+        .add_spheres(scene.spheres.clone())
+        .add_sphere(Sphere::new(Vector3::new(2.5, -2.5, -10.0), 2.0, glass))
+        .add_sphere(Sphere::new(Vector3::new(2.5, -3.6, -10.0), 1.4, water))
+        .add_sphere(Sphere::new(Vector3::new(2.9, -3.2, -10.0), 0.4, bubble))
+        .add_lights(scene.lights.clone())
+        .build()
+        .or_exit("invalid glass scene");
+    let framebuffer = render(settings, &glass_scene, pipeline);
+    framebuffer_to_image(&framebuffer, width, height)
+        .save(&context.output("out_glass.png"))
+        .or_exit_io("failed to write the image");
+}
+
+/// `compare [<left.json> <right.json>]` renders the two halves of the image with different
+/// settings into `out_compare.png`, hard against soft shadows by default
+fn compare_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        pipeline,
+        ..
+    } = context;
+    let image = match (args.get(2), args.get(3)) {
+        (Some(left), Some(right)) => {
+            let load = |path: &String| {
+                RenderSettings::load(path).or_exit("failed to load the render settings")
+            };
+            let label = |path: &String| {
+                let stem = std::path::Path::new(path).file_stem();
+                stem.map_or(path.clone(), |stem| stem.to_string_lossy().to_string())
+            };
+            render_split(
+                &Variant::new(&label(left), load(left), scene),
+                &Variant::new(&label(right), load(right), scene),
+                pipeline,
+            )
+        }
+        _ => {
+            let hard_lights: Vec<SceneLight> = scene
+                .lights
+                .iter()
+                .map(|light| light.clone().with_radius(0.0).with_shadow_samples(1))
+                .collect();
+            let hard_scene = scene.clone().with_lights(hard_lights);
+            render_split(
+                &Variant::new("hard shadows", settings.clone(), &hard_scene),
+                &Variant::new("soft shadows", settings.clone(), scene),
+                pipeline,
+            )
+        }
+    };
+    image
+        .save(&context.output("out_compare.png"))
+        .or_exit_io("failed to write the image");
+}
+
+/// `diff <before.json> <after.json>` renders two scene files with the same settings and
+/// seeds into `out_diff.png`, the signed difference of their luminance as a heatmap
+fn diff_command(context: &Context) {
+    let Context { args, settings, .. } = context;
+    let load = |index: usize| {
+        let path = args.get(index).or_usage("diff <before.json> <after.json>");
+        Scene::load(path).or_exit("failed to load the scene")
+    };
+    let (image, largest) = render_difference(settings, &load(2), &load(3));
+    println!("largest difference of luminance: {}", largest);
+    image
+        .save(&context.output("out_diff.png"))
+        .or_exit_io("failed to write the image");
+}
+
+/// `sweep <parameter>=<values> [<parameter>=<values>]` renders the scene for every value
+/// of one or two parameters into a labeled contact sheet `out_sweep.png`, every render a
+/// quarter of the image size, e.g. `sweep materials.mirror.ior=1.1,1.3,1.5 fov=40,60`
+fn sweep_command(context: &Context) {
+    let Context {
+        args,
+        scene,
+        settings,
+        pipeline,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    let parse = |arg: &String| SweepAxis::parse(arg).or_exit("failed to parse the sweep");
+    let rows = parse(args.get(2).or_usage("sweep <parameter>=<values> [...]"));
+    let columns = args.get(3).map(parse);
+    let cell_settings = RenderSettings {
+        width: (width / 4).max(1),
+        height: (height / 4).max(1),
+        ..settings.clone()
+    };
+    let sheet = render_sweep(scene, &cell_settings, &rows, columns.as_ref(), pipeline)
+        .or_exit("failed to render the sweep");
+    sheet
+        .save(&context.output("out_sweep.png"))
+        .or_exit_io("failed to write the image");
+}
+
+/// `preview-material <material.json>` renders the material on the shader ball scene from
+/// its fixed camera into `out_preview.png`
+fn preview_material_command(context: &Context) {
+    let Context {
+        args,
+        settings,
+        pipeline,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    let path = args.get(2).or_usage("preview-material <material.json>");
+    let material = Material::load(path).or_exit("failed to load the material");
+    let preview = shader_ball(material);
+    let preview_settings = settings
+        .clone()
+        .with_fov(SHADER_BALL_FOV.to_radians())
+        .with_camera(preview.cameras[0].clone());
+    let framebuffer = render(&preview_settings, &preview, pipeline);
+    framebuffer_to_image(&framebuffer, width, height)
+        .save(&context.output("out_preview.png"))
+        .or_exit_io("failed to write the image");
+}
+
+/// `resize <input.png> <output> <width>x<height> [box | bilinear | lanczos]` resizes an
+/// image, e.g. to make the smaller levels of a texture, filtering the pixels as linear
+/// values with a Lanczos filter by default
+fn resize_command(context: &Context) {
+    let args = &context.args;
+    let usage = "resize <input.png> <output> <width>x<height> [box|bilinear|lanczos]";
+    let (Some(input), Some(output), Some(size)) = (args.get(2), args.get(3), args.get(4)) else {
+        exit_with_usage(usage);
+    };
+    let (width, height) = size
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .or_usage(usage);
+    check_image_size(width, height).or_exit("failed to resize the image");
+    let filter = match args.get(5) {
+        Some(name) => ResizeFilter::from_name(name).or_usage(usage),
+        None => ResizeFilter::Lanczos3,
+    };
+    let source = TextureImage::load(input).or_exit("failed to load the image");
+    let image = Image {
+        width: source.width,
+        height: source.height,
+        data: (0..source.width as usize * source.height as usize)
+            .flat_map(|k| {
+                source
+                    .texel(k)
+                    .to_array()
+                    .map(|c| (c * 255.0).round() as u8)
+            })
+            .collect(),
+    };
+    image
+        .resize(width, height, filter)
+        .save(output)
+        .or_exit_io_format("failed to write {}", &[&output]);
+}
+
+/// `lut <input.png> <file.cube> <output>` grades an image, e.g. a render, with a 1D or 3D
+/// LUT from a grading tool
+fn lut_command(context: &Context) {
+    let args = &context.args;
+    let usage = "lut <input.png> <file.cube> <output>";
+    let (Some(input), Some(lut), Some(output)) = (args.get(2), args.get(3), args.get(4)) else {
+        exit_with_usage(usage);
+    };
+    let lut = Lut::load(Path::new(lut)).or_exit("failed to load the LUT");
+    let source = TextureImage::load(input).or_exit("failed to load the image");
+    let mut image = Image {
+        width: source.width,
+        height: source.height,
+        data: (0..source.width as usize * source.height as usize)
+            .flat_map(|k| {
+                source
+                    .texel(k)
+                    .to_array()
+                    .map(|c| (c * 255.0).round() as u8)
+            })
+            .collect(),
+    };
+    lut.apply_image(&mut image);
+    image
+        .save(output)
+        .or_exit_io_format("failed to write {}", &[&output]);
+}
+
+/// `pack <scene.json> <scene.rtpack>` bundles a scene file with the files it includes and
+/// its textures and environment maps, `--scene` loads the archive like the scene file
+fn pack_command(context: &Context) {
+    let args = &context.args;
+    let usage = "pack <scene.json> <scene.rtpack>";
+    let (Some(input), Some(output)) = (args.get(2), args.get(3)) else {
+        exit_with_usage(usage);
+    };
+    let pack = ScenePack::collect(Path::new(input)).or_exit("failed to pack the scene");
+    pack.save(Path::new(output))
+        .or_exit_io("failed to write the scene pack");
+    println!("packed {} files into {}", pack.files.len(), output);
+}
+
+/// `thumbnail <scene.json> <output.png> [size]` renders a draft of a scene file from its
+/// first camera, 256 pixels on its longest side by default, with one sample per pixel and
+/// hard shadows, for the thumbnails of a file browser
+fn thumbnail_command(context: &Context) {
+    let Context {
+        args,
+        settings,
+        pipeline,
+        ..
+    } = context;
+    let usage = "thumbnail <scene.json> <output.png> [size]";
+    let (Some(input), Some(output)) = (args.get(2), args.get(3)) else {
+        exit_with_usage(usage);
+    };
+    let size = args
+        .get(4)
+        .map_or(THUMBNAIL_SIZE, |arg| arg.parse().or_usage(usage));
+    let scene = Scene::load(input).or_exit("failed to load the scene");
+    let scene_settings = settings
+        .clone()
+        .with_scene_values(&scene)
+        .with_camera(scene.cameras[0].clone())
+        .with_environment(scene.environment.clone())
+        .with_medium(scene.medium.clone());
+    let thumbnail_settings = thumbnail_settings(&scene_settings, size);
+    let framebuffer = render(&thumbnail_settings, &thumbnail_scene(&scene), pipeline);
+    let (width, height) = (thumbnail_settings.width, thumbnail_settings.height);
+    framebuffer_to_image(&framebuffer, width, height)
+        .save(output)
+        .or_exit_io_format("failed to write {}", &[&output]);
+}
+
+/// `codegen <scene.json> [<output.rs>]` writes a Rust function building the scene of a
+/// scene file with the builders of the crate, on stdout without an output file, and tells
+/// what it leaves out (see `codegen::scene_to_rust`)
+fn codegen_command(context: &Context) {
+    let args = &context.args;
+    let usage = "codegen <scene.json> [<output.rs>]";
+    let input = args.get(2).or_usage(usage);
+    let scene = Scene::load(input).or_exit("failed to load the scene");
+    let export = scene_to_rust(&scene);
+    for omission in &export.omitted {
+        eprintln!("{}: {}", tr("left out"), omission);
+    }
+    match args.get(3) {
+        Some(output) => {
+            std::fs::write(output, &export.text).or_exit_io_format("failed to write {}", &[output])
         }
+        None => print!("{}", export.text),
     }
-    let results = futures::future::join_all(tasks).await;
-    for (i, j, color) in results {
-        let index = (i + j * width) as usize;
-        buffer[index * 3] = (color.x * 255.0) as u8;
-        buffer[index * 3 + 1] = (color.y * 255.0) as u8;
-        buffer[index * 3 + 2] = (color.z * 255.0) as u8;
+}
+
+/// `save-scene <output.json>` writes the scene of the command line (the built-in one, or the
+/// one of `--scene` with its `--set` overrides) as a scene file and tells what it leaves out
+/// (see `codegen::scene_to_json`)
+fn save_scene_command(context: &Context) {
+    let Context { args, scene, .. } = context;
+    let output = args.get(2).or_usage("save-scene <output.json>");
+    let export = scene_to_json(scene);
+    for omission in &export.omitted {
+        eprintln!("{}: {}", tr("left out"), omission);
     }
-    buffer
+    std::fs::write(output, &export.text).or_exit_io_format("failed to write {}", &[output]);
 }
 
-fn main() {
-    let ivory = Material::new(
-        Vector3::new(0.6, 0.3, 0.1),
-        Vector3::new(0.4, 0.4, 0.3),
-        50.0,
-    );
-    let red_rubber = Material::new(
-        Vector3::new(0.9, 0.1, 0.0),
-        Vector3::new(0.3, 0.1, 0.1),
-        10.0,
-    );
-    let mirror = Material::new(
-        Vector3::new(0.0, 10.0, 0.8),
-        Vector3::new(1.0, 1.0, 1.0),
-        1425.0,
-    );
+/// `envmap to-cube|to-equirect <input.png> <output.png> [size]` converts an environment
+/// map between the equirectangular layout and a cubemap with its six faces side by side
+/// (`size` is the size of a face, 512 by default, or the width of the equirectangular
+/// map, 2048 by default)
+fn envmap_command(context: &Context) {
+    let args = &context.args;
+    let usage = "envmap to-cube|to-equirect <input.png> <output.png> [size]";
+    let (Some(mode), Some(input), Some(output)) = (args.get(2), args.get(3), args.get(4)) else {
+        exit_with_usage(usage);
+    };
+    let source = TextureImage::load(input).or_exit("failed to load the environment map");
+    let size = args.get(5).map(|arg| arg.parse::<u32>().or_usage(usage));
+    let image = match mode.as_str() {
+        "to-cube" => equirect_to_cubemap(&source, size.unwrap_or(512)),
+        "to-equirect" => cubemap_to_equirect(&source, size.unwrap_or(2048)),
+        _ => exit_with_usage(usage),
+    };
+    image
+        .save(output)
+        .or_exit_io_format("failed to write {}", &[&output]);
+}
 
-    let spheres = vec![
-        Sphere::new(Vector3::new(-3.0, 0.0, -16.0), 2.0, ivory),
-        Sphere::new(Vector3::new(-1.0, -1.5, -12.0), 2.0, red_rubber),
-        Sphere::new(Vector3::new(1.5, -0.5, -18.0), 3.0, mirror),
-        Sphere::new(Vector3::new(7.0, 5.0, -18.0), 4.0, mirror),
-    ];
+/// `cameras` renders the scene from every camera into `out_<camera name>.png`
+fn cameras_command(context: &Context) {
+    let Context {
+        scene,
+        settings,
+        pipeline,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+    for camera in &scene.cameras {
+        let camera_settings = settings.clone().with_camera(camera.clone());
+        let framebuffer = render(&camera_settings, scene, pipeline);
+        framebuffer_to_image(&framebuffer, width, height)
+            .save(&context.output(&format!("out_{}.png", camera.name)))
+            .or_exit_io("failed to write the image");
+    }
+}
 
-    let lights = vec![
-        Light::new(Vector3::new(-20.0, 20.0, 20.0), 1.5),
-        Light::new(Vector3::new(30.0, 50.0, -25.0), 1.8),
-        Light::new(Vector3::new(30.0, 20.0, 30.0), 1.7),
-    ];
+/// `preview` renders a quick draft of the beauty image into `out_preview.png`, by default
+/// at a quarter of its size, with one sample per pixel and one shadow ray per light (see
+/// `PreviewSettings`)
+fn preview_command(context: &Context) {
+    let Context {
+        scene,
+        settings,
+        pipeline,
+        config,
+        ..
+    } = context;
+    let draft_settings = config.preview_settings(settings);
+    let draft_lights: Vec<SceneLight> = scene
+        .lights
+        .iter()
+        .map(|light| {
+            light
+                .clone()
+                .with_shadow_samples(config.preview.shadow_samples)
+        })
+        .collect();
+    let draft_scene = scene.clone().with_lights(draft_lights);
+    let framebuffer = render(&draft_settings, &draft_scene, pipeline);
+    let mut image = framebuffer_to_image(&framebuffer, draft_settings.width, draft_settings.height);
+    if let Some(caption) = context.caption(&draft_settings) {
+        caption.burn(&mut image);
+    }
+    image
+        .save(&context.output("out_preview.png"))
+        .or_exit_io("failed to write the image");
+}
+
+/// `render` (or no command) writes the beauty image, or the outputs listed by the scene
+fn render_command(context: &Context) {
+    let Context {
+        scene,
+        settings,
+        pipeline,
+        config,
+        json,
+        flare,
+        ..
+    } = context;
+    let (width, height) = (settings.width, settings.height);
+
+    // a scene listing its outputs gets them written, each in its own format and color space,
+    // instead of the beauty image
+    if !scene.outputs.is_empty() {
+        let layers = OutputLayers::render(&scene.outputs, settings, scene);
+        for spec in &scene.outputs {
+            let path = context.output(&spec.file);
+            create_parent_directory(&path);
+            save_pixels(
+                &path,
+                width,
+                height,
+                spec.format,
+                &spec.values(&layers, pipeline),
+            )
+            .or_exit_io_format("failed to write {}", &[&spec.file]);
+        }
+        return;
+    }
+
+    // holdouts cut the alpha of the beauty image, which is then written as an RGBA image
+    // once the whole frame is rendered, and so do supersampling, captions, flares and toon
+    // outlines
+    let start = Instant::now();
+    let holdouts = scene.spheres.iter().any(|sphere| sphere.holdout);
+    let outlines = matches!(settings.integrator, IntegratorKind::Toon(toon) if toon.outline);
+    let caption = context.caption(settings);
+    let beauty_name = config
+        .image_name("out.png", &context.path_tokens(settings, 0))
+        .or_exit("invalid output name");
+    let beauty_path = context.output(&beauty_name);
+    create_parent_directory(&beauty_path);
+    if holdouts || settings.supersampling > 1 || caption.is_some() || flare.is_some() || outlines {
+        let mut image = if settings.supersampling > 1 {
+            render_supersampled(settings, scene, pipeline)
+                .or_exit("failed to supersample the image")
+        } else {
+            let framebuffer = render(settings, scene, pipeline);
+            framebuffer_to_image(&framebuffer, width, height)
+        };
+        if let Some(flare) = &flare {
+            flare.apply(&mut image, scene, settings, pipeline);
+        }
+        if let Some(caption) = caption {
+            caption.burn(&mut image);
+        }
+        if holdouts {
+            let alpha = holdout_alpha(settings, scene);
+            image
+                .save_with_alpha(&beauty_path, &alpha)
+                .or_exit_io_format("failed to write {}", &[&beauty_path]);
+        } else {
+            image
+                .save(&beauty_path)
+                .or_exit_io_format("failed to write {}", &[&beauty_path]);
+        }
+    } else {
+        // the beauty image is written while it renders, a band of rows at a time when the
+        // settings set a memory budget
+        render_to_file(settings, scene, pipeline, &beauty_path)
+            .or_exit_io_format("failed to write {}", &[&beauty_name]);
+    }
+    if *json {
+        let statistics =
+            RenderStatistics::new(&beauty_path, settings, start.elapsed().as_secs_f64());
+        println!("{}", statistics.to_json().to_pretty_string());
+    }
+}
 
-    // size of resulting image
-    let (width, height) = (1024, 768);
-    // field of view in radians (90 degrees)
-    let fov = PI / 2.0;
-    let framebuffer: Vec<u8> = block_on(render(width, height, fov, &spheres, &lights));
+/// Create the directories of an output path that don't exist yet
+fn create_parent_directory(path: &str) {
+    if let Some(parent) = Path::new(path).parent() {
+        std::fs::create_dir_all(parent).or_exit_io("failed to create the output directory");
+    }
+}
 
+/// Copy an RGB framebuffer returned by `render` into an image
+fn framebuffer_to_image(framebuffer: &[u8], width: u32, height: u32) -> Image {
     let mut image = Image::new(width, height);
+    let row_size = width as usize * 3;
     for j in 0..height {
-        for i in 0..width {
-            let pixel_index: usize = (j * width + i) as usize;
-            let color: Vec<u8> = vec![
-                framebuffer[pixel_index * 3],
-                framebuffer[pixel_index * 3 + 1],
-                framebuffer[pixel_index * 3 + 2],
-            ];
-            image.set_pixel(i, j, color);
-        }
+        let start = j as usize * row_size;
+        image.write_row(j, &framebuffer[start..start + row_size]);
+    }
+    image
+}
+
+/// Print the commands with what they do
+fn print_commands() {
+    println!("usage: ray_tracing [<command>] [--settings <file.json>] [--scene <file.json>]");
+    println!("       [--camera <name>] [--set <parameter>=<value> ...]");
+    println!();
+    for (name, description) in COMMANDS {
+        println!("  {:<18}{}", name, tr(description));
     }
-    image.save("out.png");
 }
@@ -0,0 +1,177 @@
+use crate::color::Color;
+use crate::image::Image;
+use crate::object::Material;
+use crate::render::camera_rays;
+use crate::render::nearest_sphere;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+/// Cryptomatte-style ID mattes: the coverage of every object and every material in
+/// each pixel, estimated from a grid of sub-pixel samples
+///
+/// Pixel edges shared by several objects get fractional coverage, so the mattes can be
+/// used in compositing to isolate individual spheres (or all spheres made of one material)
+pub struct IdMattes {
+    pub width: u32,
+    pub height: u32,
+    /// `objects[k][pixel]` - coverage of the k-th sphere, in the range `[0, 1]`
+    pub objects: Vec<Vec<f64>>,
+    /// `materials[m][pixel]` - coverage of the m-th distinct material, in the range `[0, 1]`
+    pub materials: Vec<Vec<f64>>,
+    /// Index into `materials` for every sphere of the scene
+    pub material_of_object: Vec<usize>,
+}
+
+/// Compute ID mattes for the spheres of a scene seen from the default camera
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings (image size, field of view, bounces and background)
+/// * `scene` - The scene
+/// * `samples_per_axis` - Sub-pixel samples per axis (`n * n` samples per pixel)
+///
+/// ### Returns
+///
+/// IdMattes - Per-object and per-material coverage
+///
+pub fn id_mattes(settings: &RenderSettings, scene: &Scene, samples_per_axis: u32) -> IdMattes {
+    let (width, height) = (settings.width, settings.height);
+    let spheres = &scene.spheres;
+    // spheres sharing identical material parameters belong to the same material matte
+    let mut distinct: Vec<Material> = Vec::new();
+    let mut material_of_object = Vec::with_capacity(spheres.len());
+    for sphere in spheres {
+        let index = match distinct.iter().position(|m| *m == *sphere.material) {
+            Some(index) => index,
+            None => {
+                distinct.push(Material::clone(&sphere.material));
+                distinct.len() - 1
+            }
+        };
+        material_of_object.push(index);
+    }
+
+    let pixels = width as usize * height as usize;
+    let mut objects = vec![vec![0.0; pixels]; spheres.len()];
+    let mut materials = vec![vec![0.0; pixels]; distinct.len()];
+    let n = samples_per_axis.max(1);
+    let weight = 1.0 / (n * n) as f64;
+
+    for j in 0..height {
+        for i in 0..width {
+            let pixel = (j * width + i) as usize;
+            for sy in 0..n {
+                for sx in 0..n {
+                    // stratified sample positions inside the pixel
+                    let x = i as f64 + (sx as f64 + 0.5) / n as f64;
+                    let y = j as f64 + (sy as f64 + 0.5) / n as f64;
+                    let ray = settings.camera_ray(x, y);
+                    if let Some((index, _)) = nearest_sphere(&ray, scene) {
+                        // procedural objects have no mattes
+                        if let Some(object) = objects.get_mut(index) {
+                            object[pixel] += weight;
+                            materials[material_of_object[index]][pixel] += weight;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    IdMattes {
+        width,
+        height,
+        objects,
+        materials,
+        material_of_object,
+    }
+}
+
+/// Alpha of a render cut out by holdout objects: the fraction of the camera rays of every
+/// pixel (see `camera_rays`) whose first hit is not a holdout
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings (image size, camera and samples per pixel)
+/// * `scene` - The scene
+///
+/// ### Returns
+///
+/// `Vec<f64>` - The alpha of the pixels row by row, in the range `[0, 1]`
+///
+pub fn holdout_alpha(settings: &RenderSettings, scene: &Scene) -> Vec<f64> {
+    let mut alpha = Vec::with_capacity(settings.width as usize * settings.height as usize);
+    for j in 0..settings.height {
+        for i in 0..settings.width {
+            let rays = camera_rays(i, j, settings);
+            let visible = rays
+                .iter()
+                .filter(|ray| {
+                    // far hits are background for the renderer too
+                    !nearest_sphere(ray, scene).is_some_and(|(index, distance)| {
+                        scene.sphere(index).holdout && distance < 1000.0
+                    })
+                })
+                .count();
+            alpha.push(visible as f64 / rays.len() as f64);
+        }
+    }
+    alpha
+}
+
+impl IdMattes {
+    /// Grayscale matte of a single object
+    pub fn object_matte(&self, index: usize) -> Image {
+        self.coverage_image(&self.objects[index])
+    }
+
+    /// Grayscale matte of a single material
+    pub fn material_matte(&self, index: usize) -> Image {
+        self.coverage_image(&self.materials[index])
+    }
+
+    /// Preview with every object painted in a color derived from its ID and
+    /// weighted by its coverage
+    pub fn id_preview(&self) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for pixel in 0..self.width as usize * self.height as usize {
+            let mut color = Color::BLACK;
+            for (index, coverage) in self.objects.iter().enumerate() {
+                color += id_color(index as u32) * coverage[pixel];
+            }
+            let (i, j) = (pixel as u32 % self.width, pixel as u32 / self.width);
+            image.set_pixel(i, j, to_rgb8(color));
+        }
+        image
+    }
+
+    fn coverage_image(&self, coverage: &[f64]) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for (pixel, value) in coverage.iter().enumerate() {
+            let (i, j) = (pixel as u32 % self.width, pixel as u32 / self.width);
+            image.set_pixel(i, j, to_rgb8(Color::gray(*value)));
+        }
+        image
+    }
+}
+
+/// Stable pseudo-random color for an ID (integer hash spread over the RGB channels)
+fn id_color(id: u32) -> Color {
+    let mut h = id.wrapping_add(1).wrapping_mul(0x9E37_79B9);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 13;
+    Color::new(
+        (h & 0xFF) as f64 / 255.0,
+        ((h >> 8) & 0xFF) as f64 / 255.0,
+        ((h >> 16) & 0xFF) as f64 / 255.0,
+    )
+}
+
+fn to_rgb8(color: Color) -> [u8; 3] {
+    [
+        (color.r.clamp(0.0, 1.0) * 255.0).round() as u8,
+        (color.g.clamp(0.0, 1.0) * 255.0).round() as u8,
+        (color.b.clamp(0.0, 1.0) * 255.0).round() as u8,
+    ]
+}
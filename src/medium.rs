@@ -0,0 +1,378 @@
+use crate::color::Color;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::object::Sphere;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::settings::color_to_json;
+use crate::settings::json_to_color;
+use crate::settings::json_to_count;
+use crate::settings::json_to_f64;
+use crate::volume::Fog;
+
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+/// Participating medium filling a region of space, absorbing and scattering the light
+/// traveling through it: fog filling the whole scene or the inside of a dielectric object
+/// (colored glass, murky water)
+///
+/// Rays outside every medium travel through `Vacuum`, which leaves the light unchanged
+pub trait Medium: std::fmt::Debug + Send + Sync {
+    /// Fraction of the light going through a ray segment between its `t_min` and `t_max`
+    fn transmittance(&self, ray: &Ray) -> Color;
+
+    /// Sample where the light traveling along a ray segment is scattered, with a density
+    /// proportional to the transmittance up to there
+    ///
+    /// ### Arguments
+    ///
+    /// * `ray` - The segment (with a normalized direction) between its `t_min` and `t_max`
+    /// * `u` - A uniform number in `[0, 1)`
+    ///
+    /// ### Returns
+    ///
+    /// `MediumSample` - Where the light is scattered, or that it goes through the segment,
+    /// with the weight of the sample
+    ///
+    fn sample(&self, ray: &Ray, u: f64) -> MediumSample;
+
+    /// Henyey-Greenstein anisotropy of the scattering (see `volume::henyey_greenstein`)
+    fn anisotropy(&self) -> f64 {
+        0.0
+    }
+
+    /// Whether the medium leaves the light unchanged, so the integrators can skip it
+    fn is_vacuum(&self) -> bool {
+        false
+    }
+
+    /// Medium as a JSON value, read back by `medium_from_json`
+    fn to_json(&self) -> Json;
+}
+
+impl PartialEq for dyn Medium {
+    fn eq(&self, other: &dyn Medium) -> bool {
+        self.to_json() == other.to_json()
+    }
+}
+
+/// Outcome of `Medium::sample`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MediumSample {
+    /// The light is scattered at the distance `t` along the ray, `weight` multiplies the
+    /// light scattered there
+    Scattered { t: f64, weight: Color },
+    /// The light goes through the segment, `weight` multiplies the light at its end
+    Passed { weight: Color },
+}
+
+/// Empty space, the medium of the scenes without fog and of the objects without interior
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vacuum;
+
+/// Shared vacuum, the medium of the rays outside every other medium
+pub static VACUUM: Vacuum = Vacuum;
+
+impl Medium for Vacuum {
+    fn transmittance(&self, _ray: &Ray) -> Color {
+        Color::WHITE
+    }
+
+    fn sample(&self, _ray: &Ray, _u: f64) -> MediumSample {
+        MediumSample::Passed {
+            weight: Color::WHITE,
+        }
+    }
+
+    fn is_vacuum(&self) -> bool {
+        true
+    }
+
+    fn to_json(&self) -> Json {
+        Json::Object(vec![(
+            "type".to_string(),
+            Json::String("vacuum".to_string()),
+        )])
+    }
+}
+
+/// Medium with the same absorption and scattering everywhere, e.g. the tint of colored
+/// glass (absorption only) or milky water
+///
+/// The coefficients are fractions of the light lost per unit of distance, for every color
+/// channel; the light follows the Beer-Lambert law, segments reaching infinity let no light
+/// through
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HomogeneousMedium {
+    pub absorption: Color,
+    pub scattering: Color,
+    /// Henyey-Greenstein anisotropy, from -1 (backward) through 0 (isotropic) to 1 (forward)
+    pub anisotropy: f64,
+}
+
+impl HomogeneousMedium {
+    pub fn new(absorption: Color, scattering: Color) -> HomogeneousMedium {
+        HomogeneousMedium {
+            absorption,
+            scattering,
+            anisotropy: 0.0,
+        }
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f64) -> HomogeneousMedium {
+        self.anisotropy = anisotropy.clamp(-0.99, 0.99);
+        self
+    }
+
+    /// Extinction coefficient of every channel
+    fn extinction(&self) -> Color {
+        self.absorption + self.scattering
+    }
+
+    /// Transmittance over a distance, for every channel
+    fn transmittance_over(&self, distance: f64) -> Color {
+        let [r, g, b] = self.extinction().to_array().map(|sigma_t| {
+            if sigma_t <= 0.0 {
+                1.0
+            } else {
+                (-sigma_t * distance).exp()
+            }
+        });
+        Color::new(r, g, b)
+    }
+}
+
+impl Medium for HomogeneousMedium {
+    fn transmittance(&self, ray: &Ray) -> Color {
+        self.transmittance_over(ray.t_max - ray.t_min)
+    }
+
+    fn sample(&self, ray: &Ray, u: f64) -> MediumSample {
+        // distances are sampled with the average extinction of the channels, the weights
+        // make up for the channels with a different one
+        let sigma_t = self.extinction();
+        let average = (sigma_t.r + sigma_t.g + sigma_t.b) / 3.0;
+        if average <= 0.0 {
+            return MediumSample::Passed {
+                weight: Color::WHITE,
+            };
+        }
+        let t = ray.t_min - (1.0 - u).ln() / average;
+        if t < ray.t_max {
+            let density = average * (-average * (t - ray.t_min)).exp();
+            let transmittance = self.transmittance_over(t - ray.t_min);
+            MediumSample::Scattered {
+                t,
+                weight: self.scattering * transmittance / density,
+            }
+        } else {
+            let probability = (-average * (ray.t_max - ray.t_min)).exp();
+            MediumSample::Passed {
+                weight: self.transmittance(ray) / probability,
+            }
+        }
+    }
+
+    fn anisotropy(&self) -> f64 {
+        self.anisotropy
+    }
+
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("type".to_string(), Json::String("homogeneous".to_string())),
+            ("absorption".to_string(), color_to_json(self.absorption)),
+            ("scattering".to_string(), color_to_json(self.scattering)),
+            ("anisotropy".to_string(), Json::Number(self.anisotropy)),
+        ])
+    }
+}
+
+/// Medium of objects moved away from where it was defined, sampled at the positions the
+/// objects had: `offset` is added to the origins of the rays going through it (see
+/// `Scene::recentered`)
+///
+/// The distances along the rays are left as they are, so are the samples; the medium is
+/// written to JSON as its input
+#[derive(Clone, Debug)]
+pub struct OffsetMedium {
+    pub input: Arc<dyn Medium>,
+    pub offset: Vector3<f64>,
+}
+
+impl OffsetMedium {
+    /// The ray where the input medium was defined
+    fn moved(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: ray.origin + self.offset,
+            ..*ray
+        }
+    }
+}
+
+impl Medium for OffsetMedium {
+    fn transmittance(&self, ray: &Ray) -> Color {
+        self.input.transmittance(&self.moved(ray))
+    }
+
+    fn sample(&self, ray: &Ray, u: f64) -> MediumSample {
+        self.input.sample(&self.moved(ray), u)
+    }
+
+    fn anisotropy(&self) -> f64 {
+        self.input.anisotropy()
+    }
+
+    fn is_vacuum(&self) -> bool {
+        self.input.is_vacuum()
+    }
+
+    fn to_json(&self) -> Json {
+        self.input.to_json()
+    }
+}
+
+/// Read a medium from a JSON object with its `type` and its parameters, missing ones
+/// keeping their default values
+///
+/// * `vacuum` - no parameters
+/// * `homogeneous` - `absorption` and `scattering` colors and `anisotropy`
+/// * `fog` - `density`, `albedo`, `anisotropy` and `max_distance` (see `volume::Fog`)
+pub fn medium_from_json(json: &Json) -> Result<Arc<dyn Medium>, JsonError> {
+    let Json::Object(entries) = json else {
+        return Err(JsonError::new("a medium must be a JSON object"));
+    };
+    let kind = json
+        .get("type")
+        .and_then(Json::as_str)
+        .ok_or_else(|| JsonError::new("a medium must have a 'type' string"))?;
+    let unknown =
+        |key: &str| JsonError::new(&format!("unknown property '{}' of a {} medium", key, kind));
+    match kind {
+        "vacuum" => match entries.iter().find(|(key, _)| key != "type") {
+            Some((key, _)) => Err(unknown(key)),
+            None => Ok(Arc::new(Vacuum)),
+        },
+        "homogeneous" => {
+            let mut medium = HomogeneousMedium::new(Color::BLACK, Color::BLACK);
+            for (key, value) in entries {
+                match key.as_str() {
+                    "type" => {}
+                    "absorption" => medium.absorption = json_to_color(key, value)?,
+                    "scattering" => medium.scattering = json_to_color(key, value)?,
+                    "anisotropy" => {
+                        medium = medium.with_anisotropy(json_to_f64(key, value)?);
+                    }
+                    _ => return Err(unknown(key)),
+                }
+            }
+            Ok(Arc::new(medium))
+        }
+        "fog" => {
+            let mut fog = Fog::new(0.05, 0.0);
+            for (key, value) in entries {
+                match key.as_str() {
+                    "type" => {}
+                    "density" => fog.density = json_to_f64(key, value)?,
+                    "albedo" => fog.albedo = json_to_f64(key, value)?.clamp(0.0, 1.0),
+                    "anisotropy" => {
+                        fog.anisotropy = json_to_f64(key, value)?.clamp(-0.99, 0.99);
+                    }
+                    "max_distance" => fog.max_distance = json_to_f64(key, value)?,
+                    "steps" => fog.steps = json_to_count(key, value)?.max(1) as u32,
+                    _ => return Err(unknown(key)),
+                }
+            }
+            Ok(Arc::new(fog))
+        }
+        _ => Err(JsonError::new(&format!(
+            "unknown medium type '{}', expected vacuum, homogeneous or fog",
+            kind
+        ))),
+    }
+}
+
+/// Transparent objects a ray is currently inside, in the order they were entered
+///
+/// The stack is updated by counting entries and exits at every refraction, so a ray leaving
+/// a bubble inside a glass is known to be back in the glass rather than in the air, which
+/// the orientation of the surface normal alone can't tell for nested or overlapping objects
+///
+/// Overlapping objects are resolved with the medium priorities of their materials
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MediumStack {
+    /// Indices of the spheres, the last one is the innermost
+    inside: Vec<usize>,
+}
+
+impl MediumStack {
+    /// Media containing a point, for rays starting inside transparent objects
+    ///
+    /// Larger spheres are assumed to have been entered first
+    pub fn at(point: Vector3<f64>, spheres: &[Sphere]) -> MediumStack {
+        let mut inside: Vec<usize> = (0..spheres.len())
+            .filter(|&index| {
+                spheres[index].material.refraction > 0.0 && spheres[index].contains(point)
+            })
+            .collect();
+        inside.sort_by(|a, b| spheres[*b].radius.total_cmp(&spheres[*a].radius));
+        MediumStack { inside }
+    }
+
+    /// Whether the ray is inside the given sphere
+    pub fn contains(&self, index: usize) -> bool {
+        self.inside.contains(&index)
+    }
+
+    /// Index of refraction of the medium the ray travels in (1 outside every object)
+    ///
+    /// Where objects overlap, the one with the highest priority wins, then the innermost one
+    pub fn refractive_index(&self, scene: &Scene) -> f64 {
+        // `max_by_key` keeps the last of equal elements, the innermost one
+        self.inside
+            .iter()
+            .max_by_key(|&&index| scene.sphere(index).material.priority)
+            .map_or(1.0, |&index| scene.sphere(index).material.refractive_index)
+    }
+
+    /// Participating medium the ray travels in: the interior of the object chosen like for
+    /// `refractive_index`, `outside` (the medium of the scene) outside every object
+    ///
+    /// Objects without an interior medium are filled with vacuum, the fog of the scene
+    /// doesn't get into them
+    pub fn medium<'a>(&self, scene: &'a Scene, outside: &'a dyn Medium) -> &'a dyn Medium {
+        match self
+            .inside
+            .iter()
+            .max_by_key(|&&index| scene.sphere(index).material.priority)
+        {
+            Some(&index) => match &scene.sphere(index).material.interior {
+                Some(interior) => interior.as_ref(),
+                None => &VACUUM,
+            },
+            None => outside,
+        }
+    }
+
+    /// Whether the surface of the given sphere lies inside a medium of higher priority,
+    /// in which case the ray goes through it without refraction or shading
+    pub fn is_false_hit(&self, index: usize, scene: &Scene) -> bool {
+        let priority = scene.sphere(index).material.priority;
+        self.inside
+            .iter()
+            .any(|&other| other != index && scene.sphere(other).material.priority > priority)
+    }
+
+    /// Media after the ray crosses the surface of the given sphere: the sphere is left if the
+    /// ray was inside it and entered otherwise
+    pub fn cross(&self, index: usize) -> MediumStack {
+        let mut inside = self.inside.clone();
+        match inside.iter().position(|&entered| entered == index) {
+            Some(position) => {
+                inside.remove(position);
+            }
+            None => inside.push(index),
+        }
+        MediumStack { inside }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::object::Sphere;
+use crate::render::PrimaryHits;
+use crate::settings::RenderSettings;
+
+/// Screen space motion of the surfaces seen by the camera since the previous frame, for
+/// temporal denoisers and motion blur in post
+///
+/// Every pixel center holds the offset in pixels (+X right, +Y down) from the point of the
+/// previous frame its surface was seen at to the pixel center. Spheres move by translation,
+/// the sphere at an index of the previous scene being the same as at the index of the
+/// current one (spheres missing from it stand still); the background moves with the
+/// camera rotation only
+///
+/// ### Arguments
+///
+/// * `hits` - The camera ray hits of the current frame, traced with one sample per pixel
+/// * `spheres` - The spheres the hits were traced against
+/// * `previous_settings` - The render settings of the previous frame (its camera)
+/// * `previous_spheres` - The spheres of the previous frame
+///
+/// ### Returns
+///
+/// `Vec<[f64; 2]>` - The motion vectors row by row from the top left corner, zero where the
+/// surface was behind the previous camera
+///
+pub fn motion_vectors(
+    hits: &PrimaryHits,
+    spheres: &[Sphere],
+    previous_settings: &RenderSettings,
+    previous_spheres: &[Sphere],
+) -> Vec<[f64; 2]> {
+    let (width, height) = hits.size();
+    let mut vectors = Vec::with_capacity(width as usize * height as usize);
+    for j in 0..height {
+        for i in 0..width {
+            let (ray, hit) = hits.first_hit(i, j);
+            let previous_point = match hit {
+                Some((index, distance)) => {
+                    let translation = previous_spheres
+                        .get(index)
+                        .map_or(Default::default(), |previous| {
+                            spheres[index].center - previous.center
+                        });
+                    ray.at(distance) - translation
+                }
+                // a point at infinity in the direction of the ray
+                None => previous_settings.camera.position + ray.direction,
+            };
+            let vector = match previous_settings.project(previous_point) {
+                Some((x, y)) => [i as f64 + 0.5 - x, j as f64 + 0.5 - y],
+                None => [0.0, 0.0],
+            };
+            vectors.push(vector);
+        }
+    }
+    vectors
+}
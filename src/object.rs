@@ -1,84 +1,722 @@
+use crate::color::Color;
+use crate::colorspace::blackbody;
+use crate::colorspace::TextureSpace;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::medium::medium_from_json;
+use crate::medium::Medium;
+use crate::medium::OffsetMedium;
+use crate::ray::Ray;
+use crate::sampling::uniform_cone;
+use crate::sampling::uniform_sphere;
+use crate::sampling::Onb;
+use crate::settings::json_to_count;
+use crate::settings::json_to_f64;
+use crate::texture::json_to_texture_space;
+use crate::texture::texture_from_json;
+use crate::texture::SolidTexture;
+use crate::texture::Texture;
+use crate::texture::UvTransform;
+use crate::texturenodes::OffsetTexture;
+
 use nalgebra::Vector3;
 
-#[derive(Clone, Copy)]
+/// Distance of a ray origin from a sphere, in radii, beyond which the intersection is
+/// computed without the cancellations of the usual formula (see `Sphere::intersect`)
+pub const FAR_SPHERE_DISTANCE: f64 = 1e3;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
 pub struct Material {
-    pub diffuse_color: Vector3<f64>,
+    /// Diffuse color of the surface, a solid color or a texture varying over it
+    pub diffuse_color: Arc<dyn Texture>,
     pub albedo: Vector3<f64>,
-    pub specular_exponent: f64
+    pub specular_exponent: f64,
+    /// Weight of the light refracted through the surface (0 for opaque materials)
+    pub refraction: f64,
+    /// Index of refraction of the medium inside the object
+    pub refractive_index: f64,
+    /// Where transparent objects overlap, the medium with the highest priority fills the
+    /// overlap and the surfaces of the others are ignored inside it
+    pub priority: u32,
+    /// Participating medium filling a transparent object, vacuum without one
+    pub interior: Option<Arc<dyn Medium>>,
+    /// Path of an image texture multiplying the diffuse color, mapped with the UV layout of
+    /// the object
+    pub texture: Option<String>,
+    /// Scale of the UV coordinates of the object for the texture lookup, how many times the
+    /// texture repeats (or how many UDIM tiles it covers) along `u` and `v`
+    pub texture_scale: [f64; 2],
+    /// Color space of the image texture file
+    pub texture_space: TextureSpace,
+    /// Transform of the UV coordinates of the object before every texture lookup of the
+    /// material, the diffuse color texture and the image texture
+    pub uv_transform: UvTransform,
+    /// Name used to find the material in a scene, objects sharing it are edited together
+    pub name: Option<String>,
+}
+
+impl PartialEq for Material {
+    fn eq(&self, other: &Material) -> bool {
+        // textures compare through their JSON description
+        *self.diffuse_color == *other.diffuse_color
+            && self.albedo == other.albedo
+            && self.specular_exponent == other.specular_exponent
+            && self.refraction == other.refraction
+            && self.refractive_index == other.refractive_index
+            && self.priority == other.priority
+            && self.interior == other.interior
+            && self.texture == other.texture
+            && self.texture_scale == other.texture_scale
+            && self.texture_space == other.texture_space
+            && self.uv_transform == other.uv_transform
+            && self.name == other.name
+    }
 }
 
 impl Default for Material {
     fn default() -> Self {
         Material {
-            diffuse_color: Vector3::new(1.0, 0.0, 0.0),
+            diffuse_color: Arc::new(SolidTexture {
+                color: Color::new(1.0, 0.0, 0.0),
+            }),
             albedo: Vector3::new(1.0, 0.0, 0.0),
-            specular_exponent: 0.0
+            specular_exponent: 0.0,
+            refraction: 0.0,
+            refractive_index: 1.0,
+            priority: 0,
+            interior: None,
+            texture: None,
+            texture_scale: [1.0, 1.0],
+            texture_space: TextureSpace::Linear,
+            uv_transform: UvTransform::default(),
+            name: None,
         }
     }
 }
 
 impl Material {
-    pub fn new(albedo: Vector3<f64>, diffuse_color: Vector3<f64>, specular_exponent: f64) -> Material {
+    pub fn new(albedo: Vector3<f64>, diffuse_color: Color, specular_exponent: f64) -> Material {
         Material {
-            diffuse_color,
+            diffuse_color: Arc::new(SolidTexture {
+                color: diffuse_color,
+            }),
             albedo,
-            specular_exponent
+            specular_exponent,
+            refraction: 0.0,
+            refractive_index: 1.0,
+            priority: 0,
+            interior: None,
+            texture: None,
+            texture_scale: [1.0, 1.0],
+            texture_space: TextureSpace::Linear,
+            uv_transform: UvTransform::default(),
+            name: None,
+        }
+    }
+
+    /// The material of objects moved so that `origin` is the origin of their coordinates,
+    /// its diffuse color and its interior medium still looked up where the objects were
+    pub fn recentered(&self, origin: Vector3<f64>) -> Material {
+        Material {
+            diffuse_color: Arc::new(OffsetTexture {
+                input: self.diffuse_color.clone(),
+                offset: origin,
+            }),
+            interior: self.interior.clone().map(|input| {
+                Arc::new(OffsetMedium {
+                    input,
+                    offset: origin,
+                }) as Arc<dyn Medium>
+            }),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Material {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Make the material transparent, refracting light with the given weight
+    pub fn with_refraction(mut self, refraction: f64, refractive_index: f64) -> Material {
+        self.refraction = refraction;
+        self.refractive_index = refractive_index;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Material {
+        self.priority = priority;
+        self
+    }
+
+    /// Replace the diffuse color with a texture
+    pub fn with_diffuse_texture(mut self, texture: Arc<dyn Texture>) -> Material {
+        self.diffuse_color = texture;
+        self
+    }
+
+    /// Fill the transparent object with a participating medium
+    pub fn with_interior(mut self, medium: Arc<dyn Medium>) -> Material {
+        self.interior = Some(medium);
+        self
+    }
+
+    pub fn with_texture(mut self, path: &str) -> Material {
+        self.texture = Some(path.to_string());
+        self
+    }
+
+    pub fn with_texture_scale(mut self, scale_u: f64, scale_v: f64) -> Material {
+        self.texture_scale = [scale_u, scale_v];
+        self
+    }
+
+    /// Set the color space of the image texture file, `TextureSpace::Srgb` for painted colors
+    pub fn with_texture_space(mut self, space: TextureSpace) -> Material {
+        self.texture_space = space;
+        self
+    }
+
+    pub fn with_uv_transform(mut self, uv_transform: UvTransform) -> Material {
+        self.uv_transform = uv_transform;
+        self
+    }
+
+    /// Read a material from a JSON object, missing keys keep their default values
+    ///
+    /// `color` is the `[r, g, b]` diffuse color or a texture (see
+    /// `texture::texture_from_json`), `diffuse`, `specular` and `reflection` are
+    /// the albedo weights, `ior` the index of refraction, `interior` the medium inside the
+    /// object (see `medium::medium_from_json`), `texture` the path of a PNG
+    /// texture (see `texture::UDIM_TOKEN` for tiled textures), `texture_scale` the
+    /// `[u, v]` scale of its coordinates and `texture_space` the color space of the file
+    /// (`linear` or `srgb`). `uv_scale` and `uv_offset` (`[u, v]`) and
+    /// `uv_rotation` (in degrees) transform the UVs for all the textures of the material
+    /// (see `texture::UvTransform`)
+    pub fn from_json(json: &Json) -> Result<Material, JsonError> {
+        Material::from_json_in(json, Path::new(""))
+    }
+
+    /// Read a material from a JSON object, the paths of its image textures relative to
+    /// `directory`
+    pub fn from_json_in(json: &Json, directory: &Path) -> Result<Material, JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("a material must be a JSON object"));
+        };
+        let mut material = Material::default();
+        for (key, value) in entries {
+            match key.as_str() {
+                "name" => {
+                    let name = value
+                        .as_str()
+                        .ok_or_else(|| JsonError::new("'name' must be a string"))?;
+                    material.name = Some(name.to_string());
+                }
+                "color" => material.diffuse_color = texture_from_json(value, directory)?,
+                "diffuse" => material.albedo.x = json_to_f64(key, value)?,
+                "specular" => material.albedo.y = json_to_f64(key, value)?,
+                "reflection" => material.albedo.z = json_to_f64(key, value)?,
+                "specular_exponent" => material.specular_exponent = json_to_f64(key, value)?,
+                "refraction" => material.refraction = json_to_f64(key, value)?,
+                "ior" => material.refractive_index = json_to_f64(key, value)?,
+                "priority" => material.priority = json_to_count(key, value)? as u32,
+                "interior" => material.interior = Some(medium_from_json(value)?),
+                "texture" => {
+                    let path = value
+                        .as_str()
+                        .ok_or_else(|| JsonError::new("'texture' must be a string"))?;
+                    material.texture = Some(path.to_string());
+                }
+                "texture_scale" => match value.as_array() {
+                    Some([u, v]) => {
+                        material.texture_scale = [json_to_f64(key, u)?, json_to_f64(key, v)?]
+                    }
+                    _ => {
+                        return Err(JsonError::new(
+                            "'texture_scale' must be an array of 2 numbers",
+                        ))
+                    }
+                },
+                "texture_space" => material.texture_space = json_to_texture_space(key, value)?,
+                "uv_scale" => match value.as_array() {
+                    Some([u, v]) => {
+                        material.uv_transform.scale = [json_to_f64(key, u)?, json_to_f64(key, v)?]
+                    }
+                    _ => return Err(JsonError::new("'uv_scale' must be an array of 2 numbers")),
+                },
+                "uv_offset" => match value.as_array() {
+                    Some([u, v]) => {
+                        material.uv_transform.offset = [json_to_f64(key, u)?, json_to_f64(key, v)?]
+                    }
+                    _ => return Err(JsonError::new("'uv_offset' must be an array of 2 numbers")),
+                },
+                "uv_rotation" => {
+                    material.uv_transform.rotation = json_to_f64(key, value)?.to_radians()
+                }
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown material property '{}'",
+                        key
+                    )))
+                }
+            }
+        }
+        Ok(material)
+    }
+
+    /// Material as a JSON object (see `from_json`), the properties keeping their default
+    /// values left out but for the colors and the albedo weights
+    pub fn to_json(&self) -> Json {
+        let mut entries = Vec::new();
+        let mut add = |key: &str, value: Json| entries.push((key.to_string(), value));
+        let pair = |[u, v]: [f64; 2]| Json::Array(vec![Json::Number(u), Json::Number(v)]);
+        let defaults = Material::default();
+        if let Some(name) = &self.name {
+            add("name", Json::String(name.clone()));
+        }
+        add("color", self.diffuse_color.to_json());
+        add("diffuse", Json::Number(self.albedo.x));
+        add("specular", Json::Number(self.albedo.y));
+        add("reflection", Json::Number(self.albedo.z));
+        add("specular_exponent", Json::Number(self.specular_exponent));
+        if self.refraction != defaults.refraction {
+            add("refraction", Json::Number(self.refraction));
+        }
+        if self.refractive_index != defaults.refractive_index {
+            add("ior", Json::Number(self.refractive_index));
+        }
+        if self.priority != defaults.priority {
+            add("priority", Json::Number(self.priority as f64));
+        }
+        if let Some(interior) = &self.interior {
+            add("interior", interior.to_json());
+        }
+        if let Some(texture) = &self.texture {
+            add("texture", Json::String(texture.clone()));
+        }
+        if self.texture_scale != defaults.texture_scale {
+            add("texture_scale", pair(self.texture_scale));
+        }
+        if self.texture_space != defaults.texture_space {
+            add(
+                "texture_space",
+                Json::String(self.texture_space.name().to_string()),
+            );
         }
+        if self.uv_transform.scale != defaults.uv_transform.scale {
+            add("uv_scale", pair(self.uv_transform.scale));
+        }
+        if self.uv_transform.offset != defaults.uv_transform.offset {
+            add("uv_offset", pair(self.uv_transform.offset));
+        }
+        if self.uv_transform.rotation != defaults.uv_transform.rotation {
+            add(
+                "uv_rotation",
+                Json::Number(self.uv_transform.rotation.to_degrees()),
+            );
+        }
+        Json::Object(entries)
+    }
+
+    /// Load a material from a JSON file
+    pub fn load(filename: &str) -> Result<Material, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(filename)?;
+        Ok(Material::from_json(&Json::parse(&text)?)?)
+    }
+}
+
+/// Move spheres so that `origin` is the origin of their coordinates, with their materials
+/// (see `Material::recentered`); spheres sharing a material share its moved copy
+pub fn recenter_spheres(spheres: &mut [Sphere], origin: Vector3<f64>) {
+    let mut materials: HashMap<*const Material, Arc<Material>> = HashMap::new();
+    for sphere in spheres {
+        sphere.center -= origin;
+        sphere.material = materials
+            .entry(Arc::as_ptr(&sphere.material))
+            .or_insert_with(|| Arc::new(sphere.material.recentered(origin)))
+            .clone();
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct Sphere {
     pub center: Vector3<f64>,
     pub radius: f64,
-    pub material: Material
+    /// Material of the sphere, shared with the other objects made of it
+    pub material: Arc<Material>,
+    /// Name used to find the object in a scene
+    pub name: Option<String>,
+    /// Holdout objects hide what is behind them without showing up: they are shaded
+    /// transparent black and cut out the alpha of the image where the camera sees them
+    pub holdout: bool,
 }
 
 impl Sphere {
-    pub fn new(center: Vector3<f64>, radius: f64, material: Material) -> Sphere {
+    pub fn new(center: Vector3<f64>, radius: f64, material: impl Into<Arc<Material>>) -> Sphere {
         Sphere {
             center,
             radius,
-            material
+            material: material.into(),
+            name: None,
+            holdout: false,
         }
     }
-    
-    /// Ray-sphere intersection - return whether the ray intersects the sphere or not
-    /// 
+
+    pub fn with_name(mut self, name: &str) -> Sphere {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_holdout(mut self, holdout: bool) -> Sphere {
+        self.holdout = holdout;
+        self
+    }
+
+    /// Ray-sphere intersection - return the distance to the nearest intersection in the given range
+    ///
     /// ### Arguments
-    /// 
-    /// * `ray_origin` - The origin of the ray (point of origin)
-    /// * `ray_direction` - The direction of the ray (normalized)
-    /// * `t0` - The distance from the ray origin to the intersection point
-    /// 
+    ///
+    /// * `ray` - The ray (with a normalized direction)
+    /// * `t_min` - The minimum accepted distance along the ray
+    /// * `t_max` - The maximum accepted distance along the ray (the nearest hit found so far
+    ///   lets the caller skip farther intersections)
+    ///
     /// ### Returns
-    /// 
-    /// bool - Whether the ray intersects the sphere or not
-    /// 
-    #[warn(unused_assignments)]
-    pub fn ray_intersect(&self, ray_origin: Vector3<f64>, dir: Vector3<f64>, mut t0: f64) -> (bool, f64) {
-        let l: Vector3<f64> = self.center - ray_origin;
-        let tca: f64 = l.dot(&dir);
+    ///
+    /// `Option<f64>` - The distance from the ray origin to the intersection point, if any
+    ///
+    pub fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let l: Vector3<f64> = self.center - ray.origin;
+        let tca: f64 = l.dot(&ray.direction);
+        if l.norm_squared() > (FAR_SPHERE_DISTANCE * self.radius).powi(2) {
+            return self.intersect_far(l, tca, ray, t_min, t_max);
+        }
         let d2: f64 = l.dot(&l) - tca * tca;
-        if d2 > self.radius * self.radius { return (false, t0) }
+        if d2 > self.radius * self.radius {
+            return None;
+        }
         let thc: f64 = (self.radius * self.radius - d2).sqrt();
-        t0 = tca - thc;
+        let t0: f64 = tca - thc;
         let t1: f64 = tca + thc;
-        if t0 < 0.0 { t0 = t1 }
-        if t0 < 0.0 { return (false, t0) }
-        (true, t0)
+        [t0, t1].into_iter().find(|t| *t >= t_min && *t <= t_max)
+    }
+
+    /// Intersection with a sphere far from the ray origin compared to its radius, e.g. a
+    /// small object in a planetary scale scene, where the usual formula subtracts squares
+    /// of the distance that are much larger than the square of the radius and the hits
+    /// jitter from pixel to pixel
+    ///
+    /// The distance of the center to the ray is measured from the point of the ray nearest
+    /// to it, and the nearer root comes from the product of the roots, so nothing large is
+    /// subtracted (Haines et al., "Precision Improvements for Ray/Sphere Intersection", Ray
+    /// Tracing Gems, 2019)
+    fn intersect_far(
+        &self,
+        l: Vector3<f64>,
+        tca: f64,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<f64> {
+        let d2 = (l - ray.direction * tca).norm_squared();
+        if d2 > self.radius * self.radius {
+            return None;
+        }
+        let thc = (self.radius * self.radius - d2).sqrt();
+        // the roots are `q` and `c / q`, `c` being their product
+        let distance = l.norm();
+        let c = (distance - self.radius) * (distance + self.radius);
+        let q = tca + thc.copysign(tca);
+        if q == 0.0 {
+            return None;
+        }
+        let (t0, t1) = (c / q, q);
+        [t0.min(t1), t0.max(t1)]
+            .into_iter()
+            .find(|t| *t >= t_min && *t <= t_max)
+    }
+
+    /// Point and outward normal on the sphere surface for the given UV coordinates
+    ///
+    /// The UV layout is equirectangular: `u` goes around the vertical axis and `v` goes
+    /// from the bottom pole (`v = 0`) to the top one (`v = 1`)
+    pub fn surface_at(&self, u: f64, v: f64) -> (Vector3<f64>, Vector3<f64>) {
+        let phi = u * 2.0 * PI;
+        let theta = v * PI;
+        let n = Vector3::new(
+            theta.sin() * phi.cos(),
+            -theta.cos(),
+            theta.sin() * phi.sin(),
+        );
+        (self.center + n * self.radius, n)
     }
+
+    /// Whether a point lies inside the sphere
+    pub fn contains(&self, point: Vector3<f64>) -> bool {
+        (point - self.center).norm_squared() < self.radius * self.radius
+    }
+
+    /// UV coordinates of a point on the sphere surface, the inverse of `surface_at`
+    pub fn uv_at(&self, point: Vector3<f64>) -> (f64, f64) {
+        let n = (point - self.center).normalize();
+        let theta = (-n.y).clamp(-1.0, 1.0).acos();
+        let phi = n.z.atan2(n.x).rem_euclid(2.0 * PI);
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    /// UV coordinates a texture of the sphere material is looked up at for a point on the
+    /// surface, the UVs of `uv_at` through the UV transform of the material
+    pub fn texture_uv(&self, point: Vector3<f64>) -> (f64, f64) {
+        self.material.uv_transform.apply(self.uv_at(point))
+    }
+}
+
+/// How a light emits
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Light emitted from `position` in all directions
+    Point,
+    /// Light arriving from infinitely far away, traveling along `direction`;
+    /// the source covers a disk of `angular_diameter` radians in the sky
+    Directional {
+        direction: Vector3<f64>,
+        angular_diameter: f64,
+    },
+    /// Light emitted from `position` inside a cone around `direction`, fading out between
+    /// the inner and the outer cone (both given as cosines of the half angles)
+    Spot {
+        direction: Vector3<f64>,
+        cos_inner: f64,
+        cos_outer: f64,
+    },
 }
 
-pub struct Light {
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneLight {
+    pub kind: LightKind,
     pub position: Vector3<f64>,
-    pub intensity: f64
+    pub intensity: f64,
+    /// Color of the emitted light, multiplied by `intensity`
+    pub color: Color,
+    /// Radius of the light, it is sampled at random points on this sphere, which softens
+    /// the shadow edges and widens the highlights (0 gives a point light with hard shadows)
+    pub radius: f64,
+    /// Number of shadow rays traced towards the light from every shaded point
+    pub shadow_samples: u32,
+    /// Distance beyond which the light doesn't reach, faded out smoothly towards it (see
+    /// `distance_window`), so points out of its reach trace no shadow rays towards it;
+    /// unbounded without one, directional lights ignore it
+    pub max_distance: Option<f64>,
+    /// Name used to find the light in a scene
+    pub name: Option<String>,
 }
 
-impl Light {
-    pub fn new(position: Vector3<f64>, intensity: f64) -> Light {
-        Light {
+impl SceneLight {
+    pub fn new(position: Vector3<f64>, intensity: f64) -> SceneLight {
+        SceneLight {
+            kind: LightKind::Point,
             position,
-            intensity
+            intensity,
+            color: Color::WHITE,
+            radius: 0.0,
+            shadow_samples: 1,
+            max_distance: None,
+            name: None,
+        }
+    }
+
+    /// Light coming from a distant source along `direction`
+    ///
+    /// ### Arguments
+    ///
+    /// * `direction` - The direction the light travels in
+    /// * `intensity` - The intensity of the light
+    /// * `angular_diameter` - The apparent size of the source in radians (0 for hard shadows)
+    ///
+    pub fn directional(
+        direction: Vector3<f64>,
+        intensity: f64,
+        angular_diameter: f64,
+    ) -> SceneLight {
+        SceneLight {
+            kind: LightKind::Directional {
+                direction: direction.normalize(),
+                angular_diameter: angular_diameter.max(0.0),
+            },
+            position: Vector3::default(),
+            intensity,
+            color: Color::WHITE,
+            radius: 0.0,
+            shadow_samples: 1,
+            max_distance: None,
+            name: None,
+        }
+    }
+
+    /// Spot light shining from `position` along `direction`
+    ///
+    /// ### Arguments
+    ///
+    /// * `position` - The position of the light
+    /// * `direction` - The axis of the cone
+    /// * `intensity` - The intensity of the light
+    /// * `angle` - The half angle of the cone in radians
+    /// * `softness` - The fraction of the cone (from its border) over which the light fades out
+    ///
+    pub fn spot(
+        position: Vector3<f64>,
+        direction: Vector3<f64>,
+        intensity: f64,
+        angle: f64,
+        softness: f64,
+    ) -> SceneLight {
+        let mut light = SceneLight::new(position, intensity);
+        light.kind = LightKind::Spot {
+            direction: direction.normalize(),
+            cos_inner: (angle * (1.0 - softness.clamp(0.0, 1.0))).cos(),
+            cos_outer: angle.cos(),
+        };
+        light
+    }
+
+    /// Directional light with the angular diameter of the sun seen from the earth (0.53°)
+    pub fn sun(direction: Vector3<f64>, intensity: f64) -> SceneLight {
+        SceneLight::directional(direction, intensity, 0.53_f64.to_radians())
+    }
+
+    pub fn with_name(mut self, name: &str) -> SceneLight {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> SceneLight {
+        self.color = color;
+        self
+    }
+
+    /// Set the color of the light from a black body temperature in kelvins
+    pub fn with_temperature(mut self, kelvin: f64) -> SceneLight {
+        self.color = blackbody(kelvin);
+        self
+    }
+
+    /// Emitted radiance (`color * intensity`)
+    pub fn radiance(&self) -> Color {
+        self.color * self.intensity
+    }
+
+    pub fn with_radius(mut self, radius: f64) -> SceneLight {
+        self.radius = radius.max(0.0);
+        self
+    }
+
+    pub fn with_shadow_samples(mut self, shadow_samples: u32) -> SceneLight {
+        self.shadow_samples = shadow_samples.max(1);
+        self
+    }
+
+    pub fn with_max_distance(mut self, max_distance: f64) -> SceneLight {
+        self.max_distance = Some(max_distance);
+        self
+    }
+
+    /// Fraction of the light emitted towards a point (the cone falloff of spot lights) that
+    /// reaches it (see `distance_window`)
+    pub fn attenuation(&self, point: Vector3<f64>) -> f64 {
+        let cone = match self.kind {
+            LightKind::Spot {
+                direction,
+                cos_inner,
+                cos_outer,
+            } => {
+                if point == self.position {
+                    return 0.0;
+                }
+                let cos_angle = (point - self.position).normalize().dot(&direction);
+                if cos_angle >= cos_inner {
+                    1.0
+                } else if cos_angle <= cos_outer {
+                    0.0
+                } else {
+                    let s = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+                    s * s * (3.0 - 2.0 * s)
+                }
+            }
+            _ => 1.0,
+        };
+        cone * self.distance_window(point)
+    }
+
+    /// Fraction of the light reaching a point before the maximum distance of the light,
+    /// `(1 - (d / max_distance)^4)^2` (Karis, "Real Shading in Unreal Engine 4", 2013): 1 at
+    /// the light, flat nearby and down to 0 with a zero slope at the maximum distance
+    pub fn distance_window(&self, point: Vector3<f64>) -> f64 {
+        let Some(max_distance) = self.max_distance else {
+            return 1.0;
+        };
+        if let LightKind::Directional { .. } = self.kind {
+            return 1.0;
+        }
+        let ratio = (point - self.position).norm() / max_distance;
+        let window = (1.0 - ratio.powi(4)).max(0.0);
+        window * window
+    }
+
+    /// Direction from a point towards the center of the light and the distance to it
+    /// (infinite for directional lights)
+    pub fn direction_from(&self, point: Vector3<f64>) -> (Vector3<f64>, f64) {
+        match self.kind {
+            LightKind::Point | LightKind::Spot { .. } => {
+                let to_light = self.position - point;
+                (to_light.normalize(), to_light.norm())
+            }
+            LightKind::Directional { direction, .. } => (-direction, f64::INFINITY),
+        }
+    }
+
+    /// Direction and distance of a shadow ray from a point towards a sample of the light
+    ///
+    /// ### Arguments
+    ///
+    /// * `point` - The origin of the shadow ray
+    /// * `u`, `v` - Sample coordinates in the range `[0, 1)`
+    ///
+    pub fn sample_direction(&self, point: Vector3<f64>, u: f64, v: f64) -> (Vector3<f64>, f64) {
+        match self.kind {
+            LightKind::Point | LightKind::Spot { .. } => {
+                let target = self.sample_point(u, v);
+                ((target - point).normalize(), (target - point).norm())
+            }
+            LightKind::Directional {
+                direction,
+                angular_diameter,
+            } => {
+                if angular_diameter <= 0.0 {
+                    return (-direction, f64::INFINITY);
+                }
+                // uniform direction inside the cone subtended by the source
+                let cos_max = (angular_diameter / 2.0).cos();
+                let sampled = Onb::from_w(-direction).to_world(uniform_cone(u, v, cos_max));
+                (sampled.normalize(), f64::INFINITY)
+            }
         }
     }
-}
\ No newline at end of file
+
+    /// Point on a point light used as the target of a shadow ray
+    ///
+    /// ### Arguments
+    ///
+    /// * `u`, `v` - Sample coordinates in the range `[0, 1)`
+    ///
+    pub fn sample_point(&self, u: f64, v: f64) -> Vector3<f64> {
+        if self.radius <= 0.0 {
+            return self.position;
+        }
+        // uniform point on the sphere around the light position
+        self.position + uniform_sphere(u, v) * self.radius
+    }
+}
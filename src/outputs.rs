@@ -0,0 +1,321 @@
+use crate::color::Color;
+use crate::colorspace::to_linear_srgb;
+use crate::colorspace::ColorPipeline;
+use crate::colorspace::OutputEncoding;
+use crate::depth::camera_depths;
+use crate::depth::depth_range;
+use crate::depth::DepthEncoding;
+use crate::gbuffer::GBuffer;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::pixelformat::ChannelDepth;
+use crate::pixelformat::PixelFormat;
+use crate::render::render_radiance;
+use crate::render::PrimaryHits;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+use std::path::Path;
+
+/// What an output of a scene holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputLayer {
+    /// The rendered image
+    Beauty,
+    /// Diffuse color of the visible surface (see `gbuffer::GBuffer`)
+    Albedo,
+    /// Normal of the visible surface, its coordinates mapped from `[-1, 1]` to `[0, 1]` in
+    /// integer formats like a normal map
+    Normal,
+    /// World space position of the visible surface
+    Position,
+    /// Distance of the visible surface along the view axis, from the near to the far end of
+    /// the visible surfaces, normalized to `[0, 1]` in integer formats
+    Depth,
+    /// Mean curvature of the visible surface
+    Curvature,
+}
+
+impl OutputLayer {
+    /// Layer from its name: `beauty`, `albedo`, `normal`, `position`, `depth` or `curvature`
+    pub fn from_name(name: &str) -> Option<OutputLayer> {
+        match name {
+            "beauty" => Some(OutputLayer::Beauty),
+            "albedo" => Some(OutputLayer::Albedo),
+            "normal" => Some(OutputLayer::Normal),
+            "position" => Some(OutputLayer::Position),
+            "depth" => Some(OutputLayer::Depth),
+            "curvature" => Some(OutputLayer::Curvature),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputLayer::Beauty => "beauty",
+            OutputLayer::Albedo => "albedo",
+            OutputLayer::Normal => "normal",
+            OutputLayer::Position => "position",
+            OutputLayer::Depth => "depth",
+            OutputLayer::Curvature => "curvature",
+        }
+    }
+
+    /// Whether the layer holds a single value per pixel
+    pub fn is_scalar(&self) -> bool {
+        matches!(self, OutputLayer::Depth | OutputLayer::Curvature)
+    }
+}
+
+/// How the colors of an output are encoded before they are written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputSpace {
+    /// Developed by the color pipeline of the render, like `out.png`
+    Display,
+    /// Values as they are, in the working space
+    Linear,
+    /// Linear sRGB values encoded with the sRGB transfer function, without a view transform
+    Srgb,
+}
+
+impl OutputSpace {
+    /// Space from its name: `display`, `linear` or `srgb`
+    pub fn from_name(name: &str) -> Option<OutputSpace> {
+        match name {
+            "display" => Some(OutputSpace::Display),
+            "linear" => Some(OutputSpace::Linear),
+            "srgb" => Some(OutputSpace::Srgb),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputSpace::Display => "display",
+            OutputSpace::Linear => "linear",
+            OutputSpace::Srgb => "srgb",
+        }
+    }
+}
+
+/// Image a scene asks to be written when it is rendered, e.g. a float beauty image for
+/// compositing next to 8-bit normals for a review
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputSpec {
+    /// Path of the file, relative to the output directory
+    pub file: String,
+    pub layer: OutputLayer,
+    pub format: PixelFormat,
+    pub space: OutputSpace,
+}
+
+impl OutputSpec {
+    /// Output from a JSON object with its `file` and optionally its `layer` (`beauty` by
+    /// default), `format` (see `PixelFormat::from_name`) and color `space`
+    ///
+    /// The file is a PNG image for 8 and 16 bits or a PFM image for floats. Without a
+    /// format, the extension of the file gives one: 8 bits for `.png`, floats for `.pfm`,
+    /// with a single channel for the depth and the curvature. Without a space, the beauty
+    /// layer in integers is developed for the display and everything else is linear
+    pub fn from_json(json: &Json) -> Result<OutputSpec, JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("an output must be a JSON object"));
+        };
+        let mut file = None;
+        let mut layer = OutputLayer::Beauty;
+        let mut format = None;
+        let mut space = None;
+        for (key, value) in entries {
+            let name = value
+                .as_str()
+                .ok_or_else(|| JsonError::new(&format!("'{}' must be a string", key)));
+            match key.as_str() {
+                "file" => file = Some(name?.to_string()),
+                "layer" => {
+                    layer = OutputLayer::from_name(name?).ok_or_else(|| {
+                        JsonError::new(
+                            "'layer' must be beauty, albedo, normal, position, depth or curvature",
+                        )
+                    })?
+                }
+                "format" => {
+                    format = Some(PixelFormat::from_name(name?).ok_or_else(|| {
+                        JsonError::new("'format' must be gray or rgb followed by 8, 16 or 32")
+                    })?)
+                }
+                "space" => {
+                    space =
+                        Some(OutputSpace::from_name(name?).ok_or_else(|| {
+                            JsonError::new("'space' must be display, linear or srgb")
+                        })?)
+                }
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown output property '{}'",
+                        key
+                    )))
+                }
+            }
+        }
+        let file = file.ok_or_else(|| JsonError::new("an output must have a 'file'"))?;
+        let extension = Path::new(&file)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let format = match (format, extension.as_str()) {
+            (Some(format), _) => format,
+            (None, "png") => PixelFormat {
+                gray: layer.is_scalar(),
+                depth: ChannelDepth::Eight,
+            },
+            (None, "pfm") => PixelFormat {
+                gray: layer.is_scalar(),
+                depth: ChannelDepth::Float,
+            },
+            _ => {
+                return Err(JsonError::new(&format!(
+                    "output '{}' must be a .png or .pfm file",
+                    file
+                )))
+            }
+        };
+        if extension != format.extension() {
+            return Err(JsonError::new(&format!(
+                "output '{}' holds {} values, it must be a .{} file",
+                file,
+                if format.is_float() {
+                    "float"
+                } else {
+                    "integer"
+                },
+                format.extension()
+            )));
+        }
+        let space = space.unwrap_or(match layer {
+            OutputLayer::Beauty if !format.is_float() => OutputSpace::Display,
+            _ => OutputSpace::Linear,
+        });
+        Ok(OutputSpec {
+            file,
+            layer,
+            format,
+            space,
+        })
+    }
+
+    /// Output as a JSON object (see `from_json`)
+    pub fn to_json(&self) -> Json {
+        let name = |name: &str| Json::String(name.to_string());
+        Json::Object(vec![
+            ("file".to_string(), name(&self.file)),
+            ("layer".to_string(), name(self.layer.name())),
+            ("format".to_string(), name(&self.format.name())),
+            ("space".to_string(), name(self.space.name())),
+        ])
+    }
+
+    /// Colors of the output ready for `pixelformat::save_pixels`, encoded in its space
+    ///
+    /// ### Arguments
+    ///
+    /// * `layers` - The layers rendered for the outputs of the scene
+    /// * `pipeline` - The color pipeline developing the `display` outputs
+    ///
+    /// ### Returns
+    ///
+    /// `Vec<Color>` - The values of the pixels, row by row from the top
+    ///
+    pub fn values(&self, layers: &OutputLayers, pipeline: &ColorPipeline) -> Vec<Color> {
+        let integer = !self.format.is_float();
+        let colors: Vec<Color> = match self.layer {
+            OutputLayer::Beauty => layers.beauty.clone(),
+            OutputLayer::Albedo => layers.gbuffer.albedo.clone(),
+            OutputLayer::Normal => layers
+                .gbuffer
+                .normal
+                .iter()
+                .map(|n| {
+                    let color = Color::new(n.x, n.y, n.z);
+                    if integer {
+                        color * 0.5 + Color::gray(0.5)
+                    } else {
+                        color
+                    }
+                })
+                .collect(),
+            OutputLayer::Position => layers
+                .gbuffer
+                .position
+                .iter()
+                .map(|p| Color::new(p.x, p.y, p.z))
+                .collect(),
+            OutputLayer::Depth => {
+                let (near, far) = depth_range(&layers.depths).unwrap_or((1.0, 1.0));
+                let encoding = if integer {
+                    DepthEncoding::Normalized
+                } else {
+                    DepthEncoding::Linear
+                };
+                layers
+                    .depths
+                    .iter()
+                    .map(|depth| Color::gray(encoding.encode(*depth, near, far)))
+                    .collect()
+            }
+            OutputLayer::Curvature => layers
+                .gbuffer
+                .curvature
+                .iter()
+                .map(|c| Color::gray(*c))
+                .collect(),
+        };
+        match self.space {
+            OutputSpace::Display => colors.iter().map(|c| pipeline.apply(*c)).collect(),
+            OutputSpace::Linear => colors,
+            OutputSpace::Srgb => colors
+                .iter()
+                .map(|c| {
+                    to_linear_srgb(pipeline.working_space, *c)
+                        .map(|c| OutputEncoding::Srgb.encode(c.clamp(0.0, 1.0)))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Layers of a render the outputs of a scene are made of, each rendered once for all the
+/// outputs using it
+pub struct OutputLayers {
+    /// Radiance of the pixels (see `render::render_radiance`)
+    pub beauty: Vec<Color>,
+    /// Surfaces seen at the pixel centers
+    pub gbuffer: GBuffer,
+    /// Distances of the surfaces along the view axis, infinite where nothing is hit
+    pub depths: Vec<f64>,
+}
+
+impl OutputLayers {
+    /// Render the layers of the outputs, the beauty image only if one of them is a beauty
+    /// output (the surfaces are traced with one ray per pixel, that's cheap)
+    pub fn render(
+        outputs: &[OutputSpec],
+        settings: &RenderSettings,
+        scene: &Scene,
+    ) -> OutputLayers {
+        let beauty = if outputs
+            .iter()
+            .any(|output| output.layer == OutputLayer::Beauty)
+        {
+            render_radiance(settings, scene)
+        } else {
+            Vec::new()
+        };
+        let center_settings = settings.clone().with_samples_per_pixel(1);
+        let hits = PrimaryHits::trace(&center_settings, scene);
+        OutputLayers {
+            beauty,
+            gbuffer: GBuffer::from_hits(&hits, scene),
+            depths: camera_depths(&hits, &settings.camera),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use crate::json::Json;
+use crate::texture::UDIM_TOKEN;
+
+use crc32fast::Hasher;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Read;
+use std::io::Write;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Signature every scene pack starts with
+pub const PACK_SIGNATURE: &[u8; 8] = b"RTPACK1\n";
+
+/// Error raised by a scene that can't be packed or a pack that can't be read
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackError {
+    pub message: String,
+}
+
+impl PackError {
+    pub fn new(message: &str) -> PackError {
+        PackError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for PackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for PackError {}
+
+/// Scene file bundled with every file it refers to (the files it includes, the textures
+/// and the environment maps), so it can be shared or moved to another machine as one file
+///
+/// The files keep their places relative to each other, so the scene loads from the pack
+/// as it does from its directory. A pack is `PACK_SIGNATURE` followed by a zlib stream of
+/// the files, each one its path (a little endian `u32` length and UTF-8 bytes, `/`
+/// separated) and its content (a little endian `u64` length and the bytes), the scene file
+/// first
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScenePack {
+    /// Path of each file relative to the root of the pack, and its content
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl ScenePack {
+    /// Pack a scene file and the files it refers to
+    ///
+    /// The files are found in the scene files themselves: the `include` paths, the
+    /// `texture` of the materials and of the environment and the `path` of the image
+    /// textures, every tile of a UDIM texture. The paths must be relative, as they are
+    /// kept as they are
+    ///
+    /// ### Arguments
+    ///
+    /// * `scene` - The path of the scene file
+    ///
+    /// ### Returns
+    ///
+    /// `Result<ScenePack, Box<dyn std::error::Error>>` - The pack, or an error if a file
+    /// can't be read
+    ///
+    pub fn collect(scene: &Path) -> Result<ScenePack, Box<dyn std::error::Error>> {
+        let mut paths = vec![scene.canonicalize()?];
+        let mut pending = vec![paths[0].clone()];
+        while let Some(file) = pending.pop() {
+            let text = std::fs::read_to_string(&file)?;
+            let json = Json::parse(&text)
+                .map_err(|error| PackError::new(&format!("{}: {}", file.display(), error)))?;
+            let directory = file.parent().unwrap_or(Path::new(""));
+            let mut references = Vec::new();
+            file_references(&json, &mut references);
+            let includes = json_strings(json.get("include"));
+            for (relative, include) in references
+                .into_iter()
+                .map(|path| (path, false))
+                .chain(includes.into_iter().map(|path| (path, true)))
+            {
+                if Path::new(&relative).is_absolute() {
+                    return Err(PackError::new(&format!(
+                        "{}: '{}' must be relative to be packed",
+                        file.display(),
+                        relative
+                    ))
+                    .into());
+                }
+                for path in expand_udim(&directory.join(&relative))? {
+                    let path = path.canonicalize().map_err(|error| {
+                        PackError::new(&format!("{}: {}", path.display(), error))
+                    })?;
+                    if !paths.contains(&path) {
+                        paths.push(path.clone());
+                        if include {
+                            pending.push(path);
+                        }
+                    }
+                }
+            }
+        }
+        // the files keep their places below the deepest directory holding all of them
+        let mut root = paths[0].parent().unwrap_or(Path::new("")).to_path_buf();
+        while !paths.iter().all(|path| path.starts_with(&root)) {
+            root = root.parent().map(Path::to_path_buf).unwrap_or_default();
+        }
+        let mut pack = ScenePack::default();
+        for path in paths {
+            let relative = path.strip_prefix(&root).unwrap_or(&path);
+            let name: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+            pack.files.push((name.join("/"), std::fs::read(&path)?));
+        }
+        Ok(pack)
+    }
+
+    /// Write the pack to a file
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_bytes()?)
+    }
+
+    /// Bytes of the pack file
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        let mut encoder = ZlibEncoder::new(PACK_SIGNATURE.to_vec(), Compression::default());
+        for (name, data) in &self.files {
+            encoder.write_all(&(name.len() as u32).to_le_bytes())?;
+            encoder.write_all(name.as_bytes())?;
+            encoder.write_all(&(data.len() as u64).to_le_bytes())?;
+            encoder.write_all(data)?;
+        }
+        encoder.finish()
+    }
+
+    /// Read a pack file
+    pub fn load(path: &Path) -> Result<ScenePack, Box<dyn std::error::Error>> {
+        Ok(ScenePack::from_bytes(&std::fs::read(path)?)?)
+    }
+
+    /// Read a pack from its bytes (see `to_bytes`)
+    pub fn from_bytes(bytes: &[u8]) -> Result<ScenePack, PackError> {
+        let compressed = bytes
+            .strip_prefix(PACK_SIGNATURE.as_slice())
+            .ok_or_else(|| PackError::new("not a scene pack"))?;
+        let mut content = Vec::new();
+        ZlibDecoder::new(compressed)
+            .read_to_end(&mut content)
+            .map_err(|error| PackError::new(&format!("corrupted scene pack: {}", error)))?;
+        let mut rest = content.as_slice();
+        let mut pack = ScenePack::default();
+        while !rest.is_empty() {
+            let length = u32::from_le_bytes(take(&mut rest)?);
+            let name = String::from_utf8(take_bytes(&mut rest, length as usize)?.to_vec())
+                .map_err(|_| PackError::new("the path of a packed file isn't UTF-8"))?;
+            // the files can't land outside of the directory the pack is unpacked into
+            let safe = Path::new(&name)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+            if !safe {
+                return Err(PackError::new(&format!("invalid packed path '{}'", name)));
+            }
+            let length = u64::from_le_bytes(take(&mut rest)?);
+            let data = take_bytes(&mut rest, length as usize)?.to_vec();
+            pack.files.push((name, data));
+        }
+        if pack.files.is_empty() {
+            return Err(PackError::new("empty scene pack"));
+        }
+        Ok(pack)
+    }
+
+    /// Write the files of the pack into a directory
+    ///
+    /// ### Returns
+    ///
+    /// `std::io::Result<PathBuf>` - The path of the scene file unpacked
+    ///
+    pub fn unpack(&self, directory: &Path) -> std::io::Result<PathBuf> {
+        for (name, data) in &self.files {
+            let path = directory.join(name);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, data)?;
+        }
+        let scene = self
+            .files
+            .first()
+            .map(|(name, _)| name.as_str())
+            .unwrap_or("");
+        Ok(directory.join(scene))
+    }
+
+    /// Unpack into a directory of the temporary directory named after the content of the
+    /// pack, so loading the same pack again reuses its files
+    pub fn unpack_to_temp(&self) -> std::io::Result<PathBuf> {
+        let mut hasher = Hasher::new();
+        for (name, data) in &self.files {
+            hasher.update(name.as_bytes());
+            hasher.update(data);
+        }
+        let directory =
+            std::env::temp_dir().join(format!("ray_tracing-pack-{:08x}", hasher.finalize()));
+        self.unpack(&directory)
+    }
+}
+
+/// Whether a file is a scene pack, from its signature
+pub fn is_pack(path: &Path) -> bool {
+    let mut signature = [0; 8];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok()
+        && &signature == PACK_SIGNATURE
+}
+
+/// First bytes of the rest of a pack, moving past them
+fn take_bytes<'a>(rest: &mut &'a [u8], count: usize) -> Result<&'a [u8], PackError> {
+    if rest.len() < count {
+        return Err(PackError::new("truncated scene pack"));
+    }
+    let (taken, left) = rest.split_at(count);
+    *rest = left;
+    Ok(taken)
+}
+
+fn take<const N: usize>(rest: &mut &[u8]) -> Result<[u8; N], PackError> {
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(take_bytes(rest, N)?);
+    Ok(bytes)
+}
+
+/// Paths of the files a scene fragment refers to besides its includes: the `texture` of
+/// the materials and the environment, and the `path` of the image textures
+fn file_references(json: &Json, references: &mut Vec<String>) {
+    match json {
+        Json::Object(entries) => {
+            let image = json.get("type").and_then(Json::as_str) == Some("image");
+            for (key, value) in entries {
+                match (key.as_str(), value) {
+                    ("texture", Json::String(path)) => references.push(path.clone()),
+                    ("path", Json::String(path)) if image => references.push(path.clone()),
+                    _ => file_references(value, references),
+                }
+            }
+        }
+        Json::Array(values) => {
+            for value in values {
+                file_references(value, references);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn json_strings(json: Option<&Json>) -> Vec<String> {
+    match json {
+        Some(Json::String(path)) => vec![path.clone()],
+        Some(Json::Array(values)) => values
+            .iter()
+            .filter_map(Json::as_str)
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Files of a path: the path itself, or every tile of a UDIM texture
+fn expand_udim(path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let Some((prefix, suffix)) = name.split_once(UDIM_TOKEN) else {
+        return Ok(vec![path.to_path_buf()]);
+    };
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let mut tiles = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let tile = entry?.file_name().to_string_lossy().to_string();
+        let number = tile
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(suffix));
+        if number.is_some_and(|number| number.len() == 4 && number.parse::<u32>().is_ok()) {
+            tiles.push(directory.join(tile));
+        }
+    }
+    tiles.sort();
+    Ok(tiles)
+}
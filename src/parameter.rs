@@ -0,0 +1,191 @@
+use crate::color::Color;
+use crate::object::Material;
+use crate::pixelfilter::PixelFilter;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+/// Error raised when a parameter path doesn't name anything in the scene or settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterError {
+    pub message: String,
+}
+
+impl ParameterError {
+    pub fn new(message: &str) -> ParameterError {
+        ParameterError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParameterError {}
+
+/// Set a numeric parameter of the scene or of the render settings from its dotted path
+///
+/// * `fov` (or `camera.fov`) - vertical field of view in degrees
+/// * `samples_per_pixel`, `supersampling`, `max_depth`, `threads`
+/// * `path_limits.<limit>` - `diffuse_depth`, `glossy_depth`, `transmission_depth`,
+///   `clamp_direct`, `clamp_indirect` or `roughness_clamp`
+/// * `filter.radius` (in pixels)
+/// * `background` (a gray) or `background.r`, `background.g` and `background.b`
+/// * `materials.<name>.<field>` - `diffuse`, `specular`, `reflection` (the albedo
+///   weights), `specular_exponent`, `refraction`, `ior`, `priority` or `uv_rotation` (in
+///   degrees)
+/// * `lights.<name>.<field>` - `intensity`, `radius` or `max_distance`
+/// * `objects.<name>.radius`
+/// * `environment.rotation` (in degrees) or `environment.intensity`
+///
+/// ### Arguments
+///
+/// * `scene` - The scene holding the named materials, lights and objects
+/// * `settings` - The render settings
+/// * `path` - The path of the parameter
+/// * `value` - The new value of the parameter
+///
+/// ### Returns
+///
+/// `Result<(), ParameterError>` - An error if the path doesn't name a parameter
+///
+pub fn set_parameter(
+    scene: &mut Scene,
+    settings: &mut RenderSettings,
+    path: &str,
+    value: f64,
+) -> Result<(), ParameterError> {
+    let unknown = || ParameterError::new(&format!("unknown parameter `{}`", path));
+    let parts: Vec<&str> = path.split('.').collect();
+    match parts.as_slice() {
+        ["fov"] | ["camera", "fov"] => settings.fov = value.to_radians(),
+        ["samples_per_pixel"] => settings.samples_per_pixel = count(path, value)?,
+        ["supersampling"] => settings.supersampling = count(path, value)?.max(1),
+        ["filter", "radius"] => settings.filter = PixelFilter::new(settings.filter.kind, value),
+        ["max_depth"] => settings.max_depth = count(path, value)?,
+        ["threads"] => settings.threads = count(path, value)? as usize,
+        ["path_limits", limit] => {
+            let limits = &mut settings.path_limits;
+            match *limit {
+                "diffuse_depth" => limits.diffuse_depth = Some(count(path, value)?),
+                "glossy_depth" => limits.glossy_depth = Some(count(path, value)?),
+                "transmission_depth" => limits.transmission_depth = Some(count(path, value)?),
+                "clamp_direct" => limits.clamp_direct = Some(value),
+                "clamp_indirect" => limits.clamp_indirect = Some(value),
+                "roughness_clamp" => limits.roughness_clamp = Some(value),
+                _ => return Err(unknown()),
+            }
+        }
+        ["background"] => settings.background = Color::gray(value),
+        ["background", channel] => match *channel {
+            "r" => settings.background.r = value,
+            "g" => settings.background.g = value,
+            "b" => settings.background.b = value,
+            _ => return Err(unknown()),
+        },
+        ["materials", name, field] => {
+            let set: fn(&mut Material, f64) = match *field {
+                "diffuse" => |material, value| material.albedo.x = value,
+                "specular" => |material, value| material.albedo.y = value,
+                "reflection" => |material, value| material.albedo.z = value,
+                "specular_exponent" => |material, value| material.specular_exponent = value,
+                "refraction" => |material, value| material.refraction = value,
+                "ior" => |material, value| material.refractive_index = value,
+                "priority" => |material, value| material.priority = value.max(0.0) as u32,
+                "uv_rotation" => {
+                    |material, value| material.uv_transform.rotation = value.to_radians()
+                }
+                _ => return Err(unknown()),
+            };
+            let mut found = false;
+            for material in scene.materials_mut(name) {
+                set(material, value);
+                found = true;
+            }
+            if !found {
+                return Err(ParameterError::new(&format!(
+                    "no material named `{}`",
+                    name
+                )));
+            }
+        }
+        ["lights", name, field] => {
+            let light = scene
+                .light_mut(name)
+                .ok_or_else(|| ParameterError::new(&format!("no light named `{}`", name)))?;
+            match *field {
+                "intensity" => light.intensity = value,
+                "radius" => light.radius = value,
+                "max_distance" => light.max_distance = Some(value),
+                _ => return Err(unknown()),
+            }
+        }
+        ["objects", name, "radius"] => {
+            let object = scene
+                .object_mut(name)
+                .ok_or_else(|| ParameterError::new(&format!("no object named `{}`", name)))?;
+            object.radius = value;
+            scene.rebuild();
+        }
+        ["environment", field] => {
+            let environment = scene
+                .environment
+                .as_mut()
+                .ok_or_else(|| ParameterError::new("the scene has no environment"))?;
+            match *field {
+                "rotation" => environment.rotation = value.to_radians(),
+                "intensity" => environment.intensity = value,
+                _ => return Err(unknown()),
+            }
+            // the renderer reads the environment from the settings
+            settings.environment = scene.environment.clone();
+        }
+        _ => return Err(unknown()),
+    }
+    Ok(())
+}
+
+/// Environment variable holding overrides applied before the command line ones, separated
+/// by whitespace or semicolons
+pub const OVERRIDES_VARIABLE: &str = "RAY_TRACING_SET";
+
+/// Apply an override written as `<path>=<value>`, see `set_parameter` for the paths
+pub fn apply_override(
+    scene: &mut Scene,
+    settings: &mut RenderSettings,
+    text: &str,
+) -> Result<(), ParameterError> {
+    let invalid = || {
+        ParameterError::new(&format!(
+            "invalid override `{}`, expected <parameter>=<value>",
+            text
+        ))
+    };
+    let (path, value) = text.split_once('=').ok_or_else(invalid)?;
+    let value = value.trim().parse::<f64>().map_err(|_| invalid())?;
+    set_parameter(scene, settings, path.trim(), value)
+}
+
+/// Overrides listed in the `OVERRIDES_VARIABLE` environment variable
+pub fn env_overrides() -> Vec<String> {
+    std::env::var(OVERRIDES_VARIABLE)
+        .unwrap_or_default()
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parameter value that has to be a whole number
+fn count(path: &str, value: f64) -> Result<u32, ParameterError> {
+    if value.fract() != 0.0 || value < 0.0 || value > u32::MAX as f64 {
+        return Err(ParameterError::new(&format!(
+            "`{}` must be a whole number",
+            path
+        )));
+    }
+    Ok(value as u32)
+}
@@ -0,0 +1,114 @@
+use crate::color::Color;
+use crate::framebuffer::FrameBuffer;
+use crate::medium::MediumStack;
+use crate::reconstruction::Reconstruction;
+use crate::render::camera_rays;
+use crate::render::cast_ray_components;
+use crate::render::sanitize_radiance;
+use crate::render::ShadingComponents;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+/// Names of the component passes accepted by `ComponentPasses::pass`
+pub const PASS_NAMES: [&str; 5] = [
+    "diffuse",
+    "specular",
+    "reflection",
+    "transmission",
+    "emission",
+];
+
+/// Render split into the components of the light seen by the camera, the passes add up to
+/// the full render
+pub struct ComponentPasses {
+    pub width: u32,
+    pub height: u32,
+    /// Radiance of the pixels row by row from the top left corner, split into components
+    pub pixels: Vec<ShadingComponents>,
+}
+
+impl ComponentPasses {
+    /// One component of every pixel, `name` being one of `PASS_NAMES`
+    pub fn pass(&self, name: &str) -> Vec<Color> {
+        let component: fn(&ShadingComponents) -> Color = match name {
+            "diffuse" => |c| c.diffuse,
+            "specular" => |c| c.specular,
+            "reflection" => |c| c.reflection,
+            "transmission" => |c| c.transmission,
+            _ => |c| c.emission,
+        };
+        self.pixels.iter().map(component).collect()
+    }
+
+    /// Radiance of the full render with some of the passes reconstructed, so a filter
+    /// smoothing the diffuse lighting doesn't smear the sharp details of the others
+    ///
+    /// ### Arguments
+    ///
+    /// * `frame` - The frame holding the AOVs guiding the reconstruction, of the size of
+    ///   the passes
+    /// * `reconstruction` - The reconstruction applied to the listed passes
+    /// * `names` - The names of the passes to reconstruct, among `PASS_NAMES`
+    ///
+    /// ### Returns
+    ///
+    /// `Vec<Color>` - The sum of the passes, row by row from the top left corner
+    ///
+    pub fn reconstruct(
+        &self,
+        frame: &FrameBuffer,
+        reconstruction: &dyn Reconstruction,
+        names: &[String],
+    ) -> Vec<Color> {
+        let mut sum = vec![Color::BLACK; self.pixels.len()];
+        for name in PASS_NAMES {
+            let mut pass = self.pass(name);
+            if names.iter().any(|n| n == name) {
+                let mut guided = frame.clone();
+                guided.color = pass;
+                pass = reconstruction.reconstruct(&guided);
+            }
+            for (total, color) in sum.iter_mut().zip(pass) {
+                *total += color;
+            }
+        }
+        sum
+    }
+}
+
+/// Render the image split into diffuse, specular, reflection, transmission and emission
+/// passes
+///
+/// The split happens at the surface seen by the camera: its diffuse lighting, its
+/// highlights, what it reflects and what it lets through. Camera rays that don't hit a
+/// surface see the background, which goes to the emission pass
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings
+/// * `scene` - The scene
+///
+/// ### Returns
+///
+/// `ComponentPasses` - The passes, averaged over the camera rays of every pixel
+///
+pub fn render_passes(settings: &RenderSettings, scene: &Scene) -> ComponentPasses {
+    let mut pixels = Vec::with_capacity(settings.width as usize * settings.height as usize);
+    for j in 0..settings.height {
+        for i in 0..settings.width {
+            let rays = camera_rays(i, j, settings);
+            let mut sum = ShadingComponents::default();
+            for ray in &rays {
+                let media = MediumStack::at(ray.origin, &scene.spheres);
+                sum += cast_ray_components(ray, &media, scene, settings, 0);
+            }
+            let count = rays.len() as f64;
+            pixels.push(sum.map(|color| sanitize_radiance(color / count)));
+        }
+    }
+    ComponentPasses {
+        width: settings.width,
+        height: settings.height,
+        pixels,
+    }
+}
@@ -0,0 +1,121 @@
+/// Tokens an output path template can hold, see `expand_template`
+pub const TEMPLATE_TOKENS: &[&str] = &["scene", "camera", "frame", "spp", "width", "height"];
+
+/// Values of the tokens of an output path template for one image
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathTokens {
+    pub scene: String,
+    pub camera: String,
+    /// Frame of the image in its sequence, 0 for a still image
+    pub frame: u32,
+    pub samples_per_pixel: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Error raised by an output path template that can't be expanded
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateError {
+    pub message: String,
+}
+
+impl TemplateError {
+    pub fn new(message: &str) -> TemplateError {
+        TemplateError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Expand the tokens of an output path, e.g. `renders/{scene}/{camera}_{frame:04}.png`
+///
+/// A token is a name of `TEMPLATE_TOKENS` in braces; the numbers (`frame`, `spp`, `width`
+/// and `height`) can be padded with zeros to a width given after a colon, `{frame:04}`.
+/// `{{` and `}}` stand for braces
+///
+/// ### Arguments
+///
+/// * `template` - The path with its tokens
+/// * `tokens` - The values of the tokens
+///
+/// ### Returns
+///
+/// `Result<String, TemplateError>` - The path, an error for an unknown or unclosed token
+///
+pub fn expand_template(template: &str, tokens: &PathTokens) -> Result<String, TemplateError> {
+    let mut path = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        path.push_str(&rest[..start]);
+        let brace = &rest[start..];
+        if brace.starts_with("{{") || brace.starts_with("}}") {
+            path.push_str(&brace[..1]);
+            rest = &brace[2..];
+            continue;
+        }
+        let end = match brace.find('}') {
+            Some(end) if brace.starts_with('{') => end,
+            _ => {
+                return Err(TemplateError::new(&format!(
+                    "unmatched brace in the output path '{}', write {{{{ or }}}} for a brace",
+                    template
+                )))
+            }
+        };
+        path.push_str(&token_value(&brace[1..end], tokens)?);
+        rest = &brace[end + 1..];
+    }
+    path.push_str(rest);
+    Ok(path)
+}
+
+/// Value of a token, `name` or `name:width`
+fn token_value(token: &str, tokens: &PathTokens) -> Result<String, TemplateError> {
+    let (name, width) = match token.split_once(':') {
+        Some((name, width)) => {
+            let width: usize = width.parse().map_err(|_| {
+                TemplateError::new(&format!(
+                    "the width of the token {{{}}} must be a number, e.g. {{{}:04}}",
+                    token, name
+                ))
+            })?;
+            (name, Some(width))
+        }
+        None => (token, None),
+    };
+    let number = match name {
+        "frame" => tokens.frame,
+        "spp" => tokens.samples_per_pixel,
+        "width" => tokens.width,
+        "height" => tokens.height,
+        "scene" | "camera" if width.is_some() => {
+            return Err(TemplateError::new(&format!(
+                "only numbers can be padded, {{{}}} can't",
+                token
+            )))
+        }
+        "scene" => return Ok(tokens.scene.clone()),
+        "camera" => return Ok(tokens.camera.clone()),
+        _ => {
+            return Err(TemplateError::new(&format!(
+                "unknown token {{{}}} in the output path, expected one of {}",
+                name,
+                TEMPLATE_TOKENS.join(", ")
+            )))
+        }
+    };
+    Ok(format!("{:0width$}", number, width = width.unwrap_or(0)))
+}
+
+/// Whether an output path template holds a token, with or without a width
+pub fn has_token(template: &str, name: &str) -> bool {
+    template.contains(&format!("{{{}}}", name)) || template.contains(&format!("{{{}:", name))
+}
@@ -0,0 +1,171 @@
+use std::f64::consts::PI;
+
+/// Number of steps of the table the filters are sampled with
+const TABLE_SIZE: usize = 256;
+
+/// Shape of a pixel reconstruction filter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    /// Constant weight over the filter, the average of the samples inside the pixel with
+    /// its default radius of half a pixel
+    Box,
+    /// Weight falling linearly to zero at the radius
+    Tent,
+    /// Gaussian with a standard deviation of a third of the radius, shifted to reach zero at
+    /// the radius
+    Gaussian,
+    /// Mitchell-Netravali cubic with `B = C = 1/3`, sharper than the Gaussian, with small
+    /// negative lobes
+    Mitchell,
+    /// Blackman-Harris window, smooth like the Gaussian with less blur
+    BlackmanHarris,
+}
+
+impl FilterKind {
+    /// Filter from its name: `box`, `tent`, `gaussian`, `mitchell` or `blackman-harris`
+    pub fn from_name(name: &str) -> Option<FilterKind> {
+        match name {
+            "box" => Some(FilterKind::Box),
+            "tent" => Some(FilterKind::Tent),
+            "gaussian" => Some(FilterKind::Gaussian),
+            "mitchell" => Some(FilterKind::Mitchell),
+            "blackman-harris" => Some(FilterKind::BlackmanHarris),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::BlackmanHarris => "blackman-harris",
+        }
+    }
+
+    /// Radius of the filter in pixels when none is given
+    pub fn default_radius(&self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell | FilterKind::BlackmanHarris => 2.0,
+        }
+    }
+
+    /// Unnormalized weight of a sample at a distance `x` from the pixel center along one
+    /// axis, zero beyond the radius
+    pub fn evaluate(&self, x: f64, radius: f64) -> f64 {
+        let x = x.abs();
+        if x > radius {
+            return 0.0;
+        }
+        match self {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => radius - x,
+            FilterKind::Gaussian => {
+                let sigma = radius / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(x) - gaussian(radius)
+            }
+            FilterKind::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let t = 2.0 * x / radius;
+                let value = if t < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * t.powi(3)
+                        + (-18.0 + 12.0 * b + 6.0 * c) * t * t
+                        + (6.0 - 2.0 * b)
+                } else {
+                    (-b - 6.0 * c) * t.powi(3)
+                        + (6.0 * b + 30.0 * c) * t * t
+                        + (-12.0 * b - 48.0 * c) * t
+                        + (8.0 * b + 24.0 * c)
+                };
+                value / 6.0
+            }
+            FilterKind::BlackmanHarris => {
+                let t = 2.0 * PI * (x + radius) / (2.0 * radius);
+                0.35875 - 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() - 0.01168 * (3.0 * t).cos()
+            }
+        }
+    }
+}
+
+/// Pixel reconstruction filter, weighting the samples of a pixel by their distance to its
+/// center, samples reaching into the neighboring pixels when the radius is larger than half
+/// a pixel
+///
+/// The camera rays are spread over the filter with a density following the absolute value
+/// of its weight (filter importance sampling), so every pixel is still rendered on its own,
+/// and samples under the negative lobes of the filter count negatively
+#[derive(Clone, Debug, PartialEq)]
+pub struct PixelFilter {
+    pub kind: FilterKind,
+    /// Distance from the pixel center beyond which samples have no weight, in pixels
+    pub radius: f64,
+    /// Cumulative distribution of the absolute weight over `TABLE_SIZE` steps across the
+    /// filter, from `-radius` to `radius`
+    cdf: Vec<f64>,
+}
+
+impl Default for PixelFilter {
+    fn default() -> Self {
+        PixelFilter::new(FilterKind::Box, FilterKind::Box.default_radius())
+    }
+}
+
+impl PixelFilter {
+    pub fn new(kind: FilterKind, radius: f64) -> PixelFilter {
+        let radius = radius.max(1e-3);
+        let step = 2.0 * radius / TABLE_SIZE as f64;
+        let mut cdf = Vec::with_capacity(TABLE_SIZE + 1);
+        let mut total = 0.0;
+        cdf.push(0.0);
+        for k in 0..TABLE_SIZE {
+            let x = -radius + (k as f64 + 0.5) * step;
+            total += kind.evaluate(x, radius).abs();
+            cdf.push(total);
+        }
+        cdf.iter_mut()
+            .for_each(|value| *value /= total.max(f64::MIN_POSITIVE));
+        PixelFilter { kind, radius, cdf }
+    }
+
+    /// Position of a sample along one axis, from the left or top edge of the pixel, and the
+    /// sign of its weight
+    ///
+    /// ### Arguments
+    ///
+    /// * `u` - A uniform number in `[0, 1)`, e.g. from a stratified pattern
+    ///
+    /// ### Returns
+    ///
+    /// `(f64, f64)` - The position in pixels, between `0.5 - radius` and `0.5 + radius`, and
+    /// 1 or -1
+    ///
+    pub fn sample(&self, u: f64) -> (f64, f64) {
+        if self.kind == FilterKind::Box {
+            // exact for the default radius, the position is `u` itself
+            return (u * 2.0 * self.radius + (0.5 - self.radius), 1.0);
+        }
+        let k = self
+            .cdf
+            .partition_point(|value| *value <= u)
+            .clamp(1, TABLE_SIZE);
+        let (low, high) = (self.cdf[k - 1], self.cdf[k]);
+        let t = if high > low {
+            (u - low) / (high - low)
+        } else {
+            0.5
+        };
+        let step = 2.0 * self.radius / TABLE_SIZE as f64;
+        let x = -self.radius + (k as f64 - 1.0 + t) * step;
+        let sign = if self.kind.evaluate(x, self.radius) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        (0.5 + x, sign)
+    }
+}
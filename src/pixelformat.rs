@@ -0,0 +1,135 @@
+use crate::color::Color;
+use crate::gbuffer::write_pfm;
+
+use std::io::Write;
+
+/// Type of the channels of a saved image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelDepth {
+    /// 8-bit integers, in PNG files
+    Eight,
+    /// 16-bit integers, in PNG files
+    Sixteen,
+    /// 32-bit floats, in PFM files
+    Float,
+}
+
+/// Channels of a saved image and how they are stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelFormat {
+    /// Whether the image has a single luminance channel instead of RGB
+    pub gray: bool,
+    pub depth: ChannelDepth,
+}
+
+impl PixelFormat {
+    /// Format from its name: `gray` or `rgb` followed by the bits per channel, `8`, `16` or
+    /// `32` (floats), e.g. `gray16`
+    pub fn from_name(name: &str) -> Option<PixelFormat> {
+        let (gray, bits) = match name.strip_prefix("gray") {
+            Some(bits) => (true, bits),
+            None => (false, name.strip_prefix("rgb")?),
+        };
+        let depth = match bits {
+            "8" => ChannelDepth::Eight,
+            "16" => ChannelDepth::Sixteen,
+            "32" => ChannelDepth::Float,
+            _ => return None,
+        };
+        Some(PixelFormat { gray, depth })
+    }
+
+    /// Name of the format (see `from_name`)
+    pub fn name(&self) -> String {
+        let bits = match self.depth {
+            ChannelDepth::Eight => 8,
+            ChannelDepth::Sixteen => 16,
+            ChannelDepth::Float => 32,
+        };
+        format!("{}{}", if self.gray { "gray" } else { "rgb" }, bits)
+    }
+
+    pub fn channels(&self) -> usize {
+        if self.gray {
+            1
+        } else {
+            3
+        }
+    }
+
+    /// Extension of the files holding the format: `pfm` for floats, `png` otherwise
+    pub fn extension(&self) -> &'static str {
+        match self.depth {
+            ChannelDepth::Float => "pfm",
+            _ => "png",
+        }
+    }
+
+    /// Whether the values are stored as they are, without going through a color pipeline
+    pub fn is_float(&self) -> bool {
+        self.depth == ChannelDepth::Float
+    }
+}
+
+/// Save an image in a pixel format, gray images keeping the luminance of the colors
+///
+/// Floats are written as they are to a PFM file, integers to a PNG file after the values
+/// are clamped to `[0, 1]`, so they should already be encoded for display
+///
+/// ### Arguments
+///
+/// * `filename` - The path of the file, see `PixelFormat::extension`
+/// * `width`, `height` - The size of the image
+/// * `format` - The channels and their type
+/// * `values` - The colors of the pixels, row by row from the top
+///
+/// ### Returns
+///
+/// `std::io::Result<()>` - An error if the file can't be written
+///
+pub fn save_pixels(
+    filename: &str,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    values: &[Color],
+) -> std::io::Result<()> {
+    let channels: Vec<f64> = if format.gray {
+        values.iter().map(Color::luminance).collect()
+    } else {
+        values.iter().flat_map(Color::to_array).collect()
+    };
+    let (depth, data) = match format.depth {
+        ChannelDepth::Float => {
+            return write_pfm(filename, width, height, format.channels(), &channels)
+        }
+        ChannelDepth::Eight => (
+            png::BitDepth::Eight,
+            channels
+                .iter()
+                .map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8)
+                .collect(),
+        ),
+        // PNG stores 16-bit channels big-endian
+        ChannelDepth::Sixteen => (
+            png::BitDepth::Sixteen,
+            channels
+                .iter()
+                .flat_map(|c| ((c.clamp(0.0, 1.0) * 65535.0) as u16).to_be_bytes())
+                .collect::<Vec<u8>>(),
+        ),
+    };
+    let file = std::fs::File::create(filename)?;
+    let mut w = std::io::BufWriter::new(file);
+    let mut encoder = png::Encoder::new(&mut w, width, height);
+    encoder.set_color(if format.gray {
+        png::ColorType::Grayscale
+    } else {
+        png::ColorType::Rgb
+    });
+    encoder.set_depth(depth);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    w.flush()
+}
@@ -0,0 +1,114 @@
+/// Side of the square tiles the pixels are traversed in by the space filling curves
+pub const ORDER_TILE_SIZE: u32 = 16;
+
+/// Order the pixels of an image are rendered in
+///
+/// The space filling curves render the image in bands of `ORDER_TILE_SIZE` rows, tile by
+/// tile, so consecutive camera rays go through neighbouring pixels in both directions and
+/// mostly walk the same nodes of the bounding volume hierarchy. Every pixel has its own
+/// sampler, so the image doesn't depend on the order
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelOrder {
+    /// Row after row, every row from left to right
+    #[default]
+    Scanline,
+    /// Z-order curve inside the tiles, interleaving the bits of the pixel position
+    Morton,
+    /// Hilbert curve inside the tiles, every pixel next to the one before it
+    Hilbert,
+}
+
+impl PixelOrder {
+    /// Order from its name: `scanline`, `morton` or `hilbert`
+    pub fn from_name(name: &str) -> Option<PixelOrder> {
+        match name {
+            "scanline" => Some(PixelOrder::Scanline),
+            "morton" => Some(PixelOrder::Morton),
+            "hilbert" => Some(PixelOrder::Hilbert),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PixelOrder::Scanline => "scanline",
+            PixelOrder::Morton => "morton",
+            PixelOrder::Hilbert => "hilbert",
+        }
+    }
+
+    /// Number of rows rendered together, the height of the tiles
+    pub fn band_height(&self) -> u32 {
+        match self {
+            PixelOrder::Scanline => 1,
+            PixelOrder::Morton | PixelOrder::Hilbert => ORDER_TILE_SIZE,
+        }
+    }
+
+    /// Positions of the pixels of a tile in the order they are rendered
+    ///
+    /// ### Arguments
+    ///
+    /// * `width` - The width of the tile, at most `ORDER_TILE_SIZE` for the curves
+    /// * `height` - The height of the tile, at most `band_height`
+    ///
+    /// ### Returns
+    ///
+    /// `Vec<(u32, u32)>` - The column and row of every pixel in the tile, each pixel once
+    ///
+    pub fn tile_pixels(&self, width: u32, height: u32) -> Vec<(u32, u32)> {
+        let curve: fn(u32) -> (u32, u32) = match self {
+            PixelOrder::Scanline => {
+                return (0..height)
+                    .flat_map(|y| (0..width).map(move |x| (x, y)))
+                    .collect()
+            }
+            PixelOrder::Morton => morton_position,
+            PixelOrder::Hilbert => hilbert_position,
+        };
+        // the curves cover the full tile, the pixels of tiles cut by the image edge are kept
+        (0..ORDER_TILE_SIZE * ORDER_TILE_SIZE)
+            .map(curve)
+            .filter(|&(x, y)| x < width && y < height)
+            .collect()
+    }
+}
+
+/// Position of the pixel at a distance along the Z-order curve: the even bits of the
+/// distance are its column, the odd bits its row
+fn morton_position(distance: u32) -> (u32, u32) {
+    let compact = |mut bits: u32| {
+        bits &= 0x5555_5555;
+        bits = (bits | (bits >> 1)) & 0x3333_3333;
+        bits = (bits | (bits >> 2)) & 0x0f0f_0f0f;
+        bits = (bits | (bits >> 4)) & 0x00ff_00ff;
+        (bits | (bits >> 8)) & 0x0000_ffff
+    };
+    (compact(distance), compact(distance >> 1))
+}
+
+/// Position of the pixel at a distance along the Hilbert curve of a tile, the curve
+/// starting at the top left corner and ending at the top right one
+fn hilbert_position(distance: u32) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut rest = distance;
+    let mut size = 1;
+    while size < ORDER_TILE_SIZE {
+        let right = 1 & (rest / 2);
+        let down = 1 & (rest ^ right);
+        // the top quadrants hold the smaller curve mirrored along a diagonal, so the curve
+        // runs on from one quadrant to the next
+        if down == 0 {
+            if right == 1 {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += size * right;
+        y += size * down;
+        rest /= 4;
+        size *= 2;
+    }
+    (x, y)
+}
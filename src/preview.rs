@@ -0,0 +1,79 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::integrator::IntegratorKind;
+use crate::object::Material;
+use crate::object::SceneLight;
+use crate::object::Sphere;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+use nalgebra::Vector3;
+
+/// Vertical field of view in degrees the shader ball scene is framed for
+pub const SHADER_BALL_FOV: f64 = 30.0;
+
+/// Longest side in pixels of the thumbnails of scene files (see `thumbnail_settings`)
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+/// Standard scene to look at a material: a ball of the material standing on a gray floor
+/// next to a gray and a chrome reference ball, under a key, a fill and a rim light
+///
+/// The scene has a single camera, `preview`, framed for `SHADER_BALL_FOV`
+pub fn shader_ball(material: Material) -> Scene {
+    let floor = Material::new(Vector3::new(0.9, 0.1, 0.0), Color::gray(0.4), 10.0);
+    let gray = Material::new(Vector3::new(0.9, 0.05, 0.0), Color::gray(0.18), 10.0);
+    let chrome = Material::new(Vector3::new(0.0, 10.0, 0.8), Color::WHITE, 1425.0);
+    let spheres = vec![
+        Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0, floor).with_name("floor"),
+        Sphere::new(Vector3::new(0.0, 1.0, 0.0), 1.0, material).with_name("ball"),
+        Sphere::new(Vector3::new(-1.7, 0.35, 0.6), 0.35, gray).with_name("gray_ball"),
+        Sphere::new(Vector3::new(1.7, 0.35, 0.6), 0.35, chrome).with_name("chrome_ball"),
+    ];
+    let lights = vec![
+        SceneLight::new(Vector3::new(-4.0, 6.0, 4.0), 1.5)
+            .with_radius(0.5)
+            .with_shadow_samples(8)
+            .with_name("key"),
+        SceneLight::new(Vector3::new(5.0, 3.0, 3.0), 0.6).with_name("fill"),
+        SceneLight::new(Vector3::new(2.0, 5.0, -6.0), 1.0).with_name("rim"),
+    ];
+    let camera = Camera::new(
+        "preview",
+        Vector3::new(0.0, 1.6, 6.5),
+        Vector3::new(0.0, 0.8, 0.0),
+    );
+    Scene::new(spheres, lights).with_cameras(vec![camera])
+}
+
+/// Settings of a thumbnail of a scene, for a file browser or to go through a library of
+/// scenes: the image scaled down to `size` pixels on its longest side, one sample per
+/// pixel, no supersampling, rendered with the Whitted integrator
+pub fn thumbnail_settings(settings: &RenderSettings, size: u32) -> RenderSettings {
+    let size = size.max(1);
+    let (width, height) = if settings.width >= settings.height {
+        let height = (size as u64 * settings.height as u64 / settings.width.max(1) as u64) as u32;
+        (size, height.max(1))
+    } else {
+        let width = (size as u64 * settings.width as u64 / settings.height as u64) as u32;
+        (width.max(1), size)
+    };
+    RenderSettings {
+        width,
+        height,
+        supersampling: 1,
+        ..settings.clone()
+    }
+    .with_samples_per_pixel(1)
+    .with_integrator(IntegratorKind::default())
+}
+
+/// The scene of a thumbnail, its lights with hard shadows: point lights with a single
+/// shadow ray each
+pub fn thumbnail_scene(scene: &Scene) -> Scene {
+    let lights = scene
+        .lights
+        .iter()
+        .map(|light| light.clone().with_radius(0.0).with_shadow_samples(1))
+        .collect();
+    scene.clone().with_lights(lights)
+}
@@ -0,0 +1,153 @@
+use crate::color::Color;
+use crate::json::Json;
+use crate::ray::Ray;
+use crate::sampling::uniform_sphere;
+use crate::sampling::uniform_sphere_pdf;
+use crate::scene::Scene;
+use crate::settings::color_to_json;
+use crate::settings::RenderSettings;
+
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+
+/// Number of spherical harmonics coefficients of a probe, the bands 0 to 2
+pub const SH_COEFFICIENTS: usize = 9;
+
+/// Light arriving at a point from every direction, projected on the real spherical
+/// harmonics of the bands 0 to 2, for the indirect lighting of a game engine
+///
+/// Nine coefficients per channel keep the low frequencies of the light, which is all the
+/// irradiance of a diffuse surface needs (Ramamoorthi and Hanrahan, "An Efficient
+/// Representation for Irradiance Environment Maps", 2001). The coefficients are those of
+/// the radiance, in the order of `sh_basis`, the cosine convolution is left to the engine
+/// or to `irradiance`
+#[derive(Clone, Debug, PartialEq)]
+pub struct IrradianceProbe {
+    pub position: Vector3<f64>,
+    /// Coefficients of the radiance in the basis of `sh_basis`
+    pub coefficients: [Color; SH_COEFFICIENTS],
+}
+
+impl IrradianceProbe {
+    /// Probe of the light arriving at a point, traced with the integrator of the settings
+    ///
+    /// ### Arguments
+    ///
+    /// * `position` - The point the light arrives at, outside of the objects, in world space
+    /// * `scene` - The scene
+    /// * `settings` - The render settings (integrator, sampler, background and bounces)
+    /// * `samples` - The number of directions the light is traced from, spread over the
+    ///   sphere by the sampler
+    ///
+    /// ### Returns
+    ///
+    /// `IrradianceProbe` - The probe, the same for the same settings
+    ///
+    pub fn bake(
+        position: Vector3<f64>,
+        scene: &Scene,
+        settings: &RenderSettings,
+        samples: u32,
+    ) -> IrradianceProbe {
+        let samples = samples.max(1);
+        let integrator = settings.integrator.integrator();
+        let mut sampler = settings.sampler.for_pixel(0, 0, samples, settings.seed);
+        let mut coefficients = [Color::BLACK; SH_COEFFICIENTS];
+        for k in 0..samples {
+            sampler.start_pixel(k);
+            let (u, v) = sampler.get_2d();
+            let direction = uniform_sphere(u, v);
+            let ray = Ray::new(position - scene.origin, direction);
+            let radiance = integrator.li(&ray, scene, settings, &mut *sampler, 0);
+            let weight = 1.0 / (uniform_sphere_pdf() * samples as f64);
+            for (coefficient, basis) in coefficients.iter_mut().zip(sh_basis(direction)) {
+                *coefficient += radiance * (basis * weight);
+            }
+        }
+        IrradianceProbe {
+            position,
+            coefficients,
+        }
+    }
+
+    /// Radiance arriving from a direction, rebuilt from the coefficients
+    pub fn radiance(&self, direction: Vector3<f64>) -> Color {
+        self.coefficients
+            .iter()
+            .zip(sh_basis(direction))
+            .fold(Color::BLACK, |sum, (coefficient, basis)| {
+                sum + *coefficient * basis
+            })
+    }
+
+    /// Irradiance of a surface facing the normal, the radiance convolved with the cosine
+    /// lobe of the hemisphere; a diffuse surface reflects `albedo * irradiance / PI`
+    pub fn irradiance(&self, normal: Vector3<f64>) -> Color {
+        // the convolution scales every band by its own factor
+        let band = [PI, 2.0 * PI / 3.0, PI / 4.0];
+        self.coefficients
+            .iter()
+            .zip(sh_basis(normal))
+            .enumerate()
+            .fold(Color::BLACK, |sum, (k, (coefficient, basis))| {
+                let factor = band[match k {
+                    0 => 0,
+                    1..=3 => 1,
+                    _ => 2,
+                }];
+                sum + *coefficient * (basis * factor)
+            })
+    }
+
+    /// Probe as a JSON object: its `position` and its `sh` coefficients, one `[r, g, b]`
+    /// array per basis function in the order of `sh_basis`
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            (
+                "position".to_string(),
+                Json::Array(
+                    [self.position.x, self.position.y, self.position.z]
+                        .map(Json::Number)
+                        .to_vec(),
+                ),
+            ),
+            (
+                "sh".to_string(),
+                Json::Array(
+                    self.coefficients
+                        .iter()
+                        .map(|c| color_to_json(*c))
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+}
+
+/// Real spherical harmonics of the bands 0 to 2 in a direction, in the usual order of the
+/// game engines: `Y00`, `Y1-1` (y), `Y10` (z), `Y11` (x), `Y2-2` (xy), `Y2-1` (yz), `Y20`
+/// (3z² - 1), `Y21` (xz) and `Y22` (x² - y²)
+pub fn sh_basis(direction: Vector3<f64>) -> [f64; SH_COEFFICIENTS] {
+    let direction = direction.normalize();
+    let (x, y, z) = (direction.x, direction.y, direction.z);
+    [
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3.0 * z * z - 1.0),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
+
+/// Probes as a JSON object with their `probes` array (see `IrradianceProbe::to_json`),
+/// the file read by the engine
+pub fn probes_to_json(probes: &[IrradianceProbe]) -> Json {
+    Json::Object(vec![(
+        "probes".to_string(),
+        Json::Array(probes.iter().map(IrradianceProbe::to_json).collect()),
+    )])
+}
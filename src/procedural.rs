@@ -0,0 +1,235 @@
+use crate::bvh::Bounds;
+use crate::bvh::Bvh;
+use crate::json::Json;
+use crate::object::recenter_spheres;
+use crate::object::Material;
+use crate::object::Sphere;
+use crate::ray::Ray;
+use crate::settings::vector_to_json;
+
+use nalgebra::Vector3;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+/// Sphere indices of the objects generated by procedurals start here (see `Scene::sphere`):
+/// the objects of the `k`-th procedural are `(k + 1) * PROCEDURAL_STRIDE` on
+pub const PROCEDURAL_STRIDE: usize = 1 << (usize::BITS / 2);
+
+/// Generator of the geometry of a procedural object, e.g. a plugin adding detail
+/// (fractals, displacement, instances) that would be too much to build up front
+///
+/// The generator is only called once a ray reaches the bounds of the object, so objects
+/// out of sight are never generated
+pub trait Procedural: Debug + Send + Sync {
+    /// Box holding every sphere `generate` returns
+    fn bounds(&self) -> Bounds;
+
+    /// Spheres of the object, called at most once per scene and from any render thread
+    fn generate(&self) -> Vec<Sphere>;
+
+    /// Object as an entry of the `procedural` list of a scene file, `None` for generators
+    /// scene files can't describe
+    fn to_json(&self) -> Option<Json> {
+        None
+    }
+}
+
+/// Spheres generated by a procedural and their hierarchy
+#[derive(Debug, Default)]
+pub struct ProceduralGeometry {
+    pub spheres: Vec<Sphere>,
+    pub bvh: Bvh,
+}
+
+/// Object of a scene whose spheres are generated on demand by a `Procedural`, the first
+/// time a ray hits its bounds
+///
+/// The generated spheres are kept for the rest of the render and shared by the copies of
+/// the scene. They are shaded like the other objects, but they don't show in the name
+/// lookups, the mattes and the motion vectors, and rays starting inside them don't know
+/// about their media
+#[derive(Clone, Debug)]
+pub struct ProceduralObject {
+    pub generator: Arc<dyn Procedural>,
+    /// Bounds of the generator, asked for once
+    pub bounds: Bounds,
+    geometry: Arc<OnceLock<ProceduralGeometry>>,
+}
+
+impl ProceduralObject {
+    pub fn new(generator: Arc<dyn Procedural>) -> ProceduralObject {
+        ProceduralObject {
+            bounds: generator.bounds(),
+            generator,
+            geometry: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Spheres of the object, generated on the first call
+    pub fn geometry(&self) -> &ProceduralGeometry {
+        self.geometry.get_or_init(|| {
+            let spheres = self.generator.generate();
+            let bvh = Bvh::build(&spheres);
+            ProceduralGeometry { spheres, bvh }
+        })
+    }
+
+    /// Whether the spheres were generated already
+    pub fn is_generated(&self) -> bool {
+        self.geometry.get().is_some()
+    }
+
+    /// Nearest of the spheres along the ray, generating them if the ray reaches the bounds
+    /// before its `t_max`
+    pub fn nearest(&self, ray: &Ray) -> Option<(usize, f64)> {
+        if !self.bounds.hit(ray, ray.t_max) {
+            return None;
+        }
+        let geometry = self.geometry();
+        geometry.bvh.nearest(ray, &geometry.spheres)
+    }
+
+    /// Whether one of the spheres blocks the ray between its `t_min` and `t_max`
+    pub fn any_hit(&self, ray: &Ray) -> bool {
+        if !self.bounds.hit(ray, ray.t_max) {
+            return false;
+        }
+        let geometry = self.geometry();
+        geometry.bvh.any_hit(ray, &geometry.spheres)
+    }
+}
+
+/// Procedural object moved so that `origin` is the origin of its coordinates, its spheres
+/// generated where they were and moved with their materials (see `Scene::recentered`)
+#[derive(Debug)]
+pub struct OffsetProcedural {
+    pub input: Arc<dyn Procedural>,
+    /// World position of the origin of the coordinates of the spheres
+    pub origin: Vector3<f64>,
+}
+
+impl Procedural for OffsetProcedural {
+    fn bounds(&self) -> Bounds {
+        let bounds = self.input.bounds();
+        Bounds {
+            min: bounds.min - self.origin,
+            max: bounds.max - self.origin,
+        }
+    }
+
+    fn generate(&self) -> Vec<Sphere> {
+        let mut spheres = self.input.generate();
+        recenter_spheres(&mut spheres, self.origin);
+        spheres
+    }
+
+    /// Input as it is, where it was before the move
+    fn to_json(&self) -> Option<Json> {
+        self.input.to_json()
+    }
+}
+
+/// Sphereflake fractal (Haines, "A Proposal for Standard Graphics Environments", 1987): a
+/// sphere with nine spheres a third of its size around it, each with eight around it, and
+/// so on, `9^depth` spheres in all
+#[derive(Clone, Debug)]
+pub struct SphereFlake {
+    pub center: Vector3<f64>,
+    pub radius: f64,
+    /// Levels of children below the central sphere
+    pub depth: u32,
+    pub material: Arc<Material>,
+}
+
+impl SphereFlake {
+    pub fn new(
+        center: Vector3<f64>,
+        radius: f64,
+        depth: u32,
+        material: impl Into<Arc<Material>>,
+    ) -> SphereFlake {
+        SphereFlake {
+            center,
+            radius,
+            depth,
+            material: material.into(),
+        }
+    }
+
+    /// Add a sphere and its children, away from the side of its parent
+    fn grow(
+        &self,
+        spheres: &mut Vec<Sphere>,
+        center: Vector3<f64>,
+        radius: f64,
+        axis: Vector3<f64>,
+        depth: u32,
+    ) {
+        spheres.push(Sphere::new(center, radius, self.material.clone()));
+        if depth == 0 {
+            return;
+        }
+        // six children around the equator and three above it, in the frame of the axis
+        let helper = if axis.x.abs() < 0.9 {
+            Vector3::x()
+        } else {
+            Vector3::y()
+        };
+        let u = axis.cross(&helper).normalize();
+        let v = axis.cross(&u);
+        let child = radius / 3.0;
+        let mut directions = Vec::with_capacity(9);
+        for k in 0..6 {
+            let angle = k as f64 * std::f64::consts::PI / 3.0;
+            directions.push(u * angle.cos() + v * angle.sin());
+        }
+        for k in 0..3 {
+            let angle = (k as f64 * 2.0 + 1.0) * std::f64::consts::PI / 3.0;
+            let around = u * angle.cos() + v * angle.sin();
+            directions.push((around + axis * 2.0_f64.sqrt()).normalize());
+        }
+        for direction in directions {
+            self.grow(
+                spheres,
+                center + direction * (radius + child),
+                child,
+                direction,
+                depth - 1,
+            );
+        }
+    }
+}
+
+impl Procedural for SphereFlake {
+    fn bounds(&self) -> Bounds {
+        // every level adds at most two thirds of the size of the one above
+        let reach = self.radius * 3.0;
+        Bounds {
+            min: self.center - Vector3::repeat(reach),
+            max: self.center + Vector3::repeat(reach),
+        }
+    }
+
+    fn generate(&self) -> Vec<Sphere> {
+        let mut spheres = Vec::with_capacity(9_usize.pow(self.depth.min(8)));
+        self.grow(
+            &mut spheres,
+            self.center,
+            self.radius,
+            Vector3::y(),
+            self.depth,
+        );
+        spheres
+    }
+
+    fn to_json(&self) -> Option<Json> {
+        Some(Json::Object(vec![
+            ("type".to_string(), Json::String("sphereflake".to_string())),
+            ("center".to_string(), vector_to_json(self.center)),
+            ("radius".to_string(), Json::Number(self.radius)),
+            ("depth".to_string(), Json::Number(self.depth as f64)),
+            ("material".to_string(), self.material.to_json()),
+        ]))
+    }
+}
@@ -0,0 +1,161 @@
+use crate::image::Image;
+use crate::resample::ResizeFilter;
+
+use std::path::Path;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+
+/// Longest side of the proxies written next to the saved images, 0 when none are written
+static PROXY_SIZE: AtomicU32 = AtomicU32::new(0);
+
+/// Longest side of the proxies when they are enabled without a size
+pub const DEFAULT_PROXY_SIZE: u32 = 256;
+
+/// Write a downscaled proxy next to every image saved from now on (see `proxy_path`), for
+/// render farm dashboards and for browsing frame sequences, 0 to stop writing them
+pub fn set_proxy_size(size: u32) {
+    PROXY_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// Longest side of the proxies, `None` when they are not written
+pub fn proxy_size() -> Option<u32> {
+    Some(PROXY_SIZE.load(Ordering::Relaxed)).filter(|size| *size > 0)
+}
+
+/// Path of the proxy of an image: its file name with `.preview.png` in place of the
+/// extension, e.g. `out.preview.png` for `out.png`
+pub fn proxy_path(filename: &str) -> String {
+    Path::new(filename)
+        .with_extension("preview.png")
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Save the proxy of an image that was saved to `filename`, if proxies are enabled
+pub fn save_proxy(image: &Image, filename: &str) -> std::io::Result<()> {
+    match proxy_size() {
+        Some(size) => proxy(image, size).save_png(&proxy_path(filename)),
+        None => Ok(()),
+    }
+}
+
+/// Size of the proxy of an image, its longest side being `size` (images already that
+/// small keep their size)
+pub fn proxy_dimensions(width: u32, height: u32, size: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest <= size {
+        return (width, height);
+    }
+    let scale = |side: u32| (side as u64 * size as u64 + longest as u64 / 2) / longest as u64;
+    (scale(width).max(1) as u32, scale(height).max(1) as u32)
+}
+
+/// Copy of an image shrunk with a Lanczos filter so that its longest side is `size`
+pub fn proxy(image: &Image, size: u32) -> Image {
+    let (width, height) = proxy_dimensions(image.width, image.height, size);
+    image.resize(width, height, ResizeFilter::Lanczos3)
+}
+
+/// Proxy of an image written a band of rows at a time (see `image::ImageStream`)
+///
+/// The rows are averaged with a box filter into an image twice the size of the proxy as
+/// they come, which is shrunk to the proxy with a Lanczos filter at the end
+pub struct ProxyBuilder {
+    path: String,
+    width: u32,
+    height: u32,
+    downscale: BoxDownscale,
+}
+
+impl ProxyBuilder {
+    /// Start the proxy of an image of the given size saved to `filename`
+    pub fn new(filename: &str, width: u32, height: u32, size: u32) -> ProxyBuilder {
+        let (proxy_width, proxy_height) = proxy_dimensions(width, height, size);
+        let (box_width, box_height) = proxy_dimensions(width, height, size.saturating_mul(2));
+        ProxyBuilder {
+            path: proxy_path(filename),
+            width: proxy_width,
+            height: proxy_height,
+            downscale: BoxDownscale::new(width, height, box_width, box_height),
+        }
+    }
+
+    /// Add complete rows of RGB pixels of the image, starting at row `first_row`
+    pub fn add_rows(&mut self, first_row: u32, data: &[u8]) {
+        self.downscale.add_rows(first_row, data);
+    }
+
+    /// Save the proxy once all the rows were added
+    pub fn finish(self) -> std::io::Result<()> {
+        let image = self.downscale.finish();
+        image
+            .resize(self.width, self.height, ResizeFilter::Lanczos3)
+            .save_png(&self.path)
+    }
+}
+
+/// Box filter shrinking an RGB image fed a band of rows at a time, so images that are
+/// never held in memory as a whole (see `render::render_to_file`) get proxies too
+///
+/// Every pixel of the result is the average of the block of source pixels falling in it
+pub struct BoxDownscale {
+    source_width: u32,
+    source_height: u32,
+    width: u32,
+    height: u32,
+    /// Sums of the channels of the source pixels of every pixel of the result
+    sums: Vec<u64>,
+    /// Number of source pixels summed in every pixel of the result
+    counts: Vec<u32>,
+}
+
+impl BoxDownscale {
+    pub fn new(source_width: u32, source_height: u32, width: u32, height: u32) -> BoxDownscale {
+        let pixels = width as usize * height as usize;
+        BoxDownscale {
+            source_width,
+            source_height,
+            width,
+            height,
+            sums: vec![0; pixels * 3],
+            counts: vec![0; pixels],
+        }
+    }
+
+    /// Add complete rows of RGB pixels of the source, starting at row `first_row`
+    pub fn add_rows(&mut self, first_row: u32, data: &[u8]) {
+        let row_size = self.source_width as usize * 3;
+        if row_size == 0 {
+            return;
+        }
+        // the column of the result every source column falls in
+        let columns: Vec<usize> = (0..self.source_width as u64)
+            .map(|i| (i * self.width as u64 / self.source_width as u64) as usize)
+            .collect();
+        for (k, row) in data.chunks_exact(row_size).enumerate() {
+            let j = (first_row as u64 + k as u64) * self.height as u64 / self.source_height as u64;
+            let offset = j as usize * self.width as usize;
+            for (pixel, column) in row.chunks_exact(3).zip(&columns) {
+                let index = offset + column;
+                self.counts[index] += 1;
+                for (sum, c) in self.sums[index * 3..index * 3 + 3].iter_mut().zip(pixel) {
+                    *sum += *c as u64;
+                }
+            }
+        }
+    }
+
+    /// Image of the averages, pixels no source pixel fell in staying black
+    pub fn finish(self) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for (index, count) in self.counts.iter().enumerate() {
+            if *count > 0 {
+                for c in 0..3 {
+                    let sum = self.sums[index * 3 + c];
+                    image.data[index * 3 + c] = ((sum + *count as u64 / 2) / *count as u64) as u8;
+                }
+            }
+        }
+        image
+    }
+}
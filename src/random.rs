@@ -0,0 +1,122 @@
+use nalgebra::Vector3;
+
+/// Small pseudo-random number generator (PCG32)
+///
+/// Seeded explicitly everywhere it is used, so renders stay reproducible
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let mut rng = Rng { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    /// Seed the generator from a position, e.g. to decorrelate noise between shading points
+    pub fn from_point(point: Vector3<f64>) -> Rng {
+        let mut seed = 0xcbf2_9ce4_8422_2325_u64;
+        for c in point.iter() {
+            seed = (seed ^ c.to_bits()).wrapping_mul(0x0100_0000_01b3);
+        }
+        Rng::new(seed)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Uniform number in the range `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        self.next_u32() as f64 / 4_294_967_296.0
+    }
+}
+
+/// Pattern of the permutation pairing the strata of the two axes of `stratified_2d`
+const LATIN_HYPERCUBE_PATTERN: u32 = 0x68bc_21eb;
+
+/// Jittered 2D sample from the `index`-th of `count` strata of the unit square
+///
+/// A count that is a perfect square makes a grid of square cells. Any other count makes a
+/// Latin hypercube: both axes are split into `count` strata, the sample taking the
+/// `index`-th stratum along `x` and the stratum along `y` given by a fixed permutation, so
+/// every stratum of both axes holds one sample
+///
+/// ### Arguments
+///
+/// * `index` - The index of the sample, in the range `[0, count)`
+/// * `count` - The total number of samples
+/// * `rng` - The generator used to jitter the sample inside its cell
+///
+/// ### Returns
+///
+/// `(f64, f64)` - The sample, both coordinates in the range `[0, 1)`
+///
+pub fn stratified_2d(index: u32, count: u32, rng: &mut Rng) -> (f64, f64) {
+    stratified_2d_permuted(index, count, LATIN_HYPERCUBE_PATTERN, rng)
+}
+
+/// Jittered 2D sample like `stratified_2d`, the strata along `y` of a Latin hypercube being
+/// permuted with the given pattern (see `permute`)
+pub fn stratified_2d_permuted(index: u32, count: u32, pattern: u32, rng: &mut Rng) -> (f64, f64) {
+    let count = count.max(1);
+    let side = (count as f64).sqrt().round() as u32;
+    if side * side == count {
+        let (col, row) = (index % side, index / side);
+        return (
+            (col as f64 + rng.next_f64()) / side as f64,
+            (row as f64 + rng.next_f64()) / side as f64,
+        );
+    }
+    let index = index % count;
+    let row = permute(index, count, pattern);
+    (
+        (index as f64 + rng.next_f64()) / count as f64,
+        (row as f64 + rng.next_f64()) / count as f64,
+    )
+}
+
+/// Element `index` of a pseudo-random permutation of `[0, count)` chosen by `pattern`
+/// (Kensler, "Correlated Multi-Jittered Sampling")
+pub fn permute(mut index: u32, count: u32, pattern: u32) -> u32 {
+    let mut w = count.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        index ^= pattern;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= pattern >> 16;
+        index ^= (index & w) >> 4;
+        index ^= pattern >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= pattern >> 23;
+        index ^= (index & w) >> 1;
+        index = index.wrapping_mul(1 | pattern >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & w) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & w) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & w) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= w;
+        index ^= index >> 5;
+        if index < count {
+            break;
+        }
+    }
+    index.wrapping_add(pattern) % count
+}
@@ -0,0 +1,74 @@
+use nalgebra::Vector3;
+
+/// Distance rays leaving a surface are pushed off it by, relative to the largest coordinate
+/// of the point they leave from (see `offset_epsilon`)
+pub const RELATIVE_OFFSET: f64 = 1e-6;
+
+/// Smallest distance rays leaving a surface are pushed off it by, for the points at the
+/// origin of the scene
+pub const MIN_OFFSET: f64 = 1e-12;
+
+/// Distance a ray leaving a surface point is pushed off the surface by
+///
+/// The rounding errors of a hit point grow with its coordinates, which are far from the
+/// origin in large scenes and for surfaces hit at large distances, so the offset follows
+/// them: a fixed offset would leave acne on the surfaces of large scenes and step over the
+/// thin objects of small ones
+pub fn offset_epsilon(point: Vector3<f64>) -> f64 {
+    (point.amax() * RELATIVE_OFFSET).max(MIN_OFFSET)
+}
+
+/// Half-line starting at `origin` and going along `direction`, restricted to the distances
+/// between `t_min` and `t_max`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vector3<f64>,
+    /// Direction of the ray, normalized so that distances along the ray are in scene units
+    pub direction: Vector3<f64>,
+    /// Minimum accepted distance of an intersection
+    pub t_min: f64,
+    /// Maximum accepted distance of an intersection (the end of the segment for shadow rays)
+    pub t_max: f64,
+    /// Time at which the ray is traced, in seconds
+    pub time: f64,
+}
+
+impl Ray {
+    /// Ray accepting intersections at any non-negative distance, at time 0
+    pub fn new(origin: Vector3<f64>, direction: Vector3<f64>) -> Ray {
+        Ray {
+            origin,
+            direction,
+            t_min: 0.0,
+            t_max: f64::INFINITY,
+            time: 0.0,
+        }
+    }
+
+    pub fn with_t_max(mut self, t_max: f64) -> Ray {
+        self.t_max = t_max;
+        self
+    }
+
+    pub fn with_time(mut self, time: f64) -> Ray {
+        self.time = time;
+        self
+    }
+
+    /// Ray leaving a surface point, pushed off the surface along its normal `n` to the side
+    /// the ray goes to, so it doesn't hit the surface it starts from (see `offset_epsilon`)
+    pub fn offset(point: Vector3<f64>, n: Vector3<f64>, direction: Vector3<f64>) -> Ray {
+        let epsilon = offset_epsilon(point);
+        let origin = if direction.dot(&n) < 0.0 {
+            point - n * epsilon
+        } else {
+            point + n * epsilon
+        };
+        Ray::new(origin, direction)
+    }
+
+    /// Point at the distance `t` along the ray
+    pub fn at(&self, t: f64) -> Vector3<f64> {
+        self.origin + self.direction * t
+    }
+}
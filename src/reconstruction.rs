@@ -0,0 +1,150 @@
+use crate::color::Color;
+use crate::framebuffer::FrameBuffer;
+
+/// Reconstruction stage turning a noisy frame into the final radiance, using correlations
+/// between neighbouring pixels
+///
+/// Implementations get the whole frame with its AOVs, so new filters (gradient-domain
+/// solvers, learned denoisers, ...) can be plugged in without touching the renderer
+pub trait Reconstruction {
+    /// Reconstructed radiance of every pixel, in the layout of `frame.color`
+    fn reconstruct(&self, frame: &FrameBuffer) -> Vec<Color>;
+}
+
+/// Joint (cross) bilateral filter guided by the albedo, normal and depth AOVs
+///
+/// Every pixel is replaced by a weighted average of its neighbours, where neighbours lying
+/// on a different surface (according to the noise free AOVs) or having a very different
+/// radiance get a small weight. This smooths the noise of soft shadows and volumes while
+/// keeping geometric and texture edges sharp
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrossBilateral {
+    /// Half size of the filter window in pixels
+    pub radius: u32,
+    /// Number of times the filter is applied, every pass filtering the output of the
+    /// previous one
+    pub iterations: u32,
+    /// Standard deviation of the spatial gaussian, in pixels
+    pub sigma_spatial: f64,
+    /// Standard deviation of the radiance difference
+    pub sigma_color: f64,
+    /// Standard deviation of the albedo difference
+    pub sigma_albedo: f64,
+    /// Standard deviation of `1 - cos` of the angle between the normals
+    pub sigma_normal: f64,
+    /// Standard deviation of the depth difference relative to the depth of the pixel
+    pub sigma_depth: f64,
+}
+
+impl Default for CrossBilateral {
+    fn default() -> Self {
+        CrossBilateral {
+            radius: 6,
+            iterations: 1,
+            sigma_spatial: 3.0,
+            sigma_color: 0.15,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.1,
+            sigma_depth: 0.02,
+        }
+    }
+}
+
+impl CrossBilateral {
+    pub fn new(radius: u32, sigma_spatial: f64) -> CrossBilateral {
+        CrossBilateral {
+            radius,
+            sigma_spatial,
+            ..CrossBilateral::default()
+        }
+    }
+
+    pub fn with_sigma_color(mut self, sigma_color: f64) -> CrossBilateral {
+        self.sigma_color = sigma_color;
+        self
+    }
+
+    pub fn with_iterations(mut self, iterations: u32) -> CrossBilateral {
+        self.iterations = iterations;
+        self
+    }
+
+    pub fn with_sigma_albedo(mut self, sigma_albedo: f64) -> CrossBilateral {
+        self.sigma_albedo = sigma_albedo;
+        self
+    }
+
+    pub fn with_sigma_normal(mut self, sigma_normal: f64) -> CrossBilateral {
+        self.sigma_normal = sigma_normal;
+        self
+    }
+
+    pub fn with_sigma_depth(mut self, sigma_depth: f64) -> CrossBilateral {
+        self.sigma_depth = sigma_depth;
+        self
+    }
+
+    /// Weight of the pixel `q` in the filtered value of the pixel `p`, ignoring the distance
+    fn feature_weight(&self, frame: &FrameBuffer, p: usize, q: usize) -> f64 {
+        let gaussian = |distance2: f64, sigma: f64| (-distance2 / (2.0 * sigma * sigma)).exp();
+        // radiance is compared in the displayable range, so pixels of a highlight don't leak
+        // their energy into darker neighbours
+        let clamp = |c: Color| c.clamp(0.0, 1.0);
+        let color = gaussian(
+            (clamp(frame.color[p]) - clamp(frame.color[q])).norm_squared(),
+            self.sigma_color,
+        );
+        let (depth_p, depth_q) = (frame.depth[p], frame.depth[q]);
+        // pixels seeing the background only mix with other background pixels
+        if depth_p.is_infinite() || depth_q.is_infinite() {
+            if depth_p.is_infinite() && depth_q.is_infinite() {
+                return color;
+            }
+            return 0.0;
+        }
+        let depth = ((depth_p - depth_q) / depth_p).powi(2);
+        let normal = (1.0 - frame.normal[p].dot(&frame.normal[q])).powi(2);
+        let albedo = (frame.albedo[p] - frame.albedo[q]).norm_squared();
+        color
+            * gaussian(depth, self.sigma_depth)
+            * gaussian(normal, self.sigma_normal)
+            * gaussian(albedo, self.sigma_albedo)
+    }
+
+    /// One pass of the filter over the radiance of the frame
+    fn filter(&self, frame: &FrameBuffer) -> Vec<Color> {
+        let radius = self.radius as i64;
+        let (width, height) = (frame.width as i64, frame.height as i64);
+        let mut output = Vec::with_capacity(frame.color.len());
+        for j in 0..height {
+            for i in 0..width {
+                let p = (j * width + i) as usize;
+                let mut sum = Color::BLACK;
+                let mut total = 0.0;
+                for y in (j - radius).max(0)..=(j + radius).min(height - 1) {
+                    for x in (i - radius).max(0)..=(i + radius).min(width - 1) {
+                        let q = (y * width + x) as usize;
+                        let distance2 = ((x - i).pow(2) + (y - j).pow(2)) as f64;
+                        let spatial = (-distance2 / (2.0 * self.sigma_spatial.powi(2))).exp();
+                        let weight = spatial * self.feature_weight(frame, p, q);
+                        sum += frame.color[q] * weight;
+                        total += weight;
+                    }
+                }
+                // the center pixel always has a positive weight
+                output.push(sum / total);
+            }
+        }
+        output
+    }
+}
+
+impl Reconstruction for CrossBilateral {
+    fn reconstruct(&self, frame: &FrameBuffer) -> Vec<Color> {
+        let mut filtered = frame.clone();
+        for _ in 0..self.iterations.max(1) {
+            filtered.color = self.filter(&filtered);
+        }
+        filtered.color
+    }
+}
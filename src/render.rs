@@ -0,0 +1,1443 @@
+use crate::autosave;
+use crate::color::Color;
+use crate::colorspace::ColorPipeline;
+use crate::control::RenderControl;
+use crate::convergence::AccumulationSnapshot;
+use crate::convergence::ErrorEstimate;
+use crate::film::develop_pixels;
+use crate::film::Accumulator;
+use crate::film::Film;
+use crate::film::FilmPixel;
+use crate::framebuffer::render_features;
+use crate::framebuffer::FrameBuffer;
+use crate::gbuffer::GBuffer;
+use crate::image::check_image_size;
+use crate::image::Image;
+use crate::image::ImageError;
+use crate::image::ImageStream;
+use crate::image::Rect;
+use crate::integrator::IntegratorKind;
+use crate::integrator::Playblast;
+use crate::light::retain_unoccluded;
+use crate::light::Light;
+use crate::medium::MediumStack;
+use crate::object::Material;
+use crate::object::Sphere;
+use crate::pixelorder::PixelOrder;
+use crate::pixelorder::ORDER_TILE_SIZE;
+use crate::procedural::PROCEDURAL_STRIDE;
+use crate::random::stratified_2d;
+use crate::random::Rng;
+use crate::ray::Ray;
+use crate::reconstruction::Reconstruction;
+use crate::resample::ResizeFilter;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+use crate::sink::RenderSink;
+use crate::texture::texture;
+use crate::texture::udim_tile;
+
+use nalgebra::Vector3;
+use std::io::Write;
+use std::ops::AddAssign;
+use std::sync::Mutex;
+use std::time::Instant;
+
+pub const MISSING_TEXTURE_COLOR: Color = Color::new(1.0, 0.0, 1.0);
+
+/// Returning the reflection of the vector `i` on the normal `n`
+///
+/// ### Arguments
+///
+/// * `i` - The incident vector
+/// * `n` - The normal vector
+///
+/// ### Returns
+///
+/// Vector3<f64> - The reflected vector
+///
+pub fn reflect(i: Vector3<f64>, n: Vector3<f64>) -> Vector3<f64> {
+    i - n * 2.0 * (i.dot(&n))
+}
+
+/// Refraction of the vector `i` through a surface (Snell's law)
+///
+/// ### Arguments
+///
+/// * `i` - The incident vector (normalized)
+/// * `n` - The normal vector, facing the incident vector
+/// * `eta` - The ratio of the indices of refraction before and after the surface
+///
+/// ### Returns
+///
+/// `Option<Vector3<f64>>` - The refracted vector, `None` on total internal reflection
+///
+pub fn refract(i: Vector3<f64>, n: Vector3<f64>, eta: f64) -> Option<Vector3<f64>> {
+    let cos_i = -i.dot(&n).clamp(-1.0, 1.0);
+    let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+    if k < 0.0 {
+        return None;
+    }
+    Some(i * eta + n * (eta * cos_i - k.sqrt()))
+}
+
+/// Find the sphere closest to the ray origin along the ray
+///
+/// ### Arguments
+///
+/// * `ray` - The ray, only hits between its `t_min` and `t_max` are considered
+/// * `scene` - The scene, its spheres are searched through its bounding volume hierarchy
+///
+/// ### Returns
+///
+/// `Option<(usize, f64)>` - The index of the closest sphere and the distance to it
+///
+pub fn nearest_sphere(ray: &Ray, scene: &Scene) -> Option<(usize, f64)> {
+    let mut nearest = scene.bvh().nearest(ray, &scene.spheres);
+    // a procedural object is only reached (and generated) if its bounds are nearer than
+    // the nearest sphere so far
+    for (k, procedural) in scene.procedurals.iter().enumerate() {
+        let t_max = nearest.map_or(ray.t_max, |(_, distance)| distance);
+        if let Some((index, distance)) = procedural.nearest(&ray.with_t_max(t_max)) {
+            nearest = Some(((k + 1) * PROCEDURAL_STRIDE + index, distance));
+        }
+    }
+    nearest
+}
+
+/// Direction of the primary ray through the given point of the image plane
+///
+/// ### Arguments
+///
+/// * `x` - The horizontal position on the image, in pixels (`i + 0.5` is the pixel center)
+/// * `y` - The vertical position on the image, in pixels (`j + 0.5` is the pixel center)
+/// * `width` - The width of the image
+/// * `height` - The height of the image
+/// * `fov` - The vertical field of view in radians
+///
+/// ### Returns
+///
+/// Vector3<f64> - The normalized direction of the ray in camera space (looking along -Z)
+///
+pub fn primary_direction(x: f64, y: f64, width: u32, height: u32, fov: f64) -> Vector3<f64> {
+    // X and Y calculated from the camera's perspective by the formula
+    // x = (2 * (i + 0.5) / width - 1) * tan(fov / 2) * width / height
+    // y = -(2 * (j + 0.5) / height - 1) * tan(fov / 2)
+    // z = -1
+    let px = (2.0 * x / width as f64 - 1.0) * (fov / 2.0).tan() * width as f64 / height as f64;
+    let py = -(2.0 * y / height as f64 - 1.0) * (fov / 2.0).tan();
+    // The camera is at (0, 0, 0) and looks along the negative Z axis
+    // The direction of the ray is the normalized vector from the camera to the pixel
+    Vector3::new(px, py, -1.0).normalize()
+}
+
+/// Generate a ray from the camera to given object and evaluate the intersection
+/// if there is an intersection, return the intersection point, normal and material
+///
+/// ### Arguments
+///
+/// * `ray` - The ray
+/// * `scene` - The scene to intersect with
+///
+/// ### Returns
+///
+/// `(bool, Vector3<f64>, Vector3<f64>, Material)` - Whether the ray intersects the sphere or not,
+/// the intersection point, the normal and the material
+///
+pub fn scene_intersect(
+    ray: &Ray,
+    scene: &Scene,
+) -> Option<(bool, Vector3<f64>, Vector3<f64>, Material)> {
+    // initialize defaults
+    let mut point = Vector3::default();
+    let mut n = Vector3::default();
+    let mut material = Material::default();
+
+    // initialize minimum distance to max value of f64 (infinity used for comparison)
+    let mut spheres_dist = f64::MAX;
+    if let Some((index, dist_i)) = nearest_sphere(ray, scene) {
+        let sphere = scene.sphere(index);
+        spheres_dist = dist_i; // update minimum distance with the current distance
+        point = ray.at(dist_i); // get the intersection point
+        n = (point - sphere.center).normalize() as Vector3<f64>; // change the normal to point to center of the sphere
+        material = Material::clone(&sphere.material); // get material of the sphere
+    }
+    Some((spheres_dist < 1000.0, point, n, material))
+}
+
+/// Accumulate the diffuse and specular light intensities arriving at a surface point
+/// from all the lights of the scene, skipping lights that are shadowed by a sphere
+///
+/// ### Arguments
+///
+/// * `point` - The shaded point
+/// * `n` - The surface normal at `point`
+/// * `direction` - The direction of the incoming (view) ray
+/// * `specular_exponent` - The specular exponent of the surface material
+/// * `scene` - The scene
+///
+/// ### Returns
+///
+/// `(Color, Color)` - The diffuse and the specular light intensities
+///
+pub fn direct_lighting(
+    point: Vector3<f64>,
+    n: Vector3<f64>,
+    direction: Vector3<f64>,
+    specular_exponent: f64,
+    scene: &Scene,
+) -> (Color, Color) {
+    // compute color diffused by lambertian shading
+    // lambertian shading is the simplest and most common shading model:
+    // the color of a point is proportional to the cosine of the angle between the normal and the
+    // light vector
+    let mut diffuse_light_intensity = Color::BLACK;
+    let mut specular_light_intensity = Color::BLACK;
+    // every sample aims at its own stratum of the light, the samples blocked by an object
+    // leave their share of the light out
+    let mut samples = Vec::new();
+    for light in &scene.lights {
+        let light: &dyn Light = light;
+        let count = light.samples();
+        let mut rng = Rng::from_point(point);
+        for k in 0..count {
+            let u = stratified_2d(k, count, &mut rng);
+            if let Some(sample) = light.sample_li(point, u) {
+                samples.push((count, sample));
+            }
+        }
+    }
+    retain_unoccluded(&mut samples, point, n, scene);
+    for (count, sample) in samples {
+        let (diffuse, specular) = phong_factors(sample.direction, n, direction, specular_exponent);
+        let weight = sample.radiance / (sample.pdf * count as f64);
+        diffuse_light_intensity += weight * diffuse;
+        specular_light_intensity += weight * specular;
+    }
+    (diffuse_light_intensity, specular_light_intensity)
+}
+
+/// Diffuse and specular factors of the light arriving at a surface from a direction: the
+/// cosine of its angle with the normal (lambertian shading) and the Phong highlight
+///
+/// ### Arguments
+///
+/// * `light_direction` - The direction towards the light
+/// * `n` - The surface normal
+/// * `direction` - The direction of the incoming (view) ray
+/// * `specular_exponent` - The specular exponent of the surface material
+///
+pub fn phong_factors(
+    light_direction: Vector3<f64>,
+    n: Vector3<f64>,
+    direction: Vector3<f64>,
+    specular_exponent: f64,
+) -> (f64, f64) {
+    let diffuse = nalgebra::clamp(light_direction.dot(&n), 0.0, 1.0);
+    let minus_ref = reflect(light_direction, n).dot(&direction);
+    let power = nalgebra::clamp(minus_ref, 0.0, 1.0);
+    (diffuse, power.powf(specular_exponent))
+}
+
+/// Check whether a sphere blocks the ray between its `t_min` and `t_max`
+pub fn is_occluded(ray: &Ray, scene: &Scene) -> bool {
+    // any hit before the end of the segment will do, there is no need for the nearest one
+    scene.bvh().any_hit(ray, &scene.spheres)
+        || scene
+            .procedurals
+            .iter()
+            .any(|procedural| procedural.any_hit(ray))
+}
+
+/// Check whether a sphere blocks each of the rays, the rays being traced together (see
+/// `Bvh::occluded`)
+pub fn are_occluded(rays: &[Ray], scene: &Scene) -> Vec<bool> {
+    let mut occluded = scene.bvh().occluded(rays, &scene.spheres);
+    if !scene.procedurals.is_empty() {
+        for (ray, blocked) in rays.iter().zip(occluded.iter_mut()) {
+            *blocked = *blocked
+                || scene
+                    .procedurals
+                    .iter()
+                    .any(|procedural| procedural.any_hit(ray));
+        }
+    }
+    occluded
+}
+
+/// Compute the color of the ray at the point of intersection
+///
+/// ### Arguments
+///
+/// * `ray` - The ray (with a normalized direction)
+/// * `scene` - The scene
+/// * `settings` - The render settings (background and maximum number of bounces)
+/// * `depth` - The number of bounces before this ray (0 for camera rays)
+///
+/// ### Returns
+///
+/// Color - The color of the ray at the point of intersection
+///
+/// ### Notes
+///
+/// The ray is defined by the parametric equation:
+///
+/// `P(t) = origin + t * direction`
+///
+pub fn cast_ray(ray: &Ray, scene: &Scene, settings: &RenderSettings, depth: u32) -> Color {
+    let media = MediumStack::at(ray.origin, &scene.spheres);
+    cast_ray_in_media(ray, &media, scene, settings, depth)
+}
+
+/// Compute the color of a ray traveling through the given transparent objects
+/// (see `cast_ray`)
+///
+/// ### Arguments
+///
+/// * `ray` - The ray (with a normalized direction)
+/// * `media` - The transparent objects the ray origin is inside of
+/// * `scene` - The scene
+/// * `settings` - The render settings (background and maximum number of bounces)
+/// * `depth` - The number of bounces before this ray (0 for camera rays)
+///
+/// ### Returns
+///
+/// Color - The color of the ray at the point of intersection
+///
+pub fn cast_ray_in_media(
+    ray: &Ray,
+    media: &MediumStack,
+    scene: &Scene,
+    settings: &RenderSettings,
+    depth: u32,
+) -> Color {
+    cast_ray_components(ray, media, scene, settings, depth).sum()
+}
+
+/// Compute the color of a ray traveling through the given transparent objects, split into
+/// its components (see `cast_ray_in_media` and `shade_components`)
+pub fn cast_ray_components(
+    ray: &Ray,
+    media: &MediumStack,
+    scene: &Scene,
+    settings: &RenderSettings,
+    depth: u32,
+) -> ShadingComponents {
+    // check if the ray intersects any object
+    // if it does, compute the intersection point, the normal and the color
+    // if it doesn't, or if the maximum recursion depth has been reached (to avoid infinite recursion
+    // when the ray hits the mirror surface), return the background color
+    let hit = nearest_sphere(ray, scene).filter(|(_, distance)| *distance < 1000.0);
+    let components = match hit.filter(|_| depth <= settings.max_depth) {
+        Some(hit) => shade_components(ray, media, hit, scene, settings, depth),
+        None => ShadingComponents::background(settings.background_radiance(ray)),
+    };
+    // the medium the ray travels in only absorbs the light here, the path tracer scatters it
+    let medium = media.medium(scene, settings.outside_medium());
+    if medium.is_vacuum() {
+        return components;
+    }
+    let segment = ray.with_t_max(hit.map_or(f64::INFINITY, |(_, distance)| distance));
+    let transmittance = medium.transmittance(&segment);
+    components.map(|color| color * transmittance)
+}
+
+/// Compute the color of a ray at the surface it hits (see `cast_ray_in_media`)
+///
+/// ### Arguments
+///
+/// * `ray` - The ray (with a normalized direction)
+/// * `media` - The transparent objects the ray origin is inside of
+/// * `hit` - The index of the sphere hit by the ray and the distance to it
+/// * `scene` - The scene
+/// * `settings` - The render settings (background and maximum number of bounces)
+/// * `depth` - The number of bounces before this ray (0 for camera rays)
+///
+/// ### Returns
+///
+/// Color - The color of the ray at the point of intersection
+///
+pub fn shade_hit(
+    ray: &Ray,
+    media: &MediumStack,
+    hit: (usize, f64),
+    scene: &Scene,
+    settings: &RenderSettings,
+    depth: u32,
+) -> Color {
+    shade_components(ray, media, hit, scene, settings, depth).sum()
+}
+
+/// Color of a ray split by the way the light reached it from the surface it hits
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShadingComponents {
+    /// Direct light diffused by the surface
+    pub diffuse: Color,
+    /// Highlights of the lights on the surface
+    pub specular: Color,
+    /// Light mirrored by the surface
+    pub reflection: Color,
+    /// Light going through the surface of a transparent object
+    pub transmission: Color,
+    /// Light coming from the background (or environment map) without hitting a surface
+    pub emission: Color,
+}
+
+impl ShadingComponents {
+    /// Components of a ray leaving the scene
+    pub fn background(color: Color) -> ShadingComponents {
+        ShadingComponents {
+            emission: color,
+            ..Default::default()
+        }
+    }
+
+    /// Color of the ray, the sum of the components
+    pub fn sum(&self) -> Color {
+        self.diffuse + self.specular + self.reflection + self.transmission + self.emission
+    }
+
+    /// Components with the same function applied to each of them
+    pub fn map(&self, f: impl Fn(Color) -> Color) -> ShadingComponents {
+        ShadingComponents {
+            diffuse: f(self.diffuse),
+            specular: f(self.specular),
+            reflection: f(self.reflection),
+            transmission: f(self.transmission),
+            emission: f(self.emission),
+        }
+    }
+}
+
+impl AddAssign for ShadingComponents {
+    fn add_assign(&mut self, other: ShadingComponents) {
+        self.diffuse += other.diffuse;
+        self.specular += other.specular;
+        self.reflection += other.reflection;
+        self.transmission += other.transmission;
+        self.emission += other.emission;
+    }
+}
+
+/// Compute the color of a ray split into its components (see `shade_hit`)
+///
+/// Only the surface the ray hits is split: the light reflected or transmitted by it goes
+/// entirely to the reflection or transmission component, whatever it was made of
+///
+/// ### Arguments
+///
+/// * `ray` - The ray (with a normalized direction)
+/// * `media` - The transparent objects the ray origin is inside of
+/// * `hit` - The index of the sphere hit by the ray and the distance to it
+/// * `scene` - The scene
+/// * `settings` - The render settings (background and maximum number of bounces)
+/// * `depth` - The number of bounces before this ray (0 for camera rays)
+///
+/// ### Returns
+///
+/// `ShadingComponents` - The components of the color of the ray
+///
+pub fn shade_components(
+    ray: &Ray,
+    media: &MediumStack,
+    (index, distance): (usize, f64),
+    scene: &Scene,
+    settings: &RenderSettings,
+    depth: u32,
+) -> ShadingComponents {
+    // holdouts catch no light, the hidden part of the frame is cut out of the alpha
+    let sphere = scene.sphere(index);
+    if sphere.holdout {
+        return ShadingComponents::default();
+    }
+    let point = ray.at(distance);
+    let n = (point - sphere.center).normalize();
+    let material = &sphere.material;
+
+    // the surface of a transparent object overlapped by a medium of higher priority is only
+    // a boundary in the media the ray is in, the ray carries on through it
+    if material.refraction > 0.0 && media.is_false_hit(index, scene) {
+        let through = Ray::offset(point, n, ray.direction).with_time(ray.time);
+        let crossed = media.cross(index);
+        return cast_ray_components(&through, &crossed, scene, settings, depth);
+    }
+
+    // compute the reflection direction (not need to normalize because all vectors are already
+    // normalized) and the color of the reflected ray (recursive call, cuz the reflected ray can
+    // also reflect on other surfaces), the reflected ray stays in the same medium
+    let reflect_ray = Ray::offset(point, n, reflect(ray.direction, n)).with_time(ray.time);
+    let reflect_color = cast_ray_in_media(&reflect_ray, media, scene, settings, depth + 1);
+
+    // the refracted ray leaves the sphere if the ray was inside it and enters it otherwise,
+    // the indices of refraction on both sides come from the media the ray is in
+    let mut refract_color = Color::BLACK;
+    if material.refraction > 0.0 {
+        let inside = media.contains(index);
+        let crossed = media.cross(index);
+        let eta = media.refractive_index(scene) / crossed.refractive_index(scene);
+        let facing = if inside { -n } else { n };
+        refract_color = match refract(ray.direction, facing, eta) {
+            Some(direction) => {
+                let refract_ray = Ray::offset(point, n, direction).with_time(ray.time);
+                cast_ray_in_media(&refract_ray, &crossed, scene, settings, depth + 1)
+            }
+            // total internal reflection
+            None => reflect_color,
+        };
+    }
+
+    let (diffuse_light_intensity, specular_light_intensity) =
+        direct_lighting(point, n, ray.direction, material.specular_exponent, scene);
+    let mut components = ShadingComponents {
+        diffuse: surface_color(sphere, point, settings)
+            * diffuse_light_intensity
+            * material.albedo[0],
+        specular: specular_light_intensity * material.albedo[1],
+        reflection: reflect_color * material.albedo[2],
+        ..Default::default()
+    };
+    if material.refraction > 0.0 {
+        components.transmission = refract_color * material.refraction;
+    }
+    components
+}
+
+/// Diffuse color of a sphere at a point of its surface, its material color multiplied by
+/// the material image texture if it has one
+///
+/// The UVs of the sphere go through the UV transform of the material for both textures, then
+/// are scaled by the texture scale of the material for the image texture, which repeats
+/// over the scaled UV space, or its UDIM tiles cover it. Textures that fail to load are
+/// drawn magenta
+pub fn surface_color(sphere: &Sphere, point: Vector3<f64>, settings: &RenderSettings) -> Color {
+    let material = &sphere.material;
+    let (u, v) = sphere.texture_uv(point);
+    let diffuse_color = material.diffuse_color.eval((u, v), point);
+    let Some(path) = &material.texture else {
+        return diffuse_color;
+    };
+    let [scale_u, scale_v] = material.texture_scale;
+    let (path, u, v) = udim_tile(path, u * scale_u, v * scale_v);
+    let format = settings.texture_format(material.texture_space);
+    match texture(&path, settings.texture_budget, format) {
+        Some(texture) => diffuse_color * texture.sample(u, v),
+        None => MISSING_TEXTURE_COLOR,
+    }
+}
+
+/// Whether a radiance value is usable: no component is NaN, infinite or negative
+pub fn is_valid_radiance(color: Color) -> bool {
+    color.to_array().iter().all(|c| c.is_finite() && *c >= 0.0)
+}
+
+/// Replace invalid radiance (see `is_valid_radiance`) by black
+///
+/// Debug builds panic instead, so the shading bug producing the value is caught close to
+/// where it happens
+pub fn sanitize_radiance(color: Color) -> Color {
+    debug_assert!(is_valid_radiance(color), "invalid radiance {:?}", color);
+    if is_valid_radiance(color) {
+        color
+    } else {
+        Color::BLACK
+    }
+}
+
+/// Radiance of a pixel, averaged over the camera rays of the pixel weighted by the pixel
+/// filter (see `sample_pixel`)
+pub fn render_pixel(i: u32, j: u32, scene: &Scene, settings: &RenderSettings) -> Color {
+    let mut pixel = FilmPixel::default();
+    sample_pixel(i, j, scene, settings, &mut pixel);
+    pixel.value()
+}
+
+/// Add the radiance of the camera rays of a pixel (see `camera_samples`) to its samples on
+/// the film
+pub fn sample_pixel(
+    i: u32,
+    j: u32,
+    scene: &Scene,
+    settings: &RenderSettings,
+    pixel: &mut FilmPixel,
+) {
+    let integrator = settings.integrator.integrator();
+    let mut sampler = pixel_sampler(i, j, settings);
+    for k in 0..settings.samples_per_pixel.max(1) {
+        sampler.start_pixel(k);
+        let (ray, weight) = lens_sample(i, j, settings, &mut *sampler);
+        // a ray blocked inside the lens brings no light (vignetting)
+        let color = match ray {
+            Some(ray) => integrator.li(&ray, scene, settings, &mut *sampler, 0),
+            None => Color::BLACK,
+        };
+        pixel.add(color, weight);
+    }
+}
+
+/// Sampler of the samples of a pixel, of the kind chosen in the settings
+pub fn pixel_sampler(i: u32, j: u32, settings: &RenderSettings) -> Box<dyn Sampler> {
+    settings
+        .sampler
+        .for_pixel(i, j, settings.samples_per_pixel.max(1), settings.seed)
+}
+
+/// Radiance of every pixel of the image row by row from the top left corner (see
+/// `render_pixel`), for post-processing before the color pipeline
+pub fn render_radiance(settings: &RenderSettings, scene: &Scene) -> Vec<Color> {
+    let mut film = Film::full(settings.width, settings.height);
+    for j in 0..settings.height {
+        for i in 0..settings.width {
+            sample_pixel(i, j, scene, settings, film.pixel_mut(i, j));
+        }
+    }
+    film.radiance()
+}
+
+/// Camera rays of a pixel, `samples_per_pixel` of them, without their filter weights (see
+/// `camera_samples`)
+pub fn camera_rays(i: u32, j: u32, settings: &RenderSettings) -> Vec<Ray> {
+    camera_samples(i, j, settings)
+        .into_iter()
+        .map(|(ray, _)| ray)
+        .collect()
+}
+
+/// Camera rays of a pixel and their weights, `samples_per_pixel` of them (see
+/// `camera_sample`)
+pub fn camera_samples(i: u32, j: u32, settings: &RenderSettings) -> Vec<(Ray, f64)> {
+    let mut sampler = pixel_sampler(i, j, settings);
+    (0..settings.samples_per_pixel.max(1))
+        .map(|k| {
+            sampler.start_pixel(k);
+            camera_sample(i, j, settings, &mut *sampler)
+        })
+        .collect()
+}
+
+/// Camera ray of the current sample of a pixel and its weight
+///
+/// A single ray goes through the pixel center; more rays are spread over the pixel filter by
+/// the first two dimensions of the sampler, seeded by the pixel position and the render
+/// seed so renders are reproducible. A non-zero seed jitters a single ray too, so renders
+/// with different seeds can be averaged. The rays are as dense as the filter weight is
+/// large, so their weights are only its sign, -1 under the negative lobes of the Mitchell
+/// filter. A ray blocked inside a realistic lens falls back to the ray through the center
+/// of the pixel (see `lens_sample`)
+pub fn camera_sample(
+    i: u32,
+    j: u32,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+) -> (Ray, f64) {
+    let (ray, weight) = lens_sample(i, j, settings, sampler);
+    let ray = ray.unwrap_or_else(|| settings.camera_ray(i as f64 + 0.5, j as f64 + 0.5));
+    (ray, weight)
+}
+
+/// Camera ray of the current sample of a pixel and its weight like `camera_sample`, `None`
+/// when the ray is blocked inside the lens
+pub fn lens_sample(
+    i: u32,
+    j: u32,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+) -> (Option<Ray>, f64) {
+    if settings.samples_per_pixel <= 1 && settings.seed == 0 {
+        let (x, y) = (i as f64 + 0.5, j as f64 + 0.5);
+        return (settings.camera_lens_ray(x, y, (0.5, 0.5)), 1.0);
+    }
+    let (u, v) = sampler.get_2d();
+    let (x, x_sign) = settings.filter.sample(u);
+    let (y, y_sign) = settings.filter.sample(v);
+    // lenses with an aperture take the next two dimensions for the point of the aperture
+    let aperture = if settings.camera.lens.lens().has_aperture() {
+        sampler.get_2d()
+    } else {
+        (0.5, 0.5)
+    };
+    let ray = settings.camera_lens_ray(i as f64 + x, j as f64 + y, aperture);
+    (ray, x_sign * y_sign)
+}
+
+/// Surfaces hit by the camera rays of a render, kept to shade the image again without
+/// tracing the camera rays when only materials or lights changed
+///
+/// The hits stay valid as long as the camera, the image size, the field of view, the
+/// samples per pixel, the pixel filter and the geometry (positions and sizes of the
+/// spheres) are the same
+pub struct PrimaryHits {
+    settings: RenderSettings,
+    /// Centers and radii of the spheres the rays were traced against
+    geometry: Vec<(Vector3<f64>, f64)>,
+    /// Nearest hit of every camera ray, pixel by pixel from the top left corner
+    hits: Vec<Option<(usize, f64)>>,
+}
+
+impl PrimaryHits {
+    /// Trace the camera rays of every pixel
+    pub fn trace(settings: &RenderSettings, scene: &Scene) -> PrimaryHits {
+        let mut hits = Vec::new();
+        for j in 0..settings.height {
+            for i in 0..settings.width {
+                for ray in camera_rays(i, j, settings) {
+                    // the same cutoff as `cast_ray_in_media`
+                    let hit =
+                        nearest_sphere(&ray, scene).filter(|(_, distance)| *distance < 1000.0);
+                    hits.push(hit);
+                }
+            }
+        }
+        PrimaryHits {
+            settings: settings.clone(),
+            geometry: geometry(&scene.spheres),
+            hits,
+        }
+    }
+
+    /// Whether the hits are those of the camera rays of a render with these settings
+    /// and scene
+    pub fn is_valid_for(&self, settings: &RenderSettings, scene: &Scene) -> bool {
+        let traced = &self.settings;
+        traced.width == settings.width
+            && traced.height == settings.height
+            && traced.fov == settings.fov
+            && traced.samples_per_pixel == settings.samples_per_pixel
+            && traced.filter == settings.filter
+            && traced.camera == settings.camera
+            && self.geometry == geometry(&scene.spheres)
+    }
+
+    /// Width and height of the image the hits were traced for
+    pub fn size(&self) -> (u32, u32) {
+        (self.settings.width, self.settings.height)
+    }
+
+    /// First camera ray of a pixel (through its center with one sample per pixel) and the
+    /// surface it hits
+    pub fn first_hit(&self, i: u32, j: u32) -> (Ray, Option<(usize, f64)>) {
+        let rays = camera_rays(i, j, &self.settings);
+        let first = (j as usize * self.settings.width as usize + i as usize) * rays.len();
+        (rays[0], self.hits[first])
+    }
+
+    /// Radiance of a pixel, shading the cached hits of its camera rays like `render_pixel`
+    pub fn shade_pixel(&self, i: u32, j: u32, scene: &Scene, settings: &RenderSettings) -> Color {
+        let mut pixel = FilmPixel::default();
+        self.shade_samples(i, j, scene, settings, &mut pixel);
+        pixel.value()
+    }
+
+    /// Add the radiance of the cached hits of the camera rays of a pixel to its samples on
+    /// the film, like `sample_pixel`
+    pub fn shade_samples(
+        &self,
+        i: u32,
+        j: u32,
+        scene: &Scene,
+        settings: &RenderSettings,
+        pixel: &mut FilmPixel,
+    ) {
+        let samples = camera_samples(i, j, settings);
+        let first = (j as usize * settings.width as usize + i as usize) * samples.len();
+        for ((ray, weight), hit) in samples.iter().zip(&self.hits[first..first + samples.len()]) {
+            let color = match hit {
+                Some(hit) => {
+                    let media = MediumStack::at(ray.origin, &scene.spheres);
+                    shade_hit(ray, &media, *hit, scene, settings, 0)
+                }
+                None => settings.background_radiance(ray),
+            };
+            pixel.add(color, *weight);
+        }
+    }
+}
+
+fn geometry(spheres: &[Sphere]) -> Vec<(Vector3<f64>, f64)> {
+    spheres
+        .iter()
+        .map(|sphere| (sphere.center, sphere.radius))
+        .collect()
+}
+
+/// Render a scene, converting radiance to 8-bit RGB with the
+/// given color pipeline
+///
+/// The render blocks the calling thread, async applications take the tiles of the image as
+/// they are done from `renderer::Renderer::render_stream` instead
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings
+/// * `scene` - The scene
+/// * `pipeline` - The color pipeline used to encode the pixels
+///
+/// ### Returns
+///
+/// `Vec<u8>` - The RGB framebuffer, row by row from the top left corner
+///
+pub fn render(settings: &RenderSettings, scene: &Scene, pipeline: &ColorPipeline) -> Vec<u8> {
+    let mut film = Film::full(settings.width, settings.height);
+    autosave::begin_image(settings.width, settings.height);
+    render_rows(settings, pipeline, &mut film, None, &|i, j, pixel| {
+        sample_pixel(i, j, scene, settings, pixel)
+    });
+    autosave::end_image();
+    let mut image = film.develop(pipeline);
+    draw_outlines(&mut image, scene, settings);
+    image.data
+}
+
+/// Draw the outlines of the toon integrator over a developed image, if it is the integrator
+/// of the render and draws them (see `Toon::draw_outlines`)
+pub fn draw_outlines(image: &mut Image, scene: &Scene, settings: &RenderSettings) {
+    if let IntegratorKind::Toon(toon) = settings.integrator {
+        if toon.outline {
+            let center_settings = settings.clone().with_samples_per_pixel(1);
+            let gbuffer = GBuffer::from_hits(&PrimaryHits::trace(&center_settings, scene), scene);
+            toon.draw_outlines(image, &gbuffer, settings.camera.position + scene.origin);
+        }
+    }
+}
+
+/// Render a scene like `render`, handing every row to the sink as soon as it is rendered
+/// and the whole image once it is complete
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings
+/// * `scene` - The scene
+/// * `pipeline` - The color pipeline used to encode the pixels
+/// * `sink` - The receiver of the rows and of the image
+///
+/// ### Returns
+///
+/// `Image` - The rendered image, the one given to `RenderSink::on_complete`
+///
+pub fn render_to_sink(
+    settings: &RenderSettings,
+    scene: &Scene,
+    pipeline: &ColorPipeline,
+    sink: &dyn RenderSink,
+) -> Image {
+    let mut film = Film::full(settings.width, settings.height);
+    autosave::begin_image(settings.width, settings.height);
+    render_rows(settings, pipeline, &mut film, Some(sink), &|i, j, pixel| {
+        sample_pixel(i, j, scene, settings, pixel)
+    });
+    autosave::end_image();
+    let mut image = film.develop(pipeline);
+    draw_outlines(&mut image, scene, settings);
+    sink.on_complete(&image);
+    image
+}
+
+/// Size in pixels of the square blocks of the first image of a coarse-to-fine progressive
+/// render, an eighth of the resolution (see `RenderControl::with_coarse_to_fine`)
+pub const COARSE_BLOCK_SIZE: u32 = 8;
+
+/// Passes over the region in focus of a progressive render for every pass over the whole
+/// image (see `RenderControl::set_focus`)
+pub const FOCUS_PASSES: u32 = 3;
+
+/// Render a scene progressively: pass after pass of `settings.samples_per_pixel` samples
+/// per pixel accumulated on the same film, the image being developed with the display
+/// settings of the control handle and handed to the sink after every pass
+///
+/// The render can be paused, resumed or stopped between passes through the handle, and its
+/// exposure, denoise and clamp changed at any time, even while paused, without losing the
+/// samples taken so far. While its camera is moved through the handle, the sink gets a
+/// flat preview of every new camera (see `integrator::Playblast`), and the passes start
+/// over once the camera stops. In the coarse-to-fine mode of the handle, the sink first
+/// gets images of blocks of pixels getting smaller, the first one an eighth of the
+/// resolution. A region in focus (around the cursor) gets more passes, so it converges
+/// first
+///
+/// After every pass, the handle gets a snapshot of the samples with their error estimated
+/// from the spread of the passes (see `convergence::ErrorEstimate`), and the render ends
+/// early once the error of the image is down to the target error of the handle
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings, every pass using another seed
+/// * `scene` - The scene
+/// * `pipeline` - The color pipeline used to encode the pixels
+/// * `passes` - The number of passes after which the render is done
+/// * `control` - The handle controlling the render
+/// * `sink` - The receiver of the image after every pass (as a single tile) and at the end
+///
+/// ### Returns
+///
+/// `Image` - The image of all the passes rendered, the one given to
+/// `RenderSink::on_complete`
+///
+pub fn render_progressive(
+    settings: &RenderSettings,
+    scene: &Scene,
+    pipeline: &ColorPipeline,
+    passes: u32,
+    control: &RenderControl,
+    sink: &dyn RenderSink,
+) -> Image {
+    let rect = Rect::new(0, 0, settings.width, settings.height);
+    let mut settings = settings.clone();
+    let mut film = Accumulator::new(rect, settings.half_precision);
+    let mut estimate = ErrorEstimate::new(rect);
+    // the AOVs guiding the denoise, only rendered once it is turned on
+    let mut features = None;
+    // time every row took in the last pass, the costliest rows of a pass are started first
+    let mut costs = vec![0.0; settings.height as usize];
+    let mut developed = None;
+    let mut pass = 0;
+    // whether the coarse images were shown since the render started from its camera
+    let mut refined = false;
+    while pass < passes {
+        let version = control.wait(developed.unwrap_or(u64::MAX));
+        if control.is_stopped() {
+            break;
+        }
+        // the samples and the AOVs of another camera are of no use
+        if let Some(camera) = control.camera().filter(|camera| *camera != settings.camera) {
+            settings.camera = camera;
+            film = Accumulator::new(rect, settings.half_precision);
+            estimate = ErrorEstimate::new(rect);
+            control.set_snapshot(None);
+            features = None;
+            pass = 0;
+            refined = false;
+        }
+        if control.is_navigating() {
+            let image = render_playblast(&settings, scene, pipeline, control);
+            sink.on_tile(rect, &image.data);
+            developed = Some(version);
+            continue;
+        }
+        if !refined && !control.is_paused() && control.is_coarse_to_fine() {
+            render_coarse_to_fine(&settings, scene, pipeline, control, sink);
+            refined = true;
+        }
+        if !control.is_paused() {
+            let pass_settings = settings
+                .clone()
+                .with_seed(settings.seed.wrapping_add(pass as u64));
+            let mut pass_film = Film::full(settings.width, settings.height);
+            autosave::begin_image(settings.width, settings.height);
+            render_rows_by_cost(
+                &pass_settings,
+                pipeline,
+                &mut pass_film,
+                &mut costs,
+                &|i, j, pixel| sample_pixel(i, j, scene, &pass_settings, pixel),
+            );
+            autosave::end_image();
+            film.merge(&pass_film);
+            estimate.add_pass(&pass_film);
+            // the region in focus is read again every pass, so it follows the cursor
+            let focus = control.focus();
+            if let Some(rect) = focus.and_then(|focus| focus.rect(settings.width, settings.height))
+            {
+                for k in 0..FOCUS_PASSES {
+                    // seeds after those of the passes over the whole image
+                    let seed = passes as u64 + (pass * FOCUS_PASSES + k) as u64;
+                    let focus_settings =
+                        settings.clone().with_seed(settings.seed.wrapping_add(seed));
+                    let mut focus_film = Film::new(rect);
+                    render_rows(
+                        &focus_settings,
+                        pipeline,
+                        &mut focus_film,
+                        None,
+                        &|i, j, pixel| sample_pixel(i, j, scene, &focus_settings, pixel),
+                    );
+                    film.merge(&focus_film);
+                    estimate.add_pass(&focus_film);
+                }
+            }
+            pass += 1;
+            let frame_error = estimate.frame_error();
+            control.set_snapshot(Some(AccumulationSnapshot {
+                rect,
+                passes: pass,
+                radiance: film.radiance(),
+                samples: film.samples(),
+                errors: estimate.errors(),
+                frame_error,
+            }));
+            if control
+                .target_error()
+                .is_some_and(|target_error| frame_error <= target_error)
+            {
+                break;
+            }
+        }
+        let image = develop_progressive(&film, &settings, scene, pipeline, control, &mut features);
+        sink.on_tile(rect, &image.data);
+        developed = Some(version);
+    }
+    let image = develop_progressive(&film, &settings, scene, pipeline, control, &mut features);
+    sink.on_complete(&image);
+    image
+}
+
+/// Flat preview of a progressive render while its camera moves, one camera ray per pixel,
+/// with the exposure of the display settings
+fn render_playblast(
+    settings: &RenderSettings,
+    scene: &Scene,
+    pipeline: &ColorPipeline,
+    control: &RenderControl,
+) -> Image {
+    let preview_settings = settings
+        .clone()
+        .with_integrator(IntegratorKind::Playblast(Playblast))
+        .with_samples_per_pixel(1);
+    let mut film = Film::full(settings.width, settings.height);
+    autosave::begin_image(settings.width, settings.height);
+    render_rows(
+        &preview_settings,
+        pipeline,
+        &mut film,
+        None,
+        &|i, j, pixel| sample_pixel(i, j, scene, &preview_settings, pixel),
+    );
+    autosave::end_image();
+    develop_preview(&film, pipeline, control)
+}
+
+/// Coarse images of a progressive render, each one handed to the sink: a camera ray
+/// through one pixel in every block of `COARSE_BLOCK_SIZE` pixels squared fills its block,
+/// then the blocks are split in four, the pixels of the larger blocks kept, until they are
+/// single pixels
+fn render_coarse_to_fine(
+    settings: &RenderSettings,
+    scene: &Scene,
+    pipeline: &ColorPipeline,
+    control: &RenderControl,
+    sink: &dyn RenderSink,
+) {
+    let (width, height) = (settings.width, settings.height);
+    let coarse_settings = settings.clone().with_samples_per_pixel(1);
+    let mut traced = vec![Color::BLACK; width as usize * height as usize];
+    let mut block = COARSE_BLOCK_SIZE;
+    while block > 1 {
+        // the top left pixels of the blocks, without those of the larger blocks
+        let pixels: Vec<(u32, u32)> = (0..height)
+            .step_by(block as usize)
+            .flat_map(|j| (0..width).step_by(block as usize).map(move |i| (i, j)))
+            .filter(|(i, j)| block == COARSE_BLOCK_SIZE || i % (2 * block) + j % (2 * block) > 0)
+            .collect();
+        for ((i, j), color) in pixels
+            .iter()
+            .zip(trace_pixels(&pixels, scene, &coarse_settings))
+        {
+            traced[(j * width + i) as usize] = color;
+        }
+        let mut film = Film::full(width, height);
+        for j in 0..height {
+            for i in 0..width {
+                let corner = (j - j % block) * width + i - i % block;
+                film.add_sample(i, j, traced[corner as usize], 1.0);
+            }
+        }
+        let image = develop_preview(&film, pipeline, control);
+        sink.on_tile(Rect::new(0, 0, width, height), &image.data);
+        block /= 2;
+    }
+}
+
+/// Radiance of a list of pixels (see `render_pixel`), spread over the render threads
+fn trace_pixels(pixels: &[(u32, u32)], scene: &Scene, settings: &RenderSettings) -> Vec<Color> {
+    let mut colors = vec![Color::BLACK; pixels.len()];
+    let threads = settings.thread_count().clamp(1, pixels.len().max(1));
+    let chunk_size = pixels.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        for (chunk, colors) in pixels.chunks(chunk_size).zip(colors.chunks_mut(chunk_size)) {
+            scope.spawn(move || {
+                for ((i, j), color) in chunk.iter().zip(colors) {
+                    *color = render_pixel(*i, *j, scene, settings);
+                }
+            });
+        }
+    });
+    colors
+}
+
+/// Image of a preview of a progressive render, with the exposure, the clamp and the LUT of
+/// the display settings but without the denoise
+fn develop_preview(film: &Film, pipeline: &ColorPipeline, control: &RenderControl) -> Image {
+    let display = control.display();
+    let pipeline = pipeline
+        .clone()
+        .with_exposure(pipeline.exposure + display.exposure);
+    let mut image = Image::new(film.rect.width, film.rect.height);
+    for (color, rgb) in film.radiance().iter().zip(image.data.chunks_exact_mut(3)) {
+        rgb.copy_from_slice(&pipeline.to_rgb8(color.map(|c| c.min(display.clamp))));
+    }
+    if let Some(lut) = &display.lut {
+        lut.apply_image(&mut image);
+    }
+    image
+}
+
+/// Image of the samples of a progressive render with the current display settings
+fn develop_progressive(
+    film: &Accumulator,
+    settings: &RenderSettings,
+    scene: &Scene,
+    pipeline: &ColorPipeline,
+    control: &RenderControl,
+    features: &mut Option<FrameBuffer>,
+) -> Image {
+    let display = control.display();
+    let mut radiance: Vec<Color> = film
+        .radiance()
+        .into_iter()
+        .map(|color| color.map(|c| c.min(display.clamp)))
+        .collect();
+    if let Some(denoise) = display.denoise {
+        let mut frame = features
+            .get_or_insert_with(|| render_features(settings, scene))
+            .clone();
+        frame.color = radiance;
+        radiance = denoise.reconstruct(&frame);
+    }
+    let pipeline = pipeline
+        .clone()
+        .with_exposure(pipeline.exposure + display.exposure);
+    let rect = film.rect();
+    let mut image = Image::new(rect.width, rect.height);
+    for (color, rgb) in radiance.iter().zip(image.data.chunks_exact_mut(3)) {
+        rgb.copy_from_slice(&pipeline.to_rgb8(*color));
+    }
+    if let Some(lut) = &display.lut {
+        lut.apply_image(&mut image);
+    }
+    image
+}
+
+/// Render a scene `settings.supersampling` times larger than the image and shrink the result
+/// to the size of the image with a Lanczos filter (see `Image::resize`)
+///
+/// Brute force antialiasing: every pixel gets the square of the factor times more camera
+/// rays, the high resolution image is held in memory
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings
+/// * `scene` - The scene
+/// * `pipeline` - The color pipeline used to encode the pixels
+///
+/// ### Returns
+///
+/// `Result<Image, ImageError>` - The image, an error if the larger image is too large
+///
+pub fn render_supersampled(
+    settings: &RenderSettings,
+    scene: &Scene,
+    pipeline: &ColorPipeline,
+) -> Result<Image, ImageError> {
+    let factor = settings.supersampling.max(1);
+    let (width, height) = (
+        settings.width.saturating_mul(factor),
+        settings.height.saturating_mul(factor),
+    );
+    check_image_size(width, height)?;
+    let large_settings = RenderSettings {
+        width,
+        height,
+        ..settings.clone()
+    };
+    let data = render(&large_settings, scene, pipeline);
+    let image = Image {
+        width,
+        height,
+        data,
+    };
+    Ok(image.resize(settings.width, settings.height, ResizeFilter::Lanczos3))
+}
+
+/// Render a scene like `render`, reusing the camera ray hits of an earlier render of the
+/// same geometry from the same camera
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings
+/// * `scene` - The scene
+/// * `pipeline` - The color pipeline used to encode the pixels
+/// * `hits` - The camera ray hits, traced again if they are not valid for this render
+///
+/// ### Returns
+///
+/// `Vec<u8>` - The RGB framebuffer, row by row from the top left corner
+///
+pub fn render_with_hits(
+    settings: &RenderSettings,
+    scene: &Scene,
+    pipeline: &ColorPipeline,
+    hits: &mut PrimaryHits,
+) -> Vec<u8> {
+    // the hits are shaded the Whitted way, the other integrators render from scratch
+    if !matches!(settings.integrator, IntegratorKind::Whitted(_)) {
+        return render(settings, scene, pipeline);
+    }
+    if !hits.is_valid_for(settings, scene) {
+        *hits = PrimaryHits::trace(settings, scene);
+    }
+    let hits = &*hits;
+    let mut film = Film::full(settings.width, settings.height);
+    autosave::begin_image(settings.width, settings.height);
+    render_rows(settings, pipeline, &mut film, None, &|i, j, pixel| {
+        hits.shade_samples(i, j, scene, settings, pixel)
+    });
+    autosave::end_image();
+    film.develop(pipeline).data
+}
+
+/// Render a scene straight into a PNG file, a band of rows at a time
+///
+/// Only one band of `RenderSettings::band_height` rows is held in memory, so images larger
+/// than the memory budget (e.g. 16K panoramas) can be rendered
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings
+/// * `scene` - The scene
+/// * `pipeline` - The color pipeline used to encode the pixels
+/// * `filename` - The path of the PNG file to write
+///
+pub fn render_to_file(
+    settings: &RenderSettings,
+    scene: &Scene,
+    pipeline: &ColorPipeline,
+    filename: &str,
+) -> std::io::Result<()> {
+    let stream = ImageStream::create(filename, settings.width, settings.height)?;
+    render_to_stream(settings, scene, pipeline, settings.band_height(), stream)
+}
+
+/// Render a scene into a PNG stream, a band of rows at a time, every band being written as
+/// soon as it is rendered
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings
+/// * `scene` - The scene
+/// * `pipeline` - The color pipeline used to encode the pixels
+/// * `band_height` - The number of rows rendered before they are written
+/// * `stream` - The PNG stream of the image, e.g. on a file or a network connection
+///
+pub fn render_to_stream<W: Write + 'static>(
+    settings: &RenderSettings,
+    scene: &Scene,
+    pipeline: &ColorPipeline,
+    band_height: u32,
+    mut stream: ImageStream<W>,
+) -> std::io::Result<()> {
+    let band_height = band_height.clamp(1, settings.height.max(1));
+    // a copy of the whole image for the panic hook would break the memory budget
+    if settings.memory_budget == 0 {
+        autosave::begin_image(settings.width, settings.height);
+    }
+    for first_row in (0..settings.height).step_by(band_height as usize) {
+        let rows = band_height.min(settings.height - first_row);
+        let mut film = Film::new(Rect::new(0, first_row, settings.width, rows));
+        render_rows(settings, pipeline, &mut film, None, &|i, j, pixel| {
+            sample_pixel(i, j, scene, settings, pixel)
+        });
+        stream.write_rows(&film.develop(pipeline).data)?;
+    }
+    autosave::end_image();
+    stream.finish()
+}
+
+/// Rows of a film rendered together, with the number of every row
+type Band<'a> = Vec<(u32, &'a mut [FilmPixel])>;
+
+/// Render the rows of a film, `sample` adding the samples of a pixel from its position,
+/// the rows being developed with the color pipeline for the autosave and the sink as they
+/// are done
+///
+/// Rows are interleaved between the render threads so every thread gets a similar share
+/// of the expensive parts of the image, in bands of rows for the pixel orders walking
+/// tiles (see `PixelOrder::band_height`)
+fn render_rows(
+    settings: &RenderSettings,
+    pipeline: &ColorPipeline,
+    film: &mut Film,
+    sink: Option<&dyn RenderSink>,
+    sample: &(dyn Fn(u32, u32, &mut FilmPixel) + Sync),
+) {
+    let (first_column, width) = (film.rect.x, film.rect.width as usize);
+    let order = settings.pixel_order;
+    let bands = film_bands(film, order);
+    let threads = settings.thread_count().clamp(1, bands.len().max(1));
+    let mut shares: Vec<Vec<Band>> = (0..threads).map(|_| Vec::new()).collect();
+    for (k, band) in bands.into_iter().enumerate() {
+        shares[k % threads].push(band);
+    }
+    std::thread::scope(|scope| {
+        for share in shares {
+            scope.spawn(move || {
+                let mut developed = vec![0; width * 3];
+                for band in share {
+                    render_band(
+                        order,
+                        pipeline,
+                        first_column,
+                        band,
+                        sink,
+                        sample,
+                        &mut developed,
+                    );
+                }
+            });
+        }
+    });
+}
+
+/// Rows of a film grouped in bands of `order.band_height()` rows, from the top
+fn film_bands(film: &mut Film, order: PixelOrder) -> Vec<Band<'_>> {
+    let band_height = order.band_height().max(1) as usize;
+    let mut rows = film.rows_mut().peekable();
+    let mut bands = Vec::new();
+    while rows.peek().is_some() {
+        bands.push(rows.by_ref().take(band_height).collect());
+    }
+    bands
+}
+
+/// Render the rows of a film like `render_rows`, the rows that took the longest in the
+/// previous pass first, every thread taking the next row once it is done with its last one
+///
+/// The slowest rows don't end up last on a thread, so the threads finish together; the
+/// time of every row is recorded for the next pass. The bands of the pixel orders walking
+/// tiles are scheduled as a whole, their time being shared by their rows
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings (number of threads and pixel order)
+/// * `pipeline` - The color pipeline used for the autosave
+/// * `film` - The film the samples are added to
+/// * `costs` - The time every row of the film took in seconds, replaced by the times of
+///   this pass (rows without a time are started in order)
+/// * `sample` - The function adding the samples of a pixel from its position
+///
+fn render_rows_by_cost(
+    settings: &RenderSettings,
+    pipeline: &ColorPipeline,
+    film: &mut Film,
+    costs: &mut [f64],
+    sample: &(dyn Fn(u32, u32, &mut FilmPixel) + Sync),
+) {
+    let (first_row, first_column, width) = (film.rect.y, film.rect.x, film.rect.width as usize);
+    let order = settings.pixel_order;
+    let cost = |band: &Band| {
+        band.iter()
+            .map(|(j, _)| costs.get((j - first_row) as usize).copied().unwrap_or(0.0))
+            .sum::<f64>()
+    };
+    let mut bands = film_bands(film, order);
+    // the bands are taken from the end, the stable sort keeps bands of equal cost in order
+    bands.reverse();
+    bands.sort_by(|a, b| cost(a).total_cmp(&cost(b)));
+    let threads = settings.thread_count().clamp(1, bands.len().max(1));
+    let queue = Mutex::new(bands);
+    let times: Vec<(u32, f64)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut developed = vec![0; width * 3];
+                    let mut times = Vec::new();
+                    loop {
+                        let next = queue
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner())
+                            .pop();
+                        let Some(band) = next else {
+                            break;
+                        };
+                        let rows: Vec<u32> = band.iter().map(|(j, _)| *j).collect();
+                        let start = Instant::now();
+                        render_band(
+                            order,
+                            pipeline,
+                            first_column,
+                            band,
+                            None,
+                            sample,
+                            &mut developed,
+                        );
+                        let time = start.elapsed().as_secs_f64() / rows.len().max(1) as f64;
+                        times.extend(rows.into_iter().map(|j| (j, time)));
+                    }
+                    times
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    });
+    for (j, time) in times {
+        if let Some(cost) = costs.get_mut((j - first_row) as usize) {
+            *cost = time;
+        }
+    }
+}
+
+/// Add the samples of a band of rows of a film, tile by tile in the pixel order, and hand
+/// its rows to the autosave and the sink
+fn render_band(
+    order: PixelOrder,
+    pipeline: &ColorPipeline,
+    first_column: u32,
+    mut band: Band,
+    sink: Option<&dyn RenderSink>,
+    sample: &(dyn Fn(u32, u32, &mut FilmPixel) + Sync),
+    developed: &mut [u8],
+) {
+    let width = band.first().map_or(0, |(_, row)| row.len()) as u32;
+    // the rows are their own tiles in scanline order
+    let tile_size = match order {
+        PixelOrder::Scanline => width.max(1),
+        PixelOrder::Morton | PixelOrder::Hilbert => ORDER_TILE_SIZE,
+    };
+    for first_tile_column in (0..width).step_by(tile_size as usize) {
+        let tile_width = tile_size.min(width - first_tile_column);
+        for (x, y) in order.tile_pixels(tile_width, band.len() as u32) {
+            let (j, row) = &mut band[y as usize];
+            let i = first_tile_column + x;
+            sample(first_column + i, *j, &mut row[i as usize]);
+        }
+    }
+    for (j, row) in band {
+        finish_row(pipeline, first_column, j, row, sink, developed);
+    }
+}
+
+/// Hand a rendered row of a film to the autosave and the sink
+fn finish_row(
+    pipeline: &ColorPipeline,
+    first_column: u32,
+    j: u32,
+    row: &mut [FilmPixel],
+    sink: Option<&dyn RenderSink>,
+    developed: &mut [u8],
+) {
+    // the autosaved image is filled from the start of its rows
+    let autosaved = first_column == 0 && autosave::keeps_rows();
+    if autosaved || sink.is_some() {
+        develop_pixels(pipeline, row, developed);
+    }
+    if autosaved {
+        autosave::record_row(j, developed);
+    }
+    if let Some(sink) = sink {
+        sink.on_tile(Rect::new(first_column, j, row.len() as u32, 1), developed);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::colorspace::ColorPipeline;
+use crate::film::Film;
+use crate::image::Image;
+use crate::image::Rect;
+use crate::render::draw_outlines;
+use crate::render::sample_pixel;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use futures::SinkExt;
+use futures::Stream;
+use futures::StreamExt;
+use std::pin::Pin;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use std::thread::JoinHandle;
+
+/// Side of the square tiles of a stream by default
+pub const STREAM_TILE_SIZE: u32 = 32;
+
+/// Finished part of an image streamed by `Renderer::render_stream`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tile {
+    /// The part of the image the pixels cover
+    pub rect: Rect,
+    /// The RGB pixels of the part, row by row from its top left corner
+    pub pixels: Vec<u8>,
+}
+
+/// Renderer owning a scene and its settings, rendering them as a stream of tiles for async
+/// applications (a web server, a window) that show the image while it renders
+///
+/// ```ignore
+/// let renderer = Renderer::new(settings, scene, pipeline).with_tile_size(64);
+/// let mut tiles = renderer.render_stream();
+/// while let Some(tile) = tiles.next().await {
+///     window.blit_tile(tile.rect, &tile.pixels);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Renderer {
+    settings: RenderSettings,
+    scene: Arc<Scene>,
+    pipeline: ColorPipeline,
+    tile_size: u32,
+    buffered_tiles: usize,
+}
+
+impl Renderer {
+    pub fn new(settings: RenderSettings, scene: Scene, pipeline: ColorPipeline) -> Renderer {
+        Renderer {
+            settings,
+            scene: Arc::new(scene),
+            pipeline,
+            tile_size: STREAM_TILE_SIZE,
+            buffered_tiles: 0,
+        }
+    }
+
+    /// Set the side of the square tiles, the tiles on the right and bottom edges of the
+    /// image being cut to it
+    pub fn with_tile_size(mut self, tile_size: u32) -> Renderer {
+        self.tile_size = tile_size.max(1);
+        self
+    }
+
+    /// Set the number of finished tiles waiting to be taken from a stream before the render
+    /// threads stop to wait for them, at least one per render thread (the default)
+    pub fn with_buffered_tiles(mut self, buffered_tiles: usize) -> Renderer {
+        self.buffered_tiles = buffered_tiles;
+        self
+    }
+
+    /// Tiles of the image, row by row of tiles from the top left corner
+    pub fn tile_rects(&self) -> Vec<Rect> {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let size = self.tile_size as usize;
+        (0..height)
+            .step_by(size)
+            .flat_map(|y| {
+                (0..width).step_by(size).map(move |x| {
+                    let size = size as u32;
+                    Rect::new(x, y, size.min(width - x), size.min(height - y))
+                })
+            })
+            .collect()
+    }
+
+    /// Start rendering the image on `settings.thread_count()` threads of its own, the tiles
+    /// being handed out from the top as the threads are done with their last one
+    ///
+    /// The stream yields every tile once as soon as it is rendered, in no particular order,
+    /// and ends once all of them were yielded. Threads finishing tiles faster than they are
+    /// taken wait once `with_buffered_tiles` tiles are waiting, so a slow consumer holds back
+    /// the render instead of filling the memory. Dropping the stream stops the render after
+    /// the tiles being rendered
+    ///
+    /// The tiles are developed with the color pipeline as they are, the outlines of the toon
+    /// integrator are only drawn over the whole image (see `render_image`)
+    ///
+    /// ### Returns
+    ///
+    /// `TileStream` - The stream of the finished tiles
+    ///
+    pub fn render_stream(&self) -> TileStream {
+        let tiles = Arc::new(self.tile_rects());
+        let threads = self.settings.thread_count().clamp(1, tiles.len().max(1));
+        let buffered_tiles = match self.buffered_tiles {
+            0 => threads,
+            buffered_tiles => buffered_tiles,
+        };
+        // every sender has a slot of its own on top of the buffer
+        let (sender, receiver) = mpsc::channel(buffered_tiles.saturating_sub(threads));
+        let next = Arc::new(AtomicUsize::new(0));
+        let workers = (0..threads)
+            .map(|_| {
+                let (renderer, tiles, next) = (self.clone(), tiles.clone(), next.clone());
+                let mut sender = sender.clone();
+                std::thread::spawn(move || {
+                    while let Some(rect) = tiles.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let tile = renderer.render_tile(*rect);
+                        // the stream was dropped
+                        if block_on(sender.send(tile)).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+        TileStream {
+            receiver,
+            workers,
+            tile_count: tiles.len(),
+        }
+    }
+
+    /// Render one tile of the image
+    pub fn render_tile(&self, rect: Rect) -> Tile {
+        let mut film = Film::new(rect);
+        for (x, y) in self
+            .settings
+            .pixel_order
+            .tile_pixels(rect.width, rect.height)
+        {
+            let (i, j) = (rect.x + x, rect.y + y);
+            sample_pixel(i, j, &self.scene, &self.settings, film.pixel_mut(i, j));
+        }
+        Tile {
+            rect,
+            pixels: film.develop(&self.pipeline).data,
+        }
+    }
+
+    /// Render the whole image from a stream of its tiles (see `render_stream`), with the
+    /// outlines of the toon integrator, the same image as `render::render`
+    pub async fn render_image(&self) -> Image {
+        let mut image = Image::new(self.settings.width, self.settings.height);
+        let mut tiles = self.render_stream();
+        while let Some(tile) = tiles.next().await {
+            image.blit_tile(tile.rect, &tile.pixels);
+        }
+        draw_outlines(&mut image, &self.scene, &self.settings);
+        image
+    }
+}
+
+/// Tiles of a render in progress (see `Renderer::render_stream`)
+///
+/// A panic of a render thread is raised again by the stream once the other tiles are in
+pub struct TileStream {
+    receiver: mpsc::Receiver<Tile>,
+    workers: Vec<JoinHandle<()>>,
+    tile_count: usize,
+}
+
+impl TileStream {
+    /// Number of tiles of the image, yielded or not
+    pub fn tile_count(&self) -> usize {
+        self.tile_count
+    }
+}
+
+impl Stream for TileStream {
+    type Item = Tile;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Tile>> {
+        let next = self.receiver.poll_next_unpin(cx);
+        if let Poll::Ready(None) = next {
+            // all the senders are gone, the threads are done
+            for worker in self.workers.drain(..) {
+                if let Err(panic) = worker.join() {
+                    std::panic::resume_unwind(panic);
+                }
+            }
+        }
+        next
+    }
+}
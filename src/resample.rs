@@ -0,0 +1,158 @@
+use crate::colorspace::OutputEncoding;
+use crate::image::Image;
+
+use std::f64::consts::PI;
+
+/// Filter weighting the source pixels around every pixel of a resized image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResizeFilter {
+    /// Average of the source pixels covered by the pixel, blocky when enlarging
+    Box,
+    /// Tent over the neighboring pixels, linear interpolation when enlarging
+    Bilinear,
+    /// Windowed sinc over three lobes, the sharpest, with a slight ringing at hard edges
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    /// Filter from its name: `box`, `bilinear` or `lanczos`
+    pub fn from_name(name: &str) -> Option<ResizeFilter> {
+        match name {
+            "box" => Some(ResizeFilter::Box),
+            "bilinear" => Some(ResizeFilter::Bilinear),
+            "lanczos" => Some(ResizeFilter::Lanczos3),
+            _ => None,
+        }
+    }
+
+    /// Distance from the center beyond which the weight is zero, in source pixels when the
+    /// image is enlarged
+    fn support(&self) -> f64 {
+        match self {
+            ResizeFilter::Box => 0.5,
+            ResizeFilter::Bilinear => 1.0,
+            ResizeFilter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(&self, x: f64) -> f64 {
+        let x = x.abs();
+        match self {
+            ResizeFilter::Box => (x <= 0.5) as u8 as f64,
+            ResizeFilter::Bilinear => (1.0 - x).max(0.0),
+            ResizeFilter::Lanczos3 if x < 3.0 => sinc(x) * sinc(x / 3.0),
+            ResizeFilter::Lanczos3 => 0.0,
+        }
+    }
+}
+
+/// Normalized sinc, `sin(pi x) / (pi x)`
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-8 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+/// Resize an RGB image with a separable filter, rows first and then columns
+///
+/// When the image is shrunk the filter is widened to cover every source pixel, so fine
+/// details are averaged away instead of aliasing
+///
+/// ### Arguments
+///
+/// * `image` - The image to resize
+/// * `width`, `height` - The size of the result
+/// * `filter` - The filter weighting the source pixels
+/// * `gamma_correct` - Whether the pixels are taken as sRGB encoded and filtered as linear
+///   values, so averages of bright and dark pixels don't come out too dark
+///
+/// ### Returns
+///
+/// `Image` - The resized image
+///
+pub fn resize(
+    image: &Image,
+    width: u32,
+    height: u32,
+    filter: ResizeFilter,
+    gamma_correct: bool,
+) -> Image {
+    let mut resized = Image::new(width, height);
+    if image.width == 0 || image.height == 0 || width == 0 || height == 0 {
+        return resized;
+    }
+    let encoding = if gamma_correct {
+        OutputEncoding::Srgb
+    } else {
+        OutputEncoding::Linear
+    };
+    let decoded: Vec<f64> = (0..=255)
+        .map(|c| encoding.decode(c as f64 / 255.0))
+        .collect();
+    let source: Vec<f64> = image.data.iter().map(|c| decoded[*c as usize]).collect();
+
+    // rows: source height by the new width
+    let (source_width, source_height) = (image.width as usize, image.height as usize);
+    let columns = contributions(image.width, width, filter);
+    let mut rows = vec![0.0; source_height * width as usize * 3];
+    for j in 0..source_height {
+        let source_row = &source[j * source_width * 3..(j + 1) * source_width * 3];
+        let row = &mut rows[j * width as usize * 3..(j + 1) * width as usize * 3];
+        for (pixel, (first, weights)) in row.chunks_exact_mut(3).zip(&columns) {
+            for (k, weight) in weights.iter().enumerate() {
+                let offset = (first + k) * 3;
+                for (value, c) in pixel.iter_mut().zip(&source_row[offset..offset + 3]) {
+                    *value += weight * c;
+                }
+            }
+        }
+    }
+
+    // columns: the new height by the new width
+    let row_size = width as usize * 3;
+    for (j, (first, weights)) in contributions(image.height, height, filter)
+        .iter()
+        .enumerate()
+    {
+        let target = &mut resized.data[j * row_size..(j + 1) * row_size];
+        for (x, value) in target.iter_mut().enumerate() {
+            let sum: f64 = weights
+                .iter()
+                .enumerate()
+                .map(|(k, weight)| weight * rows[(first + k) * row_size + x])
+                .sum();
+            *value = (encoding.encode(sum.clamp(0.0, 1.0)) * 255.0).round() as u8;
+        }
+    }
+    resized
+}
+
+/// First source pixel and weights of the source pixels of every pixel along one axis of a
+/// resized image, the weights adding up to 1
+fn contributions(source: u32, target: u32, filter: ResizeFilter) -> Vec<(usize, Vec<f64>)> {
+    let scale = target as f64 / source as f64;
+    // the filter covers a whole pixel of the result when shrinking
+    let filter_scale = (1.0 / scale).max(1.0);
+    let support = filter.support() * filter_scale;
+    (0..target)
+        .map(|i| {
+            let center = (i as f64 + 0.5) / scale;
+            let first = ((center - support).floor().max(0.0) as usize).min(source as usize - 1);
+            let last = ((center + support).ceil() as usize).clamp(first + 1, source as usize);
+            let mut weights: Vec<f64> = (first..last)
+                .map(|k| filter.weight((k as f64 + 0.5 - center) / filter_scale))
+                .collect();
+            let total: f64 = weights.iter().sum();
+            if total.abs() > 1e-12 {
+                weights.iter_mut().for_each(|weight| *weight /= total);
+            } else {
+                // no source pixel under the filter: take the nearest one
+                let nearest = (center as usize).clamp(first, last - 1);
+                weights.iter_mut().for_each(|weight| *weight = 0.0);
+                weights[nearest - first] = 1.0;
+            }
+            (first, weights)
+        })
+        .collect()
+}
@@ -0,0 +1,262 @@
+use crate::random::permute;
+use crate::random::stratified_2d_permuted;
+use crate::random::Rng;
+
+/// Source of the sample values of the camera rays of a pixel, for everything stochastic in
+/// a render: the position of the ray in the pixel, then the numbers the integrator needs
+///
+/// Every sample of a pixel draws its values one dimension after the other, a 1D value or a
+/// 2D pair at a time, so the samplers can spread the values of a dimension evenly over the
+/// samples of the pixel
+pub trait Sampler {
+    /// Start the `sample_index`-th sample of the pixel, the dimensions start over
+    fn start_pixel(&mut self, sample_index: u32);
+
+    /// Value of the next dimension, in the range `[0, 1)`
+    fn get_1d(&mut self) -> f64;
+
+    /// Values of the next two dimensions, both in the range `[0, 1)`
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+/// Sampler of a render, chosen in the render settings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// Uniform random values, see `IndependentSampler`
+    Independent,
+    /// Jittered strata, see `StratifiedSampler`
+    #[default]
+    Stratified,
+    /// Scrambled Sobol points, see `SobolSampler`
+    Sobol,
+}
+
+impl SamplerKind {
+    /// Sampler from its name: `independent`, `stratified` or `sobol`
+    pub fn from_name(name: &str) -> Option<SamplerKind> {
+        match name {
+            "independent" => Some(SamplerKind::Independent),
+            "stratified" => Some(SamplerKind::Stratified),
+            "sobol" => Some(SamplerKind::Sobol),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+
+    /// Sampler of the samples of a pixel
+    ///
+    /// Every pixel gets its own sampler seeded by its position and the render seed, so the
+    /// values don't depend on the order or the threads the pixels are rendered in
+    ///
+    /// ### Arguments
+    ///
+    /// * `i`, `j` - The position of the pixel
+    /// * `count` - The number of samples of the pixel
+    /// * `seed` - The seed of the render
+    ///
+    /// ### Returns
+    ///
+    /// `Box<dyn Sampler>` - The sampler, before its first sample is started
+    ///
+    pub fn for_pixel(&self, i: u32, j: u32, count: u32, seed: u64) -> Box<dyn Sampler> {
+        let pixel_seed = ((j as u64) << 32 | i as u64) ^ seed.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(pixel_seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(count, pixel_seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(pixel_seed)),
+        }
+    }
+}
+
+/// Seed of a dimension (or of a sample) of a pixel, mixing the seed of the pixel with the
+/// number of the dimension
+fn stream_seed(seed: u64, stream: u64) -> u64 {
+    seed ^ stream.wrapping_mul(0xbf58_476d_1ce4_e5b9)
+}
+
+/// Independent uniform random values, the noisiest sampler, a reference for the others
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler {
+            seed,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel(&mut self, sample_index: u32) {
+        self.rng = Rng::new(stream_seed(self.seed, sample_index as u64 + 1));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.next_f64(), self.rng.next_f64())
+    }
+}
+
+/// Jittered stratified values: every dimension is split into as many strata as there are
+/// samples in the pixel (a grid or a Latin hypercube for 2D values, see
+/// `random::stratified_2d`), each sample
+/// taking a random point of its own stratum
+///
+/// The strata of every dimension but the first are visited in a random order, so the
+/// dimensions of a sample are not correlated with each other. The samples of a pixel are
+/// expected in order, from the first one
+pub struct StratifiedSampler {
+    count: u32,
+    seed: u64,
+    sample_index: u32,
+    dimension: u32,
+    /// Generator of the jitter of every dimension used so far
+    rngs: Vec<Rng>,
+}
+
+impl StratifiedSampler {
+    pub fn new(count: u32, seed: u64) -> StratifiedSampler {
+        StratifiedSampler {
+            count: count.max(1),
+            seed,
+            sample_index: 0,
+            dimension: 0,
+            rngs: Vec::new(),
+        }
+    }
+
+    /// Stratum of the current sample in the current dimension, and the jitter generator of
+    /// the dimension
+    fn next_stratum(&mut self) -> (u32, &mut Rng) {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        while self.rngs.len() <= dimension as usize {
+            let stream = self.rngs.len() as u64;
+            self.rngs.push(Rng::new(stream_seed(self.seed, stream)));
+        }
+        let mut stratum = self.sample_index % self.count;
+        if dimension > 0 {
+            let pattern = Rng::new(stream_seed(self.seed, dimension as u64)).next_u32();
+            stratum = permute(stratum, self.count, pattern);
+        }
+        (stratum, &mut self.rngs[dimension as usize])
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel(&mut self, sample_index: u32) {
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let count = self.count;
+        let (stratum, rng) = self.next_stratum();
+        (stratum as f64 + rng.next_f64()) / count as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let count = self.count;
+        // the second axis of the dimension has a permutation of its own
+        let mut patterns = Rng::new(stream_seed(self.seed, self.dimension as u64));
+        let pattern = patterns.next_u32() ^ patterns.next_u32();
+        let (stratum, rng) = self.next_stratum();
+        stratified_2d_permuted(stratum, count, pattern, rng)
+    }
+}
+
+/// Scrambled Sobol values: the first two dimensions of the Sobol sequence, with the sample
+/// indices shuffled and the points Owen scrambled by hashing (Burley, "Practical Hash-based
+/// Owen Scrambling") differently for every dimension or pair of dimensions
+///
+/// The values of a dimension are well spread over any number of samples, best with powers
+/// of two
+pub struct SobolSampler {
+    seed: u64,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Shuffled sample index and scrambling seeds of the next dimension
+    fn next_dimension(&mut self) -> (u32, u32, u32) {
+        let hash = Rng::new(stream_seed(self.seed, self.dimension as u64)).next_u32();
+        self.dimension += 1;
+        let mut rng = Rng::new(hash as u64);
+        let index = owen_scramble(self.sample_index, rng.next_u32());
+        (index, rng.next_u32(), rng.next_u32())
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel(&mut self, sample_index: u32) {
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (index, scramble, _) = self.next_dimension();
+        to_unit(owen_scramble(index.reverse_bits(), scramble))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (index, scramble_x, scramble_y) = self.next_dimension();
+        (
+            to_unit(owen_scramble(index.reverse_bits(), scramble_x)),
+            to_unit(owen_scramble(sobol_second(index), scramble_y)),
+        )
+    }
+}
+
+/// Second dimension of the Sobol sequence, as a 32-bit fraction (the first one is the bit
+/// reversal of the index)
+fn sobol_second(mut index: u32) -> u32 {
+    let mut value = 0;
+    let mut direction = 1 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            value ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    value
+}
+
+/// Nested uniform scrambling of a 32-bit fraction with a hash (Laine-Karras permutation of
+/// the reversed bits), every bit flipped depending on the bits above it
+fn owen_scramble(value: u32, seed: u32) -> u32 {
+    let mut x = value.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+/// 32-bit fraction as a number in `[0, 1)`
+fn to_unit(value: u32) -> f64 {
+    value as f64 / 4_294_967_296.0
+}
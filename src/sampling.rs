@@ -0,0 +1,181 @@
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+
+/// Orthonormal basis built around a direction `w`, used to express sampled directions
+/// given around the local Z axis in world space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Onb {
+    pub u: Vector3<f64>,
+    pub v: Vector3<f64>,
+    pub w: Vector3<f64>,
+}
+
+impl Onb {
+    /// Basis whose third axis is the (normalized) direction `w`
+    pub fn from_w(w: Vector3<f64>) -> Onb {
+        let w = w.normalize();
+        // any vector far enough from `w` gives a well conditioned cross product
+        let helper = if w.x.abs() > 0.9 {
+            Vector3::new(0.0, 1.0, 0.0)
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let u = w.cross(&helper).normalize();
+        let v = w.cross(&u);
+        Onb { u, v, w }
+    }
+
+    /// Convert local coordinates (Z along `w`) to world space
+    pub fn to_world(&self, local: Vector3<f64>) -> Vector3<f64> {
+        self.u * local.x + self.v * local.y + self.w * local.z
+    }
+}
+
+/// Map a uniform sample of the unit square to the unit disk, keeping strata adjacent
+/// (Shirley-Chiu concentric mapping)
+///
+/// ### Arguments
+///
+/// * `u`, `v` - Sample coordinates in the range `[0, 1)`
+///
+/// ### Returns
+///
+/// `(f64, f64)` - A point of the unit disk, uniformly distributed
+///
+pub fn concentric_disk(u: f64, v: f64) -> (f64, f64) {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// Cosine-weighted direction of the hemisphere around the local Z axis
+pub fn cosine_hemisphere(u: f64, v: f64) -> Vector3<f64> {
+    let (x, y) = concentric_disk(u, v);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+    Vector3::new(x, y, z)
+}
+
+/// Solid angle density of `cosine_hemisphere` for a direction at `cos_theta` from the axis
+pub fn cosine_hemisphere_pdf(cos_theta: f64) -> f64 {
+    cos_theta.max(0.0) / PI
+}
+
+/// Uniform direction of the unit sphere
+pub fn uniform_sphere(u: f64, v: f64) -> Vector3<f64> {
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Solid angle density of `uniform_sphere`
+pub fn uniform_sphere_pdf() -> f64 {
+    1.0 / (4.0 * PI)
+}
+
+/// Direction scattered by the Henyey-Greenstein phase function around the local Z axis,
+/// the direction the light was traveling in, with the density `volume::henyey_greenstein`
+pub fn henyey_greenstein_direction(u: f64, v: f64, g: f64) -> Vector3<f64> {
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u
+    } else {
+        let k = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        ((1.0 + g * g - k * k) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Uniform direction inside the cone of half angle `acos(cos_max)` around the local Z axis
+pub fn uniform_cone(u: f64, v: f64, cos_max: f64) -> Vector3<f64> {
+    let cos_theta = 1.0 - u * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Solid angle density of `uniform_cone`
+pub fn uniform_cone_pdf(cos_max: f64) -> f64 {
+    1.0 / (2.0 * PI * (1.0 - cos_max))
+}
+
+/// Sample the directions from a point towards a spherical light, uniformly over the cone
+/// the sphere subtends
+///
+/// ### Arguments
+///
+/// * `point` - The point receiving the light
+/// * `center` - The center of the light
+/// * `radius` - The radius of the light
+/// * `u`, `v` - Sample coordinates in the range `[0, 1)`
+///
+/// ### Returns
+///
+/// `(Vector3<f64>, f64)` - The direction towards the light and its solid angle density
+/// (the direction towards the center with an infinite density when the point is inside)
+///
+pub fn sample_sphere_light(
+    point: Vector3<f64>,
+    center: Vector3<f64>,
+    radius: f64,
+    u: f64,
+    v: f64,
+) -> (Vector3<f64>, f64) {
+    let to_center = center - point;
+    let distance2 = to_center.norm_squared();
+    if distance2 <= radius * radius {
+        return (to_center.normalize(), f64::INFINITY);
+    }
+    let cos_max = (1.0 - radius * radius / distance2).max(0.0).sqrt();
+    let direction = Onb::from_w(to_center).to_world(uniform_cone(u, v, cos_max));
+    (direction, uniform_cone_pdf(cos_max))
+}
+
+/// Uniform point of the triangle `a`, `b`, `c`
+///
+/// ### Arguments
+///
+/// * `a`, `b`, `c` - The vertices of the triangle
+/// * `u`, `v` - Sample coordinates in the range `[0, 1)`
+///
+/// ### Returns
+///
+/// `(Vector3<f64>, f64)` - The point and its area density (one over the triangle area)
+///
+pub fn sample_triangle(
+    a: Vector3<f64>,
+    b: Vector3<f64>,
+    c: Vector3<f64>,
+    u: f64,
+    v: f64,
+) -> (Vector3<f64>, f64) {
+    let su = u.sqrt();
+    let (b0, b1) = (1.0 - su, v * su);
+    let point = a * b0 + b * b1 + c * (1.0 - b0 - b1);
+    let area = (b - a).cross(&(c - a)).norm() / 2.0;
+    (point, 1.0 / area)
+}
+
+/// Convert an area density at `sample` (with surface normal `n`) to a solid angle density
+/// as seen from `point`
+pub fn area_to_solid_angle_pdf(
+    pdf_area: f64,
+    point: Vector3<f64>,
+    sample: Vector3<f64>,
+    n: Vector3<f64>,
+) -> f64 {
+    let to_point = point - sample;
+    let cos_theta = n.normalize().dot(&to_point.normalize()).abs();
+    if cos_theta <= 0.0 {
+        return 0.0;
+    }
+    pdf_area * to_point.norm_squared() / cos_theta
+}
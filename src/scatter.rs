@@ -0,0 +1,157 @@
+use crate::object::Sphere;
+use crate::random::Rng;
+
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Number of random positions tried per instance before the scatter gives up on it
+const ATTEMPTS_PER_INSTANCE: u32 = 30;
+
+/// Surface the instances of a scatter rest on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScatterSurface {
+    /// Rectangle of a horizontal floor, `center` on the floor and `size` its extent along
+    /// X and Z
+    Plane {
+        center: Vector3<f64>,
+        size: [f64; 2],
+    },
+    /// Cap of a sphere around its top, up to `angle` radians from the vertical, e.g. the
+    /// ground sphere of a scene
+    Sphere {
+        center: Vector3<f64>,
+        radius: f64,
+        angle: f64,
+    },
+}
+
+impl ScatterSurface {
+    /// Random point of the surface and the normal there, uniform over the area
+    fn sample(&self, rng: &mut Rng) -> (Vector3<f64>, Vector3<f64>) {
+        match *self {
+            ScatterSurface::Plane { center, size } => {
+                let x = (rng.next_f64() - 0.5) * size[0];
+                let z = (rng.next_f64() - 0.5) * size[1];
+                (
+                    center + Vector3::new(x, 0.0, z),
+                    Vector3::new(0.0, 1.0, 0.0),
+                )
+            }
+            ScatterSurface::Sphere {
+                center,
+                radius,
+                angle,
+            } => {
+                let cos_theta = 1.0 - rng.next_f64() * (1.0 - angle.min(PI).cos());
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = rng.next_f64() * 2.0 * PI;
+                let n = Vector3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+                (center + n * radius, n)
+            }
+        }
+    }
+}
+
+/// Scatter of copies of a prop over a surface, each with a random turn around the vertical
+/// axis and a random scale, no two copies closer than a minimum spacing
+///
+/// The copies are placed one after the other at random points of the surface, resting on
+/// it, a point being rejected when the copy would come closer than `spacing` to a copy
+/// already placed; a crowded surface gets fewer copies than asked for. Spheres have no
+/// orientation, the turn of a copy offsets the UVs of its own copy of the material (see
+/// `texture::UvTransform`), so it shows on textured props only
+#[derive(Clone, Debug)]
+pub struct Scatter {
+    /// Object copied, its radius is scaled and its center moved onto the surface
+    pub prop: Sphere,
+    pub count: u32,
+    pub surface: ScatterSurface,
+    /// Range of the scale of the copies
+    pub scale: [f64; 2],
+    /// Range of the turn of the copies around the vertical axis, in radians
+    pub rotation: [f64; 2],
+    /// Minimum distance between the surfaces of two copies
+    pub spacing: f64,
+    pub seed: u64,
+}
+
+impl Scatter {
+    /// Scatter of copies of the same size, turned at random all the way around, touching
+    /// each other at most
+    pub fn new(prop: Sphere, count: u32, surface: ScatterSurface) -> Scatter {
+        Scatter {
+            prop,
+            count,
+            surface,
+            scale: [1.0, 1.0],
+            rotation: [0.0, 2.0 * PI],
+            spacing: 0.0,
+            seed: 0,
+        }
+    }
+
+    pub fn with_scale(mut self, min: f64, max: f64) -> Scatter {
+        self.scale = [min, max];
+        self
+    }
+
+    pub fn with_rotation(mut self, min: f64, max: f64) -> Scatter {
+        self.rotation = [min, max];
+        self
+    }
+
+    pub fn with_spacing(mut self, spacing: f64) -> Scatter {
+        self.spacing = spacing;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Scatter {
+        self.seed = seed;
+        self
+    }
+
+    /// Copies of the prop, named after it with their index (`rock_0`, `rock_1`, ...) when
+    /// the prop has a name
+    pub fn place(&self) -> Vec<Sphere> {
+        let mut rng = Rng::new(self.seed);
+        let mut placed: Vec<Sphere> = Vec::with_capacity(self.count as usize);
+        let lerp = |[min, max]: [f64; 2], t: f64| min + (max - min) * t;
+        for _ in 0..self.count * ATTEMPTS_PER_INSTANCE {
+            if placed.len() == self.count as usize {
+                break;
+            }
+            let radius = self.prop.radius * lerp(self.scale, rng.next_f64());
+            let (point, normal) = self.surface.sample(&mut rng);
+            let turn = lerp(self.rotation, rng.next_f64());
+            let center = point + normal * radius;
+            let crowded = placed.iter().any(|other| {
+                let gap = (other.center - center).norm() - other.radius - radius;
+                gap < self.spacing
+            });
+            if crowded {
+                continue;
+            }
+            let mut copy = self.prop.clone();
+            copy.center = center;
+            copy.radius = radius;
+            copy.name = self
+                .prop
+                .name
+                .as_ref()
+                .map(|name| format!("{}_{}", name, placed.len()));
+            if turn != 0.0 {
+                // turning the sphere around its vertical axis shifts `u` by the turn
+                let transform = &copy.material.uv_transform;
+                let (u0, v0) = transform.apply((0.0, 0.0));
+                let (u1, v1) = transform.apply((turn / (2.0 * PI), 0.0));
+                let mut material = (*copy.material).clone();
+                material.uv_transform.offset[0] += u1 - u0;
+                material.uv_transform.offset[1] += v1 - v0;
+                copy.material = Arc::new(material);
+            }
+            placed.push(copy);
+        }
+        placed
+    }
+}
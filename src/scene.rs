@@ -0,0 +1,1100 @@
+use crate::animation::SceneAnimation;
+use crate::bvh::Bounds;
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::colorspace::TextureSpace;
+use crate::envmap::Environment;
+use crate::envmap::GroundProjection;
+use crate::i18n::tr_format;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::layers::LayerSpec;
+use crate::lens::LensKind;
+use crate::medium::medium_from_json;
+use crate::medium::Medium;
+use crate::medium::OffsetMedium;
+use crate::object::recenter_spheres;
+use crate::object::LightKind;
+use crate::object::Material;
+use crate::object::SceneLight;
+use crate::object::Sphere;
+use crate::outputs::OutputSpec;
+use crate::pack::is_pack;
+use crate::pack::ScenePack;
+use crate::procedural::OffsetProcedural;
+use crate::procedural::Procedural;
+use crate::procedural::ProceduralObject;
+use crate::procedural::SphereFlake;
+use crate::procedural::PROCEDURAL_STRIDE;
+use crate::scatter::Scatter;
+use crate::scatter::ScatterSurface;
+use crate::settings::json_to_color;
+use crate::settings::json_to_count;
+use crate::settings::json_to_f64;
+use crate::sky::dome_from_json;
+use crate::texture::json_to_texture_space;
+use crate::validate::scene_problems;
+
+use nalgebra::Vector3;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Objects, lights, cameras, environment and medium of a scene
+///
+/// Named objects, lights, cameras and materials can be looked up by name, so scene edits
+/// don't depend on the order the scene was built in
+///
+/// The spheres are found by the rays through a bounding volume hierarchy, which has to be
+/// rebuilt (`rebuild`) after spheres are added, removed, moved or resized
+///
+/// A scene is `Send + Sync` and rendering only reads it, so one scene can be rendered by
+/// several threads at once, e.g. from different cameras or at different sizes. Its
+/// materials, textures, media and hierarchy are behind `Arc`s, copies of a scene (to edit
+/// it for a single render) share them
+#[derive(Clone, Default)]
+pub struct Scene {
+    pub spheres: Vec<Sphere>,
+    pub lights: Vec<SceneLight>,
+    pub cameras: Vec<Camera>,
+    /// Environment map seen by the rays leaving the scene, the background color of the
+    /// render settings is used without one
+    pub environment: Option<Environment>,
+    /// Participating medium filling the space outside the objects (global fog)
+    pub medium: Option<Arc<dyn Medium>>,
+    /// Background color replacing the one of the render settings (see
+    /// `RenderSettings::with_scene_values`)
+    pub background: Option<Color>,
+    /// Maximum number of bounces replacing the one of the render settings
+    pub max_depth: Option<u32>,
+    /// Objects generated when a ray first reaches them, found after the spheres (see
+    /// `procedural::ProceduralObject`)
+    pub procedurals: Vec<ProceduralObject>,
+    /// World position of the origin of the coordinates of the scene, zero unless it was
+    /// moved next to its camera (see `recentered`)
+    pub origin: Vector3<f64>,
+    /// Images written when the scene is rendered, instead of `out.png`
+    pub outputs: Vec<OutputSpec>,
+    /// Render layers of the `layers` command, each rendering some objects into an image of
+    /// its own (see `layers::render_layers`)
+    pub layers: Vec<LayerSpec>,
+    /// Animation of the `animate` command, its frame rate and the animations of its lights
+    pub animation: Option<SceneAnimation>,
+    bvh: Arc<Bvh>,
+}
+
+// scenes are shared between render threads, a field that isn't thread safe has to be
+// caught here rather than where a scene is first sent to a thread
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Scene>();
+};
+
+impl Scene {
+    /// Scene seen from the default camera
+    pub fn new(spheres: Vec<Sphere>, lights: Vec<SceneLight>) -> Scene {
+        let bvh = Arc::new(Bvh::build(&spheres));
+        Scene {
+            spheres,
+            lights,
+            cameras: vec![Camera::default()],
+            environment: None,
+            medium: None,
+            background: None,
+            max_depth: None,
+            procedurals: Vec::new(),
+            origin: Vector3::zeros(),
+            outputs: Vec::new(),
+            layers: Vec::new(),
+            animation: None,
+            bvh,
+        }
+    }
+
+    /// Scene assembled piece by piece, checked when it is built (see `SceneBuilder`)
+    pub fn builder() -> SceneBuilder {
+        SceneBuilder::default()
+    }
+
+    /// The same scene lit by other lights, the objects keep their hierarchy
+    pub fn with_lights(mut self, lights: Vec<SceneLight>) -> Scene {
+        self.lights = lights;
+        self
+    }
+
+    pub fn with_cameras(mut self, cameras: Vec<Camera>) -> Scene {
+        self.cameras = cameras;
+        self
+    }
+
+    pub fn with_environment(mut self, environment: Environment) -> Scene {
+        self.environment = Some(environment);
+        self
+    }
+
+    pub fn with_medium(mut self, medium: Arc<dyn Medium>) -> Scene {
+        self.medium = Some(medium);
+        self
+    }
+
+    pub fn with_background(mut self, background: Color) -> Scene {
+        self.background = Some(background);
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: u32) -> Scene {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn with_procedurals(mut self, procedurals: Vec<ProceduralObject>) -> Scene {
+        self.procedurals = procedurals;
+        self
+    }
+
+    /// Sphere with the given index, as found by the rays: one of `spheres`, or one of the
+    /// spheres generated by a procedural object past `procedural::PROCEDURAL_STRIDE`
+    pub fn sphere(&self, index: usize) -> &Sphere {
+        if index < PROCEDURAL_STRIDE {
+            return &self.spheres[index];
+        }
+        let procedural = &self.procedurals[index / PROCEDURAL_STRIDE - 1];
+        &procedural.geometry().spheres[index % PROCEDURAL_STRIDE]
+    }
+
+    /// Bounding volume hierarchy of the spheres
+    pub fn bvh(&self) -> &Bvh {
+        debug_assert_eq!(
+            self.bvh.len(),
+            self.spheres.len(),
+            "the hierarchy of the scene wasn't rebuilt after a change of its spheres"
+        );
+        &self.bvh
+    }
+
+    /// Box around every object of the scene, the spheres and the bounds of the procedurals
+    pub fn bounds(&self) -> Bounds {
+        let spheres = self.spheres.iter().fold(Bounds::empty(), |bounds, sphere| {
+            bounds.union(&Bounds::of(sphere))
+        });
+        self.procedurals.iter().fold(spheres, |bounds, procedural| {
+            bounds.union(&procedural.bounds)
+        })
+    }
+
+    /// Rebuild the bounding volume hierarchy after spheres were added, removed, moved or
+    /// resized
+    pub fn rebuild(&mut self) {
+        self.bvh = Arc::new(Bvh::build(&self.spheres));
+    }
+
+    /// The scene moved so that `origin` is the origin of its coordinates, to render it in
+    /// camera-relative space
+    ///
+    /// Far from the origin of the world the positions keep fewer digits after the point,
+    /// and a large scene rendered from far away gets acne, cracks between neighbors and
+    /// jittering highlights; moved next to the camera, the positions of what it sees keep
+    /// all their precision. The objects, lights, cameras and procedurals are moved, the
+    /// textures and media are still looked up at the world positions, and `origin` keeps
+    /// where the scene was
+    ///
+    /// ### Arguments
+    ///
+    /// * `origin` - The world position becoming the origin, usually the position of the
+    ///   camera the scene is rendered from
+    ///
+    /// ### Returns
+    ///
+    /// `Scene` - The scene rendering the same images, with its hierarchy rebuilt
+    ///
+    pub fn recentered(mut self, origin: Vector3<f64>) -> Scene {
+        if origin == Vector3::zeros() {
+            return self;
+        }
+        recenter_spheres(&mut self.spheres, origin);
+        for light in &mut self.lights {
+            light.position -= origin;
+        }
+        for camera in &mut self.cameras {
+            camera.position -= origin;
+            camera.target -= origin;
+        }
+        self.medium = self.medium.map(|input| {
+            Arc::new(OffsetMedium {
+                input,
+                offset: origin,
+            }) as Arc<dyn Medium>
+        });
+        self.procedurals = self
+            .procedurals
+            .iter()
+            .map(|procedural| {
+                ProceduralObject::new(Arc::new(OffsetProcedural {
+                    input: procedural.generator.clone(),
+                    origin,
+                }))
+            })
+            .collect();
+        self.origin += origin;
+        self.rebuild();
+        self
+    }
+
+    /// Object with the given name
+    pub fn object(&self, name: &str) -> Option<&Sphere> {
+        self.spheres
+            .iter()
+            .find(|sphere| sphere.name.as_deref() == Some(name))
+    }
+
+    pub fn object_mut(&mut self, name: &str) -> Option<&mut Sphere> {
+        self.spheres
+            .iter_mut()
+            .find(|sphere| sphere.name.as_deref() == Some(name))
+    }
+
+    /// Light with the given name
+    pub fn light(&self, name: &str) -> Option<&SceneLight> {
+        self.lights
+            .iter()
+            .find(|light| light.name.as_deref() == Some(name))
+    }
+
+    pub fn light_mut(&mut self, name: &str) -> Option<&mut SceneLight> {
+        self.lights
+            .iter_mut()
+            .find(|light| light.name.as_deref() == Some(name))
+    }
+
+    /// Camera with the given name
+    pub fn camera(&self, name: &str) -> Option<&Camera> {
+        self.cameras.iter().find(|camera| camera.name == name)
+    }
+
+    pub fn camera_mut(&mut self, name: &str) -> Option<&mut Camera> {
+        self.cameras.iter_mut().find(|camera| camera.name == name)
+    }
+
+    /// Material with the given name, as used by the first object that has it
+    pub fn material(&self, name: &str) -> Option<&Material> {
+        self.spheres
+            .iter()
+            .map(|sphere| sphere.material.as_ref())
+            .find(|material| material.name.as_deref() == Some(name))
+    }
+
+    /// The material with the given name of every object using it, one per object
+    ///
+    /// Objects share their materials with the other objects and with the copies of the
+    /// scene, a material edited through this gets copied for the object first, edits of a
+    /// named material have to go through all of them to keep the objects consistent
+    pub fn materials_mut<'a>(
+        &'a mut self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a mut Material> + 'a {
+        self.spheres
+            .iter_mut()
+            .filter(move |sphere| sphere.material.name.as_deref() == Some(name))
+            .map(|sphere| Arc::make_mut(&mut sphere.material))
+    }
+
+    /// Replace the material with the given name on every object using it, returning the
+    /// number of objects changed
+    pub fn set_material(&mut self, name: &str, material: Material) -> usize {
+        let material = Arc::new(Material {
+            name: Some(name.to_string()),
+            ..material
+        });
+        let mut count = 0;
+        for sphere in &mut self.spheres {
+            if sphere.material.name.as_deref() == Some(name) {
+                sphere.material = material.clone();
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// What changed from a previous version of the scene, e.g. after reloading its file
+    ///
+    /// Lets a re-render keep what the changes don't affect: a change of camera or of a
+    /// material leaves the geometry as it was
+    pub fn diff(&self, previous: &Scene) -> SceneChanges {
+        let geometry = self.spheres.len() != previous.spheres.len()
+            || self
+                .spheres
+                .iter()
+                .zip(&previous.spheres)
+                .any(|(sphere, old)| {
+                    sphere.center != old.center
+                        || sphere.radius != old.radius
+                        || sphere.holdout != old.holdout
+                });
+        let materials = self
+            .spheres
+            .iter()
+            .zip(&previous.spheres)
+            .enumerate()
+            .filter(|(_, (sphere, old))| sphere.material != old.material)
+            .map(|(index, _)| index)
+            .collect();
+        SceneChanges {
+            geometry,
+            materials,
+            lights: self.lights != previous.lights,
+            cameras: self.cameras != previous.cameras,
+            environment: self.environment != previous.environment,
+            medium: self.medium != previous.medium,
+            background: self.background != previous.background,
+            max_depth: self.max_depth != previous.max_depth,
+        }
+    }
+
+    /// Load a scene from a JSON file
+    ///
+    /// A scene file is an object with the optional keys:
+    ///
+    /// * `include` - paths of other scene files (material libraries, reusable props),
+    ///   relative to the including file; their contents are loaded before the rest of the file
+    /// * `materials` - named materials, in the format of `Material::from_json` (texture
+    ///   paths are relative to the file)
+    /// * `objects` - spheres with `name`, `center`, `radius`, `material` (the name of a
+    ///   material loaded so far, or a material object) and `holdout`
+    /// * `scatter` - copies of a `prop` (an object as in `objects`) scattered over a
+    ///   `surface`, the rectangle of a floor (`center` and `size` along X and Z) or the cap
+    ///   of an object loaded so far around its top (`object` name and `angle` from the
+    ///   vertical in degrees, 90 by default), with their `count`, the `[min, max]` ranges
+    ///   of their `scale` and of their `rotation` in degrees, the minimum `spacing` between
+    ///   them and the `seed` of the placement (see `scatter::Scatter`)
+    /// * `lights` - lights with `name`, `position` (point light) or `direction` (sun, with
+    ///   an `angular_diameter` in degrees, 0.53 by default and 0 for hard shadows),
+    ///   `intensity`, `color`, `radius`, `shadow_samples` and `max_distance`, a light with a
+    ///   `position` and a `spot` (its `direction`, half `angle` in degrees and `softness`)
+    ///   being a spot light
+    /// * `cameras` - cameras with `name`, `position`, `target`, `up` and `lens` (see
+    ///   `lens::LensKind::from_json`)
+    /// * `environment` - an environment map with `texture` (the path of an equirectangular
+    ///   PNG, relative to the file) or an analytic `dome` (see `sky::dome_from_json`),
+    ///   `rotation` about the vertical axis in degrees, `intensity` and the color `space`
+    ///   of the file (`linear` or `srgb`), optionally projected on the ground
+    ///   (`ground_projection` with the `center` of the ground, the `height` the map was
+    ///   captured from and the `radius` of the dome); a later file replaces the environment
+    ///   of an earlier one
+    /// * `medium` - a participating medium filling the scene outside the objects, in the
+    ///   format of `medium::medium_from_json`; a later file replaces the medium of an
+    ///   earlier one
+    /// * `procedural` - objects generated when a ray first reaches them, a `sphereflake`
+    ///   `type` with its `center`, `radius`, `depth` (up to 6 levels) and `material` (as
+    ///   for `objects`), see `procedural::SphereFlake`
+    /// * `background` - the color of the rays leaving the scene and `max_depth` - the
+    ///   maximum number of bounces, replacing those of the render settings; a later file
+    ///   replaces the values of an earlier one
+    /// * `camera_relative` - whether the scene is moved next to its first camera once
+    ///   loaded, for scenes far from the origin of the world (see `Scene::recentered`)
+    /// * `outputs` - the images written when the scene is rendered instead of `out.png`,
+    ///   each with its `file`, `layer`, `format` and color `space` (see
+    ///   `outputs::OutputSpec::from_json`)
+    /// * `layers` - the render layers of the `layers` command, each with its `name` and its
+    ///   `objects` by name or index (see `layers::LayerSpec::from_json`); a later layer
+    ///   replaces an earlier one with the same name
+    /// * `animation` - the animation of the `animate` command, its `fps` and the `lights`
+    ///   it animates with their intensity and temperature tracks and their flicker (see
+    ///   `animation::SceneAnimation::from_json`); a later file replaces the animation of an
+    ///   earlier one
+    ///
+    /// Files can include files that include other files, the same file can't be included
+    /// inside itself. A scene without cameras gets the default camera
+    ///
+    /// The file can also be a scene pack, unpacked into the temporary directory to load
+    /// the scene it holds (see `pack::ScenePack`)
+    pub fn load(filename: &str) -> Result<Scene, Box<dyn std::error::Error>> {
+        if is_pack(Path::new(filename)) {
+            let scene = ScenePack::load(Path::new(filename))?.unpack_to_temp()?;
+            return Scene::load(&scene.to_string_lossy());
+        }
+        let mut loader = SceneLoader::default();
+        loader.include(Path::new(filename))?;
+        Ok(loader.finish())
+    }
+
+    /// Read a scene from the text of a scene file (see `Scene::load`), the files it refers
+    /// to being relative to `directory`
+    pub fn parse(text: &str, directory: &Path) -> Result<Scene, Box<dyn std::error::Error>> {
+        let mut loader = SceneLoader::default();
+        loader.fragment(&Json::parse(text)?, directory)?;
+        Ok(loader.finish())
+    }
+}
+
+/// Scene being assembled with `Scene::builder`
+///
+/// ```ignore
+/// let scene = Scene::builder()
+///     .add_sphere(Sphere::new(Vector3::new(0.0, 0.0, -16.0), 2.0, Material::default()))
+///     .add_light(SceneLight::new(Vector3::new(-20.0, 20.0, 20.0), 1.5))
+///     .camera(Camera::default())
+///     .build()?;
+/// ```
+#[derive(Default)]
+pub struct SceneBuilder {
+    scene: Scene,
+    camera_relative: bool,
+}
+
+impl SceneBuilder {
+    pub fn add_sphere(mut self, sphere: Sphere) -> SceneBuilder {
+        self.scene.spheres.push(sphere);
+        self
+    }
+
+    pub fn add_spheres(mut self, spheres: impl IntoIterator<Item = Sphere>) -> SceneBuilder {
+        self.scene.spheres.extend(spheres);
+        self
+    }
+
+    pub fn add_light(mut self, light: SceneLight) -> SceneBuilder {
+        self.scene.lights.push(light);
+        self
+    }
+
+    pub fn add_lights(mut self, lights: impl IntoIterator<Item = SceneLight>) -> SceneBuilder {
+        self.scene.lights.extend(lights);
+        self
+    }
+
+    /// Add an object generated when a ray first reaches it
+    pub fn add_procedural(mut self, procedural: Arc<dyn Procedural>) -> SceneBuilder {
+        self.scene
+            .procedurals
+            .push(ProceduralObject::new(procedural));
+        self
+    }
+
+    /// Add a camera, the first one added is the camera the scene is seen from by default
+    pub fn camera(mut self, camera: Camera) -> SceneBuilder {
+        self.scene.cameras.push(camera);
+        self
+    }
+
+    pub fn environment(mut self, environment: Environment) -> SceneBuilder {
+        self.scene.environment = Some(environment);
+        self
+    }
+
+    pub fn medium(mut self, medium: Arc<dyn Medium>) -> SceneBuilder {
+        self.scene.medium = Some(medium);
+        self
+    }
+
+    pub fn background(mut self, background: Color) -> SceneBuilder {
+        self.scene.background = Some(background);
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: u32) -> SceneBuilder {
+        self.scene.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Add a render layer of the `layers` command
+    pub fn add_layer(mut self, layer: LayerSpec) -> SceneBuilder {
+        self.scene.layers.push(layer);
+        self
+    }
+
+    pub fn animation(mut self, animation: SceneAnimation) -> SceneBuilder {
+        self.scene.animation = Some(animation);
+        self
+    }
+
+    /// Move the scene next to its first camera when it is built (see `Scene::recentered`)
+    pub fn camera_relative(mut self, camera_relative: bool) -> SceneBuilder {
+        self.camera_relative = camera_relative;
+        self
+    }
+
+    /// The scene, with the default camera if none was added and its bounding volume
+    /// hierarchy built
+    ///
+    /// ### Returns
+    ///
+    /// `Result<Scene, SceneError>` - The scene, or the problems found in it: degenerate
+    /// objects, lights and cameras, and names given twice (see `validate::scene_problems`)
+    ///
+    pub fn build(self) -> Result<Scene, SceneError> {
+        let mut scene = self.scene;
+        if scene.cameras.is_empty() {
+            scene.cameras.push(Camera::default());
+        }
+        let problems = scene_problems(&scene);
+        if !problems.is_empty() {
+            return Err(SceneError { problems });
+        }
+        if self.camera_relative {
+            let origin = scene.cameras[0].position;
+            return Ok(scene.recentered(origin));
+        }
+        scene.rebuild();
+        Ok(scene)
+    }
+}
+
+/// Error raised when a scene is built with problems (see `SceneBuilder::build`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneError {
+    pub problems: Vec<String>,
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            tr_format("invalid scene: {}", &[&self.problems.join("; ")])
+        )
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// Differences between two versions of a scene, as reported by `Scene::diff`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SceneChanges {
+    /// Objects were added, removed, moved or resized
+    pub geometry: bool,
+    /// Indices of the objects whose material changed (among the objects present in both
+    /// versions)
+    pub materials: Vec<usize>,
+    pub lights: bool,
+    pub cameras: bool,
+    pub environment: bool,
+    pub medium: bool,
+    pub background: bool,
+    pub max_depth: bool,
+}
+
+impl SceneChanges {
+    /// Whether the two versions render the same
+    pub fn is_empty(&self) -> bool {
+        *self == SceneChanges::default()
+    }
+
+    /// Whether only the cameras changed, so the scene itself can be kept as it is
+    pub fn camera_only(&self) -> bool {
+        self.cameras
+            && !self.geometry
+            && self.materials.is_empty()
+            && !self.lights
+            && !self.environment
+            && !self.medium
+            && !self.background
+            && !self.max_depth
+    }
+}
+
+/// Scene being assembled from a file and the files it includes
+#[derive(Default)]
+struct SceneLoader {
+    scene: Scene,
+    /// Named materials loaded so far, available to the objects of every later file and
+    /// shared by the objects using them
+    materials: Vec<Arc<Material>>,
+    /// Files being loaded, outermost first, to detect include cycles
+    open: Vec<PathBuf>,
+    /// Whether the scene is moved next to its first camera once loaded
+    camera_relative: bool,
+}
+
+impl SceneLoader {
+    /// The scene loaded, with the default camera if it has none
+    fn finish(self) -> Scene {
+        let mut scene = self.scene;
+        if scene.cameras.is_empty() {
+            scene.cameras.push(Camera::default());
+        }
+        if self.camera_relative {
+            let origin = scene.cameras[0].position;
+            return scene.recentered(origin);
+        }
+        scene.rebuild();
+        scene
+    }
+
+    fn include(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        // the errors keep their kind, so a missing file can be told from an invalid one
+        let context = |error: Box<dyn std::error::Error>| -> Box<dyn std::error::Error> {
+            let message = format!("{}: {}", path.display(), error);
+            match error.downcast_ref::<std::io::Error>() {
+                Some(error) => std::io::Error::new(error.kind(), message).into(),
+                None => JsonError::new(&message).into(),
+            }
+        };
+        let canonical = path.canonicalize().map_err(|error| context(error.into()))?;
+        if self.open.contains(&canonical) {
+            return Err(context("the file includes itself".into()));
+        }
+        let text = std::fs::read_to_string(path).map_err(|error| context(error.into()))?;
+        let json = Json::parse(&text).map_err(|error| context(error.into()))?;
+        self.open.push(canonical);
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let result = self.fragment(&json, directory);
+        self.open.pop();
+        result.map_err(context)
+    }
+
+    fn fragment(
+        &mut self,
+        json: &Json,
+        directory: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("a scene must be a JSON object").into());
+        };
+        for (key, _) in entries {
+            if ![
+                "include",
+                "materials",
+                "objects",
+                "scatter",
+                "procedural",
+                "lights",
+                "cameras",
+                "environment",
+                "medium",
+                "background",
+                "max_depth",
+                "camera_relative",
+                "outputs",
+                "layers",
+                "animation",
+            ]
+            .contains(&key.as_str())
+            {
+                return Err(JsonError::new(&format!("unknown scene key '{}'", key)).into());
+            }
+        }
+        for include in json_to_list("include", json.get("include"))? {
+            let relative = include
+                .as_str()
+                .ok_or_else(|| JsonError::new("'include' must hold file paths"))?;
+            self.include(&directory.join(relative))?;
+        }
+        for material in json_to_list("materials", json.get("materials"))? {
+            let material = material_from_json(material, directory)?;
+            if material.name.is_none() {
+                return Err(JsonError::new("library materials must have a 'name'").into());
+            }
+            // a later definition replaces an earlier one with the same name
+            self.materials.retain(|known| known.name != material.name);
+            self.materials.push(Arc::new(material));
+        }
+        for object in json_to_list("objects", json.get("objects"))? {
+            let sphere = self.object(object, directory)?;
+            self.scene.spheres.push(sphere);
+        }
+        for scatter in json_to_list("scatter", json.get("scatter"))? {
+            let copies = self.scatter(scatter, directory)?.place();
+            self.scene.spheres.extend(copies);
+        }
+        for procedural in json_to_list("procedural", json.get("procedural"))? {
+            let procedural = self.procedural(procedural, directory)?;
+            self.scene.procedurals.push(procedural);
+        }
+        for light in json_to_list("lights", json.get("lights"))? {
+            self.scene.lights.push(light_from_json(light)?);
+        }
+        for camera in json_to_list("cameras", json.get("cameras"))? {
+            self.scene.cameras.push(camera_from_json(camera)?);
+        }
+        if let Some(environment) = json.get("environment") {
+            self.scene.environment = Some(environment_from_json(environment, directory)?);
+        }
+        if let Some(medium) = json.get("medium") {
+            self.scene.medium = Some(medium_from_json(medium)?);
+        }
+        if let Some(background) = json.get("background") {
+            self.scene.background = Some(json_to_color("background", background)?);
+        }
+        if let Some(max_depth) = json.get("max_depth") {
+            self.scene.max_depth = Some(json_to_count("max_depth", max_depth)? as u32);
+        }
+        for output in json_to_list("outputs", json.get("outputs"))? {
+            let output = OutputSpec::from_json(output)?;
+            // a later output to the same file replaces an earlier one
+            self.scene.outputs.retain(|known| known.file != output.file);
+            self.scene.outputs.push(output);
+        }
+        for layer in json_to_list("layers", json.get("layers"))? {
+            let layer = LayerSpec::from_json(layer)?;
+            // a later layer with the same name replaces an earlier one
+            self.scene.layers.retain(|known| known.name != layer.name);
+            self.scene.layers.push(layer);
+        }
+        if let Some(animation) = json.get("animation") {
+            self.scene.animation = Some(SceneAnimation::from_json(animation)?);
+        }
+        if let Some(camera_relative) = json.get("camera_relative") {
+            self.camera_relative = camera_relative
+                .as_bool()
+                .ok_or_else(|| JsonError::new("'camera_relative' must be true or false"))?;
+        }
+        Ok(())
+    }
+
+    fn object(&self, json: &Json, directory: &Path) -> Result<Sphere, JsonError> {
+        let mut sphere = Sphere::new(Vector3::default(), 1.0, Material::default());
+        for (key, value) in json_to_entries("object", json)? {
+            match key.as_str() {
+                "name" => sphere.name = Some(json_to_string(key, value)?),
+                "center" => sphere.center = json_to_vector(key, value)?,
+                "radius" => sphere.radius = json_to_f64(key, value)?,
+                "holdout" => {
+                    sphere.holdout = value.as_bool().ok_or_else(|| {
+                        JsonError::new(&format!("'{}' must be true or false", key))
+                    })?
+                }
+                "material" => sphere.material = self.material(value, directory)?,
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown object property '{}'",
+                        key
+                    )))
+                }
+            }
+        }
+        Ok(sphere)
+    }
+
+    /// Material of an object: the name of a material loaded so far, or a material object
+    fn material(&self, json: &Json, directory: &Path) -> Result<Arc<Material>, JsonError> {
+        match json.as_str() {
+            Some(name) => self
+                .materials
+                .iter()
+                .find(|material| material.name.as_deref() == Some(name))
+                .cloned()
+                .ok_or_else(|| JsonError::new(&format!("unknown material '{}'", name))),
+            None => Ok(Arc::new(material_from_json(json, directory)?)),
+        }
+    }
+
+    fn procedural(&self, json: &Json, directory: &Path) -> Result<ProceduralObject, JsonError> {
+        let kind = json.get("type").and_then(Json::as_str).unwrap_or("");
+        if kind != "sphereflake" {
+            return Err(JsonError::new(&format!(
+                "unknown procedural type '{}', expected sphereflake",
+                kind
+            )));
+        }
+        let mut flake = SphereFlake::new(Vector3::default(), 1.0, 2, Material::default());
+        for (key, value) in json_to_entries("procedural", json)? {
+            match key.as_str() {
+                "type" => {}
+                "center" => flake.center = json_to_vector(key, value)?,
+                "radius" => flake.radius = json_to_f64(key, value)?,
+                "depth" => {
+                    flake.depth = json_to_count(key, value)?
+                        .try_into()
+                        .ok()
+                        .filter(|depth| *depth <= 6)
+                        .ok_or_else(|| JsonError::new("'depth' must be at most 6"))?
+                }
+                "material" => flake.material = self.material(value, directory)?,
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown procedural property '{}'",
+                        key
+                    )))
+                }
+            }
+        }
+        Ok(ProceduralObject::new(Arc::new(flake)))
+    }
+
+    fn scatter(&self, json: &Json, directory: &Path) -> Result<Scatter, JsonError> {
+        let prop = json
+            .get("prop")
+            .ok_or_else(|| JsonError::new("a scatter needs a 'prop'"))?;
+        let surface = json
+            .get("surface")
+            .ok_or_else(|| JsonError::new("a scatter needs a 'surface'"))?;
+        let surface = self.scatter_surface(surface)?;
+        let mut scatter = Scatter::new(self.object(prop, directory)?, 1, surface);
+        let range = |key: &str, value: &Json| match value.as_array() {
+            Some([min, max]) => Ok([json_to_f64(key, min)?, json_to_f64(key, max)?]),
+            _ => Err(JsonError::new(&format!("'{}' must be [min, max]", key))),
+        };
+        for (key, value) in json_to_entries("scatter", json)? {
+            match key.as_str() {
+                "prop" | "surface" => {}
+                "count" => scatter.count = json_to_count(key, value)? as u32,
+                "scale" => {
+                    let [min, max] = range(key, value)?;
+                    if min <= 0.0 || max < min {
+                        return Err(JsonError::new("'scale' must be a positive [min, max]"));
+                    }
+                    scatter = scatter.with_scale(min, max);
+                }
+                "rotation" => {
+                    let [min, max] = range(key, value)?;
+                    scatter = scatter.with_rotation(min.to_radians(), max.to_radians());
+                }
+                "spacing" => scatter = scatter.with_spacing(json_to_f64(key, value)?),
+                "seed" => scatter = scatter.with_seed(json_to_count(key, value)?),
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown scatter property '{}'",
+                        key
+                    )))
+                }
+            }
+        }
+        Ok(scatter)
+    }
+
+    fn scatter_surface(&self, json: &Json) -> Result<ScatterSurface, JsonError> {
+        let entries = json_to_entries("scatter surface", json)?;
+        if let Some(name) = json.get("object") {
+            let name = json_to_string("object", name)?;
+            let object = self
+                .scene
+                .object(&name)
+                .ok_or_else(|| JsonError::new(&format!("unknown object '{}'", name)))?;
+            let mut angle = 90.0_f64;
+            for (key, value) in entries {
+                match key.as_str() {
+                    "object" => {}
+                    "angle" => angle = json_to_f64(key, value)?,
+                    _ => {
+                        return Err(JsonError::new(&format!(
+                            "unknown surface property '{}'",
+                            key
+                        )))
+                    }
+                }
+            }
+            return Ok(ScatterSurface::Sphere {
+                center: object.center,
+                radius: object.radius,
+                angle: angle.to_radians(),
+            });
+        }
+        let (mut center, mut size) = (Vector3::default(), None);
+        for (key, value) in entries {
+            match key.as_str() {
+                "center" => center = json_to_vector(key, value)?,
+                "size" => {
+                    size = match value.as_array() {
+                        Some([x, z]) => Some([json_to_f64(key, x)?, json_to_f64(key, z)?]),
+                        _ => return Err(JsonError::new("'size' must be [x, z]")),
+                    }
+                }
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown surface property '{}'",
+                        key
+                    )))
+                }
+            }
+        }
+        let size =
+            size.ok_or_else(|| JsonError::new("a scatter surface needs a 'size' or an 'object'"))?;
+        Ok(ScatterSurface::Plane { center, size })
+    }
+}
+
+/// Material of a scene file, its texture path relative to the directory of the file
+fn material_from_json(json: &Json, directory: &Path) -> Result<Material, JsonError> {
+    let mut material = Material::from_json_in(json, directory)?;
+    if let Some(texture) = &material.texture {
+        material.texture = Some(directory.join(texture).to_string_lossy().to_string());
+    }
+    Ok(material)
+}
+
+fn light_from_json(json: &Json) -> Result<SceneLight, JsonError> {
+    let entries = json_to_entries("light", json)?;
+    let mut light = match (json.get("direction"), json.get("spot")) {
+        (Some(_), Some(_)) => {
+            return Err(JsonError::new(
+                "a light can't have both a 'direction' and a 'spot'",
+            ))
+        }
+        (Some(direction), None) => SceneLight::sun(json_to_vector("direction", direction)?, 1.0),
+        (None, Some(spot)) => spot_from_json(spot)?,
+        (None, None) => SceneLight::new(Vector3::default(), 1.0),
+    };
+    for (key, value) in entries {
+        match key.as_str() {
+            "name" => light.name = Some(json_to_string(key, value)?),
+            "position" => light.position = json_to_vector(key, value)?,
+            "direction" | "spot" => {}
+            "angular_diameter" => {
+                let LightKind::Directional {
+                    angular_diameter, ..
+                } = &mut light.kind
+                else {
+                    return Err(JsonError::new(
+                        "only a light with a 'direction' has an 'angular_diameter'",
+                    ));
+                };
+                *angular_diameter = json_to_f64(key, value)?.max(0.0).to_radians();
+            }
+            "intensity" => light.intensity = json_to_f64(key, value)?,
+            "color" => light.color = json_to_color(key, value)?,
+            "radius" => light.radius = json_to_f64(key, value)?,
+            "shadow_samples" => light.shadow_samples = json_to_count(key, value)?.max(1) as u32,
+            "max_distance" => light.max_distance = Some(json_to_f64(key, value)?),
+            _ => return Err(JsonError::new(&format!("unknown light property '{}'", key))),
+        }
+    }
+    Ok(light)
+}
+
+/// Spot light at the origin from the cone of a light: its `direction`, the half `angle` of
+/// the cone in degrees and its `softness` (see `SceneLight::spot`, 0 by default)
+fn spot_from_json(json: &Json) -> Result<SceneLight, JsonError> {
+    let (mut direction, mut angle, mut softness) = (None, None, 0.0);
+    for (key, value) in json_to_entries("spot", json)? {
+        match key.as_str() {
+            "direction" => direction = Some(json_to_vector(key, value)?),
+            "angle" => angle = Some(json_to_f64(key, value)?),
+            "softness" => softness = json_to_f64(key, value)?,
+            _ => return Err(JsonError::new(&format!("unknown spot property '{}'", key))),
+        }
+    }
+    let direction = direction.ok_or_else(|| JsonError::new("a spot needs a 'direction'"))?;
+    let angle = angle.ok_or_else(|| JsonError::new("a spot needs an 'angle'"))?;
+    if !(angle > 0.0 && angle < 180.0) {
+        return Err(JsonError::new(
+            "the 'angle' of a spot must be between 0 and 180",
+        ));
+    }
+    Ok(SceneLight::spot(
+        Vector3::default(),
+        direction,
+        1.0,
+        angle.to_radians(),
+        softness,
+    ))
+}
+
+/// Environment of a scene file, its texture path relative to the directory of the file
+fn environment_from_json(json: &Json, directory: &Path) -> Result<Environment, JsonError> {
+    let mut environment = None;
+    let (mut rotation, mut intensity, mut ground) = (0.0, 1.0, None);
+    let mut space = TextureSpace::default();
+    for (key, value) in json_to_entries("environment", json)? {
+        match key.as_str() {
+            "texture" => {
+                let path = directory.join(json_to_string(key, value)?);
+                environment = Some(Environment::new(&path.to_string_lossy()));
+            }
+            "dome" => environment = Some(Environment::dome(dome_from_json(value)?)),
+            "rotation" => rotation = json_to_f64(key, value)?.to_radians(),
+            "intensity" => intensity = json_to_f64(key, value)?,
+            "space" => space = json_to_texture_space(key, value)?,
+            "ground_projection" => ground = Some(ground_from_json(value)?),
+            _ => {
+                return Err(JsonError::new(&format!(
+                    "unknown environment property '{}'",
+                    key
+                )))
+            }
+        }
+    }
+    let environment = environment
+        .ok_or_else(|| JsonError::new("an environment needs a 'texture' or a 'dome'"))?;
+    let environment = environment
+        .with_rotation(rotation)
+        .with_intensity(intensity)
+        .with_space(space);
+    Ok(match ground {
+        Some(ground) => environment.with_ground_projection(ground),
+        None => environment,
+    })
+}
+
+/// Ground projection of an environment, `height` of the capture position above the ground
+/// is required, the dome is centered on the origin with a radius of 100 by default
+fn ground_from_json(json: &Json) -> Result<GroundProjection, JsonError> {
+    let mut ground = GroundProjection::new(Vector3::default(), f64::NAN, 100.0);
+    for (key, value) in json_to_entries("ground projection", json)? {
+        match key.as_str() {
+            "center" => ground.center = json_to_vector(key, value)?,
+            "height" => ground.height = json_to_f64(key, value)?,
+            "radius" => ground.radius = json_to_f64(key, value)?,
+            _ => {
+                return Err(JsonError::new(&format!(
+                    "unknown ground projection property '{}'",
+                    key
+                )))
+            }
+        }
+    }
+    if ground.height.is_nan() {
+        return Err(JsonError::new("a ground projection needs a 'height'"));
+    }
+    Ok(ground)
+}
+
+fn camera_from_json(json: &Json) -> Result<Camera, JsonError> {
+    let mut camera = Camera::default();
+    for (key, value) in json_to_entries("camera", json)? {
+        match key.as_str() {
+            "name" => camera.name = json_to_string(key, value)?,
+            "position" => camera.position = json_to_vector(key, value)?,
+            "target" => camera.target = json_to_vector(key, value)?,
+            "up" => camera.up = json_to_vector(key, value)?,
+            "lens" => camera.lens = LensKind::from_json(value)?,
+            _ => {
+                return Err(JsonError::new(&format!(
+                    "unknown camera property '{}'",
+                    key
+                )))
+            }
+        }
+    }
+    Ok(camera)
+}
+
+/// Entries of a JSON object, naming what it describes in the error
+fn json_to_entries<'a>(what: &str, json: &'a Json) -> Result<&'a [(String, Json)], JsonError> {
+    match json {
+        Json::Object(entries) => Ok(entries),
+        _ => Err(JsonError::new(&format!("a {} must be a JSON object", what))),
+    }
+}
+
+/// Items of an optional array, an absent key gives no items
+fn json_to_list<'a>(key: &str, value: Option<&'a Json>) -> Result<&'a [Json], JsonError> {
+    match value {
+        None => Ok(&[]),
+        Some(value) => value
+            .as_array()
+            .ok_or_else(|| JsonError::new(&format!("'{}' must be an array", key))),
+    }
+}
+
+fn json_to_string(key: &str, value: &Json) -> Result<String, JsonError> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| JsonError::new(&format!("'{}' must be a string", key)))
+}
+
+/// Read a `[x, y, z]` vector, naming the key in the error
+fn json_to_vector(key: &str, value: &Json) -> Result<Vector3<f64>, JsonError> {
+    match value.as_array() {
+        Some([x, y, z]) => Ok(Vector3::new(
+            json_to_f64(key, x)?,
+            json_to_f64(key, y)?,
+            json_to_f64(key, z)?,
+        )),
+        _ => Err(JsonError::new(&format!(
+            "'{}' must be an array of 3 numbers",
+            key
+        ))),
+    }
+}
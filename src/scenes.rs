@@ -0,0 +1,277 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::envmap::Environment;
+use crate::object::Material;
+use crate::object::SceneLight;
+use crate::object::Sphere;
+use crate::random::Rng;
+use crate::scatter::Scatter;
+use crate::scatter::ScatterSurface;
+use crate::scene::Scene;
+use crate::sky::Dome;
+use crate::sky::PhysicalSky;
+use crate::texture::NoiseTexture;
+use crate::volume::Fog;
+
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+/// Standard scene to measure the renderer with, built the same way on every machine
+pub struct BenchScene {
+    /// The scene, seen from its first camera
+    pub scene: Scene,
+    /// Vertical field of view in radians the scene is framed for
+    pub fov: f64,
+}
+
+impl BenchScene {
+    /// Build a benchmark scene from its name, `<scene>[:<parameter>]`
+    ///
+    /// * `bench-spheres[:n]` - `sphere_grid(n)`, 10 spheres per side by default
+    /// * `bench-random[:seed]` - `random_spheres(seed)`, seed 0 by default
+    /// * `landscape[:seed]` - `landscape(seed)`, seed 0 by default
+    pub fn from_name(name: &str) -> Option<BenchScene> {
+        let (scene, parameter) = match name.split_once(':') {
+            Some((scene, parameter)) => (scene, Some(parameter.parse::<u64>().ok()?)),
+            None => (name, None),
+        };
+        match scene {
+            "bench-spheres" => Some(sphere_grid(parameter.unwrap_or(10).max(1) as u32)),
+            "bench-random" => Some(random_spheres(parameter.unwrap_or(0))),
+            "landscape" => Some(landscape(parameter.unwrap_or(0))),
+            _ => None,
+        }
+    }
+}
+
+/// Cube of `n * n * n` spheres cycling through a diffuse, a glossy and a mirror material
+pub fn sphere_grid(n: u32) -> BenchScene {
+    let materials = [
+        Material::new(Vector3::new(0.9, 0.1, 0.0), Color::new(0.3, 0.1, 0.1), 10.0),
+        Material::new(Vector3::new(0.6, 0.3, 0.1), Color::new(0.4, 0.4, 0.3), 50.0),
+        Material::new(Vector3::new(0.0, 10.0, 0.8), Color::WHITE, 1425.0),
+    ];
+    // the cube spans [-1, 1] on every axis around its center
+    let center = Vector3::new(0.0, 0.0, -4.0);
+    let spacing = 2.0 / n as f64;
+    let mut spheres = Vec::with_capacity((n * n * n) as usize);
+    for x in 0..n {
+        for y in 0..n {
+            for z in 0..n {
+                let offset = Vector3::new(x as f64, y as f64, z as f64).add_scalar(0.5) * spacing;
+                let material = materials[((x + y + z) % 3) as usize].clone();
+                spheres.push(Sphere::new(
+                    center + offset.add_scalar(-1.0),
+                    spacing * 0.4,
+                    material,
+                ));
+            }
+        }
+    }
+    let scene = Scene::builder()
+        .add_spheres(spheres)
+        .add_light(SceneLight::new(Vector3::new(-20.0, 20.0, 20.0), 1.5))
+        .add_light(SceneLight::new(Vector3::new(30.0, 20.0, 30.0), 1.2))
+        .camera(Camera::new("bench", Vector3::new(2.0, 1.5, -0.5), center))
+        .build()
+        .expect("the sphere grid is a valid scene");
+    BenchScene {
+        scene,
+        fov: 50.0_f64.to_radians(),
+    }
+}
+
+/// Random field of small spheres around three large ones, after the final scene of
+/// "Ray Tracing in One Weekend"
+pub fn random_spheres(seed: u64) -> BenchScene {
+    let mut rng = Rng::new(seed);
+    let ground = Material::new(Vector3::new(0.9, 0.1, 0.0), Color::gray(0.5), 10.0);
+    let glass = Material::new(
+        Vector3::new(0.0, 0.5, 0.1),
+        Color::new(0.6, 0.7, 0.8),
+        125.0,
+    )
+    .with_refraction(0.8, 1.5);
+    let mut spheres = vec![Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0, ground)];
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Vector3::new(
+                a as f64 + 0.9 * rng.next_f64(),
+                0.2,
+                b as f64 + 0.9 * rng.next_f64(),
+            );
+            if (center - Vector3::new(4.0, 0.2, 0.0)).norm() <= 0.9 {
+                continue;
+            }
+            let choice = rng.next_f64();
+            let mut color = || Color::new(rng.next_f64(), rng.next_f64(), rng.next_f64());
+            let material = if choice < 0.8 {
+                let albedo = color() * color();
+                Material::new(Vector3::new(0.9, 0.1, 0.0), albedo, 10.0)
+            } else if choice < 0.95 {
+                let tint = color().map(|c| 0.5 + 0.5 * c);
+                Material::new(Vector3::new(0.1, 0.5, 0.6), tint, 200.0)
+            } else {
+                glass.clone()
+            };
+            spheres.push(Sphere::new(center, 0.2, material));
+        }
+    }
+    spheres.push(Sphere::new(Vector3::new(0.0, 1.0, 0.0), 1.0, glass));
+    spheres.push(Sphere::new(
+        Vector3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Material::new(Vector3::new(0.9, 0.1, 0.0), Color::new(0.4, 0.2, 0.1), 10.0),
+    ));
+    spheres.push(Sphere::new(
+        Vector3::new(4.0, 1.0, 0.0),
+        1.0,
+        Material::new(
+            Vector3::new(0.0, 10.0, 0.8),
+            Color::new(0.7, 0.6, 0.5),
+            1425.0,
+        ),
+    ));
+    let scene = Scene::builder()
+        .add_spheres(spheres)
+        .add_light(SceneLight::sun(Vector3::new(-1.0, -2.0, -1.0), 1.2))
+        .add_light(SceneLight::new(Vector3::new(10.0, 10.0, 10.0), 0.6))
+        .camera(Camera::new(
+            "bench",
+            Vector3::new(13.0, 2.0, 3.0),
+            Vector3::default(),
+        ))
+        .build()
+        .expect("the random spheres are a valid scene");
+    BenchScene {
+        scene,
+        fov: 20.0_f64.to_radians(),
+    }
+}
+
+/// Rolling hills under a clear sky with the sun low over them, rocks and trees scattered over
+/// the hills and a light haze, showing the whole pipeline at work
+///
+/// Objects are spheres, so the terrain is a heightfield of seeded value noise drawn as the
+/// caps of large spheres sunk into a ground sphere, one per cell of a grid, and a tree is a
+/// trunk of small spheres under a round crown
+pub fn landscape(seed: u64) -> BenchScene {
+    let mut rng = Rng::new(seed);
+    let grass = Material::new(Vector3::new(0.9, 0.05, 0.0), Color::WHITE, 10.0)
+        .with_diffuse_texture(Arc::new(NoiseTexture {
+            low: Color::new(0.12, 0.25, 0.06),
+            high: Color::new(0.45, 0.5, 0.2),
+            scale: 0.4,
+            octaves: 4,
+        }));
+    let grass = Arc::new(grass);
+    let mut spheres = vec![Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        grass.clone(),
+    )];
+    // heights of the hills at the corners of a coarse lattice, smoothly interpolated in
+    // between
+    let lattice: Vec<f64> = (0..8 * 8).map(|_| rng.next_f64()).collect();
+    let height = |x: f64, z: f64| {
+        let (x, z) = ((x + 21.0) / 6.0, -z / 6.0);
+        let (i, j) = (x.floor().clamp(0.0, 6.0), z.floor().clamp(0.0, 6.0));
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (s, t) = (
+            smooth((x - i).clamp(0.0, 1.0)),
+            smooth((z - j).clamp(0.0, 1.0)),
+        );
+        let at = |di: f64, dj: f64| lattice[((j + dj) * 8.0 + i + di) as usize];
+        let near = at(0.0, 0.0) + (at(1.0, 0.0) - at(0.0, 0.0)) * s;
+        let far = at(0.0, 1.0) + (at(1.0, 1.0) - at(0.0, 1.0)) * s;
+        4.0 * (near + (far - near) * t) - 1.0
+    };
+    const HILL_RADIUS: f64 = 9.0;
+    for i in 0..14 {
+        for j in 0..14 {
+            let x = -20.0 + 3.0 * i as f64 + rng.next_f64();
+            let z = -2.0 - 3.0 * j as f64 - rng.next_f64();
+            let top = height(x, z);
+            if top > 0.0 {
+                let center = Vector3::new(x, top - HILL_RADIUS, z);
+                spheres.push(Sphere::new(center, HILL_RADIUS, grass.clone()));
+            }
+        }
+    }
+    // height of the terrain, the highest of the ground and the caps of the hills
+    let hills = spheres.clone();
+    let ground_at = |x: f64, z: f64| {
+        hills[1..].iter().fold(0.0_f64, |top, hill| {
+            let d2 = (hill.center.x - x).powi(2) + (hill.center.z - z).powi(2);
+            let r2 = hill.radius * hill.radius;
+            if d2 < r2 {
+                top.max(hill.center.y + (r2 - d2).sqrt())
+            } else {
+                top
+            }
+        })
+    };
+    let floor = ScatterSurface::Plane {
+        center: Vector3::new(0.0, 0.0, -22.0),
+        size: [36.0, 38.0],
+    };
+    let stone = Material::new(Vector3::new(0.8, 0.1, 0.0), Color::gray(0.45), 20.0);
+    let rocks = Scatter::new(Sphere::new(Vector3::default(), 0.35, stone), 60, floor)
+        .with_scale(0.4, 1.6)
+        .with_spacing(0.3)
+        .with_seed(seed ^ 0x9e37_79b9);
+    let crown = Material::new(
+        Vector3::new(0.9, 0.05, 0.0),
+        Color::new(0.1, 0.3, 0.08),
+        10.0,
+    );
+    let trunk = Arc::new(Material::new(
+        Vector3::new(0.9, 0.0, 0.0),
+        Color::new(0.3, 0.2, 0.1),
+        10.0,
+    ));
+    let trees = Scatter::new(Sphere::new(Vector3::default(), 1.0, crown), 25, floor)
+        .with_scale(0.7, 1.3)
+        .with_spacing(0.5)
+        .with_seed(seed ^ 0x7f4a_7c15);
+    // the copies stand on the floor plane, they are lifted onto the hills
+    for mut rock in rocks.place() {
+        // half sunk into the ground
+        rock.center.y = ground_at(rock.center.x, rock.center.z) + rock.radius * 0.5;
+        spheres.push(rock);
+    }
+    for mut tree in trees.place() {
+        let foot = ground_at(tree.center.x, tree.center.z);
+        let trunk_height = 1.5 * tree.radius;
+        for k in 0..4 {
+            let y = foot + trunk_height * k as f64 / 3.0;
+            let center = Vector3::new(tree.center.x, y, tree.center.z);
+            spheres.push(Sphere::new(center, 0.18 * tree.radius, trunk.clone()));
+        }
+        tree.center.y = foot + trunk_height + tree.radius * 0.8;
+        spheres.push(tree);
+    }
+    // the sky gives the background and the fill light, the sun is a light of the scene
+    // so every integrator sees it
+    let (elevation, azimuth) = (35.0_f64.to_radians(), 150.0_f64.to_radians());
+    let sky = PhysicalSky::new(elevation, azimuth).with_sun_intensity(0.0);
+    let sun = SceneLight::sun(-sky.sun_direction, 1.4).with_temperature(4500.0);
+    let mut haze = Fog::new(0.012, 0.3);
+    haze.max_distance = 80.0;
+    let scene = Scene::builder()
+        .add_spheres(spheres)
+        .add_light(sun)
+        .environment(Environment::dome(Dome::Sky(sky)))
+        .medium(Arc::new(haze))
+        .camera(Camera::new(
+            "landscape",
+            Vector3::new(0.0, 4.0, 6.0),
+            Vector3::new(0.0, 1.0, -20.0),
+        ))
+        .build()
+        .expect("the landscape is a valid scene");
+    BenchScene {
+        scene,
+        fov: 55.0_f64.to_radians(),
+    }
+}
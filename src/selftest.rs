@@ -0,0 +1,111 @@
+use crate::color::Color;
+use crate::colorspace::ColorPipeline;
+use crate::info::RenderStatistics;
+use crate::json::Json;
+use crate::object::Material;
+use crate::preview::shader_ball;
+use crate::preview::SHADER_BALL_FOV;
+use crate::render::render;
+use crate::scene::Scene;
+use crate::sequence::fnv1a;
+use crate::settings::RenderSettings;
+
+use nalgebra::Vector3;
+use std::time::Instant;
+
+/// Hash of the 8-bit pixels of the self-test render, see `selftest_scene`
+pub const SELFTEST_CHECKSUM: u64 = 0xeb89_d841_9d3f_9f85;
+
+/// Size of the tile rendered to measure the speed of the machine
+const BENCHMARK_TILE: u32 = 64;
+
+/// Samples per pixel of the benchmark tile
+const BENCHMARK_SAMPLES: u32 = 16;
+
+/// Outcome of `selftest`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfTestReport {
+    /// Hash of the pixels rendered
+    pub checksum: u64,
+    /// Hash they should have
+    pub expected: u64,
+    /// Time taken by the benchmark tile
+    pub tile: RenderStatistics,
+}
+
+impl SelfTestReport {
+    /// Whether the render matches the expected one
+    pub fn passed(&self) -> bool {
+        self.checksum == self.expected
+    }
+
+    /// Report as a JSON object, the hashes written as 16 hexadecimal digits
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("passed".to_string(), Json::Bool(self.passed())),
+            (
+                "checksum".to_string(),
+                Json::String(format!("{:016x}", self.checksum)),
+            ),
+            (
+                "expected".to_string(),
+                Json::String(format!("{:016x}", self.expected)),
+            ),
+            ("tile".to_string(), self.tile.to_json()),
+        ])
+    }
+}
+
+/// Built-in scene of the self-test: a glass ball on the shader ball stage (see
+/// `shader_ball`), going through the diffuse, specular, mirror, refraction and soft shadow
+/// code paths
+pub fn selftest_scene() -> Scene {
+    let glass = Material::new(
+        Vector3::new(0.0, 0.5, 0.1),
+        Color::new(0.6, 0.7, 0.8),
+        125.0,
+    )
+    .with_refraction(0.8, 1.5);
+    shader_ball(glass)
+}
+
+/// Check that the renderer works on this machine: render a tiny image of a built-in scene,
+/// compare the hash of its pixels with the expected one, then time the render of a tile
+///
+/// The render doesn't depend on the number of threads or on user settings, only the time
+/// of the tile does
+///
+/// ### Arguments
+///
+/// * `threads` - The number of render threads, 0 for every core
+///
+/// ### Returns
+///
+/// `SelfTestReport` - The hash of the render and the speed of the tile
+///
+pub fn selftest(threads: usize) -> SelfTestReport {
+    let scene = selftest_scene();
+    let pipeline = ColorPipeline::default();
+    let settings = RenderSettings::new(64, 48)
+        .with_fov(SHADER_BALL_FOV.to_radians())
+        .with_samples_per_pixel(4)
+        .with_threads(threads)
+        .with_camera(scene.cameras[0].clone());
+    let pixels = render(&settings, &scene, &pipeline);
+    let checksum = fnv1a(&pixels);
+
+    let tile_settings = RenderSettings {
+        width: BENCHMARK_TILE,
+        height: BENCHMARK_TILE,
+        ..settings.with_samples_per_pixel(BENCHMARK_SAMPLES)
+    };
+    let start = Instant::now();
+    render(&tile_settings, &scene, &pipeline);
+    let tile = RenderStatistics::new("tile", &tile_settings, start.elapsed().as_secs_f64());
+
+    SelfTestReport {
+        checksum,
+        expected: SELFTEST_CHECKSUM,
+        tile,
+    }
+}
@@ -0,0 +1,171 @@
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::object::SceneLight;
+use crate::object::Sphere;
+use crate::settings::json_to_count;
+use crate::settings::json_to_f64;
+use crate::settings::RenderSettings;
+
+use std::path::Path;
+
+/// One rendered frame of a sequence
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameRecord {
+    /// Name of the image file of the frame
+    pub file: String,
+    /// Time of the frame in seconds
+    pub time: f64,
+    /// Hash of everything the frame was rendered from (see `frame_hash`)
+    pub hash: u64,
+}
+
+/// Description of an image sequence for the encoders and frame interpolators reading it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SequenceManifest {
+    /// Frames per second
+    pub fps: f64,
+    /// Time the shutter stays open during a frame in seconds, 0 when frames are rendered
+    /// at an instant without motion blur
+    pub shutter: f64,
+    /// Whether motion vectors were written next to the frames
+    pub motion_vectors: bool,
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<FrameRecord>,
+}
+
+impl SequenceManifest {
+    pub fn new(fps: f64, width: u32, height: u32) -> SequenceManifest {
+        SequenceManifest {
+            fps,
+            shutter: 0.0,
+            motion_vectors: false,
+            width,
+            height,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn with_shutter(mut self, shutter: f64) -> SequenceManifest {
+        self.shutter = shutter;
+        self
+    }
+
+    pub fn with_motion_vectors(mut self, motion_vectors: bool) -> SequenceManifest {
+        self.motion_vectors = motion_vectors;
+        self
+    }
+
+    /// Manifest as a JSON object, the hashes written as hexadecimal strings
+    pub fn to_json(&self) -> Json {
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| {
+                Json::Object(vec![
+                    ("file".to_string(), Json::String(frame.file.clone())),
+                    ("time".to_string(), Json::Number(frame.time)),
+                    (
+                        "hash".to_string(),
+                        Json::String(format!("{:016x}", frame.hash)),
+                    ),
+                ])
+            })
+            .collect();
+        Json::Object(vec![
+            ("fps".to_string(), Json::Number(self.fps)),
+            ("shutter".to_string(), Json::Number(self.shutter)),
+            (
+                "motion_vectors".to_string(),
+                Json::Bool(self.motion_vectors),
+            ),
+            ("width".to_string(), Json::Number(self.width as f64)),
+            ("height".to_string(), Json::Number(self.height as f64)),
+            ("frames".to_string(), Json::Array(frames)),
+        ])
+    }
+
+    /// Read a manifest written by `to_json`
+    pub fn from_json(json: &Json) -> Result<SequenceManifest, JsonError> {
+        let number = |key: &str| {
+            json.get(key)
+                .ok_or_else(|| JsonError::new(&format!("missing '{}'", key)))
+                .and_then(|value| json_to_f64(key, value))
+        };
+        let count = |key: &str| {
+            json.get(key)
+                .ok_or_else(|| JsonError::new(&format!("missing '{}'", key)))
+                .and_then(|value| json_to_count(key, value))
+        };
+        let mut manifest = SequenceManifest::new(
+            number("fps")?,
+            count("width")? as u32,
+            count("height")? as u32,
+        )
+        .with_shutter(number("shutter")?)
+        .with_motion_vectors(json.get("motion_vectors").and_then(Json::as_bool) == Some(true));
+        let frames = json
+            .get("frames")
+            .and_then(Json::as_array)
+            .ok_or_else(|| JsonError::new("'frames' must be an array"))?;
+        for frame in frames {
+            let file = frame.get("file").and_then(Json::as_str);
+            let time = frame.get("time").and_then(Json::as_f64);
+            let hash = frame
+                .get("hash")
+                .and_then(Json::as_str)
+                .and_then(|hash| u64::from_str_radix(hash, 16).ok());
+            let (Some(file), Some(time), Some(hash)) = (file, time, hash) else {
+                return Err(JsonError::new(
+                    "frames must have a 'file', a 'time' and a hexadecimal 'hash'",
+                ));
+            };
+            manifest.frames.push(FrameRecord {
+                file: file.to_string(),
+                time,
+                hash,
+            });
+        }
+        Ok(manifest)
+    }
+
+    /// Load a manifest from a JSON file
+    pub fn load(filename: &str) -> Result<SequenceManifest, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(filename)?;
+        Ok(SequenceManifest::from_json(&Json::parse(&text)?)?)
+    }
+
+    /// Save the manifest to a JSON file
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        std::fs::write(filename, self.to_json().to_pretty_string() + "\n")
+    }
+
+    /// Record of a frame already rendered from the given inputs whose image is still on
+    /// disk, so rendering it again can be skipped
+    ///
+    /// The files of the frames are looked for in `directory`, the directory of the manifest
+    pub fn completed(&self, directory: &Path, file: &str, hash: u64) -> Option<&FrameRecord> {
+        self.frames
+            .iter()
+            .find(|frame| frame.file == file && frame.hash == hash && directory.join(file).exists())
+    }
+}
+
+/// Hash of the inputs of a frame: the render settings, the spheres and the lights as they
+/// are at the time of the frame
+///
+/// The hash is stable across runs and builds (FNV-1a over the printed inputs), so frames
+/// of a sequence rendered again with unchanged inputs can be recognized
+pub fn frame_hash(settings: &RenderSettings, spheres: &[Sphere], lights: &[SceneLight]) -> u64 {
+    let text = format!("{:?}{:?}{:?}", settings, spheres, lights);
+    fnv1a(text.as_bytes())
+}
+
+/// 64-bit FNV-1a hash of some bytes
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for byte in bytes {
+        hash = (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
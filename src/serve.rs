@@ -0,0 +1,171 @@
+use crate::colorspace::ColorPipeline;
+use crate::image::ImageStream;
+use crate::parameter::apply_override;
+use crate::render::render_to_stream;
+use crate::renderer::Renderer;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+use futures::executor::block_on;
+use std::borrow::Cow;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+
+/// Rows rendered before they are sent, so clients see the image come in from the top while
+/// it renders
+const STREAM_BAND_ROWS: u32 = 16;
+
+/// Serve renders of a scene over HTTP
+///
+/// `GET /render` answers with the scene rendered as a PNG image, the query string holds
+/// overrides applied to a copy of the scene and settings for this render only (e.g.
+/// `/render?camera.fov=60&materials.mirror.ior=1.3`, see `parameter::set_parameter`)
+///
+/// Every connection is answered by its own thread, the requests without overrides render
+/// the scene as it is, shared by all of them
+///
+/// The image is sent while it renders, a band of rows at a time. Interlaced images are
+/// sent once the render is complete instead, but show the whole frame coarsely before
+/// they are fully received
+///
+/// ### Arguments
+///
+/// * `address` - The address to listen on, e.g. `127.0.0.1:8080`
+/// * `scene` - The scene to render
+/// * `settings` - The render settings
+/// * `pipeline` - The color pipeline of the images
+/// * `interlaced` - Whether the images are sent as Adam7 interlaced PNGs
+///
+/// ### Returns
+///
+/// `std::io::Result<()>` - An error if the address can't be listened on, errors of single
+/// requests are reported to stderr and the server goes on
+///
+pub fn serve(
+    address: &str,
+    scene: &Scene,
+    settings: &RenderSettings,
+    pipeline: &ColorPipeline,
+    interlaced: bool,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            scope.spawn(move || {
+                let result = stream.and_then(|mut stream| {
+                    respond(&mut stream, scene, settings, pipeline, interlaced)
+                });
+                if let Err(error) = result {
+                    eprintln!("request failed: {}", error);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Read one request from the stream and write its response
+fn respond(
+    stream: &mut TcpStream,
+    scene: &Scene,
+    settings: &RenderSettings,
+    pipeline: &ColorPipeline,
+    interlaced: bool,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // the headers are read and ignored
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if method != "GET" || path != "/render" {
+        return write_response(
+            stream,
+            "404 Not Found",
+            "text/plain",
+            b"GET /render?<overrides>\n",
+        );
+    }
+
+    // the scene and the settings are only copied when the request changes them
+    let mut scene = Cow::Borrowed(scene);
+    let mut settings = Cow::Borrowed(settings);
+    for text in query.split('&').filter(|text| !text.is_empty()) {
+        if let Err(error) = apply_override(scene.to_mut(), settings.to_mut(), &decode(text)) {
+            let message = format!("{}\n", error);
+            return write_response(stream, "400 Bad Request", "text/plain", message.as_bytes());
+        }
+    }
+
+    if interlaced {
+        // copies of the scene share its objects, the render threads own theirs
+        let renderer = Renderer::new(settings.into_owned(), scene.into_owned(), pipeline.clone());
+        let image = block_on(renderer.render_image());
+        return write_response(
+            stream,
+            "200 OK",
+            "image/png",
+            &image.encode_png_interlaced()?,
+        );
+    }
+
+    // the length of the image isn't known before it is rendered, the end of the
+    // connection marks the end of the image
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nConnection: close\r\n\r\n"
+    )?;
+    let png = ImageStream::new(stream.try_clone()?, settings.width, settings.height)?;
+    render_to_stream(&settings, &scene, pipeline, STREAM_BAND_ROWS, png)?;
+    stream.flush()
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+/// Undo the percent-encoding of a query string parameter
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(if bytes[i] == b'+' { b' ' } else { bytes[i] });
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
@@ -0,0 +1,652 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::colorspace::TextureSpace;
+use crate::colorspace::WorkingSpace;
+use crate::envmap::Environment;
+use crate::film::FilmPixel;
+use crate::image::check_image_size;
+use crate::integrator::IntegratorKind;
+use crate::integrator::PathLimits;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::medium::Medium;
+use crate::medium::VACUUM;
+use crate::passes::PASS_NAMES;
+use crate::pixelfilter::FilterKind;
+use crate::pixelfilter::PixelFilter;
+use crate::pixelorder::PixelOrder;
+use crate::ray::Ray;
+use crate::reconstruction::CrossBilateral;
+use crate::sampler::SamplerKind;
+use crate::scene::Scene;
+use crate::texture::TextureFormat;
+
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Radiance of the rays leaving the scene when neither the settings nor the scene set it
+pub const DEFAULT_BACKGROUND: Color = Color::new(0.7, 0.8, 1.0);
+
+/// Maximum number of bounces when neither the settings nor the scene set it
+pub const DEFAULT_MAX_DEPTH: u32 = 6;
+
+/// Parameters of a render that don't belong to the scene itself
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    /// Size of the image in pixels
+    pub width: u32,
+    pub height: u32,
+    /// Vertical field of view in radians
+    pub fov: f64,
+    /// Number of camera rays per pixel, spread over stratified positions inside the pixel
+    pub samples_per_pixel: u32,
+    /// Factor the beauty image is rendered larger by before it is shrunk to its size with a
+    /// Lanczos filter (see `render::render_supersampled`), 1 to render it at its size
+    pub supersampling: u32,
+    /// Filter weighting the camera rays of a pixel by their distance to its center, the
+    /// average of the rays inside the pixel by default (see `pixelfilter::PixelFilter`)
+    pub filter: PixelFilter,
+    /// Algorithm computing the radiance of the camera rays of the beauty image (see
+    /// `integrator::Integrator`)
+    pub integrator: IntegratorKind,
+    /// Source of the values of the samples of a pixel (see `sampler::Sampler`)
+    pub sampler: SamplerKind,
+    /// Order the pixels are rendered in (see `pixelorder::PixelOrder`)
+    pub pixel_order: PixelOrder,
+    /// Maximum number of mirror bounces of a ray, a scene file can set it (see
+    /// `with_scene_values`)
+    pub max_depth: u32,
+    /// Limits of the paths of the path tracer: bounces of every lobe, clamps of the radiance
+    /// and mollification of the highlights (see `integrator::PathLimits`)
+    pub path_limits: PathLimits,
+    /// Seed of the camera ray jitter (see `render::camera_rays`), 0 for the fixed pattern
+    pub seed: u64,
+    /// Radiance of the rays leaving the scene, a scene file can set it (see
+    /// `with_scene_values`)
+    pub background: Color,
+    /// Number of render threads, 0 uses every available core
+    pub threads: usize,
+    /// Memory allowed for the pixels of the image in bytes, larger images are rendered and
+    /// written to disk a band of rows at a time (0 for no limit)
+    pub memory_budget: usize,
+    /// Memory allowed for the textures kept loaded in bytes, the least recently used ones
+    /// are dropped and loaded again when needed (0 for no limit)
+    pub texture_budget: usize,
+    /// Whether textures and the film the passes of a progressive render are accumulated on
+    /// are stored in half precision, halving their memory traffic at the cost of about
+    /// three significant digits (see `half::HalfColor`)
+    pub half_precision: bool,
+    /// Working space of the color pipeline, the textures stored in sRGB are converted to it
+    /// when they are loaded (see `colorspace::TextureSpace`)
+    pub working_space: WorkingSpace,
+    /// Filter reconstructing noisy renders from their AOVs
+    pub denoise: CrossBilateral,
+    /// Component passes (see `passes::PASS_NAMES`) the filter is applied to, the others are
+    /// kept as rendered; empty to filter the full image at once
+    pub denoise_passes: Vec<String>,
+    /// Environment map seen by the rays leaving the scene instead of the background color,
+    /// it comes from the scene (not serialized)
+    pub environment: Option<Environment>,
+    /// Participating medium filling the scene outside the objects (global fog), vacuum
+    /// without one, it comes from the scene (not serialized)
+    pub medium: Option<Arc<dyn Medium>>,
+    /// Camera the image is seen from, chosen among the cameras of the scene (not serialized)
+    pub camera: Camera,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 1024,
+            height: 768,
+            fov: PI / 2.0,
+            samples_per_pixel: 1,
+            supersampling: 1,
+            filter: PixelFilter::default(),
+            integrator: IntegratorKind::default(),
+            sampler: SamplerKind::default(),
+            pixel_order: PixelOrder::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            path_limits: PathLimits::default(),
+            seed: 0,
+            background: DEFAULT_BACKGROUND,
+            threads: 0,
+            memory_budget: 0,
+            texture_budget: 0,
+            half_precision: false,
+            working_space: WorkingSpace::default(),
+            denoise: CrossBilateral::default(),
+            denoise_passes: Vec::new(),
+            environment: None,
+            medium: None,
+            camera: Camera::default(),
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn new(width: u32, height: u32) -> RenderSettings {
+        RenderSettings {
+            width,
+            height,
+            ..RenderSettings::default()
+        }
+    }
+
+    pub fn with_fov(mut self, fov: f64) -> RenderSettings {
+        self.fov = fov;
+        self
+    }
+
+    pub fn with_samples_per_pixel(mut self, samples_per_pixel: u32) -> RenderSettings {
+        self.samples_per_pixel = samples_per_pixel.max(1);
+        self
+    }
+
+    pub fn with_supersampling(mut self, supersampling: u32) -> RenderSettings {
+        self.supersampling = supersampling.max(1);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: PixelFilter) -> RenderSettings {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_integrator(mut self, integrator: IntegratorKind) -> RenderSettings {
+        self.integrator = integrator;
+        self
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerKind) -> RenderSettings {
+        self.sampler = sampler;
+        self
+    }
+
+    pub fn with_pixel_order(mut self, pixel_order: PixelOrder) -> RenderSettings {
+        self.pixel_order = pixel_order;
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: u32) -> RenderSettings {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_path_limits(mut self, path_limits: PathLimits) -> RenderSettings {
+        self.path_limits = path_limits;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> RenderSettings {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_background(mut self, background: Color) -> RenderSettings {
+        self.background = background;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> RenderSettings {
+        self.threads = threads;
+        self
+    }
+
+    pub fn with_memory_budget(mut self, memory_budget: usize) -> RenderSettings {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    pub fn with_texture_budget(mut self, texture_budget: usize) -> RenderSettings {
+        self.texture_budget = texture_budget;
+        self
+    }
+
+    /// Number of image rows rendered at once within the memory budget (at least one), the
+    /// samples of the rows on the film and their RGB pixels
+    pub fn band_height(&self) -> u32 {
+        let row_size = self.width as usize * (std::mem::size_of::<FilmPixel>() + 3);
+        if self.memory_budget == 0 || row_size == 0 {
+            return self.height.max(1);
+        }
+        (self.memory_budget / row_size).clamp(1, self.height.max(1) as usize) as u32
+    }
+
+    pub fn with_half_precision(mut self, half_precision: bool) -> RenderSettings {
+        self.half_precision = half_precision;
+        self
+    }
+
+    pub fn with_working_space(mut self, working_space: WorkingSpace) -> RenderSettings {
+        self.working_space = working_space;
+        self
+    }
+
+    /// Format the texture files of the given color space are loaded in for this render
+    pub fn texture_format(&self, space: TextureSpace) -> TextureFormat {
+        TextureFormat::new(space, self.working_space, self.half_precision)
+    }
+
+    pub fn with_camera(mut self, camera: Camera) -> RenderSettings {
+        self.camera = camera;
+        self
+    }
+
+    pub fn with_denoise(mut self, denoise: CrossBilateral) -> RenderSettings {
+        self.denoise = denoise;
+        self
+    }
+
+    pub fn with_denoise_passes(mut self, denoise_passes: Vec<String>) -> RenderSettings {
+        self.denoise_passes = denoise_passes;
+        self
+    }
+
+    pub fn with_environment(mut self, environment: Option<Environment>) -> RenderSettings {
+        self.environment = environment;
+        self
+    }
+
+    pub fn with_medium(mut self, medium: Option<Arc<dyn Medium>>) -> RenderSettings {
+        self.medium = medium;
+        self
+    }
+
+    /// The settings with the background and the number of bounces of the scene, where the
+    /// scene sets them (see `Scene::background` and `Scene::max_depth`)
+    pub fn with_scene_values(mut self, scene: &Scene) -> RenderSettings {
+        if let Some(background) = scene.background {
+            self.background = background;
+        }
+        if let Some(max_depth) = scene.max_depth {
+            self.max_depth = max_depth;
+        }
+        self
+    }
+
+    /// Medium of the rays outside every object (see `medium::MediumStack::medium`)
+    pub fn outside_medium(&self) -> &dyn Medium {
+        match &self.medium {
+            Some(medium) => medium.as_ref(),
+            None => &VACUUM,
+        }
+    }
+
+    /// Radiance of a ray leaving the scene
+    pub fn background_radiance(&self, ray: &Ray) -> Color {
+        match &self.environment {
+            Some(environment) => {
+                let format = self.texture_format(environment.space);
+                environment.radiance(ray, self.texture_budget, format)
+            }
+            None => self.background,
+        }
+    }
+
+    /// Ray of the selected camera through the given point of the image, in pixels
+    pub fn camera_ray(&self, x: f64, y: f64) -> Ray {
+        self.camera.ray(x, y, self.width, self.height, self.fov)
+    }
+
+    /// Ray of the selected camera through the given point of the image, in pixels, and a
+    /// point of the aperture of its lens, `None` when the lens blocks it (see
+    /// `Camera::lens_ray`)
+    pub fn camera_lens_ray(&self, x: f64, y: f64, aperture: (f64, f64)) -> Option<Ray> {
+        self.camera
+            .lens_ray(x, y, self.width, self.height, self.fov, aperture)
+    }
+
+    /// Point of the image a point of the scene is seen at by the selected camera, in pixels
+    /// (see `Camera::project`)
+    pub fn project(&self, point: Vector3<f64>) -> Option<(f64, f64)> {
+        self.camera
+            .project(point, self.width, self.height, self.fov)
+    }
+
+    /// Number of threads to render with, resolving 0 to the number of available cores
+    pub fn thread_count(&self) -> usize {
+        if self.threads > 0 {
+            return self.threads;
+        }
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    }
+
+    /// Settings as a JSON object (the field of view is written in degrees and the memory
+    /// budgets in megabytes)
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("width".to_string(), Json::Number(self.width as f64)),
+            ("height".to_string(), Json::Number(self.height as f64)),
+            ("fov".to_string(), Json::Number(self.fov.to_degrees())),
+            (
+                "samples_per_pixel".to_string(),
+                Json::Number(self.samples_per_pixel as f64),
+            ),
+            (
+                "supersampling".to_string(),
+                Json::Number(self.supersampling as f64),
+            ),
+            (
+                "filter".to_string(),
+                Json::Object(vec![
+                    (
+                        "type".to_string(),
+                        Json::String(self.filter.kind.name().to_string()),
+                    ),
+                    ("radius".to_string(), Json::Number(self.filter.radius)),
+                ]),
+            ),
+            ("integrator".to_string(), self.integrator.to_json()),
+            (
+                "sampler".to_string(),
+                Json::String(self.sampler.name().to_string()),
+            ),
+            (
+                "pixel_order".to_string(),
+                Json::String(self.pixel_order.name().to_string()),
+            ),
+            ("max_depth".to_string(), Json::Number(self.max_depth as f64)),
+            ("path_limits".to_string(), self.path_limits.to_json()),
+            ("seed".to_string(), Json::Number(self.seed as f64)),
+            ("background".to_string(), color_to_json(self.background)),
+            ("threads".to_string(), Json::Number(self.threads as f64)),
+            (
+                "memory_budget_mb".to_string(),
+                Json::Number((self.memory_budget >> 20) as f64),
+            ),
+            (
+                "texture_budget_mb".to_string(),
+                Json::Number((self.texture_budget >> 20) as f64),
+            ),
+            (
+                "half_precision".to_string(),
+                Json::Bool(self.half_precision),
+            ),
+            (
+                "working_space".to_string(),
+                Json::String(self.working_space.name().to_string()),
+            ),
+            ("denoise".to_string(), self.denoise_to_json()),
+        ])
+    }
+
+    /// Read settings from a JSON object, missing keys keep their default values
+    pub fn from_json(json: &Json) -> Result<RenderSettings, JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("render settings must be a JSON object"));
+        };
+        let mut settings = RenderSettings::default();
+        for (key, value) in entries {
+            match key.as_str() {
+                "width" => settings.width = json_to_count(key, value)? as u32,
+                "height" => settings.height = json_to_count(key, value)? as u32,
+                "fov" => settings.fov = json_to_f64(key, value)?.to_radians(),
+                "samples_per_pixel" => {
+                    settings.samples_per_pixel = json_to_count(key, value)?.max(1) as u32
+                }
+                "supersampling" => {
+                    settings.supersampling = json_to_count(key, value)?.max(1) as u32
+                }
+                "filter" => settings.filter = json_to_filter(value)?,
+                "integrator" => settings.integrator = IntegratorKind::from_json(value)?,
+                "sampler" => {
+                    let message = "'sampler' must be independent, stratified or sobol";
+                    settings.sampler = value
+                        .as_str()
+                        .and_then(SamplerKind::from_name)
+                        .ok_or_else(|| JsonError::new(message))?
+                }
+                "pixel_order" => {
+                    let message = "'pixel_order' must be scanline, morton or hilbert";
+                    settings.pixel_order = value
+                        .as_str()
+                        .and_then(PixelOrder::from_name)
+                        .ok_or_else(|| JsonError::new(message))?
+                }
+                "max_depth" => settings.max_depth = json_to_count(key, value)? as u32,
+                "path_limits" => settings.path_limits = PathLimits::from_json(value)?,
+                "seed" => settings.seed = json_to_count(key, value)?,
+                "background" => settings.background = json_to_color(key, value)?,
+                "threads" => settings.threads = json_to_count(key, value)? as usize,
+                "memory_budget_mb" => {
+                    settings.memory_budget = (json_to_count(key, value)? as usize) << 20
+                }
+                "texture_budget_mb" => {
+                    settings.texture_budget = (json_to_count(key, value)? as usize) << 20
+                }
+                "half_precision" => {
+                    settings.half_precision = value
+                        .as_bool()
+                        .ok_or_else(|| JsonError::new("'half_precision' must be a boolean"))?
+                }
+                "working_space" => {
+                    let message = "'working_space' must be linear-srgb or acescg";
+                    settings.working_space = value
+                        .as_str()
+                        .and_then(WorkingSpace::from_name)
+                        .ok_or_else(|| JsonError::new(message))?
+                }
+                "denoise" => settings.read_denoise(value)?,
+                _ => return Err(JsonError::new(&format!("unknown render setting '{}'", key))),
+            }
+        }
+        check_image_size(settings.width, settings.height)
+            .map_err(|error| JsonError::new(&error.message))?;
+        Ok(settings)
+    }
+
+    /// Denoise settings as a JSON object: the filter parameters and the filtered `passes`
+    fn denoise_to_json(&self) -> Json {
+        let filter = &self.denoise;
+        Json::Object(vec![
+            ("radius".to_string(), Json::Number(filter.radius as f64)),
+            (
+                "iterations".to_string(),
+                Json::Number(filter.iterations as f64),
+            ),
+            (
+                "sigma_spatial".to_string(),
+                Json::Number(filter.sigma_spatial),
+            ),
+            ("sigma_color".to_string(), Json::Number(filter.sigma_color)),
+            (
+                "sigma_albedo".to_string(),
+                Json::Number(filter.sigma_albedo),
+            ),
+            (
+                "sigma_normal".to_string(),
+                Json::Number(filter.sigma_normal),
+            ),
+            ("sigma_depth".to_string(), Json::Number(filter.sigma_depth)),
+            (
+                "passes".to_string(),
+                Json::Array(
+                    self.denoise_passes
+                        .iter()
+                        .map(|name| Json::String(name.clone()))
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+
+    /// Read the denoise settings from a JSON object, missing keys keep their values
+    fn read_denoise(&mut self, json: &Json) -> Result<(), JsonError> {
+        let Json::Object(entries) = json else {
+            return Err(JsonError::new("'denoise' must be a JSON object"));
+        };
+        let filter = &mut self.denoise;
+        for (key, value) in entries {
+            match key.as_str() {
+                "radius" => filter.radius = json_to_count(key, value)? as u32,
+                "iterations" => filter.iterations = json_to_count(key, value)?.max(1) as u32,
+                "sigma_spatial" => filter.sigma_spatial = json_to_sigma(key, value)?,
+                "sigma_color" => filter.sigma_color = json_to_sigma(key, value)?,
+                "sigma_albedo" => filter.sigma_albedo = json_to_sigma(key, value)?,
+                "sigma_normal" => filter.sigma_normal = json_to_sigma(key, value)?,
+                "sigma_depth" => filter.sigma_depth = json_to_sigma(key, value)?,
+                "passes" => {
+                    let names = value
+                        .as_array()
+                        .ok_or_else(|| JsonError::new("'passes' must be an array"))?;
+                    self.denoise_passes = Vec::new();
+                    for name in names {
+                        match name.as_str() {
+                            Some(name) if PASS_NAMES.contains(&name) => {
+                                self.denoise_passes.push(name.to_string())
+                            }
+                            _ => {
+                                return Err(JsonError::new(&format!(
+                                    "'passes' must hold names among {}",
+                                    PASS_NAMES.join(", ")
+                                )))
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    return Err(JsonError::new(&format!(
+                        "unknown denoise setting '{}'",
+                        key
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Load settings from a JSON file
+    pub fn load(filename: &str) -> Result<RenderSettings, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(filename)?;
+        Ok(RenderSettings::from_json(&Json::parse(&text)?)?)
+    }
+
+    /// Save settings to a JSON file
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        std::fs::write(filename, self.to_json().to_pretty_string() + "\n")
+    }
+}
+
+/// Color as a `[r, g, b]` array
+pub fn color_to_json(color: Color) -> Json {
+    Json::Array(color.to_array().map(Json::Number).to_vec())
+}
+
+/// Vector as an `[x, y, z]` array
+pub fn vector_to_json(vector: Vector3<f64>) -> Json {
+    Json::Array(vector.iter().map(|c| Json::Number(*c)).collect())
+}
+
+/// Read a pixel filter from a JSON object with its `type` and optionally its `radius` in
+/// pixels, the default radius of the type otherwise
+fn json_to_filter(json: &Json) -> Result<PixelFilter, JsonError> {
+    let Json::Object(entries) = json else {
+        return Err(JsonError::new("'filter' must be a JSON object"));
+    };
+    let mut kind = FilterKind::Box;
+    let mut radius = None;
+    for (key, value) in entries {
+        match key.as_str() {
+            "type" => {
+                kind = value
+                    .as_str()
+                    .and_then(FilterKind::from_name)
+                    .ok_or_else(|| {
+                        JsonError::new(
+                            "'type' must be box, tent, gaussian, mitchell or blackman-harris",
+                        )
+                    })?
+            }
+            "radius" => match json_to_f64(key, value)? {
+                r if r > 0.0 => radius = Some(r),
+                _ => return Err(JsonError::new("'radius' must be positive")),
+            },
+            _ => return Err(JsonError::new(&format!("unknown filter setting '{}'", key))),
+        }
+    }
+    Ok(PixelFilter::new(
+        kind,
+        radius.unwrap_or(kind.default_radius()),
+    ))
+}
+
+/// Read a standard deviation of the denoise filter, which must be positive
+fn json_to_sigma(key: &str, value: &Json) -> Result<f64, JsonError> {
+    let sigma = json_to_f64(key, value)?;
+    if sigma <= 0.0 {
+        return Err(JsonError::new(&format!("'{}' must be positive", key)));
+    }
+    Ok(sigma)
+}
+
+/// Read a number, naming the key in the error
+pub fn json_to_f64(key: &str, value: &Json) -> Result<f64, JsonError> {
+    value
+        .as_f64()
+        .ok_or_else(|| JsonError::new(&format!("'{}' must be a number", key)))
+}
+
+/// Read a non-negative integer, naming the key in the error
+pub fn json_to_count(key: &str, value: &Json) -> Result<u64, JsonError> {
+    match value.as_f64() {
+        Some(n) if n >= 0.0 && n.fract() == 0.0 && n <= u32::MAX as f64 => Ok(n as u64),
+        _ => Err(JsonError::new(&format!(
+            "'{}' must be a non-negative integer",
+            key
+        ))),
+    }
+}
+
+/// Read a `[r, g, b]` color, naming the key in the error
+pub fn json_to_color(key: &str, value: &Json) -> Result<Color, JsonError> {
+    match value.as_array() {
+        Some([r, g, b]) => Ok(Color::new(
+            json_to_f64(key, r)?,
+            json_to_f64(key, g)?,
+            json_to_f64(key, b)?,
+        )),
+        _ => Err(JsonError::new(&format!(
+            "'{}' must be an array of 3 numbers",
+            key
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<RenderSettings, JsonError> {
+        RenderSettings::from_json(&Json::parse(text).unwrap())
+    }
+
+    #[test]
+    fn image_sizes_are_checked() {
+        let settings = parse(r#"{"width": 65536, "height": 65536}"#).unwrap();
+        assert_eq!((settings.width, settings.height), (65536, 65536));
+        let cases = [
+            (
+                r#"{"width": 4294967295, "height": 4294967295}"#,
+                "the image size 4294967295x4294967295 is larger than 65536 pixels on a side",
+            ),
+            (r#"{"width": 0}"#, "the image size 0x768 is empty"),
+            (
+                r#"{"width": 4294967296}"#,
+                "'width' must be a non-negative integer",
+            ),
+            (
+                r#"{"height": -1}"#,
+                "'height' must be a non-negative integer",
+            ),
+            (
+                r#"{"height": 1e300}"#,
+                "'height' must be a non-negative integer",
+            ),
+        ];
+        for (text, message) in cases {
+            assert_eq!(parse(text), Err(JsonError::new(message)), "{}", text);
+        }
+    }
+}
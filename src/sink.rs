@@ -0,0 +1,20 @@
+use crate::image::Image;
+use crate::image::Rect;
+
+/// Receiver of the pixels of a render as they are done, for embedders showing the image
+/// while it renders (a window, a web page) without the renderer knowing about them
+///
+/// The methods are called from the render threads, in no particular order of the tiles
+pub trait RenderSink: Sync {
+    /// Pixels of a finished part of the image
+    ///
+    /// ### Arguments
+    ///
+    /// * `rect` - The part of the image the pixels cover
+    /// * `pixels` - The RGB pixels of the part, row by row from its top left corner
+    ///
+    fn on_tile(&self, rect: Rect, pixels: &[u8]);
+
+    /// The whole image, once every tile was sent
+    fn on_complete(&self, _image: &Image) {}
+}
@@ -0,0 +1,290 @@
+use crate::color::Color;
+use crate::colorspace::blackbody;
+use crate::colorspace::from_linear_srgb;
+use crate::colorspace::WorkingSpace;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::settings::color_to_json;
+use crate::settings::json_to_color;
+use crate::settings::json_to_f64;
+
+use nalgebra::Matrix3;
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+
+/// Angular diameter of the sun seen from the earth, in radians (0.53°)
+pub const SUN_ANGULAR_DIAMETER: f64 = 0.00925;
+
+/// Factor the luminance of the sky model (in kcd/m²) is scaled by, so a clear sky at noon
+/// lights the scene about as much as a light of intensity 1
+const SKY_SCALE: f64 = 0.1;
+
+/// Temperature of the surface of the sun in kelvins, the color of the sun disk
+const SUN_TEMPERATURE: f64 = 5778.0;
+
+/// Analytic emissive dome around the scene, in place of an environment map
+#[derive(Clone, Debug, PartialEq)]
+pub enum Dome {
+    /// Same radiance from every direction, in the working space
+    Color(Color),
+    /// Color of a black body at a temperature in kelvins, of unit luminance
+    Blackbody(f64),
+    /// Clear sky lit by the sun
+    Sky(PhysicalSky),
+}
+
+impl Dome {
+    /// Name of the kind of dome: `color`, `blackbody` or `sky`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dome::Color(_) => "color",
+            Dome::Blackbody(_) => "blackbody",
+            Dome::Sky(_) => "sky",
+        }
+    }
+
+    /// Dome as JSON (see `dome_from_json`), the angles in degrees
+    pub fn to_json(&self) -> Json {
+        let number = |key: &str, value: f64| (key.to_string(), Json::Number(value));
+        let kind = ("type".to_string(), Json::String(self.name().to_string()));
+        match self {
+            Dome::Color(color) => color_to_json(*color),
+            Dome::Blackbody(kelvin) => Json::Object(vec![kind, number("temperature", *kelvin)]),
+            Dome::Sky(sky) => {
+                let (elevation, azimuth) = sky.sun_angles();
+                Json::Object(vec![
+                    kind,
+                    number("sun_elevation", elevation.to_degrees()),
+                    number("sun_azimuth", azimuth.to_degrees()),
+                    number("turbidity", sky.turbidity),
+                    number("sun_intensity", sky.sun_intensity),
+                ])
+            }
+        }
+    }
+
+    /// Radiance of the dome seen along a direction
+    ///
+    /// ### Arguments
+    ///
+    /// * `direction` - The direction leaving the scene, in the frame of the dome
+    /// * `working_space` - The working space the radiance is given in
+    ///
+    /// ### Returns
+    ///
+    /// Color - The radiance seen along the direction
+    ///
+    pub fn radiance(&self, direction: Vector3<f64>, working_space: WorkingSpace) -> Color {
+        match self {
+            Dome::Color(color) => *color,
+            Dome::Blackbody(kelvin) => from_linear_srgb(working_space, blackbody(*kelvin)),
+            Dome::Sky(sky) => from_linear_srgb(working_space, sky.radiance(direction)),
+        }
+    }
+
+    /// Direction towards the sun in the frame of the dome and the cosine of its angular
+    /// radius, `None` for domes without a sun
+    pub fn sun(&self) -> Option<(Vector3<f64>, f64)> {
+        match self {
+            Dome::Sky(sky) => Some((sky.sun_direction, sky.cos_sun_radius())),
+            _ => None,
+        }
+    }
+}
+
+/// Clear sky of the Preetham, Shirley and Smits model ("A Practical Analytic Model for
+/// Daylight", 1999) with the disk of the sun
+///
+/// The model gives the luminance and chromaticity of the sky from the position of the sun
+/// and the turbidity of the air: 2 for a very clear sky, 3 for a clear one, up to 10 for a
+/// hazy one. Directions below the horizon see the sky at the horizon
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhysicalSky {
+    /// Direction towards the sun, +Y is up
+    pub sun_direction: Vector3<f64>,
+    pub turbidity: f64,
+    /// Irradiance of the sun on a surface facing it, in the scale of the light intensities
+    /// (a directional light of the same intensity lights the scene the same)
+    pub sun_intensity: f64,
+}
+
+impl PhysicalSky {
+    /// Clear sky with the sun at the given position
+    ///
+    /// ### Arguments
+    ///
+    /// * `elevation` - The angle of the sun above the horizon in radians
+    /// * `azimuth` - The angle of the sun around the vertical axis in radians, 0 in front
+    ///   of the default camera (-Z), a quarter turn to its right (+X)
+    ///
+    pub fn new(elevation: f64, azimuth: f64) -> PhysicalSky {
+        PhysicalSky {
+            sun_direction: Vector3::new(
+                elevation.cos() * azimuth.sin(),
+                elevation.sin(),
+                -elevation.cos() * azimuth.cos(),
+            ),
+            turbidity: 3.0,
+            sun_intensity: 1.0,
+        }
+    }
+
+    pub fn with_turbidity(mut self, turbidity: f64) -> PhysicalSky {
+        self.turbidity = turbidity;
+        self
+    }
+
+    pub fn with_sun_intensity(mut self, sun_intensity: f64) -> PhysicalSky {
+        self.sun_intensity = sun_intensity;
+        self
+    }
+
+    /// Elevation and azimuth of the sun in radians (see `new`)
+    pub fn sun_angles(&self) -> (f64, f64) {
+        let d = self.sun_direction;
+        (d.y.clamp(-1.0, 1.0).asin(), d.x.atan2(-d.z))
+    }
+
+    /// Cosine of the angular radius of the sun disk
+    pub fn cos_sun_radius(&self) -> f64 {
+        (SUN_ANGULAR_DIAMETER / 2.0).cos()
+    }
+
+    /// Radiance of the sky along a direction, the sun disk included, in linear sRGB
+    pub fn radiance(&self, direction: Vector3<f64>) -> Color {
+        let sky = self.sky_radiance(direction);
+        let cos_radius = self.cos_sun_radius();
+        if direction.y < 0.0 || direction.dot(&self.sun_direction) < cos_radius {
+            return sky;
+        }
+        // the irradiance of the disk, spread over its solid angle; the surfaces divide the
+        // radiance of the environment by pi (see `light::EnvironmentLight`)
+        let solid_angle = 2.0 * PI * (1.0 - cos_radius);
+        sky + blackbody(SUN_TEMPERATURE) * (self.sun_intensity * PI / solid_angle)
+    }
+
+    /// Radiance of the sky without the sun, in linear sRGB
+    fn sky_radiance(&self, direction: Vector3<f64>) -> Color {
+        let t = self.turbidity;
+        let sun = self.sun_direction;
+        let theta_sun = sun.y.clamp(-1.0, 1.0).acos().min(PI / 2.0);
+        // below the horizon the sky is the one at the horizon
+        let cos_theta = direction.y.max(0.001);
+        let gamma = direction.dot(&sun).clamp(-1.0, 1.0).acos();
+        // distribution of the sky relative to its zenith
+        let perez = |[a, b, c, d, e]: [f64; 5], cos_theta: f64, gamma: f64| {
+            (1.0 + a * (b / cos_theta).exp())
+                * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+        };
+        let relative = |coefficients: [f64; 5]| {
+            perez(coefficients, cos_theta, gamma) / perez(coefficients, 1.0, theta_sun)
+        };
+        let luminance_coefficients = [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ];
+        let x_coefficients = [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ];
+        let y_coefficients = [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ];
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let (s, s2, s3) = (theta_sun, theta_sun.powi(2), theta_sun.powi(3));
+        let zenith_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let zenith_y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+        let luminance = zenith_luminance * relative(luminance_coefficients) * SKY_SCALE;
+        let x = zenith_x * relative(x_coefficients);
+        let y = zenith_y * relative(y_coefficients);
+        let xyz = Vector3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let xyz_to_srgb = Matrix3::new(
+            3.2406, -1.5372, -0.4986, //
+            -0.9689, 1.8758, 0.0415, //
+            0.0557, -0.2040, 1.0570,
+        );
+        Color::from(xyz_to_srgb * xyz).map(|c| c.max(0.0))
+    }
+}
+
+/// Read a dome from JSON: a `[r, g, b]` color, or an object with the `type` of the dome
+///
+/// * `color` - `color` as `[r, g, b]`
+/// * `blackbody` - `temperature` in kelvins
+/// * `sky` - `sun_elevation` and `sun_azimuth` in degrees (see `PhysicalSky::new`),
+///   `turbidity` and `sun_intensity`
+pub fn dome_from_json(json: &Json) -> Result<Dome, JsonError> {
+    let Json::Object(entries) = json else {
+        return Ok(Dome::Color(json_to_color("dome", json)?));
+    };
+    let kind = json
+        .get("type")
+        .and_then(Json::as_str)
+        .ok_or_else(|| JsonError::new("a dome must have a 'type' string"))?;
+    let unknown =
+        |key: &str| JsonError::new(&format!("unknown property '{}' of a {} dome", key, kind));
+    match kind {
+        "color" => {
+            let mut color = Color::WHITE;
+            for (key, value) in entries {
+                match key.as_str() {
+                    "type" => {}
+                    "color" => color = json_to_color(key, value)?,
+                    _ => return Err(unknown(key)),
+                }
+            }
+            Ok(Dome::Color(color))
+        }
+        "blackbody" => {
+            let mut temperature = 6500.0;
+            for (key, value) in entries {
+                match key.as_str() {
+                    "type" => {}
+                    "temperature" => temperature = json_to_f64(key, value)?,
+                    _ => return Err(unknown(key)),
+                }
+            }
+            Ok(Dome::Blackbody(temperature))
+        }
+        "sky" => {
+            let (mut elevation, mut azimuth) = (45.0_f64, 0.0_f64);
+            let (mut turbidity, mut sun_intensity) = (3.0, 1.0);
+            for (key, value) in entries {
+                match key.as_str() {
+                    "type" => {}
+                    "sun_elevation" => elevation = json_to_f64(key, value)?,
+                    "sun_azimuth" => azimuth = json_to_f64(key, value)?,
+                    "turbidity" => turbidity = json_to_f64(key, value)?,
+                    "sun_intensity" => sun_intensity = json_to_f64(key, value)?,
+                    _ => return Err(unknown(key)),
+                }
+            }
+            if !(1.7..=10.0).contains(&turbidity) {
+                return Err(JsonError::new("'turbidity' must be between 1.7 and 10"));
+            }
+            let sky = PhysicalSky::new(elevation.to_radians(), azimuth.to_radians())
+                .with_turbidity(turbidity)
+                .with_sun_intensity(sun_intensity);
+            Ok(Dome::Sky(sky))
+        }
+        _ => Err(JsonError::new(&format!(
+            "unknown dome type '{}', expected color, blackbody or sky",
+            kind
+        ))),
+    }
+}
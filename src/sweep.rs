@@ -0,0 +1,119 @@
+use crate::colorspace::ColorPipeline;
+use crate::image::contact_sheet;
+use crate::image::Image;
+use crate::parameter::set_parameter;
+use crate::parameter::ParameterError;
+use crate::render::render_with_hits;
+use crate::render::PrimaryHits;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+/// Values taken by one parameter across a sweep
+#[derive(Clone, Debug, PartialEq)]
+pub struct SweepAxis {
+    /// Path of the parameter, as accepted by `set_parameter`
+    pub parameter: String,
+    pub values: Vec<f64>,
+}
+
+impl SweepAxis {
+    pub fn new(parameter: &str, values: Vec<f64>) -> SweepAxis {
+        SweepAxis {
+            parameter: parameter.to_string(),
+            values,
+        }
+    }
+
+    /// Parse an axis written as `<parameter>=<value>,<value>,...`
+    pub fn parse(text: &str) -> Result<SweepAxis, ParameterError> {
+        let invalid = || {
+            ParameterError::new(&format!(
+                "invalid sweep `{}`, expected <parameter>=<value>,<value>,...",
+                text
+            ))
+        };
+        let (parameter, values) = text.split_once('=').ok_or_else(invalid)?;
+        let values = values
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| invalid())?;
+        Ok(SweepAxis::new(parameter.trim(), values))
+    }
+
+    fn label(&self, k: usize) -> String {
+        // the last part of the path is enough to tell the axes apart on the sheet
+        let name = self.parameter.rsplit('.').next().unwrap_or(&self.parameter);
+        format!("{}={}", name, self.values[k])
+    }
+}
+
+/// Render the scene once for every combination of the values of one or two parameters
+/// and tile the renders into a labeled contact sheet
+///
+/// ### Arguments
+///
+/// * `scene` - The scene, left unchanged
+/// * `settings` - The render settings, their size is the size of every render
+/// * `rows` - The parameter varied from one row of the sheet to the next (or along the
+///   only row when `columns` is `None`)
+/// * `columns` - The parameter varied from one column to the next
+/// * `pipeline` - The color pipeline used to encode the pixels
+///
+/// ### Returns
+///
+/// `Result<Image, ParameterError>` - The contact sheet, or an error if a parameter path
+/// doesn't name a parameter
+///
+pub fn render_sweep(
+    scene: &Scene,
+    settings: &RenderSettings,
+    rows: &SweepAxis,
+    columns: Option<&SweepAxis>,
+    pipeline: &ColorPipeline,
+) -> Result<Image, ParameterError> {
+    if rows.values.is_empty() || columns.is_some_and(|columns| columns.values.is_empty()) {
+        return Err(ParameterError::new(
+            "a sweep needs at least one value per parameter",
+        ));
+    }
+    // sweeps of material and light parameters shade the same camera ray hits in every cell
+    let mut hits = PrimaryHits::trace(settings, scene);
+    let mut cells = Vec::new();
+    let mut labels = Vec::new();
+    let row_count = if columns.is_some() {
+        rows.values.len()
+    } else {
+        1
+    };
+    let column_count = columns.map_or(rows.values.len(), |columns| columns.values.len());
+    for row in 0..row_count {
+        for column in 0..column_count {
+            let mut cell_scene = scene.clone();
+            let mut cell_settings = settings.clone();
+            let mut label = Vec::new();
+            let assignments = match columns {
+                Some(columns) => vec![(rows, row), (columns, column)],
+                None => vec![(rows, column)],
+            };
+            for (axis, k) in assignments {
+                set_parameter(
+                    &mut cell_scene,
+                    &mut cell_settings,
+                    &axis.parameter,
+                    axis.values[k],
+                )?;
+                label.push(axis.label(k));
+            }
+            let data = render_with_hits(&cell_settings, &cell_scene, pipeline, &mut hits);
+            cells.push(Image {
+                width: settings.width,
+                height: settings.height,
+                data,
+            });
+            labels.push(label.join(" "));
+        }
+    }
+    let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+    Ok(contact_sheet(&cells, column_count, &labels))
+}
@@ -0,0 +1,87 @@
+use crate::color::Color;
+
+/// History of an animation render, blended into every new frame so few samples per frame
+/// converge over a slowly changing shot
+///
+/// The previous result is reprojected along the motion vectors of the frame (see
+/// `motion::motion_vectors`) and clamped to the range of colors around each pixel of the
+/// new frame, so changes of the shot replace the history instead of leaving ghosts behind
+pub struct TemporalAccumulator {
+    pub width: u32,
+    pub height: u32,
+    /// Weight of the history in the blended frame, in the range `[0, 1)`
+    pub history_weight: f64,
+    history: Option<Vec<Color>>,
+}
+
+impl TemporalAccumulator {
+    pub fn new(width: u32, height: u32, history_weight: f64) -> TemporalAccumulator {
+        TemporalAccumulator {
+            width,
+            height,
+            history_weight,
+            history: None,
+        }
+    }
+
+    /// Blend a new frame with the history and keep the result as the history of the next
+    /// one
+    ///
+    /// ### Arguments
+    ///
+    /// * `frame` - The radiance of the new frame, row by row from the top left corner
+    /// * `motion` - The motion vectors of the new frame, `None` for a still camera and
+    ///   scene
+    ///
+    /// ### Returns
+    ///
+    /// `Vec<Color>` - The blended frame (the new frame itself for the first one)
+    ///
+    pub fn accumulate(&mut self, frame: &[Color], motion: Option<&[[f64; 2]]>) -> Vec<Color> {
+        let Some(history) = &self.history else {
+            self.history = Some(frame.to_vec());
+            return frame.to_vec();
+        };
+        let (width, height) = (self.width as i64, self.height as i64);
+        let mut blended = Vec::with_capacity(frame.len());
+        for j in 0..height {
+            for i in 0..width {
+                let p = (j * width + i) as usize;
+                // the pixel of the previous frame the surface was seen at
+                let [dx, dy] = motion.map_or([0.0, 0.0], |motion| motion[p]);
+                let (x, y) = (
+                    (i as f64 - dx).round() as i64,
+                    (j as f64 - dy).round() as i64,
+                );
+                if x < 0 || y < 0 || x >= width || y >= height {
+                    blended.push(frame[p]);
+                    continue;
+                }
+                let previous = history[(y * width + x) as usize];
+                let (low, high) = neighborhood_range(frame, width, height, i, j);
+                let clamped = Color::new(
+                    previous.r.clamp(low.r, high.r),
+                    previous.g.clamp(low.g, high.g),
+                    previous.b.clamp(low.b, high.b),
+                );
+                blended.push(frame[p].lerp(clamped, self.history_weight));
+            }
+        }
+        self.history = Some(blended.clone());
+        blended
+    }
+}
+
+/// Smallest and largest value of every channel over the 3x3 pixels around a pixel
+fn neighborhood_range(frame: &[Color], width: i64, height: i64, i: i64, j: i64) -> (Color, Color) {
+    let mut low = Color::gray(f64::INFINITY);
+    let mut high = Color::gray(f64::NEG_INFINITY);
+    for y in (j - 1).max(0)..=(j + 1).min(height - 1) {
+        for x in (i - 1).max(0)..=(i + 1).min(width - 1) {
+            let c = frame[(y * width + x) as usize];
+            low = Color::new(low.r.min(c.r), low.g.min(c.g), low.b.min(c.b));
+            high = Color::new(high.r.max(c.r), high.g.max(c.g), high.b.max(c.b));
+        }
+    }
+    (low, high)
+}
@@ -0,0 +1,885 @@
+use crate::color::Color;
+use crate::colorspace::TextureSpace;
+use crate::colorspace::WorkingSpace;
+use crate::half::HalfColor;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::random::Rng;
+use crate::render::MISSING_TEXTURE_COLOR;
+use crate::settings::color_to_json;
+use crate::settings::json_to_color;
+use crate::settings::json_to_count;
+use crate::settings::json_to_f64;
+use crate::texturenodes::node_from_json;
+
+use nalgebra::Vector3;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+/// Image texture, its texels as colors row by row from the top left corner
+///
+/// Texel values are used as they are stored, like the default color pipeline writes them,
+/// unless the texture is decoded from another color space (see `TextureImage::decoded`)
+pub struct TextureImage {
+    pub width: u32,
+    pub height: u32,
+    texels: Texels,
+}
+
+/// Texels of a texture image, in full or half precision (see `TextureImage::to_half`)
+enum Texels {
+    Full(Vec<Color>),
+    Half(Vec<HalfColor>),
+}
+
+impl TextureImage {
+    pub fn new(width: u32, height: u32, texels: Vec<Color>) -> TextureImage {
+        TextureImage {
+            width,
+            height,
+            texels: Texels::Full(texels),
+        }
+    }
+
+    /// Texture with its texels stored in half precision, a quarter of the memory
+    ///
+    /// The texels of 8-bit images move by less than a tenth of a level
+    pub fn to_half(self) -> TextureImage {
+        let texels = match self.texels {
+            Texels::Full(texels) => texels.into_iter().map(HalfColor::new).collect(),
+            Texels::Half(texels) => texels,
+        };
+        TextureImage {
+            texels: Texels::Half(texels),
+            ..self
+        }
+    }
+
+    /// Texture with its texels decoded from the color space they are stored in to the
+    /// working space (see `TextureSpace::decode`)
+    pub fn decoded(self, space: TextureSpace, working_space: WorkingSpace) -> TextureImage {
+        if space == TextureSpace::Linear {
+            return self;
+        }
+        let texels = (0..self.width as usize * self.height as usize)
+            .map(|index| space.decode(self.texel(index), working_space))
+            .collect();
+        TextureImage::new(self.width, self.height, texels)
+    }
+
+    /// Color of a texel, from its index row by row
+    pub fn texel(&self, index: usize) -> Color {
+        match &self.texels {
+            Texels::Full(texels) => texels[index],
+            Texels::Half(texels) => texels[index].color(),
+        }
+    }
+
+    /// Load a PNG image (grayscale or RGB, with or without alpha, the alpha is ignored)
+    pub fn load(filename: &str) -> Result<TextureImage, Box<dyn std::error::Error>> {
+        TextureImage::decode(&std::fs::read(filename)?)
+    }
+
+    /// Decode a PNG image held in memory, see `TextureImage::load`
+    ///
+    /// Images whose pixels would take more than the default limits of the PNG decoder are
+    /// refused before anything is allocated
+    pub fn decode(bytes: &[u8]) -> Result<TextureImage, Box<dyn std::error::Error>> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data)?;
+        let channels = info.color_type.samples();
+        let texels = data[..info.buffer_size()]
+            .chunks(channels)
+            .map(|texel| {
+                let value = |c: usize| texel[c] as f64 / 255.0;
+                match channels {
+                    1 | 2 => Color::gray(value(0)),
+                    _ => Color::new(value(0), value(1), value(2)),
+                }
+            })
+            .collect();
+        Ok(TextureImage::new(info.width, info.height, texels))
+    }
+
+    /// Bilinearly filtered color at the given UV coordinates, `u` wrapping around and `v`
+    /// going from the bottom of the image (`v = 0`) to its top (`v = 1`)
+    pub fn sample(&self, u: f64, v: f64) -> Color {
+        let x = u.rem_euclid(1.0) * self.width as f64 - 0.5;
+        let y = (1.0 - v.clamp(0.0, 1.0)) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let texel = |x: f64, y: f64| {
+            let i = (x as i64).rem_euclid(self.width as i64) as usize;
+            let j = (y as i64).clamp(0, self.height as i64 - 1) as usize;
+            self.texel(j * self.width as usize + i)
+        };
+        let top = texel(x0, y0).lerp(texel(x0 + 1.0, y0), tx);
+        let bottom = texel(x0, y0 + 1.0).lerp(texel(x0 + 1.0, y0 + 1.0), tx);
+        top.lerp(bottom, ty)
+    }
+
+    /// Memory held by the texels in bytes
+    pub fn size_in_bytes(&self) -> usize {
+        match &self.texels {
+            Texels::Full(texels) => texels.len() * std::mem::size_of::<Color>(),
+            Texels::Half(texels) => texels.len() * std::mem::size_of::<HalfColor>(),
+        }
+    }
+}
+
+/// Transform of the UV coordinates of a material before its textures are looked up, to tile,
+/// shift or turn them without editing the texture
+///
+/// The coordinates are scaled, then rotated counterclockwise around the UV origin, then
+/// offset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvTransform {
+    /// How many times the textures repeat along `u` and `v`
+    pub scale: [f64; 2],
+    pub offset: [f64; 2],
+    /// Rotation in radians
+    pub rotation: f64,
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        UvTransform {
+            scale: [1.0, 1.0],
+            offset: [0.0, 0.0],
+            rotation: 0.0,
+        }
+    }
+}
+
+impl UvTransform {
+    /// Whether the transform leaves the coordinates as they are
+    pub fn is_identity(&self) -> bool {
+        *self == UvTransform::default()
+    }
+
+    /// Transformed UV coordinates
+    pub fn apply(&self, (u, v): (f64, f64)) -> (f64, f64) {
+        if self.is_identity() {
+            return (u, v);
+        }
+        let (u, v) = (u * self.scale[0], v * self.scale[1]);
+        let (sin, cos) = self.rotation.sin_cos();
+        (
+            u * cos - v * sin + self.offset[0],
+            u * sin + v * cos + self.offset[1],
+        )
+    }
+}
+
+/// Token replaced by the tile number in the path of a UDIM texture, e.g.
+/// `wood.<UDIM>.png` for the tiles `wood.1001.png`, `wood.1002.png`, ...
+pub const UDIM_TOKEN: &str = "<UDIM>";
+
+/// File and coordinates inside the file of a texture lookup at the given UV coordinates
+///
+/// The tiles of a UDIM texture are numbered from 1001 at the origin of the UV space, ten
+/// per row; other textures repeat over every tile
+///
+/// ### Arguments
+///
+/// * `path` - The path of the texture, with `UDIM_TOKEN` in it for UDIM textures
+/// * `u`, `v` - The UV coordinates, not negative
+///
+/// ### Returns
+///
+/// `(String, f64, f64)` - The path of the file and the UV coordinates inside it
+///
+pub fn udim_tile(path: &str, u: f64, v: f64) -> (String, f64, f64) {
+    // a coordinate on the border between two tiles belongs to the lower one, so the top
+    // edge of the UV space (`v = 1` at the top pole of a sphere) stays in the first row
+    let tile = |x: f64| (x.ceil() - 1.0).max(0.0);
+    let (column, row) = (tile(u).min(9.0), tile(v));
+    let (u, v) = (u - column, v - row);
+    if !path.contains(UDIM_TOKEN) {
+        return (path.to_string(), u, v);
+    }
+    let number = 1001 + column as u32 + 10 * row as u32;
+    (path.replace(UDIM_TOKEN, &number.to_string()), u, v)
+}
+
+/// How the texels of a texture file are turned into colors and stored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextureFormat {
+    /// Color space of the file
+    pub space: TextureSpace,
+    /// Working space of the render the colors are converted to
+    pub working_space: WorkingSpace,
+    /// Whether the texels are stored in half precision (see `TextureImage::to_half`)
+    pub half_precision: bool,
+}
+
+impl TextureFormat {
+    pub fn new(
+        space: TextureSpace,
+        working_space: WorkingSpace,
+        half_precision: bool,
+    ) -> TextureFormat {
+        TextureFormat {
+            space,
+            working_space,
+            half_precision,
+        }
+    }
+
+    pub fn with_space(mut self, space: TextureSpace) -> TextureFormat {
+        self.space = space;
+        self
+    }
+}
+
+/// Textures loaded on first use and shared by every material referencing the same file,
+/// the least recently used ones are dropped when they exceed the memory budget
+///
+/// A file is cached once per format it is used with
+#[derive(Default)]
+pub struct TextureCache {
+    entries: Vec<CacheEntry>,
+    /// Incremented on every lookup, to order the entries by last use
+    clock: u64,
+}
+
+struct CacheEntry {
+    path: String,
+    format: TextureFormat,
+    /// `None` if the file failed to load, it is not tried again
+    texture: Option<Arc<TextureImage>>,
+    last_use: u64,
+}
+
+impl TextureCache {
+    pub const fn new() -> TextureCache {
+        TextureCache {
+            entries: Vec::new(),
+            clock: 0,
+        }
+    }
+
+    /// Texture image stored in the given file, loaded if it is not in the cache
+    ///
+    /// ### Arguments
+    ///
+    /// * `path` - The path of the texture file
+    /// * `budget` - The memory allowed for the cached textures in bytes (0 for no limit),
+    ///   the texture just loaded is kept even if it alone exceeds it
+    /// * `format` - The color space of the file and how its texels are stored
+    ///
+    /// ### Returns
+    ///
+    /// `Option<Arc<TextureImage>>` - The texture, `None` if the file can't be loaded (the
+    /// error is reported the first time)
+    ///
+    pub fn get(
+        &mut self,
+        path: &str,
+        budget: usize,
+        format: TextureFormat,
+    ) -> Option<Arc<TextureImage>> {
+        self.clock += 1;
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.path == path && entry.format == format)
+        {
+            entry.last_use = self.clock;
+            return entry.texture.clone();
+        }
+        let texture = match TextureImage::load(path) {
+            Ok(texture) => {
+                let texture = texture.decoded(format.space, format.working_space);
+                if format.half_precision {
+                    Some(Arc::new(texture.to_half()))
+                } else {
+                    Some(Arc::new(texture))
+                }
+            }
+            Err(error) => {
+                eprintln!("failed to load the texture {}: {}", path, error);
+                None
+            }
+        };
+        self.entries.push(CacheEntry {
+            path: path.to_string(),
+            format,
+            texture: texture.clone(),
+            last_use: self.clock,
+        });
+        if budget > 0 {
+            self.evict(budget);
+        }
+        texture
+    }
+
+    /// Memory held by the cached textures in bytes
+    pub fn size_in_bytes(&self) -> usize {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.texture.as_ref())
+            .map(|texture| texture.size_in_bytes())
+            .sum()
+    }
+
+    /// Drop the least recently used textures until the cache fits in the budget
+    fn evict(&mut self, budget: usize) {
+        while self.size_in_bytes() > budget {
+            // the texture used last is never dropped, it is the one being looked up
+            let oldest = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.texture.is_some() && entry.last_use < self.clock)
+                .min_by_key(|(_, entry)| entry.last_use)
+                .map(|(index, _)| index);
+            match oldest {
+                Some(index) => {
+                    self.entries.remove(index);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// Cache shared by all the renders of the program
+static TEXTURES: Mutex<TextureCache> = Mutex::new(TextureCache::new());
+
+/// Texture image stored in the given file, through the shared cache (see `TextureCache::get`)
+pub fn texture(path: &str, budget: usize, format: TextureFormat) -> Option<Arc<TextureImage>> {
+    TEXTURES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(path, budget, format)
+}
+
+/// Color varying over a surface, e.g. the diffuse color of a material
+///
+/// Textures are shared between materials through an `Arc`, two textures are equal when
+/// they are described by the same JSON (see `texture_from_json`)
+pub trait Texture: std::fmt::Debug + Send + Sync {
+    /// Color at a point of a surface
+    ///
+    /// ### Arguments
+    ///
+    /// * `uv` - The UV coordinates of the point on the surface
+    /// * `p` - The position of the point in the scene
+    ///
+    /// ### Returns
+    ///
+    /// `Color` - The color of the texture at the point
+    ///
+    fn eval(&self, uv: (f64, f64), p: Vector3<f64>) -> Color;
+
+    /// Texture as a JSON value, read back by `texture_from_json`
+    fn to_json(&self) -> Json;
+}
+
+impl PartialEq for dyn Texture {
+    fn eq(&self, other: &dyn Texture) -> bool {
+        self.to_json() == other.to_json()
+    }
+}
+
+/// Same color everywhere
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SolidTexture {
+    pub color: Color,
+}
+
+impl Texture for SolidTexture {
+    fn eval(&self, _uv: (f64, f64), _p: Vector3<f64>) -> Color {
+        self.color
+    }
+
+    fn to_json(&self) -> Json {
+        color_to_json(self.color)
+    }
+}
+
+/// Checkerboard of `tiles` x `tiles` squares in UV space alternating between two colors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CheckerTexture {
+    pub even: Color,
+    pub odd: Color,
+    pub tiles: f64,
+}
+
+impl Texture for CheckerTexture {
+    fn eval(&self, (u, v): (f64, f64), _p: Vector3<f64>) -> Color {
+        let cell = (u * self.tiles).floor() as i64 + (v * self.tiles).floor() as i64;
+        if cell.rem_euclid(2) == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("type".to_string(), Json::String("checker".to_string())),
+            ("even".to_string(), color_to_json(self.even)),
+            ("odd".to_string(), color_to_json(self.odd)),
+            ("tiles".to_string(), Json::Number(self.tiles)),
+        ])
+    }
+}
+
+/// Solid noise blending between two colors, from the position in the scene rather than the
+/// UVs so it has no seams: a sum of `octaves` layers of Perlin noise, each twice finer and
+/// half as strong as the previous one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseTexture {
+    pub low: Color,
+    pub high: Color,
+    /// Frequency of the first layer, in cycles per scene unit
+    pub scale: f64,
+    pub octaves: u32,
+}
+
+impl Texture for NoiseTexture {
+    fn eval(&self, _uv: (f64, f64), p: Vector3<f64>) -> Color {
+        let mut sum = 0.0;
+        let (mut frequency, mut amplitude, mut total) = (self.scale, 1.0, 0.0);
+        for _ in 0..self.octaves.max(1) {
+            sum += perlin(p * frequency) * amplitude;
+            total += amplitude;
+            frequency *= 2.0;
+            amplitude *= 0.5;
+        }
+        let t = (0.5 + 0.5 * sum / total).clamp(0.0, 1.0);
+        self.low.lerp(self.high, t)
+    }
+
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("type".to_string(), Json::String("noise".to_string())),
+            ("low".to_string(), color_to_json(self.low)),
+            ("high".to_string(), color_to_json(self.high)),
+            ("scale".to_string(), Json::Number(self.scale)),
+            ("octaves".to_string(), Json::Number(self.octaves as f64)),
+        ])
+    }
+}
+
+/// Improved Perlin noise at a point, roughly in the range `[-1, 1]`
+fn perlin(p: Vector3<f64>) -> f64 {
+    static PERMUTATION: OnceLock<[u8; 512]> = OnceLock::new();
+    let table = PERMUTATION.get_or_init(|| {
+        let mut values: Vec<u8> = (0..=255).collect();
+        let mut rng = Rng::new(0);
+        for k in (1..values.len()).rev() {
+            values.swap(k, rng.next_u32() as usize % (k + 1));
+        }
+        let mut table = [0; 512];
+        for (k, entry) in table.iter_mut().enumerate() {
+            *entry = values[k % 256];
+        }
+        table
+    });
+    let hash = |x: i64, y: i64, z: i64| {
+        let x = table[(x & 255) as usize] as usize;
+        let y = table[x + (y & 255) as usize] as usize;
+        table[y + (z & 255) as usize]
+    };
+    let gradient = |hash: u8, x: f64, y: f64, z: f64| {
+        let h = hash & 15;
+        let u = if h < 8 { x } else { y };
+        let v = match h {
+            0..=3 => y,
+            12 | 14 => x,
+            _ => z,
+        };
+        (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+    };
+    let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let (x0, y0, z0) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (x, y, z) = (p.x - x0, p.y - y0, p.z - z0);
+    let (i, j, k) = (x0 as i64, y0 as i64, z0 as i64);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+    let corner = |di: i64, dj: i64, dk: i64| {
+        gradient(
+            hash(i + di, j + dj, k + dk),
+            x - di as f64,
+            y - dj as f64,
+            z - dk as f64,
+        )
+    };
+    let lerp = |t: f64, a: f64, b: f64| a + t * (b - a);
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+            lerp(u, corner(0, 1, 0), corner(1, 1, 0)),
+        ),
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+            lerp(u, corner(0, 1, 1), corner(1, 1, 1)),
+        ),
+    )
+}
+
+/// How a ramp blends the colors of two neighbouring stops
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RampInterpolation {
+    /// Color of the stop below the position, in hard steps (toon shading)
+    Constant,
+    /// Straight blend between the stops
+    #[default]
+    Linear,
+    /// Smoothstep blend, easing in and out of every stop
+    Smooth,
+}
+
+impl RampInterpolation {
+    /// Interpolation from its name: `constant`, `linear` or `smooth`
+    pub fn from_name(name: &str) -> Option<RampInterpolation> {
+        match name {
+            "constant" => Some(RampInterpolation::Constant),
+            "linear" => Some(RampInterpolation::Linear),
+            "smooth" => Some(RampInterpolation::Smooth),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RampInterpolation::Constant => "constant",
+            RampInterpolation::Linear => "linear",
+            RampInterpolation::Smooth => "smooth",
+        }
+    }
+}
+
+/// Position along a ramp of a point of a surface
+#[derive(Clone, Debug, Default)]
+pub enum RampInput {
+    U,
+    /// From the bottom (`v = 0`) to the top (`v = 1`) of a sphere, e.g. a sky gradient on
+    /// a dome around the scene
+    #[default]
+    V,
+    /// Luminance of another texture, remapping e.g. the values of a noise to colors
+    Texture(Arc<dyn Texture>),
+}
+
+/// Gradient through colors placed at positions along its input, the `u` or `v` coordinate
+/// or the luminance of another texture
+///
+/// Positions before the first stop get its color, positions after the last one the color
+/// of the last
+#[derive(Clone, Debug)]
+pub struct RampTexture {
+    /// Positions and colors of the stops, sorted by position
+    pub stops: Vec<(f64, Color)>,
+    pub interpolation: RampInterpolation,
+    pub input: RampInput,
+}
+
+impl RampTexture {
+    /// Linear ramp along `v` through the given stops, in any order
+    pub fn new(mut stops: Vec<(f64, Color)>) -> RampTexture {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        RampTexture {
+            stops,
+            interpolation: RampInterpolation::default(),
+            input: RampInput::default(),
+        }
+    }
+
+    pub fn with_interpolation(mut self, interpolation: RampInterpolation) -> RampTexture {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn with_input(mut self, input: RampInput) -> RampTexture {
+        self.input = input;
+        self
+    }
+
+    /// Color of the ramp at a position, black for a ramp without stops
+    pub fn color_at(&self, position: f64) -> Color {
+        let above = self.stops.partition_point(|&(stop, _)| stop <= position);
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first.1, last.1),
+            _ => return Color::BLACK,
+        };
+        if above == 0 {
+            return first;
+        }
+        if above == self.stops.len() {
+            return last;
+        }
+        let (start, low) = self.stops[above - 1];
+        let (end, high) = self.stops[above];
+        let t = (position - start) / (end - start);
+        match self.interpolation {
+            RampInterpolation::Constant => low,
+            RampInterpolation::Linear => low.lerp(high, t),
+            RampInterpolation::Smooth => low.lerp(high, t * t * (3.0 - 2.0 * t)),
+        }
+    }
+}
+
+impl Texture for RampTexture {
+    fn eval(&self, (u, v): (f64, f64), p: Vector3<f64>) -> Color {
+        let position = match &self.input {
+            RampInput::U => u,
+            RampInput::V => v,
+            RampInput::Texture(texture) => texture.eval((u, v), p).luminance(),
+        };
+        self.color_at(position)
+    }
+
+    fn to_json(&self) -> Json {
+        let stops = self
+            .stops
+            .iter()
+            .map(|&(position, color)| {
+                Json::Array(vec![Json::Number(position), color_to_json(color)])
+            })
+            .collect();
+        let input = match &self.input {
+            RampInput::U => Json::String("u".to_string()),
+            RampInput::V => Json::String("v".to_string()),
+            RampInput::Texture(texture) => texture.to_json(),
+        };
+        Json::Object(vec![
+            ("type".to_string(), Json::String("ramp".to_string())),
+            ("stops".to_string(), Json::Array(stops)),
+            (
+                "interpolation".to_string(),
+                Json::String(self.interpolation.name().to_string()),
+            ),
+            ("input".to_string(), input),
+        ])
+    }
+}
+
+/// PNG image mapped with the UV layout of the surface, repeating `scale` times along `u`
+/// and `v`
+///
+/// The image is loaded through the shared cache on first use and then held by the texture
+/// for as long as it lives, outside the texture budget, in full precision and decoded to
+/// linear sRGB primaries (its lookups don't get the render settings); files that fail to
+/// load are drawn magenta
+pub struct ImageTexture {
+    pub path: String,
+    pub scale: [f64; 2],
+    /// Color space of the file
+    pub space: TextureSpace,
+    image: OnceLock<Option<Arc<TextureImage>>>,
+}
+
+impl ImageTexture {
+    pub fn new(path: &str, scale: [f64; 2]) -> ImageTexture {
+        ImageTexture {
+            path: path.to_string(),
+            scale,
+            space: TextureSpace::default(),
+            image: OnceLock::new(),
+        }
+    }
+
+    pub fn with_space(mut self, space: TextureSpace) -> ImageTexture {
+        self.space = space;
+        self
+    }
+}
+
+impl std::fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ImageTexture")
+            .field("path", &self.path)
+            .field("scale", &self.scale)
+            .field("space", &self.space)
+            .finish()
+    }
+}
+
+impl Texture for ImageTexture {
+    fn eval(&self, (u, v): (f64, f64), _p: Vector3<f64>) -> Color {
+        let format = TextureFormat::default().with_space(self.space);
+        let image = self.image.get_or_init(|| texture(&self.path, 0, format));
+        match image {
+            Some(image) => image.sample(u * self.scale[0], v * self.scale[1]),
+            None => MISSING_TEXTURE_COLOR,
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let mut entries = vec![
+            ("type".to_string(), Json::String("image".to_string())),
+            ("path".to_string(), Json::String(self.path.clone())),
+            (
+                "scale".to_string(),
+                Json::Array(self.scale.map(Json::Number).to_vec()),
+            ),
+        ];
+        if self.space != TextureSpace::Linear {
+            let space = Json::String(self.space.name().to_string());
+            entries.push(("space".to_string(), space));
+        }
+        Json::Object(entries)
+    }
+}
+
+/// Read a texture from JSON: a `[r, g, b]` color for a solid texture, or an object with
+/// the `type` of the texture and its parameters, missing ones keeping their default values
+///
+/// * `checker` - `even` and `odd` colors, `tiles` per UV unit
+/// * `noise` - `low` and `high` colors, `scale` (the frequency) and `octaves`
+/// * `image` - `path` of a PNG file, relative to `directory`, `scale` as `[u, v]` and the
+///   color `space` of the file (see `json_to_texture_space`)
+/// * `ramp` - `stops` as `[position, [r, g, b]]` pairs, `interpolation` (`constant`,
+///   `linear` or `smooth`) and the `input` the positions are read from, `u`, `v` or a
+///   texture whose luminance is remapped (see `RampTexture`)
+/// * `multiply`, `mix`, `invert` and `color_correct` - nodes combining other textures (see
+///   `texturenodes::node_from_json`)
+pub fn texture_from_json(json: &Json, directory: &Path) -> Result<Arc<dyn Texture>, JsonError> {
+    let Json::Object(entries) = json else {
+        let color = json_to_color("color", json)?;
+        return Ok(Arc::new(SolidTexture { color }));
+    };
+    let kind = json
+        .get("type")
+        .and_then(Json::as_str)
+        .ok_or_else(|| JsonError::new("a texture must have a 'type' string"))?;
+    let unknown =
+        |key: &str| JsonError::new(&format!("unknown property '{}' of a {} texture", key, kind));
+    if let Some(node) = node_from_json(kind, entries, directory) {
+        return node;
+    }
+    match kind {
+        "checker" => {
+            let mut checker = CheckerTexture {
+                even: Color::WHITE,
+                odd: Color::gray(0.2),
+                tiles: 8.0,
+            };
+            for (key, value) in entries {
+                match key.as_str() {
+                    "type" => {}
+                    "even" => checker.even = json_to_color(key, value)?,
+                    "odd" => checker.odd = json_to_color(key, value)?,
+                    "tiles" => checker.tiles = json_to_f64(key, value)?,
+                    _ => return Err(unknown(key)),
+                }
+            }
+            Ok(Arc::new(checker))
+        }
+        "noise" => {
+            let mut noise = NoiseTexture {
+                low: Color::BLACK,
+                high: Color::WHITE,
+                scale: 1.0,
+                octaves: 4,
+            };
+            for (key, value) in entries {
+                match key.as_str() {
+                    "type" => {}
+                    "low" => noise.low = json_to_color(key, value)?,
+                    "high" => noise.high = json_to_color(key, value)?,
+                    "scale" => noise.scale = json_to_f64(key, value)?,
+                    "octaves" => noise.octaves = json_to_count(key, value)?.max(1) as u32,
+                    _ => return Err(unknown(key)),
+                }
+            }
+            Ok(Arc::new(noise))
+        }
+        "image" => {
+            let (mut path, mut scale, mut space) = (None, [1.0, 1.0], TextureSpace::default());
+            for (key, value) in entries {
+                match key.as_str() {
+                    "type" => {}
+                    "path" => {
+                        let relative = value
+                            .as_str()
+                            .ok_or_else(|| JsonError::new("'path' must be a string"))?;
+                        path = Some(directory.join(relative).to_string_lossy().to_string());
+                    }
+                    "scale" => match value.as_array() {
+                        Some([u, v]) => scale = [json_to_f64(key, u)?, json_to_f64(key, v)?],
+                        _ => return Err(JsonError::new("'scale' must be an array of 2 numbers")),
+                    },
+                    "space" => space = json_to_texture_space(key, value)?,
+                    _ => return Err(unknown(key)),
+                }
+            }
+            let path = path.ok_or_else(|| JsonError::new("an image texture needs a 'path'"))?;
+            Ok(Arc::new(ImageTexture::new(&path, scale).with_space(space)))
+        }
+        "ramp" => {
+            let mut ramp = RampTexture::new(Vec::new());
+            for (key, value) in entries {
+                match key.as_str() {
+                    "type" => {}
+                    "stops" => ramp.stops = ramp_stops_from_json(value)?,
+                    "interpolation" => {
+                        ramp.interpolation = value
+                            .as_str()
+                            .and_then(RampInterpolation::from_name)
+                            .ok_or_else(|| {
+                            JsonError::new("'interpolation' must be constant, linear or smooth")
+                        })?
+                    }
+                    "input" => {
+                        ramp.input = match value.as_str() {
+                            Some("u") => RampInput::U,
+                            Some("v") => RampInput::V,
+                            Some(_) => {
+                                return Err(JsonError::new("'input' must be u, v or a texture"))
+                            }
+                            None => RampInput::Texture(texture_from_json(value, directory)?),
+                        }
+                    }
+                    _ => return Err(unknown(key)),
+                }
+            }
+            if ramp.stops.is_empty() {
+                return Err(JsonError::new("a ramp texture needs 'stops'"));
+            }
+            Ok(Arc::new(ramp))
+        }
+        _ => Err(JsonError::new(&format!(
+            "unknown texture type '{}', expected checker, noise, image, ramp, multiply, mix, \
+             invert or color_correct",
+            kind
+        ))),
+    }
+}
+
+/// Stops of a ramp, `[position, [r, g, b]]` pairs sorted by position
+fn ramp_stops_from_json(json: &Json) -> Result<Vec<(f64, Color)>, JsonError> {
+    let invalid = || JsonError::new("'stops' must be an array of [position, [r, g, b]] pairs");
+    let mut stops = Vec::new();
+    for stop in json.as_array().ok_or_else(invalid)? {
+        let [position, color] = stop.as_array().ok_or_else(invalid)? else {
+            return Err(invalid());
+        };
+        stops.push((
+            json_to_f64("position", position)?,
+            json_to_color("color", color)?,
+        ));
+    }
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(stops)
+}
+
+/// Color space of a texture file from its name, `linear` (the default, for data textures
+/// and files used as they are stored) or `srgb` (for painted colors)
+pub fn json_to_texture_space(key: &str, value: &Json) -> Result<TextureSpace, JsonError> {
+    value
+        .as_str()
+        .and_then(TextureSpace::from_name)
+        .ok_or_else(|| JsonError::new(&format!("'{}' must be linear or srgb", key)))
+}
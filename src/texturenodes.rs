@@ -0,0 +1,292 @@
+use crate::color::Color;
+use crate::json::Json;
+use crate::json::JsonError;
+use crate::settings::json_to_f64;
+use crate::texture::texture_from_json;
+use crate::texture::Texture;
+
+use nalgebra::Vector3;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Product of textures, e.g. a color darkened by an ambient occlusion mask
+#[derive(Clone, Debug)]
+pub struct MultiplyTexture {
+    pub inputs: Vec<Arc<dyn Texture>>,
+}
+
+impl Texture for MultiplyTexture {
+    fn eval(&self, uv: (f64, f64), p: Vector3<f64>) -> Color {
+        self.inputs
+            .iter()
+            .fold(Color::WHITE, |product, input| product * input.eval(uv, p))
+    }
+
+    fn to_json(&self) -> Json {
+        let inputs = self.inputs.iter().map(|input| input.to_json()).collect();
+        Json::Object(vec![
+            ("type".to_string(), Json::String("multiply".to_string())),
+            ("inputs".to_string(), Json::Array(inputs)),
+        ])
+    }
+}
+
+/// Blend of two textures by the luminance of a mask, `a` where the mask is black and `b`
+/// where it is white
+#[derive(Clone, Debug)]
+pub struct MixTexture {
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+    pub mask: Arc<dyn Texture>,
+}
+
+impl Texture for MixTexture {
+    fn eval(&self, uv: (f64, f64), p: Vector3<f64>) -> Color {
+        let t = self.mask.eval(uv, p).luminance().clamp(0.0, 1.0);
+        self.a.eval(uv, p).lerp(self.b.eval(uv, p), t)
+    }
+
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("type".to_string(), Json::String("mix".to_string())),
+            ("a".to_string(), self.a.to_json()),
+            ("b".to_string(), self.b.to_json()),
+            ("mask".to_string(), self.mask.to_json()),
+        ])
+    }
+}
+
+/// Texture with its colors turned over, `1 - c` per channel, e.g. to swap the two sides of
+/// a mask
+#[derive(Clone, Debug)]
+pub struct InvertTexture {
+    pub input: Arc<dyn Texture>,
+}
+
+impl Texture for InvertTexture {
+    fn eval(&self, uv: (f64, f64), p: Vector3<f64>) -> Color {
+        self.input.eval(uv, p).map(|c| 1.0 - c)
+    }
+
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("type".to_string(), Json::String("invert".to_string())),
+            ("input".to_string(), self.input.to_json()),
+        ])
+    }
+}
+
+/// Color correction of a texture, applied in this order: `gain` multiplies the colors,
+/// `contrast` scales them away from mid gray (0.5), `saturation` away from their luminance
+/// and `gamma` raises them to `1 / gamma`
+///
+/// Negative values left by the contrast and the saturation are cut to 0
+#[derive(Clone, Debug)]
+pub struct ColorCorrectTexture {
+    pub input: Arc<dyn Texture>,
+    pub gain: f64,
+    pub contrast: f64,
+    pub saturation: f64,
+    pub gamma: f64,
+}
+
+impl ColorCorrectTexture {
+    /// Correction leaving the texture as it is
+    pub fn new(input: Arc<dyn Texture>) -> ColorCorrectTexture {
+        ColorCorrectTexture {
+            input,
+            gain: 1.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            gamma: 1.0,
+        }
+    }
+
+    /// Corrected color
+    pub fn correct(&self, color: Color) -> Color {
+        let color = (color * self.gain).map(|c| (c - 0.5) * self.contrast + 0.5);
+        let gray = Color::gray(color.luminance());
+        let color = gray.lerp(color, self.saturation).map(|c| c.max(0.0));
+        color.map(|c| c.powf(1.0 / self.gamma))
+    }
+}
+
+impl Texture for ColorCorrectTexture {
+    fn eval(&self, uv: (f64, f64), p: Vector3<f64>) -> Color {
+        self.correct(self.input.eval(uv, p))
+    }
+
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            (
+                "type".to_string(),
+                Json::String("color_correct".to_string()),
+            ),
+            ("input".to_string(), self.input.to_json()),
+            ("gain".to_string(), Json::Number(self.gain)),
+            ("contrast".to_string(), Json::Number(self.contrast)),
+            ("saturation".to_string(), Json::Number(self.saturation)),
+            ("gamma".to_string(), Json::Number(self.gamma)),
+        ])
+    }
+}
+
+/// Texture of objects moved away from where it was defined, looked up at the positions the
+/// objects had: `offset` is added to the positions before the lookup (see
+/// `Scene::recentered`)
+///
+/// The node isn't written to JSON, it is written as its input, the texture of the objects
+/// where they were
+#[derive(Clone, Debug)]
+pub struct OffsetTexture {
+    pub input: Arc<dyn Texture>,
+    pub offset: Vector3<f64>,
+}
+
+impl Texture for OffsetTexture {
+    fn eval(&self, uv: (f64, f64), p: Vector3<f64>) -> Color {
+        self.input.eval(uv, p + self.offset)
+    }
+
+    fn to_json(&self) -> Json {
+        self.input.to_json()
+    }
+}
+
+/// Read a node combining other textures from JSON, its inputs being textures in the format
+/// of `texture::texture_from_json` (colors, textures or other nodes)
+///
+/// * `multiply` - the product of the `inputs` array
+/// * `mix` - `a` and `b` blended by the luminance of `mask`
+/// * `invert` - the `input` turned over
+/// * `color_correct` - the `input` with its `gain`, `contrast`, `saturation` and `gamma`
+///   corrected (see `ColorCorrectTexture`)
+///
+/// ### Arguments
+///
+/// * `kind` - The type of the node
+/// * `entries` - The keys of the JSON object of the node
+/// * `directory` - The directory the paths of image textures are relative to
+///
+/// ### Returns
+///
+/// `Option<Result<Arc<dyn Texture>, JsonError>>` - The node, `None` if `kind` isn't a node
+///
+pub fn node_from_json(
+    kind: &str,
+    entries: &[(String, Json)],
+    directory: &Path,
+) -> Option<Result<Arc<dyn Texture>, JsonError>> {
+    let node: Result<Arc<dyn Texture>, JsonError> = match kind {
+        "multiply" => multiply_from_json(entries, directory).map(|node| Arc::new(node) as _),
+        "mix" => mix_from_json(entries, directory).map(|node| Arc::new(node) as _),
+        "invert" => invert_from_json(entries, directory).map(|node| Arc::new(node) as _),
+        "color_correct" => {
+            color_correct_from_json(entries, directory).map(|node| Arc::new(node) as _)
+        }
+        _ => return None,
+    };
+    Some(node)
+}
+
+fn unknown(kind: &str, key: &str) -> JsonError {
+    JsonError::new(&format!("unknown property '{}' of a {} texture", key, kind))
+}
+
+/// Input of a node that must be set
+fn required(
+    kind: &str,
+    key: &str,
+    input: Option<Arc<dyn Texture>>,
+) -> Result<Arc<dyn Texture>, JsonError> {
+    input.ok_or_else(|| JsonError::new(&format!("a {} texture needs '{}'", kind, key)))
+}
+
+fn multiply_from_json(
+    entries: &[(String, Json)],
+    directory: &Path,
+) -> Result<MultiplyTexture, JsonError> {
+    let mut inputs = Vec::new();
+    for (key, value) in entries {
+        match key.as_str() {
+            "type" => {}
+            "inputs" => {
+                let values = value
+                    .as_array()
+                    .ok_or_else(|| JsonError::new("'inputs' must be an array of textures"))?;
+                for value in values {
+                    inputs.push(texture_from_json(value, directory)?);
+                }
+            }
+            _ => return Err(unknown("multiply", key)),
+        }
+    }
+    if inputs.is_empty() {
+        return Err(JsonError::new("a multiply texture needs 'inputs'"));
+    }
+    Ok(MultiplyTexture { inputs })
+}
+
+fn mix_from_json(entries: &[(String, Json)], directory: &Path) -> Result<MixTexture, JsonError> {
+    let (mut a, mut b, mut mask) = (None, None, None);
+    for (key, value) in entries {
+        match key.as_str() {
+            "type" => {}
+            "a" => a = Some(texture_from_json(value, directory)?),
+            "b" => b = Some(texture_from_json(value, directory)?),
+            "mask" => mask = Some(texture_from_json(value, directory)?),
+            _ => return Err(unknown("mix", key)),
+        }
+    }
+    Ok(MixTexture {
+        a: required("mix", "a", a)?,
+        b: required("mix", "b", b)?,
+        mask: required("mix", "mask", mask)?,
+    })
+}
+
+fn invert_from_json(
+    entries: &[(String, Json)],
+    directory: &Path,
+) -> Result<InvertTexture, JsonError> {
+    let mut input = None;
+    for (key, value) in entries {
+        match key.as_str() {
+            "type" => {}
+            "input" => input = Some(texture_from_json(value, directory)?),
+            _ => return Err(unknown("invert", key)),
+        }
+    }
+    Ok(InvertTexture {
+        input: required("invert", "input", input)?,
+    })
+}
+
+fn color_correct_from_json(
+    entries: &[(String, Json)],
+    directory: &Path,
+) -> Result<ColorCorrectTexture, JsonError> {
+    let mut input = None;
+    let (mut gain, mut contrast, mut saturation, mut gamma) = (1.0, 1.0, 1.0, 1.0);
+    for (key, value) in entries {
+        match key.as_str() {
+            "type" => {}
+            "input" => input = Some(texture_from_json(value, directory)?),
+            "gain" => gain = json_to_f64(key, value)?,
+            "contrast" => contrast = json_to_f64(key, value)?,
+            "saturation" => saturation = json_to_f64(key, value)?,
+            "gamma" => gamma = json_to_f64(key, value)?,
+            _ => return Err(unknown("color_correct", key)),
+        }
+    }
+    if gamma <= 0.0 {
+        return Err(JsonError::new("'gamma' must be positive"));
+    }
+    Ok(ColorCorrectTexture {
+        gain,
+        contrast,
+        saturation,
+        gamma,
+        ..ColorCorrectTexture::new(required("color_correct", "input", input)?)
+    })
+}
@@ -0,0 +1,172 @@
+use crate::i18n::tr;
+use crate::i18n::tr_format;
+use crate::image::check_image_size;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+use crate::texture::udim_tile;
+use crate::texture::TextureImage;
+
+/// Problems of a scene and its render settings found before rendering, described one per
+/// line in the language of the program (see `i18n`), empty when the scene can be rendered
+/// as it is
+///
+/// Looks for textures and environment maps that fail to load (the first tile of UDIM
+/// textures), degenerate objects, lights and cameras, and names given twice, which make
+/// lookups and overrides by name ambiguous
+///
+/// ### Arguments
+///
+/// * `scene` - The scene to check
+/// * `settings` - The render settings to check
+///
+/// ### Returns
+///
+/// `Vec<String>` - The problems found
+///
+pub fn validate(scene: &Scene, settings: &RenderSettings) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let Err(error) = check_image_size(settings.width, settings.height) {
+        problems.push(error.message);
+    }
+    if settings.samples_per_pixel == 0 {
+        problems.push(tr("the settings use no samples per pixel"));
+    }
+    if !(settings.fov > 0.0 && settings.fov < std::f64::consts::PI) {
+        problems.push(tr_format(
+            "the field of view of {} degrees is out of range",
+            &[&format!("{:.1}", settings.fov.to_degrees())],
+        ));
+    }
+
+    problems.extend(scene_problems(scene));
+
+    let mut textures: Vec<&str> = Vec::new();
+    for sphere in &scene.spheres {
+        if let Some(path) = &sphere.material.texture {
+            if !textures.contains(&path.as_str()) {
+                textures.push(path);
+            }
+        }
+    }
+    for path in textures {
+        let (file, _, _) = udim_tile(path, 0.5, 0.5);
+        if let Err(error) = TextureImage::load(&file) {
+            problems.push(tr_format(
+                "texture '{}' failed to load: {}",
+                &[&file, &error],
+            ));
+        }
+    }
+    if let Some(path) = scene.environment.as_ref().and_then(|env| env.path()) {
+        if let Err(error) = TextureImage::load(path) {
+            problems.push(tr_format(
+                "environment map '{}' failed to load: {}",
+                &[&path, &error],
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Problems of the scene itself, without loading its files: degenerate objects, lights and
+/// cameras, and names given twice
+///
+/// ### Arguments
+///
+/// * `scene` - The scene to check
+///
+/// ### Returns
+///
+/// `Vec<String>` - The problems found, one per line
+///
+pub fn scene_problems(scene: &Scene) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for (index, sphere) in scene.spheres.iter().enumerate() {
+        let label = label("object", index, sphere.name.as_deref());
+        if sphere.radius <= 0.0 || sphere.radius.is_nan() {
+            problems.push(tr_format(
+                "{} has a radius of {}",
+                &[&label, &sphere.radius],
+            ));
+        }
+        if sphere.center.iter().any(|c| !c.is_finite()) {
+            problems.push(tr_format("{} has a position that is not finite", &[&label]));
+        }
+    }
+
+    for (index, light) in scene.lights.iter().enumerate() {
+        let label = label("light", index, light.name.as_deref());
+        if light.intensity < 0.0 {
+            problems.push(tr_format("{} has a negative intensity", &[&label]));
+        }
+        if light.shadow_samples == 0 {
+            problems.push(tr_format("{} casts no shadow rays", &[&label]));
+        }
+        if light
+            .max_distance
+            .is_some_and(|distance| distance <= 0.0 || distance.is_nan())
+        {
+            problems.push(tr_format(
+                "{} has a maximum distance that isn't positive",
+                &[&label],
+            ));
+        }
+    }
+
+    for camera in &scene.cameras {
+        if camera.position == camera.target {
+            problems.push(tr_format(
+                "camera '{}' looks at its own position",
+                &[&camera.name],
+            ));
+        }
+    }
+
+    let objects = scene.spheres.iter().filter_map(|s| s.name.as_deref());
+    let lights = scene.lights.iter().filter_map(|l| l.name.as_deref());
+    let cameras = scene.cameras.iter().map(|c| c.name.as_str());
+    for (template, names) in [
+        (
+            "object name '{}' is used more than once",
+            duplicates(objects),
+        ),
+        ("light name '{}' is used more than once", duplicates(lights)),
+        (
+            "camera name '{}' is used more than once",
+            duplicates(cameras),
+        ),
+    ] {
+        for name in names {
+            problems.push(tr_format(template, &[&name]));
+        }
+    }
+
+    problems
+}
+
+/// How an object or a light is referred to in the problems, by name when it has one
+fn label(kind: &str, index: usize, name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("{} '{}'", tr(kind), name),
+        None => format!("{} {}", tr(kind), index),
+    }
+}
+
+/// Names found more than once, in the order of their second occurrence
+fn duplicates<'a>(names: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut seen = Vec::new();
+    let mut repeated = Vec::new();
+    for name in names {
+        if seen.contains(&name) {
+            if !repeated.contains(&name) {
+                repeated.push(name);
+            }
+        } else {
+            seen.push(name);
+        }
+    }
+    repeated
+}
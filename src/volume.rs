@@ -0,0 +1,619 @@
+use crate::color::Color;
+use crate::colorspace::blackbody;
+use crate::colorspace::ColorPipeline;
+use crate::json::Json;
+use crate::medium::Medium;
+use crate::medium::MediumSample;
+use crate::object::SceneLight;
+use crate::random::Rng;
+use crate::ray::Ray;
+use crate::render::cast_ray;
+use crate::render::is_occluded;
+use crate::render::nearest_sphere;
+use crate::render::sanitize_radiance;
+use crate::scene::Scene;
+use crate::settings::RenderSettings;
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use nalgebra::Vector3;
+
+/// Fog filling the space between the camera and the scene
+///
+/// The fog is homogeneous unless a density grid is attached, in which case it only
+/// exists inside the bounds of the grid (a cloud or smoke volume)
+#[derive(Clone)]
+pub struct Fog {
+    /// Extinction coefficient (fraction of light lost per unit of distance)
+    pub density: f64,
+    /// Fraction of the extinction that is scattering rather than absorption
+    pub albedo: f64,
+    /// Henyey-Greenstein anisotropy, from -1 (backward) through 0 (isotropic) to 1 (forward)
+    pub anisotropy: f64,
+    /// Distance after which rays that miss every object stop marching
+    pub max_distance: f64,
+    /// Number of ray marching steps along every camera ray (homogeneous fog only, grids are
+    /// marched voxel by voxel)
+    pub steps: u32,
+    /// Optional heterogeneous density, multiplied by `density`
+    pub grid: Option<Arc<DensityGrid>>,
+    /// Optional light emitted by the volume itself (fire)
+    pub emission: Option<Emission>,
+}
+
+impl Fog {
+    pub fn new(density: f64, anisotropy: f64) -> Fog {
+        Fog {
+            density,
+            albedo: 0.9,
+            anisotropy: anisotropy.clamp(-0.99, 0.99),
+            max_distance: 50.0,
+            steps: 64,
+            grid: None,
+            emission: None,
+        }
+    }
+
+    pub fn with_grid(mut self, grid: Arc<DensityGrid>) -> Fog {
+        self.grid = Some(grid);
+        self
+    }
+
+    pub fn with_emission(mut self, emission: Emission) -> Fog {
+        self.emission = Some(emission);
+        self
+    }
+
+    /// Extinction coefficient at a point
+    pub fn density_at(&self, point: Vector3<f64>) -> f64 {
+        match &self.grid {
+            None => self.density,
+            Some(grid) => self.density * grid.density(point),
+        }
+    }
+
+    /// Parametric range of a ray (between its `t_min` and `t_max`) lying inside the medium
+    fn extent(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let (t0, t1) = match &self.grid {
+            None => (ray.t_min, ray.t_max.min(self.max_distance)),
+            Some(grid) => {
+                let (t0, t1) = grid.clip(ray)?;
+                (t0.max(ray.t_min), t1.min(ray.t_max))
+            }
+        };
+        if t0 < t1 {
+            Some((t0, t1))
+        } else {
+            None
+        }
+    }
+
+    /// Transmittance of a ray segment between its `t_min` and `t_max`
+    ///
+    /// Exact for homogeneous fog; for density grids it is estimated with delta tracking,
+    /// which is unbiased but returns either 0 or 1 for a single call
+    pub fn transmittance(&self, ray: &Ray, rng: &mut Rng) -> f64 {
+        let Some(grid) = &self.grid else {
+            // lights at infinity are treated as lighting the fog from its border
+            return if ray.t_max.is_finite() {
+                (-self.density * (ray.t_max - ray.t_min)).exp()
+            } else {
+                1.0
+            };
+        };
+        let Some((t0, t1)) = self.extent(ray) else {
+            return 1.0;
+        };
+        let majorant = self.density * grid.max_value();
+        if majorant <= 0.0 {
+            return 1.0;
+        }
+        // sample collisions against the majorant and accept the real ones with the
+        // probability density / majorant
+        let mut t = t0;
+        loop {
+            t -= (1.0 - rng.next_f64()).ln() / majorant;
+            if t >= t1 {
+                return 1.0;
+            }
+            if rng.next_f64() < self.density_at(ray.at(t)) / majorant {
+                return 0.0;
+            }
+        }
+    }
+
+    /// Transmittance and in-scattered light along a ray segment between its `t_min` and `t_max`
+    ///
+    /// Single scattering is ray marched with one jittered sample per step (one step per voxel
+    /// for density grids); lights only contribute where they reach the sample point, so spot
+    /// lights cut visible shafts out of the fog and objects cast volumetric shadows
+    ///
+    /// ### Arguments
+    ///
+    /// * `ray` - The ray (normalized), `t_max` is the distance to the hit object
+    /// * `scene` - The scene
+    /// * `rng` - The generator used to jitter the steps
+    ///
+    /// ### Returns
+    ///
+    /// `(f64, Color)` - The transmittance of the segment and the in-scattered light
+    ///
+    pub fn march(&self, ray: &Ray, scene: &Scene, rng: &mut Rng) -> (f64, Color) {
+        let mut in_scattered = Color::BLACK;
+        // emission may extend past the density, march over the union of both ranges
+        let emission_range = self.emission.as_ref().and_then(|emission| {
+            let (t0, t1) = emission.temperature.clip(ray)?;
+            let (t0, t1) = (t0.max(ray.t_min), t1.min(ray.t_max));
+            (t0 < t1).then_some((t0, t1))
+        });
+        let range = match (self.extent(ray), emission_range) {
+            (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.max(b.1))),
+            (a, b) => a.or(b),
+        };
+        let Some((t0, t1)) = range else {
+            return (1.0, in_scattered);
+        };
+        let voxel_size = [
+            self.grid.as_ref().map(|grid| grid.voxel_size()),
+            self.emission
+                .as_ref()
+                .map(|emission| emission.temperature.voxel_size()),
+        ]
+        .into_iter()
+        .flatten()
+        .reduce(f64::min);
+        let steps = match voxel_size {
+            None => self.steps.max(1),
+            Some(size) => ((t1 - t0) / size).ceil().clamp(1.0, 4096.0) as u32,
+        };
+        let dt = (t1 - t0) / steps as f64;
+        let mut optical_depth: f64 = 0.0;
+
+        let offset = rng.next_f64();
+        for step in 0..steps {
+            let point = ray.at(t0 + (step as f64 + offset) * dt);
+            if let Some(emission) = &self.emission {
+                in_scattered += emission.radiance_at(point) * ((-optical_depth).exp() * dt);
+            }
+            let sigma_t = self.density_at(point);
+            if sigma_t <= 0.0 {
+                continue;
+            }
+            let transmittance = (-optical_depth).exp();
+            let sigma_s = sigma_t * self.albedo;
+            for light in &scene.lights {
+                let attenuation = light.attenuation(point);
+                if attenuation <= 0.0 {
+                    continue;
+                }
+                let (to_light, light_distance) = light.direction_from(point);
+                let shadow_ray = Ray::new(point, to_light)
+                    .with_t_max(light_distance)
+                    .with_time(ray.time);
+                if is_occluded(&shadow_ray, scene) {
+                    continue;
+                }
+                // the light travels along `-to_light` and is scattered back to the camera;
+                // surfaces don't divide the light intensities by pi, neither does the fog
+                let phase = henyey_greenstein(to_light.dot(&ray.direction), self.anisotropy) * PI;
+                // the light reaching the sample is attenuated by the fog on its way as well
+                let light_transmittance = self.transmittance(&shadow_ray, rng);
+                in_scattered += light.radiance()
+                    * (attenuation * light_transmittance * transmittance * sigma_s * phase * dt);
+            }
+            optical_depth += sigma_t * dt;
+        }
+        ((-optical_depth).exp(), in_scattered)
+    }
+}
+
+impl std::fmt::Debug for Fog {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Fog")
+            .field("density", &self.density)
+            .field("albedo", &self.albedo)
+            .field("anisotropy", &self.anisotropy)
+            .field("max_distance", &self.max_distance)
+            .field("steps", &self.steps)
+            .field("grid", &self.grid.is_some())
+            .field("emission", &self.emission.is_some())
+            .finish()
+    }
+}
+
+/// Fog as the medium of a scene, for the integrators (see `medium::Medium`)
+///
+/// The density of grids is integrated with `steps` midpoint steps, the emission is left to
+/// `render_fog`; rays leaving the scene cross the fog up to `max_distance`
+impl Medium for Fog {
+    fn transmittance(&self, ray: &Ray) -> Color {
+        let Some((t0, t1)) = self.extent(ray) else {
+            return Color::WHITE;
+        };
+        let optical_depth = match &self.grid {
+            None => self.density * (t1 - t0),
+            Some(_) => {
+                let dt = (t1 - t0) / self.steps.max(1) as f64;
+                (0..self.steps.max(1))
+                    .map(|step| self.density_at(ray.at(t0 + (step as f64 + 0.5) * dt)) * dt)
+                    .sum()
+            }
+        };
+        Color::gray((-optical_depth).exp())
+    }
+
+    fn sample(&self, ray: &Ray, u: f64) -> MediumSample {
+        let passed = MediumSample::Passed {
+            weight: Color::WHITE,
+        };
+        let Some((t0, t1)) = self.extent(ray) else {
+            return passed;
+        };
+        // the light is scattered where the optical depth reaches the sampled one
+        let target = -(1.0 - u).ln();
+        let steps = if self.grid.is_some() {
+            self.steps.max(1)
+        } else {
+            1
+        };
+        let dt = (t1 - t0) / steps as f64;
+        let mut optical_depth = 0.0;
+        for step in 0..steps {
+            let start = t0 + step as f64 * dt;
+            let sigma_t = self.density_at(ray.at(start + 0.5 * dt));
+            if sigma_t > 0.0 && optical_depth + sigma_t * dt >= target {
+                return MediumSample::Scattered {
+                    t: start + (target - optical_depth) / sigma_t,
+                    weight: Color::gray(self.albedo),
+                };
+            }
+            optical_depth += sigma_t * dt;
+        }
+        passed
+    }
+
+    fn anisotropy(&self) -> f64 {
+        self.anisotropy
+    }
+
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("type".to_string(), Json::String("fog".to_string())),
+            ("density".to_string(), Json::Number(self.density)),
+            ("albedo".to_string(), Json::Number(self.albedo)),
+            ("anisotropy".to_string(), Json::Number(self.anisotropy)),
+            ("max_distance".to_string(), Json::Number(self.max_distance)),
+            ("steps".to_string(), Json::Number(self.steps as f64)),
+        ])
+    }
+}
+
+/// Light emitted by a volume, with colors following a black body temperature ramp
+///
+/// The temperature grid usually comes from a fire simulation and holds normalized heat
+/// values: 0 (and below) emits nothing, 0..1 maps linearly from `cold` to `hot` kelvins.
+/// The brightness follows the Stefan-Boltzmann law, relative to the hot end of the ramp
+#[derive(Clone)]
+pub struct Emission {
+    pub temperature: Arc<DensityGrid>,
+    /// Temperature in kelvins for a heat of 0
+    pub cold: f64,
+    /// Temperature in kelvins for a heat of 1
+    pub hot: f64,
+    /// Emitted radiance per unit of distance at the hot end of the ramp
+    pub intensity: f64,
+    /// Precomputed radiance for evenly spaced heat values in `[0, 1]`
+    ramp: Vec<Color>,
+}
+
+impl Emission {
+    pub fn new(temperature: Arc<DensityGrid>, cold: f64, hot: f64, intensity: f64) -> Emission {
+        // black body colors are costly to evaluate, tabulate them once
+        let ramp = (0..256)
+            .map(|k| {
+                let kelvin = cold + (hot - cold) * k as f64 / 255.0;
+                blackbody(kelvin) * (kelvin / hot).powi(4)
+            })
+            .collect();
+        Emission {
+            temperature,
+            cold,
+            hot,
+            intensity,
+            ramp,
+        }
+    }
+
+    /// Emitted radiance per unit of distance at a point
+    pub fn radiance_at(&self, point: Vector3<f64>) -> Color {
+        let heat = self.temperature.density(point);
+        if heat <= 0.0 {
+            return Color::BLACK;
+        }
+        let index = (heat.min(1.0) * (self.ramp.len() - 1) as f64).round() as usize;
+        self.ramp[index] * self.intensity
+    }
+
+    /// Approximate the volume by point lights so it lights the surfaces of the scene
+    ///
+    /// The bounds of the temperature grid are split into `cells_per_axis`^3 cells; every
+    /// emitting cell becomes a light at its center whose radiance is the emission integrated
+    /// over the cell and whose radius covers the cell, for soft shadows. Lights don't fall off
+    /// with distance in this renderer, so the integrated emission is multiplied by `scale` to
+    /// fit the brightness of the scene
+    ///
+    /// ### Arguments
+    ///
+    /// * `cells_per_axis` - The number of cells along every axis
+    /// * `scale` - The factor applied to the intensity of the lights
+    ///
+    /// ### Returns
+    ///
+    /// `Vec<SceneLight>` - The lights to add to the scene
+    ///
+    pub fn to_lights(&self, cells_per_axis: u32, scale: f64) -> Vec<SceneLight> {
+        let n = cells_per_axis.max(1);
+        let grid = &self.temperature;
+        let cell = (grid.max - grid.min) / n as f64;
+        // samples per cell axis used to integrate the emission
+        let m = 4;
+        let sample_volume = cell.x * cell.y * cell.z / (m * m * m) as f64;
+        let mut lights = Vec::new();
+        for cz in 0..n {
+            for cy in 0..n {
+                for cx in 0..n {
+                    let corner = grid.min
+                        + cell.component_mul(&Vector3::new(cx as f64, cy as f64, cz as f64));
+                    let mut power = Color::BLACK;
+                    for k in 0..m * m * m {
+                        let offset = Vector3::new(
+                            ((k % m) as f64 + 0.5) / m as f64,
+                            ((k / m % m) as f64 + 0.5) / m as f64,
+                            ((k / (m * m)) as f64 + 0.5) / m as f64,
+                        );
+                        power +=
+                            self.radiance_at(corner + cell.component_mul(&offset)) * sample_volume;
+                    }
+                    if power.max_channel() <= 0.0 {
+                        continue;
+                    }
+                    lights.push(
+                        SceneLight::new(corner + cell / 2.0, scale)
+                            .with_color(power)
+                            .with_radius(cell.min() / 2.0)
+                            .with_shadow_samples(4),
+                    );
+                }
+            }
+        }
+        lights
+    }
+}
+
+/// Density values stored in a dense voxel grid spanning an axis-aligned box
+///
+/// OpenVDB/NanoVDB files can't be read directly (that needs a VDB reader the crate doesn't
+/// depend on); grids exported to the Mitsuba `.vol` format are supported instead
+pub struct DensityGrid {
+    pub min: Vector3<f64>,
+    pub max: Vector3<f64>,
+    /// Number of voxels along x, y and z
+    pub resolution: [usize; 3],
+    /// Voxel values, x varying fastest then y then z
+    pub data: Vec<f32>,
+    max_value: f64,
+}
+
+impl DensityGrid {
+    pub fn new(
+        min: Vector3<f64>,
+        max: Vector3<f64>,
+        resolution: [usize; 3],
+        data: Vec<f32>,
+    ) -> DensityGrid {
+        assert_eq!(data.len(), resolution[0] * resolution[1] * resolution[2]);
+        let max_value = data.iter().fold(0.0_f64, |m, v| m.max(*v as f64));
+        DensityGrid {
+            min,
+            max,
+            resolution,
+            data,
+            max_value,
+        }
+    }
+
+    /// Build a grid by evaluating a function at the voxel centers
+    pub fn from_fn(
+        min: Vector3<f64>,
+        max: Vector3<f64>,
+        resolution: [usize; 3],
+        f: impl Fn(Vector3<f64>) -> f64,
+    ) -> DensityGrid {
+        let size = max - min;
+        let mut data = Vec::with_capacity(resolution[0] * resolution[1] * resolution[2]);
+        for z in 0..resolution[2] {
+            for y in 0..resolution[1] {
+                for x in 0..resolution[0] {
+                    let p = min
+                        + Vector3::new(
+                            size.x * (x as f64 + 0.5) / resolution[0] as f64,
+                            size.y * (y as f64 + 0.5) / resolution[1] as f64,
+                            size.z * (z as f64 + 0.5) / resolution[2] as f64,
+                        );
+                    data.push(f(p) as f32);
+                }
+            }
+        }
+        DensityGrid::new(min, max, resolution, data)
+    }
+
+    /// Load a single-channel float32 grid in the Mitsuba `.vol` format
+    pub fn load_vol(filename: &str) -> std::io::Result<DensityGrid> {
+        DensityGrid::from_vol_bytes(&std::fs::read(filename)?)
+    }
+
+    /// Read a grid in the Mitsuba `.vol` format held in memory, see `DensityGrid::load_vol`
+    pub fn from_vol_bytes(bytes: &[u8]) -> std::io::Result<DensityGrid> {
+        let invalid = |message: &str| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+        };
+        if bytes.len() < 48 || &bytes[0..3] != b"VOL" || bytes[3] != 3 {
+            return Err(invalid("not a version 3 .vol file"));
+        }
+        let int = |offset: usize| i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let float =
+            |offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        if int(4) != 1 {
+            return Err(invalid("only float32 .vol grids are supported"));
+        }
+        let (nx, ny, nz, channels) = (int(8), int(12), int(16), int(20));
+        if nx <= 0 || ny <= 0 || nz <= 0 || channels <= 0 {
+            return Err(invalid("invalid grid resolution"));
+        }
+        let resolution = [nx as usize, ny as usize, nz as usize];
+        let channels = channels as usize;
+        // a size read from the file can't be trusted to fit in memory, or in a usize
+        let count = resolution[0]
+            .checked_mul(resolution[1])
+            .and_then(|count| count.checked_mul(resolution[2]));
+        let size = count.and_then(|count| count.checked_mul(channels * 4));
+        let (Some(count), Some(size)) = (count, size) else {
+            return Err(invalid("invalid grid resolution"));
+        };
+        if bytes.len() - 48 < size {
+            return Err(invalid("truncated .vol file"));
+        }
+        let min = Vector3::new(float(24) as f64, float(28) as f64, float(32) as f64);
+        let max = Vector3::new(float(36) as f64, float(40) as f64, float(44) as f64);
+        // only the first channel is used as density
+        let data = (0..count).map(|k| float(48 + k * channels * 4)).collect();
+        Ok(DensityGrid::new(min, max, resolution, data))
+    }
+
+    pub fn max_value(&self) -> f64 {
+        self.max_value
+    }
+
+    /// Size of the smallest voxel edge
+    pub fn voxel_size(&self) -> f64 {
+        let size = self.max - self.min;
+        (size.x / self.resolution[0] as f64)
+            .min(size.y / self.resolution[1] as f64)
+            .min(size.z / self.resolution[2] as f64)
+    }
+
+    /// Trilinearly interpolated density at a point, zero outside of the grid
+    pub fn density(&self, point: Vector3<f64>) -> f64 {
+        let size = self.max - self.min;
+        let local = point - self.min;
+        if (0..3).any(|k| local[k] < 0.0 || local[k] > size[k]) {
+            return 0.0;
+        }
+        // continuous voxel coordinates, voxel centers at integer positions
+        let mut base = [0usize; 3];
+        let mut frac = [0.0; 3];
+        for k in 0..3 {
+            let c = (local[k] / size[k] * self.resolution[k] as f64 - 0.5)
+                .clamp(0.0, (self.resolution[k] - 1) as f64);
+            base[k] = (c.floor() as usize).min(self.resolution[k].saturating_sub(2));
+            frac[k] = c - base[k] as f64;
+        }
+        let value = |x: usize, y: usize, z: usize| {
+            let x = x.min(self.resolution[0] - 1);
+            let y = y.min(self.resolution[1] - 1);
+            let z = z.min(self.resolution[2] - 1);
+            self.data[(z * self.resolution[1] + y) * self.resolution[0] + x] as f64
+        };
+        let mut result = 0.0;
+        for corner in 0..8 {
+            let (dx, dy, dz) = (corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+            let weight = (if dx == 1 { frac[0] } else { 1.0 - frac[0] })
+                * (if dy == 1 { frac[1] } else { 1.0 - frac[1] })
+                * (if dz == 1 { frac[2] } else { 1.0 - frac[2] });
+            result += weight * value(base[0] + dx, base[1] + dy, base[2] + dz);
+        }
+        result
+    }
+
+    /// Parametric range of a ray inside the bounds of the grid (slab test), ignoring the
+    /// range of the ray itself
+    pub fn clip(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let mut t0 = f64::NEG_INFINITY;
+        let mut t1 = f64::INFINITY;
+        for k in 0..3 {
+            let inv = 1.0 / ray.direction[k];
+            let mut near = (self.min[k] - ray.origin[k]) * inv;
+            let mut far = (self.max[k] - ray.origin[k]) * inv;
+            if near > far {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // NaN appears for rays parallel to a slab starting on its plane
+            if near.is_nan() || far.is_nan() {
+                continue;
+            }
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+        }
+        if t0 <= t1 {
+            Some((t0, t1))
+        } else {
+            None
+        }
+    }
+}
+
+/// Henyey-Greenstein phase function
+///
+/// ### Arguments
+///
+/// * `cos_theta` - Cosine of the angle between the light travel direction and the scattered
+///   direction
+/// * `g` - The anisotropy
+///
+pub fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+/// Render a scene seen through fog
+///
+/// The fog is applied to the camera rays only: surfaces are shaded with `cast_ray` as usual
+/// and then attenuated and lit by the fog in front of them
+///
+/// ### Arguments
+///
+/// * `settings` - The render settings (image size, field of view, bounces and background)
+/// * `scene` - The scene
+/// * `fog` - The fog filling the scene
+/// * `pipeline` - The color pipeline used to encode the pixels
+///
+/// ### Returns
+///
+/// `Vec<u8>` - RGB pixels of the image, row by row
+///
+pub fn render_fog(
+    settings: &RenderSettings,
+    scene: &Scene,
+    fog: &Fog,
+    pipeline: &ColorPipeline,
+) -> Vec<u8> {
+    let (width, height) = (settings.width, settings.height);
+    let mut buffer = vec![0; width as usize * height as usize * 3];
+    for j in 0..height {
+        for i in 0..width {
+            let ray = settings.camera_ray(i as f64 + 0.5, j as f64 + 0.5);
+            let (surface, distance) = match nearest_sphere(&ray, scene) {
+                Some((_, distance)) => (cast_ray(&ray, scene, settings, 0), distance),
+                None => (settings.background_radiance(&ray), fog.max_distance),
+            };
+            let mut rng = Rng::new((j * width + i) as u64);
+            let (transmittance, in_scattered) =
+                fog.march(&ray.with_t_max(distance), scene, &mut rng);
+            let color = sanitize_radiance(surface * transmittance + in_scattered);
+            let index = (j * width + i) as usize;
+            buffer[index * 3..index * 3 + 3].copy_from_slice(&pipeline.to_rgb8(color));
+        }
+    }
+    buffer
+}
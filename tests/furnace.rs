@@ -0,0 +1,50 @@
+use ray_tracing::color::Color;
+use ray_tracing::furnace::furnace_test;
+use ray_tracing::furnace::FURNACE_TOLERANCE;
+use ray_tracing::object::Material;
+
+use nalgebra::Vector3;
+
+fn white(albedo: Vector3<f64>) -> Material {
+    Material::new(albedo, Color::WHITE, 50.0)
+}
+
+#[test]
+fn white_diffuse_conserves_energy() {
+    let report = furnace_test(white(Vector3::new(1.0, 0.0, 0.0)), FURNACE_TOLERANCE);
+    assert!(report.passed(), "{:?}", report);
+}
+
+#[test]
+fn white_mirror_conserves_energy() {
+    let report = furnace_test(white(Vector3::new(0.0, 0.0, 1.0)), FURNACE_TOLERANCE);
+    assert!(report.passed(), "{:?}", report);
+}
+
+#[test]
+fn diffuse_and_mirror_summing_to_one_conserve_energy() {
+    let report = furnace_test(white(Vector3::new(0.6, 0.0, 0.4)), FURNACE_TOLERANCE);
+    assert!(report.passed(), "{:?}", report);
+}
+
+#[test]
+fn glass_conserves_energy() {
+    let glass = white(Vector3::new(0.0, 0.0, 0.1)).with_refraction(0.9, 1.5);
+    let report = furnace_test(glass, FURNACE_TOLERANCE);
+    assert!(report.passed(), "{:?}", report);
+}
+
+#[test]
+fn weights_above_one_are_caught() {
+    let report = furnace_test(white(Vector3::new(1.0, 0.0, 0.5)), FURNACE_TOLERANCE);
+    assert!(!report.passed());
+    assert!(report.gains_energy());
+}
+
+#[test]
+fn gray_material_loses_energy() {
+    let gray = Material::new(Vector3::new(1.0, 0.0, 0.0), Color::gray(0.5), 50.0);
+    let report = furnace_test(gray, FURNACE_TOLERANCE);
+    assert!(!report.passed());
+    assert!(!report.gains_energy());
+}
@@ -0,0 +1,105 @@
+use ray_tracing::camera::Camera;
+use ray_tracing::color::Color;
+use ray_tracing::integrator::IntegratorKind;
+use ray_tracing::integrator::PathTracer;
+use ray_tracing::integrator::Whitted;
+use ray_tracing::object::Material;
+use ray_tracing::object::SceneLight;
+use ray_tracing::object::Sphere;
+use ray_tracing::render::render_radiance;
+use ray_tracing::scene::Scene;
+use ray_tracing::settings::RenderSettings;
+
+use nalgebra::Vector3;
+
+/// A diffuse ball with a highlight on a diffuse floor, next to a mirror ball, lit by the
+/// given lights
+fn scene(lights: Vec<SceneLight>) -> Scene {
+    let floor = Material::new(Vector3::new(0.8, 0.0, 0.0), Color::gray(0.6), 10.0);
+    let ball = Material::new(Vector3::new(0.7, 0.3, 0.0), Color::new(0.8, 0.3, 0.2), 50.0);
+    let mirror = Material::new(Vector3::new(0.0, 0.5, 0.8), Color::WHITE, 500.0);
+    Scene::builder()
+        .add_sphere(Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0, floor))
+        .add_sphere(Sphere::new(Vector3::new(-0.6, 0.5, 0.0), 0.5, ball))
+        .add_sphere(Sphere::new(Vector3::new(0.7, 0.4, -0.3), 0.4, mirror))
+        .add_lights(lights)
+        .camera(Camera::new(
+            "test",
+            Vector3::new(0.0, 1.2, 3.0),
+            Vector3::new(0.0, 0.4, 0.0),
+        ))
+        .build()
+        .unwrap()
+}
+
+/// Settings where both integrators only gather the light of the lights: no bounce, so the
+/// diffuse and mirror rays only see the background, which is black
+fn direct_lighting_settings(scene: &Scene, integrator: IntegratorKind) -> RenderSettings {
+    RenderSettings::new(24, 18)
+        .with_fov(50.0_f64.to_radians())
+        .with_samples_per_pixel(4)
+        .with_max_depth(0)
+        .with_background(Color::BLACK)
+        .with_integrator(integrator)
+        .with_camera(scene.cameras[0].clone())
+}
+
+/// Radiance of the scene rendered with the Whitted integrator and with the path tracer
+fn render_both(scene: &Scene) -> (Vec<Color>, Vec<Color>) {
+    let whitted = IntegratorKind::Whitted(Whitted);
+    let path = IntegratorKind::PathTracer(PathTracer);
+    (
+        render_radiance(&direct_lighting_settings(scene, whitted), scene),
+        render_radiance(&direct_lighting_settings(scene, path), scene),
+    )
+}
+
+fn mean(colors: &[Color]) -> Color {
+    colors
+        .iter()
+        .fold(Color::BLACK, |total, color| total + *color)
+        / colors.len() as f64
+}
+
+#[test]
+fn point_lights_agree_per_pixel() {
+    let scene = scene(vec![
+        SceneLight::new(Vector3::new(-3.0, 4.0, 3.0), 1.2),
+        SceneLight::new(Vector3::new(4.0, 2.0, 1.0), 0.5),
+    ]);
+    let (whitted, path) = render_both(&scene);
+    for (k, (whitted, path)) in whitted.iter().zip(&path).enumerate() {
+        let difference = (*whitted - *path).to_array().map(f64::abs);
+        assert!(
+            difference.iter().all(|d| *d < 1e-9),
+            "pixel {}: whitted {:?}, path {:?}",
+            k,
+            whitted,
+            path
+        );
+    }
+}
+
+#[test]
+fn soft_lights_agree_on_average() {
+    let scene = scene(vec![SceneLight::new(Vector3::new(-3.0, 4.0, 3.0), 1.2)
+        .with_radius(1.0)
+        .with_shadow_samples(16)]);
+    let (whitted, path) = render_both(&scene);
+    let (whitted, path) = (mean(&whitted), mean(&path));
+    for (whitted, path) in whitted.to_array().iter().zip(path.to_array()) {
+        assert!(
+            (whitted - path).abs() <= 0.02 * whitted,
+            "whitted {:?}, path {:?}",
+            whitted,
+            path
+        );
+    }
+}
+
+#[test]
+fn image_is_lit() {
+    let scene = scene(vec![SceneLight::new(Vector3::new(-3.0, 4.0, 3.0), 1.2)]);
+    let (whitted, _) = render_both(&scene);
+    assert!(mean(&whitted).luminance() > 0.05);
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "bench-scenes")]
+
+use ray_tracing::color::Color;
+use ray_tracing::integrator::IntegratorKind;
+use ray_tracing::integrator::PathTracer;
+use ray_tracing::integrator::Whitted;
+use ray_tracing::render::render_radiance;
+use ray_tracing::scenes::BenchScene;
+use ray_tracing::settings::RenderSettings;
+
+/// Small render of the landscape demo through the whole pipeline: terrain, scattered props,
+/// sky dome, sun and haze
+fn render_landscape(integrator: IntegratorKind) -> Vec<Color> {
+    let bench = BenchScene::from_name("landscape:3").unwrap();
+    let settings = RenderSettings::new(32, 24)
+        .with_fov(bench.fov)
+        .with_samples_per_pixel(2)
+        .with_integrator(integrator)
+        .with_camera(bench.scene.cameras[0].clone())
+        .with_environment(bench.scene.environment.clone())
+        .with_medium(bench.scene.medium.clone());
+    render_radiance(&settings, &bench.scene)
+}
+
+#[test]
+fn landscape_is_the_same_for_the_same_seed() {
+    let first = BenchScene::from_name("landscape:3").unwrap();
+    let second = BenchScene::from_name("landscape:3").unwrap();
+    let other = BenchScene::from_name("landscape:4").unwrap();
+    let centers = |bench: &BenchScene| -> Vec<_> {
+        bench
+            .scene
+            .spheres
+            .iter()
+            .map(|sphere| sphere.center)
+            .collect()
+    };
+    assert_eq!(centers(&first), centers(&second));
+    assert_ne!(centers(&first), centers(&other));
+}
+
+#[test]
+fn landscape_renders_with_both_integrators() {
+    for integrator in [
+        IntegratorKind::Whitted(Whitted),
+        IntegratorKind::PathTracer(PathTracer),
+    ] {
+        let radiance = render_landscape(integrator);
+        assert!(radiance
+            .iter()
+            .all(|color| color.to_array().iter().all(|c| c.is_finite() && *c >= 0.0)));
+        // the sky at the top of the image is blue, the lit ground at the bottom isn't black
+        let top = radiance[..32].iter().fold(Color::BLACK, |sum, c| sum + *c);
+        let bottom = radiance[radiance.len() - 32..]
+            .iter()
+            .fold(Color::BLACK, |sum, c| sum + *c);
+        let [r, _, b] = top.to_array();
+        assert!(b > r, "sky {:?}", top);
+        assert!(bottom.max_channel() > 0.0, "ground {:?}", bottom);
+    }
+}
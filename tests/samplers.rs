@@ -0,0 +1,219 @@
+use ray_tracing::sampler::SamplerKind;
+
+use std::f64::consts::PI;
+
+const KINDS: [SamplerKind; 3] = [
+    SamplerKind::Independent,
+    SamplerKind::Stratified,
+    SamplerKind::Sobol,
+];
+
+/// Function of the unit square
+type Integrand = fn(f64, f64) -> f64;
+
+/// Samples per pixel of the tests, a power of two and a square so no sampler is favored
+const SAMPLES: u32 = 64;
+
+/// Number of pixels (sampler seeds) the statistics are taken over
+const PIXELS: u32 = 256;
+
+/// Points of the samples of a pixel, every point being the values of one sample after
+/// `skipped` dimensions were drawn, `get_2d` if `pairs`, else two `get_1d`
+fn points(kind: SamplerKind, pixel: u32, skipped: u32, pairs: bool) -> Vec<(f64, f64)> {
+    let mut sampler = kind.for_pixel(pixel % 16, pixel / 16, SAMPLES, 7);
+    (0..SAMPLES)
+        .map(|k| {
+            sampler.start_pixel(k);
+            for _ in 0..skipped {
+                sampler.get_1d();
+            }
+            if pairs {
+                sampler.get_2d()
+            } else {
+                (sampler.get_1d(), sampler.get_1d())
+            }
+        })
+        .collect()
+}
+
+/// Star discrepancy of points of the unit square, measured on the boxes anchored at the
+/// origin with their far corner on a 64x64 grid or on a point
+fn star_discrepancy(points: &[(f64, f64)]) -> f64 {
+    let mut corners: Vec<(f64, f64)> = points.to_vec();
+    for a in 1..=64 {
+        for b in 1..=64 {
+            corners.push((a as f64 / 64.0, b as f64 / 64.0));
+        }
+    }
+    corners
+        .iter()
+        .map(|&(x, y)| {
+            let inside = points.iter().filter(|p| p.0 < x && p.1 < y).count();
+            let closed = points.iter().filter(|p| p.0 <= x && p.1 <= y).count();
+            let n = points.len() as f64;
+            (inside as f64 / n - x * y)
+                .abs()
+                .max((closed as f64 / n - x * y).abs())
+        })
+        .fold(0.0, f64::max)
+}
+
+/// Average star discrepancy of the points of every pixel
+fn mean_discrepancy(kind: SamplerKind, skipped: u32, pairs: bool) -> f64 {
+    let total: f64 = (0..PIXELS)
+        .map(|pixel| star_discrepancy(&points(kind, pixel, skipped, pairs)))
+        .sum();
+    total / PIXELS as f64
+}
+
+/// Estimates of the integral of a function over the unit square, one per pixel, from the
+/// samples of the pixel
+fn estimates(kind: SamplerKind, skipped: u32, pairs: bool, f: Integrand) -> Vec<f64> {
+    (0..PIXELS)
+        .map(|pixel| {
+            let points = points(kind, pixel, skipped, pairs);
+            points.iter().map(|&(x, y)| f(x, y)).sum::<f64>() / points.len() as f64
+        })
+        .collect()
+}
+
+/// Mean squared error of estimates of a known value
+fn mean_squared_error(estimates: &[f64], value: f64) -> f64 {
+    estimates.iter().map(|e| (e - value).powi(2)).sum::<f64>() / estimates.len() as f64
+}
+
+/// Analytic integrands over the unit square with their integrals: smooth, with an edge,
+/// and with a sharp feature
+const INTEGRANDS: [(&str, Integrand, f64); 3] = [
+    ("product", |x, y| x * y, 0.25),
+    (
+        "disk",
+        |x, y| if x * x + y * y < 1.0 { 1.0 } else { 0.0 },
+        PI / 4.0,
+    ),
+    (
+        "cosine lobe",
+        |x, y| (PI * x).sin() * (PI * y).sin(),
+        4.0 / (PI * PI),
+    ),
+];
+
+#[test]
+fn values_are_in_the_unit_interval() {
+    for kind in KINDS {
+        for pixel in 0..16 {
+            for pairs in [false, true] {
+                for (x, y) in points(kind, pixel, 3, pairs) {
+                    assert!(
+                        (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y),
+                        "{:?}",
+                        kind
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn estimates_are_unbiased() {
+    for kind in KINDS {
+        for (name, f, value) in INTEGRANDS {
+            for (skipped, pairs) in [(0, true), (2, true), (1, false), (5, false)] {
+                let estimates = estimates(kind, skipped, pairs, f);
+                let mean = estimates.iter().sum::<f64>() / estimates.len() as f64;
+                // the pixels are independent, the error of their mean shrinks with their count
+                let variance = mean_squared_error(&estimates, mean);
+                let tolerance = 4.0 * (variance / PIXELS as f64).sqrt() + 1e-9;
+                assert!(
+                    (mean - value).abs() <= tolerance,
+                    "{:?}, {}, dimension {}: mean {} instead of {}",
+                    kind,
+                    name,
+                    skipped,
+                    mean,
+                    value
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn stratified_samplers_reduce_the_variance() {
+    for (name, f, value) in INTEGRANDS {
+        for (skipped, pairs) in [(0, true), (2, true)] {
+            let reference = mean_squared_error(
+                &estimates(SamplerKind::Independent, skipped, pairs, f),
+                value,
+            );
+            for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+                let error = mean_squared_error(&estimates(kind, skipped, pairs, f), value);
+                assert!(
+                    error < reference / 4.0,
+                    "{:?}, {}, dimension {}: error {} against {} for independent values",
+                    kind,
+                    name,
+                    skipped,
+                    error,
+                    reference
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn stratified_samplers_have_a_low_discrepancy() {
+    // jittered strata bring the discrepancy of 64 points to about half the one of random
+    // points, scrambled Sobol points much lower
+    let reference = mean_discrepancy(SamplerKind::Independent, 0, true);
+    for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+        for skipped in [0, 2] {
+            let discrepancy = mean_discrepancy(kind, skipped, true);
+            assert!(
+                discrepancy < reference * 0.6,
+                "{:?}, dimension {}: discrepancy {} against {} for independent values",
+                kind,
+                skipped,
+                discrepancy,
+                reference
+            );
+        }
+    }
+}
+
+#[test]
+fn dimensions_are_not_correlated() {
+    // the product of two separate dimensions averages 1/4 only if they are independent,
+    // the same values in both would give 1/3
+    for kind in KINDS {
+        for skipped in [0, 1, 4] {
+            let estimates = estimates(kind, skipped, false, |x, y| x * y);
+            let mean = estimates.iter().sum::<f64>() / estimates.len() as f64;
+            assert!(
+                (mean - 0.25).abs() < 0.01,
+                "{:?}, dimension {}: {}",
+                kind,
+                skipped,
+                mean
+            );
+        }
+    }
+}
+
+#[test]
+fn pixels_get_different_values() {
+    for kind in KINDS {
+        let first = points(kind, 0, 0, true);
+        for pixel in 1..PIXELS {
+            assert_ne!(
+                points(kind, pixel, 0, true),
+                first,
+                "{:?}, pixel {}",
+                kind,
+                pixel
+            );
+        }
+    }
+}